@@ -0,0 +1,69 @@
+//! Measures reading a single transaction of a large block, which decodes that transaction only instead of the whole
+//! block.
+//!
+//! Run with `cargo bench -p dc-db --bench block_inner`.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Instant;
+
+use dc_db::DatabaseService;
+use dp_block::chain_config::ChainConfig;
+use dp_block::{BlockId, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+use dp_receipt::{ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit, TransactionReceipt};
+use dp_state_update::StateDiff;
+use dp_transactions::{InvokeTransaction, InvokeTransactionV1, Transaction};
+use starknet_types_core::felt::Felt;
+
+const N_TRANSACTIONS: u64 = 300;
+const N_CALLS: u32 = 1_000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let db = DatabaseService::new(temp_dir.path(), None, false, Arc::new(ChainConfig::test_config())).await.unwrap();
+    let backend = db.backend();
+
+    let transactions = (0..N_TRANSACTIONS)
+        .map(|n| {
+            Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+                sender_address: Felt::from(n),
+                calldata: vec![Felt::from(n); 32],
+                max_fee: Felt::from(100),
+                signature: vec![Felt::ONE, Felt::TWO],
+                nonce: Felt::from(n),
+            }))
+        })
+        .collect();
+    let receipts = (0..N_TRANSACTIONS)
+        .map(|n| {
+            TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                transaction_hash: Felt::from(n),
+                actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                messages_sent: vec![],
+                events: vec![],
+                execution_resources: Default::default(),
+                execution_result: ExecutionResult::Succeeded,
+            })
+        })
+        .collect();
+    let tx_hashes = (0..N_TRANSACTIONS).map(Felt::from).collect();
+    let block = DeoxysBlock::new(
+        DeoxysBlockInfo::new(Header::default(), tx_hashes, Felt::ONE),
+        DeoxysBlockInner::new(transactions, receipts),
+    );
+    backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..N_CALLS {
+        black_box(backend.get_transaction(&BlockId::Number(0), N_TRANSACTIONS / 2).unwrap().unwrap());
+    }
+    println!("one transaction of a {N_TRANSACTIONS} transactions block: {:?} per call", start.elapsed() / N_CALLS);
+
+    // What getTransactionByBlockIdAndIndex used to do.
+    let start = Instant::now();
+    for _ in 0..N_CALLS {
+        black_box(backend.get_block_inner(&BlockId::Number(0)).unwrap().unwrap());
+    }
+    println!("the whole block (avoided): {:?} per call", start.elapsed() / N_CALLS);
+}
@@ -1,16 +1,23 @@
+use std::ops::Range;
+use std::sync::Arc;
+
 use anyhow::Context;
 use dp_block::{
-    BlockId, BlockTag, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock,
-    DeoxysMaybePendingBlockInfo, DeoxysPendingBlock, DeoxysPendingBlockInfo,
+    BlockId, BlockN, BlockTag, BlockVerification, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner,
+    DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, DeoxysPendingBlock, DeoxysPendingBlockInfo, Header, TxIndex,
 };
+use dp_receipt::TransactionReceipt;
 use dp_state_update::StateDiff;
-use rocksdb::WriteOptions;
+use dp_transactions::Transaction;
+use dp_utils::lock::MutexExt;
+use rocksdb::{IteratorMode, WriteOptions};
 use starknet_api::core::ChainId;
 use starknet_core::types::Felt;
 
+use crate::block_inner::{encode_block_inner, FramedBlockInner};
 use crate::db_block_id::{DbBlockId, DbBlockIdResolvable};
 use crate::DeoxysStorageError;
-use crate::{Column, DatabaseExt, DeoxysBackend, WriteBatchWithTransaction};
+use crate::{Column, DatabaseExt, DeoxysBackend, WriteBatchWithTransaction, DB_UPDATES_BATCH_SIZE};
 
 type Result<T, E = DeoxysStorageError> = std::result::Result<T, E>;
 
@@ -26,8 +33,41 @@ const ROW_PENDING_STATE_UPDATE: &[u8] = b"pending_state_update";
 const ROW_PENDING_INNER: &[u8] = b"pending";
 const ROW_SYNC_TIP: &[u8] = b"sync_tip";
 const ROW_L1_LAST_CONFIRMED_BLOCK: &[u8] = b"l1_last";
+const ROW_SYNC_STALL: &[u8] = b"sync_stall";
+const ROW_UNCOMMITTED_TRIES_FROM: &[u8] = b"uncommitted_tries_from";
+const ROW_INTERRUPTED_TRIES_REVERT: &[u8] = b"interrupted_tries_revert";
+/// Set once every block info holds its verification.
+const ROW_BLOCK_INFO_VERIFICATION: &[u8] = b"block_info_verification";
+const ROW_UNVERIFIED_BLOCKS: &[u8] = b"unverified_blocks";
+
+/// The block info written by older versions, before the verification of the blocks was stored.
+#[derive(serde::Deserialize)]
+struct LegacyDeoxysBlockInfo {
+    header: Header,
+    block_hash: Felt,
+    tx_hashes: Vec<Felt>,
+}
+
+/// Why the L2 sync stopped importing blocks. The sync stays stopped until the node is restarted.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStall {
+    /// The state root computed from the state diff of this block does not match the one in its header.
+    MismatchedStateRoot { block_n: u64, block_hash: Felt, expected: Felt, got: Felt },
+    /// The chain of the feeder forks from ours deeper below our tip than the sync reverts on its own. The common
+    /// ancestor is `None` when it is further than the blocks that can be reverted.
+    DeepReorg { tip_block_n: u64, common_ancestor: Option<u64> },
+}
+
+/// The pending block as it was last stored. The readers get it from memory instead of deserializing it from the
+/// database on every request.
+pub(crate) struct StoredPendingBlock {
+    block: DeoxysPendingBlock,
+    state_diff: StateDiff,
+}
 
-pub struct TxIndex(pub u64);
+fn tx_index_from_position(position: usize) -> Result<TxIndex> {
+    TxIndex::try_from(position).map_err(|_| DeoxysStorageError::inconsistent("Transaction index out of range"))
+}
 
 // TODO(error-handling): some of the else { return Ok(None) } should be replaced with hard errors for
 // inconsistent state.
@@ -62,7 +102,7 @@ impl DeoxysBackend {
 
     // DB read operations
 
-    fn tx_hash_to_block_n(&self, tx_hash: &Felt) -> Result<Option<u64>> {
+    fn tx_hash_to_block_n(&self, tx_hash: &Felt) -> Result<Option<BlockN>> {
         let col = self.db.get_column(Column::TxHashToBlockN);
         let res = self.db.get_cf(&col, bincode::serialize(tx_hash)?)?;
         let Some(res) = res else { return Ok(None) };
@@ -70,7 +110,7 @@ impl DeoxysBackend {
         Ok(Some(block_n))
     }
 
-    fn block_hash_to_block_n(&self, block_hash: &Felt) -> Result<Option<u64>> {
+    fn block_hash_to_block_n(&self, block_hash: &Felt) -> Result<Option<BlockN>> {
         let col = self.db.get_column(Column::BlockHashToBlockN);
         let res = self.db.get_cf(&col, bincode::serialize(block_hash)?)?;
         let Some(res) = res else { return Ok(None) };
@@ -78,7 +118,7 @@ impl DeoxysBackend {
         Ok(Some(block_n))
     }
 
-    fn get_state_update(&self, block_n: u64) -> Result<Option<StateDiff>> {
+    fn get_state_update(&self, block_n: BlockN) -> Result<Option<StateDiff>> {
         let col = self.db.get_column(Column::BlockNToStateDiff);
         let res = self.db.get_cf(&col, bincode::serialize(&block_n)?)?;
         let Some(res) = res else { return Ok(None) };
@@ -86,7 +126,7 @@ impl DeoxysBackend {
         Ok(Some(block))
     }
 
-    fn get_block_info_from_block_n(&self, block_n: u64) -> Result<Option<DeoxysBlockInfo>> {
+    fn get_block_info_from_block_n(&self, block_n: BlockN) -> Result<Option<DeoxysBlockInfo>> {
         let col = self.db.get_column(Column::BlockNToBlockInfo);
         let res = self.db.get_cf(&col, bincode::serialize(&block_n)?)?;
         let Some(res) = res else { return Ok(None) };
@@ -94,22 +134,38 @@ impl DeoxysBackend {
         Ok(Some(block))
     }
 
-    fn get_block_inner_from_block_n(&self, block_n: u64) -> Result<Option<DeoxysBlockInner>> {
+    /// Runs `f` on the transactions and receipts of block `block_n`, which are only decoded when `f` reads them.
+    fn with_framed_block_inner<R>(
+        &self,
+        block_n: BlockN,
+        f: impl FnOnce(FramedBlockInner) -> Result<R>,
+    ) -> Result<Option<R>> {
         let col = self.db.get_column(Column::BlockNToBlockInner);
-        let res = self.db.get_cf(&col, bincode::serialize(&block_n)?)?;
-        let Some(res) = res else { return Ok(None) };
-        let block = bincode::deserialize(&res)?;
-        Ok(Some(block))
+        let Some(res) = self.db.get_pinned_cf(&col, bincode::serialize(&block_n)?)? else { return Ok(None) };
+        f(FramedBlockInner::parse(&res)?).map(Some)
     }
 
+    fn get_block_inner_from_block_n(&self, block_n: BlockN) -> Result<Option<DeoxysBlockInner>> {
+        self.with_framed_block_inner(block_n, |framed| framed.decode())
+    }
+
+    // TODO: return a `BlockN`, like `get_block_n`. Its many callers do tip arithmetic on the bare number and are left
+    // out of the newtype migration for now.
     pub fn get_latest_block_n(&self) -> Result<Option<u64>> {
         let col = self.db.get_column(Column::BlockStorageMeta);
         let Some(res) = self.db.get_cf(&col, ROW_SYNC_TIP)? else { return Ok(None) };
-        let res = bincode::deserialize(&res)?;
-        Ok(Some(res))
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    /// `None` when no pending block was stored since the database was opened.
+    fn stored_pending_block(&self) -> Option<Arc<StoredPendingBlock>> {
+        self.pending_block.lock_or_recover().clone()
     }
 
     fn get_pending_block_info(&self) -> Result<Option<DeoxysPendingBlockInfo>> {
+        if let Some(pending) = self.stored_pending_block() {
+            return Ok(Some(pending.block.info.clone()));
+        }
         let col = self.db.get_column(Column::BlockStorageMeta);
         let Some(res) = self.db.get_cf(&col, ROW_PENDING_INFO)? else { return Ok(None) };
         let res = bincode::deserialize(&res)?;
@@ -117,20 +173,75 @@ impl DeoxysBackend {
     }
 
     fn get_pending_block_inner(&self) -> Result<Option<DeoxysBlockInner>> {
+        if let Some(pending) = self.stored_pending_block() {
+            return Ok(Some(pending.block.inner.clone()));
+        }
         let col = self.db.get_column(Column::BlockStorageMeta);
         let Some(res) = self.db.get_cf(&col, ROW_PENDING_INNER)? else { return Ok(None) };
         let res = bincode::deserialize(&res)?;
         Ok(Some(res))
     }
 
+    fn with_pending_block_inner<R>(&self, f: impl FnOnce(&DeoxysBlockInner) -> R) -> Result<Option<R>> {
+        if let Some(pending) = self.stored_pending_block() {
+            return Ok(Some(f(&pending.block.inner)));
+        }
+        Ok(self.get_pending_block_inner()?.map(|inner| f(&inner)))
+    }
+
+    /// Runs `f` on the info and the transactions of the same pending block. They are read from a snapshot of the
+    /// database when the pending block is not in memory, a pending block stored meanwhile can't be half read.
+    fn with_pending_block<R>(&self, f: impl FnOnce(&DeoxysPendingBlock) -> R) -> Result<Option<R>> {
+        if let Some(pending) = self.stored_pending_block() {
+            return Ok(Some(f(&pending.block)));
+        }
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let snapshot = self.db.snapshot();
+        let Some(info) = snapshot.get_cf(&col, ROW_PENDING_INFO)? else { return Ok(None) };
+        let Some(inner) = snapshot.get_cf(&col, ROW_PENDING_INNER)? else { return Ok(None) };
+        let block = DeoxysPendingBlock::new(bincode::deserialize(&info)?, bincode::deserialize(&inner)?);
+        Ok(Some(f(&block)))
+    }
+
     pub fn get_l1_last_confirmed_block(&self) -> Result<Option<u64>> {
         let col = self.db.get_column(Column::BlockStorageMeta);
         let Some(res) = self.db.get_cf(&col, ROW_L1_LAST_CONFIRMED_BLOCK)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    pub fn get_sync_stall(&self) -> Result<Option<SyncStall>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_SYNC_STALL)? else { return Ok(None) };
         let res = bincode::deserialize(&res)?;
         Ok(Some(res))
     }
 
+    /// The first block whose state diff is stored but not committed to the global tries yet, when a fast sync is in
+    /// the middle of a batch. The global tries hold the state before it.
+    pub fn get_uncommitted_tries_from(&self) -> Result<Option<u64>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_UNCOMMITTED_TRIES_FROM)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    /// The block a revert removed the blocks after without reverting the global tries yet, when it was interrupted
+    /// before that: the global tries are in an unknown state between this block and the removed tip.
+    pub fn get_interrupted_tries_revert(&self) -> Result<Option<u64>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_INTERRUPTED_TRIES_REVERT)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    pub fn clear_interrupted_tries_revert(&self) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.delete_cf(&col, ROW_INTERRUPTED_TRIES_REVERT)?;
+        Ok(())
+    }
+
     pub fn get_pending_block_state_update(&self) -> Result<Option<StateDiff>> {
+        if let Some(pending) = self.stored_pending_block() {
+            return Ok(Some(pending.state_diff.clone()));
+        }
         let col = self.db.get_column(Column::BlockStorageMeta);
         let Some(res) = self.db.get_cf(&col, ROW_PENDING_STATE_UPDATE)? else { return Ok(None) };
         let res = bincode::deserialize(&res)?;
@@ -139,27 +250,41 @@ impl DeoxysBackend {
 
     // DB write
 
-    pub(crate) fn block_db_store_pending(&self, block: &DeoxysPendingBlock, state_update: &StateDiff) -> Result<()> {
-        let mut tx = WriteBatchWithTransaction::default();
+    /// Writes `tx` along with the pending block, so that the readers see all of it or none of it.
+    pub(crate) fn block_db_store_pending(
+        &self,
+        block: DeoxysPendingBlock,
+        state_diff: StateDiff,
+        mut tx: WriteBatchWithTransaction,
+    ) -> Result<()> {
         let col = self.db.get_column(Column::BlockStorageMeta);
         tx.put_cf(&col, ROW_PENDING_INFO, bincode::serialize(&block.info)?);
         tx.put_cf(&col, ROW_PENDING_INNER, bincode::serialize(&block.inner)?);
-        tx.put_cf(&col, ROW_PENDING_STATE_UPDATE, bincode::serialize(&state_update)?);
+        tx.put_cf(&col, ROW_PENDING_STATE_UPDATE, bincode::serialize(&state_diff)?);
         let mut writeopts = WriteOptions::new();
         writeopts.disable_wal(true);
+        // The lock is held during the write, so that the readers never see a pending block older than the database.
+        let mut pending = self.pending_block.lock_or_recover();
         self.db.write_opt(tx, &writeopts)?;
+        *pending = Some(Arc::new(StoredPendingBlock { block, state_diff }));
         Ok(())
     }
 
-    pub(crate) fn block_db_clear_pending(&self) -> Result<()> {
-        let mut tx = WriteBatchWithTransaction::default();
+    pub(crate) fn block_db_pending_removal(&self, tx: &mut WriteBatchWithTransaction) {
         let col = self.db.get_column(Column::BlockStorageMeta);
         tx.delete_cf(&col, ROW_PENDING_INFO);
         tx.delete_cf(&col, ROW_PENDING_INNER);
         tx.delete_cf(&col, ROW_PENDING_STATE_UPDATE);
+    }
+
+    /// Writes `tx` along with the removal of the pending block.
+    pub(crate) fn block_db_clear_pending(&self, mut tx: WriteBatchWithTransaction) -> Result<()> {
+        self.block_db_pending_removal(&mut tx);
         let mut writeopts = WriteOptions::new();
         writeopts.disable_wal(true);
+        let mut pending = self.pending_block.lock_or_recover();
         self.db.write_opt(tx, &writeopts)?;
+        *pending = None;
         Ok(())
     }
 
@@ -167,7 +292,8 @@ impl DeoxysBackend {
         let col = self.db.get_column(Column::BlockStorageMeta);
         let mut writeopts = WriteOptions::default(); // todo move that in db
         writeopts.disable_wal(true);
-        self.db.put_cf_opt(&col, ROW_L1_LAST_CONFIRMED_BLOCK, bincode::serialize(&l1_last)?, &writeopts)?;
+        self.db.put_cf_opt(&col, ROW_L1_LAST_CONFIRMED_BLOCK, bincode::serialize(&BlockN(l1_last))?, &writeopts)?;
+        self.chain_head.update_l1_confirmed(l1_last);
         Ok(())
     }
 
@@ -175,27 +301,64 @@ impl DeoxysBackend {
         self.write_last_confirmed_block(0)
     }
 
-    /// Also clears pending block
-    pub(crate) fn block_db_store_block(&self, block: &DeoxysBlock, state_diff: &StateDiff) -> Result<()> {
-        let mut tx = WriteBatchWithTransaction::default();
+    pub fn write_sync_stall(&self, stall: &SyncStall) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.put_cf(&col, ROW_SYNC_STALL, bincode::serialize(stall)?)?;
+        Ok(())
+    }
+
+    pub fn clear_sync_stall(&self) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.delete_cf(&col, ROW_SYNC_STALL)?;
+        Ok(())
+    }
+
+    pub fn write_uncommitted_tries_from(&self, block_n: u64) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.put_cf(&col, ROW_UNCOMMITTED_TRIES_FROM, bincode::serialize(&BlockN(block_n))?)?;
+        Ok(())
+    }
 
+    pub fn clear_uncommitted_tries_from(&self) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.delete_cf(&col, ROW_UNCOMMITTED_TRIES_FROM)?;
+        Ok(())
+    }
+
+    /// Writes the block along with the writes already in `tx`. Also clears pending block
+    pub(crate) fn block_db_store_block(
+        &self,
+        block: &DeoxysBlock,
+        state_diff: &StateDiff,
+        mut tx: WriteBatchWithTransaction,
+    ) -> Result<()> {
         let tx_hash_to_block_n = self.db.get_column(Column::TxHashToBlockN);
         let block_hash_to_block_n = self.db.get_column(Column::BlockHashToBlockN);
         let block_n_to_block = self.db.get_column(Column::BlockNToBlockInfo);
         let block_n_to_block_inner = self.db.get_column(Column::BlockNToBlockInner);
         let block_n_to_state_diff = self.db.get_column(Column::BlockNToStateDiff);
         let meta = self.db.get_column(Column::BlockStorageMeta);
+        let l1_messaging_nonce_to_tx_hash = self.db.get_column(Column::L1MessagingNonceToTxHash);
 
         let block_hash_encoded = bincode::serialize(&block.info.block_hash)?;
-        let block_n_encoded = bincode::serialize(&block.info.header.block_number)?;
+        let block_n_encoded = bincode::serialize(&BlockN(block.info.header.block_number))?;
 
         for hash in &block.info.tx_hashes {
             tx.put_cf(&tx_hash_to_block_n, bincode::serialize(hash)?, &block_n_encoded);
         }
+        for (transaction, hash) in block.inner.transactions.iter().zip(&block.info.tx_hashes) {
+            if let Transaction::L1Handler(l1_handler) = transaction {
+                tx.put_cf(
+                    &l1_messaging_nonce_to_tx_hash,
+                    bincode::serialize(&l1_handler.nonce)?,
+                    bincode::serialize(hash)?,
+                );
+            }
+        }
 
         tx.put_cf(&block_hash_to_block_n, block_hash_encoded, &block_n_encoded);
         tx.put_cf(&block_n_to_block, &block_n_encoded, bincode::serialize(&block.info)?);
-        tx.put_cf(&block_n_to_block_inner, &block_n_encoded, bincode::serialize(&block.inner)?);
+        tx.put_cf(&block_n_to_block_inner, &block_n_encoded, encode_block_inner(&block.inner)?);
         tx.put_cf(&block_n_to_state_diff, &block_n_encoded, bincode::serialize(state_diff)?);
         tx.put_cf(&meta, ROW_SYNC_TIP, block_n_encoded);
 
@@ -206,7 +369,156 @@ impl DeoxysBackend {
 
         let mut writeopts = WriteOptions::new();
         writeopts.disable_wal(true);
+        let mut pending = self.pending_block.lock_or_recover();
+        self.db.write_opt(tx, &writeopts)?;
+        *pending = None;
+        Ok(())
+    }
+
+    /// Adds to `tx` the removal of block `block_n` and of its indices, returns its state diff.
+    pub(crate) fn block_db_revert_block(&self, tx: &mut WriteBatchWithTransaction, block_n: u64) -> Result<StateDiff> {
+        let missing = || DeoxysStorageError::inconsistent(format!("Reverting block {block_n}, which is not stored"));
+        let info = self.get_block_info_from_block_n(BlockN(block_n))?.ok_or_else(missing)?;
+        let transactions =
+            self.with_framed_block_inner(BlockN(block_n), |framed| framed.transactions())?.ok_or_else(missing)?;
+        let state_diff = self.get_state_update(BlockN(block_n))?.ok_or_else(missing)?;
+
+        let tx_hash_to_block_n = self.db.get_column(Column::TxHashToBlockN);
+        let block_hash_to_block_n = self.db.get_column(Column::BlockHashToBlockN);
+        let block_n_to_block = self.db.get_column(Column::BlockNToBlockInfo);
+        let block_n_to_block_inner = self.db.get_column(Column::BlockNToBlockInner);
+        let block_n_to_state_diff = self.db.get_column(Column::BlockNToStateDiff);
+        let l1_messaging_nonce_to_tx_hash = self.db.get_column(Column::L1MessagingNonceToTxHash);
+
+        let block_n_encoded = bincode::serialize(&BlockN(block_n))?;
+
+        for hash in &info.tx_hashes {
+            tx.delete_cf(&tx_hash_to_block_n, bincode::serialize(hash)?);
+        }
+        for transaction in &transactions {
+            if let Transaction::L1Handler(l1_handler) = transaction {
+                tx.delete_cf(&l1_messaging_nonce_to_tx_hash, bincode::serialize(&l1_handler.nonce)?);
+            }
+        }
+
+        tx.delete_cf(&block_hash_to_block_n, bincode::serialize(&info.block_hash)?);
+        tx.delete_cf(&block_n_to_block, &block_n_encoded);
+        tx.delete_cf(&block_n_to_block_inner, &block_n_encoded);
+        tx.delete_cf(&block_n_to_state_diff, &block_n_encoded);
+
+        Ok(state_diff)
+    }
+
+    /// Writes `tx` along with the new sync tip and the removal of the pending block, in a single batch. When the global
+    /// tries are reverted next, the batch records it until [`DeoxysBackend::clear_interrupted_tries_revert`].
+    pub(crate) fn block_db_write_revert(
+        &self,
+        mut tx: WriteBatchWithTransaction,
+        tip: u64,
+        revert_tries: bool,
+    ) -> Result<()> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        tx.put_cf(&meta, ROW_SYNC_TIP, bincode::serialize(&BlockN(tip))?);
+        if revert_tries {
+            tx.put_cf(&meta, ROW_INTERRUPTED_TRIES_REVERT, bincode::serialize(&BlockN(tip))?);
+        }
+        self.block_db_pending_removal(&mut tx);
+        // Unlike the block writes, which can be synced again, the revert must survive a crash once the tries follow it.
+        let writeopts = WriteOptions::new();
+        // A verification update must not write back the info of a reverted block.
+        let _lock = self.block_verification.lock_or_recover();
+        let mut pending = self.pending_block.lock_or_recover();
+        self.db.write_opt(tx, &writeopts)?;
+        *pending = None;
+        Ok(())
+    }
+
+    /// Adds `verification` to the flags of the stored block `block_n`, for checks that ran after the block was
+    /// stored. `on_added` runs with the flags that were not set yet before the lock on the updates is released: when
+    /// several verifications of the block run at once, each flag is added, and counted by `on_added`, exactly once.
+    ///
+    /// Returns the flags of the block, `None` if the block is not stored.
+    pub fn add_block_verification(
+        &self,
+        block_n: u64,
+        verification: BlockVerification,
+        on_added: impl FnOnce(BlockVerification),
+    ) -> Result<Option<BlockVerification>> {
+        let _lock = self.block_verification.lock_or_recover();
+        let Some(mut info) = self.get_block_info_from_block_n(BlockN(block_n))? else { return Ok(None) };
+        let added = verification.difference(info.verification);
+        if !added.is_empty() {
+            info.verification |= added;
+            let col = self.db.get_column(Column::BlockNToBlockInfo);
+            self.db.put_cf(&col, bincode::serialize(&BlockN(block_n))?, bincode::serialize(&info)?)?;
+            on_added(added);
+        }
+        Ok(Some(info.verification))
+    }
+
+    /// Rewrites the block infos written by older versions with an empty verification. These blocks were verified
+    /// when they were imported, but what was verified was not recorded: they are listed in
+    /// [`DeoxysBackend::get_unverified_blocks`] to be verified again.
+    ///
+    /// The legacy rows are one byte short of the current encoding, and can't be mistaken for it. The rows that were
+    /// already rewritten are skipped, an interrupted migration resumes on the next start.
+    pub(crate) fn migrate_block_info_verification(&self) -> Result<()> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        if self.db.get_cf(&meta, ROW_BLOCK_INFO_VERIFICATION)?.is_some() {
+            return Ok(());
+        }
+
+        let col = self.db.get_column(Column::BlockNToBlockInfo);
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        let mut tx = WriteBatchWithTransaction::default();
+        let mut unverified_end = self.get_unverified_blocks()?.map_or(0, |blocks| blocks.end);
+        let mut migrated = 0usize;
+        for res in self.db.iterator_cf(&col, IteratorMode::Start) {
+            let (key, value) = res?;
+            if bincode::deserialize::<DeoxysBlockInfo>(&value).is_ok() {
+                continue;
+            }
+            if migrated == 0 {
+                log::info!("⏳ Migrating the stored block infos...");
+            }
+            let LegacyDeoxysBlockInfo { header, block_hash, tx_hashes } = bincode::deserialize(&value)?;
+            unverified_end = unverified_end.max(header.block_number + 1);
+            tx.put_cf(&col, key, bincode::serialize(&DeoxysBlockInfo::new(header, tx_hashes, block_hash))?);
+            migrated += 1;
+            if migrated % DB_UPDATES_BATCH_SIZE == 0 {
+                tx.put_cf(&meta, ROW_UNVERIFIED_BLOCKS, bincode::serialize(&(0..unverified_end))?);
+                self.db.write_opt(std::mem::take(&mut tx), &writeopts)?;
+            }
+        }
+        if unverified_end > 0 {
+            tx.put_cf(&meta, ROW_UNVERIFIED_BLOCKS, bincode::serialize(&(0..unverified_end))?);
+        }
+        tx.put_cf(&meta, ROW_BLOCK_INFO_VERIFICATION, b"");
         self.db.write_opt(tx, &writeopts)?;
+        if migrated > 0 {
+            log::info!("✅ Migrated the infos of {migrated} blocks");
+        }
+        Ok(())
+    }
+
+    /// The blocks stored by older versions that were not verified again yet, `None` once they all were.
+    pub fn get_unverified_blocks(&self) -> Result<Option<Range<u64>>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_UNVERIFIED_BLOCKS)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize(&res)?))
+    }
+
+    /// Records the blocks stored by older versions left to verify again, none when `blocks` is empty.
+    pub fn set_unverified_blocks(&self, blocks: Range<u64>) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        if blocks.is_empty() {
+            self.db.delete_cf_opt(&col, ROW_UNVERIFIED_BLOCKS, &writeopts)?;
+        } else {
+            self.db.put_cf_opt(&col, ROW_UNVERIFIED_BLOCKS, bincode::serialize(&blocks)?, &writeopts)?;
+        }
         Ok(())
     }
 
@@ -215,9 +527,16 @@ impl DeoxysBackend {
     pub(crate) fn id_to_storage_type(&self, id: &BlockId) -> Result<Option<DbBlockId>> {
         match id {
             BlockId::Hash(hash) => Ok(self.block_hash_to_block_n(hash)?.map(DbBlockId::BlockN)),
-            BlockId::Number(block_n) => Ok(Some(DbBlockId::BlockN(*block_n))),
-            BlockId::Tag(BlockTag::Latest) => Ok(self.get_latest_block_n()?.map(DbBlockId::BlockN)),
+            BlockId::Number(block_n) => Ok(Some(DbBlockId::BlockN(BlockN(*block_n)))),
+            BlockId::Tag(BlockTag::Latest) => {
+                Ok(self.get_latest_block_n()?.map(|block_n| DbBlockId::BlockN(BlockN(block_n))))
+            }
             BlockId::Tag(BlockTag::Pending) => Ok(Some(DbBlockId::Pending)),
+            // The L1 verification can be ahead of the sync.
+            BlockId::Tag(BlockTag::L1Accepted) => Ok(self
+                .get_l1_last_confirmed_block()?
+                .zip(self.get_latest_block_n()?)
+                .map(|(l1_accepted, latest)| DbBlockId::BlockN(BlockN(l1_accepted.min(latest))))),
         }
     }
 
@@ -239,10 +558,10 @@ impl DeoxysBackend {
 
     // BlockId
 
-    pub fn get_block_n(&self, id: &impl DbBlockIdResolvable) -> Result<Option<u64>> {
+    pub fn get_block_n(&self, id: &impl DbBlockIdResolvable) -> Result<Option<BlockN>> {
         let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
         match &ty {
-            DbBlockId::BlockN(block_id) => Ok(Some(*block_id)),
+            DbBlockId::BlockN(block_n) => Ok(Some(*block_n)),
             DbBlockId::Pending => Ok(None),
         }
     }
@@ -264,6 +583,15 @@ impl DeoxysBackend {
         }
     }
 
+    /// What the node verified itself about a block. Nothing is verified about the pending block.
+    pub fn get_block_verification(&self, id: &impl DbBlockIdResolvable) -> Result<Option<BlockVerification>> {
+        let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
+        match ty {
+            DbBlockId::Pending => Ok(self.get_pending_block_info()?.map(|_| BlockVerification::NONE)),
+            DbBlockId::BlockN(block_n) => Ok(self.get_block_info_from_block_n(block_n)?.map(|info| info.verification)),
+        }
+    }
+
     pub fn get_block_info(&self, id: &impl DbBlockIdResolvable) -> Result<Option<DeoxysMaybePendingBlockInfo>> {
         let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
         self.storage_to_info(&ty)
@@ -274,6 +602,45 @@ impl DeoxysBackend {
         self.storage_to_inner(&ty)
     }
 
+    /// The transaction at `index` in the block, `None` when the block is not stored or has fewer transactions. The other
+    /// transactions and the receipts of the block are not decoded.
+    pub fn get_transaction(&self, id: &impl DbBlockIdResolvable, index: u64) -> Result<Option<Transaction>> {
+        let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
+        let Ok(index) = usize::try_from(index) else { return Ok(None) };
+        match ty {
+            DbBlockId::Pending => {
+                Ok(self.with_pending_block_inner(|inner| inner.transactions.get(index).cloned())?.flatten())
+            }
+            DbBlockId::BlockN(block_n) => {
+                Ok(self.with_framed_block_inner(block_n, |framed| framed.transaction(index))?.flatten())
+            }
+        }
+    }
+
+    /// The receipt of the transaction at `index` in the block, `None` when the block is not stored or has fewer
+    /// transactions. The transactions and the other receipts of the block are not decoded.
+    pub fn get_receipt(&self, id: &impl DbBlockIdResolvable, index: u64) -> Result<Option<TransactionReceipt>> {
+        let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
+        let Ok(index) = usize::try_from(index) else { return Ok(None) };
+        match ty {
+            DbBlockId::Pending => {
+                Ok(self.with_pending_block_inner(|inner| inner.receipts.get(index).cloned())?.flatten())
+            }
+            DbBlockId::BlockN(block_n) => {
+                Ok(self.with_framed_block_inner(block_n, |framed| framed.receipt(index))?.flatten())
+            }
+        }
+    }
+
+    /// The receipts of the block, without decoding its transactions.
+    pub fn get_block_receipts(&self, id: &impl DbBlockIdResolvable) -> Result<Option<Vec<TransactionReceipt>>> {
+        let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
+        match ty {
+            DbBlockId::Pending => self.with_pending_block_inner(|inner| inner.receipts.clone()),
+            DbBlockId::BlockN(block_n) => self.with_framed_block_inner(block_n, |framed| framed.receipts()),
+        }
+    }
+
     pub fn get_block(&self, id: &impl DbBlockIdResolvable) -> Result<Option<DeoxysMaybePendingBlock>> {
         let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
         let Some(info) = self.storage_to_info(&ty)? else { return Ok(None) };
@@ -283,18 +650,31 @@ impl DeoxysBackend {
 
     // Tx hashes and tx status
 
+    /// Whether a transaction with this hash is in a stored block, the pending block included. The bloom filters of
+    /// the column rule out most of the unknown hashes without reading it.
+    pub fn tx_exists(&self, tx_hash: &Felt) -> Result<bool> {
+        let col = self.db.get_column(Column::TxHashToBlockN);
+        let key = bincode::serialize(tx_hash)?;
+        if self.db.key_may_exist_cf(&col, &key) && self.db.get_pinned_cf(&col, &key)?.is_some() {
+            return Ok(true);
+        }
+        Ok(self.get_pending_block_info()?.is_some_and(|info| info.tx_hashes.contains(tx_hash)))
+    }
+
     /// Returns the index of the tx.
     pub fn find_tx_hash_block_info(&self, tx_hash: &Felt) -> Result<Option<(DeoxysMaybePendingBlockInfo, TxIndex)>> {
         match self.tx_hash_to_block_n(tx_hash)? {
             Some(block_n) => {
                 let Some(info) = self.get_block_info_from_block_n(block_n)? else { return Ok(None) };
                 let Some(tx_index) = info.tx_hashes.iter().position(|a| a == tx_hash) else { return Ok(None) };
-                Ok(Some((info.into(), TxIndex(tx_index as _))))
+                let tx_index = tx_index_from_position(tx_index)?;
+                Ok(Some((info.into(), tx_index)))
             }
             None => {
                 let Some(info) = self.get_pending_block_info()? else { return Ok(None) };
                 let Some(tx_index) = info.tx_hashes.iter().position(|a| a == tx_hash) else { return Ok(None) };
-                Ok(Some((info.into(), TxIndex(tx_index as _))))
+                let tx_index = tx_index_from_position(tx_index)?;
+                Ok(Some((info.into(), tx_index)))
             }
         }
     }
@@ -305,15 +685,269 @@ impl DeoxysBackend {
             Some(block_n) => {
                 let Some(info) = self.get_block_info_from_block_n(block_n)? else { return Ok(None) };
                 let Some(tx_index) = info.tx_hashes.iter().position(|a| a == tx_hash) else { return Ok(None) };
+                let tx_index = tx_index_from_position(tx_index)?;
                 let Some(inner) = self.get_block_inner_from_block_n(block_n)? else { return Ok(None) };
-                Ok(Some((DeoxysMaybePendingBlock { info: info.into(), inner }, TxIndex(tx_index as _))))
+                Ok(Some((DeoxysMaybePendingBlock { info: info.into(), inner }, tx_index)))
             }
             None => {
-                let Some(info) = self.get_pending_block_info()? else { return Ok(None) };
-                let Some(tx_index) = info.tx_hashes.iter().position(|a| a == tx_hash) else { return Ok(None) };
-                let Some(inner) = self.get_pending_block_inner()? else { return Ok(None) };
-                Ok(Some((DeoxysMaybePendingBlock { info: info.into(), inner }, TxIndex(tx_index as _))))
+                let found = self.with_pending_block(|block| -> Result<_> {
+                    let Some(tx_index) = block.info.tx_hashes.iter().position(|a| a == tx_hash) else {
+                        return Ok(None);
+                    };
+                    let block = DeoxysMaybePendingBlock { info: block.info.clone().into(), inner: block.inner.clone() };
+                    Ok(Some((block, tx_index_from_position(tx_index)?)))
+                })?;
+                Ok(found.transpose()?.flatten())
+            }
+        }
+    }
+
+    /// The transaction with this hash, with the info of its block. Only this transaction of the block is decoded.
+    pub fn find_tx_hash_transaction(
+        &self,
+        tx_hash: &Felt,
+    ) -> Result<Option<(DeoxysMaybePendingBlockInfo, Transaction)>> {
+        self.find_tx_hash_with(
+            tx_hash,
+            |framed, index| framed.transaction(index),
+            |inner, index| inner.transactions.get(index).cloned(),
+        )
+    }
+
+    /// The transaction with this hash and its receipt, with the info of its block. Only this transaction and its
+    /// receipt are decoded.
+    pub fn find_tx_hash_receipt(
+        &self,
+        tx_hash: &Felt,
+    ) -> Result<Option<(DeoxysMaybePendingBlockInfo, (Transaction, TransactionReceipt))>> {
+        self.find_tx_hash_with(
+            tx_hash,
+            |framed, index| Ok(framed.transaction(index)?.zip(framed.receipt(index)?)),
+            |inner, index| inner.transactions.get(index).cloned().zip(inner.receipts.get(index).cloned()),
+        )
+    }
+
+    /// Finds the block of the transaction, and reads the transaction from it with `read_framed`, or with
+    /// `read_pending` when it is in the pending block. The pending block is read once: the info of the block and what
+    /// is read from it belong to the same pending block, even when it is replaced meanwhile.
+    fn find_tx_hash_with<R>(
+        &self,
+        tx_hash: &Felt,
+        read_framed: impl FnOnce(&FramedBlockInner, usize) -> Result<Option<R>>,
+        read_pending: impl FnOnce(&DeoxysBlockInner, usize) -> Option<R>,
+    ) -> Result<Option<(DeoxysMaybePendingBlockInfo, R)>> {
+        match self.tx_hash_to_block_n(tx_hash)? {
+            Some(block_n) => {
+                let Some(info) = self.get_block_info_from_block_n(block_n)? else { return Ok(None) };
+                let Some(position) = info.tx_hashes.iter().position(|a| a == tx_hash) else { return Ok(None) };
+                let read = self.with_framed_block_inner(block_n, |framed| read_framed(&framed, position))?.flatten();
+                Ok(read.map(|read| (info.into(), read)))
+            }
+            None => {
+                let found = self.with_pending_block(|block| {
+                    let position = block.info.tx_hashes.iter().position(|a| a == tx_hash)?;
+                    read_pending(&block.inner, position).map(|read| (block.info.clone().into(), read))
+                })?;
+                Ok(found.flatten())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dp_block::header::PendingHeader;
+    use dp_receipt::{ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit};
+    use dp_transactions::{InvokeTransaction, InvokeTransactionV1};
+
+    use super::*;
+    use crate::testing::temp_database;
+
+    #[tokio::test]
+    async fn test_pending_block_is_read_from_memory() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        let header = PendingHeader { parent_block_hash: Felt::ONE, ..Default::default() };
+        backend.store_block(DeoxysPendingBlock::new_empty(header).into(), StateDiff::default(), vec![]).unwrap();
+
+        // The stored rows are not deserialized again.
+        let col = backend.db.get_column(Column::BlockStorageMeta);
+        backend.db.put_cf(&col, ROW_PENDING_INNER, b"not a block").unwrap();
+        let pending = backend.get_block(&DbBlockId::Pending).unwrap().unwrap();
+        assert_eq!(pending.info.as_pending().unwrap().header.parent_block_hash, Felt::ONE);
+        assert!(pending.inner.transactions.is_empty());
+
+        // Closing a block clears the pending block.
+        let block = DeoxysBlock::new(
+            DeoxysBlockInfo::new(Header::default(), vec![], Felt::TWO),
+            DeoxysBlockInner::new(vec![], vec![]),
+        );
+        backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        assert!(backend.get_block(&DbBlockId::Pending).unwrap().is_none());
+    }
+
+    fn pending_block(tx_hashes: &[u64]) -> DeoxysPendingBlock {
+        let tx_hashes: Vec<_> = tx_hashes.iter().map(|tx_hash| Felt::from(*tx_hash)).collect();
+        let transactions = tx_hashes
+            .iter()
+            .map(|tx_hash| {
+                Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+                    sender_address: Felt::ONE,
+                    calldata: vec![*tx_hash],
+                    max_fee: Felt::ONE,
+                    signature: vec![],
+                    nonce: Felt::ZERO,
+                }))
+            })
+            .collect();
+        let receipts = tx_hashes
+            .iter()
+            .map(|tx_hash| {
+                TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                    transaction_hash: *tx_hash,
+                    actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                    messages_sent: vec![],
+                    events: vec![],
+                    execution_resources: Default::default(),
+                    execution_result: ExecutionResult::Succeeded,
+                })
+            })
+            .collect();
+        DeoxysPendingBlock::new(
+            DeoxysPendingBlockInfo::new(PendingHeader::default(), tx_hashes),
+            DeoxysBlockInner::new(transactions, receipts),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_find_tx_hash_reads_one_pending_block() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        backend.store_block(pending_block(&[0x10, 0x11]).into(), StateDiff::default(), vec![]).unwrap();
+
+        let check = |tx_hash: u64| {
+            let (info, (transaction, receipt)) = backend.find_tx_hash_receipt(&Felt::from(tx_hash)).unwrap().unwrap();
+            assert!(info.as_pending().is_some());
+            assert_eq!(receipt.transaction_hash(), Felt::from(tx_hash));
+            let (_, by_hash) = backend.find_tx_hash_transaction(&Felt::from(tx_hash)).unwrap().unwrap();
+            assert_eq!(by_hash, transaction);
+            let Transaction::Invoke(InvokeTransaction::V1(tx)) = transaction else { unreachable!() };
+            assert_eq!(tx.calldata, [Felt::from(tx_hash)]);
+        };
+        check(0x11);
+
+        // Without the pending block in memory, its rows are read from a snapshot of the database.
+        *backend.pending_block.lock_or_recover() = None;
+        check(0x10);
+        check(0x11);
+        assert!(backend.find_tx_hash_receipt(&Felt::from(0x12)).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_l1_accepted_tag() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let l1_accepted = || backend.resolve_block_id(&"l1_accepted".parse::<BlockId>().unwrap()).unwrap();
+
+        for block_number in 0..3 {
+            let header = Header { block_number, ..Default::default() };
+            let block = DeoxysBlock::new(
+                DeoxysBlockInfo::new(header, vec![], Felt::from(block_number)),
+                DeoxysBlockInner::new(vec![], vec![]),
+            );
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+        assert!(l1_accepted().is_none());
+
+        backend.write_last_confirmed_block(1).unwrap();
+        assert!(matches!(l1_accepted(), Some(DbBlockId::BlockN(BlockN(1)))));
+        // Not synced yet.
+        backend.write_last_confirmed_block(10).unwrap();
+        assert!(matches!(l1_accepted(), Some(DbBlockId::BlockN(BlockN(2)))));
+    }
+
+    #[tokio::test]
+    async fn test_add_block_verification() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let info = DeoxysBlockInfo::new(Header::default(), vec![], Felt::ONE)
+            .with_verification(BlockVerification::VERIFIED_HASH);
+        backend
+            .store_block(
+                DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![])).into(),
+                StateDiff::default(),
+                vec![],
+            )
+            .unwrap();
+        let header = PendingHeader { parent_block_hash: Felt::ONE, ..Default::default() };
+        backend.store_block(DeoxysPendingBlock::new_empty(header).into(), StateDiff::default(), vec![]).unwrap();
+
+        let verification = || backend.get_block_verification(&BlockId::Number(0)).unwrap().unwrap();
+        assert_eq!(verification(), BlockVerification::VERIFIED_HASH);
+        assert_eq!(
+            backend.get_block_verification(&BlockId::Tag(BlockTag::Pending)).unwrap(),
+            Some(BlockVerification::NONE)
+        );
+        assert_eq!(backend.get_block_verification(&BlockId::Number(1)).unwrap(), None);
+
+        // Only the flags that were not set yet are added.
+        let mut added = vec![];
+        let flags = BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_ROOT;
+        let res = backend.add_block_verification(0, flags, |flags| added.push(flags)).unwrap();
+        assert_eq!(res, Some(flags));
+        assert_eq!(verification(), flags);
+        backend.add_block_verification(0, BlockVerification::VERIFIED_ROOT, |flags| added.push(flags)).unwrap();
+        assert_eq!(added, [BlockVerification::VERIFIED_ROOT]);
+        // The rest of the block info is unchanged.
+        let info = backend.get_block_info(&BlockId::Number(0)).unwrap().unwrap();
+        assert_eq!(info.as_nonpending().unwrap().block_hash, Felt::ONE);
+
+        assert_eq!(backend.add_block_verification(1, flags, |_| panic!("Block 1 is not stored")).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_block_info_verification() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        for block_number in 0..3 {
+            let info = DeoxysBlockInfo::new(Header { block_number, ..Default::default() }, vec![Felt::TWO], Felt::ONE)
+                .with_verification(BlockVerification::VERIFIED_HASH);
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+        assert_eq!(backend.get_unverified_blocks().unwrap(), None);
+
+        // Blocks 0 and 1 were written by an older version.
+        let col = backend.db.get_column(Column::BlockNToBlockInfo);
+        for block_number in 0..2 {
+            let legacy = (Header { block_number, ..Default::default() }, Felt::ONE, vec![Felt::TWO]);
+            backend
+                .db
+                .put_cf(&col, bincode::serialize(&BlockN(block_number)).unwrap(), bincode::serialize(&legacy).unwrap())
+                .unwrap();
+        }
+        let meta = backend.db.get_column(Column::BlockStorageMeta);
+        backend.db.delete_cf(&meta, ROW_BLOCK_INFO_VERIFICATION).unwrap();
+        assert!(backend.get_block_info(&BlockId::Number(1)).is_err());
+
+        backend.migrate_block_info_verification().unwrap();
+        for block_n in 0..2 {
+            let info = backend.get_block_info(&BlockId::Number(block_n)).unwrap().unwrap();
+            let info = info.as_nonpending().unwrap();
+            assert_eq!(
+                (info.header.block_number, info.block_hash, &info.tx_hashes[..]),
+                (block_n, Felt::ONE, &[Felt::TWO][..])
+            );
+            assert_eq!(info.verification, BlockVerification::NONE);
+        }
+        let verification = backend.get_block_verification(&BlockId::Number(2)).unwrap();
+        assert_eq!(verification, Some(BlockVerification::VERIFIED_HASH));
+        assert_eq!(backend.get_unverified_blocks().unwrap(), Some(0..2));
+
+        backend.set_unverified_blocks(1..2).unwrap();
+        assert_eq!(backend.get_unverified_blocks().unwrap(), Some(1..2));
+        backend.set_unverified_blocks(2..2).unwrap();
+        assert_eq!(backend.get_unverified_blocks().unwrap(), None);
+    }
+}
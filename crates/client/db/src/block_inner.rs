@@ -0,0 +1,527 @@
+//! Layout of the transactions and receipts of the closed blocks, in [`Column::BlockNToBlockInner`]. Each transaction
+//! and receipt is encoded on its own, so that reading one of them does not decode the rest of the block.
+//!
+//! A row starts with [`FRAMED_MAGIC`], followed by the number of transactions and of receipts, and the end offset of
+//! every item in the data that follows, as little-endian `u32`s. The items are the transactions then the receipts,
+//! each encoded with bincode.
+//!
+//! The rows written by older versions hold the bincode encoding of the whole [`DeoxysBlockInner`], they are rewritten
+//! when the database is opened. So are the rows whose L1 handler receipts hold the hash of their message as a felt,
+//! see [`legacy`].
+
+use dp_block::{BlockN, DeoxysBlockInner};
+use dp_receipt::TransactionReceipt;
+use dp_transactions::Transaction;
+use rocksdb::{IteratorMode, WriteOptions};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{Column, DatabaseExt, DeoxysBackend, DeoxysStorageError, WriteBatchWithTransaction, DB_UPDATES_BATCH_SIZE};
+
+type Result<T, E = DeoxysStorageError> = std::result::Result<T, E>;
+
+/// Starts every row of the framed layout. Read as the length of the transactions of a legacy row, it would be
+/// absurdly large, so the two layouts can't be mistaken for one another.
+const FRAMED_MAGIC: &[u8; 8] = b"dxframed";
+const HEADER_LEN: usize = FRAMED_MAGIC.len() + 2 * 4;
+
+/// Set once every row of the column has the framed layout.
+const ROW_BLOCK_INNER_FRAMED: &[u8] = b"block_inner_framed";
+/// Progress of [`DeoxysBackend::migrate_l1_handler_message_hashes`], as a [`MessageHashMigration`].
+const ROW_MESSAGE_HASH_MIGRATION: &[u8] = b"message_hash_migration";
+
+#[derive(Serialize, Deserialize)]
+enum MessageHashMigration {
+    /// The blocks from this one on still have the legacy receipts.
+    From(u64),
+    Done,
+}
+
+/// The receipts written by older versions, whose L1 handler receipts hold the keccak hash of their message as a felt.
+/// It was zeroed when it did not fit, so the migration computes it again from the L1 handler transaction.
+mod legacy {
+    use dp_receipt::{
+        DeclareTransactionReceipt, DeployAccountTransactionReceipt, DeployTransactionReceipt, Event,
+        ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt, MsgToL1,
+    };
+    use dp_transactions::Transaction;
+    use serde::{Deserialize, Serialize};
+    use starknet_types_core::felt::Felt;
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) enum TransactionReceipt {
+        Invoke(InvokeTransactionReceipt),
+        L1Handler(L1HandlerTransactionReceipt),
+        Declare(DeclareTransactionReceipt),
+        Deploy(DeployTransactionReceipt),
+        DeployAccount(DeployAccountTransactionReceipt),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct L1HandlerTransactionReceipt {
+        pub message_hash: Felt,
+        pub transaction_hash: Felt,
+        pub actual_fee: FeePayment,
+        pub messages_sent: Vec<MsgToL1>,
+        pub events: Vec<Event>,
+        pub execution_resources: ExecutionResources,
+        pub execution_result: ExecutionResult,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct DeoxysBlockInner {
+        pub transactions: Vec<Transaction>,
+        pub receipts: Vec<TransactionReceipt>,
+    }
+}
+
+/// Converts the legacy receipts of a block, hashing the message of the L1 handler transaction at the index of each L1
+/// handler receipt.
+fn convert_legacy_receipts(
+    transactions: &[Transaction],
+    receipts: Vec<legacy::TransactionReceipt>,
+) -> Result<Vec<TransactionReceipt>> {
+    receipts
+        .into_iter()
+        .enumerate()
+        .map(|(index, receipt)| {
+            Ok(match receipt {
+                legacy::TransactionReceipt::Invoke(receipt) => TransactionReceipt::Invoke(receipt),
+                legacy::TransactionReceipt::Declare(receipt) => TransactionReceipt::Declare(receipt),
+                legacy::TransactionReceipt::Deploy(receipt) => TransactionReceipt::Deploy(receipt),
+                legacy::TransactionReceipt::DeployAccount(receipt) => TransactionReceipt::DeployAccount(receipt),
+                legacy::TransactionReceipt::L1Handler(receipt) => {
+                    let Some(Transaction::L1Handler(transaction)) = transactions.get(index) else {
+                        return Err(DeoxysStorageError::inconsistent(
+                            "L1 handler receipt without an L1 handler transaction",
+                        ));
+                    };
+                    let message_hash = dp_receipt::l1_handler_message_hash(
+                        &transaction.calldata,
+                        transaction.contract_address,
+                        transaction.entry_point_selector,
+                        transaction.nonce,
+                    );
+                    TransactionReceipt::L1Handler(dp_receipt::L1HandlerTransactionReceipt {
+                        message_hash,
+                        transaction_hash: receipt.transaction_hash,
+                        actual_fee: receipt.actual_fee,
+                        messages_sent: receipt.messages_sent,
+                        events: receipt.events,
+                        execution_resources: receipt.execution_resources,
+                        execution_result: receipt.execution_result,
+                    })
+                }
+            })
+        })
+        .collect()
+}
+
+/// Decodes a row of either layout written with the legacy receipts.
+fn decode_legacy_block_inner(bytes: &[u8]) -> Result<DeoxysBlockInner> {
+    let (transactions, receipts) = if FramedBlockInner::is_framed(bytes) {
+        let framed = FramedBlockInner::parse(bytes)?;
+        let receipts = (0..framed.n_receipts)
+            .map(|index| framed.item::<legacy::TransactionReceipt>(framed.n_transactions + index))
+            .collect::<Result<Vec<_>>>()?;
+        (framed.transactions()?, receipts)
+    } else {
+        let inner: legacy::DeoxysBlockInner = bincode::deserialize(bytes)?;
+        (inner.transactions, inner.receipts)
+    };
+    let receipts = convert_legacy_receipts(&transactions, receipts)?;
+    Ok(DeoxysBlockInner::new(transactions, receipts))
+}
+
+fn len_to_u32(len: usize) -> Result<u32> {
+    u32::try_from(len).map_err(|_| DeoxysStorageError::inconsistent("Block too large for the framed layout"))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<usize> {
+    let bytes = bytes.get(pos..pos + 4)?;
+    // UNWRAP: the slice is 4 bytes long.
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+pub(crate) fn encode_block_inner(inner: &DeoxysBlockInner) -> Result<Vec<u8>> {
+    let transactions = inner.transactions.iter().map(bincode::serialize);
+    let receipts = inner.receipts.iter().map(bincode::serialize);
+    let items = transactions.chain(receipts).collect::<Result<Vec<_>, _>>()?;
+    encode_framed(inner.transactions.len(), inner.receipts.len(), items)
+}
+
+/// `items` are the encoded transactions then the encoded receipts.
+fn encode_framed(n_transactions: usize, n_receipts: usize, items: Vec<Vec<u8>>) -> Result<Vec<u8>> {
+    let n_items = items.len();
+    let data_len: usize = items.iter().map(Vec::len).sum();
+    let mut out = Vec::with_capacity(HEADER_LEN + 4 * n_items + data_len);
+    out.extend_from_slice(FRAMED_MAGIC);
+    out.extend_from_slice(&len_to_u32(n_transactions)?.to_le_bytes());
+    out.extend_from_slice(&len_to_u32(n_receipts)?.to_le_bytes());
+    let mut end = 0;
+    for item in &items {
+        end += item.len();
+        out.extend_from_slice(&len_to_u32(end)?.to_le_bytes());
+    }
+    for item in items {
+        out.extend_from_slice(&item);
+    }
+    Ok(out)
+}
+
+/// A row of the framed layout, whose items are only decoded when they are read.
+pub(crate) struct FramedBlockInner<'a> {
+    n_transactions: usize,
+    n_receipts: usize,
+    ends: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> FramedBlockInner<'a> {
+    pub(crate) fn is_framed(bytes: &[u8]) -> bool {
+        bytes.starts_with(FRAMED_MAGIC)
+    }
+
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let malformed = || DeoxysStorageError::inconsistent("Malformed block transactions row");
+        if !Self::is_framed(bytes) {
+            return Err(DeoxysStorageError::inconsistent("Block transactions row in the legacy layout"));
+        }
+        let n_transactions = read_u32(bytes, FRAMED_MAGIC.len()).ok_or_else(malformed)?;
+        let n_receipts = read_u32(bytes, FRAMED_MAGIC.len() + 4).ok_or_else(malformed)?;
+        let ends_len = 4 * (n_transactions + n_receipts);
+        let ends = bytes.get(HEADER_LEN..HEADER_LEN + ends_len).ok_or_else(malformed)?;
+        let data = &bytes[HEADER_LEN + ends_len..];
+        Ok(Self { n_transactions, n_receipts, ends, data })
+    }
+
+    fn item<T: DeserializeOwned>(&self, item: usize) -> Result<T> {
+        let start = if item == 0 { Some(0) } else { read_u32(self.ends, 4 * (item - 1)) };
+        let bytes = start
+            .zip(read_u32(self.ends, 4 * item))
+            .and_then(|(start, end)| self.data.get(start..end))
+            .ok_or_else(|| DeoxysStorageError::inconsistent("Malformed block transactions row"))?;
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    /// `None` when the block has no transaction at `index`.
+    pub(crate) fn transaction(&self, index: usize) -> Result<Option<Transaction>> {
+        (index < self.n_transactions).then(|| self.item(index)).transpose()
+    }
+
+    /// `None` when the block has no receipt at `index`.
+    pub(crate) fn receipt(&self, index: usize) -> Result<Option<TransactionReceipt>> {
+        (index < self.n_receipts).then(|| self.item(self.n_transactions + index)).transpose()
+    }
+
+    pub(crate) fn transactions(&self) -> Result<Vec<Transaction>> {
+        (0..self.n_transactions).map(|index| self.item(index)).collect()
+    }
+
+    pub(crate) fn receipts(&self) -> Result<Vec<TransactionReceipt>> {
+        (0..self.n_receipts).map(|index| self.item(self.n_transactions + index)).collect()
+    }
+
+    pub(crate) fn decode(&self) -> Result<DeoxysBlockInner> {
+        Ok(DeoxysBlockInner::new(self.transactions()?, self.receipts()?))
+    }
+}
+
+impl DeoxysBackend {
+    /// Rewrites the blocks whose receipts were written with the hash of the L1 handler messages as a felt, in the
+    /// framed layout. It runs before [`Self::migrate_block_inner_layout`], which can't decode these receipts.
+    ///
+    /// Both encodings of the receipts decode alike, so the progress is stored in the batches that rewrite the blocks,
+    /// and an interrupted migration resumes from it. The pending block is removed, the sync fetches it again.
+    pub(crate) fn migrate_l1_handler_message_hashes(&self) -> Result<()> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        let from = match self.db.get_cf(&meta, ROW_MESSAGE_HASH_MIGRATION)? {
+            Some(bytes) => match bincode::deserialize(&bytes)? {
+                MessageHashMigration::From(block_n) => block_n,
+                MessageHashMigration::Done => return Ok(()),
+            },
+            None => 0,
+        };
+
+        let col = self.db.get_column(Column::BlockNToBlockInner);
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        let mut tx = WriteBatchWithTransaction::default();
+        self.block_db_pending_removal(&mut tx);
+        self.pending_state_removal(&mut tx)?;
+        let tip = self.get_latest_block_n()?;
+        let mut migrated = 0usize;
+        for block_n in tip.map_or(0..0, |tip| from..tip + 1) {
+            let key = bincode::serialize(&BlockN(block_n))?;
+            let Some(value) = self.db.get_cf(&col, &key)? else { continue };
+            if migrated == 0 {
+                log::info!("⏳ Migrating the receipts of the L1 handler transactions from block {block_n}...");
+            }
+            tx.put_cf(&col, key, encode_block_inner(&decode_legacy_block_inner(&value)?)?);
+            migrated += 1;
+            if migrated % DB_UPDATES_BATCH_SIZE == 0 {
+                tx.put_cf(
+                    &meta,
+                    ROW_MESSAGE_HASH_MIGRATION,
+                    bincode::serialize(&MessageHashMigration::From(block_n + 1))?,
+                );
+                self.db.write_opt(std::mem::take(&mut tx), &writeopts)?;
+            }
+        }
+        tx.put_cf(&meta, ROW_MESSAGE_HASH_MIGRATION, bincode::serialize(&MessageHashMigration::Done)?);
+        self.db.write_opt(tx, &writeopts)?;
+        if migrated > 0 {
+            log::info!("✅ Migrated the receipts of {migrated} blocks");
+        }
+        Ok(())
+    }
+
+    /// Rewrites the rows of [`Column::BlockNToBlockInner`] that are still in the legacy layout. The rows that were
+    /// already rewritten are skipped, an interrupted migration resumes on the next start.
+    pub(crate) fn migrate_block_inner_layout(&self) -> Result<()> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        if self.db.get_cf(&meta, ROW_BLOCK_INNER_FRAMED)?.is_some() {
+            return Ok(());
+        }
+
+        let col = self.db.get_column(Column::BlockNToBlockInner);
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        let mut tx = WriteBatchWithTransaction::default();
+        let mut migrated = 0usize;
+        for res in self.db.iterator_cf(&col, IteratorMode::Start) {
+            let (key, value) = res?;
+            if FramedBlockInner::is_framed(&value) {
+                continue;
+            }
+            if migrated == 0 {
+                log::info!("⏳ Migrating the stored block transactions to the framed layout...");
+            }
+            let inner: DeoxysBlockInner = bincode::deserialize(&value)?;
+            tx.put_cf(&col, key, encode_block_inner(&inner)?);
+            migrated += 1;
+            if migrated % DB_UPDATES_BATCH_SIZE == 0 {
+                self.db.write_opt(std::mem::take(&mut tx), &writeopts)?;
+            }
+        }
+        tx.put_cf(&meta, ROW_BLOCK_INNER_FRAMED, b"");
+        self.db.write_opt(tx, &writeopts)?;
+        if migrated > 0 {
+            log::info!("✅ Migrated the transactions of {migrated} blocks");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use dp_block::{BlockId, BlockN, DeoxysBlock, DeoxysBlockInfo, Header};
+    use dp_receipt::{ExecutionResult, FeePayment, InvokeTransactionReceipt, L1HandlerTransactionReceipt, PriceUnit};
+    use dp_state_update::StateDiff;
+    use dp_transactions::L1HandlerTransaction;
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::testing::temp_database;
+
+    fn block_inner(n_transactions: u64) -> DeoxysBlockInner {
+        let transactions = (0..n_transactions)
+            .map(|nonce| {
+                Transaction::L1Handler(L1HandlerTransaction {
+                    version: Felt::ZERO,
+                    nonce,
+                    contract_address: Felt::ONE,
+                    entry_point_selector: Felt::TWO,
+                    calldata: vec![Felt::from(nonce); nonce as usize],
+                })
+            })
+            .collect();
+        let receipts = (0..n_transactions)
+            .map(|n| {
+                TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                    transaction_hash: Felt::from(n),
+                    actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                    messages_sent: vec![],
+                    events: vec![],
+                    execution_resources: Default::default(),
+                    execution_result: ExecutionResult::Succeeded,
+                })
+            })
+            .collect();
+        DeoxysBlockInner::new(transactions, receipts)
+    }
+
+    #[test]
+    fn test_framed_block_inner() {
+        let inner = block_inner(3);
+        let encoded = encode_block_inner(&inner).unwrap();
+        let framed = FramedBlockInner::parse(&encoded).unwrap();
+        assert_eq!(framed.n_transactions, 3);
+        assert_eq!(framed.transaction(2).unwrap().as_ref(), Some(&inner.transactions[2]));
+        assert_eq!(framed.receipt(0).unwrap().as_ref(), Some(&inner.receipts[0]));
+        assert_eq!(framed.transaction(3).unwrap(), None);
+        assert_eq!(framed.receipt(3).unwrap(), None);
+        let decoded = framed.decode().unwrap();
+        assert_eq!(decoded.transactions, inner.transactions);
+        assert_eq!(decoded.receipts, inner.receipts);
+
+        let empty = encode_block_inner(&DeoxysBlockInner::new(vec![], vec![])).unwrap();
+        assert_eq!(FramedBlockInner::parse(&empty).unwrap().decode().unwrap().transactions, vec![]);
+
+        let legacy = bincode::serialize(&inner).unwrap();
+        assert!(!FramedBlockInner::is_framed(&legacy));
+        assert!(FramedBlockInner::parse(&legacy).is_err());
+        assert!(FramedBlockInner::parse(&encoded[..encoded.len() - 1]).unwrap().receipt(2).is_err());
+    }
+
+    #[test]
+    fn test_reading_a_transaction_decodes_only_that_transaction() {
+        let inner = block_inner(300);
+        let mut encoded = encode_block_inner(&inner).unwrap();
+        // Every other item is garbage, reading one transaction does not notice it.
+        let framed = FramedBlockInner::parse(&encoded).unwrap();
+        let (start, end) = (read_u32(framed.ends, 4 * 149).unwrap(), read_u32(framed.ends, 4 * 150).unwrap());
+        let data_start = encoded.len() - framed.data.len();
+        let kept = encoded[data_start + start..data_start + end].to_vec();
+        encoded[data_start..].fill(0xff);
+        encoded[data_start + start..data_start + end].copy_from_slice(&kept);
+
+        let framed = FramedBlockInner::parse(&encoded).unwrap();
+        assert_eq!(framed.transaction(150).unwrap().as_ref(), Some(&inner.transactions[150]));
+        assert!(framed.transaction(151).is_err());
+        assert!(framed.decode().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_rows() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        for block_number in 0..2 {
+            let info = DeoxysBlockInfo::new(Header { block_number, ..Default::default() }, vec![], Felt::ONE);
+            let block = DeoxysBlock::new(info, block_inner(block_number + 2));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+        // Block 1 was written by an older version, the migration was interrupted before.
+        let col = backend.db.get_column(Column::BlockNToBlockInner);
+        let key = bincode::serialize(&BlockN(1)).unwrap();
+        backend.db.put_cf(&col, &key, bincode::serialize(&block_inner(3)).unwrap()).unwrap();
+        let meta = backend.db.get_column(Column::BlockStorageMeta);
+        backend.db.delete_cf(&meta, ROW_BLOCK_INNER_FRAMED).unwrap();
+        assert!(backend.get_block_inner(&BlockId::Number(1)).is_err());
+
+        backend.migrate_block_inner_layout().unwrap();
+        assert!(FramedBlockInner::is_framed(&backend.db.get_cf(&col, &key).unwrap().unwrap()));
+        let inner = backend.get_block_inner(&BlockId::Number(1)).unwrap().unwrap();
+        assert_eq!(inner.transactions, block_inner(3).transactions);
+        assert_eq!(
+            backend.get_transaction(&BlockId::Number(0), 1).unwrap(),
+            Some(block_inner(2).transactions[1].clone())
+        );
+        assert!(backend.db.get_cf(&meta, ROW_BLOCK_INNER_FRAMED).unwrap().is_some());
+    }
+
+    fn l1_handler_transaction(nonce: u64) -> L1HandlerTransaction {
+        L1HandlerTransaction {
+            version: Felt::ZERO,
+            nonce,
+            contract_address: Felt::from_hex_unchecked(
+                "0x73314940630fd6dcda0d772d4c972c4e0a9946bef9dabf4ef84eda8ef542b82",
+            ),
+            entry_point_selector: Felt::from_hex_unchecked(
+                "0x2d757788a8d8d6f21d1cd40bce38a8222d70654214e96ff95d8086e684fbee5",
+            ),
+            calldata: vec![Felt::from_hex_unchecked("0xae0ee0a63a2ce6baeeffe56e7714fb4efe48d419"), Felt::from(nonce)],
+        }
+    }
+
+    fn legacy_receipt(transaction_hash: Felt, message_hash: Felt) -> legacy::TransactionReceipt {
+        legacy::TransactionReceipt::L1Handler(legacy::L1HandlerTransactionReceipt {
+            message_hash,
+            transaction_hash,
+            actual_fee: FeePayment { amount: Felt::ZERO, unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events: vec![],
+            execution_resources: Default::default(),
+            execution_result: ExecutionResult::Succeeded,
+        })
+    }
+
+    fn expected_receipt(transaction_hash: Felt, transaction: &L1HandlerTransaction) -> TransactionReceipt {
+        TransactionReceipt::L1Handler(L1HandlerTransactionReceipt {
+            message_hash: dp_receipt::l1_handler_message_hash(
+                &transaction.calldata,
+                transaction.contract_address,
+                transaction.entry_point_selector,
+                transaction.nonce,
+            ),
+            transaction_hash,
+            actual_fee: FeePayment { amount: Felt::ZERO, unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events: vec![],
+            execution_resources: Default::default(),
+            execution_result: ExecutionResult::Succeeded,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_migrate_l1_handler_message_hashes() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        for block_number in 0..3 {
+            let transaction = l1_handler_transaction(block_number);
+            let inner = DeoxysBlockInner::new(
+                vec![Transaction::L1Handler(transaction.clone())],
+                vec![expected_receipt(Felt::from(block_number), &transaction)],
+            );
+            let info = DeoxysBlockInfo::new(Header { block_number, ..Default::default() }, vec![], Felt::ONE);
+            backend.store_block(DeoxysBlock::new(info, inner).into(), StateDiff::default(), vec![]).unwrap();
+        }
+
+        // Block 0 was migrated before the migration was interrupted. Blocks 1 and 2 were written by older versions, in
+        // the framed and in the legacy layout, and the hash of block 1 did not fit in a felt.
+        let col = backend.db.get_column(Column::BlockNToBlockInner);
+        let transactions = |block_n| vec![Transaction::L1Handler(l1_handler_transaction(block_n))];
+        let items = vec![
+            bincode::serialize(&transactions(1)[0]).unwrap(),
+            bincode::serialize(&legacy_receipt(Felt::ONE, Felt::ZERO)).unwrap(),
+        ];
+        backend.db.put_cf(&col, bincode::serialize(&BlockN(1)).unwrap(), encode_framed(1, 1, items).unwrap()).unwrap();
+        let legacy = legacy::DeoxysBlockInner {
+            transactions: transactions(2),
+            receipts: vec![legacy_receipt(Felt::TWO, Felt::from(42u64))],
+        };
+        backend.db.put_cf(&col, bincode::serialize(&BlockN(2)).unwrap(), bincode::serialize(&legacy).unwrap()).unwrap();
+        let meta = backend.db.get_column(Column::BlockStorageMeta);
+        let progress = bincode::serialize(&MessageHashMigration::From(1)).unwrap();
+        backend.db.put_cf(&meta, ROW_MESSAGE_HASH_MIGRATION, progress).unwrap();
+
+        backend.migrate_l1_handler_message_hashes().unwrap();
+        for block_n in 0..3 {
+            let inner = backend.get_block_inner(&BlockId::Number(block_n)).unwrap().unwrap();
+            assert_eq!(inner.receipts, vec![expected_receipt(Felt::from(block_n), &l1_handler_transaction(block_n))]);
+            assert_eq!(inner.transactions, transactions(block_n));
+        }
+        assert!(matches!(
+            bincode::deserialize::<MessageHashMigration>(
+                &backend.db.get_cf(&meta, ROW_MESSAGE_HASH_MIGRATION).unwrap().unwrap()
+            )
+            .unwrap(),
+            MessageHashMigration::Done
+        ));
+
+        // Once done, the migration does not touch the blocks anymore.
+        backend.migrate_l1_handler_message_hashes().unwrap();
+        let inner = backend.get_block_inner(&BlockId::Number(1)).unwrap().unwrap();
+        assert_eq!(inner.receipts, vec![expected_receipt(Felt::ONE, &l1_handler_transaction(1))]);
+    }
+
+    #[test]
+    fn test_legacy_l1_handler_receipt_without_its_transaction() {
+        let transactions = vec![Transaction::L1Handler(l1_handler_transaction(0))];
+        let receipts = vec![legacy_receipt(Felt::ZERO, Felt::ZERO), legacy_receipt(Felt::ONE, Felt::ZERO)];
+        assert!(convert_legacy_receipts(&transactions, receipts).is_err());
+    }
+}
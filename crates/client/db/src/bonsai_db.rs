@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use bonsai_trie::id::BasicId;
 use bonsai_trie::{BonsaiDatabase, BonsaiPersistentDatabase, ByteVec, DatabaseKey};
 use rocksdb::{Direction, IteratorMode, WriteOptions};
@@ -22,23 +24,23 @@ impl DatabaseKeyMapping {
     }
 }
 
-pub struct BonsaiDb<'db> {
-    db: &'db DB,
+pub struct BonsaiDb {
+    db: Arc<DB>,
     /// Mapping from `DatabaseKey` => rocksdb column name
     column_mapping: DatabaseKeyMapping,
     // snapshots: BTreeMap<BasicId, SnapshotWithThreadMode<'db, DB>>,
     write_opt: WriteOptions,
 }
 
-impl<'db> BonsaiDb<'db> {
-    pub(crate) fn new(db: &'db DB, column_mapping: DatabaseKeyMapping) -> Self {
+impl BonsaiDb {
+    pub(crate) fn new(db: Arc<DB>, column_mapping: DatabaseKeyMapping) -> Self {
         let mut write_opt = WriteOptions::default();
         write_opt.disable_wal(true);
         Self { db, column_mapping, write_opt }
     }
 }
 
-impl BonsaiDatabase for BonsaiDb<'_> {
+impl BonsaiDatabase for BonsaiDb {
     type Batch = WriteBatchWithTransaction;
     type DatabaseError = DbError;
 
@@ -245,10 +247,7 @@ impl BonsaiDatabase for BonsaiDb<'_> {
 //     }
 // }
 
-impl<'db> BonsaiPersistentDatabase<BasicId> for BonsaiDb<'db>
-where
-    Self: 'db,
-{
+impl BonsaiPersistentDatabase<BasicId> for BonsaiDb {
     type Transaction = Self;
     type DatabaseError = DbError;
 
@@ -261,7 +260,7 @@ where
     fn transaction(&self, _id: BasicId) -> Option<Self::Transaction> {
         log::trace!("Generating RocksDB transaction");
         // TODO: we lie about supporting transactions here
-        Some(BonsaiDb::new(self.db, self.column_mapping.clone()))
+        Some(BonsaiDb::new(Arc::clone(&self.db), self.column_mapping.clone()))
         // if let Some(snapshot) = self.snapshots.get(&id) {
         //     let write_opts = WriteOptions::default();
         //     let mut txn_opts = OptimisticTransactionOptions::default();
@@ -0,0 +1,185 @@
+//! Where the chain is, as seen by the node: its latest block, the highest block known by the sync and the last block
+//! confirmed on L1. The backend publishes the blocks it stores and the L1 confirmations it writes, the sync publishes
+//! the blocks it sees on the feeder gateway. They are shared through a watch channel: a reader gets all of them from
+//! the same snapshot, instead of reading them one by one while they move.
+
+use starknet_types_core::felt::Felt;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChainHead {
+    /// Hash and number of the latest block of the database, `None` when it is empty.
+    pub latest: Option<(Felt, u64)>,
+    /// Highest block seen on the feeder gateway since the node started, `None` until the sync reaches the feeder.
+    pub highest_known: Option<u64>,
+    /// Last block whose state update was confirmed on L1, `None` before the first confirmation.
+    pub l1_confirmed: Option<u64>,
+}
+
+impl ChainHead {
+    pub fn latest_block_n(&self) -> Option<u64> {
+        self.latest.map(|(_, block_n)| block_n)
+    }
+
+    /// How many blocks the database is behind the highest known block, `None` while the highest known block is
+    /// unknown. An empty database is behind by one more block than the highest known block.
+    pub fn blocks_behind(&self) -> Option<u64> {
+        self.highest_known.map(|highest| match self.latest_block_n() {
+            Some(latest) => highest.saturating_sub(latest),
+            None => highest + 1,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ChainHeadProvider {
+    sender: watch::Sender<ChainHead>,
+}
+
+impl ChainHeadProvider {
+    pub(crate) fn new(head: ChainHead) -> Self {
+        Self { sender: watch::channel(head).0 }
+    }
+
+    /// The receiver is notified every time one of the fields changes.
+    pub fn subscribe(&self) -> watch::Receiver<ChainHead> {
+        self.sender.subscribe()
+    }
+
+    pub fn get(&self) -> ChainHead {
+        *self.sender.borrow()
+    }
+
+    /// Blocks lower than the highest known block are ignored.
+    pub fn update_highest_known_block(&self, block_n: u64) {
+        self.sender.send_if_modified(|head| {
+            let highest = head.highest_known.map_or(block_n, |highest| highest.max(block_n));
+            let modified = head.highest_known != Some(highest);
+            head.highest_known = Some(highest);
+            modified
+        });
+    }
+
+    /// The latest block moves back when blocks are reverted.
+    pub(crate) fn update_latest(&self, block_hash: Felt, block_n: u64) {
+        self.sender.send_if_modified(|head| {
+            let modified = head.latest != Some((block_hash, block_n));
+            head.latest = Some((block_hash, block_n));
+            modified
+        });
+    }
+
+    pub(crate) fn update_l1_confirmed(&self, block_n: u64) {
+        self.sender.send_if_modified(|head| {
+            let modified = head.l1_confirmed != Some(block_n);
+            head.l1_confirmed = Some(block_n);
+            modified
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::StateDiff;
+
+    use super::*;
+    use crate::DatabaseService;
+
+    #[test]
+    fn highest_known_block_never_goes_back() {
+        let provider = ChainHeadProvider::new(ChainHead::default());
+        assert_eq!(provider.get().highest_known, None);
+        provider.update_highest_known_block(10);
+        provider.update_highest_known_block(4);
+        assert_eq!(provider.get().highest_known, Some(10));
+        provider.update_highest_known_block(11);
+        assert_eq!(provider.get().highest_known, Some(11));
+    }
+
+    #[tokio::test]
+    async fn readers_see_the_updates_of_both_workers() {
+        let provider = Arc::new(ChainHeadProvider::new(ChainHead::default()));
+        let mut receiver = provider.subscribe();
+        receiver.mark_unchanged();
+
+        // The sync sees the blocks on the feeder before it stores them, the L1 worker confirms the stored ones.
+        let sync = tokio::spawn({
+            let provider = Arc::clone(&provider);
+            async move {
+                for block_n in 0..100 {
+                    provider.update_highest_known_block(block_n + 1);
+                    provider.update_latest(Felt::from(block_n), block_n);
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let l1 = tokio::spawn({
+            let provider = Arc::clone(&provider);
+            async move {
+                for block_n in (0..100).step_by(10) {
+                    while provider.get().latest_block_n() < Some(block_n) {
+                        tokio::task::yield_now().await;
+                    }
+                    provider.update_l1_confirmed(block_n);
+                }
+            }
+        });
+
+        let mut snapshots = vec![];
+        while receiver.changed().await.is_ok() {
+            let head = *receiver.borrow_and_update();
+            snapshots.push(head);
+            if head.latest_block_n() == Some(99) && head.l1_confirmed == Some(90) {
+                break;
+            }
+        }
+        sync.await.unwrap();
+        l1.await.unwrap();
+
+        for head in &snapshots {
+            let (block_hash, latest) = head.latest.unwrap_or((Felt::ZERO, 0));
+            assert_eq!(block_hash, Felt::from(latest));
+            // Every snapshot holds the values of a single moment.
+            assert!(head.highest_known >= head.latest_block_n(), "{head:?}");
+            assert!(head.l1_confirmed <= head.latest_block_n(), "{head:?}");
+        }
+        assert!(snapshots.windows(2).all(|w| w[0].highest_known <= w[1].highest_known));
+        assert_eq!(
+            provider.get(),
+            ChainHead { latest: Some((Felt::from(99), 99)), highest_known: Some(100), l1_confirmed: Some(90) }
+        );
+    }
+
+    #[tokio::test]
+    async fn backend_publishes_its_blocks_and_l1_confirmations() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = db.backend();
+        let mut receiver = backend.chain_head().subscribe();
+        assert_eq!(*receiver.borrow_and_update(), ChainHead::default());
+
+        for block_number in 0..3 {
+            let info =
+                DeoxysBlockInfo::new(Header { block_number, ..Default::default() }, vec![], Felt::from(block_number));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+        backend.write_last_confirmed_block(1).unwrap();
+        assert!(receiver.has_changed().unwrap());
+        let head = ChainHead { latest: Some((Felt::TWO, 2)), highest_known: None, l1_confirmed: Some(1) };
+        assert_eq!(*receiver.borrow_and_update(), head);
+
+        backend.revert_to(1, false).unwrap();
+        assert_eq!(backend.chain_head().get().latest, Some((Felt::ONE, 1)));
+
+        // The head is read back from the database when the node restarts.
+        drop(db);
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        assert_eq!(db.backend().chain_head().get(), ChainHead { latest: Some((Felt::ONE, 1)), ..head });
+    }
+}
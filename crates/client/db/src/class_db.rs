@@ -1,8 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use dp_class::{ClassInfo, CompiledClass};
+use dp_block::{BlockId, BlockN, BlockTag};
+use dp_class::{ClassInfo, CompiledClass, ConvertedClass};
+use dp_state_update::StateDiff;
+use dp_transactions::Transaction;
+use dp_utils::lock::MutexExt;
+use dp_utils::{spawn_rayon_task, wait_or_graceful_shutdown};
 use rayon::{iter::ParallelIterator, slice::ParallelSlice};
-use rocksdb::WriteOptions;
+use rocksdb::{Direction, IteratorMode, WriteOptions};
 use starknet_core::types::Felt;
 
 use crate::{
@@ -12,6 +18,56 @@ use crate::{
 
 const LAST_KEY: &[u8] = &[0xFF; 64];
 
+const ROW_CLASS_DECLARATIONS_BACKFILL: &[u8] = b"class_declarations_backfill";
+/// Blocks scanned by the backfill of the class declarations in a single write.
+const BACKFILL_BATCH_SIZE: u64 = 1000;
+
+/// Where a class was declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ClassDeclaration {
+    pub block_n: u64,
+    /// `None` for the classes declared without a declare transaction, such as the classes of a genesis block.
+    pub transaction_hash: Option<Felt>,
+}
+
+/// The compiled class hash of a confirmed class, stored apart from the class so that it is read without
+/// deserializing the class.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ClassCompiledHash {
+    compiled_class_hash: Felt,
+    block_number: u64,
+}
+
+/// The blocks stored before the class declarations were indexed, `until` excluded, are scanned from `next`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ClassDeclarationsBackfill {
+    next: u64,
+    until: u64,
+}
+
+/// The classes declared by a block, with the hash of the transaction which declared each of them.
+pub(crate) fn block_class_declarations(
+    state_diff: &StateDiff,
+    transactions: &[Transaction],
+    tx_hashes: &[Felt],
+) -> Vec<(Felt, Option<Felt>)> {
+    let declare_txs: HashMap<_, _> = transactions
+        .iter()
+        .zip(tx_hashes)
+        .filter_map(|(tx, tx_hash)| match tx {
+            Transaction::Declare(tx) => Some((*tx.class_hash(), *tx_hash)),
+            _ => None,
+        })
+        .collect();
+    state_diff
+        .deprecated_declared_classes()
+        .iter()
+        .copied()
+        .chain(state_diff.declared_classes().iter().map(|item| item.class_hash))
+        .map(|class_hash| (class_hash, declare_txs.get(&class_hash).copied()))
+        .collect()
+}
+
 impl DeoxysBackend {
     fn class_db_get_encoded_kv<V: serde::de::DeserializeOwned>(
         &self,
@@ -34,7 +90,7 @@ impl DeoxysBackend {
 
                 None
             }
-            DbBlockId::BlockN(block_n) => Some(*block_n),
+            DbBlockId::BlockN(block_n) => Some(block_n.0),
         };
         log::debug!("get encoded: not in pending");
 
@@ -75,6 +131,27 @@ impl DeoxysBackend {
         Ok(Some(info))
     }
 
+    /// The compiled class hash of a class declared on top of block `id`, zero for the legacy classes. Unlike
+    /// [`Self::get_class_info`], the class itself is not read.
+    pub fn get_class_compiled_class_hash(
+        &self,
+        id: &impl DbBlockIdResolvable,
+        class_hash: &Felt,
+    ) -> Result<Option<Felt>, DeoxysStorageError> {
+        let Some(id) = id.resolve_db_block_id(self)? else { return Ok(None) };
+
+        let col = self.db.get_column(Column::ContractClassHashes);
+        if let Some(stored) = self.db.get_pinned_cf(&col, bincode::serialize(class_hash)?)? {
+            let stored: ClassCompiledHash = bincode::deserialize(&stored)?;
+            return Ok(match id {
+                DbBlockId::BlockN(block_n) if stored.block_number > block_n.0 => None,
+                _ => Some(stored.compiled_class_hash),
+            });
+        }
+        // The pending classes and the classes stored before their compiled class hash was are read from their info.
+        Ok(self.get_class_info(&id, class_hash)?.map(|info| info.compiled_class_hash))
+    }
+
     pub fn contains_class(&self, id: &impl DbBlockIdResolvable, class_hash: &Felt) -> Result<bool, DeoxysStorageError> {
         // TODO(perf): make fast path, this only needs one db contains() call and no deserialization in most cases (block id pending/latest)
         Ok(self.get_class_info(id, class_hash)?.is_some())
@@ -96,17 +173,49 @@ impl DeoxysBackend {
                 Column::PendingClassCompiled,
                 Column::ClassCompiled,
             )?
-            .ok_or(DeoxysStorageError::InconsistentStorage("Class compiled not found while class info is".into()))?;
+            .ok_or_else(|| DeoxysStorageError::inconsistent("Class compiled not found while class info is"))?;
 
         Ok(Some((info, compiled_class)))
     }
 
+    /// Iterates lazily over the classes declared in the confirmed blocks, ordered by class hash. The iteration
+    /// starts right after `start_after`, so that a listing can be resumed from the last class it returned.
+    pub fn iter_classes(
+        &self,
+        start_after: Option<&Felt>,
+    ) -> Result<impl Iterator<Item = Result<(Felt, ClassInfo), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        let start_after = start_after.map(bincode::serialize).transpose()?;
+        let mode = match &start_after {
+            Some(key) => IteratorMode::From(key, Direction::Forward),
+            None => IteratorMode::Start,
+        };
+        let iter = self.db.iterator_cf(&self.db.get_column(Column::ClassInfo), mode);
+
+        Ok(iter.filter(move |res| !matches!((res, &start_after), (Ok((key, _)), Some(start)) if **key == **start)).map(
+            |res| -> Result<(Felt, ClassInfo), DeoxysStorageError> {
+                let (key, value) = res?;
+                Ok((bincode::deserialize(&key)?, bincode::deserialize(&value)?))
+            },
+        ))
+    }
+
+    /// The info of many classes declared in the confirmed blocks, read with a single multi-get. Infos are returned in
+    /// the order of `class_hashes`, `None` for the classes that are not declared.
+    pub fn get_classes_info(&self, class_hashes: &[Felt]) -> Result<Vec<Option<ClassInfo>>, DeoxysStorageError> {
+        let col = self.db.get_column(Column::ClassInfo);
+        let keys = class_hashes.iter().map(bincode::serialize).collect::<Result<Vec<_>, _>>()?;
+        self.db
+            .batched_multi_get_cf(&col, &keys, false)
+            .into_iter()
+            .map(|res| Ok(res?.map(|value| bincode::deserialize(&value)).transpose()?))
+            .collect()
+    }
+
     /// NB: This functions needs to run on the rayon thread pool
     pub(crate) fn store_classes(
         &self,
         block_number: Option<u64>,
-        class_infos: &[(Felt, ClassInfo)],
-        class_compiled: &[(Felt, CompiledClass)],
+        converted_classes: &[ConvertedClass],
         col_info: Column,
         col_compiled: Column,
     ) -> Result<(), DeoxysStorageError> {
@@ -116,39 +225,46 @@ impl DeoxysBackend {
         // Check if the class is already in the db, if so, skip it
         // This check is needed because blocks are fetched and converted in parallel
         let ignore_class: HashSet<_> = if let Some(block_n) = block_number {
-            class_infos
+            converted_classes
                 .iter()
-                .filter_map(|(key, _)| match self.get_class_info(&DbBlockId::BlockN(block_n), key) {
-                    Ok(Some(_)) => Some(*key),
-                    _ => None,
+                .filter_map(|ConvertedClass { class_infos: (key, _), .. }| {
+                    match self.get_class_info(&DbBlockId::BlockN(BlockN(block_n)), key) {
+                        Ok(Some(_)) => Some(*key),
+                        _ => None,
+                    }
                 })
                 .collect()
         } else {
             HashSet::new()
         };
 
-        class_infos.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
-            || self.db.get_column(col_info),
-            |col, chunk| {
+        converted_classes.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
+            || (self.db.get_column(col_info), self.db.get_column(Column::ContractClassHashes)),
+            |(col, col_compiled_hash), chunk| {
                 let mut batch = WriteBatchWithTransaction::default();
-                for (key, value) in chunk {
+                for ConvertedClass { class_infos: (key, value), .. } in chunk {
                     if ignore_class.contains(key) {
                         continue;
                     }
                     let key_bin = bincode::serialize(key)?;
                     // TODO: find a way to avoid this allocation
                     batch.put_cf(col, &key_bin, bincode::serialize(&value)?);
+                    if let Some(block_number) = value.block_number {
+                        let compiled_hash =
+                            ClassCompiledHash { compiled_class_hash: value.compiled_class_hash, block_number };
+                        batch.put_cf(col_compiled_hash, &key_bin, bincode::serialize(&compiled_hash)?);
+                    }
                 }
                 self.db.write_opt(batch, &writeopts)?;
                 Ok::<_, DeoxysStorageError>(())
             },
         )?;
 
-        class_compiled.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
+        converted_classes.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
             || self.db.get_column(col_compiled),
             |col, chunk| {
                 let mut batch = WriteBatchWithTransaction::default();
-                for (key, value) in chunk {
+                for ConvertedClass { class_compiled: (key, value), .. } in chunk {
                     if ignore_class.contains(key) {
                         continue;
                     }
@@ -168,33 +284,259 @@ impl DeoxysBackend {
     pub(crate) fn class_db_store_block(
         &self,
         block_number: u64,
-        class_infos: &[(Felt, ClassInfo)],
-        class_compiled: &[(Felt, CompiledClass)],
+        converted_classes: &[ConvertedClass],
+        class_declarations: &[(Felt, Option<Felt>)],
     ) -> Result<(), DeoxysStorageError> {
-        self.store_classes(Some(block_number), class_infos, class_compiled, Column::ClassInfo, Column::ClassCompiled)
+        self.store_classes(Some(block_number), converted_classes, Column::ClassInfo, Column::ClassCompiled)?;
+        self.store_class_declarations(block_number, class_declarations)
     }
 
+    /// Only the first declaration of a class is kept.
+    fn store_class_declarations(
+        &self,
+        block_n: u64,
+        class_declarations: &[(Felt, Option<Felt>)],
+    ) -> Result<(), DeoxysStorageError> {
+        if class_declarations.is_empty() {
+            return Ok(());
+        }
+        let col = self.db.get_column(Column::ClassDeclaredAt);
+        let mut batch = WriteBatchWithTransaction::default();
+        for (class_hash, transaction_hash) in class_declarations {
+            let key = bincode::serialize(class_hash)?;
+            if let Some(stored) = self.db.get_pinned_cf(&col, &key)? {
+                if bincode::deserialize::<ClassDeclaration>(&stored)?.block_n <= block_n {
+                    continue;
+                }
+            }
+            let declaration = ClassDeclaration { block_n, transaction_hash: *transaction_hash };
+            batch.put_cf(&col, key, bincode::serialize(&declaration)?);
+        }
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        self.db.write_opt(batch, &writeopts)?;
+        Ok(())
+    }
+
+    /// Where a class of the confirmed blocks was declared. The classes missing from the index, such as the classes
+    /// of a genesis block, are reported in the block they were stored with, without a transaction.
+    pub fn get_class_declaration(&self, class_hash: &Felt) -> Result<Option<ClassDeclaration>, DeoxysStorageError> {
+        let col = self.db.get_column(Column::ClassDeclaredAt);
+        if let Some(stored) = self.db.get_pinned_cf(&col, bincode::serialize(class_hash)?)? {
+            return Ok(Some(bincode::deserialize(&stored)?));
+        }
+        let info = self.get_class_info(&BlockId::Tag(BlockTag::Latest), class_hash)?;
+        Ok(info.and_then(|info| info.block_number).map(|block_n| ClassDeclaration { block_n, transaction_hash: None }))
+    }
+
+    /// Indexes the class declarations of the blocks stored before the index existed, at most `max_blocks` of them.
+    /// Returns the number of blocks scanned, zero once the backfill is done. The blocks stored since are indexed as
+    /// they are imported.
+    ///
     /// NB: This functions needs to run on the rayon thread pool
-    pub(crate) fn class_db_store_pending(
+    pub fn backfill_class_declarations(&self, max_blocks: u64) -> Result<u64, DeoxysStorageError> {
+        // A revert must not happen between the read of the blocks and the write of their declarations.
+        let _lock = self.class_declarations.lock_or_recover();
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        let tip = self.get_latest_block_n()?.map_or(0, |tip| tip + 1);
+        let backfill = match self.db.get_cf(&meta, ROW_CLASS_DECLARATIONS_BACKFILL)? {
+            Some(stored) => bincode::deserialize(&stored)?,
+            None => ClassDeclarationsBackfill { next: 0, until: tip },
+        };
+        // The blocks reverted since are indexed when they are imported again.
+        let until = backfill.until.min(tip);
+        let last = until.min(backfill.next.saturating_add(max_blocks));
+
+        for block_n in backfill.next..last {
+            let id = BlockId::Number(block_n);
+            let state_diff = self.get_block_state_diff(&id)?.ok_or_else(|| {
+                DeoxysStorageError::inconsistent(format!(
+                    "Indexing the classes of block {block_n}, which is not stored"
+                ))
+            })?;
+            if state_diff.declared_classes().is_empty() && state_diff.deprecated_declared_classes().is_empty() {
+                continue;
+            }
+            let (Some(info), Some(inner)) = (self.get_block_info(&id)?, self.get_block_inner(&id)?) else {
+                return Err(DeoxysStorageError::inconsistent(format!(
+                    "Indexing the classes of block {block_n}, which is not stored"
+                )));
+            };
+            let declarations = block_class_declarations(&state_diff, &inner.transactions, info.tx_hashes());
+            self.store_class_declarations(block_n, &declarations)?;
+        }
+
+        let scanned = last.saturating_sub(backfill.next);
+        let backfill = ClassDeclarationsBackfill { next: last.max(backfill.next), until };
+        self.db.put_cf(&meta, ROW_CLASS_DECLARATIONS_BACKFILL, bincode::serialize(&backfill)?)?;
+        Ok(scanned)
+    }
+
+    /// Adds to `batch` the removal of the classes first declared by block `block_number`. The classes declared again
+    /// were stored by an earlier block and are kept.
+    pub(crate) fn class_db_revert_block(
         &self,
+        batch: &mut WriteBatchWithTransaction,
+        block_number: u64,
+        class_hashes: impl IntoIterator<Item = Felt>,
+    ) -> Result<(), DeoxysStorageError> {
+        let col_info = self.db.get_column(Column::ClassInfo);
+        let col_compiled = self.db.get_column(Column::ClassCompiled);
+        let col_declared_at = self.db.get_column(Column::ClassDeclaredAt);
+        let col_compiled_hash = self.db.get_column(Column::ContractClassHashes);
+        for class_hash in class_hashes {
+            let key = bincode::serialize(&class_hash)?;
+            if let Some(declaration) = self.db.get_pinned_cf(&col_declared_at, &key)? {
+                if bincode::deserialize::<ClassDeclaration>(&declaration)?.block_n == block_number {
+                    batch.delete_cf(&col_declared_at, &key);
+                }
+            }
+            let Some(info) = self.db.get_pinned_cf(&col_info, &key)? else { continue };
+            let info: ClassInfo = bincode::deserialize(&info)?;
+            if info.block_number == Some(block_number) {
+                batch.delete_cf(&col_info, &key);
+                batch.delete_cf(&col_compiled, &key);
+                batch.delete_cf(&col_compiled_hash, &key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds to `batch` the writes replacing the classes declared in the pending block.
+    pub(crate) fn class_db_pending_writes(
+        &self,
+        batch: &mut WriteBatchWithTransaction,
         class_infos: &[(Felt, ClassInfo)],
         class_compiled: &[(Felt, CompiledClass)],
     ) -> Result<(), DeoxysStorageError> {
-        self.store_classes(None, class_infos, class_compiled, Column::PendingClassInfo, Column::PendingClassCompiled)
+        let col = self.db.get_column(Column::PendingClassInfo);
+        batch.delete_range_cf(&col, &[] as &[u8], LAST_KEY);
+        for (class_hash, info) in class_infos {
+            batch.put_cf(&col, bincode::serialize(class_hash)?, bincode::serialize(info)?);
+        }
+
+        let col = self.db.get_column(Column::PendingClassCompiled);
+        batch.delete_range_cf(&col, &[] as &[u8], LAST_KEY);
+        for (class_hash, compiled) in class_compiled {
+            batch.put_cf(&col, bincode::serialize(class_hash)?, bincode::serialize(compiled)?);
+        }
+
+        Ok(())
     }
+}
 
-    pub(crate) fn class_db_clear_pending(&self) -> Result<(), DeoxysStorageError> {
-        let mut writeopts = WriteOptions::new();
-        writeopts.disable_wal(true);
+/// Backfills the class declarations of the blocks stored before they were indexed.
+pub(crate) async fn class_declarations_backfill_task(backend: Arc<DeoxysBackend>) -> anyhow::Result<()> {
+    loop {
+        let backend = Arc::clone(&backend);
+        let backfill = spawn_rayon_task(move || backend.backfill_class_declarations(BACKFILL_BATCH_SIZE));
+        let Some(scanned) = wait_or_graceful_shutdown(backfill).await else { return Ok(()) };
+        if scanned? == 0 {
+            return Ok(());
+        }
+    }
+}
 
-        self.db.delete_range_cf_opt(&self.db.get_column(Column::PendingClassInfo), &[] as _, LAST_KEY, &writeopts)?;
-        self.db.delete_range_cf_opt(
-            &self.db.get_column(Column::PendingClassCompiled),
-            &[] as _,
-            LAST_KEY,
-            &writeopts,
-        )?;
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::DeclaredClassItem;
+    use dp_transactions::{DeclareTransaction, DeclareTransactionV0, DeclareTransactionV2};
 
-        Ok(())
+    use super::*;
+    use crate::testing::temp_database;
+
+    fn declare_v0(class_hash: Felt) -> Transaction {
+        Transaction::Declare(DeclareTransaction::V0(DeclareTransactionV0 {
+            sender_address: Felt::ONE,
+            max_fee: Felt::ZERO,
+            signature: vec![],
+            class_hash,
+        }))
+    }
+
+    fn declare_v2(class_hash: Felt) -> Transaction {
+        Transaction::Declare(DeclareTransaction::V2(DeclareTransactionV2 {
+            sender_address: Felt::ONE,
+            compiled_class_hash: Felt::ONE,
+            max_fee: Felt::ZERO,
+            signature: vec![],
+            nonce: Felt::ZERO,
+            class_hash,
+        }))
+    }
+
+    /// Block 0 is a genesis block declaring class `0x10` without a transaction. Block 1 declares the classes `0x20`
+    /// and `0x30` in transactions `0xa1` and `0xa2`, and block 2 declares the legacy class `0x30` again in transaction
+    /// `0xb1`.
+    async fn backend_with_declarations() -> (tempfile::TempDir, Arc<DeoxysBackend>) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let blocks = [
+            (vec![], vec![], StateDiff::new(vec![], vec![Felt::from(0x10)], vec![], vec![], vec![], vec![])),
+            (
+                vec![declare_v2(Felt::from(0x20)), declare_v0(Felt::from(0x30))],
+                vec![Felt::from(0xa1), Felt::from(0xa2)],
+                StateDiff::new(
+                    vec![],
+                    vec![Felt::from(0x30)],
+                    vec![DeclaredClassItem { class_hash: Felt::from(0x20), compiled_class_hash: Felt::ONE }],
+                    vec![],
+                    vec![],
+                    vec![],
+                ),
+            ),
+            (
+                vec![declare_v0(Felt::from(0x30))],
+                vec![Felt::from(0xb1)],
+                StateDiff::new(vec![], vec![Felt::from(0x30)], vec![], vec![], vec![], vec![]),
+            ),
+        ];
+        for (block_n, (transactions, tx_hashes, state_diff)) in blocks.into_iter().enumerate() {
+            let header = Header { block_number: block_n as u64, ..Default::default() };
+            let info = DeoxysBlockInfo::new(header, tx_hashes, Felt::from(block_n));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(transactions, vec![]));
+            backend.store_block(block.into(), state_diff, vec![]).unwrap();
+        }
+        (temp_dir, backend)
+    }
+
+    fn declaration(block_n: u64, transaction_hash: Option<u64>) -> Option<ClassDeclaration> {
+        Some(ClassDeclaration { block_n, transaction_hash: transaction_hash.map(Felt::from) })
+    }
+
+    #[tokio::test]
+    async fn test_class_declarations_are_indexed_at_import() {
+        let (_temp_dir, backend) = backend_with_declarations().await;
+
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x10)).unwrap(), declaration(0, None));
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x20)).unwrap(), declaration(1, Some(0xa1)));
+        // The first declaration is kept.
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x30)).unwrap(), declaration(1, Some(0xa2)));
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x40)).unwrap(), None);
+
+        backend.revert_to(0, false).unwrap();
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x10)).unwrap(), declaration(0, None));
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x20)).unwrap(), None);
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x30)).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_class_declarations_backfill() {
+        let (_temp_dir, backend) = backend_with_declarations().await;
+        // The blocks were stored before the declarations were indexed.
+        let col = backend.db.get_column(Column::ClassDeclaredAt);
+        backend.db.delete_range_cf(&col, &[] as &[u8], LAST_KEY).unwrap();
+        let meta = backend.db.get_column(Column::BlockStorageMeta);
+        backend.db.delete_cf(&meta, ROW_CLASS_DECLARATIONS_BACKFILL).unwrap();
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x20)).unwrap(), None);
+
+        let scanned: Vec<_> = (0..4).map(|_| backend.backfill_class_declarations(2).unwrap()).collect();
+        assert_eq!(scanned, vec![2, 1, 0, 0]);
+
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x10)).unwrap(), declaration(0, None));
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x20)).unwrap(), declaration(1, Some(0xa1)));
+        assert_eq!(backend.get_class_declaration(&Felt::from(0x30)).unwrap(), declaration(1, Some(0xa2)));
     }
 }
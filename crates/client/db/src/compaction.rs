@@ -0,0 +1,487 @@
+//! Scheduling of the background compactions, so that the large compactions of the block and class columns do not
+//! run during the peak RPC hours.
+//!
+//! Flushes and compactions of every column go through a single rate limiter. Its rate is set when the database is
+//! opened: the rocksdb bindings cannot change it afterwards. The number of background jobs, on the other hand, is
+//! adjusted while the node runs: fewer of them run during the quiet hours, and the heavy columns wait for more level 0
+//! files before they are compacted. The schedule can be replaced at any time, it is applied right away.
+//!
+//! The node operator can also compact columns on demand, to drop the tombstones left by a pruning or a migration
+//! without waiting for the background compactions. These go through a column one key range at a time, so that the
+//! blocks are stored in between.
+
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dc_metrics::{IntGaugeVec, MetricsRegistry, Opts, PrometheusError};
+use dp_utils::lock::MutexExt;
+use dp_utils::wait_or_graceful_shutdown;
+use serde::{Deserialize, Serialize};
+
+use crate::{Column, DatabaseExt, DeoxysBackend, DeoxysStorageError};
+
+/// Columns holding large values, whose compactions take the longest.
+const HEAVY_COLUMNS: &[Column] = &[Column::BlockNToBlockInner, Column::ClassInfo, Column::ClassCompiled];
+/// Level 0 files a heavy column waits for before it is compacted, outside of and during the quiet hours. The first
+/// one is the rocksdb default.
+const L0_COMPACTION_TRIGGER: u32 = 4;
+const QUIET_L0_COMPACTION_TRIGGER: u32 = 8;
+
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Files of a column compacted at once by a manual compaction. The blocks are only stored between these chunks.
+const COMPACTION_CHUNK_FILES: usize = 16;
+
+/// A daily time window, in UTC, such as `22:00-06:00`. It may wrap around midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    /// Minutes since midnight.
+    start: u16,
+    end: u16,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid time window `{0}`, expected a window like `22:00-06:00`")]
+pub struct InvalidTimeWindow(String);
+
+impl TimeWindow {
+    fn contains(&self, minute_of_day: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = InvalidTimeWindow;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_time = |time: &str| {
+            let (hours, minutes) = time.trim().split_once(':')?;
+            let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+            (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+        };
+        let invalid = || InvalidTimeWindow(s.into());
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (parse_time(start).ok_or_else(invalid)?, parse_time(end).ok_or_else(invalid)?);
+        if start == end {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = InvalidTimeWindow;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(value: TimeWindow) -> Self {
+        value.to_string()
+    }
+}
+
+/// How many background jobs run, depending on the time of day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactionSchedule {
+    /// Background jobs outside of the quiet hours.
+    pub background_jobs: u32,
+    /// Background jobs during the quiet hours.
+    pub quiet_background_jobs: u32,
+    pub quiet_hours: Vec<TimeWindow>,
+}
+
+impl Default for CompactionSchedule {
+    fn default() -> Self {
+        let cores = std::thread::available_parallelism().map(|e| e.get() as u32).unwrap_or(1);
+        Self { background_jobs: cores, quiet_background_jobs: 2, quiet_hours: vec![] }
+    }
+}
+
+impl CompactionSchedule {
+    fn is_quiet(&self, minute_of_day: u16) -> bool {
+        self.quiet_hours.iter().any(|window| window.contains(minute_of_day))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompactionConfig {
+    /// Flush and compaction I/O rate of the whole database, in bytes per second. `None` to not limit it.
+    pub rate_limit: Option<u64>,
+    pub schedule: CompactionSchedule,
+}
+
+/// The options currently applied to the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AppliedCompactionOptions {
+    background_jobs: u32,
+    l0_compaction_trigger: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompactionMetrics {
+    pub pending_compaction_bytes: IntGaugeVec,
+}
+
+impl CompactionMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            pending_compaction_bytes: registry.register(IntGaugeVec::new(
+                Opts::new(
+                    "deoxys_db_pending_compaction_bytes",
+                    "Estimated bytes that compactions of a RocksDB column need to rewrite",
+                ),
+                &["column"],
+            )?)?,
+        })
+    }
+}
+
+fn minute_of_day(time: SystemTime) -> u16 {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    ((secs % (24 * 60 * 60)) / 60) as u16
+}
+
+impl DeoxysBackend {
+    pub fn compaction_schedule(&self) -> CompactionSchedule {
+        self.compaction_schedule.lock_or_recover().clone()
+    }
+
+    /// Replaces the schedule, and applies it right away.
+    pub fn set_compaction_schedule(&self, schedule: CompactionSchedule) -> Result<(), DeoxysStorageError> {
+        *self.compaction_schedule.lock_or_recover() = schedule;
+        self.apply_compaction_schedule(minute_of_day(SystemTime::now()))
+    }
+
+    /// Sets the options of the schedule for this time of day. They are only written when they change, as rocksdb
+    /// writes a new options file every time.
+    pub(crate) fn apply_compaction_schedule(&self, minute_of_day: u16) -> Result<(), DeoxysStorageError> {
+        let options = {
+            let schedule = self.compaction_schedule.lock_or_recover();
+            if schedule.is_quiet(minute_of_day) {
+                AppliedCompactionOptions {
+                    background_jobs: schedule.quiet_background_jobs,
+                    l0_compaction_trigger: QUIET_L0_COMPACTION_TRIGGER,
+                }
+            } else {
+                AppliedCompactionOptions {
+                    background_jobs: schedule.background_jobs,
+                    l0_compaction_trigger: L0_COMPACTION_TRIGGER,
+                }
+            }
+        };
+
+        let mut applied = self.applied_compaction_options.lock_or_recover();
+        if *applied == Some(options) {
+            return Ok(());
+        }
+        let (background_jobs, l0_compaction_trigger) =
+            (options.background_jobs.to_string(), options.l0_compaction_trigger.to_string());
+        self.db.set_options(&[("max_background_jobs", background_jobs.as_str())])?;
+        for column in HEAVY_COLUMNS {
+            self.db.set_options_cf(
+                &self.db.get_column(*column),
+                &[("level0_file_num_compaction_trigger", l0_compaction_trigger.as_str())],
+            )?;
+        }
+        if applied.is_some() {
+            log::info!(
+                "🗜️  Compactions now use {} background jobs, the heavy columns compact after {} level 0 files",
+                options.background_jobs,
+                options.l0_compaction_trigger
+            );
+        }
+        *applied = Some(options);
+        Ok(())
+    }
+
+    pub(crate) fn update_compaction_metrics(&self, metrics: &CompactionMetrics) -> Result<(), DeoxysStorageError> {
+        for &column in Column::ALL {
+            let pending = self
+                .db
+                .property_int_value_cf(&self.db.get_column(column), "rocksdb.estimate-pending-compaction-bytes")?
+                .unwrap_or(0);
+            metrics.pending_compaction_bytes.with_label_values(&[column.rocksdb_name()]).set(pending as i64);
+        }
+        Ok(())
+    }
+
+    /// Estimated size of the live data of a column, in bytes.
+    pub fn estimate_live_data_size(&self, column: Column) -> Result<u64, DeoxysStorageError> {
+        Ok(self.db.property_int_value_cf(&self.db.get_column(column), "rocksdb.estimate-live-data-size")?.unwrap_or(0))
+    }
+
+    /// Compacts the whole key range of the columns, or of every column when `columns` is `None`, on a background
+    /// thread. The deleted keys and their tombstones are dropped from the disk.
+    ///
+    /// A column is compacted a key range at a time: a range is not compacted while a block is stored, and the blocks
+    /// only wait for the range being compacted. Fails when a manual compaction is already running.
+    pub fn compact(
+        self: &Arc<Self>,
+        columns: Option<Vec<Column>>,
+    ) -> Result<JoinHandle<Result<(), DeoxysStorageError>>, DeoxysStorageError> {
+        if self.manual_compaction.swap(true, Ordering::AcqRel) {
+            return Err(DeoxysStorageError::CompactionRunning);
+        }
+        let columns = columns.unwrap_or_else(|| Column::ALL.to_vec());
+        let backend = Arc::clone(self);
+        Ok(thread::spawn(move || {
+            let res = backend.compact_columns(&columns);
+            if let Err(err) = &res {
+                log::error!("❗ Compacting the database: {err:#}");
+            }
+            backend.manual_compaction.store(false, Ordering::Release);
+            res
+        }))
+    }
+
+    pub fn is_compacting(&self) -> bool {
+        self.manual_compaction.load(Ordering::Acquire)
+    }
+
+    fn compact_columns(&self, columns: &[Column]) -> Result<(), DeoxysStorageError> {
+        let started = Instant::now();
+        log::info!("🗜️  Compacting {} database columns", columns.len());
+        for (i, &column) in columns.iter().enumerate() {
+            let before = self.estimate_live_data_size(column)?;
+            let chunks = self.compaction_chunks(column)?;
+            for (start, end) in &chunks {
+                // Released between the chunks, so that the blocks are stored in between.
+                let _maintenance = self.maintenance.lock_or_recover();
+                self.db.compact_range_cf(&self.db.get_column(column), start.as_deref(), end.as_deref());
+            }
+            let after = self.estimate_live_data_size(column)?;
+            log::info!(
+                "🗜️  Compacted column {column} ({}/{}) in {} key ranges, live data {before} -> {after} bytes",
+                i + 1,
+                columns.len(),
+                chunks.len()
+            );
+        }
+        log::info!("✅ Compacted {} database columns in {:?}", columns.len(), started.elapsed());
+        Ok(())
+    }
+
+    /// Key ranges splitting a column into chunks of about [`COMPACTION_CHUNK_FILES`] of its files, going by the first
+    /// key of its live files.
+    fn compaction_chunks(&self, column: Column) -> Result<Vec<KeyRange>, DeoxysStorageError> {
+        let mut start_keys: Vec<_> = self
+            .db
+            .live_files()?
+            .into_iter()
+            .filter(|file| file.column_family_name == column.rocksdb_name())
+            .filter_map(|file| file.start_key)
+            .collect();
+        start_keys.sort_unstable();
+        start_keys.dedup();
+        Ok(key_ranges(start_keys.into_iter().skip(COMPACTION_CHUNK_FILES).step_by(COMPACTION_CHUNK_FILES).collect()))
+    }
+}
+
+/// A range of keys, unbounded on the sides that are `None`.
+type KeyRange = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// The ranges between sorted boundaries, from the first key to the last one.
+fn key_ranges(boundaries: Vec<Vec<u8>>) -> Vec<KeyRange> {
+    let starts = iter::once(None).chain(boundaries.iter().cloned().map(Some));
+    let ends = boundaries.into_iter().map(Some).chain(iter::once(None));
+    starts.zip(ends).collect()
+}
+
+pub(crate) async fn compaction_scheduler_task(
+    backend: Arc<DeoxysBackend>,
+    metrics: CompactionMetrics,
+) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+        backend.apply_compaction_schedule(minute_of_day(SystemTime::now()))?;
+        backend.update_compaction_metrics(&metrics)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use dc_metrics::MetricsService;
+    use dp_block::chain_config::ChainConfig;
+
+    use super::*;
+    use crate::testing::temp_database;
+    use crate::DatabaseService;
+
+    fn window(s: &str) -> TimeWindow {
+        s.parse().unwrap()
+    }
+
+    /// Value of an option in the latest options file rocksdb wrote, `section` being `DBOptions` or
+    /// `CFOptions "<column>"`.
+    fn persisted_option(db_path: &Path, section: &str, option: &str) -> Option<String> {
+        let latest = fs::read_dir(db_path)
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+            .filter_map(|name| Some((name.strip_prefix("OPTIONS-")?.parse::<u64>().ok()?, name)))
+            .max()?;
+        let content = fs::read_to_string(db_path.join(latest.1)).unwrap();
+
+        let mut in_section = false;
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_section = line == format!("[{section}]");
+            } else if let Some((key, value)) = line.split_once('=') {
+                if in_section && key == option {
+                    return Some(value.into());
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_time_window() {
+        let day = window("08:30-18:00");
+        assert_eq!(day.to_string(), "08:30-18:00");
+        assert!(!day.contains(8 * 60 + 29));
+        assert!(day.contains(8 * 60 + 30));
+        assert!(!day.contains(18 * 60));
+
+        let night = window("22:00-06:00");
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(0));
+        assert!(!night.contains(6 * 60));
+        assert!(!night.contains(12 * 60));
+
+        for invalid in ["22:00", "24:00-06:00", "08:60-09:00", "08:00-08:00", "8h-9h"] {
+            assert!(invalid.parse::<TimeWindow>().is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compaction_schedule_is_applied() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = CompactionConfig {
+            rate_limit: Some(16 * 1024 * 1024),
+            schedule: CompactionSchedule {
+                background_jobs: 6,
+                quiet_background_jobs: 1,
+                quiet_hours: vec![window("08:00-20:00")],
+            },
+        };
+        let db = DatabaseService::new_with_compaction(
+            temp_dir.path(),
+            None,
+            false,
+            Arc::new(ChainConfig::test_config()),
+            config,
+        )
+        .await
+        .unwrap();
+        let backend = db.backend();
+        let db_path = temp_dir.path().join("db");
+        let heavy_column = format!(r#"CFOptions "{}""#, Column::BlockNToBlockInner.rocksdb_name());
+        let other_column = format!(r#"CFOptions "{}""#, Column::ContractStorage.rocksdb_name());
+
+        backend.apply_compaction_schedule(12 * 60).unwrap();
+        assert_eq!(persisted_option(&db_path, "DBOptions", "max_background_jobs").as_deref(), Some("1"));
+        assert_eq!(
+            persisted_option(&db_path, &heavy_column, "level0_file_num_compaction_trigger").as_deref(),
+            Some("8")
+        );
+        assert_eq!(
+            persisted_option(&db_path, &other_column, "level0_file_num_compaction_trigger").as_deref(),
+            Some("4")
+        );
+
+        backend.apply_compaction_schedule(21 * 60).unwrap();
+        assert_eq!(persisted_option(&db_path, "DBOptions", "max_background_jobs").as_deref(), Some("6"));
+        assert_eq!(
+            persisted_option(&db_path, &heavy_column, "level0_file_num_compaction_trigger").as_deref(),
+            Some("4")
+        );
+
+        // A new schedule is applied right away.
+        backend
+            .set_compaction_schedule(CompactionSchedule {
+                background_jobs: 3,
+                quiet_background_jobs: 1,
+                quiet_hours: vec![],
+            })
+            .unwrap();
+        assert_eq!(persisted_option(&db_path, "DBOptions", "max_background_jobs").as_deref(), Some("3"));
+
+        let metrics = CompactionMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        backend.update_compaction_metrics(&metrics).unwrap();
+        let pending = metrics.pending_compaction_bytes.with_label_values(&[Column::BlockNToBlockInner.rocksdb_name()]);
+        assert_eq!(pending.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_drops_the_deleted_keys() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let column = backend.db.get_column(Column::ContractEvents);
+
+        for i in 0..10_000_u32 {
+            let value: Vec<u8> = (0..256_u32).map(|j| (i.wrapping_mul(31) ^ j) as u8).collect();
+            backend.db.put_cf(&column, i.to_be_bytes(), value).unwrap();
+        }
+        backend.maybe_flush(true).unwrap();
+        for i in 0..10_000_u32 {
+            backend.db.delete_cf(&column, i.to_be_bytes()).unwrap();
+        }
+        backend.maybe_flush(true).unwrap();
+        let before = backend.estimate_live_data_size(Column::ContractEvents).unwrap();
+        assert!(before > 0);
+
+        // The compaction waits for the block being stored.
+        let maintenance = backend.maintenance.lock().unwrap();
+        let compaction = backend.compact(Some(vec![Column::ContractEvents])).unwrap();
+        assert!(matches!(backend.compact(None), Err(DeoxysStorageError::CompactionRunning)));
+        drop(maintenance);
+        compaction.join().unwrap().unwrap();
+
+        assert!(backend.estimate_live_data_size(Column::ContractEvents).unwrap() < before);
+        assert_eq!(backend.db.get_cf(&column, 0_u32.to_be_bytes()).unwrap(), None);
+        // Another compaction may run once it is done.
+        backend.compact(Some(vec![Column::Meta])).unwrap().join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_key_ranges() {
+        assert_eq!(key_ranges(vec![]), vec![(None, None)]);
+        assert_eq!(
+            key_ranges(vec![vec![1], vec![2, 0]]),
+            vec![(None, Some(vec![1])), (Some(vec![1]), Some(vec![2, 0])), (Some(vec![2, 0]), None)]
+        );
+    }
+
+    #[test]
+    fn test_parse_column() {
+        assert_eq!("contract_storage".parse::<Column>(), Ok(Column::ContractStorage));
+        assert!("contract_storages".parse::<Column>().is_err());
+        for column in Column::ALL {
+            assert_eq!(column.to_string().parse::<Column>(), Ok(*column));
+        }
+    }
+}
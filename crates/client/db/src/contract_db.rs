@@ -5,8 +5,16 @@
 //! and getting the next value.
 //!
 //! Insertion is batched and done in parallel using rayon: this is not intended for use in the RPCs.
-use std::sync::Arc;
+//!
+//! The history is written block after block: the last block written to each [`HistoryRange`] is kept in the meta
+//! column, and writing the history of a block at or below it in the same range is refused unless the caller asks for it
+//! with [`HistoryOrder::OutOfOrder`]. The tips are written in the same batch as the block, so that a block whose import
+//! was interrupted can be written again.
+use std::ops::RangeInclusive;
+use std::sync::{Arc, MutexGuard};
 
+use dp_block::BlockN;
+use dp_utils::lock::MutexExt;
 use rayon::{iter::ParallelIterator, slice::ParallelSlice};
 use rocksdb::{BoundColumnFamily, IteratorMode, ReadOptions, WriteOptions};
 use starknet_core::types::Felt;
@@ -23,6 +31,58 @@ pub(crate) const CONTRACT_NONCES_PREFIX_EXTRACTOR: usize = 32;
 
 const LAST_KEY: &[u8] = &[0xFF; 64];
 
+/// The key ranges of the contract history, each with its own history tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryRange {
+    ClassHashes,
+    Nonces,
+    Storage,
+}
+
+impl HistoryRange {
+    pub const ALL: [Self; 3] = [Self::ClassHashes, Self::Nonces, Self::Storage];
+
+    fn tip_row(self) -> &'static [u8] {
+        match self {
+            Self::ClassHashes => b"contract_history_tip_class_hashes",
+            Self::Nonces => b"contract_history_tip_nonces",
+            Self::Storage => b"contract_history_tip_storage",
+        }
+    }
+
+    /// The ranges written by these contract updates.
+    pub(crate) fn touched(
+        contract_class_updates: &[(Felt, Felt)],
+        contract_nonces_updates: &[(Felt, Felt)],
+        contract_kv_updates: &[((Felt, Felt), Felt)],
+    ) -> Vec<Self> {
+        [
+            (Self::ClassHashes, contract_class_updates.is_empty()),
+            (Self::Nonces, contract_nonces_updates.is_empty()),
+            (Self::Storage, contract_kv_updates.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(range, empty)| (!empty).then_some(range))
+        .collect()
+    }
+}
+
+/// Whether the history of a block may be written at or below the last block written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOrder {
+    /// The block must be above the last block written to each of the ranges it touches. A block written below it
+    /// would be read under blocks that were executed without its writes, which happens when the block production and
+    /// the sync race each other.
+    Monotonic,
+    /// The block may be written anywhere, for the revert and rebuild paths which write below the tips on purpose.
+    OutOfOrder,
+}
+
+/// Suffix of the history keys written by block `block_n`.
+fn history_key_suffix(block_n: BlockN) -> Result<[u8; 4], DeoxysStorageError> {
+    block_n.to_history_key_suffix().map_err(|_| DeoxysStorageError::InvalidBlockNumber)
+}
+
 fn make_storage_key_prefix(contract_address: Felt, storage_key: Felt) -> [u8; 64] {
     let mut key = [0u8; 64];
     key[..32].copy_from_slice(contract_address.to_bytes_be().as_ref());
@@ -51,16 +111,60 @@ impl DeoxysBackend {
                 }
 
                 let Some(block_n) = self.get_latest_block_n()? else { return Ok(None) };
-                block_n
+                BlockN(block_n)
             }
             DbBlockId::BlockN(block_n) => block_n,
         };
 
         // We try to find history values.
+        self.get_history_value(nonpending_col, make_bin_prefix(k).as_ref(), block_n)
+    }
 
-        let block_n = u32::try_from(block_n).map_err(|_| DeoxysStorageError::InvalidBlockNumber)?;
-        let bin_prefix = make_bin_prefix(k);
-        let start_at = [bin_prefix.as_ref(), &block_n.to_be_bytes() as &[u8]].concat();
+    /// Same as [`Self::resolve_history_kv`] for many keys at once: the block id is only resolved once, and the pending
+    /// values are read with a single multi-get. Values are returned in the order of `ks`.
+    fn resolve_history_kvs<K: serde::Serialize, V: serde::de::DeserializeOwned, B: AsRef<[u8]>>(
+        &self,
+        id: &impl DbBlockIdResolvable,
+        pending_col: Column,
+        nonpending_col: Column,
+        ks: &[K],
+        make_bin_prefix: impl Fn(&K) -> B,
+    ) -> Result<Vec<Option<V>>, DeoxysStorageError> {
+        let mut values: Vec<Option<V>> = ks.iter().map(|_| None).collect();
+        let Some(id) = id.resolve_db_block_id(self)? else { return Ok(values) };
+
+        let block_n = match id {
+            DbBlockId::Pending => {
+                let col = self.db.get_column(pending_col);
+                let keys = ks.iter().map(bincode::serialize).collect::<Result<Vec<_>, _>>()?;
+                for (value, res) in values.iter_mut().zip(self.db.batched_multi_get_cf(&col, &keys, false)) {
+                    if let Some(res) = res? {
+                        *value = Some(bincode::deserialize(&res)?); // found in pending
+                    }
+                }
+
+                let Some(block_n) = self.get_latest_block_n()? else { return Ok(values) };
+                BlockN(block_n)
+            }
+            DbBlockId::BlockN(block_n) => block_n,
+        };
+
+        for (value, k) in values.iter_mut().zip(ks) {
+            if value.is_none() {
+                *value = self.get_history_value(nonpending_col, make_bin_prefix(k).as_ref(), block_n)?;
+            }
+        }
+        Ok(values)
+    }
+
+    /// The last value of a history key at `block_n`.
+    fn get_history_value<V: serde::de::DeserializeOwned>(
+        &self,
+        nonpending_col: Column,
+        bin_prefix: &[u8],
+        block_n: BlockN,
+    ) -> Result<Option<V>, DeoxysStorageError> {
+        let start_at = [bin_prefix, &history_key_suffix(block_n)? as &[u8]].concat();
 
         let mut options = ReadOptions::default();
         options.set_prefix_same_as_start(true);
@@ -76,7 +180,7 @@ impl DeoxysBackend {
                 #[allow(unused_variables)]
                 let (k, v) = res?;
                 #[cfg(debug_assertions)]
-                assert!(k.starts_with(bin_prefix.as_ref())); // This should fail if we forgot to set up a prefix iterator for the column.
+                assert!(k.starts_with(bin_prefix)); // This should fail if we forgot to set up a prefix iterator for the column.
 
                 Ok(Some(bincode::deserialize(&v)?))
             }
@@ -84,6 +188,174 @@ impl DeoxysBackend {
         }
     }
 
+    /// The values of a history key written by the blocks in `blocks`, with the block which wrote them. At most `limit`
+    /// values are read, in block order.
+    fn get_history_range<V: serde::de::DeserializeOwned>(
+        &self,
+        nonpending_col: Column,
+        bin_prefix: &[u8],
+        blocks: RangeInclusive<BlockN>,
+        limit: usize,
+    ) -> Result<Vec<(u64, V)>, DeoxysStorageError> {
+        let start_at = [bin_prefix, &history_key_suffix(*blocks.start())? as &[u8]].concat();
+
+        let mut options = ReadOptions::default();
+        options.set_prefix_same_as_start(true);
+        let mode = IteratorMode::From(&start_at, rocksdb::Direction::Forward);
+        let iter = self.db.iterator_cf_opt(&self.db.get_column(nonpending_col), options, mode);
+
+        let mut values = vec![];
+        for res in iter {
+            if values.len() >= limit {
+                break;
+            }
+            let (k, v) = res?;
+            #[cfg(debug_assertions)]
+            assert!(k.starts_with(bin_prefix)); // This should fail if we forgot to set up a prefix iterator for the column.
+            let block_n = BlockN::from_history_key_suffix(&k[bin_prefix.len()..])
+                .ok_or_else(|| DeoxysStorageError::inconsistent("Malformed contract history key"))?;
+            if block_n > *blocks.end() {
+                break;
+            }
+            values.push((block_n.0, bincode::deserialize(&v)?));
+        }
+        Ok(values)
+    }
+
+    /// Iterates lazily over the values of a history column at block `block_n`, in key order: the last value written at
+    /// or before it for every key starting with `bin_prefix`, from key `start` on. The keys without a value at
+    /// `block_n` are skipped.
+    fn iter_history_at<V: serde::de::DeserializeOwned>(
+        &self,
+        nonpending_col: Column,
+        bin_prefix: &[u8],
+        start: &[u8],
+        block_n: BlockN,
+    ) -> Result<impl Iterator<Item = Result<(Box<[u8]>, V), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        let at_suffix = history_key_suffix(block_n)?;
+        let mut options = ReadOptions::default();
+        // The iteration goes over the keys of many prefixes of the extractor of the column.
+        options.set_total_order_seek(true);
+        let mode = IteratorMode::From(bin_prefix.max(start), rocksdb::Direction::Forward);
+        let mut iter = self.db.iterator_cf_opt(&self.db.get_column(nonpending_col), options, mode);
+        let bin_prefix = bin_prefix.to_vec();
+
+        let decode = |key: Box<[u8]>, value: Box<[u8]>| -> Result<(Box<[u8]>, V), DeoxysStorageError> {
+            Ok((key, bincode::deserialize(&value)?))
+        };
+        // The key being read, and its last value at `block_n` so far.
+        let mut current: Option<(Box<[u8]>, Option<Box<[u8]>>)> = None;
+        let mut done = false;
+        Ok(std::iter::from_fn(move || loop {
+            if done {
+                return None;
+            }
+            let (key, value) = match iter.next() {
+                Some(Ok((key, value))) if key.starts_with(&bin_prefix) => (key, value),
+                Some(Err(err)) => return Some(Err(err.into())),
+                _ => {
+                    done = true;
+                    let (key, value) = current.take()?;
+                    return value.map(|value| decode(key, value));
+                }
+            };
+            let Some(key_len) = key.len().checked_sub(at_suffix.len()) else {
+                return Some(Err(DeoxysStorageError::inconsistent("Malformed contract history key")));
+            };
+            let (history_key, suffix) = key.split_at(key_len);
+            let value = (suffix <= &at_suffix[..]).then_some(value);
+            if let Some((current_key, current_value)) = &mut current {
+                if **current_key == *history_key {
+                    if value.is_some() {
+                        *current_value = value;
+                    }
+                    continue;
+                }
+            }
+            if let Some((key, Some(value))) = current.replace((history_key.into(), value)) {
+                return Some(decode(key, value));
+            }
+        }))
+    }
+
+    /// Iterates lazily over the contracts deployed at block `block_n` with their class hash, in address order.
+    pub fn iter_contract_class_hashes_at(
+        &self,
+        block_n: u64,
+    ) -> Result<impl Iterator<Item = Result<(Felt, Felt), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        self.iter_contract_class_hashes_from(block_n, &Felt::ZERO)
+    }
+
+    /// Same as [`Self::iter_contract_class_hashes_at`], from the contract at address `start` on.
+    pub(crate) fn iter_contract_class_hashes_from(
+        &self,
+        block_n: u64,
+        start: &Felt,
+    ) -> Result<impl Iterator<Item = Result<(Felt, Felt), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        Ok(self
+            .iter_history_at(Column::ContractToClassHashes, &[], &start.to_bytes_be(), BlockN(block_n))?
+            .map(|res| res.map(|(key, class_hash)| (Felt::from_bytes_be_slice(&key), class_hash))))
+    }
+
+    /// Iterates lazily over the nonces of the contracts at block `block_n`, in address order.
+    pub fn iter_contract_nonces_at(
+        &self,
+        block_n: u64,
+    ) -> Result<impl Iterator<Item = Result<(Felt, Felt), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        Ok(self
+            .iter_history_at(Column::ContractToNonces, &[], &[], BlockN(block_n))?
+            .map(|res| res.map(|(key, nonce)| (Felt::from_bytes_be_slice(&key), nonce))))
+    }
+
+    /// Iterates lazily over the storage of the contracts at block `block_n`, as `(contract, key, value)` in contract
+    /// then key order. Only the storage of `contract_addr` is read when it is given.
+    pub fn iter_contract_storage_at(
+        &self,
+        contract_addr: Option<&Felt>,
+        block_n: u64,
+    ) -> Result<impl Iterator<Item = Result<(Felt, Felt, Felt), DeoxysStorageError>> + '_, DeoxysStorageError> {
+        let bin_prefix = contract_addr.map(|contract_addr| contract_addr.to_bytes_be());
+        Ok(self
+            .iter_history_at(
+                Column::ContractStorage,
+                bin_prefix.as_ref().map_or(&[], |prefix| &prefix[..]),
+                &[],
+                BlockN(block_n),
+            )?
+            .map(|res| {
+                res.map(|(key, value)| {
+                    (Felt::from_bytes_be_slice(&key[..32]), Felt::from_bytes_be_slice(&key[32..]), value)
+                })
+            }))
+    }
+
+    /// The blocks in `blocks` which changed the nonce of a contract, with the nonce they set, in block order. At most
+    /// `limit` changes are returned. The pending block is not included.
+    pub fn get_contract_nonce_history(
+        &self,
+        contract_addr: &Felt,
+        blocks: RangeInclusive<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, Felt)>, DeoxysStorageError> {
+        let last = BlockN(u32::MAX.into());
+        let (start, end) = (BlockN(*blocks.start()), BlockN(*blocks.end()).min(last));
+        if start > end {
+            return Ok(vec![]);
+        }
+        self.get_history_range(Column::ContractToNonces, &contract_addr.to_bytes_be(), start..=end, limit)
+    }
+
+    /// The block which deployed a contract, the first block which set its class hash.
+    pub fn get_contract_deployed_at(&self, contract_addr: &Felt) -> Result<Option<u64>, DeoxysStorageError> {
+        let first = self.get_history_range::<Felt>(
+            Column::ContractToClassHashes,
+            &contract_addr.to_bytes_be(),
+            BlockN::GENESIS..=BlockN(u32::MAX.into()),
+            1,
+        )?;
+        Ok(first.first().map(|(block_n, _)| *block_n))
+    }
+
     pub fn is_contract_deployed_at(
         &self,
         id: &impl DbBlockIdResolvable,
@@ -107,6 +379,21 @@ impl DeoxysBackend {
         )
     }
 
+    /// Class hashes of many contracts at once, in the order of `contract_addrs`.
+    pub fn get_contract_class_hashes_at(
+        &self,
+        id: &impl DbBlockIdResolvable,
+        contract_addrs: &[Felt],
+    ) -> Result<Vec<Option<Felt>>, DeoxysStorageError> {
+        self.resolve_history_kvs(
+            id,
+            Column::PendingContractToClassHashes,
+            Column::ContractToClassHashes,
+            contract_addrs,
+            |k| k.to_bytes_be(),
+        )
+    }
+
     pub fn get_contract_nonce_at(
         &self,
         id: &impl DbBlockIdResolvable,
@@ -132,30 +419,114 @@ impl DeoxysBackend {
         )
     }
 
+    /// Last block whose contract history was written, in any of the ranges. `None` before the first one.
+    pub fn get_contract_history_tip(&self) -> Result<Option<u64>, DeoxysStorageError> {
+        HistoryRange::ALL
+            .into_iter()
+            .try_fold(None, |tip, range| Ok(tip.max(self.get_contract_history_range_tip(range)?)))
+    }
+
+    /// Last block whose contract history was written to `range`, `None` before the first one.
+    pub fn get_contract_history_range_tip(&self, range: HistoryRange) -> Result<Option<u64>, DeoxysStorageError> {
+        let col = self.db.get_column(Column::Meta);
+        let Some(res) = self.db.get_pinned_cf(&col, range.tip_row())? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    /// Held from the check of the order of a block to the write of its history.
+    pub(crate) fn lock_contract_history(&self) -> MutexGuard<'_, ()> {
+        self.contract_history.lock_or_recover()
+    }
+
+    /// Checks that `block_number` is above the history tip of each of `ranges`.
+    pub(crate) fn check_contract_history_order(
+        &self,
+        block_number: u64,
+        ranges: &[HistoryRange],
+    ) -> Result<(), DeoxysStorageError> {
+        for range in ranges {
+            match self.get_contract_history_range_tip(*range)? {
+                Some(last_committed) if block_number <= last_committed => {
+                    return Err(DeoxysStorageError::NonMonotonicCommit { block_n: block_number, last_committed })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds to `batch` the history tips of `ranges` for a block whose history is written, `batch` being the one
+    /// storing the block. A tip does not go down. The lock on the history must be held until `batch` is written.
+    pub(crate) fn contract_db_history_tip_write(
+        &self,
+        _lock: &MutexGuard<'_, ()>,
+        batch: &mut WriteBatchWithTransaction,
+        block_number: u64,
+        ranges: &[HistoryRange],
+    ) -> Result<(), DeoxysStorageError> {
+        let col = self.db.get_column(Column::Meta);
+        for range in ranges {
+            if self.get_contract_history_range_tip(*range)?.is_some_and(|tip| tip >= block_number) {
+                continue;
+            }
+            batch.put_cf(&col, range.tip_row(), bincode::serialize(&BlockN(block_number))?);
+        }
+        Ok(())
+    }
+
+    /// Writes the history values of block `block_number`. The history tips are not moved: they are written along with
+    /// the block, see [`DeoxysBackend::contract_db_history_tip_write`]. The lock on the history must be held until
+    /// then.
+    ///
     /// NB: This functions needs to run on the rayon thread pool
     pub(crate) fn contract_db_store_block(
         &self,
+        _lock: &MutexGuard<'_, ()>,
         block_number: u64,
+        order: HistoryOrder,
         contract_class_updates: &[(Felt, Felt)],
         contract_nonces_updates: &[(Felt, Felt)],
         contract_kv_updates: &[((Felt, Felt), Felt)],
     ) -> Result<(), DeoxysStorageError> {
-        let block_number = u32::try_from(block_number).map_err(|_| DeoxysStorageError::InvalidBlockNumber)?;
+        let n_updates = contract_class_updates.len() + contract_nonces_updates.len() + contract_kv_updates.len();
+        if n_updates == 0 {
+            return Ok(());
+        }
+        if order == HistoryOrder::Monotonic {
+            let ranges = HistoryRange::touched(contract_class_updates, contract_nonces_updates, contract_kv_updates);
+            self.check_contract_history_order(block_number, &ranges)?;
+        }
 
         let mut writeopts = WriteOptions::new();
         writeopts.disable_wal(true);
 
+        // Small blocks are written in a single batch.
+        if n_updates <= DB_UPDATES_BATCH_SIZE {
+            let mut batch = WriteBatchWithTransaction::default();
+            self.contract_db_block_writes(
+                &mut batch,
+                block_number,
+                contract_class_updates,
+                contract_nonces_updates,
+                contract_kv_updates,
+            )?;
+            self.db.write_opt(batch, &writeopts)?;
+            return Ok(());
+        }
+
+        let block_number = history_key_suffix(BlockN(block_number))?;
+
         fn write_chunk(
             db: &DB,
             writeopts: &WriteOptions,
             col: &Arc<BoundColumnFamily>,
-            block_number: u32,
+            block_number: [u8; 4],
             chunk: impl IntoIterator<Item = (impl AsRef<[u8]>, Felt)>,
         ) -> Result<(), DeoxysStorageError> {
             let mut batch = WriteBatchWithTransaction::default();
             for (key, value) in chunk {
                 // TODO: find a way to avoid this allocation
-                let key = [key.as_ref(), &block_number.to_be_bytes() as &[u8]].concat();
+                let key = [key.as_ref(), &block_number as &[u8]].concat();
                 batch.put_cf(col, key, bincode::serialize(&value)?);
             }
             db.write_opt(batch, writeopts)?;
@@ -182,95 +553,277 @@ impl DeoxysBackend {
                     &writeopts,
                     col,
                     block_number,
-                    chunk.iter().map(|((k1, k2), v)| {
-                        let mut key = [0u8; 64];
-                        key[..32].copy_from_slice(k1.to_bytes_be().as_ref());
-                        key[32..].copy_from_slice(k2.to_bytes_be().as_ref());
-                        (key, *v)
-                    }),
+                    chunk.iter().map(|((k1, k2), v)| (make_storage_key_prefix(*k1, *k2), *v)),
                 )
             },
         )?;
+        Ok(())
+    }
 
+    /// Adds to `batch` the history values of block `block_number`, for a block written in a single batch.
+    fn contract_db_block_writes(
+        &self,
+        batch: &mut WriteBatchWithTransaction,
+        block_number: u64,
+        contract_class_updates: &[(Felt, Felt)],
+        contract_nonces_updates: &[(Felt, Felt)],
+        contract_kv_updates: &[((Felt, Felt), Felt)],
+    ) -> Result<(), DeoxysStorageError> {
+        let suffix = history_key_suffix(BlockN(block_number))?;
+        let history_key = |prefix: &[u8]| [prefix, &suffix as &[u8]].concat();
+
+        let col = self.db.get_column(Column::ContractToClassHashes);
+        for (contract_address, class_hash) in contract_class_updates {
+            batch.put_cf(&col, history_key(&contract_address.to_bytes_be()), bincode::serialize(class_hash)?);
+        }
+        let col = self.db.get_column(Column::ContractToNonces);
+        for (contract_address, nonce) in contract_nonces_updates {
+            batch.put_cf(&col, history_key(&contract_address.to_bytes_be()), bincode::serialize(nonce)?);
+        }
+        let col = self.db.get_column(Column::ContractStorage);
+        for ((contract_address, storage_key), value) in contract_kv_updates {
+            let key = history_key(&make_storage_key_prefix(*contract_address, *storage_key));
+            batch.put_cf(&col, key, bincode::serialize(value)?);
+        }
         Ok(())
     }
 
-    /// NB: This functions needs to run on the rayon thread pool
-    pub(crate) fn contract_db_store_pending(
+    /// Adds to `batch` the removal of the history values written by block `block_number`.
+    pub(crate) fn contract_db_revert_block(
         &self,
+        batch: &mut WriteBatchWithTransaction,
+        block_number: u64,
         contract_class_updates: &[(Felt, Felt)],
         contract_nonces_updates: &[(Felt, Felt)],
         contract_kv_updates: &[((Felt, Felt), Felt)],
     ) -> Result<(), DeoxysStorageError> {
-        let mut writeopts = WriteOptions::new();
-        writeopts.disable_wal(true);
+        let suffix = history_key_suffix(BlockN(block_number))?;
+        let history_key = |prefix: &[u8]| [prefix, &suffix as &[u8]].concat();
 
-        fn write_chunk(
-            db: &DB,
-            writeopts: &WriteOptions,
-            col: &Arc<BoundColumnFamily>,
-            chunk: impl IntoIterator<Item = (impl AsRef<[u8]>, Felt)>,
-        ) -> Result<(), DeoxysStorageError> {
-            let mut batch = WriteBatchWithTransaction::default();
-            for (key, value) in chunk {
-                // TODO: find a way to avoid this allocation
-                batch.put_cf(col, key.as_ref(), bincode::serialize(&value)?);
-            }
-            db.write_opt(batch, writeopts)?;
-            Ok(())
+        let col = self.db.get_column(Column::ContractToClassHashes);
+        for (contract_address, _) in contract_class_updates {
+            batch.delete_cf(&col, history_key(&contract_address.to_bytes_be()));
+        }
+        let col = self.db.get_column(Column::ContractToNonces);
+        for (contract_address, _) in contract_nonces_updates {
+            batch.delete_cf(&col, history_key(&contract_address.to_bytes_be()));
+        }
+        let col = self.db.get_column(Column::ContractStorage);
+        for ((contract_address, storage_key), _) in contract_kv_updates {
+            batch.delete_cf(&col, history_key(&make_storage_key_prefix(*contract_address, *storage_key)));
         }
 
-        contract_class_updates.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
-            || self.db.get_column(Column::PendingContractToClassHashes),
-            |col, chunk| write_chunk(&self.db, &writeopts, col, chunk.iter().map(|(k, v)| (k.to_bytes_be(), *v))),
-        )?;
-        contract_nonces_updates.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
-            || self.db.get_column(Column::PendingContractToNonces),
-            |col, chunk| write_chunk(&self.db, &writeopts, col, chunk.iter().map(|(k, v)| (k.to_bytes_be(), *v))),
-        )?;
-        contract_kv_updates.par_chunks(DB_UPDATES_BATCH_SIZE).try_for_each_init(
-            || self.db.get_column(Column::PendingContractStorage),
-            |col, chunk| {
-                write_chunk(
-                    &self.db,
-                    &writeopts,
-                    col,
-                    chunk.iter().map(|((k1, k2), v)| {
-                        let mut key = [0u8; 64];
-                        key[..32].copy_from_slice(k1.to_bytes_be().as_ref());
-                        key[32..].copy_from_slice(k2.to_bytes_be().as_ref());
-                        (key, *v)
-                    }),
-                )
-            },
-        )?;
+        Ok(())
+    }
 
+    /// Adds to `batch` the history tips of a chain reverted to `block_n`. The lock on the history must be held until
+    /// `batch` is written.
+    pub(crate) fn contract_db_revert_history_tip(
+        &self,
+        _lock: &MutexGuard<'_, ()>,
+        batch: &mut WriteBatchWithTransaction,
+        block_n: u64,
+    ) -> Result<(), DeoxysStorageError> {
+        let col = self.db.get_column(Column::Meta);
+        for range in HistoryRange::ALL {
+            if self.get_contract_history_range_tip(range)?.is_some_and(|tip| tip > block_n) {
+                batch.put_cf(&col, range.tip_row(), bincode::serialize(&BlockN(block_n))?);
+            }
+        }
         Ok(())
     }
 
-    pub(crate) fn contract_db_clear_pending(&self) -> Result<(), DeoxysStorageError> {
-        let mut writeopts = WriteOptions::new();
-        writeopts.disable_wal(true);
+    /// Adds to `batch` the writes replacing the contract state of the pending block.
+    pub(crate) fn contract_db_pending_writes(
+        &self,
+        batch: &mut WriteBatchWithTransaction,
+        contract_class_updates: &[(Felt, Felt)],
+        contract_nonces_updates: &[(Felt, Felt)],
+        contract_kv_updates: &[((Felt, Felt), Felt)],
+    ) -> Result<(), DeoxysStorageError> {
+        let col = self.db.get_column(Column::PendingContractToClassHashes);
+        batch.delete_range_cf(&col, &[] as &[u8], LAST_KEY);
+        for (contract_address, class_hash) in contract_class_updates {
+            batch.put_cf(&col, contract_address.to_bytes_be(), bincode::serialize(class_hash)?);
+        }
 
-        self.db.delete_range_cf_opt(
-            &self.db.get_column(Column::PendingContractToNonces),
-            &[] as _,
-            LAST_KEY,
-            &writeopts,
-        )?;
-        self.db.delete_range_cf_opt(
-            &self.db.get_column(Column::PendingContractToClassHashes),
-            &[] as _,
-            LAST_KEY,
-            &writeopts,
-        )?;
-        self.db.delete_range_cf_opt(
-            &self.db.get_column(Column::PendingContractStorage),
-            &[] as _,
-            LAST_KEY,
-            &writeopts,
-        )?;
+        let col = self.db.get_column(Column::PendingContractToNonces);
+        batch.delete_range_cf(&col, &[] as &[u8], LAST_KEY);
+        for (contract_address, nonce) in contract_nonces_updates {
+            batch.put_cf(&col, contract_address.to_bytes_be(), bincode::serialize(nonce)?);
+        }
+
+        let col = self.db.get_column(Column::PendingContractStorage);
+        batch.delete_range_cf(&col, &[] as &[u8], LAST_KEY);
+        for ((contract_address, storage_key), value) in contract_kv_updates {
+            let mut key = [0u8; 64];
+            key[..32].copy_from_slice(contract_address.to_bytes_be().as_ref());
+            key[32..].copy_from_slice(storage_key.to_bytes_be().as_ref());
+            batch.put_cf(&col, key, bincode::serialize(value)?);
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::{ContractStorageDiffItem, StateDiff, StorageEntry};
+
+    use super::*;
+    use crate::testing::temp_database;
+
+    const CONTRACT: Felt = Felt::from_hex_unchecked("0xc0");
+
+    fn store_block(backend: &DeoxysBackend, block_n: u64, value: u64) -> Result<(), DeoxysStorageError> {
+        let header = Header { block_number: block_n, ..Default::default() };
+        let block = DeoxysBlock::new(
+            DeoxysBlockInfo::new(header, vec![], Felt::from(block_n)),
+            DeoxysBlockInner::new(vec![], vec![]),
+        );
+        let state_diff = StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: CONTRACT,
+                storage_entries: vec![StorageEntry { key: Felt::ONE, value: value.into() }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        );
+        backend.store_block(block.into(), state_diff, vec![])
+    }
+
+    fn storage_at(backend: &DeoxysBackend, block_n: u64, key: Felt) -> Option<Felt> {
+        backend.get_contract_storage_at(&DbBlockId::BlockN(BlockN(block_n)), &CONTRACT, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_contract_history_is_written_in_order() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        for block_n in 0..3 {
+            store_block(backend, block_n, 10 + block_n).unwrap();
+        }
+        assert_eq!(backend.get_contract_history_tip().unwrap(), Some(2));
+
+        // Block 1 again, as if the block production raced the sync.
+        assert!(matches!(
+            store_block(backend, 1, 21),
+            Err(DeoxysStorageError::NonMonotonicCommit { block_n: 1, last_committed: 2 })
+        ));
+        assert_eq!(storage_at(backend, 1, Felt::ONE), Some(Felt::from(11)));
+        assert_eq!(storage_at(backend, 2, Felt::ONE), Some(Felt::from(12)));
+
+        // Once the blocks above are reverted, the block can be written.
+        backend.revert_to(0, false).unwrap();
+        assert_eq!(backend.get_contract_history_tip().unwrap(), Some(0));
+        store_block(backend, 1, 21).unwrap();
+        assert_eq!(storage_at(backend, 1, Felt::ONE), Some(Felt::from(21)));
+
+        // The history of block 2 is written, but the import is interrupted before the block is: the tip stays at 1,
+        // and the block is imported again.
+        let updates = [((CONTRACT, Felt::ONE), Felt::from(5))];
+        backend
+            .contract_db_store_block(&backend.lock_contract_history(), 2, HistoryOrder::Monotonic, &[], &[], &updates)
+            .unwrap();
+        assert_eq!(backend.get_contract_history_tip().unwrap(), Some(1));
+        store_block(backend, 2, 22).unwrap();
+        assert_eq!(backend.get_contract_history_tip().unwrap(), Some(2));
+        assert_eq!(storage_at(backend, 2, Felt::ONE), Some(Felt::from(22)));
+        assert_eq!(storage_at(backend, 1, Felt::ONE), Some(Felt::from(21)));
+    }
+
+    #[tokio::test]
+    async fn test_contract_history_out_of_order() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        for block_n in 0..3 {
+            store_block(backend, block_n, 10 + block_n).unwrap();
+        }
+
+        // The tips are kept per range: the nonces of block 1 are not below any nonce written.
+        assert_eq!(backend.get_contract_history_range_tip(HistoryRange::Storage).unwrap(), Some(2));
+        assert_eq!(backend.get_contract_history_range_tip(HistoryRange::Nonces).unwrap(), None);
+        let nonces = [(CONTRACT, Felt::ONE)];
+        let storage = [((CONTRACT, Felt::TWO), Felt::from(5))];
+        let lock = backend.lock_contract_history();
+        backend.contract_db_store_block(&lock, 1, HistoryOrder::Monotonic, &[], &nonces, &[]).unwrap();
+        assert!(matches!(
+            backend.contract_db_store_block(&lock, 1, HistoryOrder::Monotonic, &[], &nonces, &storage),
+            Err(DeoxysStorageError::NonMonotonicCommit { block_n: 1, last_committed: 2 })
+        ));
+        drop(lock);
+        assert_eq!(storage_at(backend, 1, Felt::TWO), None);
+
+        // The rebuild path writes below the tip on purpose, the tips do not go down.
+        let state_diff = StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: CONTRACT,
+                storage_entries: vec![StorageEntry { key: Felt::TWO, value: Felt::from(5) }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        );
+        backend.commit_contract_history_out_of_order(1, &state_diff).unwrap();
+        assert_eq!(backend.get_contract_history_range_tip(HistoryRange::Storage).unwrap(), Some(2));
+        assert_eq!(storage_at(backend, 0, Felt::TWO), None);
+        assert_eq!(storage_at(backend, 1, Felt::TWO), Some(Felt::from(5)));
+        assert_eq!(storage_at(backend, 2, Felt::TWO), Some(Felt::from(5)));
+        assert_eq!(storage_at(backend, 1, Felt::ONE), Some(Felt::from(11)));
+
+        // Reverting moves every tip back.
+        backend.revert_to(0, false).unwrap();
+        assert_eq!(backend.get_contract_history_tip().unwrap(), Some(0));
+        store_block(backend, 1, 21).unwrap();
+        assert_eq!(storage_at(backend, 1, Felt::ONE), Some(Felt::from(21)));
+        assert_eq!(storage_at(backend, 0, Felt::ONE), Some(Felt::from(10)));
+    }
+
+    #[tokio::test]
+    async fn test_contract_nonce_history() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        // The nonce of the next contract changes on every block, its history is not read along with the one of CONTRACT.
+        let other_contract = CONTRACT + Felt::ONE;
+        let changes = [2, 5, 6, 11, 17];
+        for block_n in 0..20u64 {
+            let mut nonces = vec![(other_contract, Felt::from(block_n))];
+            if let Some(nonce) = changes.iter().position(|changed| *changed == block_n) {
+                nonces.push((CONTRACT, Felt::from(nonce as u64 + 1)));
+            }
+            backend
+                .contract_db_store_block(
+                    &backend.lock_contract_history(),
+                    block_n,
+                    HistoryOrder::Monotonic,
+                    &[],
+                    &nonces,
+                    &[],
+                )
+                .unwrap();
+        }
+
+        let history = |blocks: RangeInclusive<u64>, limit: usize| {
+            backend.get_contract_nonce_history(&CONTRACT, blocks, limit).unwrap()
+        };
+        let expected =
+            [(2u64, 1u64), (5, 2), (6, 3), (11, 4), (17, 5)].map(|(block_n, nonce)| (block_n, Felt::from(nonce)));
+        assert_eq!(history(0..=u64::MAX, 100), expected);
+        assert_eq!(history(0..=19, 2), expected[..2]);
+        // Both ends of the range are included.
+        assert_eq!(history(6..=11, 100), expected[2..4]);
+        assert_eq!(history(7..=16, 100), expected[3..4]);
+        assert_eq!(history(12..=16, 100), vec![]);
+        assert_eq!(history(18..=1000, 100), vec![]);
+        assert_eq!(history(11..=6, 100), vec![]);
+        assert_eq!(history(0..=19, 0), vec![]);
+        assert_eq!(backend.get_contract_nonce_history(&Felt::TWO, 0..=19, 100).unwrap(), vec![]);
+        assert_eq!(backend.get_contract_nonce_history(&other_contract, 3..=5, 100).unwrap().len(), 3);
+    }
+}
@@ -1,13 +1,13 @@
 use core::fmt;
 
-use dp_block::BlockId;
+use dp_block::{BlockId, BlockN};
 
 use crate::{DeoxysBackend, DeoxysStorageError};
 
 #[derive(Debug, Clone, Copy)]
 pub enum DbBlockId {
     Pending,
-    BlockN(u64),
+    BlockN(BlockN),
 }
 
 impl DbBlockId {
@@ -16,6 +16,12 @@ impl DbBlockId {
     }
 }
 
+impl From<BlockN> for DbBlockId {
+    fn from(value: BlockN) -> Self {
+        Self::BlockN(value)
+    }
+}
+
 pub trait DbBlockIdResolvable {
     fn resolve_db_block_id(&self, backend: &DeoxysBackend) -> Result<Option<DbBlockId>, DeoxysStorageError>;
 }
@@ -0,0 +1,298 @@
+//! Index of the contracts of each class, written when the blocks are stored.
+//!
+//! The keys are the class hash followed by the contract address, so that the contracts of a class are listed in the
+//! order of their addresses. A contract is listed under its current class only: a block replacing its class moves it
+//! to the new class, and reverting the block moves it back.
+//!
+//! The contracts stored before the index existed are indexed in the background under their class at the tip, in the
+//! order of their addresses. The index is not read until they all are.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dp_block::BlockN;
+use dp_state_update::StateDiff;
+use dp_utils::{spawn_rayon_task, wait_or_graceful_shutdown};
+use rocksdb::{Direction, IteratorMode};
+use starknet_core::types::Felt;
+
+use crate::db_block_id::DbBlockId;
+use crate::{Column, DatabaseExt, DeoxysBackend, DeoxysStorageError, WriteBatchWithTransaction};
+
+const ROW_CLASS_CONTRACTS_BACKFILL: &[u8] = b"class_contracts_backfill";
+/// Contracts indexed by the backfill in a single write.
+const BACKFILL_BATCH_SIZE: usize = 10_000;
+
+/// The contracts from address `next` on are not indexed yet, `next` is `None` once they all are.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ClassContractsBackfill {
+    next: Option<Felt>,
+}
+
+fn class_contract_key(class_hash: &Felt, contract_address: &Felt) -> [u8; 64] {
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(&class_hash.to_bytes_be());
+    key[32..].copy_from_slice(&contract_address.to_bytes_be());
+    key
+}
+
+/// The class of the contracts deployed by a block or whose class it replaced, once the block is applied.
+fn block_class_updates(state_diff: &StateDiff) -> HashMap<Felt, Felt> {
+    state_diff
+        .deployed_contracts()
+        .iter()
+        .map(|item| (item.address, item.class_hash))
+        .chain(state_diff.replaced_classes().iter().map(|item| (item.contract_address, item.class_hash)))
+        .collect()
+}
+
+impl DeoxysBackend {
+    /// The class of a contract before block `block_n`.
+    fn class_before(&self, block_n: u64, contract_address: &Felt) -> Result<Option<Felt>, DeoxysStorageError> {
+        match block_n.checked_sub(1) {
+            Some(parent) => self.get_contract_class_hash_at(&DbBlockId::BlockN(BlockN(parent)), contract_address),
+            None => Ok(None),
+        }
+    }
+
+    /// Adds to `batch` the moves of the contracts of block `block_n` to their new class, `batch` being the one storing
+    /// the block. The contract history of the parent block must be stored.
+    pub(crate) fn deployed_contracts_store_block(
+        &self,
+        batch: &mut WriteBatchWithTransaction,
+        block_n: u64,
+        state_diff: &StateDiff,
+    ) -> Result<(), DeoxysStorageError> {
+        let col = self.db.get_column(Column::ClassToContracts);
+        for (contract_address, class_hash) in updates {
+            let deployed_at = match self.class_before(block_n, &contract_address)? {
+                Some(old_class_hash) if old_class_hash == class_hash => continue,
+                Some(old_class_hash) => {
+                    batch.delete_cf(&col, class_contract_key(&old_class_hash, &contract_address));
+                    self.get_contract_deployed_at(&contract_address)?.unwrap_or(block_n)
+                }
+                None => block_n,
+            };
+            batch.put_cf(&col, class_contract_key(&class_hash, &contract_address), bincode::serialize(&deployed_at)?);
+        }
+        Ok(())
+    }
+
+    /// Adds to `batch` the moves of the contracts of block `block_n` back to their previous class. The contract
+    /// history of the block must not be reverted yet.
+    pub(crate) fn deployed_contracts_revert_block(
+        &self,
+        batch: &mut WriteBatchWithTransaction,
+        block_n: u64,
+        state_diff: &StateDiff,
+    ) -> Result<(), DeoxysStorageError> {
+        let col = self.db.get_column(Column::ClassToContracts);
+        for (contract_address, class_hash) in block_class_updates(state_diff) {
+            match self.class_before(block_n, &contract_address)? {
+                Some(old_class_hash) if old_class_hash == class_hash => {}
+                Some(old_class_hash) => {
+                    let deployed_at = self.get_contract_deployed_at(&contract_address)?.unwrap_or(block_n);
+                    batch.delete_cf(&col, class_contract_key(&class_hash, &contract_address));
+                    batch.put_cf(
+                        &col,
+                        class_contract_key(&old_class_hash, &contract_address),
+                        bincode::serialize(&deployed_at)?,
+                    );
+                }
+                None => batch.delete_cf(&col, class_contract_key(&class_hash, &contract_address)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the backfill of the index when the database was created without it. A database without blocks has
+    /// nothing to backfill.
+    pub(crate) fn init_class_contracts_backfill(&self) -> Result<(), DeoxysStorageError> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        if self.db.get_pinned_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL)?.is_some() {
+            return Ok(());
+        }
+        let backfill = ClassContractsBackfill { next: self.get_latest_block_n()?.map(|_| Felt::ZERO) };
+        self.db.put_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL, bincode::serialize(&backfill)?)?;
+        Ok(())
+    }
+
+    /// Whether the contracts stored before the index existed are all indexed, see
+    /// [`Self::backfill_class_contracts`]. The index is incomplete until then.
+    pub fn are_class_contracts_indexed(&self) -> Result<bool, DeoxysStorageError> {
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        let Some(stored) = self.db.get_pinned_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL)? else { return Ok(false) };
+        Ok(bincode::deserialize::<ClassContractsBackfill>(&stored)?.next.is_none())
+    }
+
+    /// Indexes the contracts stored before the index existed under their class at the tip, at most `max_contracts` of
+    /// them. Returns the number of contracts indexed, zero once the backfill is done. The blocks stored since move
+    /// their contracts as they are imported.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn backfill_class_contracts(&self, max_contracts: usize) -> Result<usize, DeoxysStorageError> {
+        // A block must not move a contract between the read of its class and the write of the index.
+        let _contract_history = self.lock_contract_history();
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        let Some(stored) = self.db.get_pinned_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL)? else { return Ok(0) };
+        let ClassContractsBackfill { next: Some(next) } = bincode::deserialize(&stored)? else { return Ok(0) };
+
+        let col = self.db.get_column(Column::ClassToContracts);
+        let mut batch = WriteBatchWithTransaction::default();
+        let (mut indexed, mut last) = (0, None);
+        if let Some(tip) = self.get_latest_block_n()? {
+            for res in self.iter_contract_class_hashes_from(tip, &next)?.take(max_contracts) {
+                let (contract_address, class_hash) = res?;
+                let deployed_at = self.get_contract_deployed_at(&contract_address)?.unwrap_or(tip);
+                batch.put_cf(
+                    &col,
+                    class_contract_key(&class_hash, &contract_address),
+                    bincode::serialize(&deployed_at)?,
+                );
+                (indexed, last) = (indexed + 1, Some(contract_address));
+            }
+        }
+
+        let next = last.filter(|_| indexed == max_contracts).map(|last| last + Felt::ONE);
+        batch.put_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL, bincode::serialize(&ClassContractsBackfill { next })?);
+        self.db.write(batch)?;
+        Ok(indexed)
+    }
+
+    /// Iterates lazily over the contracts of a class in the confirmed blocks, ordered by address, along with the block
+    /// which deployed them. The iteration starts right after `start_after`, so that a listing can be resumed from the
+    /// last contract it returned.
+    pub fn iter_class_contracts(
+        &self,
+        class_hash: &Felt,
+        start_after: Option<&Felt>,
+    ) -> impl Iterator<Item = Result<(Felt, u64), DeoxysStorageError>> + '_ {
+        let prefix = class_hash.to_bytes_be();
+        let start = class_contract_key(class_hash, start_after.unwrap_or(&Felt::ZERO));
+        let skip = start_after.is_some().then_some(start);
+        self.db
+            .iterator_cf(&self.db.get_column(Column::ClassToContracts), IteratorMode::From(&start, Direction::Forward))
+            .take_while(move |res| res.as_ref().map_or(true, |(key, _)| key.starts_with(&prefix)))
+            .filter(move |res| !matches!((res, &skip), (Ok((key, _)), Some(skip)) if **key == *skip))
+            .map(|res| {
+                let (key, value) = res?;
+                Ok((Felt::from_bytes_be_slice(&key[32..]), bincode::deserialize(&value)?))
+            })
+    }
+}
+
+/// Backfills the index with the contracts stored before it existed.
+pub(crate) async fn class_contracts_backfill_task(backend: Arc<DeoxysBackend>) -> anyhow::Result<()> {
+    loop {
+        let backend = Arc::clone(&backend);
+        let backfill = spawn_rayon_task(move || backend.backfill_class_contracts(BACKFILL_BATCH_SIZE));
+        let Some(indexed) = wait_or_graceful_shutdown(backfill).await else { return Ok(()) };
+        if indexed? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::{DeployedContractItem, ReplacedClassItem};
+
+    use super::*;
+    use crate::testing::temp_database;
+
+    const CLASS_A: Felt = Felt::from_hex_unchecked("0xa");
+    const CLASS_B: Felt = Felt::from_hex_unchecked("0xb");
+
+    fn store_block(backend: &DeoxysBackend, block_n: u64, deployed: &[(u64, Felt)], replaced: &[(u64, Felt)]) {
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            deployed
+                .iter()
+                .map(|(address, class_hash)| DeployedContractItem {
+                    address: Felt::from(*address),
+                    class_hash: *class_hash,
+                })
+                .collect(),
+            replaced
+                .iter()
+                .map(|(address, class_hash)| ReplacedClassItem {
+                    contract_address: Felt::from(*address),
+                    class_hash: *class_hash,
+                })
+                .collect(),
+            vec![],
+        );
+        let header = Header { block_number: block_n, ..Default::default() };
+        let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_n));
+        let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+        backend.store_block(block.into(), state_diff, vec![]).unwrap();
+    }
+
+    /// Checks the contracts of a class, given as addresses with the block which deployed them.
+    fn assert_contracts(backend: &DeoxysBackend, class_hash: Felt, start_after: Option<u64>, expected: &[(u64, u64)]) {
+        let contracts = backend
+            .iter_class_contracts(&class_hash, start_after.map(Felt::from).as_ref())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected: Vec<_> = expected.iter().map(|(address, block_n)| (Felt::from(*address), *block_n)).collect();
+        assert_eq!(contracts, expected);
+    }
+
+    #[tokio::test]
+    async fn test_contracts_of_a_class() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        // A new database has nothing to backfill.
+        assert!(backend.are_class_contracts_indexed().unwrap());
+
+        store_block(&backend, 0, &[(0x3, CLASS_A), (0x1, CLASS_A), (0x2, CLASS_B)], &[]);
+        store_block(&backend, 1, &[(0x4, CLASS_B)], &[]);
+        // Contract 0x1 is upgraded to class B, and contract 0x5 is deployed and upgraded in the same block.
+        store_block(&backend, 2, &[(0x5, CLASS_A)], &[(0x1, CLASS_B), (0x5, CLASS_B)]);
+
+        assert_contracts(&backend, CLASS_A, None, &[(0x3, 0)]);
+        assert_contracts(&backend, CLASS_B, None, &[(0x1, 0), (0x2, 0), (0x4, 1), (0x5, 2)]);
+        assert_contracts(&backend, CLASS_B, Some(0x2), &[(0x4, 1), (0x5, 2)]);
+        assert_contracts(&backend, Felt::from(0xc), None, &[]);
+
+        store_block(&backend, 3, &[], &[(0x1, CLASS_A)]);
+        assert_contracts(&backend, CLASS_A, None, &[(0x1, 0), (0x3, 0)]);
+        assert_contracts(&backend, CLASS_B, None, &[(0x2, 0), (0x4, 1), (0x5, 2)]);
+
+        // The contracts go back to the class they had.
+        backend.revert_to(1, false).unwrap();
+        assert_contracts(&backend, CLASS_A, None, &[(0x1, 0), (0x3, 0)]);
+        assert_contracts(&backend, CLASS_B, None, &[(0x2, 0), (0x4, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_class_contracts_backfill() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_block(&backend, 0, &[(0x3, CLASS_A), (0x1, CLASS_A), (0x2, CLASS_B)], &[]);
+        store_block(&backend, 1, &[(0x4, CLASS_B)], &[]);
+        store_block(&backend, 2, &[(0x5, CLASS_A)], &[(0x1, CLASS_B)]);
+        // The blocks were stored before the contracts were indexed.
+        let col = backend.db.get_column(Column::ClassToContracts);
+        backend.db.delete_range_cf(&col, &[] as &[u8], &[0xFF; 64]).unwrap();
+        let meta = backend.db.get_column(Column::BlockStorageMeta);
+        backend.db.delete_cf(&meta, ROW_CLASS_CONTRACTS_BACKFILL).unwrap();
+        backend.init_class_contracts_backfill().unwrap();
+        assert!(!backend.are_class_contracts_indexed().unwrap());
+
+        assert_eq!(backend.backfill_class_contracts(2).unwrap(), 2);
+        // The contracts not indexed yet are moved by the new blocks, and indexed under their class at the tip.
+        store_block(&backend, 3, &[], &[(0x5, CLASS_B)]);
+        let indexed: Vec<_> = (0..3).map(|_| backend.backfill_class_contracts(2).unwrap()).collect();
+        assert_eq!(indexed, vec![2, 1, 0]);
+        assert!(backend.are_class_contracts_indexed().unwrap());
+
+        assert_contracts(&backend, CLASS_A, None, &[(0x3, 0)]);
+        assert_contracts(&backend, CLASS_B, None, &[(0x1, 0), (0x2, 0), (0x4, 1), (0x5, 2)]);
+    }
+}
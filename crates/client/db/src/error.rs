@@ -1,4 +1,5 @@
 use crate::Column;
+use dp_utils::error_reporting::{self, Severity};
 use std::borrow::Cow;
 
 #[derive(thiserror::Error, Debug)]
@@ -21,6 +22,58 @@ pub enum DeoxysStorageError {
     InconsistentStorage(Cow<'static, str>),
     #[error("Cannot create a pending block of the genesis block of a chain")]
     PendingCreationNoGenesis,
+    #[error(
+        "Cannot revert to block {block_n}, only the last {} blocks below the tip {tip} can be reverted",
+        crate::MAX_REVERTIBLE_BLOCKS
+    )]
+    RevertTooDeep { block_n: u64, tip: u64 },
+    #[error(
+        "Cannot write the contract history of block {block_n}, it is already written up to block {last_committed}"
+    )]
+    NonMonotonicCommit { block_n: u64, last_committed: u64 },
+    #[error("A manual compaction of the database is already running")]
+    CompactionRunning,
+}
+
+impl DeoxysStorageError {
+    /// The database content contradicts itself. This is reported to the node operator.
+    pub fn inconsistent(message: impl Into<Cow<'static, str>>) -> Self {
+        let message = message.into();
+        error_reporting::report(Severity::Critical, "db.inconsistent", format_args!("Inconsistent storage: {message}"));
+        Self::InconsistentStorage(message)
+    }
+
+    /// The operation may succeed if it is attempted again, after the database is done with its other writes or
+    /// background work. Corrupted data, unsupported options and the other errors fail again the same way.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::RocksDB(err) => is_transient_kind(err.kind()),
+            Self::Bincode(err) => is_transient_bincode(err),
+            Self::BonsaiStorageError(bonsai_trie::BonsaiStorageError::Database(err)) => err.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Whether a [`DeoxysStorageError`] in the chain of sources of `err` is transient.
+    pub fn is_transient_source(err: &(dyn std::error::Error + 'static)) -> bool {
+        std::iter::successors(Some(err), |err| err.source())
+            .any(|err| err.downcast_ref::<Self>().is_some_and(Self::is_transient))
+    }
+}
+
+/// RocksDB returns these when it cannot take a lock or when a write is stalled by the compactions.
+pub fn is_transient_kind(kind: rocksdb::ErrorKind) -> bool {
+    matches!(kind, rocksdb::ErrorKind::Busy | rocksdb::ErrorKind::TryAgain | rocksdb::ErrorKind::TimedOut)
+}
+
+fn is_transient_bincode(err: &bincode::Error) -> bool {
+    match &**err {
+        bincode::ErrorKind::Io(err) => matches!(
+            err.kind(),
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+        ),
+        _ => false,
+    }
 }
 
 impl From<bonsai_trie::BonsaiStorageError<DbError>> for DeoxysStorageError {
@@ -41,6 +94,16 @@ pub enum DbError {
     Bincode(#[from] bincode::Error),
 }
 
+impl DbError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::RocksDB(err) => is_transient_kind(err.kind()),
+            Self::Bincode(err) => is_transient_bincode(err),
+            _ => false,
+        }
+    }
+}
+
 impl bonsai_trie::DBError for DbError {}
 
 #[derive(Debug)]
@@ -65,3 +128,61 @@ impl std::fmt::Display for TrieType {
         write!(f, "{}", self.as_str())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rocksdb::ErrorKind;
+
+    use super::*;
+
+    fn io_error(kind: std::io::ErrorKind) -> bincode::Error {
+        Box::new(bincode::ErrorKind::Io(kind.into()))
+    }
+
+    #[test]
+    fn test_transient_kinds() {
+        let table = [
+            (ErrorKind::Busy, true),
+            (ErrorKind::TryAgain, true),
+            (ErrorKind::TimedOut, true),
+            (ErrorKind::Corruption, false),
+            (ErrorKind::NotSupported, false),
+            (ErrorKind::InvalidArgument, false),
+            (ErrorKind::IOError, false),
+            (ErrorKind::NotFound, false),
+            (ErrorKind::ShutdownInProgress, false),
+            (ErrorKind::Unknown, false),
+        ];
+        for (kind, transient) in table {
+            assert_eq!(is_transient_kind(kind), transient, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_transient_storage_errors() {
+        use std::io::ErrorKind::*;
+
+        for kind in [TimedOut, Interrupted, WouldBlock] {
+            assert!(DeoxysStorageError::Bincode(io_error(kind)).is_transient(), "{kind:?}");
+            assert!(DeoxysStorageError::from(bonsai_trie::BonsaiStorageError::Database(DbError::Bincode(io_error(
+                kind
+            ))))
+            .is_transient());
+        }
+        for kind in [UnexpectedEof, InvalidData, PermissionDenied] {
+            assert!(!DeoxysStorageError::Bincode(io_error(kind)).is_transient(), "{kind:?}");
+        }
+        assert!(!DeoxysStorageError::Bincode(Box::new(bincode::ErrorKind::SizeLimit)).is_transient());
+        assert!(!DeoxysStorageError::InconsistentStorage("".into()).is_transient());
+        assert!(!DeoxysStorageError::RevertTooDeep { block_n: 0, tip: 1000 }.is_transient());
+        assert!(!DeoxysStorageError::from(bonsai_trie::BonsaiStorageError::Database(DbError::Format("".into())))
+            .is_transient());
+
+        // Through the sources of the error that is returned to the caller.
+        let err = anyhow::Error::from(DeoxysStorageError::Bincode(io_error(TimedOut))).context("Storing new block");
+        assert!(DeoxysStorageError::is_transient_source(err.as_ref()));
+        let err = anyhow::Error::from(DeoxysStorageError::MissingChainInfo).context("Storing new block");
+        assert!(!DeoxysStorageError::is_transient_source(err.as_ref()));
+        assert!(!DeoxysStorageError::is_transient_source(anyhow::anyhow!("Not a storage error").as_ref()));
+    }
+}
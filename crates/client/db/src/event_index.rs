@@ -0,0 +1,522 @@
+//! Index of the events of the stored blocks, so that event queries skip the blocks without matching events instead of
+//! reading every block of their range.
+//!
+//! Each block has a bloom filter of the addresses and keys of its events, and each contract has the list of the
+//! blocks it emitted events in. The index is written by a background task after the blocks are stored, the sync never
+//! waits for it. The task follows [`DeoxysBackend::subscribe_latest_header`], whose notifications coalesce: when the
+//! blocks come faster than they are indexed, the task indexes them in batches from the last indexed block. That block
+//! is persisted, the task catches up from it after a restart.
+//!
+//! The blocks above the last indexed one are not in the index yet, queries read them.
+//!
+//! When enabled, the events themselves are also indexed by contract, in the order of the chain: the queries on the
+//! events of a single contract read them without decoding the blocks. This index has its own tip, it is backfilled
+//! from the first block when it is enabled on an existing database, and removed when it is disabled.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use dp_block::{BlockId, BlockN, EventIndex, TxIndex};
+use dp_utils::lock::MutexExt;
+use dp_utils::{spawn_rayon_task, wait_or_graceful_shutdown};
+use rocksdb::{IteratorMode, ReadOptions, WriteOptions};
+use starknet_core::utils::starknet_keccak;
+use starknet_types_core::felt::Felt;
+
+use crate::{Column, DatabaseExt, DeoxysBackend, DeoxysStorageError, WriteBatchWithTransaction};
+
+type Result<T, E = DeoxysStorageError> = std::result::Result<T, E>;
+
+/// Key positions whose keys are in the bloom filters. The filters on the following positions are only checked
+/// against the events themselves.
+pub const INDEXED_KEY_POSITIONS: usize = 8;
+/// Blocks indexed in a single write.
+const INDEX_BATCH_SIZE: u64 = 64;
+
+const BLOOM_BITS_PER_ITEM: usize = 16;
+const BLOOM_MIN_BYTES: usize = 64;
+const BLOOM_HASHES: usize = 3;
+
+const ROW_EVENT_INDEX_TIP: &[u8] = b"event_index_tip";
+const ROW_CONTRACT_EVENTS_TIP: &[u8] = b"contract_events_tip";
+
+// NB: Columns cf needs prefix extractor of these length during creation
+pub(crate) const CONTRACT_EVENT_BLOCKS_PREFIX_EXTRACTOR: usize = 32;
+pub(crate) const CONTRACT_EVENTS_PREFIX_EXTRACTOR: usize = 32;
+
+const LAST_CONTRACT_EVENT_KEY: &[u8] = &[0xFF; 48];
+
+/// An event of a contract, read from the contract events index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractEvent {
+    pub block_n: u64,
+    pub tx_index: TxIndex,
+    pub transaction_hash: Felt,
+    pub keys: Vec<Felt>,
+    pub data: Vec<Felt>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredContractEvent {
+    transaction_hash: Felt,
+    keys: Vec<Felt>,
+    data: Vec<Felt>,
+}
+
+/// Bloom filter of the addresses and keys of the events of a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventBloom(Vec<u8>);
+
+impl EventBloom {
+    fn new(n_items: usize) -> Self {
+        Self(vec![0; (n_items * BLOOM_BITS_PER_ITEM / 8).max(BLOOM_MIN_BYTES)])
+    }
+
+    pub fn from_events<'a>(events: impl IntoIterator<Item = &'a dp_receipt::Event>) -> Self {
+        let items: HashSet<_> = events
+            .into_iter()
+            .flat_map(|event| {
+                let keys = event.keys.iter().take(INDEXED_KEY_POSITIONS).enumerate();
+                [address_item(&event.from_address)].into_iter().chain(keys.map(|(i, key)| key_item(i, key)))
+            })
+            .collect();
+        let mut bloom = Self::new(items.len());
+        for item in &items {
+            bloom.insert(item);
+        }
+        bloom
+    }
+
+    fn bits(&self, item: &[u8; 33]) -> impl Iterator<Item = usize> {
+        let n_bits = self.0.len() as u64 * 8;
+        // The top bits of a starknet keccak are zero.
+        let hash = starknet_keccak(item).to_bytes_be();
+        (0..BLOOM_HASHES).map(move |i| {
+            let start = 8 + 8 * i;
+            // UNWRAP: the slice is 8 bytes long.
+            (u64::from_be_bytes(hash[start..start + 8].try_into().unwrap()) % n_bits) as usize
+        })
+    }
+
+    fn insert(&mut self, item: &[u8; 33]) {
+        for bit in self.bits(item).collect::<Vec<_>>() {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    fn may_contain(&self, item: &[u8; 33]) -> bool {
+        self.bits(item).all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Whether the block may have events from `address` matching `keys`, with the semantics of the `starknet_getEvents`
+    /// filter. `false` means there are none.
+    pub fn may_match(&self, address: Option<&Felt>, keys: &[Vec<Felt>]) -> bool {
+        address.map_or(true, |address| self.may_contain(&address_item(address)))
+            && keys
+                .iter()
+                .take(INDEXED_KEY_POSITIONS)
+                .enumerate()
+                .all(|(i, keys)| keys.is_empty() || keys.iter().any(|key| self.may_contain(&key_item(i, key))))
+    }
+}
+
+fn address_item(address: &Felt) -> [u8; 33] {
+    felt_item(u8::MAX, address)
+}
+
+fn key_item(position: usize, key: &Felt) -> [u8; 33] {
+    felt_item(position as u8, key)
+}
+
+fn felt_item(tag: u8, felt: &Felt) -> [u8; 33] {
+    let mut item = [0u8; 33];
+    item[0] = tag;
+    item[1..].copy_from_slice(&felt.to_bytes_be());
+    item
+}
+
+fn contract_event_block_key(address: &Felt, block_n: BlockN) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..32].copy_from_slice(&address.to_bytes_be());
+    key[32..].copy_from_slice(&block_n.to_be_bytes());
+    key
+}
+
+/// `(contract_address, block_n, tx_index, event_index)`, the event index being the position of the event in its
+/// transaction.
+fn contract_event_key(address: &Felt, block_n: BlockN, tx_index: TxIndex, event_index: EventIndex) -> [u8; 48] {
+    let mut key = [0u8; 48];
+    key[..32].copy_from_slice(&address.to_bytes_be());
+    key[32..40].copy_from_slice(&block_n.to_be_bytes());
+    key[40..44].copy_from_slice(&tx_index.to_be_bytes());
+    key[44..].copy_from_slice(&event_index.to_be_bytes());
+    key
+}
+
+/// The block and the transaction index of a key of the contract events.
+fn contract_event_position(key: &[u8]) -> Option<(BlockN, TxIndex)> {
+    Some((BlockN::from_be_bytes(key.get(32..40)?)?, TxIndex::from_be_bytes(key.get(40..44)?)?))
+}
+
+fn index_out_of_range() -> DeoxysStorageError {
+    DeoxysStorageError::inconsistent("Event index out of range")
+}
+
+impl DeoxysBackend {
+    /// The last block whose events are indexed, `None` when no block is.
+    pub fn get_event_index_tip(&self) -> Result<Option<u64>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_EVENT_INDEX_TIP)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    /// Indexes the events of the stored blocks following the event index tip, at most `max_blocks` of them. Returns
+    /// the number of blocks indexed, zero when the index is up to date.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn index_block_events(&self, max_blocks: u64) -> Result<u64> {
+        // A revert must not happen between the read of the blocks and the write of their index.
+        let _lock = self.event_index.lock_or_recover();
+        let Some(latest_block_n) = self.get_latest_block_n()? else { return Ok(0) };
+        let first = self.get_event_index_tip()?.map_or(0, |tip| tip + 1);
+        let last = latest_block_n.min(first.saturating_add(max_blocks).saturating_sub(1));
+        if first > last {
+            return Ok(0);
+        }
+
+        let mut tx = WriteBatchWithTransaction::default();
+        let blooms = self.db.get_column(Column::BlockNToEventBloom);
+        let contract_event_blocks = self.db.get_column(Column::ContractToEventBlocks);
+        for block_n in first..=last {
+            let receipts = self.get_block_receipts(&BlockId::Number(block_n))?.ok_or_else(|| {
+                DeoxysStorageError::inconsistent(format!("Indexing the events of block {block_n}, which is not stored"))
+            })?;
+            let events = receipts.iter().flat_map(|receipt| receipt.events());
+
+            let addresses: HashSet<_> = events.clone().map(|event| event.from_address).collect();
+            for address in &addresses {
+                tx.put_cf(&contract_event_blocks, contract_event_block_key(address, BlockN(block_n)), b"");
+            }
+            tx.put_cf(&blooms, bincode::serialize(&BlockN(block_n))?, EventBloom::from_events(events).0);
+        }
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        tx.put_cf(&meta, ROW_EVENT_INDEX_TIP, bincode::serialize(&BlockN(last))?);
+
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        self.db.write_opt(tx, &writeopts)?;
+        Ok(last - first + 1)
+    }
+
+    /// The bloom filter of the events of block `block_n`, `None` when the block is not indexed.
+    pub fn get_event_bloom(&self, block_n: u64) -> Result<Option<EventBloom>> {
+        let col = self.db.get_column(Column::BlockNToEventBloom);
+        Ok(self.db.get_cf(&col, bincode::serialize(&BlockN(block_n))?)?.map(EventBloom))
+    }
+
+    /// The first block of `from..=to` in which `address` emitted events. The blocks of the range must be indexed.
+    pub fn next_block_with_events_from(&self, address: &Felt, from: u64, to: u64) -> Result<Option<u64>> {
+        let start_at = contract_event_block_key(address, BlockN(from));
+        let mut options = ReadOptions::default();
+        options.set_prefix_same_as_start(true);
+        let mode = IteratorMode::From(&start_at, rocksdb::Direction::Forward);
+        let mut iter = self.db.iterator_cf_opt(&self.db.get_column(Column::ContractToEventBlocks), options, mode);
+
+        let Some(res) = iter.next() else { return Ok(None) };
+        let (key, _) = res?;
+        let block_n = key
+            .get(32..)
+            .and_then(BlockN::from_be_bytes)
+            .ok_or_else(|| DeoxysStorageError::inconsistent("Invalid key in the contract event blocks"))?;
+        Ok(Some(block_n.0).filter(|block_n| *block_n <= to))
+    }
+
+    /// The last block whose events are in the contract events index, `None` when no block is.
+    pub fn get_contract_events_tip(&self) -> Result<Option<u64>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_CONTRACT_EVENTS_TIP)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize::<BlockN>(&res)?.0))
+    }
+
+    /// Adds the events of the stored blocks following the contract events tip to the contract events index, at most
+    /// `max_blocks` of them. Returns the number of blocks indexed, zero when the index is up to date.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn index_contract_events(&self, max_blocks: u64) -> Result<u64> {
+        let _lock = self.event_index.lock_or_recover();
+        let Some(latest_block_n) = self.get_latest_block_n()? else { return Ok(0) };
+        let first = self.get_contract_events_tip()?.map_or(0, |tip| tip + 1);
+        let last = latest_block_n.min(first.saturating_add(max_blocks).saturating_sub(1));
+        if first > last {
+            return Ok(0);
+        }
+
+        let mut tx = WriteBatchWithTransaction::default();
+        let contract_events = self.db.get_column(Column::ContractEvents);
+        for block_n in first..=last {
+            let receipts = self.get_block_receipts(&BlockId::Number(block_n))?.ok_or_else(|| {
+                DeoxysStorageError::inconsistent(format!("Indexing the events of block {block_n}, which is not stored"))
+            })?;
+            for (tx_index, receipt) in receipts.iter().enumerate() {
+                let transaction_hash = receipt.transaction_hash();
+                for (event_index, event) in receipt.events().iter().enumerate() {
+                    let tx_index = TxIndex::try_from(tx_index).map_err(|_| index_out_of_range())?;
+                    let event_index = EventIndex::try_from(event_index).map_err(|_| index_out_of_range())?;
+                    let key = contract_event_key(&event.from_address, BlockN(block_n), tx_index, event_index);
+                    let stored =
+                        StoredContractEvent { transaction_hash, keys: event.keys.clone(), data: event.data.clone() };
+                    tx.put_cf(&contract_events, key, bincode::serialize(&stored)?);
+                }
+            }
+        }
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        tx.put_cf(&meta, ROW_CONTRACT_EVENTS_TIP, bincode::serialize(&BlockN(last))?);
+
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        self.db.write_opt(tx, &writeopts)?;
+        Ok(last - first + 1)
+    }
+
+    /// Removes the contract events index, when it is disabled.
+    pub fn clear_contract_events(&self) -> Result<()> {
+        let _lock = self.event_index.lock_or_recover();
+        if self.get_contract_events_tip()?.is_none() {
+            return Ok(());
+        }
+        let mut tx = WriteBatchWithTransaction::default();
+        tx.delete_range_cf(&self.db.get_column(Column::ContractEvents), &[] as &[u8], LAST_CONTRACT_EVENT_KEY);
+        tx.delete_cf(&self.db.get_column(Column::BlockStorageMeta), ROW_CONTRACT_EVENTS_TIP);
+        self.db.write(tx)?;
+        Ok(())
+    }
+
+    /// The events of `address` in `from_block..=to_block`, in the order of the chain, at most `limit` of them. The
+    /// first `skip` events of `address` in `from_block` are skipped. The blocks of the range must be in the contract
+    /// events index.
+    ///
+    /// Returns `None` when `address` emitted fewer than `skip` events in `from_block`.
+    pub fn events_for_contract(
+        &self,
+        address: &Felt,
+        from_block: u64,
+        to_block: u64,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Option<Vec<ContractEvent>>> {
+        let start_at = contract_event_key(address, BlockN(from_block), TxIndex(0), EventIndex(0));
+        let mut options = ReadOptions::default();
+        options.set_prefix_same_as_start(true);
+        let mode = IteratorMode::From(&start_at, rocksdb::Direction::Forward);
+        let iter = self.db.iterator_cf_opt(&self.db.get_column(Column::ContractEvents), options, mode);
+
+        let mut events = Vec::new();
+        let mut skipped = 0;
+        for res in iter {
+            let (key, value) = res?;
+            let (block_n, tx_index) = contract_event_position(&key)
+                .ok_or_else(|| DeoxysStorageError::inconsistent("Invalid key in the contract events"))?;
+            if block_n.0 > to_block {
+                break;
+            }
+            if skipped < skip {
+                if block_n.0 != from_block {
+                    return Ok(None);
+                }
+                skipped += 1;
+                continue;
+            }
+            if events.len() == limit {
+                break;
+            }
+            let StoredContractEvent { transaction_hash, keys, data } = bincode::deserialize(&value)?;
+            events.push(ContractEvent { block_n: block_n.0, tx_index, transaction_hash, keys, data });
+        }
+        Ok(Some(events).filter(|_| skipped == skip))
+    }
+
+    /// Adds to `tx` the removal of the index of the blocks after `block_n`, which are being reverted. The caller holds
+    /// the event index lock until `tx` is written.
+    pub(crate) fn event_index_revert(&self, tx: &mut WriteBatchWithTransaction, block_n: u64) -> Result<()> {
+        let tip = self.get_event_index_tip()?.filter(|tip| *tip > block_n);
+        let contract_events_tip = self.get_contract_events_tip()?.filter(|tip| *tip > block_n);
+        let Some(last) = tip.max(contract_events_tip) else { return Ok(()) };
+        let blooms = self.db.get_column(Column::BlockNToEventBloom);
+        let contract_event_blocks = self.db.get_column(Column::ContractToEventBlocks);
+        let contract_events = self.db.get_column(Column::ContractEvents);
+        for reverted in block_n + 1..=last {
+            let receipts = self.get_block_receipts(&BlockId::Number(reverted))?.ok_or_else(|| {
+                DeoxysStorageError::inconsistent(format!(
+                    "Reverting the events of block {reverted}, which is not stored"
+                ))
+            })?;
+            let addresses: HashSet<_> =
+                receipts.iter().flat_map(|receipt| receipt.events()).map(|event| event.from_address).collect();
+            if tip.is_some_and(|tip| reverted <= tip) {
+                for address in &addresses {
+                    tx.delete_cf(&contract_event_blocks, contract_event_block_key(address, BlockN(reverted)));
+                }
+                tx.delete_cf(&blooms, bincode::serialize(&BlockN(reverted))?);
+            }
+            if contract_events_tip.is_some_and(|tip| reverted <= tip) {
+                for address in &addresses {
+                    tx.delete_range_cf(
+                        &contract_events,
+                        contract_event_key(address, BlockN(reverted), TxIndex(0), EventIndex(0)),
+                        contract_event_key(address, BlockN(reverted + 1), TxIndex(0), EventIndex(0)),
+                    );
+                }
+            }
+        }
+        let meta = self.db.get_column(Column::BlockStorageMeta);
+        if tip.is_some() {
+            tx.put_cf(&meta, ROW_EVENT_INDEX_TIP, bincode::serialize(&BlockN(block_n))?);
+        }
+        if contract_events_tip.is_some() {
+            tx.put_cf(&meta, ROW_CONTRACT_EVENTS_TIP, bincode::serialize(&BlockN(block_n))?);
+        }
+        Ok(())
+    }
+}
+
+/// Indexes the new blocks, along with their contract events when `index_contract_events` is set. The contract events
+/// index is removed otherwise.
+pub(crate) async fn event_index_task(backend: Arc<DeoxysBackend>, index_contract_events: bool) -> anyhow::Result<()> {
+    if !index_contract_events {
+        let backend = Arc::clone(&backend);
+        spawn_rayon_task(move || backend.clear_contract_events()).await?;
+    }
+    let mut latest_header = backend.subscribe_latest_header();
+    loop {
+        latest_header.borrow_and_update();
+        loop {
+            let backend = Arc::clone(&backend);
+            let indexing = spawn_rayon_task(move || {
+                let indexed = backend.index_block_events(INDEX_BATCH_SIZE)?;
+                let contract_events =
+                    if index_contract_events { backend.index_contract_events(INDEX_BATCH_SIZE)? } else { 0 };
+                Ok::<_, DeoxysStorageError>(indexed.max(contract_events))
+            });
+            let Some(indexed) = wait_or_graceful_shutdown(indexing).await else { return Ok(()) };
+            if indexed? == 0 {
+                break;
+            }
+        }
+        match wait_or_graceful_shutdown(latest_header.changed()).await {
+            Some(Ok(())) => {}
+            _ => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_receipt::{Event, ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit, TransactionReceipt};
+    use dp_state_update::StateDiff;
+
+    use super::*;
+    use crate::testing::temp_database;
+
+    fn event(from_address: u64, keys: &[u64]) -> Event {
+        Event {
+            from_address: from_address.into(),
+            keys: keys.iter().map(|key| Felt::from(*key)).collect(),
+            data: vec![],
+        }
+    }
+
+    fn store_block(backend: &DeoxysBackend, block_n: u64, events: Vec<Event>) {
+        let receipt = TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            transaction_hash: Felt::from(0x100 + block_n),
+            actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events,
+            execution_resources: Default::default(),
+            execution_result: ExecutionResult::Succeeded,
+        });
+        let header = Header { block_number: block_n, ..Default::default() };
+        let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_n));
+        let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![receipt]));
+        backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_event_bloom() {
+        let events = [event(1, &[10, 11]), event(2, &[20])];
+        let bloom = EventBloom::from_events(&events);
+        let felts = |felts: &[u64]| felts.iter().map(|felt| Felt::from(*felt)).collect::<Vec<_>>();
+
+        assert!(bloom.may_match(None, &[]));
+        assert!(bloom.may_match(Some(&Felt::ONE), &[]));
+        assert!(bloom.may_match(None, &[felts(&[10, 99]), vec![], felts(&[])]));
+        assert!(bloom.may_match(Some(&Felt::TWO), &[felts(&[20])]));
+        assert!(!bloom.may_match(Some(&Felt::THREE), &[]));
+        // The keys are indexed along with their position.
+        assert!(!bloom.may_match(None, &[felts(&[11])]));
+        assert!(bloom.may_match(None, &[vec![], felts(&[11])]));
+
+        let empty = EventBloom::from_events(std::iter::empty());
+        assert!(!empty.may_match(Some(&Felt::ONE), &[]));
+        assert!(empty.may_match(None, &[]));
+    }
+
+    #[tokio::test]
+    async fn test_event_index_catches_up_and_reverts() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        assert_eq!(backend.index_block_events(INDEX_BATCH_SIZE).unwrap(), 0);
+
+        store_block(backend, 0, vec![event(1, &[10])]);
+        store_block(backend, 1, vec![event(2, &[20])]);
+        store_block(backend, 2, vec![event(1, &[30]), event(2, &[30])]);
+        assert_eq!(backend.get_event_index_tip().unwrap(), None);
+        assert_eq!(backend.get_event_bloom(0).unwrap(), None);
+
+        // The index catches up from its tip.
+        assert_eq!(backend.index_block_events(2).unwrap(), 2);
+        assert_eq!(backend.get_event_index_tip().unwrap(), Some(1));
+        assert_eq!(backend.get_event_bloom(2).unwrap(), None);
+        assert_eq!(backend.index_block_events(INDEX_BATCH_SIZE).unwrap(), 1);
+        assert_eq!(backend.get_event_index_tip().unwrap(), Some(2));
+        assert_eq!(backend.index_block_events(INDEX_BATCH_SIZE).unwrap(), 0);
+
+        assert!(backend.get_event_bloom(2).unwrap().unwrap().may_match(Some(&Felt::ONE), &[vec![Felt::from(30)]]));
+        assert_eq!(backend.next_block_with_events_from(&Felt::ONE, 0, 2).unwrap(), Some(0));
+        assert_eq!(backend.next_block_with_events_from(&Felt::ONE, 1, 2).unwrap(), Some(2));
+        assert_eq!(backend.next_block_with_events_from(&Felt::ONE, 1, 1).unwrap(), None);
+        assert_eq!(backend.next_block_with_events_from(&Felt::THREE, 0, 2).unwrap(), None);
+
+        // The index of the reverted blocks is removed along with them.
+        backend.revert_to(0, false).unwrap();
+        assert_eq!(backend.get_event_index_tip().unwrap(), Some(0));
+        assert_eq!(backend.get_event_bloom(1).unwrap(), None);
+        assert_eq!(backend.get_event_bloom(2).unwrap(), None);
+        assert_eq!(backend.next_block_with_events_from(&Felt::TWO, 0, u64::MAX).unwrap(), None);
+        assert_eq!(backend.next_block_with_events_from(&Felt::ONE, 1, u64::MAX).unwrap(), None);
+
+        // The blocks of the new chain are indexed instead.
+        store_block(backend, 1, vec![event(3, &[40])]);
+        assert_eq!(backend.index_block_events(INDEX_BATCH_SIZE).unwrap(), 1);
+        assert_eq!(backend.next_block_with_events_from(&Felt::THREE, 0, 1).unwrap(), Some(1));
+        assert_eq!(backend.next_block_with_events_from(&Felt::TWO, 0, 1).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_event_index_task_follows_new_blocks() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_block(&backend, 0, vec![event(1, &[10])]);
+
+        let task = tokio::spawn(event_index_task(Arc::clone(&backend), false));
+        store_block(&backend, 1, vec![event(2, &[20])]);
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while backend.get_event_index_tip().unwrap() != Some(1) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The blocks are indexed");
+        task.abort();
+    }
+}
@@ -0,0 +1,142 @@
+//! Gas prices of the latest blocks, kept in memory to suggest fees without reading the headers back from the database.
+//! The backend records the prices of every block it stores, and reads the ones of the latest stored blocks when it is
+//! opened.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use dp_block::header::GasPrices;
+use dp_utils::lock::MutexExt;
+
+/// Number of blocks whose gas prices are kept.
+pub const GAS_PRICE_HISTORY_LEN: usize = 256;
+
+#[derive(Debug)]
+pub struct GasPriceHistory {
+    capacity: usize,
+    /// Ordered by block number, without gaps.
+    prices: Mutex<VecDeque<(u64, GasPrices)>>,
+}
+
+impl Default for GasPriceHistory {
+    fn default() -> Self {
+        Self::new(GAS_PRICE_HISTORY_LEN)
+    }
+}
+
+impl GasPriceHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), prices: Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The prices of the blocks from `block_n` replace the recorded ones, which happens when blocks are reverted. A
+    /// block that does not follow the recorded ones starts the history again.
+    pub(crate) fn record(&self, block_n: u64, prices: GasPrices) {
+        let mut history = self.prices.lock_or_recover();
+        while history.back().is_some_and(|(recorded, _)| *recorded >= block_n) {
+            history.pop_back();
+        }
+        if history.back().is_some_and(|(recorded, _)| *recorded + 1 != block_n) {
+            history.clear();
+        }
+        if history.len() == self.capacity {
+            history.pop_front();
+        }
+        history.push_back((block_n, prices));
+    }
+
+    /// The gas prices of the last `n` recorded blocks, oldest first. Fewer blocks are returned when fewer are recorded.
+    pub fn last(&self, n: usize) -> Vec<(u64, GasPrices)> {
+        let history = self.prices.lock_or_recover();
+        history.iter().skip(history.len().saturating_sub(n)).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::StateDiff;
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::DatabaseService;
+
+    fn prices(price: u128) -> GasPrices {
+        GasPrices {
+            eth_l1_gas_price: price,
+            strk_l1_gas_price: price * 10,
+            eth_l1_data_gas_price: price + 1,
+            strk_l1_data_gas_price: price * 10 + 1,
+        }
+    }
+
+    fn block_numbers(history: &[(u64, GasPrices)]) -> Vec<u64> {
+        history.iter().map(|(block_n, _)| *block_n).collect()
+    }
+
+    #[test]
+    fn oldest_blocks_are_evicted() {
+        let history = GasPriceHistory::new(3);
+        for block_n in 0..5 {
+            history.record(block_n, prices(block_n as u128));
+        }
+        assert_eq!(history.last(10), vec![(2, prices(2)), (3, prices(3)), (4, prices(4))]);
+        assert_eq!(history.last(2), vec![(3, prices(3)), (4, prices(4))]);
+        assert_eq!(history.last(0), vec![]);
+    }
+
+    #[test]
+    fn reverted_blocks_are_replaced() {
+        let history = GasPriceHistory::new(10);
+        for block_n in 0..5 {
+            history.record(block_n, prices(block_n as u128));
+        }
+        // Reverted to block 2, then block 3 is stored again.
+        history.record(2, prices(2));
+        history.record(3, prices(30));
+        assert_eq!(block_numbers(&history.last(10)), vec![0, 1, 2, 3]);
+        assert_eq!(history.last(1), vec![(3, prices(30))]);
+
+        // A gap starts the history again.
+        history.record(8, prices(8));
+        assert_eq!(history.last(10), vec![(8, prices(8))]);
+    }
+
+    #[tokio::test]
+    async fn backend_records_its_blocks_and_reads_them_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = db.backend();
+        assert_eq!(backend.gas_price_history().last(GAS_PRICE_HISTORY_LEN), vec![]);
+
+        let n_blocks = GAS_PRICE_HISTORY_LEN as u64 + 4;
+        for block_number in 0..n_blocks {
+            let header = Header { block_number, l1_gas_price: prices(block_number as u128), ..Default::default() };
+            let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_number));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+        let history = backend.gas_price_history().last(GAS_PRICE_HISTORY_LEN);
+        assert_eq!(block_numbers(&history), (4..n_blocks).collect::<Vec<_>>());
+        assert_eq!(history[0].1, prices(4));
+
+        backend.revert_to(n_blocks - 3, false).unwrap();
+        assert_eq!(backend.gas_price_history().last(1), vec![(n_blocks - 3, prices(n_blocks as u128 - 3))]);
+
+        // The history is read back from the database when the node restarts.
+        drop(db);
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        let history = db.backend().gas_price_history().last(GAS_PRICE_HISTORY_LEN);
+        let tip = n_blocks - 3;
+        assert_eq!(block_numbers(&history), (tip + 1 - GAS_PRICE_HISTORY_LEN as u64..=tip).collect::<Vec<_>>());
+        assert_eq!(history[0].1, prices((tip + 1) as u128 - GAS_PRICE_HISTORY_LEN as u128));
+    }
+}
@@ -0,0 +1,60 @@
+//! L1 to L2 messages: the ones executed on L2, by message nonce, so that no message is executed twice, and how far
+//! the L1 workers got on L1.
+use serde::{Deserialize, Serialize};
+use starknet_core::types::Felt;
+
+use crate::{Column, DatabaseExt, DeoxysBackend, DeoxysStorageError};
+
+type Result<T, E = DeoxysStorageError> = std::result::Result<T, E>;
+
+const ROW_LAST_SYNCED_L1_BLOCK_WITH_EVENT: &[u8] = b"messaging_last_synced_l1_block_with_event";
+const ROW_L1_LAST_CONFIRMED_EVENT_BLOCK: &[u8] = b"l1_last_confirmed_event_block";
+
+/// The L1 block an L1 worker processed the core contract events up to. The hash tells whether that block was
+/// reorged away since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastSyncedEventBlock {
+    pub block_number: u64,
+    pub block_hash: [u8; 32],
+}
+
+impl DeoxysBackend {
+    /// Hash of the L1 handler transaction that executed the message with this nonce, if it is part of a closed block.
+    pub fn get_l1_handler_tx_hash(&self, nonce: u64) -> Result<Option<Felt>> {
+        let col = self.db.get_column(Column::L1MessagingNonceToTxHash);
+        let Some(res) = self.db.get_pinned_cf(&col, bincode::serialize(&nonce)?)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize(&res)?))
+    }
+
+    /// Every message sent to L2 up to this L1 block was handled by the L1 messaging worker.
+    pub fn messaging_last_synced_l1_block_with_event(&self) -> Result<Option<LastSyncedEventBlock>> {
+        self.get_event_block(ROW_LAST_SYNCED_L1_BLOCK_WITH_EVENT)
+    }
+
+    /// Written with the WAL enabled, so that the L1 messaging worker resumes from there after a crash.
+    pub fn messaging_update_last_synced_l1_block_with_event(&self, block: LastSyncedEventBlock) -> Result<()> {
+        self.put_event_block(ROW_LAST_SYNCED_L1_BLOCK_WITH_EVENT, block)
+    }
+
+    /// The L1 block the last confirmed block was read at: every `LogStateUpdate` event up to this L1 block was
+    /// applied.
+    pub fn get_l1_last_confirmed_event_block(&self) -> Result<Option<LastSyncedEventBlock>> {
+        self.get_event_block(ROW_L1_LAST_CONFIRMED_EVENT_BLOCK)
+    }
+
+    pub fn write_l1_last_confirmed_event_block(&self, block: LastSyncedEventBlock) -> Result<()> {
+        self.put_event_block(ROW_L1_LAST_CONFIRMED_EVENT_BLOCK, block)
+    }
+
+    fn get_event_block(&self, row: &[u8]) -> Result<Option<LastSyncedEventBlock>> {
+        let col = self.db.get_column(Column::Meta);
+        let Some(res) = self.db.get_pinned_cf(&col, row)? else { return Ok(None) };
+        Ok(Some(bincode::deserialize(&res)?))
+    }
+
+    fn put_event_block(&self, row: &[u8], block: LastSyncedEventBlock) -> Result<()> {
+        let col = self.db.get_column(Column::Meta);
+        self.db.put_cf(&col, row, bincode::serialize(&block)?)?;
+        Ok(())
+    }
+}
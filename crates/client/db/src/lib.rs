@@ -1,20 +1,26 @@
 //! Deoxys database
 
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard, RwLockReadGuard, RwLockWriteGuard};
 use std::time::{Duration, Instant};
 use std::{fmt, fs};
 
 use anyhow::{Context, Result};
-use bonsai_db::{BonsaiDb, DatabaseKeyMapping};
 use bonsai_trie::id::BasicId;
-use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
+use compaction::{AppliedCompactionOptions, CompactionConfig, CompactionMetrics, CompactionSchedule};
+use db_block_id::DbBlockId;
 use db_metrics::DbMetrics;
 use dp_block::chain_config::ChainConfig;
+use dp_block::{BlockId, BlockN, BlockTag, DeoxysMaybePendingBlockInfo, Header};
+use dp_utils::lock::MutexExt;
 use dp_utils::service::Service;
 use rocksdb::backup::{BackupEngine, BackupEngineOptions};
 
 pub mod block_db;
+mod block_inner;
+pub mod chain_head;
 mod error;
 use rocksdb::{
     BoundColumnFamily, ColumnFamilyDescriptor, DBCompressionType, DBWithThreadMode, Env, FlushOptions, MultiThreaded,
@@ -22,14 +28,27 @@ use rocksdb::{
 };
 pub mod bonsai_db;
 pub mod class_db;
+pub mod compaction;
 pub mod contract_db;
 pub mod db_block_id;
 pub mod db_metrics;
+pub mod deployed_contracts;
+pub mod event_index;
+pub mod gas_price_history;
+pub mod l1_db;
 pub mod storage_updates;
+pub mod sync_status;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tries;
+pub mod worker_health;
 
 pub use error::{DeoxysStorageError, TrieType};
-use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
-use tokio::sync::{mpsc, oneshot};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Pedersen, Poseidon};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+pub use tries::GlobalTrie;
 
 pub type DB = DBWithThreadMode<MultiThreaded>;
 
@@ -38,11 +57,15 @@ pub type WriteBatchWithTransaction = rocksdb::WriteBatchWithTransaction<false>;
 
 const DB_UPDATES_BATCH_SIZE: usize = 1024;
 
+/// Number of blocks below the tip that can be reverted. The global tries keep the changes of that many blocks.
+pub const MAX_REVERTIBLE_BLOCKS: u64 = 1024;
+
 pub(crate) async fn open_rocksdb(
     path: &Path,
     create: bool,
     backup_dir: Option<PathBuf>,
     restore_from_latest_backup: bool,
+    compaction_rate_limit: Option<u64>,
 ) -> Result<(Arc<DB>, Option<mpsc::Sender<BackupRequest>>)> {
     let mut opts = Options::default();
     opts.set_report_bg_io_stats(true);
@@ -59,6 +82,10 @@ pub(crate) async fn open_rocksdb(
     opts.set_atomic_flush(true);
     opts.set_manual_wal_flush(true);
     opts.set_max_subcompactions(cores as _);
+    if let Some(rate_limit) = compaction_rate_limit {
+        // Set on the database options, so that every column shares it.
+        opts.set_ratelimiter(rate_limit as _, 100_000, 10);
+    }
 
     let mut env = Env::new().context("Creating rocksdb env")?;
     // env.set_high_priority_background_threads(cores); // flushes
@@ -162,7 +189,7 @@ pub enum Column {
     // contract_address history block_number => nonce
     ContractToNonces,
 
-    // Class hash => compiled class hash
+    // Class hash => compiled class hash and block_n which declared the class, for the confirmed classes
     ContractClassHashes,
 
     // Pending columns for contract db
@@ -188,6 +215,22 @@ pub enum Column {
     BonsaiClassesTrie,
     BonsaiClassesFlat,
     BonsaiClassesLog,
+
+    /// L1 to L2 message nonce => hash of the L1 handler transaction that executed it
+    L1MessagingNonceToTxHash,
+
+    /// Event index, written after the blocks are stored
+    // block_n => bloom filter of the event addresses and keys
+    BlockNToEventBloom,
+    // (contract_address, block_n) => ()
+    ContractToEventBlocks,
+    // (contract_address, block_n, tx_index, event_index) => event, when the contract events index is enabled
+    ContractEvents,
+
+    // class_hash => block_n and hash of the transaction which declared the class
+    ClassDeclaredAt,
+    // (class_hash, contract_address) => block_n which deployed the contract, for the contracts of the class
+    ClassToContracts,
 }
 
 impl fmt::Debug for Column {
@@ -202,6 +245,18 @@ impl fmt::Display for Column {
     }
 }
 
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|column| column.rocksdb_name() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown column `{s}`"))
+    }
+}
+
 impl Column {
     pub const ALL: &'static [Self] = {
         use Column::*;
@@ -234,6 +289,12 @@ impl Column {
             PendingContractToClassHashes,
             PendingContractToNonces,
             PendingContractStorage,
+            L1MessagingNonceToTxHash,
+            BlockNToEventBloom,
+            ContractToEventBlocks,
+            ContractEvents,
+            ClassDeclaredAt,
+            ClassToContracts,
         ]
     };
     pub const NUM_COLUMNS: usize = Self::ALL.len();
@@ -269,6 +330,12 @@ impl Column {
             PendingContractToClassHashes => "pending_contract_to_class_hashes",
             PendingContractToNonces => "pending_contract_to_nonces",
             PendingContractStorage => "pending_contract_storage",
+            L1MessagingNonceToTxHash => "l1_messaging_nonce_to_tx_hash",
+            BlockNToEventBloom => "block_n_to_event_bloom",
+            ContractToEventBlocks => "contract_to_event_blocks",
+            ContractEvents => "contract_events",
+            ClassDeclaredAt => "class_declared_at",
+            ClassToContracts => "class_to_contracts",
         }
     }
 
@@ -292,6 +359,16 @@ impl Column {
                     contract_db::CONTRACT_NONCES_PREFIX_EXTRACTOR,
                 ));
             }
+            Column::ContractToEventBlocks => {
+                opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(
+                    event_index::CONTRACT_EVENT_BLOCKS_PREFIX_EXTRACTOR,
+                ));
+            }
+            Column::ContractEvents => {
+                opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(
+                    event_index::CONTRACT_EVENTS_PREFIX_EXTRACTOR,
+                ));
+            }
             _ => {}
         }
         opts
@@ -319,10 +396,39 @@ pub struct DeoxysBackend {
     db: Arc<DB>,
     last_flush_time: Mutex<Option<Instant>>,
     chain_config: Arc<ChainConfig>,
+    /// Header of the latest closed block, updated every time a new block is stored.
+    latest_header: watch::Sender<Option<Arc<Header>>>,
+    /// The last stored pending block, `None` once it is cleared.
+    pending_block: Mutex<Option<Arc<block_db::StoredPendingBlock>>>,
+    /// Held while the verification flags of a stored block are updated, and while blocks are reverted.
+    block_verification: Mutex<()>,
+    /// Held while the events of blocks are indexed, and while blocks are reverted.
+    event_index: Mutex<()>,
+    /// Held while the contract history of a block is written, from the check of its order to the update of the tip.
+    contract_history: Mutex<()>,
+    /// Held while the class declarations of the stored blocks are backfilled, and while blocks are reverted.
+    class_declarations: Mutex<()>,
+    /// Held while a block is stored, and while a column is compacted by [`DeoxysBackend::compact`].
+    maintenance: Mutex<()>,
+    /// A manual compaction is running.
+    manual_compaction: AtomicBool,
+    /// Held by the block production from the update of the global tries for a block to its storage, and while the
+    /// tries are updated then reverted for a block preview.
+    tries_update: Mutex<()>,
+    tries: tries::GlobalTries,
+    compaction_schedule: Mutex<CompactionSchedule>,
+    applied_compaction_options: Mutex<Option<AppliedCompactionOptions>>,
+    chain_head: chain_head::ChainHeadProvider,
+    gas_price_history: gas_price_history::GasPriceHistory,
+    sync_status: sync_status::SyncStatusProvider,
+    worker_health: worker_health::WorkerHealth,
 }
 
 pub struct DatabaseService {
     handle: Arc<DeoxysBackend>,
+    /// `None` when the compactions are not scheduled.
+    compaction_metrics: Option<CompactionMetrics>,
+    index_contract_events: bool,
 }
 
 impl DatabaseService {
@@ -331,14 +437,42 @@ impl DatabaseService {
         backup_dir: Option<PathBuf>,
         restore_from_latest_backup: bool,
         chain_config: Arc<ChainConfig>,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_compaction(base_path, backup_dir, restore_from_latest_backup, chain_config, Default::default())
+            .await
+    }
+
+    /// The compactions follow the schedule of `compaction` once [`DatabaseService::schedule_compactions`] is called.
+    pub async fn new_with_compaction(
+        base_path: &Path,
+        backup_dir: Option<PathBuf>,
+        restore_from_latest_backup: bool,
+        chain_config: Arc<ChainConfig>,
+        compaction: CompactionConfig,
     ) -> anyhow::Result<Self> {
         log::info!("💾 Opening database at: {}", base_path.display());
 
-        let handle =
-            DeoxysBackend::open(base_path.to_owned(), backup_dir.clone(), restore_from_latest_backup, chain_config)
-                .await?;
+        let handle = DeoxysBackend::open(
+            base_path.to_owned(),
+            backup_dir.clone(),
+            restore_from_latest_backup,
+            chain_config,
+            compaction,
+        )
+        .await?;
+
+        Ok(Self { handle, compaction_metrics: None, index_contract_events: false })
+    }
+
+    /// Starts the compaction scheduler along with the service.
+    pub fn schedule_compactions(&mut self, metrics: CompactionMetrics) {
+        self.compaction_metrics = Some(metrics);
+    }
 
-        Ok(Self { handle })
+    /// Indexes the events by contract once the service is started, which takes disk space. The index is removed when
+    /// the service starts without it.
+    pub fn index_contract_events(&mut self, enabled: bool) {
+        self.index_contract_events = enabled;
     }
 
     pub fn backend(&self) -> &Arc<DeoxysBackend> {
@@ -346,7 +480,18 @@ impl DatabaseService {
     }
 }
 
-impl Service for DatabaseService {}
+#[async_trait::async_trait]
+impl Service for DatabaseService {
+    async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+        join_set.spawn(event_index::event_index_task(Arc::clone(&self.handle), self.index_contract_events));
+        join_set.spawn(class_db::class_declarations_backfill_task(Arc::clone(&self.handle)));
+        join_set.spawn(deployed_contracts::class_contracts_backfill_task(Arc::clone(&self.handle)));
+        if let Some(metrics) = self.compaction_metrics.take() {
+            join_set.spawn(compaction::compaction_scheduler_task(Arc::clone(&self.handle), metrics));
+        }
+        Ok(())
+    }
+}
 
 struct BackupRequest {
     callback: oneshot::Sender<()>,
@@ -364,29 +509,110 @@ impl DeoxysBackend {
         &self.chain_config
     }
 
+    /// Subscribe to new block notifications. The receiver always holds the header of the latest closed block, or
+    /// `None` when the database is empty.
+    pub fn subscribe_latest_header(&self) -> watch::Receiver<Option<Arc<Header>>> {
+        self.latest_header.subscribe()
+    }
+
+    /// The latest block, the highest block known by the sync and the last block confirmed on L1, published by the
+    /// backend and the sync workers.
+    pub fn chain_head(&self) -> &chain_head::ChainHeadProvider {
+        &self.chain_head
+    }
+
+    /// Gas prices of the latest blocks.
+    pub fn gas_price_history(&self) -> &gas_price_history::GasPriceHistory {
+        &self.gas_price_history
+    }
+
+    /// Last contact of the sync workers with their sources.
+    pub fn sync_status(&self) -> &sync_status::SyncStatusProvider {
+        &self.sync_status
+    }
+
+    /// Liveness of the long-running workers of the node.
+    pub fn worker_health(&self) -> &worker_health::WorkerHealth {
+        &self.worker_health
+    }
+
+    /// Reads a row of the meta column: an error means that the database cannot be read anymore.
+    pub fn check_readable(&self) -> Result<()> {
+        let col = self.db.get_column(Column::Meta);
+        self.db.get_pinned_cf(&col, b"health_check")?;
+        Ok(())
+    }
+
+    pub(crate) fn notify_new_block(&self, header: Header, block_hash: Felt) {
+        self.chain_head.update_latest(block_hash, header.block_number);
+        self.gas_price_history.record(header.block_number, header.l1_gas_price.clone());
+        self.latest_header.send_replace(Some(Arc::new(header)));
+    }
+
     /// Open the db.
     async fn open(
         db_config_dir: PathBuf,
         backup_dir: Option<PathBuf>,
         restore_from_latest_backup: bool,
         chain_config: Arc<ChainConfig>,
+        compaction: CompactionConfig,
     ) -> Result<Arc<DeoxysBackend>> {
         let db_path = db_config_dir.join("db");
 
-        let (db, backup_handle) = open_rocksdb(&db_path, true, backup_dir, restore_from_latest_backup).await?;
+        let (db, backup_handle) =
+            open_rocksdb(&db_path, true, backup_dir, restore_from_latest_backup, compaction.rate_limit).await?;
+        let global_tries = tries::GlobalTries::new(&db);
 
         let backend = Arc::new(Self {
             backup_handle,
             db,
             last_flush_time: Default::default(),
             chain_config: Arc::clone(&chain_config),
+            latest_header: watch::channel(None).0,
+            pending_block: Default::default(),
+            block_verification: Default::default(),
+            event_index: Default::default(),
+            contract_history: Default::default(),
+            class_declarations: Default::default(),
+            maintenance: Default::default(),
+            manual_compaction: Default::default(),
+            tries_update: Default::default(),
+            tries: global_tries,
+            compaction_schedule: Mutex::new(compaction.schedule),
+            applied_compaction_options: Default::default(),
+            chain_head: chain_head::ChainHeadProvider::new(Default::default()),
+            gas_price_history: Default::default(),
+            sync_status: Default::default(),
+            worker_health: Default::default(),
         });
         backend.check_configuration()?;
+        backend.migrate_l1_handler_message_hashes()?;
+        backend.migrate_block_info_verification()?;
+        backend.migrate_block_inner_layout()?;
+        backend.init_class_contracts_backfill()?;
+
+        if let Some(DeoxysMaybePendingBlockInfo::NotPending(info)) =
+            backend.get_block_info(&BlockId::Tag(BlockTag::Latest))?
+        {
+            let first = (info.header.block_number + 1).saturating_sub(backend.gas_price_history.capacity() as u64);
+            for block_n in first..info.header.block_number {
+                if let Some(DeoxysMaybePendingBlockInfo::NotPending(info)) =
+                    backend.get_block_info(&DbBlockId::BlockN(BlockN(block_n)))?
+                {
+                    backend.gas_price_history.record(block_n, info.header.l1_gas_price);
+                }
+            }
+            backend.notify_new_block(info.header, info.block_hash);
+        }
+        if let Some(l1_confirmed) = backend.get_l1_last_confirmed_block()? {
+            backend.chain_head.update_l1_confirmed(l1_confirmed);
+        }
+
         Ok(backend)
     }
 
     pub fn maybe_flush(&self, force: bool) -> Result<bool> {
-        let mut inst = self.last_flush_time.lock().expect("poisoned mutex");
+        let mut inst = self.last_flush_time.lock_or_recover();
         let should_flush = force
             || match *inst {
                 Some(inst) => inst.elapsed() >= Duration::from_secs(5),
@@ -420,62 +646,100 @@ impl DeoxysBackend {
 
     // tries
 
-    pub(crate) fn get_bonsai<H: StarkHash + Send + Sync>(
-        &self,
-        map: DatabaseKeyMapping,
-    ) -> BonsaiStorage<BasicId, BonsaiDb<'_>, H> {
-        let bonsai = BonsaiStorage::new(
-            BonsaiDb::new(&self.db, map),
-            BonsaiStorageConfig {
-                max_saved_trie_logs: Some(0),
-                max_saved_snapshots: Some(0),
-                snapshot_interval: u64::MAX,
-            },
-        )
-        // UNWRAP: function actually cannot panic
-        .unwrap();
+    /// Shared view of the contract trie, as of the last commit.
+    pub fn contract_trie(&self) -> RwLockReadGuard<'_, GlobalTrie<Pedersen>> {
+        self.tries.contract.read()
+    }
 
-        bonsai
+    /// Exclusive access to the contract trie, to update and commit it.
+    pub fn contract_trie_mut(&self) -> RwLockWriteGuard<'_, GlobalTrie<Pedersen>> {
+        self.tries.contract.write()
     }
 
-    pub fn contract_trie(&self) -> BonsaiStorage<BasicId, BonsaiDb<'_>, Pedersen> {
-        self.get_bonsai(DatabaseKeyMapping {
-            flat: Column::BonsaiContractsFlat,
-            trie: Column::BonsaiContractsTrie,
-            log: Column::BonsaiContractsLog,
-        })
+    /// Shared view of the contract storage trie, as of the last commit.
+    pub fn contract_storage_trie(&self) -> RwLockReadGuard<'_, GlobalTrie<Pedersen>> {
+        self.tries.contract_storage.read()
     }
 
-    pub fn contract_storage_trie(&self) -> BonsaiStorage<BasicId, BonsaiDb<'_>, Pedersen> {
-        self.get_bonsai(DatabaseKeyMapping {
-            flat: Column::BonsaiContractsStorageFlat,
-            trie: Column::BonsaiContractsStorageTrie,
-            log: Column::BonsaiContractsStorageLog,
-        })
+    /// Exclusive access to the contract storage trie, to update and commit it.
+    pub fn contract_storage_trie_mut(&self) -> RwLockWriteGuard<'_, GlobalTrie<Pedersen>> {
+        self.tries.contract_storage.write()
     }
 
-    pub fn class_trie(&self) -> BonsaiStorage<BasicId, BonsaiDb<'_>, Poseidon> {
-        self.get_bonsai(DatabaseKeyMapping {
-            flat: Column::BonsaiClassesFlat,
-            trie: Column::BonsaiClassesTrie,
-            log: Column::BonsaiClassesLog,
-        })
+    /// Shared view of the class trie, as of the last commit.
+    pub fn class_trie(&self) -> RwLockReadGuard<'_, GlobalTrie<Poseidon>> {
+        self.tries.class.read()
+    }
+
+    /// Exclusive access to the class trie, to update and commit it.
+    pub fn class_trie_mut(&self) -> RwLockWriteGuard<'_, GlobalTrie<Poseidon>> {
+        self.tries.class.write()
+    }
+
+    /// Drops the changes made to the global tries since their last commit, when the update of a block failed.
+    pub fn reset_tries(&self) {
+        self.tries.contract_storage.reset();
+        self.tries.contract.reset();
+        self.tries.class.reset();
+    }
+
+    /// Deletes the global tries, before they are built again from the state diffs of the blocks. They cannot be
+    /// reverted afterwards.
+    pub fn clear_tries(&self) -> Result<(), DeoxysStorageError> {
+        self.tries.contract_storage.clear()?;
+        self.tries.contract.clear()?;
+        self.tries.class.clear()
+    }
+
+    /// Excludes the other updates of the global tries that take this lock, see [`DeoxysBackend::revert_tries_to`].
+    pub fn lock_tries_update(&self) -> MutexGuard<'_, ()> {
+        self.tries_update.lock_or_recover()
+    }
+
+    /// Reverts the global tries to their state after block `block_n`, or empties them when it is `None`. `tip` is the
+    /// last block committed to them.
+    pub fn revert_tries_to(&self, block_n: Option<u64>, tip: u64) -> Result<(), DeoxysStorageError> {
+        match block_n {
+            Some(block_n) => self.revert_tries(block_n, tip),
+            None => self.clear_tries(),
+        }
+    }
+
+    /// Reverts the global tries to their state after block `block_n`. `tip` is the last block committed to them.
+    pub(crate) fn revert_tries(&self, block_n: u64, tip: u64) -> Result<(), DeoxysStorageError> {
+        let (block_n, tip) = (BasicId::new(block_n), BasicId::new(tip));
+        let res = (|| -> Result<(), DeoxysStorageError> {
+            self.contract_storage_trie_mut().revert_to(block_n, tip)?;
+            self.contract_trie_mut().revert_to(block_n, tip)?;
+            self.class_trie_mut().revert_to(block_n, tip)?;
+            Ok(())
+        })();
+        // The tries are read again from the database, a failed revert may have left them halfway.
+        self.reset_tries();
+        res
     }
 
     pub fn get_storage_size(&self, db_metrics: &DbMetrics) -> u64 {
         let mut storage_size = 0;
 
-        for &column in Column::ALL.iter() {
-            let cf_handle = self.db.get_column(column);
-            let cf_metadata = self.db.get_column_family_metadata_cf(&cf_handle);
-            let column_size = cf_metadata.size;
+        for (column, column_size) in self.column_sizes() {
             storage_size += column_size;
-
             db_metrics.column_sizes.with_label_values(&[column.rocksdb_name()]).set(column_size as i64);
         }
 
         storage_size
     }
+
+    /// Size on disk of every column, in bytes.
+    pub fn column_sizes(&self) -> Vec<(Column, u64)> {
+        Column::ALL
+            .iter()
+            .map(|&column| {
+                let cf_handle = self.db.get_column(column);
+                (column, self.db.get_column_family_metadata_cf(&cf_handle).size)
+            })
+            .collect()
+    }
 }
 
 pub mod bonsai_identifier {
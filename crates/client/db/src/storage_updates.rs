@@ -1,10 +1,15 @@
+use crate::class_db::block_class_declarations;
+use crate::contract_db::{HistoryOrder, HistoryRange};
+use crate::db_block_id::DbBlockId;
 use crate::DeoxysBackend;
 use crate::DeoxysStorageError;
-use dp_block::{DeoxysBlock, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, DeoxysPendingBlock};
-use dp_class::ConvertedClass;
+use crate::WriteBatchWithTransaction;
+use dp_block::{BlockN, DeoxysBlock, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, DeoxysPendingBlock};
+use dp_class::{ClassInfo, CompiledClass, ConvertedClass};
 use dp_state_update::{
     ContractStorageDiffItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff, StorageEntry,
 };
+use dp_utils::lock::MutexExt;
 use starknet_core::types::ContractClass;
 use starknet_types_core::felt::Felt;
 use std::collections::HashMap;
@@ -16,7 +21,55 @@ pub struct DbClassUpdate {
     pub compiled_class_hash: Felt,
 }
 
+/// The contract updates of a state diff: class hashes, nonces and storage writes.
+type ContractUpdates = (Vec<(Felt, Felt)>, Vec<(Felt, Felt)>, Vec<((Felt, Felt), Felt)>);
+
+fn contract_updates(state_diff: &StateDiff) -> ContractUpdates {
+    // let nonces_from_deployed =
+    //     state_diff.deployed_contracts.iter().map(|&DeployedContractItem { address, .. }| (address, Felt::ZERO));
+
+    let nonces_from_updates =
+        state_diff.nonces().iter().map(|&NonceUpdate { contract_address, nonce }| (contract_address, nonce));
+
+    // let nonce_map: HashMap<Felt, Felt> = nonces_from_deployed.chain(nonces_from_updates).collect(); // set nonce to zero when contract deployed
+    let nonce_map: HashMap<Felt, Felt> = nonces_from_updates.collect();
+
+    let contract_class_updates_replaced = state_diff
+        .replaced_classes()
+        .iter()
+        .map(|&ReplacedClassItem { contract_address, class_hash }| (contract_address, class_hash));
+
+    let contract_class_updates_deployed = state_diff
+        .deployed_contracts()
+        .iter()
+        .map(|&DeployedContractItem { address, class_hash }| (address, class_hash));
+
+    let contract_class_updates =
+        contract_class_updates_replaced.chain(contract_class_updates_deployed).collect::<Vec<_>>();
+    let nonces_updates = nonce_map.into_iter().collect::<Vec<_>>();
+
+    let storage_kv_updates = state_diff
+        .storage_diffs()
+        .iter()
+        .flat_map(|ContractStorageDiffItem { address, storage_entries }| {
+            storage_entries.iter().map(move |&StorageEntry { key, value }| ((*address, key), value))
+        })
+        .collect::<Vec<_>>();
+
+    (contract_class_updates, nonces_updates, storage_kv_updates)
+}
+
+fn class_updates(converted_classes: Vec<ConvertedClass>) -> (Vec<(Felt, ClassInfo)>, Vec<(Felt, CompiledClass)>) {
+    converted_classes
+        .into_iter()
+        .map(|ConvertedClass { class_infos, class_compiled }| (class_infos, class_compiled))
+        .unzip()
+}
+
 impl DeoxysBackend {
+    /// Storing a pending block replaces the previous one, along with its state diff and classes. Storing a closed
+    /// block clears the pending block first.
+    ///
     /// NB: This functions needs to run on the rayon thread pool
     pub fn store_block(
         &self,
@@ -24,78 +77,183 @@ impl DeoxysBackend {
         state_diff: StateDiff,
         converted_classes: Vec<ConvertedClass>,
     ) -> Result<(), DeoxysStorageError> {
-        let block_n = block.info.block_n();
-        let state_diff_cpy = state_diff.clone();
-
-        let task_block_db = || match block.info {
+        let info = match block.info {
             DeoxysMaybePendingBlockInfo::Pending(info) => {
-                self.block_db_store_pending(&DeoxysPendingBlock { info, inner: block.inner }, &state_diff_cpy)
-            }
-            DeoxysMaybePendingBlockInfo::NotPending(info) => {
-                self.block_db_store_block(&DeoxysBlock { info, inner: block.inner }, &state_diff_cpy)
+                return self.store_pending_block(
+                    DeoxysPendingBlock { info, inner: block.inner },
+                    state_diff,
+                    converted_classes,
+                )
             }
+            DeoxysMaybePendingBlockInfo::NotPending(info) => info,
         };
+        self.store_closed_block(&DeoxysBlock { info, inner: block.inner }, &state_diff, &converted_classes)
+    }
 
-        let task_contract_db = || {
-            // let nonces_from_deployed =
-            //     state_diff.deployed_contracts.iter().map(|&DeployedContractItem { address, .. }| (address, Felt::ZERO));
-
-            let nonces_from_updates =
-                state_diff.nonces.into_iter().map(|NonceUpdate { contract_address, nonce }| (contract_address, nonce));
+    /// Stores a closed block, borrowing it so that a failed store can be retried with the same block.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn store_closed_block(
+        &self,
+        block: &DeoxysBlock,
+        state_diff: &StateDiff,
+        converted_classes: &[ConvertedClass],
+    ) -> Result<(), DeoxysStorageError> {
+        let _maintenance = self.maintenance.lock_or_recover();
+        // The pending block this block supersedes must not be read on top of it.
+        if self.get_block_info(&DbBlockId::Pending)?.is_some() {
+            self.clear_pending_block()?;
+        }
 
-            // let nonce_map: HashMap<Felt, Felt> = nonces_from_deployed.chain(nonces_from_updates).collect(); // set nonce to zero when contract deployed
-            let nonce_map: HashMap<Felt, Felt> = nonces_from_updates.collect();
+        let block_n = block.info.header.block_number;
+        let (new_header, block_hash) = (block.info.header.clone(), block.info.block_hash);
+        let class_declarations = block_class_declarations(state_diff, &block.inner.transactions, &block.info.tx_hashes);
 
-            let contract_class_updates_replaced = state_diff
-                .replaced_classes
-                .into_iter()
-                .map(|ReplacedClassItem { contract_address, class_hash }| (contract_address, class_hash));
+        let (contract_class_updates, nonces_updates, storage_kv_updates) = contract_updates(state_diff);
+        let history_ranges = HistoryRange::touched(&contract_class_updates, &nonces_updates, &storage_kv_updates);
+        // Held until the block is written, along with the history tips.
+        let contract_history = self.lock_contract_history();
+        // A block out of order must not be half stored.
+        self.check_contract_history_order(block_n, &history_ranges)?;
 
-            let contract_class_updates_deployed = state_diff
-                .deployed_contracts
-                .into_iter()
-                .map(|DeployedContractItem { address, class_hash }| (address, class_hash));
+        // The block is written once its history is, a block stored with its history tip has all of its history.
+        let task_contract_and_block_db = || {
+            self.contract_db_store_block(
+                &contract_history,
+                block_n,
+                HistoryOrder::Monotonic,
+                &contract_class_updates,
+                &nonces_updates,
+                &storage_kv_updates,
+            )?;
+            let mut tx = WriteBatchWithTransaction::default();
+            self.contract_db_history_tip_write(&contract_history, &mut tx, block_n, &history_ranges)?;
+            self.deployed_contracts_store_block(&mut tx, block_n, state_diff)?;
+            self.block_db_store_block(block, state_diff, tx)
+        };
 
-            let contract_class_updates =
-                contract_class_updates_replaced.chain(contract_class_updates_deployed).collect::<Vec<_>>();
-            let nonces_updates = nonce_map.into_iter().collect::<Vec<_>>();
+        let task_class_db = || self.class_db_store_block(block_n, converted_classes, &class_declarations);
 
-            let storage_kv_updates = state_diff
-                .storage_diffs
-                .into_iter()
-                .flat_map(|ContractStorageDiffItem { address, storage_entries }| {
-                    storage_entries.into_iter().map(move |StorageEntry { key, value }| ((address, key), value))
-                })
-                .collect::<Vec<_>>();
+        let (r1, r2) = rayon::join(task_contract_and_block_db, task_class_db);
 
-            match block_n {
-                None => self.contract_db_store_pending(&contract_class_updates, &nonces_updates, &storage_kv_updates),
-                Some(block_n) => {
-                    self.contract_db_store_block(block_n, &contract_class_updates, &nonces_updates, &storage_kv_updates)
-                }
-            }
-        };
+        r1.and(r2)?;
+        drop(contract_history);
 
-        let task_class_db = || {
-            let (class_info_updates, compiled_class_updates): (Vec<_>, Vec<_>) = converted_classes
-                .into_iter()
-                .map(|ConvertedClass { class_infos, class_compiled }| (class_infos, class_compiled))
-                .unzip();
-            match block_n {
-                None => self.class_db_store_pending(&class_info_updates, &compiled_class_updates),
-                Some(block_n) => self.class_db_store_block(block_n, &class_info_updates, &compiled_class_updates),
-            }
-        };
+        self.notify_new_block(new_header, block_hash);
+        Ok(())
+    }
 
-        let ((r1, r2), r3) = rayon::join(|| rayon::join(task_block_db, task_contract_db), task_class_db);
+    /// Writes the contract history of block `block_n` whatever the history tips, for the revert and rebuild paths which
+    /// write below them on purpose. The tips are only moved up.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn commit_contract_history_out_of_order(
+        &self,
+        block_n: u64,
+        state_diff: &StateDiff,
+    ) -> Result<(), DeoxysStorageError> {
+        let (contract_class_updates, nonces_updates, storage_kv_updates) = contract_updates(state_diff);
+        let history_ranges = HistoryRange::touched(&contract_class_updates, &nonces_updates, &storage_kv_updates);
+        let contract_history = self.lock_contract_history();
+        self.contract_db_store_block(
+            &contract_history,
+            block_n,
+            HistoryOrder::OutOfOrder,
+            &contract_class_updates,
+            &nonces_updates,
+            &storage_kv_updates,
+        )?;
+        let mut tx = WriteBatchWithTransaction::default();
+        self.contract_db_history_tip_write(&contract_history, &mut tx, block_n, &history_ranges)?;
+        self.db.write(tx)?;
+        Ok(())
+    }
 
-        r1.and(r2).and(r3)
+    /// The pending block is replaced in a single write.
+    fn store_pending_block(
+        &self,
+        block: DeoxysPendingBlock,
+        state_diff: StateDiff,
+        converted_classes: Vec<ConvertedClass>,
+    ) -> Result<(), DeoxysStorageError> {
+        let mut tx = WriteBatchWithTransaction::default();
+        let (contract_class_updates, nonces_updates, storage_kv_updates) = contract_updates(&state_diff);
+        self.contract_db_pending_writes(&mut tx, &contract_class_updates, &nonces_updates, &storage_kv_updates)?;
+        let (class_info_updates, compiled_class_updates) = class_updates(converted_classes);
+        self.class_db_pending_writes(&mut tx, &class_info_updates, &compiled_class_updates)?;
+        self.block_db_store_pending(block, state_diff, tx)
     }
 
     pub fn clear_pending_block(&self) -> Result<(), DeoxysStorageError> {
-        self.block_db_clear_pending()?;
-        self.contract_db_clear_pending()?;
-        self.class_db_clear_pending()?;
+        let mut tx = WriteBatchWithTransaction::default();
+        self.pending_state_removal(&mut tx)?;
+        self.block_db_clear_pending(tx)
+    }
+
+    /// Adds to `tx` the removal of the contract state and the classes of the pending block.
+    pub(crate) fn pending_state_removal(&self, tx: &mut WriteBatchWithTransaction) -> Result<(), DeoxysStorageError> {
+        self.contract_db_pending_writes(tx, &[], &[], &[])?;
+        self.class_db_pending_writes(tx, &[], &[])
+    }
+
+    /// Removes the blocks after `block_n`, along with the state and the classes they wrote, and the pending block.
+    /// The global tries are only up to date when the sync verifies the state roots: they are reverted when
+    /// `revert_tries` is set.
+    ///
+    /// The blocks are removed in a single batch. The global tries are reverted after it: when that is interrupted, the
+    /// batch left [`DeoxysBackend::get_interrupted_tries_revert`] set, for the sync to repair the tries.
+    ///
+    /// NB: This functions needs to run on the rayon thread pool
+    pub fn revert_to(&self, block_n: u64, revert_tries: bool) -> Result<(), DeoxysStorageError> {
+        let Some(tip) = self.get_latest_block_n()?.filter(|tip| *tip > block_n) else { return Ok(()) };
+        if tip - block_n > crate::MAX_REVERTIBLE_BLOCKS {
+            return Err(DeoxysStorageError::RevertTooDeep { block_n, tip });
+        }
+        let info = match self.get_block_info(&DbBlockId::BlockN(BlockN(block_n)))? {
+            Some(DeoxysMaybePendingBlockInfo::NotPending(info)) => info,
+            _ => {
+                return Err(DeoxysStorageError::inconsistent(format!(
+                    "Reverting to block {block_n}, which is not stored"
+                )))
+            }
+        };
+
+        // The tries must not be updated from the chain before the revert.
+        let _tries_update = revert_tries.then(|| self.lock_tries_update());
+        // The event index task must not index the reverted blocks again before they are removed.
+        let _event_index = self.event_index.lock_or_recover();
+        let _class_declarations = self.class_declarations.lock_or_recover();
+        let contract_history = self.lock_contract_history();
+        let mut tx = WriteBatchWithTransaction::default();
+        self.pending_state_removal(&mut tx)?;
+        self.event_index_revert(&mut tx, block_n)?;
+        self.contract_db_revert_history_tip(&contract_history, &mut tx, block_n)?;
+        for reverted in (block_n + 1..=tip).rev() {
+            let state_diff = self.block_db_revert_block(&mut tx, reverted)?;
+            let declared_classes = state_diff
+                .deprecated_declared_classes()
+                .iter()
+                .copied()
+                .chain(state_diff.declared_classes().iter().map(|item| item.class_hash))
+                .collect::<Vec<_>>();
+            self.class_db_revert_block(&mut tx, reverted, declared_classes)?;
+            self.deployed_contracts_revert_block(&mut tx, reverted, &state_diff)?;
+            let (contract_class_updates, nonces_updates, storage_kv_updates) = contract_updates(&state_diff);
+            self.contract_db_revert_block(
+                &mut tx,
+                reverted,
+                &contract_class_updates,
+                &nonces_updates,
+                &storage_kv_updates,
+            )?;
+        }
+        self.block_db_write_revert(tx, block_n, revert_tries)?;
+        if revert_tries {
+            self.revert_tries(block_n, tip)?;
+            self.clear_interrupted_tries_revert()?;
+        }
+
+        self.notify_new_block(info.header, info.block_hash);
         Ok(())
     }
 }
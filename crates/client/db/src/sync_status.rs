@@ -0,0 +1,28 @@
+//! What the sync workers know of their sources beyond the database. It is kept in memory only, and tells the readiness
+//! check of the node whether the L1 endpoint is still reached. The blocks known by the sync are in the
+//! [`ChainHead`](crate::chain_head::ChainHead).
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use dp_utils::lock::MutexExt;
+
+#[derive(Debug, Default)]
+pub struct SyncStatusProvider {
+    l1_last_connected: Mutex<Option<Instant>>,
+}
+
+impl SyncStatusProvider {
+    /// Last time the L1 worker got an answer from the L1 endpoint, `None` when it never did.
+    pub fn l1_last_connected(&self) -> Option<Instant> {
+        *self.l1_last_connected.lock_or_recover()
+    }
+
+    pub fn record_l1_connected(&self) {
+        self.record_l1_connected_at(Instant::now())
+    }
+
+    pub fn record_l1_connected_at(&self, at: Instant) {
+        *self.l1_last_connected.lock_or_recover() = Some(at);
+    }
+}
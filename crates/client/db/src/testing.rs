@@ -0,0 +1,44 @@
+//! Fixtures for the tests of the crates built on the database, enabled by the `testing` feature.
+
+use std::sync::Arc;
+
+use dp_block::chain_config::ChainConfig;
+use tempfile::TempDir;
+
+use crate::{Column, DatabaseExt, DatabaseService, DeoxysBackend, WriteBatchWithTransaction};
+
+/// A new database with the test chain config. It lives in the returned directory, which is removed when dropped.
+pub async fn temp_database() -> (TempDir, DatabaseService) {
+    let temp_dir = TempDir::new().expect("Creating a temporary directory");
+    let db = DatabaseService::new(temp_dir.path(), None, false, Arc::new(ChainConfig::test_config()))
+        .await
+        .expect("Opening the test database");
+    (temp_dir, db)
+}
+
+/// Runs `f`, then puts the nodes of the contract trie back as they were before it, as if the writes of the nodes made
+/// by `f` were lost. The flat storage and the logs of the trie keep the writes.
+pub fn lose_contract_trie_node_writes<R>(backend: &DeoxysBackend, f: impl FnOnce() -> R) -> R {
+    let col = backend.db.get_column(Column::BonsaiContractsTrie);
+    let read_nodes = || {
+        backend
+            .db
+            .iterator_cf(&col, rocksdb::IteratorMode::Start)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Reading the contract trie nodes")
+    };
+    let nodes = read_nodes();
+    let res = f();
+
+    let mut batch = WriteBatchWithTransaction::default();
+    for (key, _) in read_nodes() {
+        batch.delete_cf(&col, key);
+    }
+    for (key, value) in nodes {
+        batch.put_cf(&col, key, value);
+    }
+    backend.db.write(batch).expect("Writing the lost nodes back");
+    // The trie is read again from the database.
+    backend.reset_tries();
+    res
+}
@@ -0,0 +1,209 @@
+//! The global tries are built once when the database is opened, instead of every time they are accessed. Readers share
+//! the committed tries, the updates of a block lock them exclusively until they are committed.
+//!
+//! When a thread holds several of them, it locks the contract storage trie before the contract trie.
+
+use std::fmt;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use bonsai_trie::id::BasicId;
+use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+
+use crate::bonsai_db::{BonsaiDb, DatabaseKeyMapping};
+use crate::{Column, DatabaseExt, DeoxysStorageError, WriteBatchWithTransaction, DB, MAX_REVERTIBLE_BLOCKS};
+
+/// Upper bound of the keys of the bonsai columns: no key starts with `0xFF`, the identifiers of the tries are contract
+/// addresses or ascii strings and the logs are keyed by block number.
+const LAST_BONSAI_KEY: &[u8] = &[0xFF; 64];
+
+pub type GlobalTrie<H> = BonsaiStorage<BasicId, BonsaiDb, H>;
+
+pub(crate) struct CachedTrie<H: StarkHash + Send + Sync> {
+    db: Arc<DB>,
+    mapping: DatabaseKeyMapping,
+    trie: RwLock<GlobalTrie<H>>,
+}
+
+impl<H: StarkHash + Send + Sync> CachedTrie<H> {
+    fn new(db: &Arc<DB>, mapping: DatabaseKeyMapping) -> Self {
+        let trie = RwLock::new(open_trie(db, &mapping));
+        Self { db: Arc::clone(db), mapping, trie }
+    }
+
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, GlobalTrie<H>> {
+        loop {
+            match self.trie.read() {
+                Ok(guard) => return guard,
+                // The changes of the thread that panicked were not committed, drop them before reading.
+                Err(poisoned) => drop(poisoned),
+            }
+            drop(self.write());
+        }
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, GlobalTrie<H>> {
+        self.trie.write().unwrap_or_else(|poisoned| {
+            log::warn!("Rebuilding a global trie, a thread panicked while updating it");
+            self.trie.clear_poison();
+            let mut guard = poisoned.into_inner();
+            *guard = open_trie(&self.db, &self.mapping);
+            guard
+        })
+    }
+
+    /// Drops the uncommitted changes of the trie, by building it again from the database.
+    pub(crate) fn reset(&self) {
+        *self.write() = open_trie(&self.db, &self.mapping);
+    }
+
+    /// Deletes the trie, its flat storage and its logs from the database.
+    pub(crate) fn clear(&self) -> Result<(), DeoxysStorageError> {
+        let mut trie = self.write();
+        let mut batch = WriteBatchWithTransaction::default();
+        for column in [self.mapping.trie, self.mapping.flat, self.mapping.log] {
+            batch.delete_range_cf(&self.db.get_column(column), &[] as &[u8], LAST_BONSAI_KEY);
+        }
+        let res = self.db.write(batch);
+        *trie = open_trie(&self.db, &self.mapping);
+        Ok(res?)
+    }
+}
+
+fn open_trie<H: StarkHash + Send + Sync>(db: &Arc<DB>, mapping: &DatabaseKeyMapping) -> GlobalTrie<H> {
+    BonsaiStorage::new(
+        BonsaiDb::new(Arc::clone(db), mapping.clone()),
+        BonsaiStorageConfig {
+            max_saved_trie_logs: Some(MAX_REVERTIBLE_BLOCKS as usize),
+            max_saved_snapshots: Some(0),
+            snapshot_interval: u64::MAX,
+        },
+    )
+    // UNWRAP: function actually cannot panic
+    .unwrap()
+}
+
+pub(crate) struct GlobalTries {
+    pub(crate) contract: CachedTrie<Pedersen>,
+    pub(crate) contract_storage: CachedTrie<Pedersen>,
+    pub(crate) class: CachedTrie<Poseidon>,
+}
+
+impl GlobalTries {
+    pub(crate) fn new(db: &Arc<DB>) -> Self {
+        Self {
+            contract: CachedTrie::new(
+                db,
+                DatabaseKeyMapping {
+                    flat: Column::BonsaiContractsFlat,
+                    trie: Column::BonsaiContractsTrie,
+                    log: Column::BonsaiContractsLog,
+                },
+            ),
+            contract_storage: CachedTrie::new(
+                db,
+                DatabaseKeyMapping {
+                    flat: Column::BonsaiContractsStorageFlat,
+                    trie: Column::BonsaiContractsStorageTrie,
+                    log: Column::BonsaiContractsStorageLog,
+                },
+            ),
+            class: CachedTrie::new(
+                db,
+                DatabaseKeyMapping {
+                    flat: Column::BonsaiClassesFlat,
+                    trie: Column::BonsaiClassesTrie,
+                    log: Column::BonsaiClassesLog,
+                },
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for GlobalTries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalTries").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use bitvec::order::Msb0;
+    use bitvec::vec::BitVec;
+    use bitvec::view::AsBits;
+    use bonsai_trie::id::BasicId;
+    use starknet_types_core::felt::Felt;
+
+    use crate::bonsai_identifier;
+    use crate::testing::temp_database;
+
+    fn key(n: u64) -> BitVec<u8, Msb0> {
+        Felt::from(n).to_bytes_be().as_bits()[5..].to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_roots_read_during_a_commit() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        let mut trie = backend.contract_trie_mut();
+        trie.insert(bonsai_identifier::CONTRACT, &key(0), &Felt::ONE).unwrap();
+        trie.commit(BasicId::new(0)).unwrap();
+        let root_0 = trie.root_hash(bonsai_identifier::CONTRACT).unwrap();
+        drop(trie);
+
+        let committed = AtomicBool::new(false);
+        let root_1 = std::thread::scope(|s| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut roots = vec![];
+                        while !committed.load(Ordering::Acquire) {
+                            roots.push(backend.contract_trie().root_hash(bonsai_identifier::CONTRACT).unwrap());
+                        }
+                        roots.push(backend.contract_trie().root_hash(bonsai_identifier::CONTRACT).unwrap());
+                        roots
+                    })
+                })
+                .collect();
+
+            let mut trie = backend.contract_trie_mut();
+            for n in 1..100 {
+                trie.insert(bonsai_identifier::CONTRACT, &key(n), &Felt::from(n)).unwrap();
+            }
+            trie.commit(BasicId::new(1)).unwrap();
+            let root_1 = trie.root_hash(bonsai_identifier::CONTRACT).unwrap();
+            drop(trie);
+            committed.store(true, Ordering::Release);
+
+            for reader in readers {
+                let roots = reader.join().unwrap();
+                // The roots only move forward, from the first commit to the second one.
+                assert!(roots.iter().all(|root| *root == root_0 || *root == root_1));
+                assert!(roots.windows(2).all(|w| w[0] == w[1] || w[1] == root_1));
+                assert_eq!(roots.last(), Some(&root_1));
+            }
+            root_1
+        });
+        assert_ne!(root_0, root_1);
+
+        // Reverting rebuilds the shared tries.
+        backend.revert_tries(0, 1).unwrap();
+        assert_eq!(backend.contract_trie().root_hash(bonsai_identifier::CONTRACT).unwrap(), root_0);
+        assert_eq!(backend.contract_trie().get(bonsai_identifier::CONTRACT, &key(1)).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reset_drops_uncommitted_changes() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        backend.class_trie_mut().insert(bonsai_identifier::CLASS, &key(1), &Felt::ONE).unwrap();
+        assert_eq!(backend.class_trie().get(bonsai_identifier::CLASS, &key(1)).unwrap(), Some(Felt::ONE));
+
+        backend.reset_tries();
+        assert_eq!(backend.class_trie().get(bonsai_identifier::CLASS, &key(1)).unwrap(), None);
+    }
+}
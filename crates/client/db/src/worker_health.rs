@@ -0,0 +1,130 @@
+//! Liveness of the long-running workers of the node. Every worker touches the registry on each iteration of its loop:
+//! a worker that stopped touching it for too long is stuck, or stopped without taking the node down with it.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dp_utils::lock::MutexExt;
+
+/// The L2 sync, from the feeder gateway.
+pub const WORKER_SYNC: &str = "sync";
+/// The stage of the L2 sync verifying the fetched blocks and storing them.
+pub const WORKER_SYNC_APPLY: &str = "sync_apply";
+/// The worker following the state updates verified on L1.
+pub const WORKER_ETH_STATE: &str = "eth_state";
+/// The worker following the L1 to L2 messages.
+pub const WORKER_MESSAGING: &str = "messaging";
+
+/// A worker is stale after missing this many iterations.
+const MISSED_ITERATIONS_BEFORE_STALE: u32 = 10;
+/// A single iteration may take long when its requests are retried, a worker is never stale before this.
+const MIN_STALE_AFTER: Duration = Duration::from_secs(120);
+
+#[derive(Debug)]
+struct WorkerActivity {
+    stale_after: Duration,
+    last_activity: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerStatus {
+    pub name: &'static str,
+    /// `false` when the worker did not touch the registry for too long.
+    pub alive: bool,
+    pub since_last_activity: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct WorkerHealth {
+    workers: Mutex<BTreeMap<&'static str, WorkerActivity>>,
+}
+
+impl WorkerHealth {
+    /// Starts following a worker which iterates every `poll_interval`, as if it was just active. Registering a worker
+    /// again, when it is restarted, replaces it.
+    pub fn register(&self, name: &'static str, poll_interval: Duration) {
+        self.register_at(name, poll_interval, Instant::now())
+    }
+
+    pub fn register_at(&self, name: &'static str, poll_interval: Duration, at: Instant) {
+        let stale_after = poll_interval.saturating_mul(MISSED_ITERATIONS_BEFORE_STALE).max(MIN_STALE_AFTER);
+        self.workers.lock_or_recover().insert(name, WorkerActivity { stale_after, last_activity: at });
+    }
+
+    /// Stops following a worker which is done, as opposed to stuck.
+    pub fn unregister(&self, name: &'static str) {
+        self.workers.lock_or_recover().remove(name);
+    }
+
+    /// Records an iteration of the worker. The workers that are not registered are ignored.
+    pub fn touch(&self, name: &'static str) {
+        self.touch_at(name, Instant::now())
+    }
+
+    pub fn touch_at(&self, name: &'static str, at: Instant) {
+        if let Some(worker) = self.workers.lock_or_recover().get_mut(name) {
+            worker.last_activity = worker.last_activity.max(at);
+        }
+    }
+
+    /// The registered workers, by name.
+    pub fn report(&self) -> Vec<WorkerStatus> {
+        self.report_at(Instant::now())
+    }
+
+    pub fn report_at(&self, now: Instant) -> Vec<WorkerStatus> {
+        self.workers
+            .lock_or_recover()
+            .iter()
+            .map(|(&name, worker)| {
+                let since_last_activity = now.saturating_duration_since(worker.last_activity);
+                WorkerStatus { name, alive: since_last_activity <= worker.stale_after, since_last_activity }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workers_are_stale_once_they_stop_iterating() {
+        let health = WorkerHealth::default();
+        let start = Instant::now();
+        health.register_at(WORKER_SYNC, Duration::from_secs(2), start);
+        health.register_at(WORKER_ETH_STATE, Duration::from_secs(60), start);
+        // Not registered, the node does not run it.
+        health.touch_at(WORKER_MESSAGING, start);
+
+        let later = start + Duration::from_secs(300);
+        health.touch_at(WORKER_SYNC, later - Duration::from_secs(30));
+        assert_eq!(
+            health.report_at(later),
+            [
+                WorkerStatus { name: WORKER_ETH_STATE, alive: true, since_last_activity: Duration::from_secs(300) },
+                WorkerStatus { name: WORKER_SYNC, alive: true, since_last_activity: Duration::from_secs(30) },
+            ]
+        );
+
+        // The sync stops iterating, the L1 worker keeps going.
+        let much_later = later + Duration::from_secs(500);
+        health.touch_at(WORKER_ETH_STATE, much_later);
+        let report = health.report_at(much_later);
+        assert_eq!(
+            report.iter().map(|worker| (worker.name, worker.alive)).collect::<Vec<_>>(),
+            [(WORKER_ETH_STATE, true), (WORKER_SYNC, false)]
+        );
+
+        // A restarted worker is alive again.
+        health.register_at(WORKER_SYNC, Duration::from_secs(2), much_later);
+        assert!(health.report_at(much_later).iter().all(|worker| worker.alive));
+
+        // A worker which is done is not reported, even once its touches stop.
+        health.unregister(WORKER_SYNC);
+        health.touch_at(WORKER_SYNC, much_later);
+        let report = health.report_at(much_later + Duration::from_secs(3600));
+        assert_eq!(report.iter().map(|worker| worker.name).collect::<Vec<_>>(), [WORKER_ETH_STATE]);
+    }
+}
@@ -1,16 +1,16 @@
 use crate::client::StarknetCoreContract::StarknetCoreContractInstance;
+use crate::failover::FailoverTransport;
 use crate::utils::u256_to_felt;
 use alloy::sol_types::SolEvent;
 use alloy::{
     primitives::Address,
-    providers::{Provider, ProviderBuilder, ReqwestProvider, RootProvider},
-    rpc::types::Filter,
+    providers::{Provider, RootProvider},
+    rpc::{client::RpcClient, types::Filter},
     sol,
-    transports::http::{Client, Http},
 };
-use anyhow::{bail, Context};
+use anyhow::{bail, ensure, Context};
 use bitvec::macros::internal::funty::Fundamental;
-use dc_metrics::{Gauge, MetricsRegistry, PrometheusError, F64};
+use dc_metrics::{Gauge, IntGaugeVec, MetricsRegistry, Opts, PrometheusError, F64};
 use starknet_types_core::felt::Felt;
 use std::sync::Arc;
 use url::Url;
@@ -19,9 +19,16 @@ use url::Url;
 pub struct L1BlockMetrics {
     // L1 network metrics
     pub l1_block_number: Gauge<F64>,
-    // gas price is also define in sync/metrics/block_metrics.rs but this would be the price from l1
+    /// Last L1 base fee sampled by the gas price worker, see [`crate::l1_gas_price::GasPriceProvider::with_metrics`].
+    /// The gas prices of the imported blocks are exported by the sync.
     pub l1_gas_price_wei: Gauge<F64>,
     pub l1_gas_price_strk: Gauge<F64>,
+    /// Set when the last `LogStateUpdate` event did not match the block we synced from L2.
+    pub l1_state_update_mismatch: Gauge<F64>,
+    /// Index of the L1 endpoint in use, in the order they were given.
+    pub l1_active_endpoint: Gauge<F64>,
+    /// Requests that failed in a row, by L1 endpoint index.
+    pub l1_endpoint_consecutive_failures: IntGaugeVec,
 }
 
 impl L1BlockMetrics {
@@ -33,6 +40,16 @@ impl L1BlockMetrics {
             l1_gas_price_wei: registry.register(Gauge::new("deoxys_l1_gas_price", "Gauge for deoxys L1 gas price")?)?,
             l1_gas_price_strk: registry
                 .register(Gauge::new("deoxys_l1_gas_price_strk", "Gauge for deoxys L1 gas price in strk")?)?,
+            l1_state_update_mismatch: registry.register(Gauge::new(
+                "deoxys_l1_state_update_mismatch",
+                "Set to 1 when the state verified on L1 does not match the synced block",
+            )?)?,
+            l1_active_endpoint: registry
+                .register(Gauge::new("deoxys_l1_active_endpoint", "Index of the L1 endpoint in use")?)?,
+            l1_endpoint_consecutive_failures: registry.register(IntGaugeVec::new(
+                Opts::new("deoxys_l1_endpoint_consecutive_failures", "Requests that failed in a row, by L1 endpoint"),
+                &["endpoint"],
+            )?)?,
         })
     }
 }
@@ -45,9 +62,11 @@ sol!(
     "src/abis/starknet_core.json"
 );
 
+pub type L1Provider = RootProvider<FailoverTransport>;
+
 pub struct EthereumClient {
-    pub provider: Arc<ReqwestProvider>,
-    pub l1_core_contract: StarknetCoreContractInstance<Http<Client>, RootProvider<Http<Client>>>,
+    pub provider: Arc<L1Provider>,
+    pub l1_core_contract: StarknetCoreContractInstance<FailoverTransport, L1Provider>,
     pub l1_block_metrics: L1BlockMetrics,
 }
 
@@ -62,11 +81,18 @@ impl Clone for EthereumClient {
 }
 
 impl EthereumClient {
-    /// Create a new EthereumClient instance with the given RPC URL
-    pub async fn new(url: Url, l1_core_address: Address, metrics_handle: MetricsRegistry) -> anyhow::Result<Self> {
-        let provider = ProviderBuilder::new().on_http(url);
-        let core_contract = StarknetCoreContract::new(l1_core_address, provider.clone());
+    /// Create a new EthereumClient instance with the given RPC URLs. The first one is used until it fails, see
+    /// [`crate::failover`].
+    pub async fn new(
+        urls: Vec<Url>,
+        l1_core_address: Address,
+        metrics_handle: MetricsRegistry,
+    ) -> anyhow::Result<Self> {
+        ensure!(!urls.is_empty(), "No L1 endpoint");
         let l1_block_metrics = L1BlockMetrics::register(&metrics_handle)?;
+        let transport = FailoverTransport::new(urls, l1_block_metrics.clone());
+        let provider = RootProvider::new(RpcClient::new(transport, false));
+        let core_contract = StarknetCoreContract::new(l1_core_address, provider.clone());
 
         Ok(Self { provider: Arc::new(provider), l1_core_contract: core_contract, l1_block_metrics })
     }
@@ -139,12 +165,9 @@ pub mod eth_client_getter_test {
     #[once]
     pub fn eth_client() -> EthereumClient {
         let rpc_url: Url = "http://localhost:8545".parse().expect("issue while parsing");
-        let provider = ProviderBuilder::new().on_http(rpc_url.clone());
         let address = Address::parse_checksummed(CORE_CONTRACT_ADDRESS, None).unwrap();
-        let contract = StarknetCoreContract::new(address, provider.clone());
         let prometheus_service = MetricsService::new(true, false, 9615).unwrap();
-        let l1_block_metrics = L1BlockMetrics::register(&prometheus_service.registry()).unwrap();
-        EthereumClient { provider: Arc::new(provider), l1_core_contract: contract.clone(), l1_block_metrics }
+        futures::executor::block_on(EthereumClient::new(vec![rpc_url], address, prometheus_service.registry())).unwrap()
     }
 
     #[rstest]
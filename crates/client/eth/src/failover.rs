@@ -0,0 +1,184 @@
+//! Several L1 RPC endpoints behind a single alloy transport.
+//!
+//! Requests go to the active endpoint. When it fails at the transport level (connection refused, timeout, HTTP error
+//! status), the request is sent again to the next endpoint, which becomes the active one. Once every endpoint failed
+//! the same request, the next round waits, with an exponential backoff. JSON-RPC errors are answers of the endpoint,
+//! they are returned as is.
+//!
+//! The L1 workers poll the events from the last L1 block they stored, so switching endpoints does not skip or repeat
+//! events.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::http::{Client, Http};
+use alloy::transports::{RpcError, TransportError, TransportFut};
+use tower::ServiceExt;
+use url::Url;
+
+use crate::client::L1BlockMetrics;
+
+/// Number of times every endpoint is tried before a request fails.
+const MAX_ROUNDS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+#[derive(Clone)]
+pub struct FailoverTransport {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    endpoints: Vec<Http<Client>>,
+    active: AtomicUsize,
+    metrics: L1BlockMetrics,
+}
+
+impl FailoverTransport {
+    /// The endpoints are tried in order, starting with the first one. `urls` must not be empty.
+    pub fn new(urls: Vec<Url>, metrics: L1BlockMetrics) -> Self {
+        assert!(!urls.is_empty(), "No L1 endpoint");
+        for index in 0..urls.len() {
+            metrics.l1_endpoint_consecutive_failures.with_label_values(&[&index.to_string()]).set(0);
+        }
+        metrics.l1_active_endpoint.set(0.0);
+
+        let endpoints = urls.into_iter().map(Http::new).collect();
+        Self { inner: Arc::new(Inner { endpoints, active: AtomicUsize::new(0), metrics }) }
+    }
+
+    async fn send(self, request: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let n_endpoints = self.inner.endpoints.len();
+        let mut backoff = INITIAL_BACKOFF;
+        let (mut round, mut failures_in_round) = (0, 0);
+        loop {
+            let index = self.inner.active.load(Ordering::Relaxed);
+            let failures = self.inner.metrics.l1_endpoint_consecutive_failures.with_label_values(&[&index.to_string()]);
+            match self.inner.endpoints[index].clone().oneshot(request.clone()).await {
+                Err(RpcError::Transport(err)) => {
+                    failures.inc();
+                    self.switch_from(index, &err);
+
+                    failures_in_round += 1;
+                    if failures_in_round == n_endpoints {
+                        round += 1;
+                        if round == MAX_ROUNDS {
+                            return Err(RpcError::Transport(err));
+                        }
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        failures_in_round = 0;
+                    }
+                }
+                res => {
+                    failures.set(0);
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Moves on to the next endpoint, unless a concurrent request already did.
+    fn switch_from(&self, index: usize, err: &impl std::fmt::Display) {
+        let next = (index + 1) % self.inner.endpoints.len();
+        if self.inner.active.compare_exchange(index, next, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            // The urls are not logged, they usually contain an API key.
+            log::warn!("⚠️  L1 endpoint #{index} failed, switching to endpoint #{next}: {err}");
+            self.inner.metrics.l1_active_endpoint.set(next as f64);
+        }
+    }
+}
+
+impl tower::Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().send(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, I256, U256};
+    use alloy::rpc::types::Log;
+    use alloy::sol_types::SolEvent;
+    use dc_metrics::MetricsService;
+    use mockito::Matcher;
+
+    use crate::client::{EthereumClient, StarknetCoreContract};
+    use crate::state_update::L1StateUpdateSource;
+
+    /// L2 block `n` is verified on L1 in block `10 * n`.
+    fn state_update_logs(core_contract: Address, from_block: u64, to_block: u64) -> Vec<serde_json::Value> {
+        (from_block..=to_block)
+            .filter(|l1_block| *l1_block > 0 && l1_block % 10 == 0)
+            .map(|l1_block| {
+                let event = StarknetCoreContract::LogStateUpdate {
+                    globalRoot: U256::from(l1_block / 10),
+                    blockNumber: I256::from_raw(U256::from(l1_block / 10)),
+                    blockHash: U256::from(l1_block / 10),
+                };
+                let log = Log {
+                    inner: alloy::primitives::Log { address: core_contract, data: event.encode_log_data() },
+                    block_number: Some(l1_block),
+                    ..Default::default()
+                };
+                serde_json::to_value(log).unwrap()
+            })
+            .collect()
+    }
+
+    async fn mock_logs(server: &mut mockito::Server, core_contract: Address, from_block: u64, to_block: u64) {
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "result": state_update_logs(core_contract, from_block, to_block),
+        });
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("eth_getLogs".into()),
+                Matcher::Regex(format!(r#""fromBlock":"{from_block:#x}""#)),
+            ]))
+            .with_body(response.to_string())
+            .create_async()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_events_continue_from_the_next_endpoint() {
+        let core_contract = Address::repeat_byte(0x11);
+        let mut first = mockito::Server::new_async().await;
+        let mut second = mockito::Server::new_async().await;
+        for server in [&mut first, &mut second] {
+            mock_logs(server, core_contract, 0, 49).await;
+            mock_logs(server, core_contract, 50, 99).await;
+        }
+
+        let urls = vec![first.url().parse().unwrap(), second.url().parse().unwrap()];
+        let registry = MetricsService::new(true, false, 9615).unwrap().registry();
+        let client = EthereumClient::new(urls, core_contract, registry).await.unwrap();
+        let metrics = &client.l1_block_metrics;
+        let failures = |index: &str| metrics.l1_endpoint_consecutive_failures.with_label_values(&[index]).get();
+
+        let mut state_updates = client.get_state_updates(0, 49).await.unwrap();
+        assert_eq!(metrics.l1_active_endpoint.get(), 0.0);
+
+        drop(first);
+        state_updates.extend(client.get_state_updates(50, 99).await.unwrap());
+        assert_eq!(metrics.l1_active_endpoint.get(), 1.0);
+        assert_eq!((failures("0"), failures("1")), (1, 0));
+
+        let l2_blocks: Vec<_> = state_updates.iter().map(|state_update| state_update.block_number).collect();
+        assert_eq!(l2_blocks, (1..=9).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,357 @@
+//! L1 gas price oracle, used to fill in the gas prices of the blocks produced by this node.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alloy::providers::Provider;
+use alloy::rpc::types::BlockNumberOrTag;
+use anyhow::Context;
+use dp_block::header::{GasPrices, L1DataAvailabilityMode};
+use dp_utils::lock::MutexExt;
+use dp_utils::wait_or_graceful_shutdown;
+use tokio::time::Instant;
+
+use crate::client::{EthereumClient, L1BlockMetrics};
+
+/// Fees of the next Ethereum block, in wei.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1Fees {
+    pub base_fee: u128,
+    pub blob_base_fee: u128,
+}
+
+/// Where the L1 fees are sampled from.
+#[async_trait::async_trait]
+pub trait L1FeeSource: Send + Sync {
+    async fn latest_fees(&self) -> anyhow::Result<L1Fees>;
+}
+
+#[async_trait::async_trait]
+impl L1FeeSource for EthereumClient {
+    async fn latest_fees(&self) -> anyhow::Result<L1Fees> {
+        let fee_history = self.provider.get_fee_history(1, BlockNumberOrTag::Latest, &[]).await?;
+        // The last entry is the fee of the block after the latest one.
+        let base_fee = *fee_history.base_fee_per_gas.last().context("Fee history has no base fee")?;
+        // Blob fees are not reported before the Dencun upgrade.
+        let blob_base_fee = fee_history.base_fee_per_blob_gas.last().copied().unwrap_or_default();
+
+        Ok(L1Fees { base_fee, blob_base_fee })
+    }
+}
+
+/// Price of 1 ETH in STRK.
+#[async_trait::async_trait]
+pub trait EthStrkOracle: Send + Sync {
+    async fn strk_per_eth(&self) -> anyhow::Result<f64>;
+}
+
+#[derive(Clone)]
+pub enum StrkPerEth {
+    Fixed(f64),
+    Oracle(Arc<dyn EthStrkOracle>),
+}
+
+#[derive(Clone)]
+pub struct GasPriceProviderConfig {
+    /// Time between two samples of the L1 fees.
+    pub poll_interval: Duration,
+    /// Number of samples the moving average is computed over.
+    pub ema_window: u32,
+    /// Used to derive the STRK gas prices from the ETH ones. When `None`, the STRK prices stay at their initial value.
+    pub strk_per_eth: Option<StrkPerEth>,
+    /// Gas prices served until the first successful sample.
+    pub initial_gas_prices: GasPrices,
+    pub da_mode: L1DataAvailabilityMode,
+    /// The prices are considered stale when no sample has succeeded for this long.
+    pub stale_after: Duration,
+}
+
+/// Exponential moving average, with the usual smoothing factor of `2 / (window + 1)`.
+#[derive(Debug, Clone, Copy)]
+struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    fn new(window: u32) -> Self {
+        Self { alpha: 2.0 / (f64::from(window.max(1)) + 1.0), value: None }
+    }
+
+    fn update(&mut self, sample: f64) -> f64 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+struct GasPriceState {
+    base_fee: Ema,
+    blob_base_fee: Ema,
+    strk_per_eth: Option<f64>,
+    gas_prices: GasPrices,
+    last_update: Instant,
+}
+
+/// Gas prices derived from a moving average of the recent L1 fees. The prices are updated by [`GasPriceProvider::run`]
+/// and the last known value is kept while the L1 is unreachable.
+pub struct GasPriceProvider {
+    config: GasPriceProviderConfig,
+    state: Mutex<GasPriceState>,
+    metrics: Option<L1BlockMetrics>,
+}
+
+impl GasPriceProvider {
+    pub fn new(config: GasPriceProviderConfig) -> Self {
+        let state = GasPriceState {
+            base_fee: Ema::new(config.ema_window),
+            blob_base_fee: Ema::new(config.ema_window),
+            strk_per_eth: match &config.strk_per_eth {
+                Some(StrkPerEth::Fixed(rate)) => Some(*rate),
+                _ => None,
+            },
+            gas_prices: config.initial_gas_prices.clone(),
+            last_update: Instant::now(),
+        };
+        Self { config, state: Mutex::new(state), metrics: None }
+    }
+
+    /// Exports the sampled L1 gas prices, in wei and in fri.
+    pub fn with_metrics(self, metrics: L1BlockMetrics) -> Self {
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    pub fn gas_prices(&self) -> GasPrices {
+        self.state.lock_or_recover().gas_prices.clone()
+    }
+
+    pub fn da_mode(&self) -> L1DataAvailabilityMode {
+        self.config.da_mode
+    }
+
+    /// Whether no sample has succeeded for [`GasPriceProviderConfig::stale_after`].
+    pub fn is_stale(&self) -> bool {
+        self.state.lock_or_recover().last_update.elapsed() > self.config.stale_after
+    }
+
+    /// Samples the L1 fees once and updates the gas prices. On error, the previous gas prices are kept.
+    pub async fn update(&self, source: &dyn L1FeeSource) -> anyhow::Result<GasPrices> {
+        let fees = source.latest_fees().await.context("Sampling L1 fees")?;
+        let strk_per_eth = match &self.config.strk_per_eth {
+            Some(StrkPerEth::Oracle(oracle)) => match oracle.strk_per_eth().await {
+                Ok(rate) => Some(rate),
+                Err(err) => {
+                    log::warn!("Failed to get the ETH/STRK rate, keeping the last one: {err:#}");
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let mut state = self.state.lock_or_recover();
+        if strk_per_eth.is_some() {
+            state.strk_per_eth = strk_per_eth;
+        }
+        let base_fee = state.base_fee.update(fees.base_fee as f64);
+        let blob_base_fee = state.blob_base_fee.update(fees.blob_base_fee as f64);
+
+        state.gas_prices.eth_l1_gas_price = to_gas_price(base_fee);
+        state.gas_prices.eth_l1_data_gas_price = to_gas_price(blob_base_fee);
+        if let Some(rate) = state.strk_per_eth {
+            state.gas_prices.strk_l1_gas_price = to_gas_price(base_fee * rate);
+            state.gas_prices.strk_l1_data_gas_price = to_gas_price(blob_base_fee * rate);
+        }
+        state.last_update = Instant::now();
+
+        if let Some(metrics) = &self.metrics {
+            metrics.l1_gas_price_wei.set(fees.base_fee as f64);
+            if let Some(rate) = state.strk_per_eth {
+                metrics.l1_gas_price_strk.set(fees.base_fee as f64 * rate);
+            }
+        }
+        Ok(state.gas_prices.clone())
+    }
+
+    /// Samples the L1 fees every [`GasPriceProviderConfig::poll_interval`], until the node shuts down.
+    pub async fn run(&self, source: impl L1FeeSource) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.config.poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+            match self.update(&source).await {
+                Ok(gas_prices) => log::debug!("Updated L1 gas prices: {gas_prices:?}"),
+                Err(err) if self.is_stale() => {
+                    log::error!("L1 gas prices are stale, still serving the last known value: {err:#}")
+                }
+                Err(err) => log::warn!("Failed to update L1 gas prices, keeping the last known value: {err:#}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Gas prices are never zero.
+fn to_gas_price(price: f64) -> u128 {
+    (price.round() as u128).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    struct MockFeeSource(Mutex<VecDeque<anyhow::Result<L1Fees>>>);
+
+    impl MockFeeSource {
+        fn new(samples: impl IntoIterator<Item = anyhow::Result<L1Fees>>) -> Self {
+            Self(Mutex::new(samples.into_iter().collect()))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl L1FeeSource for MockFeeSource {
+        async fn latest_fees(&self) -> anyhow::Result<L1Fees> {
+            self.0.lock().unwrap().pop_front().expect("No more samples")
+        }
+    }
+
+    struct MockOracle(Mutex<VecDeque<anyhow::Result<f64>>>);
+
+    #[async_trait::async_trait]
+    impl EthStrkOracle for MockOracle {
+        async fn strk_per_eth(&self) -> anyhow::Result<f64> {
+            self.0.lock().unwrap().pop_front().expect("No more rates")
+        }
+    }
+
+    fn fees(base_fee: u128, blob_base_fee: u128) -> anyhow::Result<L1Fees> {
+        Ok(L1Fees { base_fee, blob_base_fee })
+    }
+
+    fn initial_gas_prices() -> GasPrices {
+        GasPrices { eth_l1_gas_price: 10, strk_l1_gas_price: 20, eth_l1_data_gas_price: 1, strk_l1_data_gas_price: 2 }
+    }
+
+    fn config(ema_window: u32, strk_per_eth: Option<StrkPerEth>) -> GasPriceProviderConfig {
+        GasPriceProviderConfig {
+            poll_interval: Duration::from_secs(10),
+            ema_window,
+            strk_per_eth,
+            initial_gas_prices: initial_gas_prices(),
+            da_mode: L1DataAvailabilityMode::Blob,
+            stale_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_ema() {
+        // A window of 3 samples gives a smoothing factor of 0.5.
+        let mut ema = Ema::new(3);
+        assert_eq!(ema.update(100.0), 100.0);
+        assert_eq!(ema.update(200.0), 150.0);
+        assert_eq!(ema.update(400.0), 275.0);
+
+        // A window of 1 sample follows the last sample.
+        let mut ema = Ema::new(1);
+        ema.update(100.0);
+        assert_eq!(ema.update(400.0), 400.0);
+    }
+
+    #[tokio::test]
+    async fn test_gas_prices_follow_the_ema() {
+        let provider = GasPriceProvider::new(config(3, Some(StrkPerEth::Fixed(2.0))));
+        assert_eq!(provider.gas_prices(), initial_gas_prices());
+
+        let source = MockFeeSource::new([fees(100, 10), fees(200, 30), fees(400, 0)]);
+        provider.update(&source).await.unwrap();
+        provider.update(&source).await.unwrap();
+        let gas_prices = provider.update(&source).await.unwrap();
+
+        assert_eq!(
+            gas_prices,
+            GasPrices {
+                eth_l1_gas_price: 275,
+                strk_l1_gas_price: 550,
+                eth_l1_data_gas_price: 10,
+                strk_l1_data_gas_price: 20,
+            }
+        );
+        assert_eq!(provider.gas_prices(), gas_prices);
+    }
+
+    #[tokio::test]
+    async fn test_strk_prices_from_oracle() {
+        let oracle = MockOracle(Mutex::new([Ok(3.0), Err(anyhow::anyhow!("Oracle is down"))].into()));
+        let provider = GasPriceProvider::new(config(1, Some(StrkPerEth::Oracle(Arc::new(oracle)))));
+        let source = MockFeeSource::new([fees(100, 10), fees(200, 20)]);
+
+        let gas_prices = provider.update(&source).await.unwrap();
+        assert_eq!((gas_prices.strk_l1_gas_price, gas_prices.strk_l1_data_gas_price), (300, 30));
+
+        // The last rate is kept when the oracle fails.
+        let gas_prices = provider.update(&source).await.unwrap();
+        assert_eq!((gas_prices.strk_l1_gas_price, gas_prices.strk_l1_data_gas_price), (600, 60));
+    }
+
+    #[tokio::test]
+    async fn test_sampled_prices_are_exported() {
+        let registry = dc_metrics::MetricsService::new(true, false, 0).unwrap().registry();
+        let metrics = L1BlockMetrics::register(&registry).unwrap();
+        let provider = GasPriceProvider::new(config(3, Some(StrkPerEth::Fixed(2.0)))).with_metrics(metrics.clone());
+        let source = MockFeeSource::new([fees(100, 10), fees(200, 30)]);
+
+        // The metrics follow the samples, not their moving average.
+        provider.update(&source).await.unwrap();
+        provider.update(&source).await.unwrap();
+        assert_eq!((metrics.l1_gas_price_wei.get(), metrics.l1_gas_price_strk.get()), (200.0, 400.0));
+    }
+
+    #[tokio::test]
+    async fn test_strk_prices_without_rate() {
+        let provider = GasPriceProvider::new(config(1, None));
+        let gas_prices = provider.update(&MockFeeSource::new([fees(100, 10)])).await.unwrap();
+        assert_eq!((gas_prices.eth_l1_gas_price, gas_prices.eth_l1_data_gas_price), (100, 10));
+        assert_eq!((gas_prices.strk_l1_gas_price, gas_prices.strk_l1_data_gas_price), (20, 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_last_value_kept_when_l1_unreachable() {
+        let provider = GasPriceProvider::new(config(1, Some(StrkPerEth::Fixed(1.0))));
+        let source = MockFeeSource::new([
+            fees(100, 10),
+            Err(anyhow::anyhow!("L1 is unreachable")),
+            Err(anyhow::anyhow!("L1 is unreachable")),
+            fees(300, 30),
+        ]);
+
+        let gas_prices = provider.update(&source).await.unwrap();
+        assert!(!provider.is_stale());
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(provider.update(&source).await.is_err());
+        assert_eq!(provider.gas_prices(), gas_prices);
+        assert!(!provider.is_stale());
+
+        tokio::time::advance(Duration::from_secs(40)).await;
+        assert!(provider.update(&source).await.is_err());
+        assert_eq!(provider.gas_prices(), gas_prices);
+        assert!(provider.is_stale());
+
+        // The prices recover with the next successful sample.
+        provider.update(&source).await.unwrap();
+        assert_eq!(provider.gas_prices().eth_l1_gas_price, 300);
+        assert!(!provider.is_stale());
+    }
+
+    #[test]
+    fn test_gas_prices_are_never_zero() {
+        assert_eq!(to_gas_price(0.0), 1);
+        assert_eq!(to_gas_price(0.4), 1);
+        assert_eq!(to_gas_price(2.5), 3);
+    }
+}
@@ -0,0 +1,808 @@
+//! L1 → L2 messages: the `LogMessageToL2` events of the Starknet core contract become L1 handler transactions, unless
+//! the message was cancelled on L1.
+//!
+//! A message is cancelled in two steps on L1: the sender starts the cancellation, and once the cancellation delay is
+//! over, `cancelL1ToL2Message` emits `MessageToL2Canceled`. From then on, the message must not be executed anymore.
+//!
+//! On a sequencer, the resulting transactions are handed to an [`L1HandlerSubmitter`], the mempool. The messages sent
+//! while the node was down are fetched first, by block ranges, before following the new ones. Messages are only
+//! handled once they are confirmed on L1, see [`crate::reorg`].
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use alloy::primitives::{keccak256, Address, B256, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::Filter;
+use alloy::sol_types::SolEvent;
+use anyhow::Context;
+use dc_db::worker_health::WORKER_MESSAGING;
+use dc_db::DeoxysBackend;
+use dp_transactions::L1HandlerTransaction;
+use dp_utils::wait_or_graceful_shutdown;
+use starknet_types_core::felt::Felt;
+
+use crate::client::{EthereumClient, StarknetCoreContract};
+use crate::reorg::{check_l1_reorg, confirmed_head, event_block, L1BlockSource};
+use crate::utils::u256_to_felt;
+
+#[async_trait::async_trait]
+pub trait L1MessageSource: Send + Sync {
+    /// The time at which the cancellation of the message with hash `msg_hash` was started, or zero when it was not.
+    async fn get_l1_to_l2_message_cancellations(&self, msg_hash: B256) -> anyhow::Result<U256>;
+}
+
+#[async_trait::async_trait]
+impl L1MessageSource for EthereumClient {
+    async fn get_l1_to_l2_message_cancellations(&self, msg_hash: B256) -> anyhow::Result<U256> {
+        let cancellations = self.l1_core_contract.l1ToL2MessageCancellations(msg_hash).call().await?;
+        Ok(cancellations._0)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GetLogsError {
+    /// The provider refuses to return this many logs at once, the block range must be reduced.
+    #[error("Too many logs in the block range")]
+    RangeTooLarge,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// An event of the core contract the L1 messaging worker follows.
+#[derive(Debug, Clone)]
+pub enum L1MessagingEvent {
+    Message(StarknetCoreContract::LogMessageToL2),
+    Cancelled(StarknetCoreContract::MessageToL2Canceled),
+}
+
+#[async_trait::async_trait]
+pub trait L1MessageLogSource: L1BlockSource {
+    /// The `LogMessageToL2` and `MessageToL2Canceled` events emitted between these two blocks, both included, in
+    /// order, with their L1 block number.
+    async fn get_messaging_events(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(L1MessagingEvent, u64)>, GetLogsError>;
+}
+
+#[async_trait::async_trait]
+impl L1MessageLogSource for EthereumClient {
+    async fn get_messaging_events(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(L1MessagingEvent, u64)>, GetLogsError> {
+        let filter = Filter::new()
+            .from_block(from_block)
+            .to_block(to_block)
+            .address(*self.l1_core_contract.address())
+            .event_signature(vec![
+                StarknetCoreContract::LogMessageToL2::SIGNATURE_HASH,
+                StarknetCoreContract::MessageToL2Canceled::SIGNATURE_HASH,
+            ]);
+        let logs = self.provider.get_logs(&filter).await.map_err(|err| {
+            if is_range_too_large(&err.to_string()) {
+                GetLogsError::RangeTooLarge
+            } else {
+                GetLogsError::Other(anyhow::Error::from(err).context("Getting L1 messaging logs"))
+            }
+        })?;
+
+        let events = logs
+            .into_iter()
+            .map(|log| {
+                let block_number = log.block_number.context("No block number in log")?;
+                let event = if log.topic0() == Some(&StarknetCoreContract::MessageToL2Canceled::SIGNATURE_HASH) {
+                    L1MessagingEvent::Cancelled(log.log_decode()?.inner.data)
+                } else {
+                    L1MessagingEvent::Message(log.log_decode()?.inner.data)
+                };
+                Ok((event, block_number))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(events)
+    }
+}
+
+/// Whether an `eth_getLogs` error means that the block range must be reduced. Every provider has its own wording.
+fn is_range_too_large(message: &str) -> bool {
+    const PATTERNS: &[&str] = &[
+        "query returned more than",
+        "response size exceeded",
+        "block range",
+        "range is too large",
+        "too many results",
+        "limit exceeded",
+    ];
+    let message = message.to_ascii_lowercase();
+    PATTERNS.iter().any(|pattern| message.contains(pattern))
+}
+
+/// Where the L1 handler transactions go to be executed on L2.
+pub trait L1HandlerSubmitter: Send + Sync {
+    /// Queues the transaction for execution, and returns its hash.
+    fn submit_l1_handler_tx(&self, tx: L1HandlerTransaction, paid_fee_on_l1: u128) -> anyhow::Result<Felt>;
+    /// Drops the transaction of the message with this nonce if it was not executed yet. Returns whether it was
+    /// dropped.
+    fn remove_l1_handler_tx(&self, nonce: u64) -> bool;
+}
+
+/// Hash of a message, as computed by the core contract.
+pub fn l1_to_l2_message_hash(
+    from_address: &Address,
+    to_address: U256,
+    selector: U256,
+    payload: &[U256],
+    nonce: U256,
+) -> B256 {
+    let mut data = Vec::with_capacity(32 * (5 + payload.len()));
+    data.extend_from_slice(from_address.into_word().as_slice());
+    data.extend_from_slice(&to_address.to_be_bytes::<32>());
+    data.extend_from_slice(&nonce.to_be_bytes::<32>());
+    data.extend_from_slice(&selector.to_be_bytes::<32>());
+    data.extend_from_slice(&U256::from(payload.len()).to_be_bytes::<32>());
+    for value in payload {
+        data.extend_from_slice(&value.to_be_bytes::<32>());
+    }
+    keccak256(data)
+}
+
+/// The L1 handler transaction executing a message. The L1 sender is the first calldata item.
+pub fn l1_handler_from_log(event: &StarknetCoreContract::LogMessageToL2) -> anyhow::Result<L1HandlerTransaction> {
+    let nonce = u64::try_from(event.nonce).context("Message nonce does not fit in a u64")?;
+    let calldata = std::iter::once(Ok(Felt::from_bytes_be_slice(event.fromAddress.as_slice())))
+        .chain(event.payload.iter().map(|value| u256_to_felt(*value)))
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(L1HandlerTransaction {
+        version: Felt::ZERO,
+        nonce,
+        contract_address: u256_to_felt(event.toAddress)?,
+        entry_point_selector: u256_to_felt(event.selector)?,
+        calldata,
+    })
+}
+
+/// Which messages were turned into transactions, and which ones were cancelled, by nonce.
+#[derive(Debug, Default)]
+pub struct L1MessagingState {
+    consumed_nonces: HashSet<u64>,
+    cancelled: HashMap<u64, B256>,
+}
+
+impl L1MessagingState {
+    /// Turns a `LogMessageToL2` event into the L1 handler transaction to submit.
+    ///
+    /// Returns `None` when the message was already submitted, or when its cancellation was started on L1. A cancelled
+    /// message is recorded as such. The nonce of the message is only consumed once its transaction is submitted, see
+    /// [`handle_l1_message`].
+    pub async fn process_l1_message(
+        &mut self,
+        source: &dyn L1MessageSource,
+        event: &StarknetCoreContract::LogMessageToL2,
+    ) -> anyhow::Result<Option<L1HandlerTransaction>> {
+        let tx = l1_handler_from_log(event)?;
+        if self.consumed_nonces.contains(&tx.nonce) || self.cancelled.contains_key(&tx.nonce) {
+            return Ok(None);
+        }
+
+        let msg_hash =
+            l1_to_l2_message_hash(&event.fromAddress, event.toAddress, event.selector, &event.payload, event.nonce);
+        let cancellation = source
+            .get_l1_to_l2_message_cancellations(msg_hash)
+            .await
+            .context("Getting the cancellation of the L1 to L2 message")?;
+        if cancellation != U256::ZERO {
+            log::debug!("Skipping cancelled L1 to L2 message {msg_hash} with nonce {}", tx.nonce);
+            self.cancelled.insert(tx.nonce, msg_hash);
+            return Ok(None);
+        }
+
+        Ok(Some(tx))
+    }
+
+    /// Records a `MessageToL2Canceled` event. Returns the nonce of the message, so that its L1 handler transaction can
+    /// be dropped if it is still waiting to be executed.
+    pub fn on_message_cancelled(&mut self, event: &StarknetCoreContract::MessageToL2Canceled) -> anyhow::Result<u64> {
+        let nonce = u64::try_from(event.nonce).context("Message nonce does not fit in a u64")?;
+        let msg_hash =
+            l1_to_l2_message_hash(&event.fromAddress, event.toAddress, event.selector, &event.payload, event.nonce);
+        self.cancelled.insert(nonce, msg_hash);
+        Ok(nonce)
+    }
+
+    pub fn is_consumed(&self, nonce: u64) -> bool {
+        self.consumed_nonces.contains(&nonce)
+    }
+
+    pub fn is_cancelled(&self, nonce: u64) -> bool {
+        self.cancelled.contains_key(&nonce)
+    }
+}
+
+/// Submits the L1 handler transaction of a message, unless it was already submitted or was cancelled. Returns the
+/// hash of the submitted transaction. The message is only recorded as submitted when the submission succeeds, so that
+/// it is handled again after a failure.
+pub async fn handle_l1_message(
+    state: &mut L1MessagingState,
+    source: &dyn L1MessageSource,
+    submitter: &dyn L1HandlerSubmitter,
+    event: &StarknetCoreContract::LogMessageToL2,
+) -> anyhow::Result<Option<Felt>> {
+    let Some(tx) = state.process_l1_message(source, event).await? else { return Ok(None) };
+    let nonce = tx.nonce;
+    let paid_fee_on_l1 = u128::try_from(event.fee).context("Message fee does not fit in a u128")?;
+    let tx_hash =
+        submitter.submit_l1_handler_tx(tx, paid_fee_on_l1).context("Submitting the L1 handler transaction")?;
+    state.consumed_nonces.insert(nonce);
+
+    let msg_hash =
+        l1_to_l2_message_hash(&event.fromAddress, event.toAddress, event.selector, &event.payload, event.nonce);
+    tracing::debug!(
+        msg_hash = %msg_hash,
+        nonce,
+        tx_hash = format!("{tx_hash:#x}"),
+        "L1 to L2 message {msg_hash} with nonce {nonce} is executed by transaction {tx_hash:#x}"
+    );
+    Ok(Some(tx_hash))
+}
+
+/// Settings of the L1 messaging worker.
+#[derive(Debug, Clone)]
+pub struct L1MessagingConfig {
+    /// Number of L1 blocks the messages sent while the node was down are fetched by. A range is split in two for as
+    /// long as the provider returns too many logs for it.
+    pub backfill_chunk_size: u64,
+    /// Number of L1 blocks on top of a message before it is handled. This is also how far back the messages are
+    /// handled again after a reorg.
+    pub confirmations: u64,
+    /// Interval at which the new L1 blocks are checked for messages.
+    pub poll_interval: Duration,
+}
+
+impl Default for L1MessagingConfig {
+    fn default() -> Self {
+        Self { backfill_chunk_size: 5_000, confirmations: 12, poll_interval: Duration::from_secs(12) }
+    }
+}
+
+/// Handles the messages and cancellations from `from_block` up to the last confirmed L1 block, by chunks of
+/// [`L1MessagingConfig::backfill_chunk_size`] blocks. The progress is stored after every chunk, so that a restart
+/// resumes from there. Returns the first block that was not fetched.
+///
+/// A message that cannot be handled stops the catch-up with an error before the progress of its chunk is stored: the
+/// chunk is fetched again on the next run, and the messages of the chunk that were submitted are skipped by nonce.
+///
+/// When the last stored block was reorged away, the messages are handled again from
+/// [`L1MessagingConfig::confirmations`] blocks before it. The messages that were already handled are skipped by
+/// nonce.
+pub async fn catch_up_l1_messages<S: L1MessageLogSource + L1MessageSource>(
+    backend: &DeoxysBackend,
+    source: &S,
+    submitter: &dyn L1HandlerSubmitter,
+    state: &mut L1MessagingState,
+    config: &L1MessagingConfig,
+    mut from_block: u64,
+) -> anyhow::Result<u64> {
+    anyhow::ensure!(config.backfill_chunk_size > 0, "The L1 messages backfill chunk size must not be zero");
+
+    loop {
+        if let Some(last_synced) = backend.messaging_last_synced_l1_block_with_event()? {
+            if let Some(resume_from) = check_l1_reorg(source, &last_synced, config.confirmations).await? {
+                from_block = from_block.min(resume_from);
+            }
+        }
+
+        let head = confirmed_head(source, config.confirmations).await?;
+        // Touched on every chunk, the backfill of a node down for long takes many of them.
+        backend.worker_health().touch(WORKER_MESSAGING);
+        if from_block > head {
+            return Ok(from_block);
+        }
+
+        let mut to_block = head.min(from_block.saturating_add(config.backfill_chunk_size - 1));
+        let (events, synced) = loop {
+            // Fetched before the events: if L1 reorgs in between, the next check sees it.
+            let synced = event_block(source, to_block).await?;
+            match source.get_messaging_events(from_block, to_block).await {
+                Ok(events) => break (events, synced),
+                Err(GetLogsError::RangeTooLarge) if to_block > from_block => {
+                    to_block = from_block + (to_block - from_block) / 2;
+                    log::debug!("Too many L1 messages in the range, retrying with blocks {from_block}..={to_block}");
+                }
+                Err(GetLogsError::RangeTooLarge) => {
+                    anyhow::bail!("Too many L1 messages in block {from_block} for the L1 provider")
+                }
+                Err(GetLogsError::Other(err)) => return Err(err),
+            }
+        };
+
+        for (event, _block_number) in &events {
+            match event {
+                L1MessagingEvent::Message(event) => {
+                    // After a restart, the messages of the last confirmed blocks are fetched again: most of them were
+                    // executed already.
+                    if let Some(tx_hash) = executed_by(backend, event)? {
+                        log::debug!(
+                            "Skipping the L1 to L2 message with nonce {}, executed by transaction {tx_hash:#x}",
+                            event.nonce
+                        );
+                        continue;
+                    }
+                    handle_l1_message(state, source, submitter, event)
+                        .await
+                        .with_context(|| format!("Handling the L1 to L2 message with nonce {}", event.nonce))?;
+                }
+                L1MessagingEvent::Cancelled(event) => {
+                    let nonce = state.on_message_cancelled(event)?;
+                    if submitter.remove_l1_handler_tx(nonce) {
+                        log::info!("Dropped the L1 handler transaction of the cancelled message with nonce {nonce}");
+                    }
+                }
+            }
+        }
+        backend
+            .messaging_update_last_synced_l1_block_with_event(synced)
+            .context("Storing the L1 messaging progress")?;
+        log::debug!("Handled {} L1 messaging events from blocks {from_block}..={to_block}", events.len());
+
+        from_block = to_block + 1;
+    }
+}
+
+/// The hash of the stored L1 handler transaction of a message.
+fn executed_by(backend: &DeoxysBackend, event: &StarknetCoreContract::LogMessageToL2) -> anyhow::Result<Option<Felt>> {
+    let Ok(nonce) = u64::try_from(event.nonce) else { return Ok(None) };
+    Ok(backend.get_l1_handler_tx_hash(nonce)?)
+}
+
+/// Handles the messages sent to L2 since the last run, then polls L1 for the new ones and for their cancellations,
+/// and keeps the submitter up to date until the node shuts down.
+///
+/// On the first run, only the messages sent from the last confirmed L1 block on are picked up.
+pub async fn sync_l1_messages(
+    eth_client: &EthereumClient,
+    backend: &DeoxysBackend,
+    submitter: &dyn L1HandlerSubmitter,
+    config: &L1MessagingConfig,
+) -> anyhow::Result<()> {
+    let mut from_block = match backend.messaging_last_synced_l1_block_with_event()? {
+        Some(last_synced) => last_synced.block_number + 1,
+        None => confirmed_head(eth_client, config.confirmations).await?,
+    };
+
+    backend.worker_health().register(WORKER_MESSAGING, config.poll_interval);
+    let mut state = L1MessagingState::default();
+    from_block = catch_up_l1_messages(backend, eth_client, submitter, &mut state, config, from_block).await?;
+    log::info!("📨 Caught up with L1 to L2 messages at L1 block {}", from_block.saturating_sub(1));
+
+    let mut interval = tokio::time::interval(config.poll_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+        from_block = catch_up_l1_messages(backend, eth_client, submitter, &mut state, config, from_block).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use dc_db::DatabaseService;
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, Header};
+    use dp_transactions::Transaction;
+
+    use super::*;
+
+    /// Messages whose cancellation was started.
+    #[derive(Default)]
+    struct MockL1MessageSource(Mutex<HashSet<B256>>);
+
+    #[async_trait::async_trait]
+    impl L1MessageSource for MockL1MessageSource {
+        async fn get_l1_to_l2_message_cancellations(&self, msg_hash: B256) -> anyhow::Result<U256> {
+            let cancelled = self.0.lock().unwrap().contains(&msg_hash);
+            Ok(if cancelled { U256::from(1_700_000_000u64) } else { U256::ZERO })
+        }
+    }
+
+    fn message(nonce: u64) -> StarknetCoreContract::LogMessageToL2 {
+        StarknetCoreContract::LogMessageToL2 {
+            fromAddress: Address::repeat_byte(0xae),
+            toAddress: U256::from(0x1234),
+            selector: U256::from(0x5678),
+            payload: vec![U256::from(1), U256::from(2)],
+            nonce: U256::from(nonce),
+            fee: U256::from(1_000_000),
+        }
+    }
+
+    #[derive(Default)]
+    struct MockSubmitter(Mutex<Vec<(L1HandlerTransaction, u128)>>);
+
+    impl L1HandlerSubmitter for MockSubmitter {
+        fn submit_l1_handler_tx(&self, tx: L1HandlerTransaction, paid_fee_on_l1: u128) -> anyhow::Result<Felt> {
+            let mut submitted = self.0.lock().unwrap();
+            submitted.push((tx, paid_fee_on_l1));
+            Ok(Felt::from(submitted.len()))
+        }
+
+        fn remove_l1_handler_tx(&self, nonce: u64) -> bool {
+            let mut submitted = self.0.lock().unwrap();
+            let len = submitted.len();
+            submitted.retain(|(tx, _)| tx.nonce != nonce);
+            submitted.len() < len
+        }
+    }
+
+    fn msg_hash(event: &StarknetCoreContract::LogMessageToL2) -> B256 {
+        l1_to_l2_message_hash(&event.fromAddress, event.toAddress, event.selector, &event.payload, event.nonce)
+    }
+
+    #[tokio::test]
+    async fn test_message_becomes_l1_handler() {
+        let mut state = L1MessagingState::default();
+        let tx = state.process_l1_message(&MockL1MessageSource::default(), &message(7)).await.unwrap().unwrap();
+
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.contract_address, Felt::from(0x1234));
+        assert_eq!(tx.entry_point_selector, Felt::from(0x5678));
+        assert_eq!(
+            tx.calldata,
+            vec![Felt::from_hex_unchecked("0xaeaeaeaeaeaeaeaeaeaeaeaeaeaeaeaeaeaeaeae"), Felt::ONE, Felt::TWO]
+        );
+        // The message is processed again until its transaction is submitted.
+        assert!(!state.is_consumed(7));
+        assert!(state.process_l1_message(&MockL1MessageSource::default(), &message(7)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_message_is_skipped() {
+        let source = MockL1MessageSource::default();
+        source.0.lock().unwrap().insert(msg_hash(&message(7)));
+        let mut state = L1MessagingState::default();
+
+        assert!(state.process_l1_message(&source, &message(7)).await.unwrap().is_none());
+        assert!(!state.is_consumed(7));
+        assert!(state.is_cancelled(7));
+
+        // Other messages are not affected.
+        assert!(state.process_l1_message(&source, &message(8)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_message_cancelled_after_processing() {
+        let mut state = L1MessagingState::default();
+        let event = message(7);
+        state.process_l1_message(&MockL1MessageSource::default(), &event).await.unwrap().unwrap();
+
+        let cancelled = StarknetCoreContract::MessageToL2Canceled {
+            fromAddress: event.fromAddress,
+            toAddress: event.toAddress,
+            selector: event.selector,
+            payload: event.payload.clone(),
+            nonce: event.nonce,
+        };
+        assert_eq!(state.on_message_cancelled(&cancelled).unwrap(), 7);
+        assert!(state.is_cancelled(7));
+    }
+
+    #[test]
+    fn test_message_hash_depends_on_every_field() {
+        let event = message(7);
+        let hash = msg_hash(&event);
+        assert_ne!(hash, msg_hash(&message(8)));
+        assert_ne!(hash, msg_hash(&StarknetCoreContract::LogMessageToL2 { payload: vec![], ..message(7) }));
+        assert_ne!(
+            hash,
+            msg_hash(&StarknetCoreContract::LogMessageToL2 { fromAddress: Address::repeat_byte(1), ..message(7) })
+        );
+        // The fee is not part of the message.
+        assert_eq!(hash, msg_hash(&StarknetCoreContract::LogMessageToL2 { fee: U256::ZERO, ..message(7) }));
+    }
+
+    #[test]
+    fn test_message_hash_matches_receipt() {
+        // The receipt of the L1 handler transaction recomputes the hash of its message from the transaction fields.
+        let event = message(7);
+        let tx = l1_handler_from_log(&event).unwrap();
+        assert_eq!(
+            dp_receipt::l1_handler_message_hash(&tx.calldata, tx.contract_address, tx.entry_point_selector, tx.nonce),
+            msg_hash(&event).0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_l1_message_submits_once() {
+        let (source, submitter) = (MockL1MessageSource::default(), MockSubmitter::default());
+        let mut state = L1MessagingState::default();
+
+        let tx_hash = handle_l1_message(&mut state, &source, &submitter, &message(7)).await.unwrap();
+        assert_eq!(tx_hash, Some(Felt::ONE));
+        assert!(handle_l1_message(&mut state, &source, &submitter, &message(7)).await.unwrap().is_none());
+
+        let submitted = submitter.0.lock().unwrap();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(submitted[0].0.nonce, 7);
+        assert_eq!(submitted[0].1, 1_000_000);
+    }
+
+    /// A mempool refusing every transaction.
+    struct FailingSubmitter;
+
+    impl L1HandlerSubmitter for FailingSubmitter {
+        fn submit_l1_handler_tx(&self, _tx: L1HandlerTransaction, _paid_fee_on_l1: u128) -> anyhow::Result<Felt> {
+            anyhow::bail!("The mempool is full")
+        }
+
+        fn remove_l1_handler_tx(&self, _nonce: u64) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_submission_is_retried() {
+        let source = MockL1MessageSource::default();
+        let mut state = L1MessagingState::default();
+
+        assert!(handle_l1_message(&mut state, &source, &FailingSubmitter, &message(7)).await.is_err());
+        assert!(!state.is_consumed(7));
+
+        let submitter = MockSubmitter::default();
+        assert_eq!(handle_l1_message(&mut state, &source, &submitter, &message(7)).await.unwrap(), Some(Felt::ONE));
+        assert!(state.is_consumed(7));
+    }
+
+    /// Messages sent at given L1 blocks, behind a provider returning at most `max_logs` logs per call.
+    struct MockLogSource {
+        head: u64,
+        messages: Vec<(u64, StarknetCoreContract::LogMessageToL2)>,
+        max_logs: usize,
+        /// Calls with an index from this one fail.
+        fail_from_call: Option<usize>,
+        /// The blocks from this one are on another fork than the ones of a source without it.
+        fork_from_block: Option<u64>,
+        calls: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl MockLogSource {
+        fn new(head: u64, blocks: &[u64], max_logs: usize) -> Self {
+            let messages = blocks.iter().enumerate().map(|(nonce, block)| (*block, message(nonce as u64))).collect();
+            Self { head, messages, max_logs, fail_from_call: None, fork_from_block: None, calls: Default::default() }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl L1MessageSource for MockLogSource {
+        async fn get_l1_to_l2_message_cancellations(&self, _msg_hash: B256) -> anyhow::Result<U256> {
+            Ok(U256::ZERO)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl L1BlockSource for MockLogSource {
+        async fn get_latest_block_number(&self) -> anyhow::Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn get_block_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>> {
+            if block_number > self.head {
+                return Ok(None);
+            }
+            let mut hash = B256::from(U256::from(block_number));
+            if self.fork_from_block.is_some_and(|fork_from_block| block_number >= fork_from_block) {
+                hash.0[0] = 0xff;
+            }
+            Ok(Some(hash))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl L1MessageLogSource for MockLogSource {
+        async fn get_messaging_events(
+            &self,
+            from_block: u64,
+            to_block: u64,
+        ) -> Result<Vec<(L1MessagingEvent, u64)>, GetLogsError> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push((from_block, to_block));
+            if self.fail_from_call.is_some_and(|fail_from_call| calls.len() > fail_from_call) {
+                return Err(GetLogsError::Other(anyhow::anyhow!("Connection reset")));
+            }
+
+            let events: Vec<_> = self
+                .messages
+                .iter()
+                .filter(|(block, _)| (from_block..=to_block).contains(block))
+                .map(|(block, event)| (L1MessagingEvent::Message(event.clone()), *block))
+                .collect();
+            if events.len() > self.max_logs {
+                return Err(GetLogsError::RangeTooLarge);
+            }
+            Ok(events)
+        }
+    }
+
+    fn config(backfill_chunk_size: u64, confirmations: u64) -> L1MessagingConfig {
+        L1MessagingConfig { backfill_chunk_size, confirmations, ..Default::default() }
+    }
+
+    async fn test_backend() -> (tempfile::TempDir, Arc<DeoxysBackend>) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        (temp_dir, Arc::clone(db.backend()))
+    }
+
+    fn last_synced_block(backend: &DeoxysBackend) -> Option<u64> {
+        backend.messaging_last_synced_l1_block_with_event().unwrap().map(|block| block.block_number)
+    }
+
+    fn submitted_nonces(submitter: &MockSubmitter) -> Vec<u64> {
+        submitter.0.lock().unwrap().iter().map(|(tx, _)| tx.nonce).collect()
+    }
+
+    #[tokio::test]
+    async fn test_backfill_bisects_ranges_with_too_many_logs() {
+        let (_temp_dir, backend) = test_backend().await;
+        let source = MockLogSource::new(100, &[10, 20, 30, 40], 2);
+        let submitter = MockSubmitter::default();
+        let config = config(100, 0);
+
+        let next_block =
+            catch_up_l1_messages(&backend, &source, &submitter, &mut Default::default(), &config, 0).await.unwrap();
+
+        assert_eq!(next_block, 101);
+        assert_eq!(*source.calls.lock().unwrap(), [(0, 99), (0, 49), (0, 24), (25, 100)]);
+        assert_eq!(submitted_nonces(&submitter), [0, 1, 2, 3]);
+        assert_eq!(last_synced_block(&backend), Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_fails_on_single_block_with_too_many_logs() {
+        let (_temp_dir, backend) = test_backend().await;
+        let source = MockLogSource::new(100, &[10, 10, 10], 2);
+        let config = config(100, 0);
+
+        let res =
+            catch_up_l1_messages(&backend, &source, &MockSubmitter::default(), &mut Default::default(), &config, 0)
+                .await;
+        assert!(res.is_err());
+        assert_eq!(source.calls.lock().unwrap().last(), Some(&(10, 10)));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_resumes_from_stored_progress() {
+        let (_temp_dir, backend) = test_backend().await;
+        let blocks = [10, 20, 30, 40, 60];
+        let submitter = MockSubmitter::default();
+        let config = config(25, 0);
+
+        // The provider goes down after two chunks.
+        let source = MockLogSource { fail_from_call: Some(2), ..MockLogSource::new(100, &blocks, 10) };
+        let res = catch_up_l1_messages(&backend, &source, &submitter, &mut Default::default(), &config, 0).await;
+        assert!(res.is_err());
+        assert_eq!(*source.calls.lock().unwrap(), [(0, 24), (25, 49), (50, 74)]);
+        assert_eq!(submitted_nonces(&submitter), [0, 1, 2, 3]);
+        assert_eq!(last_synced_block(&backend), Some(49));
+
+        // After a restart, the backfill picks up where it stopped.
+        let source = MockLogSource::new(100, &blocks, 10);
+        let from_block = last_synced_block(&backend).unwrap() + 1;
+        let next_block =
+            catch_up_l1_messages(&backend, &source, &submitter, &mut Default::default(), &config, from_block)
+                .await
+                .unwrap();
+
+        assert_eq!(next_block, 101);
+        assert_eq!(*source.calls.lock().unwrap(), [(50, 74), (75, 99), (100, 100)]);
+        assert_eq!(submitted_nonces(&submitter), [0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_keeps_the_chunk_of_a_failed_message() {
+        let (_temp_dir, backend) = test_backend().await;
+        let blocks = [10, 20, 30, 40];
+        let config = config(25, 0);
+        let mut state = L1MessagingState::default();
+
+        // The chunk of the first message is not stored as synced.
+        let source = MockLogSource::new(100, &blocks, 10);
+        let res = catch_up_l1_messages(&backend, &source, &FailingSubmitter, &mut state, &config, 0).await;
+        assert!(res.is_err());
+        assert_eq!(*source.calls.lock().unwrap(), [(0, 24)]);
+        assert_eq!(last_synced_block(&backend), None);
+
+        // The next run fetches the chunk again and submits its messages.
+        let submitter = MockSubmitter::default();
+        let next_block = catch_up_l1_messages(&backend, &source, &submitter, &mut state, &config, 0).await.unwrap();
+        assert_eq!(next_block, 101);
+        assert_eq!(submitted_nonces(&submitter), [0, 1, 2, 3]);
+        assert_eq!(last_synced_block(&backend), Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_messages_wait_for_confirmations() {
+        let (_temp_dir, backend) = test_backend().await;
+        let submitter = MockSubmitter::default();
+        let mut state = L1MessagingState::default();
+        let config = config(100, 5);
+
+        let source = MockLogSource::new(100, &[10, 98], 10);
+        let next_block = catch_up_l1_messages(&backend, &source, &submitter, &mut state, &config, 0).await.unwrap();
+        assert_eq!(next_block, 96);
+        assert_eq!(submitted_nonces(&submitter), [0]);
+
+        // The message of block 98 is handled once 5 blocks were built on top of it.
+        let source = MockLogSource::new(103, &[10, 98], 10);
+        let next_block =
+            catch_up_l1_messages(&backend, &source, &submitter, &mut state, &config, next_block).await.unwrap();
+        assert_eq!(next_block, 99);
+        assert_eq!(submitted_nonces(&submitter), [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_handled_again_after_a_reorg() {
+        let (_temp_dir, backend) = test_backend().await;
+        let submitter = MockSubmitter::default();
+        let mut state = L1MessagingState::default();
+        let config = config(100, 5);
+
+        let source = MockLogSource::new(100, &[10, 90], 10);
+        let next_block = catch_up_l1_messages(&backend, &source, &submitter, &mut state, &config, 0).await.unwrap();
+        assert_eq!(next_block, 96);
+        assert_eq!(last_synced_block(&backend), Some(95));
+
+        // L1 reorgs from block 94, deeper than the confirmations. The reorged chain has a new message in block 94.
+        let source = MockLogSource { fork_from_block: Some(94), ..MockLogSource::new(105, &[10, 90, 94], 10) };
+        let next_block =
+            catch_up_l1_messages(&backend, &source, &submitter, &mut state, &config, next_block).await.unwrap();
+
+        // The events are fetched again from 5 blocks before the reorged block we stored.
+        assert_eq!(*source.calls.lock().unwrap(), [(90, 100)]);
+        assert_eq!(next_block, 101);
+        // The message of block 90 is not submitted twice.
+        assert_eq!(submitted_nonces(&submitter), [0, 1, 2]);
+        let last_synced = backend.messaging_last_synced_l1_block_with_event().unwrap().unwrap();
+        assert_eq!(last_synced.block_number, 100);
+        assert_eq!(Some(B256::from(last_synced.block_hash)), source.get_block_hash(100).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_executed_messages_are_skipped_on_restart() {
+        let (_temp_dir, backend) = test_backend().await;
+        let submitter = MockSubmitter::default();
+        let config = config(100, 0);
+
+        // The L1 handler transaction of the message with nonce 0 is in block 0.
+        let l1_handler = l1_handler_from_log(&message(0)).unwrap();
+        let tx_hash = Felt::from(0xabc);
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(
+                Header::default(),
+                vec![tx_hash],
+                Felt::ZERO,
+            )),
+            inner: DeoxysBlockInner::new(vec![Transaction::L1Handler(l1_handler)], vec![]),
+        };
+        backend.store_block(block, Default::default(), vec![]).unwrap();
+
+        // A fresh state, as after a restart.
+        let source = MockLogSource::new(100, &[10, 20], 10);
+        catch_up_l1_messages(&backend, &source, &submitter, &mut Default::default(), &config, 0).await.unwrap();
+        assert_eq!(submitted_nonces(&submitter), [1]);
+    }
+
+    #[test]
+    fn test_is_range_too_large() {
+        assert!(is_range_too_large("query returned more than 10000 results"));
+        assert!(is_range_too_large(
+            "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"
+        ));
+        assert!(!is_range_too_large("error sending request for url"));
+    }
+}
@@ -1,4 +1,8 @@
 pub mod client;
 pub mod error;
+pub mod failover;
+pub mod l1_gas_price;
+pub mod l1_messaging;
+pub mod reorg;
 pub mod state_update;
 pub mod utils;
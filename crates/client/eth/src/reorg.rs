@@ -0,0 +1,71 @@
+//! Ethereum reorgs, as seen by the L1 workers.
+//!
+//! The L1 workers only act on the core contract events once they are buried under a number of confirmations. They
+//! store the L1 block they processed the events up to, along with its hash. When that block is no longer on
+//! the canonical chain, a reorg deeper than the confirmation depth happened: the worker goes back that many blocks
+//! before the stored one, and processes the events from there again.
+
+use alloy::primitives::B256;
+use alloy::providers::Provider;
+use alloy::rpc::types::BlockNumberOrTag;
+use anyhow::Context;
+use dc_db::l1_db::LastSyncedEventBlock;
+
+use crate::client::EthereumClient;
+
+#[async_trait::async_trait]
+pub trait L1BlockSource: Send + Sync {
+    async fn get_latest_block_number(&self) -> anyhow::Result<u64>;
+    /// Hash of the canonical block with this number, `None` when the chain is not that long.
+    async fn get_block_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>>;
+}
+
+#[async_trait::async_trait]
+impl L1BlockSource for EthereumClient {
+    async fn get_latest_block_number(&self) -> anyhow::Result<u64> {
+        EthereumClient::get_latest_block_number(self).await
+    }
+
+    async fn get_block_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>> {
+        let block = self.provider.get_block_by_number(BlockNumberOrTag::Number(block_number), false).await?;
+        block.map(|block| block.header.hash.context("No hash in L1 block")).transpose()
+    }
+}
+
+/// The last L1 block with at least `confirmations` blocks on top of it.
+pub async fn confirmed_head(source: &dyn L1BlockSource, confirmations: u64) -> anyhow::Result<u64> {
+    let head = source.get_latest_block_number().await.context("Getting the L1 head")?;
+    Ok(head.saturating_sub(confirmations))
+}
+
+/// The canonical block with this number, to store as the last synced block.
+pub async fn event_block(source: &dyn L1BlockSource, block_number: u64) -> anyhow::Result<LastSyncedEventBlock> {
+    let block_hash = source
+        .get_block_hash(block_number)
+        .await?
+        .with_context(|| format!("L1 block {block_number} does not exist"))?;
+    Ok(LastSyncedEventBlock { block_number, block_hash: block_hash.0 })
+}
+
+/// Checks that the last synced block is still on the canonical chain. If it is not, returns the block to process the
+/// events from again, `confirmations` blocks before it.
+pub async fn check_l1_reorg(
+    source: &dyn L1BlockSource,
+    last_synced: &LastSyncedEventBlock,
+    confirmations: u64,
+) -> anyhow::Result<Option<u64>> {
+    let canonical_hash =
+        source.get_block_hash(last_synced.block_number).await.context("Getting the last synced L1 block")?;
+    if canonical_hash == Some(B256::from(last_synced.block_hash)) {
+        return Ok(None);
+    }
+
+    let resume_from = last_synced.block_number.saturating_sub(confirmations);
+    log::warn!(
+        "🔀 L1 block {} {} is no longer on the canonical chain, processing the L1 events again from block {}",
+        last_synced.block_number,
+        B256::from(last_synced.block_hash),
+        resume_from
+    );
+    Ok(Some(resume_from))
+}
@@ -1,16 +1,27 @@
+//! The L2 blocks verified on L1, from the `LogStateUpdate` events of the Starknet core contract. An event is only
+//! applied once it is confirmed on L1, see [`crate::reorg`].
+
+use std::time::Duration;
+
 use crate::client::{L1BlockMetrics, StarknetCoreContract};
+use crate::reorg::{check_l1_reorg, confirmed_head, event_block, L1BlockSource};
 use crate::{
     client::EthereumClient,
-    utils::{convert_log_state_update, trim_hash},
+    utils::{convert_log_state_update, trim_hash, u256_to_felt},
 };
-use anyhow::Context;
+use alloy::primitives::{Address, B256, I256};
+use alloy::providers::Provider;
+use alloy::rpc::types::{BlockId, Filter, Log};
+use alloy::sol_types::SolEvent;
+use anyhow::{ensure, Context};
+use dc_db::db_block_id::DbBlockId;
+use dc_db::l1_db::LastSyncedEventBlock;
+use dc_db::worker_health::WORKER_ETH_STATE;
 use dc_db::DeoxysBackend;
-use dp_transactions::MAIN_CHAIN_ID;
-use dp_utils::channel_wait_or_graceful_shutdown;
-use futures::StreamExt;
+use dp_utils::error_reporting::{self, Severity};
+use dp_utils::wait_or_graceful_shutdown;
 use serde::Deserialize;
 use starknet_api::hash::StarkHash;
-use starknet_types_core::felt::Felt;
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct L1StateUpdate {
@@ -19,6 +30,70 @@ pub struct L1StateUpdate {
     pub block_hash: StarkHash,
 }
 
+/// Settings of the L1 state update worker.
+#[derive(Debug, Clone)]
+pub struct L1StateSyncConfig {
+    /// Number of L1 blocks on top of a `LogStateUpdate` event before it is applied. This is also how far back the
+    /// events are applied again after a reorg.
+    pub confirmations: u64,
+    /// Interval at which the new L1 blocks are checked for state updates.
+    pub poll_interval: Duration,
+}
+
+impl Default for L1StateSyncConfig {
+    fn default() -> Self {
+        Self { confirmations: 12, poll_interval: Duration::from_secs(12) }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait L1StateUpdateSource: L1BlockSource {
+    /// The last Starknet state verified on L1, as of the given L1 block.
+    async fn get_state_at(&self, l1_block: &LastSyncedEventBlock) -> anyhow::Result<L1StateUpdate>;
+    /// The `LogStateUpdate` events emitted between these two blocks, both included, in order.
+    async fn get_state_updates(&self, from_block: u64, to_block: u64) -> anyhow::Result<Vec<L1StateUpdate>>;
+}
+
+#[async_trait::async_trait]
+impl L1StateUpdateSource for EthereumClient {
+    async fn get_state_at(&self, l1_block: &LastSyncedEventBlock) -> anyhow::Result<L1StateUpdate> {
+        let at = BlockId::hash(B256::from(l1_block.block_hash));
+        let block_number = self.l1_core_contract.stateBlockNumber().block(at).call().await?._0;
+        let block_hash = self.l1_core_contract.stateBlockHash().block(at).call().await?._0;
+        let global_root = self.l1_core_contract.stateRoot().block(at).call().await?._0;
+        ensure!(block_number >= I256::ZERO, "Block number is negative");
+
+        Ok(L1StateUpdate {
+            block_number: block_number.low_u64(),
+            global_root: u256_to_felt(global_root)?,
+            block_hash: u256_to_felt(block_hash)?,
+        })
+    }
+
+    async fn get_state_updates(&self, from_block: u64, to_block: u64) -> anyhow::Result<Vec<L1StateUpdate>> {
+        let filter = Filter::new()
+            .from_block(from_block)
+            .to_block(to_block)
+            .address(*self.l1_core_contract.address())
+            .event_signature(StarknetCoreContract::LogStateUpdate::SIGNATURE_HASH);
+        let logs = self.provider.get_logs(&filter).await.context("Getting LogStateUpdate logs")?;
+
+        let core_contract = *self.l1_core_contract.address();
+        logs.iter().filter_map(|log| decode_state_update_log(log, core_contract).transpose()).collect()
+    }
+}
+
+/// Decodes a `LogStateUpdate` log. Logs emitted by any other contract than the core contract are ignored: the filter
+/// already asks for the core contract ones only, but the L1 endpoint is not trusted to apply it.
+fn decode_state_update_log(log: &Log, core_contract: Address) -> anyhow::Result<Option<L1StateUpdate>> {
+    if log.address() != core_contract {
+        log::warn!("Ignoring a LogStateUpdate event emitted by {}, which is not the core contract", log.address());
+        return Ok(None);
+    }
+    let event = log.log_decode::<StarknetCoreContract::LogStateUpdate>()?.inner.data;
+    convert_log_state_update(event).context("formatting event into an L1StateUpdate").map(Some)
+}
+
 /// Get the last Starknet state update verified on the L1
 pub async fn get_initial_state(client: &EthereumClient) -> anyhow::Result<L1StateUpdate> {
     let block_number = client.get_last_verified_block_number().await?;
@@ -28,71 +103,200 @@ pub async fn get_initial_state(client: &EthereumClient) -> anyhow::Result<L1Stat
     Ok(L1StateUpdate { global_root, block_number, block_hash })
 }
 
-/// Subscribes to the LogStateUpdate event from the Starknet core contract and store latest
-/// verified state
+/// Applies the `LogStateUpdate` events from `from_block` up to the last confirmed L1 block, and stores that block as
+/// the one the confirmed state was read at. Returns the first block that was not fetched.
+///
+/// When the stored block was reorged away, the confirmed state may come from an event that no longer exists: it is
+/// read again from the core contract [`L1StateSyncConfig::confirmations`] blocks before the stored block, and the
+/// events are applied again from there.
+///
+/// `unverified` is the state update applied before its block was synced, see [`update_l1`]. It is checked again
+/// first.
+pub async fn update_confirmed_state<S: L1StateUpdateSource>(
+    backend: &DeoxysBackend,
+    source: &S,
+    block_metrics: &L1BlockMetrics,
+    config: &L1StateSyncConfig,
+    unverified: &mut Option<L1StateUpdate>,
+    mut from_block: u64,
+) -> anyhow::Result<u64> {
+    if let Some(state_update) = unverified.take() {
+        *unverified = verify_applied_state_update(backend, state_update, block_metrics)?;
+    }
+
+    if let Some(last_synced) = backend.get_l1_last_confirmed_event_block()? {
+        if let Some(resume_from) = check_l1_reorg(source, &last_synced, config.confirmations).await? {
+            let base = event_block(source, resume_from).await?;
+            let state = source.get_state_at(&base).await.context("Getting the state verified on L1")?;
+            // The confirmed block may come from an event that was reorged away, this is the only way back.
+            backend.clear_last_confirmed_block().context("Clearing l1 last confirmed block number")?;
+            *unverified = update_l1(backend, state, block_metrics)?;
+            backend.write_l1_last_confirmed_event_block(base).context("Storing the L1 state update progress")?;
+            from_block = resume_from + 1;
+        }
+    }
+
+    let head = confirmed_head(source, config.confirmations).await?;
+    backend.sync_status().record_l1_connected();
+    if from_block > head {
+        return Ok(from_block);
+    }
+
+    // Fetched before the events: if L1 reorgs in between, the next check sees it.
+    let synced = event_block(source, head).await?;
+    let state_updates = source.get_state_updates(from_block, head).await?;
+    // Only the last verified block matters.
+    if let Some(state_update) = state_updates.into_iter().last() {
+        *unverified = update_l1(backend, state_update, block_metrics)?.or(unverified.take());
+    }
+    backend.write_l1_last_confirmed_event_block(synced).context("Storing the L1 state update progress")?;
+
+    Ok(head + 1)
+}
+
+/// Polls the LogStateUpdate events of the Starknet core contract and stores the latest verified state, until the
+/// node shuts down.
 pub async fn listen_and_update_state(
     eth_client: &EthereumClient,
     backend: &DeoxysBackend,
     block_metrics: &L1BlockMetrics,
-    chain_id: Felt,
+    config: &L1StateSyncConfig,
+    mut unverified: Option<L1StateUpdate>,
 ) -> anyhow::Result<()> {
-    let event_filter = eth_client.l1_core_contract.event_filter::<StarknetCoreContract::LogStateUpdate>();
-
-    let mut event_stream = event_filter.watch().await.context("Failed to watch event filter")?.into_stream();
-
-    while let Some(event_result) = channel_wait_or_graceful_shutdown(event_stream.next()).await {
-        let log = event_result.context("listening for events")?;
-        let format_event: L1StateUpdate =
-            convert_log_state_update(log.0.clone()).context("formatting event into an L1StateUpdate")?;
-        update_l1(backend, format_event, block_metrics, chain_id)?;
+    let mut from_block = match backend.get_l1_last_confirmed_event_block()? {
+        Some(last_synced) => last_synced.block_number + 1,
+        None => confirmed_head(eth_client, config.confirmations).await?,
+    };
+
+    backend.worker_health().register(WORKER_ETH_STATE, config.poll_interval);
+    let mut interval = tokio::time::interval(config.poll_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+        from_block =
+            update_confirmed_state(backend, eth_client, block_metrics, config, &mut unverified, from_block).await?;
+        backend.worker_health().touch(WORKER_ETH_STATE);
     }
 
     Ok(())
 }
 
+/// Stores the L2 block verified on L1 as the last confirmed block.
+///
+/// When the block was synced already, its state root and hash must match the ones verified on L1. A mismatch means
+/// that either the synced chain or the L1 endpoint is wrong: it is reported, and the state update is not applied. The
+/// last confirmed block never goes back, a state update older than it is ignored.
+///
+/// A state update whose block is not synced yet is applied, and returned: it must be checked with
+/// [`verify_applied_state_update`] once the block is stored.
 pub fn update_l1(
     backend: &DeoxysBackend,
     state_update: L1StateUpdate,
     block_metrics: &L1BlockMetrics,
-    chain_id: Felt,
-) -> anyhow::Result<()> {
-    // This is a provisory check to avoid updating the state with an L1StateUpdate that should not have been detected
-    //
-    // TODO: Remove this check when the L1StateUpdate is properly verified
-    if state_update.block_number > 500000u64 || chain_id == MAIN_CHAIN_ID {
-        log::info!(
-            "🔄 Updated L1 head #{} ({}) with state root ({})",
-            state_update.block_number,
-            trim_hash(&state_update.block_hash),
-            trim_hash(&state_update.global_root)
+) -> anyhow::Result<Option<L1StateUpdate>> {
+    if backend.get_l1_last_confirmed_block()?.is_some_and(|last_confirmed| state_update.block_number < last_confirmed) {
+        log::debug!(
+            "update_l1: ignoring the state update of block {}, older than the confirmed one",
+            state_update.block_number
         );
+        return Ok(None);
+    }
+
+    let synced = match matches_synced_block(backend, &state_update, block_metrics)? {
+        Some(false) => return Ok(None),
+        Some(true) => true,
+        None => false,
+    };
+
+    tracing::info!(
+        l1_block_number = state_update.block_number,
+        block_hash = format!("{:#x}", state_update.block_hash),
+        state_root = format!("{:#x}", state_update.global_root),
+        "🔄 Updated L1 head #{} ({}) with state root ({})",
+        state_update.block_number,
+        trim_hash(&state_update.block_hash),
+        trim_hash(&state_update.global_root)
+    );
+
+    block_metrics.l1_block_number.set(state_update.block_number as f64);
 
-        block_metrics.l1_block_number.set(state_update.block_number as f64);
+    backend.write_last_confirmed_block(state_update.block_number).context("Setting l1 last confirmed block number")?;
+    log::debug!("update_l1: wrote last confirmed block number");
 
-        backend
-            .write_last_confirmed_block(state_update.block_number)
-            .context("Setting l1 last confirmed block number")?;
-        log::debug!("update_l1: wrote last confirmed block number");
+    Ok((!synced).then_some(state_update))
+}
+
+/// Checks a state update applied by [`update_l1`] before its block was synced. It is returned back while the block is
+/// still not synced. On a mismatch, the last confirmed block is cleared: it was never verified.
+pub fn verify_applied_state_update(
+    backend: &DeoxysBackend,
+    state_update: L1StateUpdate,
+    block_metrics: &L1BlockMetrics,
+) -> anyhow::Result<Option<L1StateUpdate>> {
+    match matches_synced_block(backend, &state_update, block_metrics)? {
+        None => Ok(Some(state_update)),
+        Some(true) => Ok(None),
+        Some(false) => {
+            if backend.get_l1_last_confirmed_block()? == Some(state_update.block_number) {
+                backend.clear_last_confirmed_block().context("Clearing l1 last confirmed block number")?;
+            }
+            Ok(None)
+        }
     }
+}
 
-    Ok(())
+/// Whether the state root and hash of the synced block match the state update, or `None` when the block is not synced
+/// yet. A mismatch is reported.
+fn matches_synced_block(
+    backend: &DeoxysBackend,
+    state_update: &L1StateUpdate,
+    block_metrics: &L1BlockMetrics,
+) -> anyhow::Result<Option<bool>> {
+    let synced_block = backend
+        .get_block_info(&DbBlockId::BlockN(state_update.block_number))
+        .context("Getting the block verified on L1")?;
+    let Some(synced_block) = synced_block.as_ref().and_then(|block| block.as_nonpending()) else { return Ok(None) };
+
+    if synced_block.header.global_state_root != state_update.global_root
+        || synced_block.block_hash != state_update.block_hash
+    {
+        log::error!(
+            "❗ L1 verified block #{} with hash {:#x} and state root {:#x}, but the synced block has hash {:#x} and \
+             state root {:#x}",
+            state_update.block_number,
+            state_update.block_hash,
+            state_update.global_root,
+            synced_block.block_hash,
+            synced_block.header.global_state_root
+        );
+        block_metrics.l1_state_update_mismatch.set(1.0);
+        return Ok(Some(false));
+    }
+    block_metrics.l1_state_update_mismatch.set(0.0);
+    Ok(Some(true))
 }
 
-pub async fn sync(backend: &DeoxysBackend, eth_client: &EthereumClient, chain_id: Felt) -> anyhow::Result<()> {
+pub async fn sync(
+    backend: &DeoxysBackend,
+    eth_client: &EthereumClient,
+    config: &L1StateSyncConfig,
+) -> anyhow::Result<()> {
     // Clear L1 confirmed block at startup
     backend.clear_last_confirmed_block().context("Clearing l1 last confirmed block number")?;
     log::debug!("update_l1: cleared confirmed block number");
 
     log::info!("🚀 Subscribed to L1 state verification");
 
-    // Get and store the latest verified state
-    let initial_state = get_initial_state(eth_client).await.context("Getting initial ethereum state")?;
-    update_l1(backend, initial_state, &eth_client.l1_block_metrics, chain_id)?;
+    // Get and store the latest confirmed verified state
+    let initial_block = event_block(eth_client, confirmed_head(eth_client, config.confirmations).await?).await?;
+    let initial_state = eth_client.get_state_at(&initial_block).await.context("Getting initial ethereum state")?;
+    let unverified = update_l1(backend, initial_state, &eth_client.l1_block_metrics)?;
+    backend.write_l1_last_confirmed_event_block(initial_block).context("Storing the L1 state update progress")?;
 
-    // Listen to LogStateUpdate (0x77552641) update and send changes continusly
-    listen_and_update_state(eth_client, backend, &eth_client.l1_block_metrics, chain_id)
+    // Poll LogStateUpdate (0x77552641) and store the changes continuously
+    listen_and_update_state(eth_client, backend, &eth_client.l1_block_metrics, config, unverified)
         .await
-        .context("Subscribing to the LogStateUpdate event")?;
+        .context("Following the LogStateUpdate event")
+        .inspect_err(|err| error_reporting::report(Severity::Critical, "l1.listener", format_args!("{err:#}")))?;
 
     Ok(())
 }
@@ -103,11 +307,13 @@ mod eth_client_event_subscription_test {
     use std::{sync::Arc, time::Duration};
 
     use alloy::{providers::ProviderBuilder, sol};
+    use dc_db::testing::temp_database;
     use dc_db::DatabaseService;
     use dc_metrics::MetricsService;
     use dp_block::chain_config::ChainConfig;
-    use dp_convert::ToFelt;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, Header};
     use rstest::*;
+    use starknet_types_core::felt::Felt;
     use tempfile::TempDir;
     use url::Url;
 
@@ -158,28 +364,22 @@ mod eth_client_event_subscription_test {
 
         // Set up metrics service
         let prometheus_service = MetricsService::new(true, false, 9615).unwrap();
-        let l1_block_metrics = L1BlockMetrics::register(&prometheus_service.registry()).unwrap();
 
         let rpc_url: Url = "http://localhost:8545".parse().expect("issue while parsing");
-        let provider = ProviderBuilder::new().on_http(rpc_url);
+        let provider = ProviderBuilder::new().on_http(rpc_url.clone());
 
         let contract = DummyContract::deploy(provider.clone()).await.unwrap();
-        let core_contract = StarknetCoreContract::new(*contract.address(), provider.clone());
 
-        let eth_client =
-            EthereumClient { provider: Arc::new(provider), l1_core_contract: core_contract.clone(), l1_block_metrics };
+        let eth_client = EthereumClient::new(vec![rpc_url], *contract.address(), prometheus_service.registry())
+            .await
+            .expect("Failed to create the ethereum client");
 
         // Start listening for state updates
         let listen_handle = {
             let db = Arc::clone(&db);
             tokio::spawn(async move {
-                listen_and_update_state(
-                    &eth_client,
-                    db.backend(),
-                    &eth_client.l1_block_metrics,
-                    chain_info.chain_id.clone().to_felt(),
-                )
-                .await
+                let config = L1StateSyncConfig { confirmations: 0, poll_interval: Duration::from_millis(100) };
+                listen_and_update_state(&eth_client, db.backend(), &eth_client.l1_block_metrics, &config, None).await
             })
         };
 
@@ -196,4 +396,211 @@ mod eth_client_event_subscription_test {
         listen_handle.abort();
         assert_eq!(block_in_db, Some(L2_BLOCK_NUMBER), "Block in DB does not match expected L2 block number");
     }
+
+    /// `LogStateUpdate` events at given L1 blocks.
+    struct MockStateUpdateSource {
+        head: u64,
+        state_updates: Vec<(u64, L1StateUpdate)>,
+        /// The blocks from this one are on another fork than the ones of a source without it.
+        fork_from_block: Option<u64>,
+    }
+
+    fn state_update(block_number: u64) -> L1StateUpdate {
+        L1StateUpdate { block_number, global_root: Felt::from(block_number), block_hash: Felt::from(block_number) }
+    }
+
+    #[async_trait::async_trait]
+    impl L1BlockSource for MockStateUpdateSource {
+        async fn get_latest_block_number(&self) -> anyhow::Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn get_block_hash(&self, block_number: u64) -> anyhow::Result<Option<B256>> {
+            if block_number > self.head {
+                return Ok(None);
+            }
+            let mut hash = B256::from(alloy::primitives::U256::from(block_number));
+            if self.fork_from_block.is_some_and(|fork_from_block| block_number >= fork_from_block) {
+                hash.0[0] = 0xff;
+            }
+            Ok(Some(hash))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl L1StateUpdateSource for MockStateUpdateSource {
+        async fn get_state_at(&self, l1_block: &LastSyncedEventBlock) -> anyhow::Result<L1StateUpdate> {
+            let (_, state_update) = self
+                .state_updates
+                .iter()
+                .rev()
+                .find(|(block, _)| *block <= l1_block.block_number)
+                .context("No state update")?;
+            Ok(state_update.clone())
+        }
+
+        async fn get_state_updates(&self, from_block: u64, to_block: u64) -> anyhow::Result<Vec<L1StateUpdate>> {
+            Ok(self
+                .state_updates
+                .iter()
+                .filter(|(block, _)| (from_block..=to_block).contains(block))
+                .map(|(_, state_update)| state_update.clone())
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_state_is_rolled_back_after_a_reorg() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let metrics = L1BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        let config = L1StateSyncConfig { confirmations: 5, ..Default::default() };
+
+        // The state update of block 98 is not confirmed yet.
+        let source = MockStateUpdateSource {
+            head: 100,
+            state_updates: vec![(80, state_update(600_000)), (93, state_update(600_010)), (98, state_update(600_020))],
+            fork_from_block: None,
+        };
+        let next_block = update_confirmed_state(backend, &source, &metrics, &config, &mut None, 0).await.unwrap();
+        assert_eq!(next_block, 96);
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), Some(600_010));
+        assert_eq!(backend.get_l1_last_confirmed_event_block().unwrap().unwrap().block_number, 95);
+
+        // L1 reorgs from block 92: the state update of block 93 now lands in block 102, which is not confirmed yet.
+        let source = MockStateUpdateSource {
+            head: 105,
+            state_updates: vec![(80, state_update(600_000)), (102, state_update(600_010))],
+            fork_from_block: Some(92),
+        };
+        let next_block =
+            update_confirmed_state(backend, &source, &metrics, &config, &mut None, next_block).await.unwrap();
+        assert_eq!(next_block, 101);
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), Some(600_000));
+        let last_synced = backend.get_l1_last_confirmed_event_block().unwrap().unwrap();
+        assert_eq!(last_synced.block_number, 100);
+        assert_eq!(Some(B256::from(last_synced.block_hash)), source.get_block_hash(100).await.unwrap());
+
+        // Once confirmed again, the state update is applied.
+        let source = MockStateUpdateSource { head: 107, ..source };
+        let next_block =
+            update_confirmed_state(backend, &source, &metrics, &config, &mut None, next_block).await.unwrap();
+        assert_eq!(next_block, 103);
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), Some(600_010));
+    }
+
+    /// Stores blocks `0..n_blocks`. Block `n` has the hash and state root of `state_update(n)`.
+    async fn synced_backend(temp_dir: &TempDir, n_blocks: u64) -> DatabaseService {
+        let db =
+            DatabaseService::new(temp_dir.path(), None, false, Arc::new(ChainConfig::test_config())).await.unwrap();
+        for block_number in 0..n_blocks {
+            store_synced_block(db.backend(), block_number);
+        }
+        db
+    }
+
+    fn store_synced_block(backend: &DeoxysBackend, block_number: u64) {
+        let header = Header { block_number, global_state_root: Felt::from(block_number), ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(
+                header,
+                vec![],
+                Felt::from(block_number),
+            )),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, Default::default(), vec![]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_update_matching_the_synced_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = synced_backend(&temp_dir, 3).await;
+        let metrics = L1BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+
+        update_l1(db.backend(), state_update(1), &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(1));
+        update_l1(db.backend(), state_update(2), &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(2));
+        assert_eq!(metrics.l1_state_update_mismatch.get(), 0.0);
+
+        // Not synced yet, so it is checked later.
+        assert_eq!(update_l1(db.backend(), state_update(10), &metrics).unwrap(), Some(state_update(10)));
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(10));
+
+        // The confirmed block never goes back.
+        update_l1(db.backend(), state_update(2), &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(10));
+    }
+
+    #[tokio::test]
+    async fn test_state_update_not_matching_the_synced_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = synced_backend(&temp_dir, 3).await;
+        let metrics = L1BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        update_l1(db.backend(), state_update(1), &metrics).unwrap();
+
+        let wrong_root = L1StateUpdate { global_root: Felt::from(0x1234), ..state_update(2) };
+        update_l1(db.backend(), wrong_root, &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(1));
+        assert_eq!(metrics.l1_state_update_mismatch.get(), 1.0);
+
+        let wrong_hash = L1StateUpdate { block_hash: Felt::from(0x1234), ..state_update(2) };
+        update_l1(db.backend(), wrong_hash, &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(1));
+
+        update_l1(db.backend(), state_update(2), &metrics).unwrap();
+        assert_eq!(db.backend().get_l1_last_confirmed_block().unwrap(), Some(2));
+        assert_eq!(metrics.l1_state_update_mismatch.get(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_state_update_is_verified_once_its_block_is_synced() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = synced_backend(&temp_dir, 3).await;
+        let backend = db.backend();
+        let metrics = L1BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+
+        // L1 verified block 4 before it is synced.
+        let wrong_root = L1StateUpdate { global_root: Felt::from(0x1234), ..state_update(4) };
+        let unverified = update_l1(backend, wrong_root.clone(), &metrics).unwrap();
+        assert_eq!(unverified, Some(wrong_root.clone()));
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), Some(4));
+        assert_eq!(
+            verify_applied_state_update(backend, wrong_root.clone(), &metrics).unwrap(),
+            Some(wrong_root.clone())
+        );
+
+        // The sync stores blocks 3 and 4, which do not match: the L1 state was never verified.
+        store_synced_block(backend, 3);
+        store_synced_block(backend, 4);
+        assert_eq!(verify_applied_state_update(backend, wrong_root, &metrics).unwrap(), None);
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), None);
+        assert_eq!(metrics.l1_state_update_mismatch.get(), 1.0);
+
+        // A matching state update is applied as soon as it is checked.
+        assert_eq!(update_l1(backend, state_update(4), &metrics).unwrap(), None);
+        assert_eq!(backend.get_l1_last_confirmed_block().unwrap(), Some(4));
+        assert_eq!(metrics.l1_state_update_mismatch.get(), 0.0);
+    }
+
+    #[test]
+    fn test_logs_of_other_contracts_are_ignored() {
+        let core_contract = Address::repeat_byte(0x11);
+        let event = StarknetCoreContract::LogStateUpdate {
+            globalRoot: alloy::primitives::U256::from(3),
+            blockNumber: I256::from_dec_str("2").unwrap(),
+            blockHash: alloy::primitives::U256::from(4),
+        };
+        let log = |address| Log {
+            inner: alloy::primitives::Log { address, data: event.encode_log_data() },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            decode_state_update_log(&log(core_contract), core_contract).unwrap(),
+            Some(L1StateUpdate { block_number: 2, global_root: Felt::from(3), block_hash: Felt::from(4) })
+        );
+        assert_eq!(decode_state_update_log(&log(Address::repeat_byte(0x22)), core_contract).unwrap(), None);
+    }
 }
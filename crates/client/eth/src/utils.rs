@@ -1,7 +1,8 @@
 use crate::client::StarknetCoreContract;
 use crate::state_update::L1StateUpdate;
 use alloy::primitives::{I256, U256};
-use anyhow::bail;
+use anyhow::{bail, Context};
+use dp_convert::felt_from_be_bytes;
 use starknet_types_core::felt::Felt;
 
 pub fn convert_log_state_update(
@@ -19,13 +20,9 @@ pub fn convert_log_state_update(
     Ok(L1StateUpdate { block_number, global_root, block_hash })
 }
 
+/// Fails on the values larger than [`Felt::MAX`], they are not reduced modulo the prime.
 pub fn u256_to_felt(u256: U256) -> anyhow::Result<Felt> {
-    let binding = u256.to_be_bytes_vec();
-    let bytes = binding.as_slice();
-    let mut bytes_array = [0u8; 32];
-    bytes_array.copy_from_slice(bytes);
-    let felt = Felt::from_bytes_be(&bytes_array);
-    Ok(felt)
+    felt_from_be_bytes(&u256.to_be_bytes::<32>()).with_context(|| format!("{u256:#x} does not fit in a felt"))
 }
 
 pub fn trim_hash(hash: &Felt) -> String {
@@ -83,6 +80,16 @@ mod eth_client_conversion_tests {
         assert_eq!(result, expected, "u256_to_felt failed for input: {}", input);
     }
 
+    #[test]
+    fn u256_to_felt_rejects_values_above_the_prime() {
+        // The prime, 2**251 + 17 * 2**192 + 1.
+        let prime =
+            U256::from_str_radix("800000000000011000000000000000000000000000000000000000000000001", 16).unwrap();
+        assert_eq!(u256_to_felt(prime - U256::from(1)).unwrap(), Felt::MAX);
+        assert!(u256_to_felt(prime).is_err());
+        assert!(u256_to_felt(U256::MAX).is_err());
+    }
+
     #[rstest]
     #[case(30000000000000, "0x1b48eb...57e000")]
     #[case(12345678123456789, "0x2bdc54...0f5915")]
@@ -1,4 +1,9 @@
-use crate::{blockifier_state_adapter::BlockifierStateAdapter, Error};
+use crate::{
+    blockifier_state_adapter::BlockifierStateAdapter,
+    class_cache::GlobalClassCache,
+    state_cache::{StateReadCache, StateReadMetrics, StateReadStats},
+    Error,
+};
 use blockifier::{
     blockifier::{
         config::TransactionExecutorConfig, stateful_validator::StatefulValidator,
@@ -8,10 +13,11 @@ use blockifier::{
     state::cached_state::CachedState,
 };
 use dc_db::{db_block_id::DbBlockId, DeoxysBackend};
-use dp_block::{header::L1DataAvailabilityMode, DeoxysMaybePendingBlockInfo};
+use dp_block::{header::L1DataAvailabilityMode, BlockN, DeoxysMaybePendingBlockInfo};
+use dp_convert::TryFromFelt;
 use starknet_api::{
     block::{BlockNumber, BlockTimestamp},
-    core::Nonce,
+    core::{ContractAddress, Nonce},
 };
 use starknet_types_core::felt::Felt;
 use std::sync::Arc;
@@ -20,6 +26,10 @@ pub struct ExecutionContext {
     pub(crate) backend: Arc<DeoxysBackend>,
     pub(crate) block_context: BlockContext,
     pub(crate) db_id: DbBlockId,
+    /// Shared by the cached states of all the executions made within this context.
+    pub(crate) state_cache: Arc<StateReadCache>,
+    /// The converted classes, shared with the other contexts.
+    pub(crate) class_cache: Arc<GlobalClassCache>,
 }
 
 impl ExecutionContext {
@@ -40,21 +50,29 @@ impl ExecutionContext {
     }
 
     pub fn init_cached_state(&self) -> CachedState<BlockifierStateAdapter> {
-        let on_top_of = match self.db_id {
-            DbBlockId::Pending => Some(DbBlockId::Pending),
-            DbBlockId::BlockN(block_n) => {
-                // We exec on top of the previous block. None means we are executing genesis.
-                block_n.checked_sub(1).map(DbBlockId::BlockN)
-            }
-        };
-
         CachedState::new(BlockifierStateAdapter::new(
             Arc::clone(&self.backend),
             self.block_context.block_info().block_number.0,
-            on_top_of,
+            Arc::clone(&self.state_cache),
+            Arc::clone(&self.class_cache),
         ))
     }
 
+    /// Uses `class_cache` instead of [`GlobalClassCache::global`].
+    pub fn with_class_cache(self, class_cache: Arc<GlobalClassCache>) -> Self {
+        Self { class_cache, ..self }
+    }
+
+    /// Exports the state reads of the executions made within this context as metrics.
+    pub fn with_metrics(self, metrics: StateReadMetrics) -> Self {
+        let state_cache = Arc::new(StateReadCache::new(self.state_cache.on_top_of_block_id(), Some(metrics)));
+        Self { state_cache, ..self }
+    }
+
+    pub fn state_read_stats(&self) -> StateReadStats {
+        self.state_cache.stats()
+    }
+
     pub fn new(backend: Arc<DeoxysBackend>, block_info: &DeoxysMaybePendingBlockInfo) -> Result<Self, Error> {
         let (db_id, protocol_version, block_number, block_timestamp, sequencer_address, l1_gas_price, l1_da_mode) =
             match block_info {
@@ -70,7 +88,7 @@ impl ExecutionContext {
                     block.header.l1_da_mode,
                 ),
                 DeoxysMaybePendingBlockInfo::NotPending(block) => (
-                    DbBlockId::BlockN(block.header.block_number),
+                    DbBlockId::BlockN(BlockN(block.header.block_number)),
                     block.header.protocol_version,
                     block.header.block_number,
                     block.header.block_timestamp,
@@ -80,7 +98,8 @@ impl ExecutionContext {
                 ),
             };
 
-        let versioned_constants = backend.chain_config().exec_constants_by_protocol_version(protocol_version)?;
+        // Historical blocks are re-executed with the constants of their own protocol version, not the latest ones.
+        let versioned_constants = backend.chain_config().exec_constants_by_protocol_version(protocol_version)?.clone();
         let chain_info = ChainInfo {
             chain_id: backend.chain_config().chain_id.clone(),
             fee_token_addresses: FeeTokenAddresses {
@@ -91,13 +110,20 @@ impl ExecutionContext {
         let block_info = blockifier::blockifier::block::BlockInfo {
             block_number: BlockNumber(block_number),
             block_timestamp: BlockTimestamp(block_timestamp),
-            sequencer_address: sequencer_address
-                .try_into()
+            sequencer_address: ContractAddress::try_from_felt(sequencer_address)
                 .map_err(|_| Error::InvalidSequencerAddress(sequencer_address))?,
             gas_prices: (&l1_gas_price).into(),
             use_kzg_da: l1_da_mode == L1DataAvailabilityMode::Blob,
         };
 
+        let on_top_of = match db_id {
+            DbBlockId::Pending => Some(DbBlockId::Pending),
+            DbBlockId::BlockN(block_n) => {
+                // We exec on top of the previous block. None means we are executing genesis.
+                block_n.parent().map(DbBlockId::BlockN)
+            }
+        };
+
         Ok(ExecutionContext {
             block_context: BlockContext::new(
                 block_info,
@@ -106,7 +132,169 @@ impl ExecutionContext {
                 backend.chain_config().bouncer_config.clone(),
             ),
             db_id,
+            state_cache: Arc::new(StateReadCache::new(on_top_of, None)),
+            class_cache: GlobalClassCache::global(),
             backend,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockifier::fee::fee_utils::{calculate_l1_gas_by_vm_usage, get_fee_by_gas_vector};
+    use blockifier::state::state_api::StateReader;
+    use blockifier::transaction::objects::FeeType;
+    use cairo_vm::types::builtin_name::BuiltinName;
+    use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
+    use dc_db::testing::temp_database;
+    use dc_metrics::MetricsService;
+    use dp_block::{
+        header::{GasPrices, PendingHeader},
+        DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysPendingBlockInfo, Header, StarknetVersion,
+    };
+    use dp_state_update::{ContractStorageDiffItem, StateDiff, StorageEntry};
+    use starknet_api::{
+        core::{ContractAddress, PatriciaKey},
+        state::StorageKey,
+    };
+
+    fn block_info(protocol_version: StarknetVersion) -> DeoxysMaybePendingBlockInfo {
+        let header = Header {
+            block_number: 1,
+            protocol_version,
+            l1_gas_price: GasPrices { eth_l1_gas_price: 10, ..Default::default() },
+            ..Default::default()
+        };
+        DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(header, vec![], Felt::ONE))
+    }
+
+    /// The fee of a transaction applying 100 ECDSA builtins, re-executed in a block of `protocol_version`.
+    fn ecdsa_fee(backend: &Arc<DeoxysBackend>, protocol_version: StarknetVersion) -> (u128, u128) {
+        let context = ExecutionContext::new(Arc::clone(backend), &block_info(protocol_version)).unwrap();
+        let resources = ExecutionResources {
+            n_steps: 0,
+            n_memory_holes: 0,
+            builtin_instance_counter: [(BuiltinName::ecdsa, 100)].into(),
+        };
+        let gas = calculate_l1_gas_by_vm_usage(context.block_context.versioned_constants(), &resources, 0).unwrap();
+        let l1_gas = gas.l1_gas;
+        let fee = get_fee_by_gas_vector(context.block_context.block_info(), gas, &FeeType::Eth);
+        (l1_gas, fee.0)
+    }
+
+    #[tokio::test]
+    async fn historical_blocks_use_their_versioned_constants() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        // The ECDSA builtin costs 10.24 gas per application in 0.13.0, and half of it since 0.13.1.
+        assert_eq!(ecdsa_fee(&backend, StarknetVersion::STARKNET_VERSION_0_13_0), (1024, 10240));
+        assert_eq!(ecdsa_fee(&backend, StarknetVersion::STARKNET_VERSION_0_13_1), (512, 5120));
+        assert_eq!(ecdsa_fee(&backend, StarknetVersion::LATEST_SUPPORTED), (512, 5120));
+
+        // Versions without their own constants use the ones of the nearest older version.
+        assert_eq!(ecdsa_fee(&backend, StarknetVersion::new(0, 13, 0, 5)), (1024, 10240));
+        assert!(matches!(
+            ExecutionContext::new(Arc::clone(&backend), &block_info(StarknetVersion::STARKNET_VERSION_0_11_1)),
+            Err(Error::UnsupportedProtocolVersion(_))
+        ));
+    }
+
+    const CONTRACT: Felt = Felt::from_hex_unchecked("0x100");
+
+    fn storage_diff(value: u64) -> StateDiff {
+        StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: CONTRACT,
+                storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::from(value) }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+    }
+
+    /// Block 0 sets the storage of [`CONTRACT`] to 7, and the pending block on top of it to 8.
+    async fn backend_with_storage() -> (tempfile::TempDir, Arc<DeoxysBackend>) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let header = Header { protocol_version: StarknetVersion::LATEST_SUPPORTED, ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(header, vec![], Felt::ONE)),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, storage_diff(7), vec![]).unwrap();
+
+        let header = PendingHeader {
+            parent_block_hash: Felt::ONE,
+            protocol_version: StarknetVersion::LATEST_SUPPORTED,
+            ..Default::default()
+        };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::Pending(DeoxysPendingBlockInfo::new(header, vec![])),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, storage_diff(8), vec![]).unwrap();
+
+        (temp_dir, backend)
+    }
+
+    fn read_storage(context: &ExecutionContext) -> Felt {
+        let address = ContractAddress(PatriciaKey::try_from(CONTRACT).unwrap());
+        let key = StorageKey(PatriciaKey::try_from(Felt::ONE).unwrap());
+        context.init_cached_state().get_storage_at(address, key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn repeated_calls_are_served_by_the_state_cache() {
+        let (_temp_dir, backend) = backend_with_storage().await;
+        let context =
+            ExecutionContext::new(Arc::clone(&backend), &block_info(StarknetVersion::LATEST_SUPPORTED)).unwrap();
+
+        // No class is deployed at the address: the call fails after reading the state.
+        context.call_contract(&CONTRACT, &Felt::TWO, &[]).unwrap_err();
+        let first = context.state_read_stats();
+        assert!(first.state_reads > 0);
+        assert_eq!(first.cache_hits, 0);
+
+        context.call_contract(&CONTRACT, &Felt::TWO, &[]).unwrap_err();
+        let second = context.state_read_stats();
+        assert_eq!(second.state_reads, first.state_reads);
+        assert_eq!(second.cache_misses, first.cache_misses);
+        assert!(second.cache_hits > 0);
+
+        // Every execution starts from a new cached state, they all share the cache of the context.
+        assert_eq!(read_storage(&context), Felt::from(7));
+        let reads = context.state_read_stats().state_reads;
+        assert_eq!(read_storage(&context), Felt::from(7));
+        assert_eq!(context.state_read_stats().state_reads, reads);
+    }
+
+    #[tokio::test]
+    async fn state_cache_is_scoped_to_the_block() {
+        let (_temp_dir, backend) = backend_with_storage().await;
+        let metrics = StateReadMetrics::register(&MetricsService::new(true, false, 0).unwrap().registry()).unwrap();
+
+        let block_1 = ExecutionContext::new(Arc::clone(&backend), &block_info(StarknetVersion::LATEST_SUPPORTED))
+            .unwrap()
+            .with_metrics(metrics.clone());
+        assert_eq!(read_storage(&block_1), Felt::from(7));
+
+        // The pending block overlays its own state diff, whatever the other contexts have cached.
+        let pending_info = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        let pending = ExecutionContext::new(Arc::clone(&backend), &pending_info).unwrap().with_metrics(metrics.clone());
+        assert_eq!(read_storage(&pending), Felt::from(8));
+        assert_eq!(read_storage(&pending), Felt::from(8));
+        assert_eq!(read_storage(&block_1), Felt::from(7));
+
+        assert_eq!(block_1.state_read_stats(), StateReadStats { cache_hits: 1, cache_misses: 1, state_reads: 1 });
+        assert_eq!(pending.state_read_stats(), StateReadStats { cache_hits: 1, cache_misses: 1, state_reads: 1 });
+        assert_eq!(metrics.cache_hits.get(), 2);
+        assert_eq!(metrics.cache_misses.get(), 2);
+        assert_eq!(metrics.state_reads.get(), 2);
+    }
+}
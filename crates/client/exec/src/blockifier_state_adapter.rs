@@ -11,19 +11,33 @@ use starknet_api::state::StorageKey;
 use starknet_core::types::Felt;
 use std::sync::Arc;
 
+use crate::class_cache::GlobalClassCache;
+use crate::state_cache::StateReadCache;
+
 /// Adapter for the db queries made by blockifier.
 /// There is no actual mutable logic here - when using block production, the actual key value
 /// changes in db are evaluated at the end only from the produced state diff.
 pub struct BlockifierStateAdapter {
     backend: Arc<DeoxysBackend>,
-    /// When this value is None, we are executing the genesis block.
-    pub on_top_of_block_id: Option<DbBlockId>,
+    /// The reads are made on top of the block of the cache.
+    cache: Arc<StateReadCache>,
+    class_cache: Arc<GlobalClassCache>,
     pub block_number: u64,
 }
 
 impl BlockifierStateAdapter {
-    pub fn new(backend: Arc<DeoxysBackend>, block_number: u64, on_top_of_block_id: Option<DbBlockId>) -> Self {
-        Self { backend, on_top_of_block_id, block_number }
+    pub fn new(
+        backend: Arc<DeoxysBackend>,
+        block_number: u64,
+        cache: Arc<StateReadCache>,
+        class_cache: Arc<GlobalClassCache>,
+    ) -> Self {
+        Self { backend, cache, class_cache, block_number }
+    }
+
+    /// When this value is None, we are executing the genesis block.
+    pub fn on_top_of_block_id(&self) -> Option<DbBlockId> {
+        self.cache.on_top_of_block_id()
     }
 }
 
@@ -42,6 +56,7 @@ impl StateReader for BlockifierStateAdapter {
                 return Ok(Felt::ZERO);
             }
 
+            self.cache.record_state_read();
             return self
                 .backend
                 .get_block_hash(&BlockId::Number(requested_block_number))
@@ -54,91 +69,123 @@ impl StateReader for BlockifierStateAdapter {
                 .ok_or(StateError::OldBlockHashNotProvided);
         }
 
-        let Some(on_top_of_block_id) = self.on_top_of_block_id else { return Ok(Felt::ZERO) };
-
-        Ok(self
-            .backend
-            .get_contract_storage_at(&on_top_of_block_id, &contract_address.to_felt(), &key.to_felt())
-            .map_err(|err| {
-                log::warn!(
-                    "Failed to retrieve storage value for contract {contract_address:#?} at key {key:#?}: {err:#}"
-                );
-                StateError::StateReadError(format!(
-                    "Failed to retrieve storage value for contract {contract_address:#?} at key {key:#?}",
-                ))
-            })?
-            .unwrap_or(Felt::ZERO))
+        let Some(on_top_of_block_id) = self.on_top_of_block_id() else { return Ok(Felt::ZERO) };
+
+        self.cache.storage((contract_address, key), || {
+            Ok(self
+                .backend
+                .get_contract_storage_at(&on_top_of_block_id, &contract_address.to_felt(), &key.to_felt())
+                .map_err(|err| {
+                    log::warn!(
+                        "Failed to retrieve storage value for contract {contract_address:#?} at key {key:#?}: {err:#}"
+                    );
+                    StateError::StateReadError(format!(
+                        "Failed to retrieve storage value for contract {contract_address:#?} at key {key:#?}",
+                    ))
+                })?
+                .unwrap_or(Felt::ZERO))
+        })
     }
 
     fn get_nonce_at(&self, contract_address: ContractAddress) -> StateResult<Nonce> {
         log::debug!("get_nonce_at for {:#?}", contract_address);
-        let Some(on_top_of_block_id) = self.on_top_of_block_id else { return Ok(Nonce::default()) };
+        let Some(on_top_of_block_id) = self.on_top_of_block_id() else { return Ok(Nonce::default()) };
 
-        Ok(Nonce(
-            self.backend
-                .get_contract_nonce_at(&on_top_of_block_id, &contract_address.to_felt())
-                .map_err(|err| {
-                    log::warn!("Failed to retrieve nonce for contract {contract_address:#?}: {err:#}");
-                    StateError::StateReadError(format!("Failed to retrieve nonce for contract {contract_address:#?}",))
-                })?
-                .unwrap_or(Felt::ZERO),
-        ))
+        self.cache.nonce(contract_address, || {
+            Ok(Nonce(
+                self.backend
+                    .get_contract_nonce_at(&on_top_of_block_id, &contract_address.to_felt())
+                    .map_err(|err| {
+                        log::warn!("Failed to retrieve nonce for contract {contract_address:#?}: {err:#}");
+                        StateError::StateReadError(format!(
+                            "Failed to retrieve nonce for contract {contract_address:#?}",
+                        ))
+                    })?
+                    .unwrap_or(Felt::ZERO),
+            ))
+        })
     }
 
     fn get_class_hash_at(&self, contract_address: ContractAddress) -> StateResult<ClassHash> {
         log::debug!("get_class_hash_at for {:#?}", contract_address);
-        let Some(on_top_of_block_id) = self.on_top_of_block_id else { return Ok(ClassHash::default()) };
+        let Some(on_top_of_block_id) = self.on_top_of_block_id() else { return Ok(ClassHash::default()) };
 
         // Note that blockifier is fine with us returning ZERO as a class_hash if it is not found, they do the check on their end after
-        Ok(ClassHash(
-            self.backend
-                .get_contract_class_hash_at(&on_top_of_block_id, &contract_address.to_felt())
-                .map_err(|err| {
-                    StateError::StateReadError(format!(
-                        "Failed to retrieve class hash for contract {:#}: {:#}",
-                        contract_address.0.key(),
-                        err
-                    ))
-                })?
-                .unwrap_or_default(),
-        ))
+        self.cache.class_hash(contract_address, || {
+            Ok(ClassHash(
+                self.backend
+                    .get_contract_class_hash_at(&on_top_of_block_id, &contract_address.to_felt())
+                    .map_err(|err| {
+                        StateError::StateReadError(format!(
+                            "Failed to retrieve class hash for contract {:#}: {:#}",
+                            contract_address.0.key(),
+                            err
+                        ))
+                    })?
+                    .unwrap_or_default(),
+            ))
+        })
     }
 
     fn get_compiled_contract_class(&self, class_hash: ClassHash) -> StateResult<ContractClass> {
         log::debug!("get_compiled_contract_class for {:#?}", class_hash);
 
-        let Some(on_top_of_block_id) = self.on_top_of_block_id else {
+        let Some(on_top_of_block_id) = self.on_top_of_block_id() else {
             return Err(StateError::UndeclaredClassHash(class_hash));
         };
 
-        let Some((_class_info, compiled_class)) =
-            self.backend.get_class(&on_top_of_block_id, &class_hash.to_felt()).map_err(|err| {
-                log::warn!("Failed to retrieve compiled class {class_hash:#}: {err:#}");
-                StateError::StateReadError(format!("Failed to retrieve compiled class {class_hash:#}"))
-            })?
-        else {
-            return Err(StateError::UndeclaredClassHash(class_hash));
-        };
+        self.cache
+            .compiled_class(class_hash, || {
+                // The class must be declared on top of this block, whether or not it is converted already. Only its
+                // compiled class hash is read, the class itself is read when it is not converted yet.
+                let Some(compiled_class_hash) = self
+                    .backend
+                    .get_class_compiled_class_hash(&on_top_of_block_id, &class_hash.to_felt())
+                    .map_err(|err| {
+                        log::warn!("Failed to retrieve compiled class hash {class_hash:#}: {err:#}");
+                        StateError::StateReadError(format!("Failed to retrieve compiled class hash {class_hash:#}"))
+                    })?
+                else {
+                    return Ok(None);
+                };
 
-        to_blockifier_class(compiled_class).map_err(StateError::ProgramError)
+                self.class_cache
+                    .get_or_convert(class_hash, compiled_class_hash, || {
+                        let (_class_info, compiled_class) = self
+                            .backend
+                            .get_class(&on_top_of_block_id, &class_hash.to_felt())
+                            .map_err(|err| {
+                                log::warn!("Failed to retrieve compiled class {class_hash:#}: {err:#}");
+                                StateError::StateReadError(format!("Failed to retrieve compiled class {class_hash:#}"))
+                            })?
+                            .ok_or(StateError::UndeclaredClassHash(class_hash))?;
+
+                        to_blockifier_class(compiled_class).map_err(StateError::ProgramError)
+                    })
+                    .map(Some)
+            })?
+            .ok_or(StateError::UndeclaredClassHash(class_hash))
     }
 
     fn get_compiled_class_hash(&self, class_hash: ClassHash) -> StateResult<CompiledClassHash> {
         log::debug!("get_compiled_class_hash for {:#?}", class_hash);
 
-        let Some(on_top_of_block_id) = self.on_top_of_block_id else {
-            return Err(StateError::UndeclaredClassHash(class_hash));
-        };
-        let Some(class_info) =
-            self.backend.get_class_info(&on_top_of_block_id, &class_hash.to_felt()).map_err(|err| {
-                log::warn!("Failed to retrieve compiled class hash {class_hash:#}: {err:#}");
-                StateError::StateReadError(format!("Failed to retrieve compiled class hash {class_hash:#}",))
-            })?
-        else {
+        let Some(on_top_of_block_id) = self.on_top_of_block_id() else {
             return Err(StateError::UndeclaredClassHash(class_hash));
         };
 
-        Ok(CompiledClassHash(class_info.compiled_class_hash))
+        self.cache
+            .compiled_class_hash(class_hash, || {
+                let compiled_class_hash = self
+                    .backend
+                    .get_class_compiled_class_hash(&on_top_of_block_id, &class_hash.to_felt())
+                    .map_err(|err| {
+                        log::warn!("Failed to retrieve compiled class hash {class_hash:#}: {err:#}");
+                        StateError::StateReadError(format!("Failed to retrieve compiled class hash {class_hash:#}",))
+                    })?;
+                Ok(compiled_class_hash.map(CompiledClassHash))
+            })?
+            .ok_or(StateError::UndeclaredClassHash(class_hash))
     }
 }
 
@@ -152,9 +199,88 @@ fn block_hash_storage_check_range(chain_id: &ChainId, current_block: u64, to_che
 
 #[cfg(test)]
 mod tests {
+    use blockifier::test_utils::contracts::FeatureContract;
+    use blockifier::test_utils::CairoVersion;
+    use dc_db::testing::temp_database;
+    use dp_block::{BlockN, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_class::{ClassInfo, ConvertedClass, ToCompiledClass};
+    use dp_state_update::StateDiff;
     use starknet_api::core::ChainId;
+    use starknet_core::types::contract::legacy::LegacyContractClass;
+
+    use super::*;
+    use crate::class_cache::ClassCacheStats;
+
+    const CLASS_HASH: Felt = Felt::from_hex_unchecked("0xc1a55");
+
+    /// The Cairo 0 account of the blockifier test contracts, declared by block 1.
+    fn declared_class() -> ConvertedClass {
+        let raw_class = FeatureContract::AccountWithoutValidations(CairoVersion::Cairo0).get_raw_class();
+        let class: LegacyContractClass = serde_json::from_str(&raw_class).unwrap();
+        let class = class.compress().unwrap();
+        let compiled = class.compile().unwrap();
+        let class_info = ClassInfo {
+            contract_class: dp_class::ContractClass::Legacy(class.into()),
+            compiled_class_hash: Felt::ZERO,
+            block_number: Some(1),
+        };
+        ConvertedClass { class_infos: (CLASS_HASH, class_info), class_compiled: (CLASS_HASH, compiled) }
+    }
 
-    use super::block_hash_storage_check_range;
+    async fn backend_with_class() -> (tempfile::TempDir, Arc<DeoxysBackend>) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        for block_n in 0..2u64 {
+            let header = Header { block_number: block_n, ..Default::default() };
+            let block = DeoxysBlock::new(
+                DeoxysBlockInfo::new(header, vec![], Felt::from(block_n)),
+                DeoxysBlockInner::new(vec![], vec![]),
+            );
+            let (state_diff, classes) = if block_n == 1 {
+                (StateDiff::new(vec![], vec![CLASS_HASH], vec![], vec![], vec![], vec![]), vec![declared_class()])
+            } else {
+                (StateDiff::default(), vec![])
+            };
+            backend.store_block(block.into(), state_diff, classes).unwrap();
+        }
+        (temp_dir, backend)
+    }
+
+    /// An adapter executing the block following `on_top_of`, with a state cache of its own.
+    fn adapter(
+        backend: &Arc<DeoxysBackend>,
+        on_top_of: u64,
+        class_cache: &Arc<GlobalClassCache>,
+    ) -> BlockifierStateAdapter {
+        let cache = Arc::new(StateReadCache::new(Some(DbBlockId::BlockN(BlockN(on_top_of))), None));
+        BlockifierStateAdapter::new(Arc::clone(backend), on_top_of + 1, cache, Arc::clone(class_cache))
+    }
+
+    #[tokio::test]
+    async fn converted_classes_are_shared_by_the_adapters() {
+        let (_temp_dir, backend) = backend_with_class().await;
+        let class_cache = Arc::new(GlobalClassCache::new(8));
+        let class_hash = ClassHash(CLASS_HASH);
+
+        adapter(&backend, 1, &class_cache).get_compiled_contract_class(class_hash).unwrap();
+        assert_eq!(class_cache.stats(), ClassCacheStats { hits: 0, misses: 1, evictions: 0 });
+
+        // Another execution reuses the converted class, only its compiled class hash is read from the database.
+        let on_top_of_1 = adapter(&backend, 1, &class_cache);
+        on_top_of_1.get_compiled_contract_class(class_hash).unwrap();
+        assert_eq!(class_cache.stats(), ClassCacheStats { hits: 1, misses: 1, evictions: 0 });
+        assert_eq!(on_top_of_1.get_compiled_class_hash(class_hash).unwrap(), CompiledClassHash(Felt::ZERO));
+
+        // The class is not declared on top of block 0, although it is converted already.
+        let on_top_of_0 = adapter(&backend, 0, &class_cache);
+        assert!(matches!(
+            on_top_of_0.get_compiled_contract_class(class_hash),
+            Err(StateError::UndeclaredClassHash(hash)) if hash == class_hash
+        ));
+        assert!(matches!(on_top_of_0.get_compiled_class_hash(class_hash), Err(StateError::UndeclaredClassHash(_))));
+        assert_eq!(class_cache.stats(), ClassCacheStats { hits: 1, misses: 1, evictions: 0 });
+    }
 
     #[test]
     fn check_block_n_range() {
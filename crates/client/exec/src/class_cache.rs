@@ -0,0 +1,266 @@
+//! Classes converted to blockifier, shared by all the execution contexts of the node.
+//!
+//! Converting a stored class to a blockifier [`ContractClass`] means parsing its whole CASM or legacy program, which
+//! costs much more than executing most transactions. The hot classes (fee tokens, accounts) are converted once and
+//! reused by every trace, estimate and block executed afterwards.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use blockifier::execution::contract_class::ContractClass;
+use blockifier::state::state_api::StateResult;
+use dc_metrics::{Counter, MetricsRegistry, PrometheusError, U64};
+use dp_utils::lock::MutexExt;
+use starknet_api::core::ClassHash;
+use starknet_types_core::felt::Felt;
+
+/// Number of converted classes kept by [`GlobalClassCache::global`].
+pub const CLASS_CACHE_CAPACITY: usize = 512;
+
+#[derive(Clone, Debug)]
+pub struct ClassCacheMetrics {
+    pub hits: Counter<U64>,
+    pub misses: Counter<U64>,
+    pub evictions: Counter<U64>,
+}
+
+impl ClassCacheMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            hits: registry.register(Counter::new(
+                "deoxys_exec_class_cache_hits",
+                "Counter of the classes of the execution found already converted in the class cache",
+            )?)?,
+            misses: registry.register(Counter::new(
+                "deoxys_exec_class_cache_misses",
+                "Counter of the classes of the execution converted from their stored compiled class",
+            )?)?,
+            evictions: registry.register(Counter::new(
+                "deoxys_exec_class_cache_evictions",
+                "Counter of the converted classes dropped from the class cache",
+            )?)?,
+        })
+    }
+}
+
+/// Number of lookups made in a [`GlobalClassCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClassCacheStats {
+    pub hits: u64,
+    /// Every miss converts a class.
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct CachedClass {
+    /// Checked on every lookup: a class declared again with another compiled class is converted again.
+    compiled_class_hash: Felt,
+    class: ContractClass,
+    age: u64,
+}
+
+#[derive(Default)]
+struct ClassCacheInner {
+    next_age: u64,
+    by_hash: HashMap<ClassHash, CachedClass>,
+    by_age: BTreeMap<u64, ClassHash>,
+}
+
+/// Size-bounded cache of the classes converted to blockifier, keyed by class hash. The least recently used class is
+/// evicted first.
+///
+/// The cache does not know at which block a class is declared: the state adapter checks that the class is declared
+/// on top of the block it executes before looking it up.
+pub struct GlobalClassCache {
+    capacity: usize,
+    inner: Mutex<ClassCacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    metrics: OnceLock<ClassCacheMetrics>,
+}
+
+impl GlobalClassCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
+            evictions: Default::default(),
+            metrics: OnceLock::new(),
+        }
+    }
+
+    /// The cache used by the execution contexts of the node.
+    pub fn global() -> Arc<GlobalClassCache> {
+        static CACHE: OnceLock<Arc<GlobalClassCache>> = OnceLock::new();
+        Arc::clone(CACHE.get_or_init(|| Arc::new(GlobalClassCache::new(CLASS_CACHE_CAPACITY))))
+    }
+
+    /// Exports the lookups as metrics. Only the first metrics set are used.
+    pub fn set_metrics(&self, metrics: ClassCacheMetrics) {
+        let _ = self.metrics.set(metrics);
+    }
+
+    pub fn stats(&self) -> ClassCacheStats {
+        ClassCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The converted class of `class_hash`, or the class returned by `convert` when it is not cached yet or when it
+    /// was cached for another compiled class. Conversion errors are not cached.
+    pub fn get_or_convert(
+        &self,
+        class_hash: ClassHash,
+        compiled_class_hash: Felt,
+        convert: impl FnOnce() -> StateResult<ContractClass>,
+    ) -> StateResult<ContractClass> {
+        if let Some(class) = self.get(class_hash, compiled_class_hash) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = self.metrics.get() {
+                metrics.hits.inc();
+            }
+            return Ok(class);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = self.metrics.get() {
+            metrics.misses.inc();
+        }
+        // The lock is not held while converting, another context may convert the same class in the meantime.
+        let class = convert()?;
+        self.insert(class_hash, compiled_class_hash, class.clone());
+        Ok(class)
+    }
+
+    /// Drops the converted class of `class_hash`.
+    pub fn invalidate(&self, class_hash: ClassHash) {
+        let mut inner = self.inner.lock_or_recover();
+        if let Some(cached) = inner.by_hash.remove(&class_hash) {
+            inner.by_age.remove(&cached.age);
+        }
+    }
+
+    fn get(&self, class_hash: ClassHash, compiled_class_hash: Felt) -> Option<ContractClass> {
+        let mut inner = self.inner.lock_or_recover();
+        let age = inner.next_age;
+        let cached = inner.by_hash.get_mut(&class_hash)?;
+        if cached.compiled_class_hash != compiled_class_hash {
+            log::warn!("Class {:#x} was declared again with another compiled class", class_hash.0);
+            drop(inner);
+            self.invalidate(class_hash);
+            return None;
+        }
+
+        let previous_age = std::mem::replace(&mut cached.age, age);
+        let class = cached.class.clone();
+        inner.by_age.remove(&previous_age);
+        inner.by_age.insert(age, class_hash);
+        inner.next_age += 1;
+        Some(class)
+    }
+
+    fn insert(&self, class_hash: ClassHash, compiled_class_hash: Felt, class: ContractClass) {
+        let mut inner = self.inner.lock_or_recover();
+        let age = inner.next_age;
+        inner.next_age += 1;
+        if let Some(previous) = inner.by_hash.insert(class_hash, CachedClass { compiled_class_hash, class, age }) {
+            inner.by_age.remove(&previous.age);
+        }
+        inner.by_age.insert(age, class_hash);
+
+        while inner.by_age.len() > self.capacity {
+            let Some((_, oldest)) = inner.by_age.pop_first() else { break };
+            inner.by_hash.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = self.metrics.get() {
+                metrics.evictions.inc();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blockifier::execution::contract_class::ContractClassV0;
+    use blockifier::state::errors::StateError;
+
+    use super::*;
+
+    fn class_hash(n: u64) -> ClassHash {
+        ClassHash(Felt::from(n))
+    }
+
+    /// Counts the conversions made by the cache.
+    fn lookup(cache: &GlobalClassCache, conversions: &mut u32, n: u64, compiled_class_hash: Felt) -> ContractClass {
+        cache
+            .get_or_convert(class_hash(n), compiled_class_hash, || {
+                *conversions += 1;
+                Ok(ContractClass::V0(ContractClassV0::default()))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn classes_are_converted_once() {
+        let cache = GlobalClassCache::new(8);
+        let mut conversions = 0;
+
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        lookup(&cache, &mut conversions, 2, Felt::TWO);
+        assert_eq!(conversions, 2);
+
+        // A second trace touching the same classes.
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        lookup(&cache, &mut conversions, 2, Felt::TWO);
+        assert_eq!(conversions, 2);
+        assert_eq!(cache.stats(), ClassCacheStats { hits: 2, misses: 2, evictions: 0 });
+
+        // Conversion errors are not cached.
+        let err =
+            cache.get_or_convert(class_hash(3), Felt::from(3), || Err(StateError::UndeclaredClassHash(class_hash(3))));
+        assert!(err.is_err());
+        lookup(&cache, &mut conversions, 3, Felt::from(3));
+        assert_eq!(conversions, 3);
+    }
+
+    #[test]
+    fn least_recently_used_class_is_evicted() {
+        let cache = GlobalClassCache::new(2);
+        let mut conversions = 0;
+
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        lookup(&cache, &mut conversions, 2, Felt::TWO);
+        // Class 1 is used again, class 2 is now the least recently used.
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        lookup(&cache, &mut conversions, 3, Felt::from(3));
+        assert_eq!(conversions, 3);
+        assert_eq!(cache.stats().evictions, 1);
+
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        assert_eq!(conversions, 3);
+        lookup(&cache, &mut conversions, 2, Felt::TWO);
+        assert_eq!(conversions, 4);
+    }
+
+    #[test]
+    fn class_declared_again_is_converted_again() {
+        let cache = GlobalClassCache::new(8);
+        let mut conversions = 0;
+
+        lookup(&cache, &mut conversions, 1, Felt::ONE);
+        lookup(&cache, &mut conversions, 1, Felt::TWO);
+        assert_eq!(conversions, 2);
+        lookup(&cache, &mut conversions, 1, Felt::TWO);
+        assert_eq!(conversions, 2);
+
+        cache.invalidate(class_hash(1));
+        lookup(&cache, &mut conversions, 1, Felt::TWO);
+        assert_eq!(conversions, 3);
+    }
+}
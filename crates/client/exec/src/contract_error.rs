@@ -0,0 +1,172 @@
+//! The call stack of a failed execution.
+//!
+//! Blockifier flattens the errors of the nested calls of an execution into a trace, with one frame per call followed
+//! by the error of the Cairo VM. The frames are read back from that trace, so that the RPC can tell which contract and
+//! entry point failed, and which calls led to it.
+
+use blockifier::execution::stack_trace::gen_transaction_execution_error_trace;
+use blockifier::transaction::errors::TransactionExecutionError;
+use starknet_types_core::felt::Felt;
+
+/// Frames kept in the call stack of an error. Deeper call stacks keep their outermost and innermost frames.
+pub const MAX_CALL_STACK_DEPTH: usize = 32;
+
+const FRAME_PREAMBLES: [&str; 3] =
+    ["Error in the called contract", "Error in a library call", "Error in the contract class constructor"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallFrame {
+    pub contract_address: Felt,
+    pub class_hash: Felt,
+    /// Unknown for the constructors of the contracts deployed by the transaction.
+    pub selector: Option<Felt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallStackEntry {
+    Call(CallFrame),
+    /// Stands for the frames removed from a call stack deeper than [`MAX_CALL_STACK_DEPTH`].
+    Truncated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractErrorData {
+    /// The error of the innermost call.
+    pub revert_error: String,
+    /// The calls that led to the error, outermost first.
+    pub call_stack: Vec<CallStackEntry>,
+}
+
+impl ContractErrorData {
+    /// An error without a call stack.
+    pub fn new(revert_error: impl Into<String>) -> Self {
+        Self { revert_error: revert_error.into(), call_stack: vec![] }
+    }
+
+    pub fn from_execution_error(err: &TransactionExecutionError) -> Self {
+        Self::from_trace(&gen_transaction_execution_error_trace(err))
+    }
+
+    fn from_trace(trace: &str) -> Self {
+        let mut frames = vec![];
+        let mut revert_error = trace;
+        let mut offset = 0;
+        for line in trace.split_inclusive('\n') {
+            offset += line.len();
+            if let Some(frame) = parse_frame(line.trim_end()) {
+                frames.push(frame);
+                revert_error = &trace[offset..];
+            }
+        }
+
+        let call_stack = if frames.len() > MAX_CALL_STACK_DEPTH {
+            let innermost = frames.split_off(frames.len() - MAX_CALL_STACK_DEPTH / 2);
+            frames.truncate(MAX_CALL_STACK_DEPTH / 2);
+            frames
+                .into_iter()
+                .map(CallStackEntry::Call)
+                .chain([CallStackEntry::Truncated])
+                .chain(innermost.into_iter().map(CallStackEntry::Call))
+                .collect()
+        } else {
+            frames.into_iter().map(CallStackEntry::Call).collect()
+        };
+        Self { revert_error: revert_error.trim().into(), call_stack }
+    }
+}
+
+/// Parses `<depth>: <preamble> (contract address: <felt>, class hash: <felt>, selector: <felt or UNKNOWN>):`.
+fn parse_frame(line: &str) -> Option<CallFrame> {
+    let (depth, line) = line.split_once(": ")?;
+    if depth.is_empty() || !depth.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let preamble = FRAME_PREAMBLES.iter().find(|preamble| line.starts_with(**preamble))?;
+    let line = line[preamble.len()..].strip_prefix(" (contract address: ")?.strip_suffix("):")?;
+    let (contract_address, line) = line.split_once(", class hash: ")?;
+    let (class_hash, selector) = line.split_once(", selector: ")?;
+    Some(CallFrame {
+        contract_address: Felt::from_hex(contract_address).ok()?,
+        class_hash: Felt::from_hex(class_hash).ok()?,
+        selector: match selector {
+            "UNKNOWN" => None,
+            selector => Some(Felt::from_hex(selector).ok()?),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_line(depth: usize, preamble: &str, frame: &CallFrame) -> String {
+        let selector = frame.selector.map_or("UNKNOWN".into(), |selector| format!("{selector:#064x}"));
+        format!(
+            "{depth}: {preamble} (contract address: {:#064x}, class hash: {:#064x}, selector: {selector}):\n",
+            frame.contract_address, frame.class_hash
+        )
+    }
+
+    fn frame(n: u64) -> CallFrame {
+        CallFrame {
+            contract_address: Felt::from(0x1000 + n),
+            class_hash: Felt::from(0x2000 + n),
+            selector: Some(Felt::from(0x3000 + n)),
+        }
+    }
+
+    #[test]
+    fn nested_calls() {
+        // An account calls a contract, which panics in a library call.
+        let library_call = CallFrame { selector: None, ..frame(2) };
+        let trace = [
+            frame_line(0, "Error in the called contract", &frame(0)),
+            "Error at pc=0:4835:\nCairo traceback (most recent call last):\nUnknown location (pc=0:67)\n".into(),
+            frame_line(1, "Error in the called contract", &frame(1)),
+            "Error at pc=0:1205:\n".into(),
+            frame_line(2, "Error in a library call", &library_call),
+            "Execution failed. Failure reason: 0x6661696c6564 ('failed').\n".into(),
+        ]
+        .concat();
+
+        assert_eq!(
+            ContractErrorData::from_trace(&trace),
+            ContractErrorData {
+                revert_error: "Execution failed. Failure reason: 0x6661696c6564 ('failed').".into(),
+                call_stack: vec![
+                    CallStackEntry::Call(frame(0)),
+                    CallStackEntry::Call(frame(1)),
+                    CallStackEntry::Call(library_call),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn no_frames() {
+        let trace = "Transaction validation has failed: Invalid signature.";
+        assert_eq!(ContractErrorData::from_trace(trace), ContractErrorData::new(trace));
+        // Looks like a frame, but is not one.
+        let trace = "0: Error in the called contract (contract address: 0x1, class hash: 0xg, selector: 0x2):";
+        assert_eq!(ContractErrorData::from_trace(trace), ContractErrorData::new(trace));
+    }
+
+    #[test]
+    fn deep_recursion_is_truncated() {
+        let depth = MAX_CALL_STACK_DEPTH as u64 + 10;
+        let trace: String = (0..depth)
+            .map(|n| frame_line(n as usize, "Error in the called contract", &frame(n)))
+            .chain(["Execution failed due to recursion depth exceeded.".into()])
+            .collect();
+
+        let data = ContractErrorData::from_trace(&trace);
+        assert_eq!(data.revert_error, "Execution failed due to recursion depth exceeded.");
+        assert_eq!(data.call_stack.len(), MAX_CALL_STACK_DEPTH + 1);
+        let half = MAX_CALL_STACK_DEPTH / 2;
+        assert_eq!(data.call_stack[0], CallStackEntry::Call(frame(0)));
+        assert_eq!(data.call_stack[half - 1], CallStackEntry::Call(frame(half as u64 - 1)));
+        assert_eq!(data.call_stack[half], CallStackEntry::Truncated);
+        assert_eq!(data.call_stack[half + 1], CallStackEntry::Call(frame(depth - half as u64)));
+        assert_eq!(data.call_stack[MAX_CALL_STACK_DEPTH], CallStackEntry::Call(frame(depth - 1)));
+    }
+}
@@ -1,12 +1,19 @@
 use blockifier::fee::fee_utils::get_fee_by_gas_vector;
 use blockifier::fee::gas_usage::estimate_minimal_gas_vector;
-use blockifier::state::cached_state::TransactionalState;
+use blockifier::state::cached_state::{CommitmentStateDiff, TransactionalState};
+use blockifier::state::state_api::{StateReader, StateResult};
 use blockifier::transaction::account_transaction::AccountTransaction;
 use blockifier::transaction::errors::TransactionExecutionError;
 use blockifier::transaction::objects::{FeeType, HasRelatedFeeType, TransactionExecutionInfo};
 use blockifier::transaction::transaction_execution::Transaction;
 use blockifier::transaction::transaction_types::TransactionType;
 use blockifier::transaction::transactions::{ExecutableTransaction, ExecutionFlags};
+use dp_convert::ToFelt;
+use dp_state_update::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff,
+    StorageEntry,
+};
+use starknet_api::core::ClassHash;
 use starknet_api::transaction::TransactionHash;
 
 use crate::{Error, ExecutionContext, ExecutionResult, TxFeeEstimationError, TxReexecError};
@@ -44,6 +51,7 @@ impl ExecutionContext {
                 log::debug!("executing {hash:#} (trace)");
                 let tx_type = tx.tx_type();
                 let fee_type = tx.fee_type();
+                let declared_class_hash = tx.declared_class_hash();
 
                 // We need to estimate gas too.
                 let minimal_l1_gas = match &tx {
@@ -81,27 +89,87 @@ impl ExecutionContext {
 
                 let state_diff = transactional_state
                     .to_state_diff()
+                    .and_then(|state_diff| tx_state_diff(&transactional_state.state, state_diff, declared_class_hash))
                     .map_err(TransactionExecutionError::StateError)
                     .map_err(make_reexec_error)?;
                 transactional_state.commit();
 
-                Ok(ExecutionResult {
-                    hash,
-                    tx_type,
-                    fee_type,
-                    minimal_l1_gas,
-                    execution_info,
-                    state_diff: state_diff.into(),
-                })
+                Ok(ExecutionResult { hash, tx_type, fee_type, minimal_l1_gas, execution_info, state_diff })
             })
             .collect::<Result<Vec<_>, _>>()
     }
 }
 
+/// The state diff of a single transaction. `state_before` is the state the transaction is executed on: the contracts
+/// which had no class before are deployed, the other ones had their class replaced.
+pub(crate) fn tx_state_diff(
+    state_before: &impl StateReader,
+    state_diff: CommitmentStateDiff,
+    declared_class_hash: Option<ClassHash>,
+) -> StateResult<StateDiff> {
+    let CommitmentStateDiff {
+        address_to_class_hash,
+        address_to_nonce,
+        storage_updates,
+        class_hash_to_compiled_class_hash,
+    } = state_diff;
+
+    let (mut deployed_contracts, mut replaced_classes) = (Vec::new(), Vec::new());
+    for (contract_address, class_hash) in address_to_class_hash {
+        if state_before.get_class_hash_at(contract_address)? == ClassHash::default() {
+            deployed_contracts
+                .push(DeployedContractItem { address: contract_address.to_felt(), class_hash: class_hash.to_felt() });
+        } else {
+            replaced_classes.push(ReplacedClassItem {
+                contract_address: contract_address.to_felt(),
+                class_hash: class_hash.to_felt(),
+            });
+        }
+    }
+
+    // Legacy classes have no compiled class hash, they are not in the commitment state diff.
+    let deprecated_declared_classes = declared_class_hash
+        .filter(|class_hash| !class_hash_to_compiled_class_hash.contains_key(class_hash))
+        .map(ToFelt::to_felt)
+        .into_iter()
+        .collect();
+
+    Ok(StateDiff::new(
+        storage_updates
+            .into_iter()
+            .map(|(address, storage_entries)| ContractStorageDiffItem {
+                address: address.to_felt(),
+                storage_entries: storage_entries
+                    .into_iter()
+                    .map(|(key, value)| StorageEntry { key: key.to_felt(), value })
+                    .collect(),
+            })
+            .collect(),
+        deprecated_declared_classes,
+        class_hash_to_compiled_class_hash
+            .into_iter()
+            .map(|(class_hash, compiled_class_hash)| DeclaredClassItem {
+                class_hash: class_hash.to_felt(),
+                compiled_class_hash: compiled_class_hash.to_felt(),
+            })
+            .collect(),
+        deployed_contracts,
+        replaced_classes,
+        address_to_nonce
+            .into_iter()
+            .map(|(contract_address, nonce)| NonceUpdate {
+                contract_address: contract_address.to_felt(),
+                nonce: nonce.to_felt(),
+            })
+            .collect(),
+    ))
+}
+
 pub trait TxInfo {
     fn tx_hash(&self) -> TransactionHash;
     fn tx_type(&self) -> TransactionType;
     fn fee_type(&self) -> FeeType;
+    fn declared_class_hash(&self) -> Option<ClassHash>;
 }
 
 impl TxInfo for Transaction {
@@ -129,4 +197,11 @@ impl TxInfo for Transaction {
             Self::L1HandlerTransaction(tx) => tx.fee_type(),
         }
     }
+
+    fn declared_class_hash(&self) -> Option<ClassHash> {
+        match self {
+            Self::AccountTransaction(AccountTransaction::Declare(tx)) => Some(tx.class_hash()),
+            _ => None,
+        }
+    }
 }
@@ -2,6 +2,8 @@ use crate::{ExecutionContext, ExecutionResult};
 use blockifier::transaction::objects::FeeType;
 
 impl ExecutionContext {
+    /// The fee estimate of an execution, priced with the gas prices of the block in the unit of the fee token of the
+    /// transaction. Used by `estimateFee`, `estimateMessageFee` and `simulateTransactions`.
     pub fn execution_result_to_fee_estimate(
         &self,
         executions_result: &ExecutionResult,
@@ -15,14 +17,12 @@ impl ExecutionContext {
             .get_data_gas_price_by_fee_type(&executions_result.fee_type)
             .get();
 
-        let data_gas_consumed = executions_result.execution_info.transaction_receipt.da_gas.l1_data_gas;
-        let data_gas_fee = data_gas_consumed.saturating_mul(data_gas_price);
-        let gas_consumed =
-            executions_result.execution_info.transaction_receipt.fee.0.saturating_sub(data_gas_fee) / gas_price.max(1);
-        let minimal_gas_consumed = executions_result.minimal_l1_gas.unwrap_or_default().l1_gas;
-        let minimal_data_gas_consumed = executions_result.minimal_l1_gas.unwrap_or_default().l1_data_gas;
-        let gas_consumed = gas_consumed.max(minimal_gas_consumed);
-        let data_gas_consumed = data_gas_consumed.max(minimal_data_gas_consumed);
+        // The gas vector of the receipt holds the L1 gas and, when the block uses blob DA, the L1 data gas of the
+        // state diff. It is filled even when the fee is not charged.
+        let gas = executions_result.execution_info.transaction_receipt.gas;
+        let minimal_gas = executions_result.minimal_l1_gas.unwrap_or_default();
+        let gas_consumed = gas.l1_gas.max(minimal_gas.l1_gas);
+        let data_gas_consumed = gas.l1_data_gas.max(minimal_gas.l1_data_gas);
         let overall_fee =
             gas_consumed.saturating_mul(gas_price).saturating_add(data_gas_consumed.saturating_mul(data_gas_price));
 
@@ -40,3 +40,80 @@ impl ExecutionContext {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockifier::fee::gas_usage::get_da_gas_cost;
+    use blockifier::state::cached_state::StateChangesCount;
+    use blockifier::transaction::objects::{GasVector, TransactionExecutionInfo};
+    use blockifier::transaction::transaction_types::TransactionType;
+    use dc_db::testing::temp_database;
+    use dp_block::{
+        header::{GasPrices, L1DataAvailabilityMode},
+        DeoxysBlockInfo, DeoxysMaybePendingBlockInfo, Header, StarknetVersion,
+    };
+    use starknet_api::transaction::TransactionHash;
+    use starknet_core::types::{Felt, PriceUnit};
+    use std::sync::Arc;
+
+    /// An invoke writing 20 storage slots of one contract.
+    fn storage_heavy_invoke(use_kzg_da: bool) -> ExecutionResult {
+        let state_changes = StateChangesCount {
+            n_storage_updates: 20,
+            n_class_hash_updates: 0,
+            n_compiled_class_hash_updates: 0,
+            n_modified_contracts: 1,
+        };
+        let da_gas = get_da_gas_cost(&state_changes, use_kzg_da);
+        let mut execution_info = TransactionExecutionInfo::default();
+        execution_info.transaction_receipt.gas =
+            GasVector { l1_gas: 5000 + da_gas.l1_gas, l1_data_gas: da_gas.l1_data_gas };
+        execution_info.transaction_receipt.da_gas = da_gas;
+
+        ExecutionResult {
+            hash: TransactionHash(Felt::ONE),
+            tx_type: TransactionType::InvokeFunction,
+            fee_type: FeeType::Strk,
+            minimal_l1_gas: None,
+            execution_info,
+            state_diff: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fee_estimate_includes_blob_data_gas() {
+        let (_temp_dir, db) = temp_database().await;
+        let header = Header {
+            block_number: 1,
+            protocol_version: StarknetVersion::LATEST_SUPPORTED,
+            l1_gas_price: GasPrices {
+                eth_l1_gas_price: 30,
+                strk_l1_gas_price: 40,
+                eth_l1_data_gas_price: 3,
+                strk_l1_data_gas_price: 4,
+            },
+            l1_da_mode: L1DataAvailabilityMode::Blob,
+            ..Default::default()
+        };
+        let block_info = DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(header, vec![], Felt::ONE));
+        let context = ExecutionContext::new(Arc::clone(db.backend()), &block_info).unwrap();
+
+        let estimate = context.execution_result_to_fee_estimate(&storage_heavy_invoke(true));
+        assert_eq!(estimate.unit, PriceUnit::Fri);
+        assert_eq!(estimate.gas_consumed, Felt::from(5000));
+        assert_eq!(estimate.gas_price, Felt::from(40));
+        assert_ne!(estimate.data_gas_consumed, Felt::ZERO);
+        assert_eq!(estimate.data_gas_price, Felt::from(4));
+        assert_eq!(
+            estimate.overall_fee,
+            estimate.gas_consumed * estimate.gas_price + estimate.data_gas_consumed * estimate.data_gas_price
+        );
+
+        // With calldata DA, the state diff is paid with L1 gas.
+        let estimate = context.execution_result_to_fee_estimate(&storage_heavy_invoke(false));
+        assert!(estimate.gas_consumed > Felt::from(5000));
+        assert_eq!(estimate.data_gas_consumed, Felt::ZERO);
+        assert_eq!(estimate.overall_fee, estimate.gas_consumed * estimate.gas_price);
+    }
+}
@@ -1,24 +1,29 @@
-use blockifier::{
-    state::cached_state::CommitmentStateDiff,
-    transaction::{
-        errors::TransactionExecutionError,
-        objects::{FeeType, GasVector, TransactionExecutionInfo},
-        transaction_types::TransactionType,
-    },
+use blockifier::state::errors::StateError;
+use blockifier::transaction::{
+    errors::TransactionExecutionError,
+    objects::{FeeType, GasVector, TransactionExecutionInfo},
+    transaction_types::TransactionType,
 };
 use dc_db::{db_block_id::DbBlockId, DeoxysStorageError};
+use dp_state_update::StateDiff;
 use starknet_api::transaction::TransactionHash;
 use starknet_core::types::Felt;
 
 mod block_context;
 mod blockifier_state_adapter;
 mod call;
+mod class_cache;
+mod contract_error;
 mod execution;
 mod fee;
+mod state_cache;
 mod trace;
 
 pub use block_context::ExecutionContext;
 pub use blockifier_state_adapter::BlockifierStateAdapter;
+pub use class_cache::{ClassCacheMetrics, ClassCacheStats, GlobalClassCache, CLASS_CACHE_CAPACITY};
+pub use contract_error::{CallFrame, CallStackEntry, ContractErrorData, MAX_CALL_STACK_DEPTH};
+pub use state_cache::{StateReadCache, StateReadMetrics, StateReadStats};
 pub use trace::execution_result_to_tx_trace;
 
 #[derive(thiserror::Error, Debug)]
@@ -39,6 +44,42 @@ pub enum Error {
     InvalidSequencerAddress(Felt),
 }
 
+impl Error {
+    /// The execution error of blockifier, when the error comes from the execution of a transaction or a call.
+    fn execution_error(&self) -> Option<&TransactionExecutionError> {
+        match self {
+            Error::Reexecution(TxReexecError { err, .. })
+            | Error::FeeEstimation(TxFeeEstimationError { err, .. })
+            | Error::MessageFeeEstimation(MessageFeeEstimationError { err, .. })
+            | Error::CallContract(CallContractError { err, .. }) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Index of the transaction that failed, in the executed transactions.
+    pub fn tx_index(&self) -> Option<usize> {
+        match self {
+            Error::Reexecution(TxReexecError { index, .. })
+            | Error::FeeEstimation(TxFeeEstimationError { index, .. }) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Whether the error comes from reading the database rather than from the executed contracts.
+    pub fn is_storage_error(&self) -> bool {
+        matches!(self, Error::Storage(_))
+            || matches!(
+                self.execution_error(),
+                Some(TransactionExecutionError::StateError(StateError::StateReadError(_)))
+            )
+    }
+
+    /// The error of the innermost call and the call stack that led to it.
+    pub fn contract_error_data(&self) -> Option<ContractErrorData> {
+        self.execution_error().map(ContractErrorData::from_execution_error)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Reexecuting tx {hash:#} (index {index}) on top of {block_n}: {err:#}")]
 pub struct TxReexecError {
@@ -81,5 +122,6 @@ pub struct ExecutionResult {
     pub fee_type: FeeType,
     pub minimal_l1_gas: Option<GasVector>,
     pub execution_info: TransactionExecutionInfo,
-    pub state_diff: CommitmentStateDiff,
+    /// The state diff of the transaction alone.
+    pub state_diff: StateDiff,
 }
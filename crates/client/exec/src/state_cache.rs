@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use blockifier::execution::contract_class::ContractClass;
+use blockifier::state::state_api::StateResult;
+use dc_db::db_block_id::DbBlockId;
+use dc_metrics::{Counter, MetricsRegistry, PrometheusError, U64};
+use dp_utils::lock::RwLockExt;
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce};
+use starknet_api::state::StorageKey;
+use starknet_types_core::felt::Felt;
+
+#[derive(Clone, Debug)]
+pub struct StateReadMetrics {
+    pub cache_hits: Counter<U64>,
+    pub cache_misses: Counter<U64>,
+    pub state_reads: Counter<U64>,
+}
+
+impl StateReadMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            cache_hits: registry.register(Counter::new(
+                "deoxys_exec_state_cache_hits",
+                "Counter of the state reads of the execution answered by the cache of the execution context",
+            )?)?,
+            cache_misses: registry.register(Counter::new(
+                "deoxys_exec_state_cache_misses",
+                "Counter of the state reads of the execution not found in the cache of the execution context",
+            )?)?,
+            state_reads: registry.register(Counter::new(
+                "deoxys_exec_state_reads",
+                "Counter of the state reads of the execution made to the database",
+            )?)?,
+        })
+    }
+}
+
+/// Number of state reads made through a [`StateReadCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateReadStats {
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Reads made to the database, including the block hash reads which are not cached.
+    pub state_reads: u64,
+}
+
+/// Read-through cache of the state an [`crate::ExecutionContext`] executes on top of, shared by all the transactions
+/// and calls executed within the context.
+///
+/// A cache only ever serves the reads of the block it was created for. When this is the pending block, the values are
+/// the ones of the pending block at the time of the first read: the context keeps seeing the same state for its whole
+/// lifetime, as it would with the cached state of blockifier.
+#[derive(Debug, Default)]
+pub struct StateReadCache {
+    /// When this value is None, we are executing the genesis block.
+    on_top_of_block_id: Option<DbBlockId>,
+    storage: RwLock<HashMap<(ContractAddress, StorageKey), Felt>>,
+    nonces: RwLock<HashMap<ContractAddress, Nonce>>,
+    class_hashes: RwLock<HashMap<ContractAddress, ClassHash>>,
+    /// `None` for the classes that are not declared.
+    compiled_classes: RwLock<HashMap<ClassHash, Option<ContractClass>>>,
+    compiled_class_hashes: RwLock<HashMap<ClassHash, Option<CompiledClassHash>>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    state_reads: AtomicU64,
+    metrics: Option<StateReadMetrics>,
+}
+
+impl StateReadCache {
+    pub fn new(on_top_of_block_id: Option<DbBlockId>, metrics: Option<StateReadMetrics>) -> Self {
+        Self { on_top_of_block_id, metrics, ..Default::default() }
+    }
+
+    pub fn on_top_of_block_id(&self) -> Option<DbBlockId> {
+        self.on_top_of_block_id
+    }
+
+    pub fn stats(&self) -> StateReadStats {
+        StateReadStats {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            state_reads: self.state_reads.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn storage(
+        &self,
+        key: (ContractAddress, StorageKey),
+        read: impl FnOnce() -> StateResult<Felt>,
+    ) -> StateResult<Felt> {
+        self.read_through(&self.storage, key, read)
+    }
+
+    pub(crate) fn nonce(&self, key: ContractAddress, read: impl FnOnce() -> StateResult<Nonce>) -> StateResult<Nonce> {
+        self.read_through(&self.nonces, key, read)
+    }
+
+    pub(crate) fn class_hash(
+        &self,
+        key: ContractAddress,
+        read: impl FnOnce() -> StateResult<ClassHash>,
+    ) -> StateResult<ClassHash> {
+        self.read_through(&self.class_hashes, key, read)
+    }
+
+    pub(crate) fn compiled_class(
+        &self,
+        key: ClassHash,
+        read: impl FnOnce() -> StateResult<Option<ContractClass>>,
+    ) -> StateResult<Option<ContractClass>> {
+        self.read_through(&self.compiled_classes, key, read)
+    }
+
+    pub(crate) fn compiled_class_hash(
+        &self,
+        key: ClassHash,
+        read: impl FnOnce() -> StateResult<Option<CompiledClassHash>>,
+    ) -> StateResult<Option<CompiledClassHash>> {
+        self.read_through(&self.compiled_class_hashes, key, read)
+    }
+
+    /// Counts a database read that does not go through the cache.
+    pub(crate) fn record_state_read(&self) {
+        self.state_reads.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.state_reads.inc();
+        }
+    }
+
+    /// Failed reads are not cached, they are made again on the next lookup.
+    fn read_through<K: Eq + Hash, V: Clone>(
+        &self,
+        cache: &RwLock<HashMap<K, V>>,
+        key: K,
+        read: impl FnOnce() -> StateResult<V>,
+    ) -> StateResult<V> {
+        if let Some(value) = cache.read_or_recover().get(&key) {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = &self.metrics {
+                metrics.cache_hits.inc();
+            }
+            return Ok(value.clone());
+        }
+
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.cache_misses.inc();
+        }
+        self.record_state_read();
+        let value = read()?;
+        cache.write_or_recover().insert(key, value.clone());
+        Ok(value)
+    }
+}
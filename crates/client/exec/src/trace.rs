@@ -1,6 +1,5 @@
 use std::collections::HashMap;
 
-use blockifier::state::cached_state::CommitmentStateDiff;
 use blockifier::{execution::call_info::CallInfo, transaction::transaction_types::TransactionType};
 use cairo_vm::types::builtin_name::BuiltinName;
 use dp_convert::ToFelt;
@@ -30,10 +29,8 @@ pub fn execution_result_to_tx_trace(
 ) -> Result<starknet_core::types::TransactionTrace, ConvertCallInfoToExecuteInvocationError> {
     let ExecutionResult { tx_type, execution_info, state_diff, .. } = executions_result;
 
-    let state_diff = match state_diff_is_empty(state_diff) {
-        true => None,
-        false => Some(to_state_diff(state_diff)),
-    };
+    // Reverted transactions still have the state diff of their nonce bump and fee transfer.
+    let state_diff = (!state_diff.is_empty()).then(|| state_diff.clone().into());
 
     let validate_invocation =
         execution_info.validate_call_info.as_ref().map(try_get_funtion_invocation_from_call_info).transpose()?;
@@ -223,41 +220,6 @@ fn resources_mapping(
     }
 }
 
-fn to_state_diff(commitment_state_diff: &CommitmentStateDiff) -> starknet_core::types::StateDiff {
-    starknet_core::types::StateDiff {
-        storage_diffs: commitment_state_diff
-            .storage_updates
-            .iter()
-            .map(|(address, updates)| {
-                let storage_entries = updates
-                    .into_iter()
-                    .map(|(key, value)| starknet_core::types::StorageEntry { key: key.to_felt(), value: *value })
-                    .collect();
-                starknet_core::types::ContractStorageDiffItem { address: address.to_felt(), storage_entries }
-            })
-            .collect(),
-        deprecated_declared_classes: vec![],
-        declared_classes: vec![],
-        deployed_contracts: vec![],
-        replaced_classes: vec![],
-        nonces: commitment_state_diff
-            .address_to_nonce
-            .iter()
-            .map(|(address, nonce)| starknet_core::types::NonceUpdate {
-                contract_address: address.to_felt(),
-                nonce: nonce.to_felt(),
-            })
-            .collect(),
-    }
-}
-
-fn state_diff_is_empty(commitment_state_diff: &CommitmentStateDiff) -> bool {
-    commitment_state_diff.address_to_class_hash.is_empty()
-        && commitment_state_diff.address_to_nonce.is_empty()
-        && commitment_state_diff.storage_updates.is_empty()
-        && commitment_state_diff.class_hash_to_compiled_class_hash.is_empty()
-}
-
 fn agregate_execution_ressources(
     a: Option<&starknet_core::types::ComputationResources>,
     b: Option<&starknet_core::types::ComputationResources>,
@@ -348,3 +310,219 @@ fn agregate_execution_ressources(
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use blockifier::abi::abi_utils::get_fee_token_var_address;
+    use blockifier::context::BlockContext;
+    use blockifier::execution::contract_class::ContractClass;
+    use blockifier::invoke_tx_args;
+    use blockifier::state::cached_state::{CommitmentStateDiff, TransactionalState};
+    use blockifier::state::errors::StateError;
+    use blockifier::state::state_api::{StateReader, StateResult};
+    use blockifier::test_utils::contracts::FeatureContract;
+    use blockifier::test_utils::initial_test_state::test_state;
+    use blockifier::test_utils::{create_calldata, CairoVersion, BALANCE, MAX_FEE};
+    use blockifier::transaction::objects::{FeeType, TransactionExecutionInfo};
+    use blockifier::transaction::test_utils::account_invoke_tx;
+    use blockifier::transaction::transaction_execution::Transaction;
+    use blockifier::transaction::transactions::{ExecutableTransaction, ExecutionFlags};
+    use indexmap::IndexMap;
+    use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce, PatriciaKey};
+    use starknet_api::state::StorageKey;
+    use starknet_api::transaction::{Fee, TransactionHash, TransactionVersion};
+    use starknet_core::types::{
+        ContractStorageDiffItem, DeployedContractItem, ExecuteInvocation, NonceUpdate, ReplacedClassItem, StateDiff,
+        StorageEntry, TransactionTrace,
+    };
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::execution::tx_state_diff;
+
+    const TOKEN: u64 = 0x49d;
+    const SENDER: u64 = 0x100;
+    const RECIPIENT: u64 = 0x200;
+
+    fn address(n: u64) -> ContractAddress {
+        ContractAddress(PatriciaKey::try_from(Felt::from(n)).unwrap())
+    }
+
+    /// The classes of the contracts deployed before the transaction.
+    struct DeployedClasses(Vec<(ContractAddress, ClassHash)>);
+
+    impl StateReader for DeployedClasses {
+        fn get_storage_at(&self, _contract_address: ContractAddress, _key: StorageKey) -> StateResult<Felt> {
+            Ok(Felt::ZERO)
+        }
+        fn get_nonce_at(&self, _contract_address: ContractAddress) -> StateResult<Nonce> {
+            Ok(Nonce::default())
+        }
+        fn get_class_hash_at(&self, contract_address: ContractAddress) -> StateResult<ClassHash> {
+            Ok(self
+                .0
+                .iter()
+                .find(|(address, _)| *address == contract_address)
+                .map(|(_, class)| *class)
+                .unwrap_or_default())
+        }
+        fn get_compiled_contract_class(&self, class_hash: ClassHash) -> StateResult<ContractClass> {
+            Err(StateError::UndeclaredClassHash(class_hash))
+        }
+        fn get_compiled_class_hash(&self, class_hash: ClassHash) -> StateResult<CompiledClassHash> {
+            Err(StateError::UndeclaredClassHash(class_hash))
+        }
+    }
+
+    /// The state diff of an ERC20 transfer of 10 tokens from the sender to the recipient.
+    fn transfer_state_diff() -> dp_state_update::StateDiff {
+        let balances = IndexMap::from([
+            (StorageKey(PatriciaKey::try_from(Felt::from(SENDER)).unwrap()), Felt::from(90)),
+            (StorageKey(PatriciaKey::try_from(Felt::from(RECIPIENT)).unwrap()), Felt::from(10)),
+        ]);
+        let state_diff = CommitmentStateDiff {
+            address_to_class_hash: Default::default(),
+            address_to_nonce: IndexMap::from([(address(SENDER), Nonce(Felt::from(4)))]),
+            storage_updates: IndexMap::from([(address(TOKEN), balances)]),
+            class_hash_to_compiled_class_hash: Default::default(),
+        };
+        tx_state_diff(&DeployedClasses(vec![]), state_diff, None).unwrap()
+    }
+
+    fn invoke_result(execution_info: TransactionExecutionInfo) -> ExecutionResult {
+        ExecutionResult {
+            hash: TransactionHash(Felt::ONE),
+            tx_type: TransactionType::InvokeFunction,
+            fee_type: FeeType::Eth,
+            minimal_l1_gas: None,
+            execution_info,
+            state_diff: transfer_state_diff(),
+        }
+    }
+
+    fn expected_transfer_state_diff() -> StateDiff {
+        StateDiff {
+            storage_diffs: vec![ContractStorageDiffItem {
+                address: Felt::from(TOKEN),
+                storage_entries: vec![
+                    StorageEntry { key: Felt::from(SENDER), value: Felt::from(90) },
+                    StorageEntry { key: Felt::from(RECIPIENT), value: Felt::from(10) },
+                ],
+            }],
+            deprecated_declared_classes: vec![],
+            declared_classes: vec![],
+            deployed_contracts: vec![],
+            replaced_classes: vec![],
+            nonces: vec![NonceUpdate { contract_address: Felt::from(SENDER), nonce: Felt::from(4) }],
+        }
+    }
+
+    #[test]
+    fn invoke_trace_has_the_state_diff_of_the_transaction() {
+        let execution_info = TransactionExecutionInfo {
+            validate_call_info: Some(Default::default()),
+            execute_call_info: Some(Default::default()),
+            fee_transfer_call_info: Some(Default::default()),
+            ..Default::default()
+        };
+
+        let TransactionTrace::Invoke(trace) = execution_result_to_tx_trace(&invoke_result(execution_info)).unwrap()
+        else {
+            panic!("Expected an invoke trace")
+        };
+        assert!(matches!(trace.execute_invocation, ExecuteInvocation::Success(_)));
+        assert!(trace.validate_invocation.is_some());
+        assert!(trace.fee_transfer_invocation.is_some());
+        assert_eq!(trace.state_diff, Some(expected_transfer_state_diff()));
+    }
+
+    /// The state diff of a transaction reverted by blockifier is checked against the fee transfer computed from the
+    /// fee blockifier charged: the sender pays the fee to the sequencer, and the reverted storage write is dropped.
+    #[test]
+    fn reverted_invoke_trace_keeps_the_fee_transfer_state_diff() {
+        let block_context = BlockContext::create_for_account_testing();
+        let account = FeatureContract::AccountWithoutValidations(CairoVersion::Cairo0);
+        let test_contract = FeatureContract::TestContract(CairoVersion::Cairo1);
+        let mut state = test_state(block_context.chain_info(), BALANCE, &[(account, 1), (test_contract, 1)]);
+        let sender = account.get_instance_address(0);
+        let tx = Transaction::AccountTransaction(account_invoke_tx(invoke_tx_args! {
+            sender_address: sender,
+            calldata: create_calldata(
+                test_contract.get_instance_address(0),
+                "write_and_revert",
+                &[Felt::ONE, Felt::from(99)],
+            ),
+            max_fee: Fee(MAX_FEE),
+            version: TransactionVersion::ONE,
+            nonce: Nonce(Felt::ZERO),
+        }));
+
+        let mut transactional_state = TransactionalState::create_transactional(&mut state);
+        let execution_flags = ExecutionFlags { charge_fee: true, validate: true, concurrency_mode: false };
+        let execution_info = tx.execute_raw(&mut transactional_state, &block_context, execution_flags).unwrap();
+        assert!(execution_info.is_reverted());
+        let state_diff = transactional_state.to_state_diff().unwrap();
+        let state_diff = tx_state_diff(&transactional_state.state, state_diff, None).unwrap();
+
+        let fee = execution_info.transaction_receipt.fee.0;
+        let fee_token = block_context.chain_info().fee_token_address(&FeeType::Eth);
+        let sequencer = block_context.block_info().sequencer_address;
+        let expected = dp_state_update::StateDiff::new(
+            vec![dp_state_update::ContractStorageDiffItem {
+                address: fee_token.to_felt(),
+                storage_entries: vec![
+                    dp_state_update::StorageEntry {
+                        key: get_fee_token_var_address(sender).to_felt(),
+                        value: Felt::from(BALANCE - fee),
+                    },
+                    dp_state_update::StorageEntry {
+                        key: get_fee_token_var_address(sequencer).to_felt(),
+                        value: Felt::from(fee),
+                    },
+                ],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![dp_state_update::NonceUpdate { contract_address: sender.to_felt(), nonce: Felt::ONE }],
+        );
+
+        let result = ExecutionResult { state_diff, ..invoke_result(execution_info) };
+        let TransactionTrace::Invoke(trace) = execution_result_to_tx_trace(&result).unwrap() else {
+            panic!("Expected an invoke trace")
+        };
+        assert!(matches!(trace.execute_invocation, ExecuteInvocation::Reverted(_)));
+        assert!(trace.fee_transfer_invocation.is_some());
+        assert_eq!(trace.state_diff, Some(expected.into()));
+    }
+
+    #[test]
+    fn state_diff_tells_deployed_contracts_and_declared_classes_apart() {
+        let (account_class, upgraded_class, legacy_class) =
+            (ClassHash(Felt::from(0xa)), ClassHash(Felt::from(0xb)), ClassHash(Felt::from(0xc)));
+        let state_diff = CommitmentStateDiff {
+            address_to_class_hash: IndexMap::from([
+                (address(SENDER), upgraded_class),
+                (address(RECIPIENT), account_class),
+            ]),
+            address_to_nonce: Default::default(),
+            storage_updates: Default::default(),
+            class_hash_to_compiled_class_hash: Default::default(),
+        };
+
+        let state_diff: StateDiff =
+            tx_state_diff(&DeployedClasses(vec![(address(SENDER), account_class)]), state_diff, Some(legacy_class))
+                .unwrap()
+                .into();
+        assert_eq!(
+            state_diff.deployed_contracts,
+            [DeployedContractItem { address: Felt::from(RECIPIENT), class_hash: Felt::from(0xa) }]
+        );
+        assert_eq!(
+            state_diff.replaced_classes,
+            [ReplacedClassItem { contract_address: Felt::from(SENDER), class_hash: Felt::from(0xb) }]
+        );
+        assert_eq!(state_diff.deprecated_declared_classes, [Felt::from(0xc)]);
+    }
+}
@@ -0,0 +1,63 @@
+use dc_db::DeoxysStorageError;
+use hyper::{Body, Response, StatusCode};
+use serde::Serialize;
+use starknet_types_core::felt::Felt;
+
+/// Errors of the feeder gateway, served with the error format of the sequencer so that the clients of the feeder
+/// gateway can tell them apart.
+#[derive(thiserror::Error, Debug)]
+pub enum GatewayError {
+    #[error("Block not found")]
+    BlockNotFound,
+    #[error("Class with hash {0:#x} is not declared")]
+    UndeclaredClass(Felt),
+    #[error("Class with hash {0:#x} is a Cairo 0 class, it has no compiled class")]
+    NoCompiledClass(Felt),
+    #[error("Malformed request: {0}")]
+    MalformedRequest(String),
+    #[error("Storage error: {0:#}")]
+    Storage(#[from] DeoxysStorageError),
+    #[error("Internal error: {0:#}")]
+    Internal(#[from] anyhow::Error),
+}
+
+#[derive(Serialize)]
+struct SequencerError {
+    code: &'static str,
+    message: String,
+}
+
+impl GatewayError {
+    fn code(&self) -> &'static str {
+        match self {
+            GatewayError::BlockNotFound => "StarknetErrorCode.BLOCK_NOT_FOUND",
+            GatewayError::UndeclaredClass(_) | GatewayError::NoCompiledClass(_) => "StarknetErrorCode.UNDECLARED_CLASS",
+            GatewayError::MalformedRequest(_) => "StarkErrorCode.MALFORMED_REQUEST",
+            GatewayError::Storage(_) | GatewayError::Internal(_) => "StarknetErrorCode.INTERNAL_ERROR",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            GatewayError::Storage(_) | GatewayError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        let message = match &self {
+            GatewayError::Storage(_) | GatewayError::Internal(_) => {
+                log::error!("Feeder gateway: {self:#}");
+                "Internal error".to_string()
+            }
+            _ => self.to_string(),
+        };
+        let body = serde_json::to_vec(&SequencerError { code: self.code(), message })
+            .expect("Serializing an error cannot fail");
+        Response::builder()
+            .status(self.status())
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .expect("The response headers are valid")
+    }
+}
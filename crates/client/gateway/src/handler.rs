@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use dc_db::db_block_id::DbBlockId;
+use dc_db::DeoxysBackend;
+use dp_block::{BlockId, BlockN, BlockTag, DeoxysMaybePendingBlockInfo};
+use dp_class::{CompiledClass, ContractClass};
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use starknet_types_core::felt::Felt;
+
+use crate::error::GatewayError;
+use crate::models::{class_definition, Block, BlockStatus, StateUpdate, StateUpdateWithBlock};
+
+pub(crate) fn handle(req: &Request<Body>, backend: &DeoxysBackend) -> Response<Body> {
+    let params: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|query| url::form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+        .unwrap_or_default();
+
+    let res = match req.uri().path().trim_end_matches('/') {
+        "/feeder_gateway/get_block" => get_block(backend, &params),
+        "/feeder_gateway/get_state_update" => get_state_update(backend, &params),
+        "/feeder_gateway/get_class_by_hash" => get_class_by_hash(backend, &params),
+        "/feeder_gateway/get_compiled_class_by_class_hash" => get_compiled_class_by_class_hash(backend, &params),
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Not found."))
+                .expect("The response headers are valid")
+        }
+    };
+    res.unwrap_or_else(GatewayError::into_response)
+}
+
+fn json_response(body: Vec<u8>) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("The response headers are valid")
+}
+
+fn to_json(value: &impl Serialize) -> Result<Response<Body>, GatewayError> {
+    Ok(json_response(serde_json::to_vec(value).map_err(anyhow::Error::from)?))
+}
+
+/// The block of the `blockHash` or `blockNumber` parameters, a block number or one of the `latest` and `pending` tags.
+fn block_id(params: &HashMap<String, String>, default: BlockTag) -> Result<BlockId, GatewayError> {
+    if let Some(hash) = params.get("blockHash") {
+        return Felt::from_hex(hash)
+            .map(BlockId::Hash)
+            .map_err(|_| GatewayError::MalformedRequest(format!("Invalid block hash `{hash}`")));
+    }
+    match params.get("blockNumber") {
+        None => Ok(BlockId::Tag(default)),
+        Some(number) => match BlockTag::from_str(number) {
+            Ok(tag) => Ok(BlockId::Tag(tag)),
+            Err(_) => number
+                .parse()
+                .map(BlockId::Number)
+                .map_err(|_| GatewayError::MalformedRequest(format!("Invalid block number `{number}`"))),
+        },
+    }
+}
+
+fn class_hash(params: &HashMap<String, String>) -> Result<Felt, GatewayError> {
+    let class_hash =
+        params.get("classHash").ok_or_else(|| GatewayError::MalformedRequest("Missing `classHash`".into()))?;
+    Felt::from_hex(class_hash).map_err(|_| GatewayError::MalformedRequest(format!("Invalid class hash `{class_hash}`")))
+}
+
+fn block(backend: &DeoxysBackend, id: &DbBlockId) -> Result<Block, GatewayError> {
+    let block = backend.get_block(id)?.ok_or(GatewayError::BlockNotFound)?;
+    Ok(match block.info {
+        DeoxysMaybePendingBlockInfo::Pending(info) => Block::pending(&info.header, &info.tx_hashes, block.inner),
+        DeoxysMaybePendingBlockInfo::NotPending(info) => {
+            let on_l1 = backend.get_l1_last_confirmed_block()?.is_some_and(|l1| info.header.block_number <= l1);
+            let status = if on_l1 { BlockStatus::AcceptedOnL1 } else { BlockStatus::AcceptedOnL2 };
+            Block::new(&info.header, info.block_hash, &info.tx_hashes, block.inner, status)
+        }
+    })
+}
+
+fn state_update(backend: &DeoxysBackend, id: &DbBlockId) -> Result<StateUpdate, GatewayError> {
+    let state_diff = backend.get_block_state_diff(id)?.ok_or(GatewayError::BlockNotFound)?;
+    let (block_hash, new_root, parent) = match id {
+        DbBlockId::Pending => (None, None, backend.get_latest_block_n()?),
+        DbBlockId::BlockN(block_n) => {
+            let info = backend.get_block_info(id)?.ok_or(GatewayError::BlockNotFound)?;
+            let info = info.as_nonpending().ok_or_else(|| anyhow::anyhow!("Block {block_n} is pending"))?;
+            (Some(info.block_hash), Some(info.header.global_state_root), block_n.parent().map(|parent| parent.0))
+        }
+    };
+    // The root before the genesis block is zero.
+    let old_root = match parent {
+        Some(parent) => {
+            let info = backend
+                .get_block_info(&DbBlockId::BlockN(BlockN(parent)))?
+                .ok_or_else(|| anyhow::anyhow!("Parent block {parent} not found"))?;
+            info.as_nonpending().ok_or_else(|| anyhow::anyhow!("Block {parent} is pending"))?.header.global_state_root
+        }
+        None => Felt::ZERO,
+    };
+    Ok(StateUpdate { block_hash, new_root, old_root, state_diff: state_diff.into() })
+}
+
+fn resolve(backend: &DeoxysBackend, id: &BlockId) -> Result<DbBlockId, GatewayError> {
+    backend.resolve_block_id(id)?.ok_or(GatewayError::BlockNotFound)
+}
+
+fn get_block(backend: &DeoxysBackend, params: &HashMap<String, String>) -> Result<Response<Body>, GatewayError> {
+    let id = resolve(backend, &block_id(params, BlockTag::Latest)?)?;
+    to_json(&block(backend, &id)?)
+}
+
+/// With `includeBlock=true`, the block is served along with its state update.
+fn get_state_update(backend: &DeoxysBackend, params: &HashMap<String, String>) -> Result<Response<Body>, GatewayError> {
+    let id = resolve(backend, &block_id(params, BlockTag::Latest)?)?;
+    let state_update = state_update(backend, &id)?;
+    if params.get("includeBlock").is_some_and(|include| include == "true") {
+        to_json(&StateUpdateWithBlock { state_update, block: block(backend, &id)? })
+    } else {
+        to_json(&state_update)
+    }
+}
+
+fn get_class_by_hash(
+    backend: &DeoxysBackend,
+    params: &HashMap<String, String>,
+) -> Result<Response<Body>, GatewayError> {
+    let id = resolve(backend, &block_id(params, BlockTag::Pending)?)?;
+    let class_hash = class_hash(params)?;
+    let class_info = backend.get_class_info(&id, &class_hash)?.ok_or(GatewayError::UndeclaredClass(class_hash))?;
+    to_json(&class_definition(class_info.contract_class)?)
+}
+
+/// The compiled classes are stored as the JSON of their CASM, which is served as is.
+fn get_compiled_class_by_class_hash(
+    backend: &DeoxysBackend,
+    params: &HashMap<String, String>,
+) -> Result<Response<Body>, GatewayError> {
+    let id = resolve(backend, &block_id(params, BlockTag::Pending)?)?;
+    let class_hash = class_hash(params)?;
+    let (class_info, compiled_class) =
+        backend.get_class(&id, &class_hash)?.ok_or(GatewayError::UndeclaredClass(class_hash))?;
+    match (class_info.contract_class, compiled_class) {
+        (ContractClass::Sierra(_), compiled_class @ CompiledClass::Sierra(_)) => {
+            Ok(json_response(compiled_class.to_vec()))
+        }
+        _ => Err(GatewayError::NoCompiledClass(class_hash)),
+    }
+}
@@ -0,0 +1,350 @@
+//! Feeder gateway server: serves the blocks, state updates and classes of the database with the REST API and the JSON
+//! formats of the Starknet sequencer's feeder gateway, so that a synced node can act as a feeder gateway mirror for
+//! other nodes and tools.
+
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use anyhow::Context;
+use dc_db::DeoxysBackend;
+use dp_utils::{service::Service, wait_or_graceful_shutdown, StopHandle};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Server,
+};
+use tokio::{net::TcpListener, sync::oneshot, task::JoinSet};
+
+mod error;
+mod handler;
+mod models;
+
+pub struct GatewayService {
+    gateway_enabled: bool,
+    gateway_external: bool,
+    gateway_port: u16,
+    backend: Arc<DeoxysBackend>,
+    stop_handle: StopHandle,
+}
+
+impl GatewayService {
+    pub fn new(gateway_enabled: bool, gateway_external: bool, gateway_port: u16, backend: Arc<DeoxysBackend>) -> Self {
+        Self { gateway_enabled, gateway_external, gateway_port, backend, stop_handle: Default::default() }
+    }
+}
+
+/// Serves the feeder gateway on the socket until the shutdown future resolves.
+async fn serve(
+    socket: TcpListener,
+    backend: Arc<DeoxysBackend>,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let service = make_service_fn(move |_| {
+        let backend = Arc::clone(&backend);
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                let backend = Arc::clone(&backend);
+                async move { Ok::<_, hyper::Error>(handler::handle(&req, &backend)) }
+            }))
+        }
+    });
+
+    let listener = hyper::server::conn::AddrIncoming::from_listener(socket).context("Opening feeder gateway server")?;
+    log::info!("🌐 Feeder gateway endpoint started at {}", listener.local_addr());
+    Server::builder(listener).serve(service).with_graceful_shutdown(shutdown).await.context("Running feeder gateway")
+}
+
+#[async_trait::async_trait]
+impl Service for GatewayService {
+    async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+        if !self.gateway_enabled {
+            return Ok(());
+        }
+
+        let listen_addr = if self.gateway_external {
+            Ipv4Addr::UNSPECIFIED // listen on 0.0.0.0
+        } else {
+            Ipv4Addr::LOCALHOST
+        };
+        let addr = SocketAddr::new(listen_addr.into(), self.gateway_port);
+
+        let (stop_send, stop_recv) = oneshot::channel();
+        self.stop_handle = StopHandle::new(Some(stop_send));
+
+        let backend = Arc::clone(&self.backend);
+        join_set.spawn(async move {
+            let socket = TcpListener::bind(addr).await.with_context(|| format!("Opening socket server at {addr}"))?;
+            serve(socket, backend, async {
+                wait_or_graceful_shutdown(stop_recv).await;
+            })
+            .await
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use dc_db::testing::temp_database;
+    use dp_block::header::PendingHeader;
+    use dp_block::{
+        DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysPendingBlockInfo, Header, StarknetVersion,
+    };
+    use dp_class::{
+        ClassInfo, CompressedLegacyContractClass, ContractClass, ConvertedClass, LegacyContractEntryPoint,
+        LegacyEntryPointsByType, ToCompiledClass,
+    };
+    use dp_convert::ToStateUpdateCore;
+    use dp_receipt::{
+        DataAvailabilityResources, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt,
+        PriceUnit, TransactionReceipt,
+    };
+    use dp_state_update::{ContractStorageDiffItem, NonceUpdate, StateDiff, StorageEntry};
+    use dp_transactions::{
+        DataAvailabilityMode, InvokeTransaction, InvokeTransactionV1, InvokeTransactionV3, Transaction,
+    };
+    use starknet_core::types::StarknetError;
+    use starknet_providers::sequencer::models as p;
+    use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+
+    const CLASS_HASH: Felt = Felt::from_hex_unchecked("0xc1a55");
+
+    fn invoke_v1(nonce: u64) -> Transaction {
+        Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+            sender_address: Felt::ONE,
+            calldata: vec![Felt::TWO, Felt::THREE],
+            max_fee: Felt::from(100),
+            signature: vec![Felt::from(4)],
+            nonce: nonce.into(),
+        }))
+    }
+
+    fn invoke_v3() -> Transaction {
+        Transaction::Invoke(InvokeTransaction::V3(InvokeTransactionV3 {
+            sender_address: Felt::ONE,
+            calldata: vec![Felt::TWO],
+            signature: vec![],
+            nonce: Felt::TWO,
+            resource_bounds: Default::default(),
+            tip: 0,
+            paymaster_data: vec![],
+            account_deployment_data: vec![],
+            nonce_data_availability_mode: DataAvailabilityMode::L1,
+            fee_data_availability_mode: DataAvailabilityMode::L1,
+        }))
+    }
+
+    fn receipt(tx_hash: Felt) -> TransactionReceipt {
+        TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            transaction_hash: tx_hash,
+            actual_fee: FeePayment { amount: Felt::from(2), unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events: vec![],
+            execution_resources: ExecutionResources {
+                steps: 9,
+                memory_holes: Some(10),
+                range_check_builtin_applications: Some(11),
+                pedersen_builtin_applications: Some(12),
+                poseidon_builtin_applications: None,
+                ec_op_builtin_applications: None,
+                ecdsa_builtin_applications: None,
+                bitwise_builtin_applications: Some(16),
+                keccak_builtin_applications: None,
+                segment_arena_builtin: None,
+                data_availability: DataAvailabilityResources { l1_gas: 19, l1_data_gas: 20 },
+                total_gas_consumed: DataAvailabilityResources { l1_gas: 21, l1_data_gas: 22 },
+            },
+            execution_result: ExecutionResult::Succeeded,
+        })
+    }
+
+    /// A Cairo 0 class with an empty program.
+    fn legacy_class() -> ConvertedClass {
+        let program = serde_json::json!({
+            "builtins": [],
+            "data": [],
+            "hints": {},
+            "identifiers": {},
+            "main_scope": "__main__",
+            "prime": "0x800000000000011000000000000000000000000000000000000000000000001",
+            "reference_manager": { "references": [] },
+        });
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&serde_json::to_vec(&program).unwrap()).unwrap();
+        let contract_class = ContractClass::Legacy(CompressedLegacyContractClass {
+            program: encoder.finish().unwrap(),
+            entry_points_by_type: LegacyEntryPointsByType {
+                constructor: vec![],
+                external: vec![LegacyContractEntryPoint { offset: 0x10, selector: Felt::from(0x20) }],
+                l1_handler: vec![],
+            },
+            abi: Some(vec![]),
+        });
+        let compiled_class = starknet_core::types::ContractClass::from(contract_class.clone()).compile().unwrap();
+        ConvertedClass {
+            class_infos: (
+                CLASS_HASH,
+                ClassInfo { contract_class, compiled_class_hash: Felt::ZERO, block_number: Some(0) },
+            ),
+            class_compiled: (CLASS_HASH, compiled_class),
+        }
+    }
+
+    fn block_0() -> DeoxysMaybePendingBlock {
+        let header = Header {
+            block_number: 0,
+            global_state_root: Felt::from(0x10),
+            protocol_version: StarknetVersion::STARKNET_VERSION_0_13_1,
+            ..Default::default()
+        };
+        DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![Felt::from(0x100)], Felt::from(0xb0)).into(),
+            inner: DeoxysBlockInner::new(vec![invoke_v1(0)], vec![receipt(Felt::from(0x100))]),
+        }
+    }
+
+    fn block_1() -> DeoxysMaybePendingBlock {
+        let header = Header {
+            block_number: 1,
+            parent_block_hash: Felt::from(0xb0),
+            global_state_root: Felt::from(0x11),
+            protocol_version: StarknetVersion::STARKNET_VERSION_0_13_2,
+            ..Default::default()
+        };
+        DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![Felt::from(0x101)], Felt::from(0xb1)).into(),
+            inner: DeoxysBlockInner::new(vec![invoke_v3()], vec![receipt(Felt::from(0x101))]),
+        }
+    }
+
+    fn state_diff_1() -> StateDiff {
+        StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![StorageEntry { key: Felt::from(0x5), value: Felt::from(0x6) }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![NonceUpdate { contract_address: Felt::ONE, nonce: Felt::THREE }],
+        )
+    }
+
+    fn pending_block() -> DeoxysMaybePendingBlock {
+        let header = PendingHeader {
+            parent_block_hash: Felt::from(0xb1),
+            protocol_version: StarknetVersion::STARKNET_VERSION_0_13_2,
+            ..Default::default()
+        };
+        DeoxysMaybePendingBlock {
+            info: DeoxysPendingBlockInfo::new(header, vec![Felt::from(0x102)]).into(),
+            inner: DeoxysBlockInner::new(vec![invoke_v1(3)], vec![receipt(Felt::from(0x102))]),
+        }
+    }
+
+    /// Serves blocks 0 and 1 and a pending block, and returns a sequencer client of the gateway.
+    async fn gateway() -> (tempfile::TempDir, SequencerGatewayProvider) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let state_diff_0 = StateDiff::new(vec![], vec![CLASS_HASH], vec![], vec![], vec![], vec![]);
+        backend.store_block(block_0(), state_diff_0, vec![legacy_class()]).unwrap();
+        backend.store_block(block_1(), state_diff_1(), vec![]).unwrap();
+        backend.store_block(pending_block(), StateDiff::default(), vec![]).unwrap();
+
+        let socket = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url: url::Url = format!("http://{}", socket.local_addr().unwrap()).parse().unwrap();
+        tokio::spawn(serve(socket, backend, std::future::pending()));
+
+        let provider = SequencerGatewayProvider::new(
+            url.join("gateway").unwrap(),
+            url.join("feeder_gateway").unwrap(),
+            Felt::from_bytes_be_slice(b"SN_MAIN"),
+        );
+        (temp_dir, provider)
+    }
+
+    fn transactions(block: &p::Block) -> Vec<Transaction> {
+        block.transactions.iter().cloned().map(Transaction::try_from).collect::<Result<_, _>>().unwrap()
+    }
+
+    fn receipts(block: &p::Block) -> Vec<TransactionReceipt> {
+        Iterator::zip(block.transaction_receipts.iter().cloned(), &block.transactions)
+            .map(|(receipt, tx)| TransactionReceipt::from_provider(receipt, tx))
+            .collect()
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)] // Sequencer-specific functions are deprecated. Use it via the Provider trait instead.
+    async fn test_get_block() {
+        let (_temp_dir, provider) = gateway().await;
+
+        let block = provider.get_block(p::BlockId::Number(0)).await.unwrap();
+        assert_eq!(block.block_hash, Some(Felt::from(0xb0)));
+        assert_eq!(block.block_number, Some(0));
+        assert_eq!(block.state_root, Some(Felt::from(0x10)));
+        assert!(matches!(block.status, p::BlockStatus::AcceptedOnL2));
+        assert_eq!(block.starknet_version.as_deref(), Some("0.13.1"));
+        assert_eq!(transactions(&block), block_0().inner.transactions);
+        assert_eq!(receipts(&block), block_0().inner.receipts);
+
+        let block = provider.get_block(p::BlockId::Latest).await.unwrap();
+        assert_eq!(block.block_hash, Some(Felt::from(0xb1)));
+        assert_eq!(block.parent_block_hash, Felt::from(0xb0));
+        assert_eq!(transactions(&block), block_1().inner.transactions);
+
+        let block = provider.get_block(p::BlockId::Pending).await.unwrap();
+        assert_eq!(block.block_hash, None);
+        assert!(matches!(block.status, p::BlockStatus::Pending));
+        assert_eq!(block.parent_block_hash, Felt::from(0xb1));
+        assert_eq!(transactions(&block), pending_block().inner.transactions);
+
+        assert!(matches!(
+            provider.get_block(p::BlockId::Number(5)).await,
+            Err(ProviderError::StarknetError(StarknetError::BlockNotFound))
+        ));
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)] // Sequencer-specific functions are deprecated. Use it via the Provider trait instead.
+    async fn test_get_state_update() {
+        let (_temp_dir, provider) = gateway().await;
+
+        let state_update = provider.get_state_update(p::BlockId::Number(1)).await.unwrap();
+        assert_eq!(state_update.block_hash, Some(Felt::from(0xb1)));
+        assert_eq!(state_update.old_root, Felt::from(0x10));
+        assert_eq!(StateDiff::from(state_update.to_state_update_core().state_diff), state_diff_1());
+
+        let with_block = provider.get_state_update_with_block(p::BlockId::Number(0)).await.unwrap();
+        assert_eq!(with_block.state_update.old_root, Felt::ZERO);
+        assert_eq!(with_block.state_update.state_diff.old_declared_contracts, vec![CLASS_HASH]);
+        assert_eq!(with_block.block.block_hash, Some(Felt::from(0xb0)));
+
+        assert!(matches!(
+            provider.get_state_update(p::BlockId::Hash(Felt::from(0xdead))).await,
+            Err(ProviderError::StarknetError(StarknetError::BlockNotFound))
+        ));
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)] // Sequencer-specific functions are deprecated. Use it via the Provider trait instead.
+    async fn test_get_class() {
+        let (_temp_dir, provider) = gateway().await;
+
+        let class = provider.get_class(starknet_core::types::BlockId::Number(1), CLASS_HASH).await.unwrap();
+        let starknet_core::types::ContractClass::Legacy(class) = class else { panic!("Expected a legacy class") };
+        let ContractClass::Legacy(stored) = legacy_class().class_infos.1.contract_class else { unreachable!() };
+        assert_eq!(LegacyEntryPointsByType::from(class.entry_points_by_type), stored.entry_points_by_type);
+
+        assert!(provider.get_class(starknet_core::types::BlockId::Number(1), Felt::from(0xdead)).await.is_err());
+        // Cairo 0 classes have no compiled class.
+        assert!(provider.get_compiled_class_by_class_hash(CLASS_HASH, p::BlockId::Latest).await.is_err());
+    }
+}
@@ -0,0 +1,648 @@
+//! The JSON shapes of the feeder gateway responses, built from the blocks, state diffs and classes of the database.
+//! This is the inverse of the conversions done by the sync from the `starknet_providers` sequencer models.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use anyhow::Context;
+use dp_block::header::PendingHeader;
+use dp_block::{DeoxysBlockInner, Header, StarknetVersion};
+use dp_class::ContractClass;
+use dp_receipt::{ExecutionResult, TransactionReceipt};
+use dp_state_update::StateDiff;
+use dp_transactions::{
+    DataAvailabilityMode, DeclareTransaction, DeployAccountTransaction, InvokeTransaction, ResourceBoundsMapping,
+};
+use serde::Serialize;
+use starknet_core::types::ResourcePrice;
+use starknet_types_core::felt::Felt;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BlockStatus {
+    Pending,
+    AcceptedOnL2,
+    AcceptedOnL1,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    pub parent_block_hash: Felt,
+    pub timestamp: u64,
+    pub sequencer_address: Felt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_commitment: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_commitment: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt_commitment: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_diff_commitment: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_diff_length: Option<u64>,
+    pub status: BlockStatus,
+    pub l1_da_mode: starknet_core::types::L1DataAvailabilityMode,
+    pub l1_gas_price: ResourcePrice,
+    pub l1_data_gas_price: ResourcePrice,
+    pub transactions: Vec<Transaction>,
+    pub transaction_receipts: Vec<Receipt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starknet_version: Option<String>,
+}
+
+impl Block {
+    pub fn new(
+        header: &Header,
+        block_hash: Felt,
+        tx_hashes: &[Felt],
+        inner: DeoxysBlockInner,
+        status: BlockStatus,
+    ) -> Self {
+        // The receipt and state diff commitments are part of the block since 0.13.2.
+        let commitments_0_13_2 = header.protocol_version >= StarknetVersion::STARKNET_VERSION_0_13_2;
+        Self {
+            block_hash: Some(block_hash),
+            block_number: Some(header.block_number),
+            state_root: Some(header.global_state_root),
+            transaction_commitment: Some(header.transaction_commitment),
+            event_commitment: Some(header.event_commitment),
+            receipt_commitment: commitments_0_13_2.then_some(header.receipt_commitment),
+            state_diff_commitment: commitments_0_13_2.then_some(header.state_diff_commitment),
+            state_diff_length: commitments_0_13_2.then_some(header.state_diff_length),
+            status,
+            ..Self::pending(
+                &PendingHeader {
+                    parent_block_hash: header.parent_block_hash,
+                    sequencer_address: header.sequencer_address,
+                    block_timestamp: header.block_timestamp,
+                    protocol_version: header.protocol_version,
+                    l1_gas_price: header.l1_gas_price.clone(),
+                    l1_da_mode: header.l1_da_mode,
+                },
+                tx_hashes,
+                inner,
+            )
+        }
+    }
+
+    pub fn pending(header: &PendingHeader, tx_hashes: &[Felt], inner: DeoxysBlockInner) -> Self {
+        let (transactions, transaction_receipts) = Iterator::zip(inner.transactions.iter(), inner.receipts.iter())
+            .zip(tx_hashes)
+            .enumerate()
+            .map(|(index, ((tx, receipt), hash))| {
+                (Transaction::new(tx, *hash, receipt), Receipt::new(receipt, tx, index as u64, header.protocol_version))
+            })
+            .unzip();
+
+        Self {
+            block_hash: None,
+            block_number: None,
+            parent_block_hash: header.parent_block_hash,
+            timestamp: header.block_timestamp,
+            sequencer_address: header.sequencer_address,
+            state_root: None,
+            transaction_commitment: None,
+            event_commitment: None,
+            receipt_commitment: None,
+            state_diff_commitment: None,
+            state_diff_length: None,
+            status: BlockStatus::Pending,
+            l1_da_mode: header.l1_da_mode.into(),
+            l1_gas_price: header.l1_gas_price.l1_gas_price(),
+            l1_data_gas_price: header.l1_gas_price.l1_data_gas_price(),
+            transactions,
+            transaction_receipts,
+            // The oldest blocks have no version.
+            starknet_version: (header.protocol_version != StarknetVersion::default())
+                .then(|| header.protocol_version.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionType {
+    Declare,
+    Deploy,
+    DeployAccount,
+    InvokeFunction,
+    L1Handler,
+}
+
+/// A transaction of any type and version, the fields which do not exist for it are omitted.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transaction {
+    pub r#type: TransactionType,
+    pub transaction_hash: Felt,
+    pub version: Felt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender_address: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_address: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point_selector: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_hash: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compiled_class_hash: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_address_salt: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constructor_calldata: Option<Vec<Felt>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calldata: Option<Vec<Felt>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Vec<Felt>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee: Option<Felt>,
+    #[serde(flatten)]
+    pub v3: Option<V3Fields>,
+}
+
+/// Fields of the v3 transactions.
+#[derive(Debug, Clone, Serialize)]
+pub struct V3Fields {
+    pub resource_bounds: ResourceBounds,
+    pub tip: Felt,
+    pub paymaster_data: Vec<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_deployment_data: Option<Vec<Felt>>,
+    pub nonce_data_availability_mode: u8,
+    pub fee_data_availability_mode: u8,
+}
+
+impl V3Fields {
+    fn new(
+        resource_bounds: &ResourceBoundsMapping,
+        tip: u64,
+        paymaster_data: &[Felt],
+        account_deployment_data: Option<&[Felt]>,
+        nonce_data_availability_mode: DataAvailabilityMode,
+        fee_data_availability_mode: DataAvailabilityMode,
+    ) -> Self {
+        Self {
+            resource_bounds: ResourceBounds {
+                l1_gas: ResourceBound {
+                    max_amount: resource_bounds.l1_gas.max_amount.into(),
+                    max_price_per_unit: resource_bounds.l1_gas.max_price_per_unit.into(),
+                },
+                l2_gas: ResourceBound {
+                    max_amount: resource_bounds.l2_gas.max_amount.into(),
+                    max_price_per_unit: resource_bounds.l2_gas.max_price_per_unit.into(),
+                },
+            },
+            tip: tip.into(),
+            paymaster_data: paymaster_data.to_vec(),
+            account_deployment_data: account_deployment_data.map(<[Felt]>::to_vec),
+            nonce_data_availability_mode: nonce_data_availability_mode as u8,
+            fee_data_availability_mode: fee_data_availability_mode as u8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct ResourceBounds {
+    pub l1_gas: ResourceBound,
+    pub l2_gas: ResourceBound,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceBound {
+    pub max_amount: Felt,
+    pub max_price_per_unit: Felt,
+}
+
+impl Transaction {
+    /// The receipt holds the address of the deployed contracts, which the transactions do not store.
+    pub fn new(tx: &dp_transactions::Transaction, transaction_hash: Felt, receipt: &TransactionReceipt) -> Self {
+        let empty = |r#type| Self {
+            r#type,
+            transaction_hash,
+            version: Felt::ZERO,
+            sender_address: None,
+            contract_address: None,
+            entry_point_selector: None,
+            class_hash: None,
+            compiled_class_hash: None,
+            contract_address_salt: None,
+            constructor_calldata: None,
+            calldata: None,
+            signature: None,
+            nonce: None,
+            max_fee: None,
+            v3: None,
+        };
+        let deployed_address = match receipt {
+            TransactionReceipt::Deploy(receipt) => Some(receipt.contract_address),
+            TransactionReceipt::DeployAccount(receipt) => Some(receipt.contract_address),
+            _ => None,
+        };
+
+        match tx {
+            dp_transactions::Transaction::Invoke(InvokeTransaction::V0(tx)) => Self {
+                contract_address: Some(tx.contract_address),
+                entry_point_selector: Some(tx.entry_point_selector),
+                calldata: Some(tx.calldata.clone()),
+                signature: Some(tx.signature.clone()),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::InvokeFunction)
+            },
+            dp_transactions::Transaction::Invoke(InvokeTransaction::V1(tx)) => Self {
+                version: Felt::ONE,
+                sender_address: Some(tx.sender_address),
+                calldata: Some(tx.calldata.clone()),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::InvokeFunction)
+            },
+            dp_transactions::Transaction::Invoke(InvokeTransaction::V3(tx)) => Self {
+                version: Felt::THREE,
+                sender_address: Some(tx.sender_address),
+                calldata: Some(tx.calldata.clone()),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                v3: Some(V3Fields::new(
+                    &tx.resource_bounds,
+                    tx.tip,
+                    &tx.paymaster_data,
+                    Some(&tx.account_deployment_data),
+                    tx.nonce_data_availability_mode,
+                    tx.fee_data_availability_mode,
+                )),
+                ..empty(TransactionType::InvokeFunction)
+            },
+            dp_transactions::Transaction::L1Handler(tx) => Self {
+                version: tx.version,
+                contract_address: Some(tx.contract_address),
+                entry_point_selector: Some(tx.entry_point_selector),
+                calldata: Some(tx.calldata.clone()),
+                nonce: Some(tx.nonce.into()),
+                ..empty(TransactionType::L1Handler)
+            },
+            dp_transactions::Transaction::Declare(DeclareTransaction::V0(tx)) => Self {
+                sender_address: Some(tx.sender_address),
+                class_hash: Some(tx.class_hash),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(Felt::ZERO),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::Declare)
+            },
+            dp_transactions::Transaction::Declare(DeclareTransaction::V1(tx)) => Self {
+                version: Felt::ONE,
+                sender_address: Some(tx.sender_address),
+                class_hash: Some(tx.class_hash),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::Declare)
+            },
+            dp_transactions::Transaction::Declare(DeclareTransaction::V2(tx)) => Self {
+                version: Felt::TWO,
+                sender_address: Some(tx.sender_address),
+                class_hash: Some(tx.class_hash),
+                compiled_class_hash: Some(tx.compiled_class_hash),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::Declare)
+            },
+            dp_transactions::Transaction::Declare(DeclareTransaction::V3(tx)) => Self {
+                version: Felt::THREE,
+                sender_address: Some(tx.sender_address),
+                class_hash: Some(tx.class_hash),
+                compiled_class_hash: Some(tx.compiled_class_hash),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                v3: Some(V3Fields::new(
+                    &tx.resource_bounds,
+                    tx.tip,
+                    &tx.paymaster_data,
+                    Some(&tx.account_deployment_data),
+                    tx.nonce_data_availability_mode,
+                    tx.fee_data_availability_mode,
+                )),
+                ..empty(TransactionType::Declare)
+            },
+            dp_transactions::Transaction::Deploy(tx) => Self {
+                version: tx.version,
+                contract_address: deployed_address,
+                class_hash: Some(tx.class_hash),
+                contract_address_salt: Some(tx.contract_address_salt),
+                constructor_calldata: Some(tx.constructor_calldata.clone()),
+                ..empty(TransactionType::Deploy)
+            },
+            dp_transactions::Transaction::DeployAccount(DeployAccountTransaction::V1(tx)) => Self {
+                version: Felt::ONE,
+                contract_address: deployed_address,
+                class_hash: Some(tx.class_hash),
+                contract_address_salt: Some(tx.contract_address_salt),
+                constructor_calldata: Some(tx.constructor_calldata.clone()),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                max_fee: Some(tx.max_fee),
+                ..empty(TransactionType::DeployAccount)
+            },
+            dp_transactions::Transaction::DeployAccount(DeployAccountTransaction::V3(tx)) => Self {
+                version: Felt::THREE,
+                contract_address: deployed_address,
+                class_hash: Some(tx.class_hash),
+                contract_address_salt: Some(tx.contract_address_salt),
+                constructor_calldata: Some(tx.constructor_calldata.clone()),
+                signature: Some(tx.signature.clone()),
+                nonce: Some(tx.nonce),
+                v3: Some(V3Fields::new(
+                    &tx.resource_bounds,
+                    tx.tip,
+                    &tx.paymaster_data,
+                    None,
+                    tx.nonce_data_availability_mode,
+                    tx.fee_data_availability_mode,
+                )),
+                ..empty(TransactionType::DeployAccount)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExecutionStatus {
+    Succeeded,
+    Reverted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Receipt {
+    pub transaction_hash: Felt,
+    pub transaction_index: u64,
+    pub execution_status: ExecutionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_error: Option<String>,
+    pub execution_resources: ExecutionResources,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_to_l2_consumed_message: Option<L1ToL2Message>,
+    pub l2_to_l1_messages: Vec<L2ToL1Message>,
+    pub events: Vec<Event>,
+    pub actual_fee: Felt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionResources {
+    pub n_steps: u64,
+    pub builtin_instance_counter: BuiltinInstanceCounter,
+    pub n_memory_holes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_availability: Option<GasVector>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_gas_consumed: Option<GasVector>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuiltinInstanceCounter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pedersen_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_check_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitwise_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ecdsa_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ec_op_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poseidon_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keccak_builtin: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment_arena_builtin: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GasVector {
+    pub l1_gas: u64,
+    pub l1_data_gas: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct L1ToL2Message {
+    pub from_address: Felt,
+    pub to_address: Felt,
+    pub selector: Felt,
+    pub payload: Vec<Felt>,
+    pub nonce: Felt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct L2ToL1Message {
+    pub from_address: Felt,
+    pub to_address: Felt,
+    pub payload: Vec<Felt>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub from_address: Felt,
+    pub keys: Vec<Felt>,
+    pub data: Vec<Felt>,
+}
+
+impl Receipt {
+    pub fn new(
+        receipt: &TransactionReceipt,
+        tx: &dp_transactions::Transaction,
+        transaction_index: u64,
+        protocol_version: StarknetVersion,
+    ) -> Self {
+        let (execution_status, revert_error) = match receipt.execution_result() {
+            ExecutionResult::Succeeded => (ExecutionStatus::Succeeded, None),
+            ExecutionResult::Reverted { reason } => (ExecutionStatus::Reverted, Some(reason.clone())),
+            ExecutionResult::Rejected { reason } => (ExecutionStatus::Rejected, Some(reason.clone())),
+        };
+        // The message consumed by an L1 handler is its calldata, prefixed by the L1 sender.
+        let l1_to_l2_consumed_message = match tx {
+            dp_transactions::Transaction::L1Handler(tx) => {
+                tx.calldata.split_first().map(|(from_address, payload)| L1ToL2Message {
+                    from_address: *from_address,
+                    to_address: tx.contract_address,
+                    selector: tx.entry_point_selector,
+                    payload: payload.to_vec(),
+                    nonce: tx.nonce.into(),
+                })
+            }
+            _ => None,
+        };
+        // The gas vectors are part of the receipts since 0.13.1.
+        let gas_vectors = protocol_version >= StarknetVersion::STARKNET_VERSION_0_13_1;
+        let resources = receipt.execution_resources();
+
+        Self {
+            transaction_hash: receipt.transaction_hash(),
+            transaction_index,
+            execution_status,
+            revert_error,
+            execution_resources: ExecutionResources {
+                n_steps: resources.steps,
+                builtin_instance_counter: BuiltinInstanceCounter {
+                    pedersen_builtin: resources.pedersen_builtin_applications,
+                    range_check_builtin: resources.range_check_builtin_applications,
+                    bitwise_builtin: resources.bitwise_builtin_applications,
+                    ecdsa_builtin: resources.ecdsa_builtin_applications,
+                    ec_op_builtin: resources.ec_op_builtin_applications,
+                    poseidon_builtin: resources.poseidon_builtin_applications,
+                    keccak_builtin: resources.keccak_builtin_applications,
+                    segment_arena_builtin: resources.segment_arena_builtin,
+                },
+                n_memory_holes: resources.memory_holes.unwrap_or(0),
+                data_availability: gas_vectors.then(|| GasVector {
+                    l1_gas: resources.data_availability.l1_gas,
+                    l1_data_gas: resources.data_availability.l1_data_gas,
+                }),
+                total_gas_consumed: gas_vectors.then(|| GasVector {
+                    l1_gas: resources.total_gas_consumed.l1_gas,
+                    l1_data_gas: resources.total_gas_consumed.l1_data_gas,
+                }),
+            },
+            l1_to_l2_consumed_message,
+            l2_to_l1_messages: receipt
+                .messages_sent()
+                .iter()
+                .map(|msg| L2ToL1Message {
+                    from_address: msg.from_address,
+                    to_address: msg.to_address,
+                    payload: msg.payload.clone(),
+                })
+                .collect(),
+            events: receipt
+                .events()
+                .iter()
+                .map(|event| Event {
+                    from_address: event.from_address,
+                    keys: event.keys.clone(),
+                    data: event.data.clone(),
+                })
+                .collect(),
+            actual_fee: receipt.actual_fee().amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<Felt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_root: Option<Felt>,
+    pub old_root: Felt,
+    pub state_diff: GatewayStateDiff,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayStateDiff {
+    pub storage_diffs: BTreeMap<Felt, Vec<StorageEntry>>,
+    pub deployed_contracts: Vec<DeployedContract>,
+    pub old_declared_contracts: Vec<Felt>,
+    pub declared_classes: Vec<DeclaredClass>,
+    pub nonces: BTreeMap<Felt, Felt>,
+    pub replaced_classes: Vec<DeployedContract>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageEntry {
+    pub key: Felt,
+    pub value: Felt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployedContract {
+    pub address: Felt,
+    pub class_hash: Felt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeclaredClass {
+    pub class_hash: Felt,
+    pub compiled_class_hash: Felt,
+}
+
+impl From<StateDiff> for GatewayStateDiff {
+    fn from(state_diff: StateDiff) -> Self {
+        let (
+            storage_diffs,
+            deprecated_declared_classes,
+            declared_classes,
+            deployed_contracts,
+            replaced_classes,
+            nonces,
+        ) = state_diff.into_parts();
+        Self {
+            storage_diffs: storage_diffs
+                .into_iter()
+                .map(|diff| {
+                    let entries = diff
+                        .storage_entries
+                        .into_iter()
+                        .map(|entry| StorageEntry { key: entry.key, value: entry.value })
+                        .collect();
+                    (diff.address, entries)
+                })
+                .collect(),
+            deployed_contracts: deployed_contracts
+                .into_iter()
+                .map(|item| DeployedContract { address: item.address, class_hash: item.class_hash })
+                .collect(),
+            old_declared_contracts: deprecated_declared_classes,
+            declared_classes: declared_classes
+                .into_iter()
+                .map(|item| DeclaredClass {
+                    class_hash: item.class_hash,
+                    compiled_class_hash: item.compiled_class_hash,
+                })
+                .collect(),
+            nonces: nonces.into_iter().map(|item| (item.contract_address, item.nonce)).collect(),
+            replaced_classes: replaced_classes
+                .into_iter()
+                .map(|item| DeployedContract { address: item.contract_address, class_hash: item.class_hash })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateUpdateWithBlock {
+    pub state_update: StateUpdate,
+    pub block: Block,
+}
+
+/// The class definition as returned by `get_class_by_hash`. The program of the legacy classes is stored compressed,
+/// the gateway serves it as plain JSON.
+pub fn class_definition(class: ContractClass) -> anyhow::Result<serde_json::Value> {
+    match class {
+        ContractClass::Sierra(class) => {
+            Ok(serde_json::to_value(starknet_core::types::FlattenedSierraClass::from(class))?)
+        }
+        ContractClass::Legacy(class) => {
+            let class = starknet_core::types::CompressedLegacyContractClass::from(class);
+            let mut program = Vec::new();
+            flate2::read::GzDecoder::new(class.program.as_slice())
+                .read_to_end(&mut program)
+                .context("Decompressing program")?;
+            let program: serde_json::Value = serde_json::from_slice(&program).context("Parsing program JSON")?;
+            Ok(serde_json::json!({
+                "program": program,
+                "entry_points_by_type": class.entry_points_by_type,
+                "abi": class.abi,
+            }))
+        }
+    }
+}
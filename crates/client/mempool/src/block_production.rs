@@ -1,18 +1,23 @@
 // TODO: Move this into its own crate.
 
-use blockifier::blockifier::transaction_executor::{TransactionExecutor, VisitedSegmentsMapping};
-use blockifier::bouncer::{Bouncer, BouncerWeights, BuiltinCount};
+use blockifier::blockifier::transaction_executor::{
+    TransactionExecutor, TransactionExecutorError, TransactionExecutorResult, VisitedSegmentsMapping,
+};
+use blockifier::bouncer::{Bouncer, BouncerConfig, BouncerWeights, BuiltinCount};
 use blockifier::state::cached_state::CommitmentStateDiff;
 use blockifier::state::state_api::StateReader;
 use blockifier::transaction::errors::TransactionExecutionError;
+use blockifier::transaction::objects::TransactionExecutionInfo;
 use blockifier::transaction::transaction_execution::Transaction;
 use dc_db::db_block_id::DbBlockId;
 use dc_db::{DeoxysBackend, DeoxysStorageError};
 use dc_exec::{BlockifierStateAdapter, ExecutionContext};
-use dp_block::{BlockId, BlockTag, DeoxysPendingBlock};
+use dc_sync::commitments::CommitmentError;
+use dp_block::chain_config::ChainConfig;
+use dp_block::DeoxysPendingBlock;
 use dp_class::ConvertedClass;
 use dp_convert::ToFelt;
-use dp_receipt::from_blockifier_execution_info;
+use dp_receipt::{from_blockifier_execution_info, TransactionReceipt};
 use dp_state_update::{
     ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff,
     StorageEntry,
@@ -22,10 +27,13 @@ use dp_utils::graceful_shutdown;
 use starknet_core::types::Felt;
 use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
 
 use crate::close_block::close_block;
 use crate::header::make_pending_header;
-use crate::{clone_account_tx, L1DataProvider, Mempool, MempoolTransaction};
+use crate::preview::{BlockPreviewHandle, PendingBlockSnapshot};
+use crate::{clone_account_tx, L1DataProvider, Mempool, MempoolL1HandlerTransaction, MempoolTransaction};
 
 /// We always take transactions in batches from the mempool
 const TX_BATCH_SIZE: usize = 128;
@@ -38,6 +46,8 @@ pub enum Error {
     Execution(#[from] TransactionExecutionError),
     #[error(transparent)]
     ExecutionContext(#[from] dc_exec::Error),
+    #[error("Failed to compute the block commitments: {0}")]
+    Commitment(#[from] CommitmentError),
     #[error("No genesis block in storage")]
     NoGenesis,
 }
@@ -75,8 +85,8 @@ fn csd_to_state_diff(
         }
     }
 
-    Ok(StateDiff {
-        storage_diffs: storage_updates
+    let mut state_diff = StateDiff::new(
+        storage_updates
             .into_iter()
             .map(|(address, storage_entries)| ContractStorageDiffItem {
                 address: address.to_felt(),
@@ -86,24 +96,30 @@ fn csd_to_state_diff(
                     .collect(),
             })
             .collect(),
-        deprecated_declared_classes: vec![],
-        declared_classes: class_hash_to_compiled_class_hash
+        vec![],
+        class_hash_to_compiled_class_hash
             .iter()
             .map(|(class_hash, compiled_class_hash)| DeclaredClassItem {
                 class_hash: class_hash.to_felt(),
                 compiled_class_hash: compiled_class_hash.to_felt(),
             })
             .collect(),
-        nonces: address_to_nonce
+        deployed_contracts,
+        replaced_classes,
+        address_to_nonce
             .into_iter()
             .map(|(contract_address, nonce)| NonceUpdate {
                 contract_address: contract_address.to_felt(),
                 nonce: nonce.to_felt(),
             })
             .collect(),
-        deployed_contracts,
-        replaced_classes,
-    })
+    );
+    if let Some(on_top_of) = on_top_of {
+        state_diff
+            .remove_noop_storage_writes(|address, key| backend.get_contract_storage_at(on_top_of, address, key))?;
+    }
+
+    Ok(state_diff)
 }
 
 pub const BLOCK_STATE_ACCESS_ERR: &str = "Error: The block state should be `Some`.";
@@ -131,7 +147,6 @@ fn get_visited_segments<S: StateReader>(
 }
 
 fn finalize_execution_state<S: StateReader>(
-    _executed_txs: &[MempoolTransaction],
     tx_executor: &mut TransactionExecutor<S>,
     backend: &DeoxysBackend,
     on_top_of: &Option<DbBlockId>,
@@ -149,6 +164,231 @@ fn finalize_execution_state<S: StateReader>(
     Ok((state_update, visited_segments, *tx_executor.bouncer.get_accumulated_weights()))
 }
 
+/// Bouncer capacity available at a pending tick: `n_ticks` ticks make up the full block capacity.
+fn tick_capacity(block_capacity: &BouncerWeights, tick: usize, n_ticks: usize) -> BouncerWeights {
+    // Computed on u128 so that `BouncerConfig::max()` does not overflow.
+    let scale = |value: usize| (value as u128 * tick as u128 / n_ticks as u128) as usize;
+    BouncerWeights {
+        builtin_count: BuiltinCount {
+            add_mod: scale(block_capacity.builtin_count.add_mod),
+            bitwise: scale(block_capacity.builtin_count.bitwise),
+            ecdsa: scale(block_capacity.builtin_count.ecdsa),
+            ec_op: scale(block_capacity.builtin_count.ec_op),
+            keccak: scale(block_capacity.builtin_count.keccak),
+            mul_mod: scale(block_capacity.builtin_count.mul_mod),
+            pedersen: scale(block_capacity.builtin_count.pedersen),
+            poseidon: scale(block_capacity.builtin_count.poseidon),
+            range_check: scale(block_capacity.builtin_count.range_check),
+            range_check96: scale(block_capacity.builtin_count.range_check96),
+        },
+        gas: scale(block_capacity.gas),
+        message_segment_length: scale(block_capacity.message_segment_length),
+        n_events: scale(block_capacity.n_events),
+        n_steps: scale(block_capacity.n_steps),
+        state_diff_size: scale(block_capacity.state_diff_size),
+    }
+}
+
+/// Serialized size of a part of a block, as stored in the database.
+fn stored_size(value: &impl serde::Serialize) -> u64 {
+    bincode::serialized_size(value).expect("Block contents are always serializable")
+}
+
+/// Room left in the pending block for new transactions, see [`ChainConfig::max_block_size`].
+///
+/// Transactions are executed in batches, and their receipts are only known once they are executed: the room is
+/// reserved for the transactions themselves, the receipts are accounted for on the next tick. The block can go over
+/// its cap by the receipts of a tick.
+#[derive(Debug, Clone, Copy)]
+struct SizeBudget {
+    remaining: u64,
+    max: u64,
+}
+
+impl SizeBudget {
+    /// Splits a batch of transactions into the ones that fit in the rest of the block, and the ones that come after
+    /// them, deferred to the next block. A transaction larger than the whole cap can never be included: it is
+    /// dropped.
+    fn take_fitting<T>(&mut self, txs: Vec<T>, size_of: impl Fn(&T) -> (Felt, u64)) -> (Vec<T>, Vec<T>) {
+        let mut fitting = Vec::with_capacity(txs.len());
+        let mut txs = txs.into_iter();
+        while let Some(tx) = txs.next() {
+            let (tx_hash, size) = size_of(&tx);
+            if size > self.max {
+                log::error!("Dropping transaction {tx_hash:#x}: its size of {size} bytes exceeds the block size cap");
+            } else if size > self.remaining {
+                return (fitting, std::iter::once(tx).chain(txs).collect());
+            } else {
+                self.remaining -= size;
+                fitting.push(tx);
+            }
+        }
+        (fitting, vec![])
+    }
+}
+
+/// Takes batches of transactions from the mempool and executes them until the block is full or the mempool is
+/// empty.
+///
+/// The executor's bouncer accumulates the resources of every executed transaction, and stops executing a batch
+/// as soon as the next transaction would exceed one of its caps. The rest of that batch is added back to the
+/// mempool.
+///
+/// A transaction that alone exceeds the bouncer caps is dropped when `at_full_capacity` is `true`, as it can never
+/// be included in a block. Otherwise, it only exceeds the reduced capacity of a pending tick and it is added back
+/// to the mempool once this call is done, so that a later tick can include it.
+///
+/// The block is also full once its transactions use up the `size_budget`: the next transactions are added back to
+/// the mempool for the next block, whatever the bouncer capacity left.
+fn fill_block(
+    mempool: &Mempool,
+    at_full_capacity: bool,
+    size_budget: &mut SizeBudget,
+    mut execute_txs: impl FnMut(&[Transaction]) -> Vec<TransactionExecutorResult<TransactionExecutionInfo>>,
+) -> Vec<(MempoolTransaction, TransactionExecutionInfo)> {
+    let mut executed = Vec::new();
+    let mut too_large_for_tick = Vec::new();
+
+    loop {
+        let mut txs_to_process = Vec::with_capacity(TX_BATCH_SIZE);
+        mempool.take_txs_chunk(&mut txs_to_process, TX_BATCH_SIZE);
+        if txs_to_process.is_empty() {
+            break;
+        }
+        let (txs_to_process, deferred) = size_budget.take_fitting(txs_to_process, |tx| {
+            let converted_tx = TransactionWithHash::from(clone_account_tx(&tx.tx));
+            (converted_tx.hash, stored_size(&converted_tx.transaction))
+        });
+
+        let blockifier_txs: Vec<_> =
+            txs_to_process.iter().map(|tx| Transaction::AccountTransaction(clone_account_tx(&tx.tx))).collect();
+
+        // Execute the transactions.
+        let all_results = execute_txs(&blockifier_txs);
+        let block_full = all_results.len() < txs_to_process.len() || !deferred.is_empty();
+
+        // Split the `txs_to_process` vec into two iterators.
+        let mut to_process_iter = txs_to_process.into_iter();
+        // This iterator will consume the first part of `to_process_iter`.
+        let consumed_txs_to_process = to_process_iter.by_ref().take(all_results.len());
+
+        for (exec_result, mempool_tx) in Iterator::zip(all_results.into_iter(), consumed_txs_to_process) {
+            match exec_result {
+                Ok(execution_info) => {
+                    // Note: reverted txs also appear as Ok here.
+                    log::debug!("Successful execution of transaction {:?}", mempool_tx.tx_hash());
+                    executed.push((mempool_tx, execution_info));
+                }
+                Err(TransactionExecutorError::TransactionExecutionError(
+                    TransactionExecutionError::TransactionTooLarge,
+                )) if !at_full_capacity => too_large_for_tick.push(mempool_tx),
+                Err(err) => {
+                    // TODO: revert handling
+                    log::error!("Unsuccessful execution of transaction {:?}: {err:#}", mempool_tx.tx_hash());
+                }
+            }
+        }
+
+        if block_full {
+            // Add back the unexecuted transactions to the mempool.
+            mempool.re_add_txs(to_process_iter.chain(deferred).collect());
+            break;
+        }
+    }
+
+    mempool.re_add_txs(too_large_for_tick);
+    executed
+}
+
+/// Executes the L1 handler transactions of the mempool, by message nonce, until the block is full or there are none
+/// left. They are executed before any account transaction of the mempool: they were already paid for on L1.
+///
+/// See [`fill_block`] for how a full block and transactions too large for a pending tick are handled.
+fn fill_block_l1_handlers(
+    mempool: &Mempool,
+    at_full_capacity: bool,
+    size_budget: &mut SizeBudget,
+    mut execute_txs: impl FnMut(&[Transaction]) -> Vec<TransactionExecutorResult<TransactionExecutionInfo>>,
+) -> Vec<(MempoolL1HandlerTransaction, TransactionExecutionInfo)> {
+    let mut executed = Vec::new();
+    let mut too_large_for_tick = Vec::new();
+
+    loop {
+        let mut txs_to_process = Vec::with_capacity(TX_BATCH_SIZE);
+        mempool.take_l1_handler_txs_chunk(&mut txs_to_process, TX_BATCH_SIZE);
+        if txs_to_process.is_empty() {
+            break;
+        }
+        let (txs_to_process, deferred) = size_budget.take_fitting(txs_to_process, |tx| {
+            let converted_tx = TransactionWithHash::from(tx.to_blockifier());
+            (converted_tx.hash, stored_size(&converted_tx.transaction))
+        });
+
+        let blockifier_txs: Vec<_> =
+            txs_to_process.iter().map(|tx| Transaction::L1HandlerTransaction(tx.to_blockifier())).collect();
+
+        let all_results = execute_txs(&blockifier_txs);
+        let block_full = all_results.len() < txs_to_process.len() || !deferred.is_empty();
+
+        let mut to_process_iter = txs_to_process.into_iter();
+        let consumed_txs_to_process = to_process_iter.by_ref().take(all_results.len());
+
+        for (exec_result, l1_handler_tx) in Iterator::zip(all_results.into_iter(), consumed_txs_to_process) {
+            match exec_result {
+                Ok(execution_info) => {
+                    log::debug!("Successful execution of L1 handler transaction {:?}", l1_handler_tx.tx_hash);
+                    executed.push((l1_handler_tx, execution_info));
+                }
+                Err(TransactionExecutorError::TransactionExecutionError(
+                    TransactionExecutionError::TransactionTooLarge,
+                )) if !at_full_capacity => too_large_for_tick.push(l1_handler_tx),
+                Err(err) => {
+                    log::error!(
+                        "Unsuccessful execution of L1 handler transaction {:?}: {err:#}",
+                        l1_handler_tx.tx_hash
+                    );
+                }
+            }
+        }
+
+        if block_full {
+            mempool.re_add_l1_handler_txs(to_process_iter.chain(deferred).collect());
+            break;
+        }
+    }
+
+    mempool.re_add_l1_handler_txs(too_large_for_tick);
+    executed
+}
+
+/// Cadence of the block production task.
+#[derive(Debug, Clone)]
+pub struct BlockProductionConfig {
+    /// A block is closed every `block_time`.
+    pub block_time: Duration,
+    /// When `false`, a block without transactions is not closed at its deadline: it stays open for another
+    /// `block_time`, until it holds at least one transaction.
+    pub allow_empty_blocks: bool,
+    /// The pending block is updated with the newly arrived mempool transactions every `pending_update_interval`.
+    pub pending_update_interval: Duration,
+}
+
+impl BlockProductionConfig {
+    /// Uses the block time and pending block update time of the chain config, and allows empty blocks.
+    pub fn from_chain_config(chain_config: &ChainConfig) -> Self {
+        Self {
+            block_time: chain_config.block_time,
+            allow_empty_blocks: true,
+            pending_update_interval: chain_config.pending_block_update_time,
+        }
+    }
+
+    /// Number of pending ticks (see [`BlockProductionConfig::pending_update_interval`]) in a block.
+    pub fn n_pending_ticks_per_block(&self) -> usize {
+        (self.block_time.as_millis() / self.pending_update_interval.as_millis()) as usize
+    }
+}
+
 /// The block production task consumes transactions from the mempool in batches.
 /// This is to allow optimistic concurrency. However, the block may get full during batch execution,
 /// and we need to re-add the transactions back into the mempool.
@@ -160,6 +400,14 @@ pub struct BlockProductionTask {
     executor: TransactionExecutor<BlockifierStateAdapter>,
     l1_data_provider: Arc<dyn L1DataProvider>,
     current_pending_tick: usize,
+    config: BlockProductionConfig,
+    /// Per-block bouncer caps. The executor's bouncer only gets a fraction of them during pending ticks.
+    bouncer_config: BouncerConfig,
+    /// Serialized size of the transactions and receipts of the pending block, updated as they are added to it.
+    block_size: u64,
+    max_block_size: u64,
+    /// Latest state of the pending block, for [`BlockPreviewHandle`]s.
+    snapshot: watch::Sender<Option<Arc<PendingBlockSnapshot>>>,
 }
 
 impl BlockProductionTask {
@@ -167,12 +415,14 @@ impl BlockProductionTask {
         backend: Arc<DeoxysBackend>,
         mempool: Arc<Mempool>,
         l1_data_provider: Arc<dyn L1DataProvider>,
+        config: BlockProductionConfig,
     ) -> Result<Self, Error> {
-        let parent_block_hash = backend.get_block_hash(&BlockId::Tag(BlockTag::Latest))?.ok_or(Error::NoGenesis)?;
+        let (parent_block_hash, _) = backend.chain_head().get().latest.ok_or(Error::NoGenesis)?;
         let pending_block = DeoxysPendingBlock::new_empty(make_pending_header(
             parent_block_hash,
             backend.chain_config(),
             l1_data_provider.as_ref(),
+            mempool.clock().as_ref(),
         ));
         // NB: we cannot continue a previously started pending block yet.
         // let pending_block = backend.get_or_create_pending_block(|| CreatePendingBlockExtraInfo {
@@ -183,9 +433,15 @@ impl BlockProductionTask {
             ExecutionContext::new(Arc::clone(&backend), &pending_block.info.clone().into())?.tx_executor();
 
         let bouncer_config = backend.chain_config().bouncer_config.clone();
-        executor.bouncer = Bouncer::new(bouncer_config);
+        executor.bouncer = Bouncer::new(bouncer_config.clone());
+        let max_block_size = backend.chain_config().max_block_size as u64;
+
+        // The L1 data snapshot is pinned for the whole block: store the pending header right away so that fee
+        // estimation and mempool validation against the pending block see the same gas prices as block execution.
+        backend.store_block(pending_block.clone().into(), StateDiff::default(), vec![])?;
 
-        Ok(Self {
+        let task = Self {
+            block_size: stored_size(&pending_block.inner),
             backend,
             mempool,
             executor,
@@ -193,71 +449,100 @@ impl BlockProductionTask {
             block: pending_block,
             declared_classes: vec![],
             l1_data_provider,
-        })
+            config,
+            bouncer_config,
+            max_block_size,
+            snapshot: watch::channel(None).0,
+        };
+        task.publish_snapshot(StateDiff::default());
+        Ok(task)
     }
 
-    fn continue_block(&mut self, bouncer_cap: BouncerWeights) -> Result<StateDiff, Error> {
-        self.executor.bouncer.bouncer_config.block_max_capacity = bouncer_cap;
-
-        let mut txs_to_process = Vec::with_capacity(TX_BATCH_SIZE);
-        self.mempool.take_txs_chunk(&mut txs_to_process, TX_BATCH_SIZE);
+    /// A read-only view of the block being produced, which stays valid across blocks.
+    pub fn preview_handle(&self) -> BlockPreviewHandle {
+        BlockPreviewHandle::new(
+            Arc::clone(&self.backend),
+            self.snapshot.subscribe(),
+            self.bouncer_config.block_max_capacity,
+            self.max_block_size,
+            self.backend.chain_config().chain_id.clone().to_felt(),
+            Arc::clone(self.mempool.clock()),
+        )
+    }
 
-        let blockifier_txs: Vec<_> =
-            txs_to_process.iter().map(|tx| Transaction::AccountTransaction(clone_account_tx(&tx.tx))).collect();
+    /// Publishes the current pending block for the [`BlockPreviewHandle`]s, along with its whole state diff.
+    fn publish_snapshot(&self, state_diff: StateDiff) {
+        self.snapshot.send_replace(Some(Arc::new(PendingBlockSnapshot {
+            block: self.block.clone(),
+            state_diff,
+            block_n: self.block_n(),
+            bouncer_weights: *self.executor.bouncer.get_accumulated_weights(),
+            block_size: self.block_size,
+        })));
+    }
 
-        // Execute the transactions.
-        let all_results = self.executor.execute_txs(&blockifier_txs);
+    fn continue_block(&mut self, bouncer_cap: BouncerWeights) -> Result<StateDiff, Error> {
+        self.executor.bouncer.bouncer_config.block_max_capacity = bouncer_cap;
+        let at_full_capacity = bouncer_cap == self.bouncer_config.block_max_capacity;
 
-        // Split the `txs_to_process` vec into two iterators.
-        let mut to_process_iter = txs_to_process.into_iter();
-        // This iterator will consume the first part of `to_process_iter`.
-        let consumed_txs_to_process = to_process_iter.by_ref().take(all_results.len());
+        let mut size_budget =
+            SizeBudget { remaining: self.max_block_size.saturating_sub(self.block_size), max: self.max_block_size };
+        let executor = &mut self.executor;
+        let executed_l1_handlers =
+            fill_block_l1_handlers(&self.mempool, at_full_capacity, &mut size_budget, |txs| executor.execute_txs(txs));
+        let executed_txs =
+            fill_block(&self.mempool, at_full_capacity, &mut size_budget, |txs| executor.execute_txs(txs));
 
         let on_top_of = self.executor.block_state.as_ref().unwrap().state.on_top_of_block_id;
-        let executed_txs: Vec<_> = consumed_txs_to_process.collect();
-        let (state_diff, _visited_segments, _weights) =
-            finalize_execution_state(&executed_txs, &mut self.executor, &self.backend, &on_top_of)?;
+        let (state_diff, _visited_segments, weights) =
+            finalize_execution_state(&mut self.executor, &self.backend, &on_top_of)?;
 
-        let n_executed_txs = executed_txs.len();
-
-        for (exec_result, mempool_tx) in Iterator::zip(all_results.into_iter(), executed_txs) {
-            match exec_result {
-                Ok(execution_info) => {
-                    // Note: reverted txs also appear as Ok here.
-                    log::debug!("Successful execution of transaction {:?}", mempool_tx.tx_hash());
+        let n_executed_txs = executed_l1_handlers.len() + executed_txs.len();
 
-                    if let Some(class) = mempool_tx.converted_class {
-                        self.declared_classes.push(class);
-                    }
+        for (l1_handler_tx, execution_info) in executed_l1_handlers {
+            self.push_executed_l1_handler_tx(&l1_handler_tx, &execution_info);
+        }
 
-                    self.block.inner.receipts.push(from_blockifier_execution_info(
-                        &execution_info,
-                        &Transaction::AccountTransaction(clone_account_tx(&mempool_tx.tx)),
-                    ));
-                    let converted_tx = TransactionWithHash::from(mempool_tx.tx);
-                    self.block.info.tx_hashes.push(converted_tx.hash);
-                    self.block.inner.transactions.push(converted_tx.transaction);
-                }
-                Err(err) => {
-                    // TODO: revert handling
-                    log::error!("Unsuccessful execution of transaction {:?}: {err:#}", mempool_tx.tx_hash());
-                }
+        for (mempool_tx, execution_info) in executed_txs {
+            if let Some(class) = mempool_tx.converted_class {
+                self.declared_classes.push(class);
             }
+
+            let receipt = from_blockifier_execution_info(
+                &execution_info,
+                &Transaction::AccountTransaction(clone_account_tx(&mempool_tx.tx)),
+            );
+            self.push_tx(TransactionWithHash::from(mempool_tx.tx), receipt);
         }
 
         log::debug!(
-            "Finished tick with {} new transactions, now at {}",
+            "Finished tick with {} new transactions, now at {} ({} bytes, accumulated bouncer weights: {:?})",
             n_executed_txs,
-            self.block.inner.transactions.len()
+            self.block.inner.transactions.len(),
+            self.block_size,
+            weights
         );
 
-        // This contains the rest of `to_process_iter`.
-        let rest_txs_to_process: Vec<_> = to_process_iter.collect();
+        Ok(state_diff)
+    }
 
-        // Add back the unexecuted transactions to the mempool.
-        self.mempool.re_add_txs(rest_txs_to_process);
+    fn push_executed_l1_handler_tx(
+        &mut self,
+        l1_handler_tx: &MempoolL1HandlerTransaction,
+        execution_info: &TransactionExecutionInfo,
+    ) {
+        let receipt = from_blockifier_execution_info(
+            execution_info,
+            &Transaction::L1HandlerTransaction(l1_handler_tx.to_blockifier()),
+        );
+        self.push_tx(TransactionWithHash::from(l1_handler_tx.to_blockifier()), receipt);
+    }
 
-        Ok(state_diff)
+    fn push_tx(&mut self, tx: TransactionWithHash, receipt: TransactionReceipt) {
+        self.block_size += stored_size(&tx.transaction) + stored_size(&receipt);
+        self.block.inner.receipts.push(receipt);
+        self.block.info.tx_hashes.push(tx.hash);
+        self.block.inner.transactions.push(tx.transaction);
     }
 
     /// Each "tick" of the block time updates the pending block but only with the appropriate fraction of the total bouncer capacity.
@@ -265,7 +550,7 @@ impl BlockProductionTask {
         let current_pending_tick = self.current_pending_tick;
         self.current_pending_tick += 1;
 
-        let n_pending_ticks_per_block = self.backend.chain_config().n_pending_ticks_per_block();
+        let n_pending_ticks_per_block = self.config.n_pending_ticks_per_block();
 
         if current_pending_tick == 0 || current_pending_tick >= n_pending_ticks_per_block {
             // first tick is ignored.
@@ -275,32 +560,11 @@ impl BlockProductionTask {
         log::debug!("begin pending tick {}/{}", current_pending_tick, n_pending_ticks_per_block);
 
         // Reduced bouncer capacity for the current pending tick
-
-        let config_bouncer = self.executor.bouncer.bouncer_config.block_max_capacity;
-        let frac = n_pending_ticks_per_block / current_pending_tick; // div by zero: current_pending_tick has been checked for 0 above
-
-        log::debug!("frac for this tick: {:.2}", 1f64 / frac as f64);
-        let bouncer_cap = BouncerWeights {
-            builtin_count: BuiltinCount {
-                add_mod: config_bouncer.builtin_count.add_mod / frac,
-                bitwise: config_bouncer.builtin_count.bitwise / frac,
-                ecdsa: config_bouncer.builtin_count.ecdsa / frac,
-                ec_op: config_bouncer.builtin_count.ec_op / frac,
-                keccak: config_bouncer.builtin_count.keccak / frac,
-                mul_mod: config_bouncer.builtin_count.mul_mod / frac,
-                pedersen: config_bouncer.builtin_count.pedersen / frac,
-                poseidon: config_bouncer.builtin_count.poseidon / frac,
-                range_check: config_bouncer.builtin_count.range_check / frac,
-                range_check96: config_bouncer.builtin_count.range_check96 / frac,
-            },
-            gas: config_bouncer.gas / frac,
-            message_segment_length: config_bouncer.message_segment_length / frac,
-            n_events: config_bouncer.n_events / frac,
-            n_steps: config_bouncer.n_steps / frac,
-            state_diff_size: config_bouncer.state_diff_size / frac,
-        };
+        let bouncer_cap =
+            tick_capacity(&self.bouncer_config.block_max_capacity, current_pending_tick, n_pending_ticks_per_block);
 
         let state_diff = self.continue_block(bouncer_cap)?;
+        self.publish_snapshot(state_diff.clone());
 
         // Store pending block
         self.backend.store_block(self.block.clone().into(), state_diff, self.declared_classes.clone())?;
@@ -313,7 +577,15 @@ impl BlockProductionTask {
         log::debug!("closing block #{}", block_n);
 
         // Complete the block with full bouncer capacity.
-        let new_state_diff = self.continue_block(self.executor.bouncer.bouncer_config.block_max_capacity)?;
+        let new_state_diff = self.continue_block(self.bouncer_config.block_max_capacity)?;
+
+        if !self.config.allow_empty_blocks && self.block.inner.transactions.is_empty() {
+            log::debug!("block #{} is empty, keeping it open for another block time", block_n);
+            // Pending ticks start over, so that transactions arriving from now on get included progressively again.
+            self.current_pending_tick = 0;
+            self.publish_snapshot(new_state_diff);
+            return Ok(());
+        }
 
         // Convert the pending block to a closed block and save to db.
 
@@ -322,22 +594,33 @@ impl BlockProductionTask {
             parent_block_hash,
             self.backend.chain_config(),
             self.l1_data_provider.as_ref(),
+            self.mempool.clock().as_ref(),
         ));
 
-        let block_to_close = mem::replace(&mut self.block, new_empty_block);
+        let mut block_to_close = mem::replace(&mut self.block, new_empty_block);
+        // The block was opened one block time ago: its timestamp is its close time.
+        block_to_close.info.header.block_timestamp = self.mempool.clock().unix_timestamp();
         let declared_classes = mem::take(&mut self.declared_classes);
 
         // This is compute heavy as it does the commitments and trie computations.
         let chain_id = self.backend.chain_config().chain_id.clone().to_felt();
-        let closed_block = close_block(&self.backend, block_to_close, &new_state_diff, chain_id, block_n);
+        // The block previews commit to the tries and revert, this must not happen until the block is stored.
+        let tries_update = self.backend.lock_tries_update();
+        let closed_block = close_block(&self.backend, block_to_close, &new_state_diff, chain_id, block_n)?;
         self.block.info.header.parent_block_hash = closed_block.info.block_hash; // fix temp parent block hash for new pending :)
 
         self.backend.store_block(closed_block.into(), new_state_diff, declared_classes)?;
+        drop(tries_update);
 
         // Prepare for next block.
         self.executor =
             ExecutionContext::new(Arc::clone(&self.backend), &self.block.info.clone().into())?.tx_executor();
         self.current_pending_tick = 0;
+        self.block_size = stored_size(&self.block.inner);
+
+        // Storing the closed block cleared the pending block, pin the new one with its fresh L1 data snapshot.
+        self.backend.store_block(self.block.clone().into(), StateDiff::default(), vec![])?;
+        self.publish_snapshot(StateDiff::default());
 
         Ok(())
     }
@@ -345,11 +628,10 @@ impl BlockProductionTask {
     pub async fn block_production_task(&mut self) -> Result<(), anyhow::Error> {
         let start = tokio::time::Instant::now();
 
-        let mut interval_block_time = tokio::time::interval_at(start, self.backend.chain_config().block_time);
+        let mut interval_block_time = tokio::time::interval_at(start, self.config.block_time);
         interval_block_time.reset(); // do not fire the first tick immediately
         interval_block_time.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
-        let mut interval_pending_block_update =
-            tokio::time::interval_at(start, self.backend.chain_config().pending_block_update_time);
+        let mut interval_pending_block_update = tokio::time::interval_at(start, self.config.pending_update_interval);
         interval_pending_block_update.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
         log::info!("⛏️  Starting block production on top of block {}", self.block_n());
@@ -377,3 +659,695 @@ impl BlockProductionTask {
         self.executor.block_context.block_info().block_number.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockifier::context::BlockContext;
+    use blockifier::transaction::account_transaction::AccountTransaction;
+    use blockifier::transaction::transaction_types::TransactionType;
+    use blockifier::transaction::transactions::InvokeTransaction;
+    use dc_db::DatabaseService;
+    use dc_eth::l1_messaging::L1HandlerSubmitter;
+    use dp_block::header::{GasPrices, L1DataAvailabilityMode};
+    use dp_block::{BlockId, BlockN, BlockTag, BlockVerification, DeoxysBlockInner, StarknetVersion};
+    use dp_convert::TryFromFelt;
+    use dp_receipt::{Event, ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit};
+    use dp_transactions::broadcasted_to_blockifier;
+    use dp_utils::clock::MockClock;
+    use starknet_api::core::{ContractAddress, Nonce};
+    use starknet_api::data_availability::DataAvailabilityMode;
+    use starknet_api::transaction::{Fee, InvokeTransactionV3, TransactionHash};
+    use starknet_core::types::{BroadcastedInvokeTransaction, BroadcastedInvokeTransactionV1, BroadcastedTransaction};
+    use starknet_core::utils::{get_selector_from_name, get_storage_var_address};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    use crate::genesis::{GenesisBuilder, ERC20_BALANCES_VAR};
+    use crate::testing::{
+        test_account_class, test_erc20_class, TEST_ACCOUNT_BALANCE, TEST_ACCOUNT_CLASS_HASH, TEST_ERC20_CLASS_HASH,
+    };
+
+    struct MockL1DataProvider(Mutex<GasPrices>);
+
+    impl MockL1DataProvider {
+        fn set_gas_prices(&self, gas_prices: GasPrices) {
+            *self.0.lock().expect("Poisoned lock") = gas_prices;
+        }
+    }
+
+    impl L1DataProvider for MockL1DataProvider {
+        fn get_gas_prices(&self) -> GasPrices {
+            self.0.lock().expect("Poisoned lock").clone()
+        }
+        fn get_da_mode(&self) -> L1DataAvailabilityMode {
+            L1DataAvailabilityMode::Calldata
+        }
+    }
+
+    fn gas_prices(price: u128) -> GasPrices {
+        GasPrices {
+            eth_l1_gas_price: price,
+            strk_l1_gas_price: price,
+            eth_l1_data_gas_price: price,
+            strk_l1_data_gas_price: price,
+        }
+    }
+
+    /// The execution context used by fee estimation and mempool validation on top of the pending block.
+    fn pending_execution_context(backend: &Arc<DeoxysBackend>) -> ExecutionContext {
+        let block_info = backend.get_block_info(&DbBlockId::Pending).unwrap().expect("No pending block");
+        ExecutionContext::new(Arc::clone(backend), &block_info).unwrap()
+    }
+
+    fn assert_same_gas_prices(estimate: &BlockContext, execution: &BlockContext) {
+        let (estimate, execution) = (&estimate.block_info().gas_prices, &execution.block_info().gas_prices);
+        assert_eq!(estimate.eth_l1_gas_price, execution.eth_l1_gas_price);
+        assert_eq!(estimate.strk_l1_gas_price, execution.strk_l1_gas_price);
+        assert_eq!(estimate.eth_l1_data_gas_price, execution.eth_l1_data_gas_price);
+        assert_eq!(estimate.strk_l1_data_gas_price, execution.strk_l1_data_gas_price);
+    }
+
+    #[tokio::test]
+    async fn pending_block_l1_data_is_pinned() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = Arc::clone(db.backend());
+        let l1_data_provider = Arc::new(MockL1DataProvider(Mutex::new(gas_prices(100))));
+
+        let genesis = DeoxysPendingBlock::new_empty(make_pending_header(
+            Felt::ZERO,
+            &chain_config,
+            l1_data_provider.as_ref(),
+            &MockClock::new(CLOCK_START),
+        ));
+        let genesis =
+            close_block(&backend, genesis, &StateDiff::default(), chain_config.chain_id.clone().to_felt(), 0).unwrap();
+        backend.store_block(genesis.into(), StateDiff::default(), vec![]).unwrap();
+
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), l1_data_provider.clone()));
+        let config = BlockProductionConfig::from_chain_config(&chain_config);
+        let mut task =
+            BlockProductionTask::new(Arc::clone(&backend), mempool, l1_data_provider.clone(), config).unwrap();
+
+        // The pending block is visible with its snapshot as soon as it is created.
+        let pending = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        assert_eq!(pending.as_pending().unwrap().header.l1_gas_price, gas_prices(100));
+
+        // L1 gas prices move while the block is being built.
+        l1_data_provider.set_gas_prices(gas_prices(200));
+        task.update_pending_block_tick().unwrap();
+        task.update_pending_block_tick().unwrap();
+
+        let pending = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        assert_eq!(pending.as_pending().unwrap().header.l1_gas_price, gas_prices(100));
+        assert_same_gas_prices(
+            &pending_execution_context(&backend).tx_executor().block_context,
+            &task.executor.block_context,
+        );
+
+        // Closing the block takes a new snapshot for the next pending block.
+        task.produce_block_tick().unwrap();
+
+        let pending = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        assert_eq!(pending.as_pending().unwrap().header.l1_gas_price, gas_prices(200));
+        assert_same_gas_prices(
+            &pending_execution_context(&backend).tx_executor().block_context,
+            &task.executor.block_context,
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_block_header_follows_the_chain_spec() {
+        let spec = r#"
+            chain_name = "Appchain"
+            chain_id = "MY_APPCHAIN"
+            native_fee_token_address = "0x1"
+            parent_fee_token_address = "0x2"
+            sequencer_address = "0x123"
+            eth_core_contract_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+            latest_protocol_version = "0.13.1"
+        "#;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::from_toml(spec).unwrap());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = Arc::clone(db.backend());
+        let l1_data_provider = Arc::new(MockL1DataProvider(Mutex::new(gas_prices(100))));
+        let chain_id = Felt::from_bytes_be_slice(b"MY_APPCHAIN");
+
+        let genesis = DeoxysPendingBlock::new_empty(make_pending_header(
+            Felt::ZERO,
+            &chain_config,
+            l1_data_provider.as_ref(),
+            &MockClock::new(CLOCK_START),
+        ));
+        let genesis = close_block(&backend, genesis, &StateDiff::default(), chain_id, 0).unwrap();
+        backend.store_block(genesis.into(), StateDiff::default(), vec![]).unwrap();
+
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), l1_data_provider.clone()));
+        let config = BlockProductionConfig::from_chain_config(&chain_config);
+        let mut task =
+            BlockProductionTask::new(Arc::clone(&backend), mempool, l1_data_provider.clone(), config).unwrap();
+
+        let pending = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        let header = &pending.as_pending().unwrap().header;
+        assert_eq!(header.sequencer_address, Felt::from(0x123));
+        assert_eq!(header.protocol_version, StarknetVersion::STARKNET_VERSION_0_13_1);
+
+        // The chain id of the spec is in the hash of the produced block.
+        task.produce_block_tick().unwrap();
+        let block = backend.get_block_info(&BlockId::Number(1)).unwrap().unwrap();
+        let block = block.as_nonpending().unwrap();
+        assert_eq!(block.header.sequencer_address, Felt::from(0x123));
+        assert_eq!(block.block_hash, block.header.compute_hash(chain_id));
+    }
+
+    /// Unix timestamp of the mock clock of the tests, when they start.
+    const CLOCK_START: u64 = 1_700_000_000;
+
+    /// A block production task on top of a genesis block, reading the time from the returned clock.
+    async fn test_block_production(
+        config: BlockProductionConfig,
+    ) -> (tempfile::TempDir, BlockProductionTask, Arc<MockClock>) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = Arc::clone(db.backend());
+        let l1_data_provider = Arc::new(MockL1DataProvider(Mutex::new(gas_prices(100))));
+
+        let genesis = DeoxysPendingBlock::new_empty(make_pending_header(
+            Felt::ZERO,
+            &chain_config,
+            l1_data_provider.as_ref(),
+            &MockClock::new(CLOCK_START),
+        ));
+        let genesis =
+            close_block(&backend, genesis, &StateDiff::default(), chain_config.chain_id.clone().to_felt(), 0).unwrap();
+        backend.store_block(genesis.into(), StateDiff::default(), vec![]).unwrap();
+
+        let clock = Arc::new(MockClock::new(CLOCK_START));
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), l1_data_provider.clone()).with_clock(clock.clone()));
+        (temp_dir, BlockProductionTask::new(backend, mempool, l1_data_provider, config).unwrap(), clock)
+    }
+
+    /// Runs the block production loop for `duration`, on the paused tokio clock.
+    async fn run_block_production(task: &mut BlockProductionTask, duration: Duration) {
+        let res = tokio::time::timeout(duration, task.block_production_task()).await;
+        assert!(res.is_err(), "Block production task stopped early: {res:?}");
+    }
+
+    fn config(allow_empty_blocks: bool) -> BlockProductionConfig {
+        BlockProductionConfig {
+            block_time: Duration::from_secs(10),
+            allow_empty_blocks,
+            pending_update_interval: Duration::from_secs(2),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn empty_blocks_are_produced_when_allowed() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(true)).await;
+
+        run_block_production(&mut task, Duration::from_secs(35)).await;
+
+        assert_eq!(task.backend.get_latest_block_n().unwrap(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn devnet_transfer_is_produced() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        // The ERC20 contract refuses the transfers of the fees to the zero address.
+        let sequencer_address = ContractAddress::try_from_felt(Felt::from_hex_unchecked("0x5e9")).unwrap();
+        let chain_config = Arc::new(ChainConfig { sequencer_address, ..ChainConfig::test_config() });
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = Arc::clone(db.backend());
+        let l1_data_provider = Arc::new(MockL1DataProvider(Mutex::new(gas_prices(1))));
+        let eth = chain_config.parent_fee_token_address.to_felt();
+        let fee_tokens = [eth, chain_config.native_fee_token_address.to_felt()];
+
+        let genesis = GenesisBuilder::new()
+            .declare(test_account_class())
+            .declare(test_erc20_class())
+            .deploy(fee_tokens[0], TEST_ERC20_CLASS_HASH)
+            .deploy(fee_tokens[1], TEST_ERC20_CLASS_HASH)
+            .with_devnet_accounts(0, 2, TEST_ACCOUNT_CLASS_HASH, &fee_tokens, TEST_ACCOUNT_BALANCE);
+        let [sender, recipient] = [0, 1].map(|index| genesis.accounts()[index].address);
+        genesis.store(&backend, l1_data_provider.as_ref(), &MockClock::new(CLOCK_START)).unwrap();
+
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), l1_data_provider.clone()));
+        let config = BlockProductionConfig::from_chain_config(&chain_config);
+        let mut task =
+            BlockProductionTask::new(Arc::clone(&backend), Arc::clone(&mempool), l1_data_provider, config).unwrap();
+
+        let amount = 1_000u128;
+        let selector = get_selector_from_name("transfer").unwrap();
+        let transfer = BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+            sender_address: sender,
+            // The call of the account to the fee token, with the recipient and the `u256` amount as its calldata.
+            calldata: vec![eth, selector, Felt::THREE, recipient, Felt::from(amount), Felt::ZERO],
+            max_fee: Felt::from(10u128.pow(15)),
+            signature: vec![],
+            nonce: Felt::ZERO,
+            is_query: false,
+        });
+        let chain_id = chain_config.chain_id.clone().to_felt();
+        let (Transaction::AccountTransaction(tx), _) =
+            broadcasted_to_blockifier(BroadcastedTransaction::Invoke(transfer), chain_id, None).unwrap()
+        else {
+            unreachable!("An invoke transaction is an account transaction")
+        };
+        mempool.accept_account_tx(tx, None, None).unwrap();
+
+        task.produce_block_tick().unwrap();
+        assert_eq!(mempool.tx_count(), 0);
+
+        let receipts = backend.get_block_receipts(&BlockId::Number(1)).unwrap().unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].execution_result(), &ExecutionResult::Succeeded);
+        let fee = receipts[0].actual_fee();
+        assert_eq!(fee.unit, PriceUnit::Wei);
+        assert_ne!(fee.amount, Felt::ZERO);
+
+        let latest = BlockId::Tag(BlockTag::Latest);
+        let balance = |address| {
+            let key = get_storage_var_address(ERC20_BALANCES_VAR, &[address]).unwrap();
+            backend.get_contract_storage_at(&latest, &eth, &key).unwrap()
+        };
+        assert_eq!(balance(sender), Some(Felt::from(TEST_ACCOUNT_BALANCE - amount) - fee.amount));
+        assert_eq!(balance(recipient), Some(Felt::from(TEST_ACCOUNT_BALANCE + amount)));
+        assert_eq!(balance(chain_config.sequencer_address.to_felt()), Some(fee.amount));
+        assert_eq!(backend.get_contract_nonce_at(&latest, &sender).unwrap(), Some(Felt::ONE));
+        assert_eq!(backend.get_contract_nonce_at(&latest, &recipient).unwrap(), None);
+    }
+
+    fn block_timestamp(task: &BlockProductionTask, block_n: u64) -> u64 {
+        let block = task.backend.get_block_info(&DbBlockId::BlockN(BlockN(block_n))).unwrap().unwrap();
+        block.as_nonpending().unwrap().header.block_timestamp
+    }
+
+    #[tokio::test]
+    async fn block_timestamp_is_the_close_time() {
+        let (_temp_dir, mut task, clock) = test_block_production(config(true)).await;
+        assert_eq!(task.block.info.header.block_timestamp, CLOCK_START);
+
+        clock.advance(Duration::from_secs(10));
+        task.produce_block_tick().unwrap();
+        assert_eq!(block_timestamp(&task, 1), CLOCK_START + 10);
+        // The next block is opened right away.
+        assert_eq!(task.block.info.header.block_timestamp, CLOCK_START + 10);
+
+        clock.advance(Duration::from_secs(12));
+        task.produce_block_tick().unwrap();
+        assert_eq!(block_timestamp(&task, 2), CLOCK_START + 22);
+    }
+
+    #[tokio::test]
+    async fn preview_timestamp_is_the_current_time() {
+        let (_temp_dir, mut task, clock) = test_block_production(config(true)).await;
+        let preview_handle = task.preview_handle();
+
+        clock.advance(Duration::from_secs(4));
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.header.block_timestamp, CLOCK_START + 4);
+        // The pending block itself keeps its open time.
+        assert_eq!(task.block.info.header.block_timestamp, CLOCK_START);
+
+        // Closed at the same time, the block is the one of the preview.
+        task.produce_block_tick().unwrap();
+        assert_eq!(block_timestamp(&task, 1), preview.header.block_timestamp);
+        let closed = task.backend.get_block_info(&DbBlockId::BlockN(BlockN(1))).unwrap().unwrap();
+        assert_eq!(closed.as_nonpending().unwrap().block_hash, preview.block_hash);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn empty_blocks_are_not_produced_when_disallowed() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(false)).await;
+
+        run_block_production(&mut task, Duration::from_secs(35)).await;
+
+        assert_eq!(task.backend.get_latest_block_n().unwrap(), Some(0));
+        assert!(task.backend.get_block_info(&DbBlockId::Pending).unwrap().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_block_is_updated_at_interval() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(true)).await;
+
+        // Pending ticks at 0s, 2s, 4s and 6s.
+        run_block_production(&mut task, Duration::from_secs(7)).await;
+        assert_eq!(task.current_pending_tick, 4);
+        assert_eq!(task.backend.get_latest_block_n().unwrap(), Some(0));
+    }
+
+    /// Adds a synthetic invoke transaction with one event to the pending block, as if it had been executed.
+    fn push_executed_tx(block: &mut DeoxysPendingBlock, n: u64, actual_fee: FeePayment) {
+        let tx = dp_transactions::Transaction::Invoke(dp_transactions::InvokeTransaction::V1(
+            dp_transactions::InvokeTransactionV1 {
+                sender_address: Felt::from(n),
+                calldata: vec![Felt::from(n)],
+                max_fee: Felt::from(1000),
+                signature: vec![],
+                nonce: Felt::ZERO,
+            },
+        ));
+        let tx_hash = Felt::from(0x100 + n);
+        block.inner.receipts.push(TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            transaction_hash: tx_hash,
+            actual_fee,
+            messages_sent: vec![],
+            events: vec![Event { from_address: Felt::from(n), keys: vec![Felt::ONE], data: vec![Felt::from(n)] }],
+            execution_resources: Default::default(),
+            execution_result: ExecutionResult::Succeeded,
+        }));
+        block.info.tx_hashes.push(tx_hash);
+        block.inner.transactions.push(tx);
+    }
+
+    #[tokio::test]
+    async fn preview_matches_closed_block() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(true)).await;
+        let preview_handle = task.preview_handle();
+
+        // The preview is available as soon as the pending block is created.
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.block_number, 1);
+        assert_eq!(preview.commitments.transaction_count, 0);
+
+        push_executed_tx(&mut task.block, 1, FeePayment { amount: Felt::from(100), unit: PriceUnit::Wei });
+        push_executed_tx(&mut task.block, 2, FeePayment { amount: Felt::from(50), unit: PriceUnit::Fri });
+        push_executed_tx(&mut task.block, 3, FeePayment { amount: Felt::from(20), unit: PriceUnit::Wei });
+        // The first pending tick is skipped.
+        task.update_pending_block_tick().unwrap();
+        task.update_pending_block_tick().unwrap();
+
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.block_number, 1);
+        assert_eq!(preview.fees_wei, Felt::from(120));
+        assert_eq!(preview.fees_fri, Felt::from(50));
+        assert_eq!(preview.block_max_capacity, task.bouncer_config.block_max_capacity);
+        assert_eq!(preview.bouncer_weights, *task.executor.bouncer.get_accumulated_weights());
+
+        task.produce_block_tick().unwrap();
+
+        let closed = task.backend.get_block_info(&DbBlockId::BlockN(BlockN(1))).unwrap().unwrap();
+        let closed = closed.as_nonpending().unwrap();
+        let (header, commitments) = (&closed.header, &preview.commitments);
+        assert_eq!(header.parent_block_hash, preview.header.parent_block_hash);
+        assert_eq!(header.block_timestamp, preview.header.block_timestamp);
+        assert_eq!(header.sequencer_address, preview.header.sequencer_address);
+        assert_eq!(header.protocol_version, preview.header.protocol_version);
+        assert_eq!(header.l1_gas_price, preview.header.l1_gas_price);
+        assert_eq!(header.transaction_count, 3);
+        assert_eq!(header.transaction_count, commitments.transaction_count);
+        assert_eq!(header.transaction_commitment, commitments.transaction_commitment);
+        assert_eq!(header.event_count, commitments.event_count);
+        assert_eq!(header.event_commitment, commitments.event_commitment);
+        assert_eq!(header.receipt_commitment, commitments.receipt_commitment);
+        assert_eq!(header.state_diff_length, commitments.state_diff_length);
+        assert_eq!(header.state_diff_commitment, commitments.state_diff_commitment);
+        assert_eq!(closed.tx_hashes, commitments.tx_hashes);
+        assert_eq!(header.global_state_root, preview.global_state_root);
+        assert_eq!(closed.block_hash, preview.block_hash);
+
+        // The preview follows the next pending block.
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.block_number, 2);
+        assert_eq!(preview.header.parent_block_hash, closed.block_hash);
+        assert_eq!(preview.commitments.transaction_count, 0);
+        assert_eq!(preview.fees_wei, Felt::ZERO);
+    }
+
+    #[tokio::test]
+    async fn l1_handler_tx_reaches_closed_block() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(true)).await;
+        let tx = dp_transactions::L1HandlerTransaction {
+            version: Felt::ZERO,
+            nonce: 7,
+            contract_address: Felt::from(0x1234),
+            entry_point_selector: Felt::from(0x5678),
+            calldata: vec![Felt::from(0xae), Felt::ONE, Felt::TWO],
+        };
+
+        // The L1 messaging worker hands the message over to the mempool.
+        let submitter: &dyn L1HandlerSubmitter = task.mempool.as_ref();
+        let tx_hash = submitter.submit_l1_handler_tx(tx.clone(), 1_000).unwrap();
+        assert_eq!(tx_hash, tx.compute_hash(task.backend.chain_config().chain_id.clone().to_felt(), false, false));
+        assert!(submitter.submit_l1_handler_tx(tx.clone(), 1_000).is_err());
+        let snapshot = task.mempool.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].tx_type, TransactionType::L1Handler);
+
+        // There is no contract to handle the message on the test chain: only the execution is mocked.
+        let executed = fill_block_l1_handlers(&task.mempool, true, &mut unlimited_size(), |txs| {
+            txs.iter().map(|_| Ok(TransactionExecutionInfo::default())).collect()
+        });
+        assert_eq!(executed.len(), 1);
+        assert!(task.mempool.snapshot().is_empty());
+        for (l1_handler_tx, execution_info) in &executed {
+            task.push_executed_l1_handler_tx(l1_handler_tx, execution_info);
+        }
+        task.produce_block_tick().unwrap();
+
+        let block = task.backend.get_block(&DbBlockId::BlockN(BlockN(1))).unwrap().unwrap();
+        assert_eq!(block.info.tx_hashes(), [tx_hash]);
+        // The node computed the header of the block it produced.
+        assert!(block.info.as_nonpending().unwrap().verification.contains(
+            BlockVerification::VERIFIED_HASH
+                | BlockVerification::VERIFIED_COMMITMENTS
+                | BlockVerification::VERIFIED_ROOT
+        ));
+        let TransactionReceipt::L1Handler(receipt) = &block.inner.receipts[0] else {
+            panic!("Not an L1 handler receipt")
+        };
+        assert_eq!(receipt.transaction_hash, tx_hash);
+        assert_eq!(
+            receipt.message_hash,
+            dp_receipt::l1_handler_message_hash(&tx.calldata, tx.contract_address, tx.entry_point_selector, tx.nonce)
+        );
+
+        // The message is now executed on L2.
+        assert_eq!(task.backend.get_l1_handler_tx_hash(7).unwrap(), Some(tx_hash));
+        assert!(matches!(
+            task.mempool.accept_l1_handler_tx(tx, Fee(1_000)),
+            Err(crate::Error::L1MessageAlreadyExecuted { nonce: 7, .. })
+        ));
+    }
+
+    async fn test_mempool() -> (tempfile::TempDir, Arc<Mempool>) {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        let l1_data_provider = Arc::new(MockL1DataProvider(Mutex::new(gas_prices(100))));
+        (temp_dir, Arc::new(Mempool::new(Arc::clone(db.backend()), l1_data_provider)))
+    }
+
+    /// A synthetic transaction from its own sender, arriving `n` seconds after the epoch.
+    fn invoke_tx(n: u64) -> MempoolTransaction {
+        let tx = AccountTransaction::Invoke(InvokeTransaction::new(
+            starknet_api::transaction::InvokeTransaction::V3(InvokeTransactionV3 {
+                resource_bounds: Default::default(),
+                tip: Default::default(),
+                signature: Default::default(),
+                nonce: Nonce(Felt::ZERO),
+                sender_address: ContractAddress::try_from(Felt::from(n)).unwrap(),
+                calldata: Default::default(),
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                paymaster_data: Default::default(),
+                account_deployment_data: Default::default(),
+            }),
+            TransactionHash(Felt::from(n)),
+        ));
+        MempoolTransaction {
+            tx,
+            arrived_at: SystemTime::UNIX_EPOCH + Duration::from_secs(n),
+            converted_class: None,
+            submitted: None,
+        }
+    }
+
+    /// Mimics the blockifier bouncer, using a known number of steps for every transaction.
+    struct StepsBouncer {
+        cap: usize,
+        used: usize,
+        steps: HashMap<Felt, usize>,
+    }
+
+    impl StepsBouncer {
+        fn new(cap: usize, steps: &[usize]) -> Self {
+            let steps = steps.iter().enumerate().map(|(i, steps)| (Felt::from(i as u64 + 1), *steps)).collect();
+            Self { cap, used: 0, steps }
+        }
+
+        fn execute_txs(&mut self, txs: &[Transaction]) -> Vec<TransactionExecutorResult<TransactionExecutionInfo>> {
+            let mut results = vec![];
+            for tx in txs {
+                let Transaction::AccountTransaction(tx) = tx else { unreachable!("Only account transactions") };
+                let steps = self.steps[&crate::tx_hash(tx).to_felt()];
+                if steps > self.cap {
+                    results.push(Err(TransactionExecutorError::TransactionExecutionError(
+                        TransactionExecutionError::TransactionTooLarge,
+                    )));
+                } else if self.used + steps > self.cap {
+                    // Block full.
+                    break;
+                } else {
+                    self.used += steps;
+                    results.push(Ok(TransactionExecutionInfo::default()));
+                }
+            }
+            results
+        }
+    }
+
+    fn unlimited_size() -> SizeBudget {
+        SizeBudget { remaining: u64::MAX, max: u64::MAX }
+    }
+
+    /// Fills the mempool with one transaction per entry of `steps`, and fills a block from it.
+    fn fill_block_with_steps(
+        mempool: &Mempool,
+        cap: usize,
+        steps: &[usize],
+        at_full_capacity: bool,
+    ) -> (Vec<Felt>, Vec<Felt>) {
+        fill_block_with_size_budget(mempool, cap, steps, at_full_capacity, &mut unlimited_size())
+    }
+
+    fn fill_block_with_size_budget(
+        mempool: &Mempool,
+        cap: usize,
+        steps: &[usize],
+        at_full_capacity: bool,
+        size_budget: &mut SizeBudget,
+    ) -> (Vec<Felt>, Vec<Felt>) {
+        for n in 1..=steps.len() as u64 {
+            mempool.inner.write().unwrap().insert_tx(invoke_tx(n), false).unwrap();
+        }
+        let mut bouncer = StepsBouncer::new(cap, steps);
+        let executed = fill_block(mempool, at_full_capacity, size_budget, |txs| bouncer.execute_txs(txs));
+
+        let executed = executed.iter().map(|(tx, _)| tx.tx_hash().to_felt()).collect();
+        let remaining = mempool.snapshot().iter().map(|tx| tx.tx_hash).collect();
+        (executed, remaining)
+    }
+
+    #[tokio::test]
+    async fn fill_block_stops_at_bouncer_cap() {
+        let (_temp_dir, mempool) = test_mempool().await;
+
+        let (executed, remaining) = fill_block_with_steps(&mempool, 100, &[30, 30, 30, 30, 10], true);
+        // The 4th transaction would exceed the cap: it and every following transaction go back to the mempool.
+        assert_eq!(executed, [1, 2, 3].map(Felt::from));
+        assert_eq!(remaining, [4, 5].map(Felt::from));
+    }
+
+    #[tokio::test]
+    async fn fill_block_spans_several_batches() {
+        let (_temp_dir, mempool) = test_mempool().await;
+
+        let n_txs = TX_BATCH_SIZE * 2;
+        let cap = TX_BATCH_SIZE + TX_BATCH_SIZE / 2;
+        let (executed, remaining) = fill_block_with_steps(&mempool, cap, &vec![1; n_txs], true);
+        assert_eq!(executed.len(), cap);
+        assert_eq!(remaining.len(), n_txs - cap);
+        assert_eq!(remaining.first(), Some(&Felt::from(cap as u64 + 1)));
+    }
+
+    #[tokio::test]
+    async fn fill_block_too_large_tx() {
+        // At full capacity, the transaction can never be included: it is dropped.
+        let (_temp_dir, mempool) = test_mempool().await;
+        let (executed, remaining) = fill_block_with_steps(&mempool, 100, &[10, 500, 10], true);
+        assert_eq!(executed, [1, 3].map(Felt::from));
+        assert!(remaining.is_empty());
+
+        // During a pending tick, it goes back to the mempool for a later tick.
+        let (_temp_dir, mempool) = test_mempool().await;
+        let (executed, remaining) = fill_block_with_steps(&mempool, 100, &[10, 500, 10], false);
+        assert_eq!(executed, [1, 3].map(Felt::from));
+        assert_eq!(remaining, [Felt::from(2)]);
+    }
+
+    #[tokio::test]
+    async fn fill_block_defers_past_size_cap() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        let tx_size = stored_size(&TransactionWithHash::from(invoke_tx(1).tx).transaction);
+
+        // Room for two transactions and a half, the bouncer would take them all.
+        let mut size_budget = SizeBudget { remaining: 2 * tx_size + tx_size / 2, max: 10 * tx_size };
+        let (executed, remaining) = fill_block_with_size_budget(&mempool, 100, &[10; 5], true, &mut size_budget);
+        assert_eq!(executed, [1, 2].map(Felt::from));
+        // Deferred to the next block.
+        assert_eq!(remaining, [3, 4, 5].map(Felt::from));
+        assert_eq!(size_budget.remaining, tx_size / 2);
+
+        // A transaction larger than the whole cap can never be included.
+        let (_temp_dir, mempool) = test_mempool().await;
+        let mut size_budget = SizeBudget { remaining: tx_size - 1, max: tx_size - 1 };
+        let (executed, remaining) = fill_block_with_size_budget(&mempool, 100, &[10; 2], true, &mut size_budget);
+        assert!(executed.is_empty());
+        assert!(remaining.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pending_block_size_is_capped() {
+        let (_temp_dir, mut task, _clock) = test_block_production(config(true)).await;
+        let submitter: &dyn L1HandlerSubmitter = task.mempool.as_ref();
+        let l1_handler_tx = |nonce| dp_transactions::L1HandlerTransaction {
+            version: Felt::ZERO,
+            nonce,
+            contract_address: Felt::from(0x1234),
+            entry_point_selector: Felt::from(0x5678),
+            calldata: vec![Felt::from(0xae); 1000],
+        };
+        for nonce in 0..3 {
+            submitter.submit_l1_handler_tx(l1_handler_tx(nonce), 1_000).unwrap();
+        }
+        let tx_size = stored_size(&dp_transactions::Transaction::L1Handler(l1_handler_tx(0)));
+
+        // The block is full after two transactions, the third one waits for the next block.
+        task.max_block_size = task.block_size + 2 * tx_size;
+        let preview_handle = task.preview_handle();
+        let mut size_budget = SizeBudget { remaining: task.max_block_size - task.block_size, max: task.max_block_size };
+        let executed = fill_block_l1_handlers(&task.mempool, false, &mut size_budget, |txs| {
+            txs.iter().map(|_| Ok(TransactionExecutionInfo::default())).collect()
+        });
+        assert_eq!(executed.len(), 2);
+        assert_eq!(task.mempool.snapshot().len(), 1);
+        for (l1_handler_tx, execution_info) in &executed {
+            task.push_executed_l1_handler_tx(l1_handler_tx, execution_info);
+        }
+        task.update_pending_block_tick().unwrap();
+        task.update_pending_block_tick().unwrap();
+
+        // The accounted size is the size of the stored block.
+        let pending = task.backend.get_block(&DbBlockId::Pending).unwrap().unwrap();
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.block_size, stored_size(&pending.inner));
+        assert_eq!(preview.max_block_size, task.max_block_size);
+        // Over the cap by the receipts only.
+        assert!(preview.block_size > task.max_block_size);
+        assert_eq!(pending.inner.transactions.len(), 2);
+
+        task.produce_block_tick().unwrap();
+        assert_eq!(task.mempool.snapshot().len(), 1);
+        let preview = preview_handle.preview().unwrap();
+        assert_eq!(preview.block_size, stored_size(&DeoxysBlockInner::new(vec![], vec![])));
+    }
+
+    #[test]
+    fn test_tick_capacity() {
+        let block_capacity = BouncerWeights { n_steps: 1000, gas: 10, ..BouncerConfig::max().block_max_capacity };
+        let cap = tick_capacity(&block_capacity, 3, 10);
+        assert_eq!(cap.n_steps, 300);
+        assert_eq!(cap.gas, 3);
+        assert_eq!(tick_capacity(&block_capacity, 10, 10), block_capacity);
+
+        // No overflow on unbounded capacities.
+        let cap = tick_capacity(&BouncerConfig::max().block_max_capacity, 1, 2);
+        assert_eq!(cap.n_steps, usize::MAX / 2);
+    }
+}
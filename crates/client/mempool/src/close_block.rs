@@ -1,10 +1,11 @@
 use dc_db::DeoxysBackend;
 use dc_sync::{
-    commitments::update_tries_and_compute_state_root,
+    commitments::{update_tries_and_compute_state_root, CommitmentError},
     convert::{compute_commitments_for_block, BlockCommitments},
 };
 use dp_block::{
-    header::PendingHeader, DeoxysBlock, DeoxysBlockInfo, DeoxysPendingBlock, DeoxysPendingBlockInfo, Header,
+    header::PendingHeader, BlockVerification, DeoxysBlock, DeoxysBlockInfo, DeoxysPendingBlock, DeoxysPendingBlockInfo,
+    Header,
 };
 use dp_state_update::StateDiff;
 use starknet_core::types::Felt;
@@ -15,11 +16,34 @@ pub fn close_block(
     state_diff: &StateDiff,
     chain_id: Felt,
     block_number: u64,
-) -> DeoxysBlock {
+) -> Result<DeoxysBlock, CommitmentError> {
     let DeoxysPendingBlock { info, inner } = block;
     let DeoxysPendingBlockInfo { header, tx_hashes: _tx_hashes } = info;
 
-    // Header
+    let (global_state_root, block_commitments) = rayon::join(
+        || update_tries_and_compute_state_root(backend, state_diff, block_number),
+        || compute_commitments_for_block(&inner, state_diff, header.protocol_version, chain_id, block_number),
+    );
+    let block_commitments = block_commitments?;
+    let header = closed_header(header, block_number, global_state_root, &block_commitments);
+    let tx_hashes = block_commitments.tx_hashes;
+
+    let block_hash = header.compute_hash(chain_id);
+
+    // The node computed all of the header itself. There is no signature to check on the blocks it produces.
+    let verification =
+        BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS | BlockVerification::VERIFIED_ROOT;
+
+    Ok(DeoxysBlock { info: DeoxysBlockInfo::new(header, tx_hashes, block_hash).with_verification(verification), inner })
+}
+
+/// The header of the pending block once closed as block `block_number`.
+pub(crate) fn closed_header(
+    header: PendingHeader,
+    block_number: u64,
+    global_state_root: Felt,
+    commitments: &BlockCommitments,
+) -> Header {
     let PendingHeader {
         parent_block_hash,
         sequencer_address,
@@ -28,12 +52,6 @@ pub fn close_block(
         l1_gas_price,
         l1_da_mode,
     } = header;
-
-    let (global_state_root, block_commitments) = rayon::join(
-        || update_tries_and_compute_state_root(backend, state_diff, block_number),
-        || compute_commitments_for_block(&inner, state_diff, protocol_version, chain_id, block_number),
-    );
-
     let BlockCommitments {
         transaction_commitment,
         transaction_count,
@@ -42,10 +60,10 @@ pub fn close_block(
         receipt_commitment,
         state_diff_commitment,
         state_diff_length,
-        tx_hashes,
-    } = block_commitments;
+        tx_hashes: _,
+    } = *commitments;
 
-    let header = Header {
+    Header {
         parent_block_hash,
         sequencer_address,
         block_timestamp,
@@ -65,9 +83,5 @@ pub fn close_block(
         state_diff_length,
         state_diff_commitment,
         receipt_commitment,
-    };
-
-    let block_hash = header.compute_hash(chain_id);
-
-    DeoxysBlock { info: DeoxysBlockInfo { header, block_hash, tx_hashes }, inner }
+    }
 }
@@ -0,0 +1,325 @@
+//! Genesis block of the chains produced by the node, such as a local devnet.
+//!
+//! The genesis state is made of the classes it declares, the contracts it deploys and their storage. It is closed
+//! and stored as block 0 like the produced blocks, so that its global tries and its header are consistent with the
+//! blocks built on top of it.
+//!
+//! The `--devnet` flag of the node builds its genesis with `testing::devnet_genesis`, from the account and ERC20
+//! classes of the blockifier test contracts.
+//!
+//! TODO: declare the UDC and the OpenZeppelin account class in the devnet genesis, which needs these contract artifacts
+//! in the tree.
+
+use std::collections::BTreeMap;
+
+use dc_db::{DeoxysBackend, DeoxysStorageError};
+use dc_sync::commitments::CommitmentError;
+use dp_block::{DeoxysBlock, DeoxysPendingBlock};
+use dp_class::{ContractClass, ConvertedClass};
+use dp_convert::ToFelt;
+use dp_state_update::{ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, StateDiff, StorageEntry};
+use dp_utils::clock::Clock;
+use starknet_core::types::Felt;
+use starknet_core::utils::{get_contract_address, get_storage_var_address};
+use starknet_types_core::hash::{Pedersen, StarkHash};
+
+use crate::close_block::close_block;
+use crate::header::make_pending_header;
+use crate::L1DataProvider;
+
+/// Storage variable of the balances of the OpenZeppelin ERC20 contracts, as `u256` split in two felts.
+pub const ERC20_BALANCES_VAR: &str = "ERC20_balances";
+/// Storage variable of the public key of the OpenZeppelin account contracts.
+pub const ACCOUNT_PUBLIC_KEY_VAR: &str = "Account_public_key";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Storage error: {0:#}")]
+    StorageError(#[from] DeoxysStorageError),
+    #[error("Failed to compute the block commitments: {0}")]
+    Commitment(#[from] CommitmentError),
+    #[error("The database already has a genesis block")]
+    AlreadyStored,
+}
+
+/// Storage key of the ERC20 balance of `address`, the low part of the `u256`.
+pub fn erc20_balance_key(address: Felt) -> Felt {
+    get_storage_var_address(ERC20_BALANCES_VAR, &[address]).expect("Storage variable name is ASCII")
+}
+
+/// An account deployed and funded by the genesis block, whose private key is derived from a seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevnetAccount {
+    pub address: Felt,
+    pub private_key: Felt,
+    pub public_key: Felt,
+}
+
+impl DevnetAccount {
+    /// The account number `index` of `seed`, an instance of the account class `class_hash`. The same seed always
+    /// gives the same keys and addresses.
+    pub fn derive(seed: u64, index: u64, class_hash: Felt) -> Self {
+        let mut private_key = Pedersen::hash(&Felt::from(seed), &Felt::from(index)).to_bytes_be();
+        // Keeps the key below the order of the curve.
+        private_key[0] = 0;
+        let private_key = Felt::from_bytes_be(&private_key);
+        let public_key = starknet_crypto::get_public_key(&private_key);
+        // The address of a counterfactual deployment of the account, salted with its public key.
+        let address = get_contract_address(public_key, class_hash, &[public_key], Felt::ZERO);
+        Self { address, private_key, public_key }
+    }
+}
+
+/// Builds the state of a genesis block.
+#[derive(Default)]
+pub struct GenesisBuilder {
+    classes: Vec<ConvertedClass>,
+    deployed_contracts: BTreeMap<Felt, Felt>,
+    storage: BTreeMap<Felt, BTreeMap<Felt, Felt>>,
+    accounts: Vec<DevnetAccount>,
+}
+
+impl GenesisBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares `class`, which is stored with the genesis block.
+    pub fn declare(mut self, class: ConvertedClass) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    /// Deploys an instance of `class_hash` at `address`, without running its constructor.
+    pub fn deploy(mut self, address: Felt, class_hash: Felt) -> Self {
+        self.deployed_contracts.insert(address, class_hash);
+        self
+    }
+
+    pub fn set_storage(mut self, address: Felt, key: Felt, value: Felt) -> Self {
+        self.storage.entry(address).or_default().insert(key, value);
+        self
+    }
+
+    /// Gives `amount` of the ERC20 `token` to `address`, on top of what it was already given.
+    pub fn fund(self, token: Felt, address: Felt, amount: u128) -> Self {
+        let key = erc20_balance_key(address);
+        let balance = self.storage.get(&token).and_then(|entries| entries.get(&key)).copied().unwrap_or(Felt::ZERO);
+        // The high part of the `u256` balance stays zero: the genesis amounts never add up to 2^128.
+        self.set_storage(token, key, balance + Felt::from(amount))
+    }
+
+    /// Deploys the first `count` accounts of `seed`, instances of the account class `class_hash`, and gives each of
+    /// them `balance` of every token of `fee_tokens`.
+    pub fn with_devnet_accounts(
+        mut self,
+        seed: u64,
+        count: u64,
+        class_hash: Felt,
+        fee_tokens: &[Felt],
+        balance: u128,
+    ) -> Self {
+        let public_key_key =
+            get_storage_var_address(ACCOUNT_PUBLIC_KEY_VAR, &[]).expect("Storage variable name is ASCII");
+        for index in 0..count {
+            let account = DevnetAccount::derive(seed, index, class_hash);
+            self = self.deploy(account.address, class_hash).set_storage(
+                account.address,
+                public_key_key,
+                account.public_key,
+            );
+            for token in fee_tokens {
+                self = self.fund(*token, account.address, balance);
+            }
+            self.accounts.push(account);
+        }
+        self
+    }
+
+    /// The accounts deployed with [`GenesisBuilder::with_devnet_accounts`].
+    pub fn accounts(&self) -> &[DevnetAccount] {
+        &self.accounts
+    }
+
+    pub fn state_diff(&self) -> StateDiff {
+        let (mut declared_classes, mut deprecated_declared_classes) = (Vec::new(), Vec::new());
+        for ConvertedClass { class_infos: (class_hash, class_info), .. } in &self.classes {
+            match class_info.contract_class {
+                ContractClass::Sierra(_) => declared_classes.push(DeclaredClassItem {
+                    class_hash: *class_hash,
+                    compiled_class_hash: class_info.compiled_class_hash,
+                }),
+                ContractClass::Legacy(_) => deprecated_declared_classes.push(*class_hash),
+            }
+        }
+        StateDiff::new(
+            self.storage
+                .iter()
+                .map(|(address, entries)| ContractStorageDiffItem {
+                    address: *address,
+                    storage_entries: entries
+                        .iter()
+                        .map(|(key, value)| StorageEntry { key: *key, value: *value })
+                        .collect(),
+                })
+                .collect(),
+            deprecated_declared_classes,
+            declared_classes,
+            self.deployed_contracts
+                .iter()
+                .map(|(address, class_hash)| DeployedContractItem { address: *address, class_hash: *class_hash })
+                .collect(),
+            vec![],
+            vec![],
+        )
+    }
+
+    /// Closes the genesis block and stores it as block 0 of an empty database.
+    pub fn store(
+        self,
+        backend: &DeoxysBackend,
+        l1_data_provider: &dyn L1DataProvider,
+        clock: &dyn Clock,
+    ) -> Result<DeoxysBlock, Error> {
+        if backend.get_latest_block_n()?.is_some() {
+            return Err(Error::AlreadyStored);
+        }
+        let chain_config = backend.chain_config();
+        let state_diff = self.state_diff();
+        let classes = self
+            .classes
+            .into_iter()
+            .map(|mut class| {
+                class.class_infos.1.block_number = Some(0);
+                class
+            })
+            .collect();
+
+        let header = make_pending_header(Felt::ZERO, chain_config, l1_data_provider, clock);
+        let block = close_block(
+            backend,
+            DeoxysPendingBlock::new_empty(header),
+            &state_diff,
+            chain_config.chain_id.clone().to_felt(),
+            0,
+        )?;
+        backend.store_block(block.clone().into(), state_diff, classes)?;
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::db_block_id::DbBlockId;
+    use dc_db::testing::temp_database;
+    use dp_block::{BlockId, BlockTag};
+    use dp_class::{ClassInfo, CompiledClass, EntryPointsByType, FlattenedSierraClass};
+    use dp_utils::clock::MockClock;
+
+    use super::*;
+    use crate::block_production::{BlockProductionConfig, BlockProductionTask};
+    use crate::testing::MockL1DataProvider;
+    use crate::Mempool;
+
+    const ACCOUNT_CLASS_HASH: Felt = Felt::from_hex_unchecked("0xacc");
+    const ERC20_CLASS_HASH: Felt = Felt::from_hex_unchecked("0xe2c");
+    const FEE_TOKEN: Felt = Felt::from_hex_unchecked("0xfee");
+
+    fn converted_class(class_hash: Felt) -> ConvertedClass {
+        let contract_class = ContractClass::Sierra(FlattenedSierraClass {
+            sierra_program: vec![class_hash],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: String::new(),
+        });
+        ConvertedClass {
+            class_infos: (class_hash, ClassInfo { contract_class, compiled_class_hash: Felt::ONE, block_number: None }),
+            class_compiled: (class_hash, serde_json::from_str::<CompiledClass>(r#"{"Sierra":[]}"#).unwrap()),
+        }
+    }
+
+    fn devnet_genesis(seed: u64) -> GenesisBuilder {
+        GenesisBuilder::new()
+            .declare(converted_class(ACCOUNT_CLASS_HASH))
+            .declare(converted_class(ERC20_CLASS_HASH))
+            .deploy(FEE_TOKEN, ERC20_CLASS_HASH)
+            .with_devnet_accounts(seed, 3, ACCOUNT_CLASS_HASH, &[FEE_TOKEN], 1_000)
+    }
+
+    #[test]
+    fn test_devnet_accounts_are_deterministic() {
+        let accounts = devnet_genesis(42).accounts().to_vec();
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts, devnet_genesis(42).accounts());
+        assert_ne!(accounts, devnet_genesis(43).accounts());
+
+        for (index, account) in accounts.iter().enumerate() {
+            assert_eq!(account, &DevnetAccount::derive(42, index as u64, ACCOUNT_CLASS_HASH));
+            assert_eq!(starknet_crypto::get_public_key(&account.private_key), account.public_key);
+        }
+        let addresses: std::collections::HashSet<_> = accounts.iter().map(|account| account.address).collect();
+        assert_eq!(addresses.len(), 3);
+    }
+
+    #[test]
+    fn test_fund_is_additive() {
+        let account = Felt::from_hex_unchecked("0xa");
+        let genesis = GenesisBuilder::new().fund(FEE_TOKEN, account, 1_000).fund(FEE_TOKEN, account, 500);
+        let key = get_storage_var_address(ERC20_BALANCES_VAR, &[account]).unwrap();
+        assert_eq!(genesis.storage[&FEE_TOKEN][&key], Felt::from(1_500));
+
+        // A devnet account funded again gets both amounts.
+        let genesis = devnet_genesis(0);
+        let account = genesis.accounts()[0].address;
+        let genesis = genesis.fund(FEE_TOKEN, account, 1);
+        let key = get_storage_var_address(ERC20_BALANCES_VAR, &[account]).unwrap();
+        assert_eq!(genesis.storage[&FEE_TOKEN][&key], Felt::from(1_001));
+    }
+
+    #[tokio::test]
+    async fn test_devnet_genesis() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let clock = MockClock::new(1_700_000_000);
+
+        let genesis = devnet_genesis(0);
+        let accounts = genesis.accounts().to_vec();
+        let block = genesis.store(&backend, &MockL1DataProvider, &clock).unwrap();
+        assert_eq!(block.info.header.block_number, 0);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+        assert!(matches!(devnet_genesis(0).store(&backend, &MockL1DataProvider, &clock), Err(Error::AlreadyStored)));
+
+        let latest = BlockId::Tag(BlockTag::Latest);
+        let balance_key = |address| get_storage_var_address(ERC20_BALANCES_VAR, &[address]).unwrap();
+        let public_key_key = get_storage_var_address(ACCOUNT_PUBLIC_KEY_VAR, &[]).unwrap();
+        for account in &accounts {
+            assert_eq!(
+                backend.get_contract_class_hash_at(&latest, &account.address).unwrap(),
+                Some(ACCOUNT_CLASS_HASH)
+            );
+            // The accounts have never sent a transaction.
+            assert_eq!(backend.get_contract_nonce_at(&latest, &account.address).unwrap(), None);
+            assert_eq!(
+                backend.get_contract_storage_at(&latest, &account.address, &public_key_key).unwrap(),
+                Some(account.public_key)
+            );
+            assert_eq!(
+                backend.get_contract_storage_at(&latest, &FEE_TOKEN, &balance_key(account.address)).unwrap(),
+                Some(Felt::from(1_000))
+            );
+        }
+        assert_eq!(backend.get_contract_class_hash_at(&latest, &FEE_TOKEN).unwrap(), Some(ERC20_CLASS_HASH));
+        let class_info = backend.get_class_info(&latest, &ACCOUNT_CLASS_HASH).unwrap().unwrap();
+        assert_eq!(class_info.block_number, Some(0));
+
+        // The blocks are produced on top of the genesis block.
+        let l1_data_provider = Arc::new(MockL1DataProvider);
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), l1_data_provider.clone()));
+        let config = BlockProductionConfig::from_chain_config(backend.chain_config());
+        BlockProductionTask::new(Arc::clone(&backend), mempool, l1_data_provider, config).unwrap();
+        let pending = backend.get_block_info(&DbBlockId::Pending).unwrap().unwrap();
+        assert_eq!(pending.as_pending().unwrap().header.parent_block_hash, block.info.block_hash);
+    }
+}
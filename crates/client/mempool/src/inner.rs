@@ -7,16 +7,22 @@
 //! TODO(perf): should we box the MempoolTransaction?
 
 use crate::{clone_account_tx, contract_addr, nonce, tx_hash};
-use blockifier::transaction::account_transaction::AccountTransaction;
+use blockifier::transaction::{
+    account_transaction::AccountTransaction, transaction_types::TransactionType,
+    transactions::L1HandlerTransaction as BL1HandlerTransaction,
+};
 use dp_class::ConvertedClass;
+use dp_convert::ToFelt;
+use dp_transactions::{Transaction, TransactionWithHash};
 use starknet_api::{
     core::{ContractAddress, Nonce},
-    transaction::TransactionHash,
+    transaction::{Fee, L1HandlerTransaction, TransactionHash},
 };
+use starknet_types_core::felt::Felt;
 use std::{
     cmp,
-    collections::{hash_map, BTreeSet, HashMap, HashSet},
-    iter,
+    collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap, HashSet},
+    iter, mem,
     time::SystemTime,
 };
 
@@ -26,6 +32,8 @@ pub struct MempoolTransaction {
     pub tx: AccountTransaction,
     pub arrived_at: ArrivedAtTimestamp,
     pub converted_class: Option<ConvertedClass>,
+    /// The transaction as it was submitted, served by the RPC until the transaction is included in a block.
+    pub submitted: Option<Transaction>,
 }
 
 impl Clone for MempoolTransaction {
@@ -34,6 +42,7 @@ impl Clone for MempoolTransaction {
             tx: clone_account_tx(&self.tx),
             arrived_at: self.arrived_at,
             converted_class: self.converted_class.clone(),
+            submitted: self.submitted.clone(),
         }
     }
 }
@@ -48,6 +57,70 @@ impl MempoolTransaction {
     pub fn tx_hash(&self) -> TransactionHash {
         tx_hash(&self.tx)
     }
+    /// The submitted transaction, or the transaction converted back from blockifier when it was not kept.
+    pub fn transaction(&self) -> TransactionWithHash {
+        match &self.submitted {
+            Some(transaction) => TransactionWithHash::new(transaction.clone(), self.tx_hash().to_felt()),
+            None => clone_account_tx(&self.tx).into(),
+        }
+    }
+    pub fn summary(&self) -> MempoolTxSummary {
+        MempoolTxSummary {
+            tx_hash: self.tx_hash().to_felt(),
+            sender_address: self.contract_address().to_felt(),
+            nonce: self.nonce().to_felt(),
+            tx_type: self.tx.tx_type(),
+            arrived_at: self.arrived_at,
+            class_hash: match &self.tx {
+                AccountTransaction::Declare(tx) => Some(tx.tx.class_hash().to_felt()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// An L1 handler transaction, executing an L1 to L2 message. It is not validated: it was paid for on L1.
+#[derive(Clone)]
+pub struct MempoolL1HandlerTransaction {
+    pub tx: L1HandlerTransaction,
+    pub tx_hash: TransactionHash,
+    pub paid_fee_on_l1: Fee,
+    pub arrived_at: ArrivedAtTimestamp,
+}
+
+impl MempoolL1HandlerTransaction {
+    /// Nonce of the L1 to L2 message.
+    pub fn nonce(&self) -> Nonce {
+        self.tx.nonce
+    }
+    pub fn to_blockifier(&self) -> BL1HandlerTransaction {
+        BL1HandlerTransaction { tx: self.tx.clone(), tx_hash: self.tx_hash, paid_fee_on_l1: self.paid_fee_on_l1 }
+    }
+    pub fn transaction(&self) -> TransactionWithHash {
+        TransactionWithHash::new(Transaction::L1Handler(self.tx.clone().into()), self.tx_hash.to_felt())
+    }
+    pub fn summary(&self) -> MempoolTxSummary {
+        MempoolTxSummary {
+            tx_hash: self.tx_hash.to_felt(),
+            sender_address: self.tx.contract_address.to_felt(),
+            nonce: self.nonce().to_felt(),
+            tx_type: TransactionType::L1Handler,
+            arrived_at: self.arrived_at,
+            class_hash: None,
+        }
+    }
+}
+
+/// A lightweight view of a transaction sitting in the mempool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolTxSummary {
+    pub tx_hash: Felt,
+    pub sender_address: Felt,
+    pub nonce: Felt,
+    pub tx_type: TransactionType,
+    pub arrived_at: ArrivedAtTimestamp,
+    /// The declared class hash, for declare transactions.
+    pub class_hash: Option<Felt>,
 }
 
 struct OrderMempoolTransactionByNonce(MempoolTransaction);
@@ -109,13 +182,13 @@ impl NonceChain {
         debug_assert_eq!(front.0.arrived_at, self.front_arrived_at);
     }
 
-    /// Returns where in the chain it was inserted.
+    /// Returns where in the chain it was inserted, and the hash of the transaction it replaced with the same nonce.
     /// When `force` is `true`, this function should never return any error.
     pub fn insert(
         &mut self,
         mempool_tx: MempoolTransaction,
         force: bool,
-    ) -> Result<InsertedPosition, TxInsersionError> {
+    ) -> Result<(InsertedPosition, Option<TransactionHash>), TxInsersionError> {
         let position = if self.front_arrived_at > mempool_tx.arrived_at {
             // We are inserting at the front here
             let former_head_arrived_at = self.front_arrived_at;
@@ -132,13 +205,14 @@ impl NonceChain {
         #[cfg(debug_assertions)] // unknown field `front_tx_hash` in release if debug_assert_eq is used
         assert_eq!(self.transactions.first().unwrap().0.tx_hash(), self.front_tx_hash);
 
+        let mut replaced = None;
         if force {
-            self.transactions.replace(OrderMempoolTransactionByNonce(mempool_tx));
+            replaced = self.transactions.replace(OrderMempoolTransactionByNonce(mempool_tx)).map(|tx| tx.0.tx_hash());
         } else if !self.transactions.insert(OrderMempoolTransactionByNonce(mempool_tx)) {
             return Err(TxInsersionError::NonceConflict);
         }
 
-        Ok(position)
+        Ok((position, replaced))
     }
 
     pub fn pop(&mut self) -> (MempoolTransaction, NonceChainNewState) {
@@ -155,6 +229,28 @@ impl NonceChain {
             (tx.0, NonceChainNewState::Empty)
         }
     }
+
+    /// Removes and returns the transactions for which `remove` returns `true`.
+    pub fn remove_where(
+        &mut self,
+        mut remove: impl FnMut(&MempoolTransaction) -> bool,
+    ) -> (Vec<MempoolTransaction>, NonceChainNewState) {
+        let (removed, kept): (BTreeSet<_>, BTreeSet<_>) =
+            mem::take(&mut self.transactions).into_iter().partition(|tx| remove(&tx.0));
+        self.transactions = kept;
+
+        let removed = removed.into_iter().map(|tx| tx.0).collect();
+        if let Some(new_front) = self.transactions.first() {
+            self.front_arrived_at = new_front.0.arrived_at;
+            #[cfg(debug_assertions)]
+            {
+                self.front_tx_hash = new_front.0.tx_hash();
+            }
+            (removed, NonceChainNewState::NotEmpty)
+        } else {
+            (removed, NonceChainNewState::Empty)
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -187,6 +283,9 @@ impl PartialOrd for AccountOrderedByTimestamp {
 /// - Every nonce chain in `nonce_chains` should have a one to one match with `tx_queue`.
 /// - Every [`AccountTransaction::DeployAccount`] transaction should have a one to one match with `deployed_contracts`.
 /// - See [`NonceChain`] invariants.
+///
+/// L1 handler transactions do not belong to an account: they are kept apart, and come out before every account
+/// transaction, by message nonce.
 pub struct MempoolInner {
     /// We have one nonce chain per contract address.
     nonce_chains: HashMap<ContractAddress, NonceChain>,
@@ -194,6 +293,17 @@ pub struct MempoolInner {
     tx_queue: BTreeSet<AccountOrderedByTimestamp>,
     /// This is used for quickly checking if the contract has been deployed for the same block it is invoked.
     deployed_contracts: HashSet<ContractAddress>,
+    /// L1 handler transactions, by message nonce.
+    l1_handler_txs: BTreeMap<Nonce, MempoolL1HandlerTransaction>,
+    /// Where every transaction is, by hash.
+    tx_hashes: HashMap<TransactionHash, TxLocation>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TxLocation {
+    Account(ContractAddress),
+    /// By message nonce.
+    L1Handler(Nonce),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -222,6 +332,16 @@ impl MempoolInner {
             };
         }
         debug_assert!(deployed_contracts.is_empty());
+        let mut tx_hashes = self.tx_hashes.clone();
+        for (contract_addr, chain) in &self.nonce_chains {
+            for tx in &chain.transactions {
+                debug_assert_eq!(tx_hashes.remove(&tx.0.tx_hash()), Some(TxLocation::Account(*contract_addr)));
+            }
+        }
+        for (nonce, tx) in &self.l1_handler_txs {
+            debug_assert_eq!(tx_hashes.remove(&tx.tx_hash), Some(TxLocation::L1Handler(*nonce)));
+        }
+        debug_assert!(tx_hashes.is_empty());
     }
 
     /// When `force` is `true`, this function should never return any error.
@@ -230,6 +350,7 @@ impl MempoolInner {
 
         let contract_addr = mempool_tx.contract_address();
         let arrived_at = mempool_tx.arrived_at;
+        let tx_hash = mempool_tx.tx_hash();
 
         let deployed_contract_address =
             if let AccountTransaction::DeployAccount(tx) = &mempool_tx.tx { Some(tx.contract_address) } else { None };
@@ -244,7 +365,12 @@ impl MempoolInner {
             hash_map::Entry::Occupied(mut entry) => {
                 // Handle nonce collision.
                 let position = match entry.get_mut().insert(mempool_tx, force) {
-                    Ok(position) => position,
+                    Ok((position, replaced)) => {
+                        if let Some(replaced) = replaced {
+                            self.tx_hashes.remove(&replaced);
+                        }
+                        position
+                    }
                     Err(_nonce_collision) => {
                         if force {
                             panic!("Force add should never error")
@@ -286,13 +412,183 @@ impl MempoolInner {
                 debug_assert!(inserted);
             }
         };
+        self.tx_hashes.insert(tx_hash, TxLocation::Account(contract_addr));
+        Ok(())
+    }
+
+    /// When `force` is `true`, this function should never return any error.
+    pub fn insert_l1_handler_tx(
+        &mut self,
+        tx: MempoolL1HandlerTransaction,
+        force: bool,
+    ) -> Result<(), TxInsersionError> {
+        let (nonce, tx_hash) = (tx.nonce(), tx.tx_hash);
+        match self.l1_handler_txs.entry(nonce) {
+            btree_map::Entry::Occupied(mut entry) => {
+                if !force {
+                    return Err(TxInsersionError::NonceConflict);
+                }
+                let replaced = entry.insert(tx);
+                self.tx_hashes.remove(&replaced.tx_hash);
+            }
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(tx);
+            }
+        }
+        self.tx_hashes.insert(tx_hash, TxLocation::L1Handler(nonce));
         Ok(())
     }
 
+    pub fn pop_l1_handler_chunk(&mut self, dest: &mut Vec<MempoolL1HandlerTransaction>, n: usize) {
+        for _ in 0..n {
+            let Some((_nonce, tx)) = self.l1_handler_txs.pop_first() else { break };
+            self.tx_hashes.remove(&tx.tx_hash);
+            dest.push(tx);
+        }
+    }
+
+    pub fn re_add_l1_handler_txs(&mut self, txs: Vec<MempoolL1HandlerTransaction>) {
+        for tx in txs {
+            let force = true;
+            self.insert_l1_handler_tx(tx, force).expect("Force insert tx should not error");
+        }
+    }
+
+    pub fn remove_l1_handler_tx(&mut self, nonce: &Nonce) -> Option<MempoolL1HandlerTransaction> {
+        let tx = self.l1_handler_txs.remove(nonce)?;
+        self.tx_hashes.remove(&tx.tx_hash);
+        Some(tx)
+    }
+
+    /// Removes the L1 handler transactions for which `remove` returns `true`.
+    pub fn remove_l1_handler_txs_where(
+        &mut self,
+        mut remove: impl FnMut(&MempoolL1HandlerTransaction) -> bool,
+    ) -> Vec<MempoolL1HandlerTransaction> {
+        let (removed, kept): (BTreeMap<_, _>, _) =
+            mem::take(&mut self.l1_handler_txs).into_iter().partition(|(_nonce, tx)| remove(tx));
+        self.l1_handler_txs = kept;
+        for tx in removed.values() {
+            self.tx_hashes.remove(&tx.tx_hash);
+        }
+        removed.into_values().collect()
+    }
+
+    pub fn summaries(&self) -> impl Iterator<Item = MempoolTxSummary> + '_ {
+        self.nonce_chains
+            .values()
+            .flat_map(|chain| chain.transactions.iter().map(|tx| tx.0.summary()))
+            .chain(self.l1_handler_txs.values().map(MempoolL1HandlerTransaction::summary))
+    }
+
+    pub fn tx_count(&self) -> usize {
+        self.nonce_chains.values().map(|chain| chain.transactions.len()).sum::<usize>() + self.l1_handler_txs.len()
+    }
+
+    /// Located with the hash index, only the transactions of its account are then looked through.
+    pub fn get_transaction(&self, tx_hash: &TransactionHash) -> Option<TransactionWithHash> {
+        match self.tx_hashes.get(tx_hash)? {
+            TxLocation::Account(contract_addr) => self
+                .nonce_chains
+                .get(contract_addr)?
+                .transactions
+                .iter()
+                .find(|tx| tx.0.tx_hash() == *tx_hash)
+                .map(|tx| tx.0.transaction()),
+            TxLocation::L1Handler(nonce) => {
+                self.l1_handler_txs.get(nonce).map(MempoolL1HandlerTransaction::transaction)
+            }
+        }
+    }
+
+    pub fn contains_tx_hash(&self, tx_hash: &TransactionHash) -> bool {
+        self.tx_hashes.contains_key(tx_hash)
+    }
+
+    /// The nonce following the transactions of this account with consecutive nonces from `nonce`, `None` when the
+    /// account has no transaction with this nonce.
+    pub fn next_nonce(&self, contract_addr: &ContractAddress, nonce: Nonce) -> Option<Nonce> {
+        let chain = self.nonce_chains.get(contract_addr)?;
+        let mut next = None;
+        for tx in chain.transactions.iter().skip_while(|tx| tx.0.nonce() < nonce) {
+            let expected = next.unwrap_or(nonce);
+            if tx.0.nonce() != expected {
+                break;
+            }
+            next = Some(Nonce(expected.0 + Felt::ONE));
+        }
+        next
+    }
+
     pub fn has_deployed_contract(&self, addr: &ContractAddress) -> bool {
         self.deployed_contracts.contains(addr)
     }
 
+    /// Contract address and transaction hash of every deploy account transaction.
+    pub fn deploy_account_txs(&self) -> impl Iterator<Item = (ContractAddress, TransactionHash)> + '_ {
+        self.deployed_contracts.iter().filter_map(|contract_addr| {
+            self.nonce_chains.get(contract_addr)?.transactions.iter().find_map(|tx| match &tx.0.tx {
+                AccountTransaction::DeployAccount(tx) => Some((tx.contract_address, tx.tx_hash)),
+                _ => None,
+            })
+        })
+    }
+
+    /// Removes the transactions of this account with a nonce lower than `nonce`. The account then gets queued with
+    /// its next transaction, if any.
+    pub fn remove_txs_below_nonce(&mut self, contract_addr: &ContractAddress, nonce: Nonce) -> Vec<MempoolTransaction> {
+        self.remove_account_txs_where(contract_addr, |tx| tx.nonce() < nonce)
+    }
+
+    /// Removes every transaction of this account.
+    pub fn remove_account_txs(&mut self, contract_addr: &ContractAddress) -> Vec<MempoolTransaction> {
+        self.remove_account_txs_where(contract_addr, |_| true)
+    }
+
+    fn remove_account_txs_where(
+        &mut self,
+        contract_addr: &ContractAddress,
+        remove: impl FnMut(&MempoolTransaction) -> bool,
+    ) -> Vec<MempoolTransaction> {
+        let Some(nonce_chain) = self.nonce_chains.get_mut(contract_addr) else { return vec![] };
+        let former_front_arrived_at = nonce_chain.front_arrived_at;
+        let (removed, nonce_chain_new_state) = nonce_chain.remove_where(remove);
+        let new_front_arrived_at = nonce_chain.front_arrived_at;
+        if removed.is_empty() {
+            return removed;
+        }
+
+        // The front of the nonce chain may have changed: update the tx queue.
+        let was_queued = self
+            .tx_queue
+            .remove(&AccountOrderedByTimestamp { contract_addr: *contract_addr, timestamp: former_front_arrived_at });
+        debug_assert!(was_queued);
+        match nonce_chain_new_state {
+            NonceChainNewState::Empty => {
+                let removed = self.nonce_chains.remove(contract_addr);
+                debug_assert!(removed.is_some());
+            }
+            NonceChainNewState::NotEmpty => {
+                let inserted = self.tx_queue.insert(AccountOrderedByTimestamp {
+                    contract_addr: *contract_addr,
+                    timestamp: new_front_arrived_at,
+                });
+                debug_assert!(inserted);
+            }
+        }
+
+        // Update deployed contracts.
+        for mempool_tx in &removed {
+            self.tx_hashes.remove(&mempool_tx.tx_hash());
+            if let AccountTransaction::DeployAccount(tx) = &mempool_tx.tx {
+                let removed = self.deployed_contracts.remove(&tx.contract_address);
+                debug_assert!(removed);
+            }
+        }
+
+        removed
+    }
+
     pub fn pop_next(&mut self) -> Option<MempoolTransaction> {
         // Pop tx queue.
         let tx_queue_account = self.tx_queue.pop_first()?; // Bubble up None if the mempool is empty.
@@ -322,6 +618,7 @@ impl MempoolInner {
             let removed = self.deployed_contracts.remove(&tx.contract_address);
             debug_assert!(removed);
         }
+        self.tx_hashes.remove(&mempool_tx.tx_hash());
 
         Some(mempool_tx)
     }
@@ -347,7 +644,7 @@ mod tests {
     use blockifier::{
         execution::contract_class::ClassInfo,
         test_utils::{contracts::FeatureContract, CairoVersion},
-        transaction::transactions::{DeclareTransaction, InvokeTransaction},
+        transaction::transactions::{DeclareTransaction, DeployAccountTransaction, InvokeTransaction},
     };
     use proptest::{
         arbitrary::{any, Arbitrary},
@@ -355,10 +652,10 @@ mod tests {
     };
     use proptest_derive::Arbitrary;
     use starknet_api::{
+        core::ClassHash,
         data_availability::DataAvailabilityMode,
-        transaction::{DeclareTransactionV3, InvokeTransactionV3},
+        transaction::{DeclareTransactionV3, DeployAccountTransactionV3, InvokeTransactionV3},
     };
-    use starknet_core::types::Felt;
 
     use super::*;
     use std::fmt;
@@ -477,7 +774,7 @@ mod tests {
                         )),
                     };
 
-                    Insert(MempoolTransaction { tx, arrived_at, converted_class: None }, force)
+                    Insert(MempoolTransaction { tx, arrived_at, converted_class: None, submitted: None }, force)
                 })
                 .boxed()
         }
@@ -504,6 +801,9 @@ mod tests {
                         log::trace!("Insert {:?}", insert);
                         let res = mempool.insert_tx(insert.0.clone(), insert.1);
                         log::trace!("Result {:?}", res);
+                        if res.is_ok() {
+                            assert!(mempool.get_transaction(&insert.0.tx_hash()).is_some());
+                        }
                         inserted.insert(insert.0.tx_hash());
                     }
                     Operation::Pop => {
@@ -511,6 +811,7 @@ mod tests {
                         let res = mempool.pop_next();
                         if let Some(res) = &res {
                             inserted.remove(&res.tx_hash());
+                            assert!(mempool.get_transaction(&res.tx_hash()).is_none());
                         }
                         log::trace!("Popped {:?}", res.map(|el| Insert(el, false)));
                     }
@@ -530,6 +831,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_summaries() {
+        let contract_addr = |n: u64| ContractAddress::try_from(Felt::from(n)).unwrap();
+        let arrived_at = SystemTime::UNIX_EPOCH;
+        let dummy_contract_class = FeatureContract::TestContract(CairoVersion::Cairo1);
+        let dummy_class_info = ClassInfo::new(&dummy_contract_class.get_class(), 100, 100).unwrap();
+
+        let declare = AccountTransaction::Declare(
+            DeclareTransaction::new(
+                starknet_api::transaction::DeclareTransaction::V3(DeclareTransactionV3 {
+                    resource_bounds: Default::default(),
+                    tip: Default::default(),
+                    signature: Default::default(),
+                    nonce: Nonce(Felt::ZERO),
+                    class_hash: ClassHash(Felt::from(0x1234)),
+                    compiled_class_hash: Default::default(),
+                    sender_address: contract_addr(1),
+                    nonce_data_availability_mode: DataAvailabilityMode::L1,
+                    fee_data_availability_mode: DataAvailabilityMode::L1,
+                    paymaster_data: Default::default(),
+                    account_deployment_data: Default::default(),
+                }),
+                TransactionHash(Felt::from(1)),
+                dummy_class_info,
+            )
+            .unwrap(),
+        );
+        let deploy_account = AccountTransaction::DeployAccount(DeployAccountTransaction {
+            tx: starknet_api::transaction::DeployAccountTransaction::V3(DeployAccountTransactionV3 {
+                resource_bounds: Default::default(),
+                tip: Default::default(),
+                signature: Default::default(),
+                nonce: Nonce(Felt::ZERO),
+                class_hash: Default::default(),
+                contract_address_salt: Default::default(),
+                constructor_calldata: Default::default(),
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                paymaster_data: Default::default(),
+            }),
+            tx_hash: TransactionHash(Felt::from(2)),
+            contract_address: contract_addr(2),
+            only_query: false,
+        });
+        let invoke = AccountTransaction::Invoke(InvokeTransaction::new(
+            starknet_api::transaction::InvokeTransaction::V3(InvokeTransactionV3 {
+                resource_bounds: Default::default(),
+                tip: Default::default(),
+                signature: Default::default(),
+                nonce: Nonce(Felt::ONE),
+                sender_address: contract_addr(1),
+                calldata: Default::default(),
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                paymaster_data: Default::default(),
+                account_deployment_data: Default::default(),
+            }),
+            TransactionHash(Felt::from(3)),
+        ));
+
+        let mut mempool = MempoolInner::default();
+        for tx in [declare, deploy_account, invoke] {
+            mempool
+                .insert_tx(MempoolTransaction { tx, arrived_at, converted_class: None, submitted: None }, false)
+                .unwrap();
+        }
+        mempool.check_invariants();
+
+        let mut summaries: Vec<_> = mempool.summaries().collect();
+        summaries.sort_by_key(|summary| summary.tx_hash);
+        assert_eq!(
+            summaries,
+            vec![
+                MempoolTxSummary {
+                    tx_hash: Felt::from(1),
+                    sender_address: Felt::from(1),
+                    nonce: Felt::ZERO,
+                    tx_type: TransactionType::Declare,
+                    arrived_at,
+                    class_hash: Some(Felt::from(0x1234)),
+                },
+                MempoolTxSummary {
+                    tx_hash: Felt::from(2),
+                    sender_address: Felt::from(2),
+                    nonce: Felt::ZERO,
+                    tx_type: TransactionType::DeployAccount,
+                    arrived_at,
+                    class_hash: None,
+                },
+                MempoolTxSummary {
+                    tx_hash: Felt::from(3),
+                    sender_address: Felt::from(1),
+                    nonce: Felt::ONE,
+                    tx_type: TransactionType::InvokeFunction,
+                    arrived_at,
+                    class_hash: None,
+                },
+            ]
+        );
+    }
+
     proptest::proptest! {
         #[test]
         fn proptest_mempool(pb in any::<MempoolInvariantsProblem>()) {
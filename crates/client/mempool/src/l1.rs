@@ -1,4 +1,9 @@
+use std::sync::Arc;
+
+use dc_db::DeoxysBackend;
+use dc_eth::l1_gas_price::GasPriceProvider;
 use dp_block::header::{GasPrices, L1DataAvailabilityMode};
+use dp_block::{BlockId, BlockTag, Header};
 
 /// This trait enables the block production task to fill in the L1 info.
 /// Gas prices and DA mode
@@ -10,3 +15,45 @@ pub trait L1DataProvider: Send + Sync {
 
     // fn get_pending_l1_handler_txs
 }
+
+impl L1DataProvider for GasPriceProvider {
+    fn get_gas_prices(&self) -> GasPrices {
+        GasPriceProvider::gas_prices(self)
+    }
+
+    fn get_da_mode(&self) -> L1DataAvailabilityMode {
+        GasPriceProvider::da_mode(self)
+    }
+}
+
+/// The L1 data of the latest synced block. Full nodes do not follow the L1 gas prices themselves: the transactions they
+/// keep in their mempool are validated against the blocks of the sequencer.
+pub struct LatestBlockL1DataProvider {
+    backend: Arc<DeoxysBackend>,
+}
+
+impl LatestBlockL1DataProvider {
+    pub fn new(backend: Arc<DeoxysBackend>) -> Self {
+        Self { backend }
+    }
+
+    fn latest_header<R>(&self, f: impl FnOnce(&Header) -> R) -> Option<R> {
+        match self.backend.get_block_info(&BlockId::Tag(BlockTag::Latest)) {
+            Ok(info) => info.as_ref().and_then(|info| info.as_nonpending()).map(|info| f(&info.header)),
+            Err(err) => {
+                log::warn!("Reading the latest block header for its L1 data: {err:#}");
+                None
+            }
+        }
+    }
+}
+
+impl L1DataProvider for LatestBlockL1DataProvider {
+    fn get_gas_prices(&self) -> GasPrices {
+        self.latest_header(|header| header.l1_gas_price.clone()).unwrap_or_default()
+    }
+
+    fn get_da_mode(&self) -> L1DataAvailabilityMode {
+        self.latest_header(|header| header.l1_da_mode).unwrap_or_default()
+    }
+}
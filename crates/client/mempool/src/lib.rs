@@ -6,26 +6,38 @@ use blockifier::transaction::transactions::InvokeTransaction;
 use dc_db::db_block_id::DbBlockId;
 use dc_db::DeoxysBackend;
 use dc_db::DeoxysStorageError;
+use dc_eth::l1_messaging::L1HandlerSubmitter;
 use dc_exec::ExecutionContext;
-use dp_block::BlockId;
-use dp_block::BlockTag;
+use dc_sync::l2::BlockImportHook;
+use dp_block::BlockN;
+use dp_block::DeoxysBlockInfo;
 use dp_block::DeoxysPendingBlockInfo;
 use dp_class::ConvertedClass;
+use dp_convert::ToFelt;
+use dp_state_update::{NonceUpdate, StateDiff};
+use dp_transactions::{L1HandlerTransaction, Transaction, TransactionApiError, TransactionWithHash};
+use dp_utils::clock::{Clock, SystemClock};
+use dp_utils::lock::RwLockExt;
 use header::make_pending_header;
 use inner::MempoolInner;
-pub use inner::{ArrivedAtTimestamp, MempoolTransaction};
-pub use l1::L1DataProvider;
+pub use inner::{ArrivedAtTimestamp, MempoolL1HandlerTransaction, MempoolTransaction, MempoolTxSummary};
+pub use l1::{L1DataProvider, LatestBlockL1DataProvider};
 use starknet_api::core::{ContractAddress, Nonce};
-use starknet_api::transaction::TransactionHash;
-use starknet_core::types::Felt;
+use starknet_api::transaction::{Fee, TransactionHash};
+use starknet_types_core::felt::Felt;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::RwLock;
 
 pub mod block_production;
 mod close_block;
+pub mod genesis;
 pub mod header;
 mod inner;
 mod l1;
+pub mod preview;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -39,36 +51,68 @@ pub enum Error {
     InnerMempool(#[from] inner::TxInsersionError),
     #[error(transparent)]
     Exec(#[from] dc_exec::Error),
+    #[error("L1 to L2 message with nonce {nonce} was already executed by transaction {tx_hash:#x}")]
+    L1MessageAlreadyExecuted { nonce: u64, tx_hash: Felt },
+    #[error("Invalid L1 handler transaction: {0:#}")]
+    InvalidL1HandlerTx(#[from] TransactionApiError),
+    #[error("Transaction {0:#x} is already in the chain or in the mempool")]
+    DuplicateTx(Felt),
 }
 
 pub struct Mempool {
     backend: Arc<DeoxysBackend>,
     l1_data_provider: Arc<dyn L1DataProvider>,
     inner: RwLock<MempoolInner>,
+    /// Gives the arrival time of the transactions, and the timestamp of the blocks produced from them.
+    clock: Arc<dyn Clock>,
 }
 
 impl Mempool {
     pub fn new(backend: Arc<DeoxysBackend>, l1_data_provider: Arc<dyn L1DataProvider>) -> Self {
-        Mempool { backend, l1_data_provider, inner: Default::default() }
+        Mempool { backend, l1_data_provider, inner: Default::default(), clock: Arc::new(SystemClock) }
     }
 
+    /// Reads the time from `clock` instead of the system clock.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// `submitted` is the transaction as it was received, returned by [`Mempool::get_transaction`].
     pub fn accept_account_tx(
         &self,
         tx: AccountTransaction,
         converted_class: Option<ConvertedClass>,
+        submitted: Option<Transaction>,
     ) -> Result<(), Error> {
         // The timestamp *does not* take the transaction validation time into account.
-        let arrived_at = ArrivedAtTimestamp::now();
+        let arrived_at = self.clock.now_unix();
+
+        // Checked before the validation, which is much more expensive, and again when the transaction is inserted.
+        let only_query = is_only_query(&tx);
+        let tx_hash = tx_hash(&tx).to_felt();
+        if !only_query && (self.contains_tx_hash(&tx_hash) || self.backend.tx_exists(&tx_hash)?) {
+            return Err(Error::DuplicateTx(tx_hash));
+        }
 
         // Get pending block.
         let pending_block_info = if let Some(block) = self.backend.get_block_info(&DbBlockId::Pending)? {
             block
         } else {
             // No current pending block, we'll make an unsaved empty one for the sake of validating this tx.
-            let parent_block_hash =
-                self.backend.get_block_hash(&BlockId::Tag(BlockTag::Latest))?.ok_or(Error::NoGenesis)?;
+            // The block production task stores the pending block along with its pinned L1 data as soon as it is
+            // created, so this only happens when block production is not running.
+            let (parent_block_hash, _) = self.backend.chain_head().get().latest.ok_or(Error::NoGenesis)?;
             DeoxysPendingBlockInfo::new(
-                make_pending_header(parent_block_hash, self.backend.chain_config(), self.l1_data_provider.as_ref()),
+                make_pending_header(
+                    parent_block_hash,
+                    self.backend.chain_config(),
+                    self.l1_data_provider.as_ref(),
+                    self.clock.as_ref(),
+                ),
                 vec![],
             )
             .into()
@@ -78,7 +122,7 @@ impl Mempool {
         // NB: the lock is NOT taken the entire time the tx is being validated. As such, the deploy tx
         //  may appear during that time - but it is not a problem.
         let deploy_account_tx_hash = if let AccountTransaction::Invoke(tx) = &tx {
-            let mempool = self.inner.read().expect("Poisoned lock");
+            let mempool = self.inner.read_or_recover();
             if mempool.has_deployed_contract(&tx.tx.sender_address()) {
                 Some(tx.tx_hash) // we return the wrong tx hash here but it's ok because the actual hash is unused by blockifier
             } else {
@@ -93,35 +137,194 @@ impl Mempool {
         let mut validator = exec_context.tx_validator();
         validator.perform_validations(clone_account_tx(&tx), deploy_account_tx_hash)?;
 
-        if !is_only_query(&tx) {
+        if !only_query {
             // Finally, add it to the nonce chain for the account nonce
             let force = false;
-            self.inner
-                .write()
-                .expect("Poisoned lock")
-                .insert_tx(MempoolTransaction { tx, arrived_at, converted_class }, force)?
+            let mempool_tx = MempoolTransaction { tx, arrived_at, converted_class, submitted };
+            let (tx_hash, sender_address, nonce) =
+                (mempool_tx.tx_hash().to_felt(), mempool_tx.contract_address().to_felt(), mempool_tx.nonce().to_felt());
+            {
+                // The same transaction may have been submitted twice at once.
+                let mut inner = self.inner.write_or_recover();
+                if inner.contains_tx_hash(&mempool_tx.tx_hash()) {
+                    return Err(Error::DuplicateTx(tx_hash));
+                }
+                inner.insert_tx(mempool_tx, force)?;
+            }
+            tracing::debug!(
+                tx_hash = format!("{tx_hash:#x}"),
+                sender_address = format!("{sender_address:#x}"),
+                nonce = format!("{nonce:#x}"),
+                "Accepted transaction {tx_hash:#x} from {sender_address:#x} in the mempool"
+            );
         }
 
         Ok(())
     }
 
+    /// Queues the L1 handler transaction executing an L1 to L2 message, and returns its hash. There is no account
+    /// validation: the message nonce is only checked against the messages already executed on L2.
+    pub fn accept_l1_handler_tx(
+        &self,
+        tx: L1HandlerTransaction,
+        paid_fee_on_l1: Fee,
+    ) -> Result<TransactionHash, Error> {
+        if let Some(tx_hash) = self.backend.get_l1_handler_tx_hash(tx.nonce)? {
+            return Err(Error::L1MessageAlreadyExecuted { nonce: tx.nonce, tx_hash });
+        }
+
+        let chain_id = self.backend.chain_config().chain_id.clone().to_felt();
+        let tx_hash = TransactionHash(tx.compute_hash(chain_id, false, false));
+        let mempool_tx = MempoolL1HandlerTransaction {
+            tx: (&tx).try_into()?,
+            tx_hash,
+            paid_fee_on_l1,
+            arrived_at: self.clock.now_unix(),
+        };
+
+        let force = false;
+        self.inner.write_or_recover().insert_l1_handler_tx(mempool_tx, force)?;
+        tracing::debug!(
+            tx_hash = format!("{:#x}", tx_hash.0),
+            nonce = tx.nonce,
+            "Accepted L1 handler transaction {:#x} of the message with nonce {} in the mempool",
+            tx_hash.0,
+            tx.nonce
+        );
+        Ok(tx_hash)
+    }
+
+    pub fn take_l1_handler_txs_chunk(&self, dest: &mut Vec<MempoolL1HandlerTransaction>, n: usize) {
+        let mut inner = self.inner.write_or_recover();
+        inner.pop_l1_handler_chunk(dest, n)
+    }
+
+    pub fn re_add_l1_handler_txs(&self, txs: Vec<MempoolL1HandlerTransaction>) {
+        let mut inner = self.inner.write_or_recover();
+        inner.re_add_l1_handler_txs(txs)
+    }
+
+    /// Drops the L1 handler transaction of the message with this nonce. Returns whether it was in the mempool.
+    pub fn remove_l1_handler_tx(&self, nonce: u64) -> bool {
+        let mut inner = self.inner.write_or_recover();
+        inner.remove_l1_handler_tx(&Nonce(Felt::from(nonce))).is_some()
+    }
+
     pub fn take_txs_chunk(&self, dest: &mut Vec<MempoolTransaction>, n: usize) {
-        let mut inner = self.inner.write().expect("Poisoned lock");
+        let mut inner = self.inner.write_or_recover();
         inner.pop_next_chunk(dest, n)
     }
 
     pub fn take_tx(&self) -> Option<MempoolTransaction> {
-        let mut inner = self.inner.write().expect("Poisoned lock");
+        let mut inner = self.inner.write_or_recover();
         inner.pop_next()
     }
 
     pub fn re_add_txs(&self, txs: Vec<MempoolTransaction>) {
-        let mut inner = self.inner.write().expect("Poisoned lock");
+        let mut inner = self.inner.write_or_recover();
         inner.re_add_txs(txs)
     }
 
-    pub fn chain_id(&self) -> Felt {
-        Felt::from_bytes_be_slice(format!("{}", self.backend.chain_config().chain_id).as_bytes())
+    /// Prunes the transactions made invalid by a newly imported block:
+    /// - transactions with a nonce lower than the new nonce of their account are removed, and the account gets
+    ///   queued again with its next transaction;
+    /// - every transaction from an account whose deploy account transaction made it into the block without deploying
+    ///   the account is removed;
+    /// - L1 handler transactions of the block are removed.
+    ///
+    /// The mempool lock is not held while reading the state of the backend.
+    pub fn on_new_block(&self, block_info: &DeoxysBlockInfo, state_diff: &StateDiff) -> Result<(), Error> {
+        let block_tx_hashes: HashSet<_> = block_info.tx_hashes.iter().collect();
+        let deployed_in_block: HashSet<_> = state_diff.deployed_contracts().iter().map(|item| &item.address).collect();
+
+        let deploy_candidates: Vec<_> = self
+            .inner
+            .read_or_recover()
+            .deploy_account_txs()
+            .filter(|(contract_address, tx_hash)| {
+                block_tx_hashes.contains(&tx_hash.to_felt()) && !deployed_in_block.contains(&contract_address.to_felt())
+            })
+            .map(|(contract_address, _)| contract_address)
+            .collect();
+
+        let block_id = DbBlockId::BlockN(BlockN(block_info.header.block_number));
+        let mut reverted_deploys = Vec::with_capacity(deploy_candidates.len());
+        for contract_address in deploy_candidates {
+            if !self.backend.is_contract_deployed_at(&block_id, &contract_address.to_felt())? {
+                reverted_deploys.push(contract_address);
+            }
+        }
+
+        let mut inner = self.inner.write_or_recover();
+        let mut n_removed = 0;
+        for NonceUpdate { contract_address, nonce } in state_diff.nonces() {
+            let Ok(contract_address) = ContractAddress::try_from(*contract_address) else { continue };
+            n_removed += inner.remove_txs_below_nonce(&contract_address, Nonce(*nonce)).len();
+        }
+        for contract_address in &reverted_deploys {
+            n_removed += inner.remove_account_txs(contract_address).len();
+        }
+        n_removed += inner.remove_l1_handler_txs_where(|tx| block_tx_hashes.contains(&tx.tx_hash.to_felt())).len();
+        drop(inner);
+
+        if n_removed > 0 {
+            log::debug!(
+                "Removed {} stale transactions from the mempool after block #{} ({} reverted account deployments)",
+                n_removed,
+                block_info.header.block_number,
+                reverted_deploys.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// The nonce of an account once its transactions waiting in the mempool are executed, counting only the
+    /// transactions with consecutive nonces from its nonce in the pending block. `None` when none of them can be
+    /// executed next.
+    pub fn next_nonce_hint(&self, contract_address: Felt) -> Result<Option<Felt>, Error> {
+        let Ok(contract_addr) = ContractAddress::try_from(contract_address) else { return Ok(None) };
+        let nonce = self.backend.get_contract_nonce_at(&DbBlockId::Pending, &contract_address)?.unwrap_or(Felt::ZERO);
+        Ok(self.inner.read_or_recover().next_nonce(&contract_addr, Nonce(nonce)).map(|nonce| nonce.0))
+    }
+
+    /// The transaction with this hash, if it is waiting in the mempool.
+    pub fn get_transaction(&self, tx_hash: Felt) -> Option<TransactionWithHash> {
+        self.inner.read_or_recover().get_transaction(&TransactionHash(tx_hash))
+    }
+
+    /// Whether the transaction with this hash is waiting in the mempool.
+    pub fn contains_tx_hash(&self, tx_hash: &Felt) -> bool {
+        self.inner.read_or_recover().contains_tx_hash(&TransactionHash(*tx_hash))
+    }
+
+    /// Number of transactions waiting in the mempool, L1 handler transactions included.
+    pub fn tx_count(&self) -> usize {
+        self.inner.read_or_recover().tx_count()
+    }
+
+    /// Summaries of every transaction in the mempool, ordered by arrival time.
+    /// The lock is only held while the summaries are being collected.
+    pub fn snapshot(&self) -> Vec<MempoolTxSummary> {
+        let mut summaries: Vec<_> = self.inner.read_or_recover().summaries().collect();
+        summaries
+            .sort_by(|a, b| (a.arrived_at, a.sender_address, a.nonce).cmp(&(b.arrived_at, b.sender_address, b.nonce)));
+        summaries
+    }
+}
+
+impl BlockImportHook for Mempool {
+    fn on_block_imported(&self, block_info: &DeoxysBlockInfo, state_diff: &StateDiff) -> anyhow::Result<()> {
+        Ok(self.on_new_block(block_info, state_diff)?)
+    }
+}
+
+impl L1HandlerSubmitter for Mempool {
+    fn submit_l1_handler_tx(&self, tx: L1HandlerTransaction, paid_fee_on_l1: u128) -> anyhow::Result<Felt> {
+        Ok(self.accept_l1_handler_tx(tx, Fee(paid_fee_on_l1))?.to_felt())
+    }
+
+    fn remove_l1_handler_tx(&self, nonce: u64) -> bool {
+        Mempool::remove_l1_handler_tx(self, nonce)
     }
 }
 
@@ -178,3 +381,265 @@ pub(crate) fn clone_account_tx(tx: &AccountTransaction) -> AccountTransaction {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockL1DataProvider;
+    use blockifier::transaction::transactions::InvokeTransaction;
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysMaybePendingBlock, Header};
+    use dp_state_update::DeployedContractItem;
+    use dp_utils::clock::MockClock;
+    use starknet_api::data_availability::DataAvailabilityMode;
+    use starknet_api::transaction::{DeployAccountTransactionV3, InvokeTransactionV3};
+    use std::time::{Duration, SystemTime};
+
+    async fn test_mempool() -> (tempfile::TempDir, Mempool) {
+        let (temp_dir, db) = temp_database().await;
+        (temp_dir, Mempool::new(Arc::clone(db.backend()), Arc::new(MockL1DataProvider)))
+    }
+
+    fn contract_address(n: u64) -> ContractAddress {
+        ContractAddress::try_from(Felt::from(n)).unwrap()
+    }
+
+    fn mempool_tx(tx: AccountTransaction, arrived_at: u64) -> MempoolTransaction {
+        MempoolTransaction {
+            tx,
+            arrived_at: SystemTime::UNIX_EPOCH + Duration::from_secs(arrived_at),
+            converted_class: None,
+            submitted: None,
+        }
+    }
+
+    fn invoke_tx(tx_hash: u64, sender: u64, nonce: u64) -> MempoolTransaction {
+        let tx = AccountTransaction::Invoke(InvokeTransaction::new(
+            starknet_api::transaction::InvokeTransaction::V3(InvokeTransactionV3 {
+                resource_bounds: Default::default(),
+                tip: Default::default(),
+                signature: Default::default(),
+                nonce: Nonce(Felt::from(nonce)),
+                sender_address: contract_address(sender),
+                calldata: Default::default(),
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                paymaster_data: Default::default(),
+                account_deployment_data: Default::default(),
+            }),
+            TransactionHash(Felt::from(tx_hash)),
+        ));
+        mempool_tx(tx, tx_hash)
+    }
+
+    fn deploy_account_tx(tx_hash: u64, contract: u64) -> MempoolTransaction {
+        let tx = AccountTransaction::DeployAccount(DeployAccountTransaction {
+            tx: starknet_api::transaction::DeployAccountTransaction::V3(DeployAccountTransactionV3 {
+                resource_bounds: Default::default(),
+                tip: Default::default(),
+                signature: Default::default(),
+                nonce: Nonce(Felt::ZERO),
+                class_hash: Default::default(),
+                contract_address_salt: Default::default(),
+                constructor_calldata: Default::default(),
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                paymaster_data: Default::default(),
+            }),
+            tx_hash: TransactionHash(Felt::from(tx_hash)),
+            contract_address: contract_address(contract),
+            only_query: false,
+        });
+        mempool_tx(tx, tx_hash)
+    }
+
+    fn block_info(tx_hashes: &[u64]) -> DeoxysBlockInfo {
+        let header = Header { block_number: 1, ..Default::default() };
+        DeoxysBlockInfo::new(header, tx_hashes.iter().copied().map(Felt::from).collect(), Felt::ONE)
+    }
+
+    fn insert(mempool: &Mempool, txs: impl IntoIterator<Item = MempoolTransaction>) {
+        let mut inner = mempool.inner.write().unwrap();
+        for tx in txs {
+            inner.insert_tx(tx, false).unwrap();
+        }
+    }
+
+    fn remaining_tx_hashes(mempool: &Mempool) -> Vec<Felt> {
+        mempool.inner.read().unwrap().check_invariants();
+        mempool.snapshot().iter().map(|tx| tx.tx_hash).collect()
+    }
+
+    fn l1_handler_tx(nonce: u64) -> L1HandlerTransaction {
+        L1HandlerTransaction {
+            version: Felt::ZERO,
+            nonce,
+            contract_address: Felt::from(0x1234),
+            entry_point_selector: Felt::from(0x5678),
+            calldata: vec![Felt::from(0xae), Felt::from(nonce)],
+        }
+    }
+
+    #[tokio::test]
+    async fn arrival_time_is_read_from_the_clock() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mempool = mempool.with_clock(clock.clone());
+
+        let first = mempool.accept_l1_handler_tx(l1_handler_tx(1), Fee(1)).unwrap();
+        clock.advance(Duration::from_secs(5));
+        let second = mempool.accept_l1_handler_tx(l1_handler_tx(2), Fee(1)).unwrap();
+
+        let snapshot = mempool.snapshot();
+        assert_eq!(snapshot.iter().map(|tx| tx.tx_hash).collect::<Vec<_>>(), [first.to_felt(), second.to_felt()]);
+        assert_eq!(snapshot[0].arrived_at, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        assert_eq!(snapshot[1].arrived_at, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_005));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_by_hash() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        let submitted =
+            Transaction::Invoke(dp_transactions::InvokeTransaction::V1(dp_transactions::InvokeTransactionV1 {
+                sender_address: Felt::from(20),
+                calldata: vec![Felt::ONE],
+                max_fee: Felt::from(0x100),
+                signature: vec![Felt::TWO, Felt::THREE],
+                nonce: Felt::ZERO,
+            }));
+        insert(
+            &mempool,
+            [invoke_tx(1, 10, 0), MempoolTransaction { submitted: Some(submitted.clone()), ..invoke_tx(2, 20, 0) }],
+        );
+        let l1_tx_hash = mempool.accept_l1_handler_tx(l1_handler_tx(1), Fee(1)).unwrap();
+
+        assert_eq!(mempool.get_transaction(Felt::from(2)), Some(TransactionWithHash::new(submitted, Felt::from(2))));
+        // Without its submitted form, the transaction is converted back from blockifier.
+        let tx = mempool.get_transaction(Felt::from(1)).unwrap();
+        assert_eq!(tx.hash, Felt::from(1));
+        assert!(matches!(tx.transaction, Transaction::Invoke(ref tx) if *tx.sender_address() == Felt::from(10)));
+        assert_eq!(
+            mempool.get_transaction(l1_tx_hash.to_felt()),
+            Some(TransactionWithHash::new(Transaction::L1Handler(l1_handler_tx(1)), l1_tx_hash.to_felt()))
+        );
+        assert_eq!(mempool.get_transaction(Felt::from(3)), None);
+
+        // Transactions taken out of the mempool are not served anymore.
+        mempool.take_tx().unwrap();
+        assert_eq!(mempool.get_transaction(Felt::from(1)), None);
+    }
+
+    #[tokio::test]
+    async fn on_new_block_prunes_consumed_nonces() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        insert(
+            &mempool,
+            [invoke_tx(1, 10, 0), invoke_tx(2, 10, 1), invoke_tx(3, 10, 2), invoke_tx(4, 20, 5), invoke_tx(5, 10, 3)],
+        );
+
+        // The block consumes nonces 0 and 1 of account 10, and none of account 20.
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![NonceUpdate { contract_address: Felt::from(10), nonce: Felt::TWO }],
+        );
+        mempool.on_new_block(&block_info(&[1, 2]), &state_diff).unwrap();
+        assert_eq!(remaining_tx_hashes(&mempool), [3, 4, 5].map(Felt::from));
+
+        // The next transaction of account 10 is ready and comes first again.
+        assert_eq!(mempool.take_tx().unwrap().tx_hash().to_felt(), Felt::from(3));
+    }
+
+    #[tokio::test]
+    async fn on_new_block_drops_accounts_with_reverted_deploy() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        insert(
+            &mempool,
+            [deploy_account_tx(1, 10), invoke_tx(2, 10, 1), deploy_account_tx(3, 20), invoke_tx(4, 20, 1)],
+        );
+
+        // Both deploy transactions are in the block, but only account 20 was deployed.
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![DeployedContractItem { address: Felt::from(20), class_hash: Felt::ONE }],
+            vec![],
+            vec![NonceUpdate { contract_address: Felt::from(20), nonce: Felt::ONE }],
+        );
+        mempool.on_new_block(&block_info(&[1, 3]), &state_diff).unwrap();
+        assert_eq!(remaining_tx_hashes(&mempool), [Felt::from(4)]);
+        assert!(!mempool.inner.read().unwrap().has_deployed_contract(&contract_address(10)));
+    }
+
+    #[tokio::test]
+    async fn next_nonce_hint_follows_consecutive_nonces() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        insert(
+            &mempool,
+            [invoke_tx(1, 10, 0), invoke_tx(2, 10, 1), invoke_tx(3, 10, 2), invoke_tx(4, 10, 4), invoke_tx(5, 20, 1)],
+        );
+
+        // Nonce 3 of account 10 is missing, and account 20 is waiting for its nonce 0.
+        assert_eq!(mempool.next_nonce_hint(Felt::from(10)).unwrap(), Some(Felt::THREE));
+        assert_eq!(mempool.next_nonce_hint(Felt::from(20)).unwrap(), None);
+        assert_eq!(mempool.next_nonce_hint(Felt::from(30)).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn mempool_survives_panicking_writer() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        insert(&mempool, [invoke_tx(1, 10, 0), invoke_tx(2, 20, 0)]);
+
+        // A task panics while holding the write lock, poisoning it.
+        std::thread::scope(|s| {
+            let res = s
+                .spawn(|| {
+                    let _inner = mempool.inner.write().unwrap();
+                    panic!("Panicking while holding the mempool lock");
+                })
+                .join();
+            assert!(res.is_err());
+        });
+        assert!(mempool.inner.is_poisoned());
+
+        // Readers and writers keep working.
+        assert_eq!(mempool.snapshot().len(), 2);
+        assert!(!mempool.inner.is_poisoned());
+        assert!(mempool.take_tx().is_some());
+        assert_eq!(remaining_tx_hashes(&mempool).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn duplicate_transactions_are_rejected() {
+        let (_temp_dir, mempool) = test_mempool().await;
+        let is_duplicate = |res: Result<(), Error>, tx_hash: u64| matches!(res, Err(Error::DuplicateTx(hash)) if hash == Felt::from(tx_hash));
+
+        // The same transaction, submitted again.
+        insert(&mempool, [invoke_tx(1, 10, 0)]);
+        assert!(mempool.contains_tx_hash(&Felt::ONE));
+        assert!(is_duplicate(mempool.accept_account_tx(invoke_tx(1, 10, 0).tx, None, None), 1));
+        assert_eq!(remaining_tx_hashes(&mempool), [Felt::ONE]);
+
+        // Transactions of a stored block and of the pending block.
+        let block = DeoxysMaybePendingBlock {
+            info: block_info(&[2]).into(),
+            inner: dp_block::DeoxysBlockInner::new(vec![], vec![]),
+        };
+        mempool.backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+        let pending = DeoxysMaybePendingBlock {
+            info: DeoxysPendingBlockInfo::new(Default::default(), vec![Felt::THREE]).into(),
+            inner: dp_block::DeoxysBlockInner::new(vec![], vec![]),
+        };
+        mempool.backend.store_block(pending, StateDiff::default(), vec![]).unwrap();
+        assert!(mempool.backend.tx_exists(&Felt::TWO).unwrap());
+        assert!(mempool.backend.tx_exists(&Felt::THREE).unwrap());
+        assert!(!mempool.backend.tx_exists(&Felt::from(4)).unwrap());
+        assert!(is_duplicate(mempool.accept_account_tx(invoke_tx(2, 20, 0).tx, None, None), 2));
+        assert!(is_duplicate(mempool.accept_account_tx(invoke_tx(3, 30, 0).tx, None, None), 3));
+        assert_eq!(remaining_tx_hashes(&mempool), [Felt::ONE]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Read-only view of the block being produced, for operators asking what the block would look like if it was closed
+//! now.
+
+use std::sync::Arc;
+
+use blockifier::bouncer::BouncerWeights;
+use dc_db::DeoxysBackend;
+use dc_sync::commitments::preview_state_root;
+use dc_sync::convert::{compute_commitments_for_block, BlockCommitments};
+use dp_block::header::PendingHeader;
+use dp_block::DeoxysPendingBlock;
+use dp_receipt::PriceUnit;
+use dp_state_update::StateDiff;
+use dp_utils::clock::Clock;
+use starknet_core::types::Felt;
+use tokio::sync::watch;
+
+use crate::close_block::closed_header;
+
+/// The block production state, as published by the block production task after every update of the pending block.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingBlockSnapshot {
+    pub block: DeoxysPendingBlock,
+    /// State diff of the whole pending block.
+    pub state_diff: StateDiff,
+    pub block_n: u64,
+    pub bouncer_weights: BouncerWeights,
+    /// Serialized size of the transactions and receipts of the block.
+    pub block_size: u64,
+}
+
+/// What the block being produced would look like if it was closed now.
+#[derive(Debug, Clone)]
+pub struct BlockPreview {
+    pub block_number: u64,
+    /// The pending header, with the timestamp the block would get if it was closed now.
+    pub header: PendingHeader,
+    pub commitments: BlockCommitments,
+    /// The global state root after the block.
+    pub global_state_root: Felt,
+    /// The hash the block would get.
+    pub block_hash: Felt,
+    /// Resources used by the transactions of the block.
+    pub bouncer_weights: BouncerWeights,
+    pub block_max_capacity: BouncerWeights,
+    /// Serialized size of the transactions and receipts of the block, in bytes.
+    pub block_size: u64,
+    /// Size above which new transactions are deferred to the next block.
+    pub max_block_size: u64,
+    /// Sum of the actual fees of the transactions paying in wei.
+    pub fees_wei: Felt,
+    /// Sum of the actual fees of the transactions paying in fri.
+    pub fees_fri: Felt,
+}
+
+/// Gives access to the block being produced. Cloning the handle is cheap.
+#[derive(Clone)]
+pub struct BlockPreviewHandle {
+    backend: Arc<DeoxysBackend>,
+    snapshot: watch::Receiver<Option<Arc<PendingBlockSnapshot>>>,
+    block_max_capacity: BouncerWeights,
+    max_block_size: u64,
+    chain_id: Felt,
+    clock: Arc<dyn Clock>,
+}
+
+impl BlockPreviewHandle {
+    pub(crate) fn new(
+        backend: Arc<DeoxysBackend>,
+        snapshot: watch::Receiver<Option<Arc<PendingBlockSnapshot>>>,
+        block_max_capacity: BouncerWeights,
+        max_block_size: u64,
+        chain_id: Felt,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self { backend, snapshot, block_max_capacity, max_block_size, chain_id, clock }
+    }
+
+    /// Computes the preview of the pending block. The block production task keeps executing transactions while the
+    /// commitments are computed.
+    ///
+    /// The state root is computed by committing the state diff of the block to the global tries, then reverting it:
+    /// the block production waits for the revert before closing a block. The readers of the tries, such as
+    /// `starknet_getStorageProof`, may see the state of the pending block in the meantime.
+    ///
+    /// Returns `None` when the block production task has not created a pending block yet, when it closed the block
+    /// while the preview was computed, or when the commitments cannot be computed.
+    pub fn preview(&self) -> Option<BlockPreview> {
+        // Release the channel lock right away: the block production task must not wait on the commitments.
+        let snapshot = self.snapshot.borrow().clone()?;
+        let PendingBlockSnapshot { block, state_diff, block_n, bouncer_weights, block_size } = snapshot.as_ref();
+
+        let mut header = block.info.header.clone();
+        header.block_timestamp = self.clock.unix_timestamp();
+
+        let (global_state_root, commitments) = rayon::join(
+            || self.preview_state_root(state_diff, *block_n),
+            || {
+                compute_commitments_for_block(
+                    &block.inner,
+                    state_diff,
+                    header.protocol_version,
+                    self.chain_id,
+                    *block_n,
+                )
+            },
+        );
+        let commitments = match commitments {
+            Ok(commitments) => commitments,
+            Err(err) => {
+                log::error!("Failed to compute the commitments of the preview of block {block_n}: {err:#}");
+                return None;
+            }
+        };
+        let global_state_root = global_state_root?;
+        let block_hash =
+            closed_header(header.clone(), *block_n, global_state_root, &commitments).compute_hash(self.chain_id);
+
+        let (mut fees_wei, mut fees_fri) = (Felt::ZERO, Felt::ZERO);
+        for receipt in &block.inner.receipts {
+            let fee = receipt.actual_fee();
+            match fee.unit {
+                PriceUnit::Wei => fees_wei += fee.amount,
+                PriceUnit::Fri => fees_fri += fee.amount,
+            }
+        }
+
+        Some(BlockPreview {
+            block_number: *block_n,
+            header,
+            commitments,
+            global_state_root,
+            block_hash,
+            bouncer_weights: *bouncer_weights,
+            block_max_capacity: self.block_max_capacity,
+            block_size: *block_size,
+            max_block_size: self.max_block_size,
+            fees_wei,
+            fees_fri,
+        })
+    }
+
+    fn preview_state_root(&self, state_diff: &StateDiff, block_n: u64) -> Option<Felt> {
+        let _tries_update = self.backend.lock_tries_update();
+        // The block was closed since the snapshot was taken: its state diff is in the tries already.
+        match self.backend.get_latest_block_n() {
+            Ok(latest) if latest == block_n.checked_sub(1) => {}
+            Ok(_) => return None,
+            Err(err) => {
+                log::error!("Failed to read the latest block for the preview of block {block_n}: {err:#}");
+                return None;
+            }
+        }
+        preview_state_root(&self.backend, state_diff, block_n)
+            .inspect_err(|err| {
+                log::error!("Failed to compute the state root of the preview of block {block_n}: {err:#}")
+            })
+            .ok()
+    }
+}
@@ -0,0 +1,116 @@
+//! Fixtures for the tests of the crates built on the mempool, enabled by the `testing` feature.
+
+use blockifier::test_utils::contracts::FeatureContract;
+use blockifier::test_utils::CairoVersion;
+use dc_db::DeoxysBackend;
+use dp_block::chain_config::ChainConfig;
+use dp_block::header::{GasPrices, L1DataAvailabilityMode};
+use dp_block::DeoxysBlock;
+use dp_class::{ClassInfo, ContractClass, ConvertedClass, ToCompiledClass};
+use dp_convert::ToFelt;
+use dp_utils::clock::SystemClock;
+use starknet_core::types::contract::legacy::LegacyContractClass;
+use starknet_core::types::{BroadcastedInvokeTransaction, BroadcastedInvokeTransactionV1};
+use starknet_core::utils::get_selector_from_name;
+use starknet_types_core::felt::Felt;
+
+use crate::genesis::GenesisBuilder;
+use crate::L1DataProvider;
+
+/// Class hash under which the account class of the tests is declared. It is not the hash of the class.
+pub const TEST_ACCOUNT_CLASS_HASH: Felt = Felt::from_hex_unchecked("0xacc0");
+/// Class hash under which the test contract class is declared. It is not the hash of the class.
+pub const TEST_CONTRACT_CLASS_HASH: Felt = Felt::from_hex_unchecked("0xc0c0");
+/// Class hash under which the ERC20 class of the tests is declared. It is not the hash of the class.
+pub const TEST_ERC20_CLASS_HASH: Felt = Felt::from_hex_unchecked("0xe2c0");
+/// Address of the account deployed by [`store_test_genesis`].
+pub const TEST_ACCOUNT_ADDRESS: Felt = Felt::from_hex_unchecked("0xacc1");
+/// Balance of the account in both fee tokens of the chain.
+pub const TEST_ACCOUNT_BALANCE: u128 = 10u128.pow(24);
+/// Balance of each devnet account in both fee tokens of the chain.
+pub const DEVNET_ACCOUNT_BALANCE: u128 = 10u128.pow(24);
+
+/// Default gas prices, with the state diffs posted as calldata.
+pub struct MockL1DataProvider;
+
+impl L1DataProvider for MockL1DataProvider {
+    fn get_gas_prices(&self) -> GasPrices {
+        GasPrices::default()
+    }
+    fn get_da_mode(&self) -> L1DataAvailabilityMode {
+        L1DataAvailabilityMode::Calldata
+    }
+}
+
+/// The Cairo 0 account of the blockifier test contracts, which accepts every transaction without checking its
+/// signature.
+pub fn test_account_class() -> ConvertedClass {
+    cairo0_class(FeatureContract::AccountWithoutValidations(CairoVersion::Cairo0), TEST_ACCOUNT_CLASS_HASH)
+}
+
+/// The Cairo 0 test contract of blockifier, which calls other contracts and fails on demand.
+pub fn test_contract_class() -> ConvertedClass {
+    cairo0_class(FeatureContract::TestContract(CairoVersion::Cairo0), TEST_CONTRACT_CLASS_HASH)
+}
+
+/// The Cairo 0 ERC20 contract of blockifier, which keeps its balances in [`crate::genesis::ERC20_BALANCES_VAR`] and
+/// can be deployed as a fee token.
+pub fn test_erc20_class() -> ConvertedClass {
+    cairo0_class(FeatureContract::ERC20, TEST_ERC20_CLASS_HASH)
+}
+
+fn cairo0_class(contract: FeatureContract, class_hash: Felt) -> ConvertedClass {
+    let raw_class = contract.get_raw_class();
+    let class: LegacyContractClass = serde_json::from_str(&raw_class).expect("Parsing a test class");
+    let class = class.compress().expect("Compressing a test class");
+    let compiled = class.compile().expect("Compiling a test class");
+    let class_info = ClassInfo {
+        contract_class: ContractClass::Legacy(class.into()),
+        compiled_class_hash: Felt::ZERO,
+        block_number: None,
+    };
+    ConvertedClass { class_infos: (class_hash, class_info), class_compiled: (class_hash, compiled) }
+}
+
+/// Stores a genesis block deploying the account of [`test_account_class`] at [`TEST_ACCOUNT_ADDRESS`], so that the
+/// transactions of [`test_invoke_transaction`] are accepted by the mempool.
+pub fn store_test_genesis(backend: &DeoxysBackend) -> DeoxysBlock {
+    let chain_config = backend.chain_config();
+    GenesisBuilder::new()
+        .declare(test_account_class())
+        .deploy(TEST_ACCOUNT_ADDRESS, TEST_ACCOUNT_CLASS_HASH)
+        .fund(chain_config.parent_fee_token_address.to_felt(), TEST_ACCOUNT_ADDRESS, TEST_ACCOUNT_BALANCE)
+        .fund(chain_config.native_fee_token_address.to_felt(), TEST_ACCOUNT_ADDRESS, TEST_ACCOUNT_BALANCE)
+        .store(backend, &MockL1DataProvider, &SystemClock)
+        .expect("Storing the test genesis block")
+}
+
+/// The genesis of a local devnet: the fee tokens of the chain are deployed as instances of [`test_erc20_class`], and
+/// the first `count` accounts of `seed`, instances of [`test_account_class`], are given [`DEVNET_ACCOUNT_BALANCE`] of
+/// both of them.
+///
+/// The account class does not check the signatures: the private keys of the accounts are only there for the clients
+/// that sign their transactions anyway.
+pub fn devnet_genesis(chain_config: &ChainConfig, seed: u64, count: u64) -> GenesisBuilder {
+    let fee_tokens = [chain_config.parent_fee_token_address.to_felt(), chain_config.native_fee_token_address.to_felt()];
+    let mut genesis = GenesisBuilder::new().declare(test_account_class()).declare(test_erc20_class());
+    for token in fee_tokens {
+        genesis = genesis.deploy(token, TEST_ERC20_CLASS_HASH);
+    }
+    genesis.with_devnet_accounts(seed, count, TEST_ACCOUNT_CLASS_HASH, &fee_tokens, DEVNET_ACCOUNT_BALANCE)
+}
+
+/// An invoke transaction of the test account, calling a function without arguments of a contract. Each nonce gives
+/// another transaction.
+pub fn test_invoke_transaction(nonce: u64) -> BroadcastedInvokeTransaction {
+    let selector = get_selector_from_name("transfer").expect("Selector name is ASCII");
+    BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+        sender_address: TEST_ACCOUNT_ADDRESS,
+        // The arguments of `__validate__` and `__execute__` of the Cairo 0 accounts: the call and its calldata.
+        calldata: vec![Felt::from(0x1234), selector, Felt::ZERO],
+        max_fee: Felt::from(0x100),
+        signature: vec![],
+        nonce: Felt::from(nonce),
+        is_query: false,
+    })
+}
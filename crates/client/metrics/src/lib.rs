@@ -1,7 +1,8 @@
 use std::net::{Ipv4Addr, SocketAddr};
 
 use anyhow::Context;
-use dp_utils::{service::Service, wait_or_graceful_shutdown, StopHandle};
+use dp_utils::{service::Service, supervisor::Supervisor, wait_or_graceful_shutdown, StopHandle};
+use futures::{Future, FutureExt};
 use hyper::{
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server, StatusCode,
@@ -34,6 +35,11 @@ impl MetricsRegistry {
     pub fn is_enabled(&self) -> bool {
         self.0.is_some()
     }
+
+    /// The metrics registered so far, none when Prometheus is disabled.
+    pub fn gather(&self) -> Vec<prometheus::proto::MetricFamily> {
+        self.0.as_ref().map(Registry::gather).unwrap_or_default()
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -63,12 +69,46 @@ async fn endpoint(req: Request<Body>, registry: Registry) -> Result<Response<Bod
     }
 }
 
+/// Serves the Prometheus endpoint until `stop` resolves or the node shuts down.
+async fn serve(addr: SocketAddr, registry: MetricsRegistry, stop: impl Future) -> anyhow::Result<()> {
+    let service = make_service_fn(move |_| {
+        let registry = registry.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                let registry = registry.clone();
+                async move {
+                    match endpoint(req, registry.0.expect("Registry should not be none").clone()).await {
+                        Ok(res) => Ok::<_, Error>(res),
+                        Err(err) => {
+                            log::error!("Error when handling prometheus request: {}", err);
+                            Ok(Response::builder()
+                                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                                .body(Body::from("Internal server error"))?)
+                        }
+                    }
+                }
+            }))
+        }
+    });
+
+    let socket = TcpListener::bind(addr).await.with_context(|| format!("Opening socket server at {addr}"))?;
+    let listener = hyper::server::conn::AddrIncoming::from_listener(socket)
+        .with_context(|| format!("Opening socket server at {addr}"))?;
+    log::info!("📈 Prometheus endpoint started at {}", listener.local_addr());
+    let server = Server::builder(listener).serve(service).with_graceful_shutdown(async {
+        wait_or_graceful_shutdown(stop).await;
+    });
+    server.await.context("Running prometheus server")?;
+    Ok(())
+}
+
 pub struct MetricsService {
     no_prometheus: bool,
     prometheus_external: bool,
     prometheus_port: u16,
     registry: MetricsRegistry,
     stop_handle: StopHandle,
+    supervisor: Supervisor,
 }
 
 impl MetricsService {
@@ -79,9 +119,15 @@ impl MetricsService {
             prometheus_port,
             registry: MetricsRegistry(if no_prometheus { None } else { Some(Default::default()) }),
             stop_handle: Default::default(),
+            supervisor: Default::default(),
         })
     }
 
+    /// Restarts the Prometheus endpoint with `supervisor` when it fails.
+    pub fn with_supervisor(self, supervisor: Supervisor) -> Self {
+        Self { supervisor, ..self }
+    }
+
     pub fn registry(&self) -> MetricsRegistry {
         self.registry.clone()
     }
@@ -101,41 +147,12 @@ impl Service for MetricsService {
         };
         let addr = SocketAddr::new(listen_addr.into(), self.prometheus_port);
 
-        let registry = self.registry.clone();
-        let service = make_service_fn(move |_| {
-            let registry = registry.clone();
-            async move {
-                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
-                    let registry = registry.clone();
-                    async move {
-                        match endpoint(req, registry.0.expect("Registry should not be none").clone()).await {
-                            Ok(res) => Ok::<_, Error>(res),
-                            Err(err) => {
-                                log::error!("Error when handling prometheus request: {}", err);
-                                Ok(Response::builder()
-                                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                                    .body(Body::from("Internal server error"))?)
-                            }
-                        }
-                    }
-                }))
-            }
-        });
-
         let (stop_send, stop_recv) = oneshot::channel();
         self.stop_handle = StopHandle::new(Some(stop_send));
+        let stop_recv = stop_recv.shared();
 
-        join_set.spawn(async move {
-            let socket = TcpListener::bind(addr).await.with_context(|| format!("Opening socket server at {addr}"))?;
-            let listener = hyper::server::conn::AddrIncoming::from_listener(socket)
-                .with_context(|| format!("Opening socket server at {addr}"))?;
-            log::info!("📈 Prometheus endpoint started at {}", listener.local_addr());
-            let server = Server::builder(listener).serve(service).with_graceful_shutdown(async {
-                wait_or_graceful_shutdown(stop_recv).await;
-            });
-            server.await.context("Running prometheus server")?;
-            Ok(())
-        });
+        let registry = self.registry.clone();
+        self.supervisor.spawn(join_set, "metrics", move || serve(addr, registry.clone(), stop_recv.clone()));
 
         Ok(())
     }
@@ -0,0 +1,353 @@
+//! Results of `starknet_call`, kept for a short time.
+//!
+//! Frontends poll the same view functions (balances, reserves) at the latest block many times per second, and every
+//! call executes a contract entry point. The result of a call only depends on the state of the block it is executed
+//! on: it is cached by block hash, and the whole cache is dropped every time the chain tip moves so that it only holds
+//! the calls of the blocks being polled.
+//!
+//! The calls on the pending block are never cached: the pending block has no hash, and its state changes every time
+//! it is updated.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use dc_metrics::{Counter, MetricsRegistry, PrometheusError, U64};
+use dp_block::Header;
+use dp_utils::clock::{Clock, SystemClock};
+use dp_utils::lock::MutexExt;
+use starknet_core::types::Felt;
+use starknet_types_core::hash::{Poseidon, StarkHash};
+use tokio::sync::watch;
+
+use crate::errors::StarknetRpcResult;
+
+#[derive(Debug, Clone)]
+pub struct CallCacheConfig {
+    /// Number of call results kept. The cache is disabled when this is 0.
+    pub capacity: usize,
+    /// A result is executed again once it is older than this, even if the chain tip did not move.
+    pub ttl: Duration,
+}
+
+impl Default for CallCacheConfig {
+    fn default() -> Self {
+        Self { capacity: 1024, ttl: Duration::from_secs(2) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CallCacheMetrics {
+    pub hits: Counter<U64>,
+    pub misses: Counter<U64>,
+}
+
+impl CallCacheMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            hits: registry.register(Counter::new(
+                "deoxys_rpc_call_cache_hits",
+                "Counter of the starknet_call requests answered from the call cache",
+            )?)?,
+            misses: registry.register(Counter::new(
+                "deoxys_rpc_call_cache_misses",
+                "Counter of the starknet_call requests on a closed block that were executed",
+            )?)?,
+        })
+    }
+}
+
+/// Number of lookups made in a [`CallCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallCacheStats {
+    pub hits: u64,
+    /// Every miss executes the call.
+    pub misses: u64,
+}
+
+/// A call on a closed block. The calldata is hashed so that large calldatas do not stay in memory.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallKey {
+    block_hash: Felt,
+    contract_address: Felt,
+    entry_point_selector: Felt,
+    calldata_hash: Felt,
+}
+
+impl CallKey {
+    pub fn new(block_hash: Felt, contract_address: Felt, entry_point_selector: Felt, calldata: &[Felt]) -> Self {
+        Self { block_hash, contract_address, entry_point_selector, calldata_hash: Poseidon::hash_array(calldata) }
+    }
+}
+
+struct CachedCall {
+    result: Vec<Felt>,
+    inserted_at: SystemTime,
+    age: u64,
+}
+
+struct CallCacheInner {
+    /// The cache is cleared when this header changes.
+    latest_header: watch::Receiver<Option<Arc<Header>>>,
+    next_age: u64,
+    calls: HashMap<CallKey, CachedCall>,
+    by_age: BTreeMap<u64, CallKey>,
+}
+
+impl CallCacheInner {
+    fn clear_on_new_tip(&mut self) {
+        if self.latest_header.has_changed().unwrap_or(false) {
+            self.latest_header.mark_unchanged();
+            self.calls.clear();
+            self.by_age.clear();
+        }
+    }
+
+    fn remove(&mut self, key: &CallKey) {
+        if let Some(cached) = self.calls.remove(key) {
+            self.by_age.remove(&cached.age);
+        }
+    }
+}
+
+/// Size-bounded cache of the results of `starknet_call`, the oldest result is evicted first.
+pub struct CallCache {
+    config: CallCacheConfig,
+    inner: Mutex<CallCacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    metrics: Option<CallCacheMetrics>,
+    /// Gives the age of the results.
+    clock: Arc<dyn Clock>,
+}
+
+impl CallCache {
+    /// `latest_header` is the chain tip of the backend, see [`dc_db::DeoxysBackend::subscribe_latest_header`].
+    pub fn new(config: CallCacheConfig, mut latest_header: watch::Receiver<Option<Arc<Header>>>) -> Self {
+        latest_header.mark_unchanged();
+        Self {
+            config,
+            inner: Mutex::new(CallCacheInner {
+                latest_header,
+                next_age: 0,
+                calls: Default::default(),
+                by_age: Default::default(),
+            }),
+            hits: Default::default(),
+            misses: Default::default(),
+            metrics: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn with_metrics(self, metrics: CallCacheMetrics) -> Self {
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    pub fn stats(&self) -> CallCacheStats {
+        CallCacheStats { hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed) }
+    }
+
+    /// The cached result of the call, or the result of `call` when it is not cached, in which case only it is awaited.
+    /// Errors are not cached.
+    pub async fn get_or_call(
+        &self,
+        key: CallKey,
+        call: impl Future<Output = StarknetRpcResult<Vec<Felt>>>,
+    ) -> StarknetRpcResult<Vec<Felt>> {
+        if let Some(result) = self.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = &self.metrics {
+                metrics.hits.inc();
+            }
+            return Ok(result);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.misses.inc();
+        }
+        // The lock is not held while executing the call.
+        let result = call.await?;
+        self.insert(key, result.clone());
+        Ok(result)
+    }
+
+    fn get(&self, key: &CallKey) -> Option<Vec<Felt>> {
+        let mut inner = self.inner.lock_or_recover();
+        inner.clear_on_new_tip();
+        let cached = inner.calls.get(key)?;
+        if self.clock.elapsed_since(cached.inserted_at) >= self.config.ttl {
+            inner.remove(key);
+            return None;
+        }
+        Some(cached.result.clone())
+    }
+
+    fn insert(&self, key: CallKey, result: Vec<Felt>) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock_or_recover();
+        inner.clear_on_new_tip();
+        inner.remove(&key);
+        let age = inner.next_age;
+        inner.next_age += 1;
+        inner.calls.insert(key.clone(), CachedCall { result, inserted_at: self.clock.now_unix(), age });
+        inner.by_age.insert(age, key);
+
+        while inner.by_age.len() > self.config.capacity {
+            let Some((_, oldest)) = inner.by_age.pop_first() else { break };
+            inner.calls.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dc_db::testing::temp_database;
+    use dc_db::DatabaseService;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock};
+    use dp_state_update::StateDiff;
+    use dp_utils::clock::MockClock;
+
+    use super::*;
+    use crate::errors::StarknetRpcApiError;
+
+    fn balance_of(block_hash: Felt, account: u64) -> CallKey {
+        CallKey::new(block_hash, Felt::from(0xfee), Felt::from(0xba1), &[Felt::from(account)])
+    }
+
+    /// Counts the executions of the calls.
+    async fn call(cache: &CallCache, executions: &mut u32, key: CallKey) -> Vec<Felt> {
+        cache
+            .get_or_call(key, async {
+                *executions += 1;
+                Ok(vec![Felt::from(*executions)])
+            })
+            .await
+            .unwrap()
+    }
+
+    fn store_block(db: &DatabaseService, block_n: u64) {
+        let header = dp_block::Header { block_number: block_n, ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![], Felt::from(0x10 + block_n)).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        db.backend().store_block(block, StateDiff::default(), vec![]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_calls_are_cached_until_the_tip_moves() {
+        let (_temp_dir, db) = temp_database().await;
+        store_block(&db, 0);
+        let config = CallCacheConfig { capacity: 16, ttl: Duration::from_secs(60) };
+        let cache = CallCache::new(config, db.backend().subscribe_latest_header());
+        let mut executions = 0;
+
+        // The same call at a pinned block is executed once.
+        assert_eq!(call(&cache, &mut executions, balance_of(Felt::from(0x10), 1)).await, [Felt::ONE]);
+        assert_eq!(call(&cache, &mut executions, balance_of(Felt::from(0x10), 1)).await, [Felt::ONE]);
+        assert_eq!(executions, 1);
+        assert_eq!(cache.stats(), CallCacheStats { hits: 1, misses: 1 });
+
+        // Another calldata is another call.
+        call(&cache, &mut executions, balance_of(Felt::from(0x10), 2)).await;
+        assert_eq!(executions, 2);
+
+        // A new block drops the cached results.
+        store_block(&db, 1);
+        assert_eq!(call(&cache, &mut executions, balance_of(Felt::from(0x10), 1)).await, [Felt::THREE]);
+        assert_eq!(executions, 3);
+        call(&cache, &mut executions, balance_of(Felt::from(0x10), 1)).await;
+        assert_eq!(executions, 3);
+    }
+
+    fn cache_with_clock(ttl: Duration) -> (CallCache, Arc<MockClock>) {
+        let (_sender, latest_header) = watch::channel(None);
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let cache = CallCache::new(CallCacheConfig { capacity: 16, ttl }, latest_header).with_clock(clock.clone());
+        (cache, clock)
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_executed_again() {
+        let (cache, _clock) = cache_with_clock(Duration::from_secs(2));
+        let mut executions = 0;
+
+        let err =
+            cache.get_or_call(balance_of(Felt::ONE, 1), async { Err(StarknetRpcApiError::ContractNotFound) }).await;
+        assert!(matches!(err, Err(StarknetRpcApiError::ContractNotFound)));
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        assert_eq!(executions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_calls_expire_after_the_ttl() {
+        let (cache, clock) = cache_with_clock(Duration::from_secs(2));
+        let mut executions = 0;
+
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        clock.advance(Duration::from_millis(1_999));
+        assert_eq!(call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await, [Felt::ONE]);
+        assert_eq!(executions, 1);
+
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await, [Felt::TWO]);
+        assert_eq!(executions, 2);
+        // The result executed again gets a new TTL.
+        clock.advance(Duration::from_secs(1));
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        assert_eq!(executions, 2);
+    }
+
+    #[tokio::test]
+    async fn test_calls_expire_independently() {
+        let (cache, clock) = cache_with_clock(Duration::from_secs(2));
+        let mut executions = 0;
+
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        clock.advance(Duration::from_secs(1));
+        call(&cache, &mut executions, balance_of(Felt::ONE, 2)).await;
+        clock.advance(Duration::from_secs(1));
+
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        call(&cache, &mut executions, balance_of(Felt::ONE, 2)).await;
+        assert_eq!(executions, 3);
+        assert_eq!(cache.stats(), CallCacheStats { hits: 1, misses: 3 });
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_never_serves_a_result() {
+        let (cache, _clock) = cache_with_clock(Duration::ZERO);
+        let mut executions = 0;
+
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        assert_eq!(executions, 2);
+    }
+
+    #[tokio::test]
+    async fn test_oldest_call_is_evicted() {
+        let (_sender, latest_header) = watch::channel(None);
+        let cache = CallCache::new(CallCacheConfig { capacity: 2, ttl: Duration::from_secs(60) }, latest_header);
+        let mut executions = 0;
+
+        for account in [1, 2, 3] {
+            call(&cache, &mut executions, balance_of(Felt::ONE, account)).await;
+        }
+        call(&cache, &mut executions, balance_of(Felt::ONE, 3)).await;
+        assert_eq!(executions, 3);
+        call(&cache, &mut executions, balance_of(Felt::ONE, 1)).await;
+        assert_eq!(executions, 4);
+    }
+}
@@ -0,0 +1,184 @@
+//! Chain constants served by the RPC server.
+
+use std::sync::Arc;
+
+use dc_db::DeoxysBackend;
+use dp_block::chain_config::ChainConfig;
+use dp_block::{Header, StarknetVersion};
+use dp_convert::ToFelt;
+use starknet_api::transaction::TransactionVersion;
+use starknet_types_core::felt::Felt;
+use tokio::sync::watch;
+
+/// Chain id, fee tokens and protocol version of the chain, computed once from the [`ChainConfig`] and kept up to date
+/// with the chain tip. Answering a query from this handle never touches the database or the execution machinery.
+#[derive(Clone)]
+pub struct ChainHandle {
+    chain_id: Felt,
+    chain_id_str: Arc<str>,
+    native_fee_token_address: Felt,
+    parent_fee_token_address: Felt,
+    /// Used until the first block is stored.
+    default_protocol_version: StarknetVersion,
+    latest_header: watch::Receiver<Option<Arc<Header>>>,
+}
+
+impl ChainHandle {
+    pub fn new(chain_config: &ChainConfig, latest_header: watch::Receiver<Option<Arc<Header>>>) -> Self {
+        Self {
+            chain_id: chain_config.chain_id.clone().to_felt(),
+            chain_id_str: chain_config.chain_id.to_string().into(),
+            native_fee_token_address: chain_config.native_fee_token_address.to_felt(),
+            parent_fee_token_address: chain_config.parent_fee_token_address.to_felt(),
+            default_protocol_version: chain_config.latest_protocol_version,
+            latest_header,
+        }
+    }
+
+    pub fn from_backend(backend: &DeoxysBackend) -> Self {
+        Self::new(backend.chain_config(), backend.subscribe_latest_header())
+    }
+
+    /// Chain id, as a short string encoded in a felt.
+    pub fn chain_id(&self) -> Felt {
+        self.chain_id
+    }
+
+    /// Human readable chain id, such as `SN_MAIN`.
+    pub fn chain_id_str(&self) -> &str {
+        &self.chain_id_str
+    }
+
+    /// The STRK fee token contract address.
+    pub fn native_fee_token_address(&self) -> Felt {
+        self.native_fee_token_address
+    }
+
+    /// The ETH fee token contract address.
+    pub fn parent_fee_token_address(&self) -> Felt {
+        self.parent_fee_token_address
+    }
+
+    /// Protocol version of the latest block.
+    pub fn protocol_version(&self) -> StarknetVersion {
+        match &*self.latest_header.borrow() {
+            Some(header) => header.protocol_version,
+            None => self.default_protocol_version,
+        }
+    }
+
+    /// Transaction versions accepted at the protocol version of the latest block.
+    pub fn supported_tx_versions(&self) -> &'static [TransactionVersion] {
+        const WITHOUT_V3: &[TransactionVersion] =
+            &[TransactionVersion::ZERO, TransactionVersion::ONE, TransactionVersion::TWO];
+        const WITH_V3: &[TransactionVersion] =
+            &[TransactionVersion::ZERO, TransactionVersion::ONE, TransactionVersion::TWO, TransactionVersion::THREE];
+
+        if self.protocol_version() < StarknetVersion::STARKNET_VERSION_0_13_0 {
+            WITHOUT_V3
+        } else {
+            WITH_V3
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dc_db::DatabaseService;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo};
+    use dp_state_update::StateDiff;
+    use starknet_providers::SequencerGatewayProvider;
+
+    use super::*;
+    use crate::providers::ForwardToProvider;
+    use crate::{Starknet, StarknetReadRpcApiServer};
+
+    fn block(block_number: u64, protocol_version: StarknetVersion) -> DeoxysMaybePendingBlock {
+        let header = Header { block_number, protocol_version, ..Default::default() };
+        DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(
+                header,
+                vec![],
+                Felt::from(block_number),
+            )),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        }
+    }
+
+    #[test]
+    fn test_values_match_config() {
+        let chain_config = ChainConfig::starknet_mainnet();
+        let (_sender, receiver) = watch::channel(None);
+        let handle = ChainHandle::new(&chain_config, receiver);
+
+        assert_eq!(handle.chain_id(), Felt::from_bytes_be_slice(b"SN_MAIN"));
+        assert_eq!(handle.chain_id_str(), "SN_MAIN");
+        assert_eq!(handle.native_fee_token_address(), chain_config.native_fee_token_address.to_felt());
+        assert_eq!(handle.parent_fee_token_address(), chain_config.parent_fee_token_address.to_felt());
+        assert_eq!(handle.protocol_version(), chain_config.latest_protocol_version);
+    }
+
+    #[test]
+    fn test_supported_tx_versions() {
+        let chain_config = ChainConfig::starknet_mainnet();
+        let header = Header { protocol_version: StarknetVersion::STARKNET_VERSION_0_11_1, ..Default::default() };
+        let (sender, receiver) = watch::channel(Some(Arc::new(header)));
+        let handle = ChainHandle::new(&chain_config, receiver);
+        assert!(!handle.supported_tx_versions().contains(&TransactionVersion::THREE));
+
+        let header = Header { protocol_version: StarknetVersion::STARKNET_VERSION_0_13_0, ..Default::default() };
+        sender.send_replace(Some(Arc::new(header)));
+        assert!(handle.supported_tx_versions().contains(&TransactionVersion::THREE));
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_follows_new_blocks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::test_config());
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::clone(&chain_config)).await.unwrap();
+        let backend = db.backend();
+        let handle = ChainHandle::from_backend(backend);
+        assert_eq!(handle.protocol_version(), chain_config.latest_protocol_version);
+
+        backend.store_block(block(0, StarknetVersion::STARKNET_VERSION_0_13_1), StateDiff::default(), vec![]).unwrap();
+        assert_eq!(handle.protocol_version(), StarknetVersion::STARKNET_VERSION_0_13_1);
+
+        backend.store_block(block(1, StarknetVersion::STARKNET_VERSION_0_13_2), StateDiff::default(), vec![]).unwrap();
+        assert_eq!(handle.protocol_version(), StarknetVersion::STARKNET_VERSION_0_13_2);
+
+        // The tip is read back from the database when the node restarts.
+        drop(db);
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        let handle = ChainHandle::from_backend(db.backend());
+        assert_eq!(handle.protocol_version(), StarknetVersion::STARKNET_VERSION_0_13_2);
+    }
+
+    #[tokio::test]
+    async fn test_chain_id_of_a_chain_spec() {
+        let spec = r#"
+            chain_name = "Appchain"
+            chain_id = "MY_APPCHAIN"
+            native_fee_token_address = "0x1"
+            parent_fee_token_address = "0x2"
+            sequencer_address = "0x3"
+            eth_core_contract_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+        "#;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = Arc::new(ChainConfig::from_toml(spec).unwrap());
+        let db = DatabaseService::new(temp_dir.path(), None, false, chain_config).await.unwrap();
+        let backend = Arc::clone(db.backend());
+
+        let url: url::Url = "http://localhost:1".parse().unwrap();
+        let add_transaction_provider = Arc::new(ForwardToProvider::new(SequencerGatewayProvider::new(
+            url.join("gateway").unwrap(),
+            url.join("feeder_gateway").unwrap(),
+            Felt::from_bytes_be_slice(b"MY_APPCHAIN"),
+        )));
+        let rpc_chain_config = crate::ChainConfig { feeder_gateway: url.clone(), gateway: url };
+        let chain = ChainHandle::from_backend(&backend);
+        let starknet = Starknet::new(backend, rpc_chain_config, chain, add_transaction_provider, None, None);
+
+        assert_eq!(StarknetReadRpcApiServer::chain_id(&starknet).unwrap(), Felt::from_bytes_be_slice(b"MY_APPCHAIN"));
+        assert_eq!(starknet.chain().native_fee_token_address(), Felt::ONE);
+    }
+}
@@ -1,4 +1,56 @@
+/// Version of the Starknet JSON-RPC specification served by the node, returned by `starknet_specVersion`. The types
+/// and optional fields of the responses are the ones of this version.
+pub const SPEC_VERSION: &str = "0.7.1";
+/// Traces carry the state diff of their transaction since version 0.7 of the specification.
+pub(crate) const TRACE_STATE_DIFFS: bool = spec_version_at_least(0, 7);
 /// Maximum number of filter keys that can be passed to the `get_events` RPC.
 pub const MAX_EVENTS_KEYS: usize = 100;
 /// Maximum number of events that can be fetched in a single chunk for the `get_events` RPC.
 pub const MAX_EVENTS_CHUNK_SIZE: usize = 1000;
+/// Maximum number of transactions that can be fetched in a single page for the `deoxys_getMempoolTransactions` RPC.
+pub const MAX_MEMPOOL_PAGE_SIZE: usize = 1000;
+/// Maximum number of addresses that can be passed to the `deoxys_getClassHashesAt` RPC.
+pub const MAX_CLASS_HASHES_AT_ADDRESSES: usize = 1000;
+/// Maximum number of classes that can be listed in a single page for the `deoxys_listClasses` RPC.
+pub const MAX_CLASSES_PAGE_SIZE: usize = 1000;
+/// Maximum number of class hashes that can be passed to the `deoxys_getClassesBatch` RPC. Class definitions are large,
+/// this keeps the response within the response size limit of the server.
+pub const MAX_CLASSES_BATCH_SIZE: usize = 50;
+/// Maximum number of contracts that can be listed in a single page for the `deoxys_getContractsByClassHash` RPC.
+pub const MAX_CONTRACTS_PAGE_SIZE: usize = 1000;
+/// Maximum number of nonce changes that can be fetched in a single page for the `deoxys_getNonceHistory` RPC.
+pub const MAX_NONCE_HISTORY_PAGE_SIZE: usize = 1000;
+/// Maximum number of blocks that can be passed to the `deoxys_getGasPriceHistory` RPC, the node keeps the gas prices of
+/// these blocks only.
+pub const MAX_GAS_PRICE_HISTORY_BLOCKS: usize = dc_db::gas_price_history::GAS_PRICE_HISTORY_LEN;
+/// The default margin of the L1 gas price suggested by `deoxys_getGasPriceHistory`, in percent of the p90.
+pub const DEFAULT_SUGGESTED_GAS_PRICE_PERCENT: u64 = 150;
+
+/// Whether [`SPEC_VERSION`] is `major.minor` or a later version, the optional fields added by a version of the
+/// specification being emitted from that version on.
+const fn spec_version_at_least(major: u64, minor: u64) -> bool {
+    let version = SPEC_VERSION.as_bytes();
+    let (mut parts, mut part, mut i) = ([0u64; 2], 0, 0);
+    while i < version.len() && part < parts.len() {
+        match version[i] {
+            b'.' => part += 1,
+            digit => parts[part] = parts[part] * 10 + (digit - b'0') as u64,
+        }
+        i += 1;
+    }
+    parts[0] > major || (parts[0] == major && parts[1] >= minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_version_gates() {
+        assert!(spec_version_at_least(0, 6));
+        assert!(spec_version_at_least(0, 7));
+        assert!(!spec_version_at_least(0, 8));
+        assert!(!spec_version_at_least(1, 0));
+        assert!(TRACE_STATE_DIFFS);
+    }
+}
@@ -1,8 +1,12 @@
 use dc_db::DeoxysStorageError;
+use dc_exec::{CallStackEntry, ContractErrorData};
+use dp_transactions::BroadcastedToBlockifierError;
 use serde_json::json;
 use starknet_api::StarknetApiError;
 use starknet_core::types::StarknetError;
 
+use crate::utils::internal_server_error;
+
 pub type StarknetRpcResult<T> = Result<T, StarknetRpcApiError>;
 
 pub enum StarknetTransactionExecutionError {
@@ -44,9 +48,9 @@ pub enum StarknetRpcApiError {
     #[error("Failed to fetch pending transactions")]
     FailedToFetchPendingTransactions,
     #[error("Contract error")]
-    ContractError,
+    ContractError { data: ContractErrorData },
     #[error("Transaction execution error")]
-    TxnExecutionError { tx_index: usize, error: String },
+    TxnExecutionError { tx_index: usize, error: String, call_stack: Vec<CallStackEntry> },
     #[error("Invalid contract class")]
     InvalidContractClass,
     #[error("Class already declared")]
@@ -77,10 +81,16 @@ pub enum StarknetRpcApiError {
     ErrUnexpectedError { data: String },
     #[error("Internal server error")]
     InternalServerError,
+    #[error("The node is busy, the request can be retried")]
+    ServerBusy,
     #[error("Unimplemented method")]
     UnimplementedMethod,
     #[error("Too many storage keys requested")]
     ProofLimitExceeded,
+    #[error("Transactions of this Starknet version can only be traced by the sequencer fallback, which is disabled")]
+    UnsupportedTraceVersion { protocol_version: String },
+    #[error("The compilation of the class took too long")]
+    CompilationTimeout,
 }
 
 impl From<&StarknetRpcApiError> for i32 {
@@ -99,7 +109,7 @@ impl From<&StarknetRpcApiError> for i32 {
             StarknetRpcApiError::InvalidContinuationToken => 33,
             StarknetRpcApiError::TooManyKeysInFilter => 34,
             StarknetRpcApiError::FailedToFetchPendingTransactions => 38,
-            StarknetRpcApiError::ContractError => 40,
+            StarknetRpcApiError::ContractError { .. } => 40,
             StarknetRpcApiError::TxnExecutionError { .. } => 41,
             StarknetRpcApiError::InvalidContractClass => 50,
             StarknetRpcApiError::ClassAlreadyDeclared => 51,
@@ -116,8 +126,11 @@ impl From<&StarknetRpcApiError> for i32 {
             StarknetRpcApiError::UnsupportedContractClassVersion => 62,
             StarknetRpcApiError::ErrUnexpectedError { .. } => 63,
             StarknetRpcApiError::InternalServerError => 500,
+            StarknetRpcApiError::ServerBusy => 503,
             StarknetRpcApiError::UnimplementedMethod => 501,
             StarknetRpcApiError::ProofLimitExceeded => 10000,
+            StarknetRpcApiError::UnsupportedTraceVersion { .. } => 10001,
+            StarknetRpcApiError::CompilationTimeout => 10002,
         }
     }
 }
@@ -126,18 +139,69 @@ impl StarknetRpcApiError {
     pub fn data(&self) -> Option<serde_json::Value> {
         match self {
             StarknetRpcApiError::ErrUnexpectedError { data } => Some(json!(data)),
-            StarknetRpcApiError::TxnExecutionError { tx_index, error } => Some(json!({
+            StarknetRpcApiError::ContractError { data } => Some(json!({
+                "revert_error": data.revert_error,
+                "call_stack": call_stack_json(&data.call_stack),
+            })),
+            StarknetRpcApiError::TxnExecutionError { tx_index, error, call_stack } => Some(json!({
                 "transaction_index": tx_index,
                 "execution_error": error,
+                "call_stack": call_stack_json(call_stack),
+            })),
+            StarknetRpcApiError::UnsupportedTraceVersion { protocol_version } => Some(json!({
+                "starknet_version": protocol_version,
             })),
             _ => None,
         }
     }
 }
 
+/// The frames removed from a deep call stack are replaced with `"..."`.
+fn call_stack_json(call_stack: &[CallStackEntry]) -> serde_json::Value {
+    call_stack
+        .iter()
+        .map(|entry| match entry {
+            CallStackEntry::Call(frame) => json!({
+                "contract_address": frame.contract_address,
+                "class_hash": frame.class_hash,
+                "selector": frame.selector,
+            }),
+            CallStackEntry::Truncated => json!("..."),
+        })
+        .collect()
+}
+
+impl StarknetRpcApiError {
+    /// The error of a transaction of a request that could not be converted to blockifier, at `tx_index` in the request.
+    pub fn from_broadcasted_conversion(tx_index: usize, err: BroadcastedToBlockifierError) -> Self {
+        match err {
+            BroadcastedToBlockifierError::CompiledClassHashMismatch { .. } => Self::CompiledClassHashMismatch,
+            BroadcastedToBlockifierError::CompilationFailed(_) => Self::CompilationFailed,
+            BroadcastedToBlockifierError::QueryTransactionSubmitted => Self::UnsupportedTxnVersion,
+            BroadcastedToBlockifierError::ZeroMaxFee | BroadcastedToBlockifierError::ZeroResourceBounds => {
+                Self::InsufficientMaxFee
+            }
+            err => Self::TxnExecutionError { tx_index, error: format!("{err:#}"), call_stack: vec![] },
+        }
+    }
+}
+
+/// Calls fail with `CONTRACT_ERROR`, transactions with `TRANSACTION_EXECUTION_ERROR`. Both come with the call stack of
+/// the failure. The database errors met during the execution are internal errors, as they are not the contract's.
 impl From<dc_exec::Error> for StarknetRpcApiError {
     fn from(err: dc_exec::Error) -> Self {
-        Self::TxnExecutionError { tx_index: 0, error: format!("{:#}", err) }
+        if err.is_storage_error() {
+            return internal_server_error("Executing", err.into());
+        }
+        let data = err.contract_error_data();
+        match (err, data) {
+            (dc_exec::Error::CallContract(_), Some(data)) => Self::ContractError { data },
+            (err, data) => Self::TxnExecutionError {
+                tx_index: err.tx_index().unwrap_or(0),
+                error: format!("{:#}", err),
+                call_stack: data.map(|data| data.call_stack).unwrap_or_default(),
+            },
+        }
     }
 }
 
@@ -148,7 +212,9 @@ impl From<StarknetTransactionExecutionError> for StarknetRpcApiError {
             StarknetTransactionExecutionError::ClassAlreadyDeclared => StarknetRpcApiError::ClassAlreadyDeclared,
             StarknetTransactionExecutionError::ClassHashNotFound => StarknetRpcApiError::ClassHashNotFound,
             StarknetTransactionExecutionError::InvalidContractClass => StarknetRpcApiError::InvalidContractClass,
-            StarknetTransactionExecutionError::ContractError => StarknetRpcApiError::ContractError,
+            StarknetTransactionExecutionError::ContractError => {
+                StarknetRpcApiError::ContractError { data: ContractErrorData::new("Contract error") }
+            }
         }
     }
 }
@@ -172,7 +238,9 @@ impl From<StarknetError> for StarknetRpcApiError {
             StarknetError::NoBlocks => StarknetRpcApiError::NoBlocks,
             StarknetError::InvalidContinuationToken => StarknetRpcApiError::InvalidContinuationToken,
             StarknetError::TooManyKeysInFilter => StarknetRpcApiError::TooManyKeysInFilter,
-            StarknetError::ContractError(_) => StarknetRpcApiError::ContractError,
+            StarknetError::ContractError(data) => {
+                StarknetRpcApiError::ContractError { data: ContractErrorData::new(data.revert_error) }
+            }
             StarknetError::ClassAlreadyDeclared => StarknetRpcApiError::ClassAlreadyDeclared,
             StarknetError::InvalidTransactionNonce => StarknetRpcApiError::InvalidTxnNonce,
             StarknetError::InsufficientMaxFee => StarknetRpcApiError::InsufficientMaxFee,
@@ -190,13 +258,17 @@ impl From<StarknetError> for StarknetRpcApiError {
             StarknetError::TransactionExecutionError(error) => StarknetRpcApiError::TxnExecutionError {
                 tx_index: error.transaction_index as usize,
                 error: error.execution_error.to_string(),
+                call_stack: vec![],
             },
         }
     }
 }
 
 impl From<DeoxysStorageError> for StarknetRpcApiError {
-    fn from(_: DeoxysStorageError) -> Self {
+    fn from(err: DeoxysStorageError) -> Self {
+        if err.is_transient() {
+            return StarknetRpcApiError::ServerBusy;
+        }
         StarknetRpcApiError::ErrUnexpectedError { data: "DB error".to_string() }
     }
 }
@@ -206,3 +278,42 @@ impl From<StarknetApiError> for StarknetRpcApiError {
         StarknetRpcApiError::ErrUnexpectedError { data: err.to_string() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dc_exec::CallFrame;
+    use starknet_core::types::Felt;
+
+    use super::*;
+
+    #[test]
+    fn contract_error_data() {
+        let frame = |n: u64| CallFrame {
+            contract_address: Felt::from(0x10 + n),
+            class_hash: Felt::from(0x20 + n),
+            selector: (n > 0).then(|| Felt::from(0x30 + n)),
+        };
+        let err = StarknetRpcApiError::ContractError {
+            data: ContractErrorData {
+                revert_error: "Execution failed. Failure reason: 0x6661696c6564 ('failed').".into(),
+                call_stack: vec![
+                    CallStackEntry::Call(frame(0)),
+                    CallStackEntry::Truncated,
+                    CallStackEntry::Call(frame(1)),
+                ],
+            },
+        };
+        assert_eq!(i32::from(&err), 40);
+        assert_eq!(
+            err.data(),
+            Some(json!({
+                "revert_error": "Execution failed. Failure reason: 0x6661696c6564 ('failed').",
+                "call_stack": [
+                    { "contract_address": "0x10", "class_hash": "0x20", "selector": null },
+                    "...",
+                    { "contract_address": "0x11", "class_hash": "0x21", "selector": "0x31" },
+                ],
+            }))
+        );
+    }
+}
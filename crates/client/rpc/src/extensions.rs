@@ -0,0 +1,90 @@
+//! Custom JSON-RPC methods served alongside the built-in ones, for app-chains that need their own endpoints without
+//! forking this crate.
+//!
+//! An extension is a jsonrpsee [`RpcModule`] whose methods live in their own namespace, such as `mygame_getScores`.
+//! Its context usually holds an [`RpcExtensionContext`] to read the chain state.
+//!
+//! # Thread safety
+//!
+//! The methods of an extension are called concurrently from the tokio worker threads of the RPC server, so the
+//! context must be `Send + Sync`. Methods registered with `register_method` run on the worker thread itself: they
+//! must not block for long, and should use `register_blocking_method` for heavy database reads. The backend handles
+//! its own synchronization, extensions only have to synchronize their own state.
+
+use std::sync::Arc;
+
+use dc_db::DeoxysBackend;
+use jsonrpsee::{Methods, RpcModule};
+
+use crate::ChainHandle;
+
+/// Namespaces of the methods served by the node itself.
+pub const RESERVED_NAMESPACES: &[&str] = &["starknet", "deoxys", "rpc"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcExtensionError {
+    #[error("Method `{0}` is in the reserved `{1}` namespace")]
+    ReservedNamespace(&'static str, &'static str),
+    #[error("Method `{0}` has no namespace, expected a name like `namespace_method`")]
+    MissingNamespace(&'static str),
+    #[error("Method `{0}` is already registered")]
+    MethodAlreadyRegistered(&'static str),
+    #[error("Registering the methods: {0}")]
+    Register(String),
+}
+
+/// What an extension can read from the node.
+#[derive(Clone)]
+pub struct RpcExtensionContext {
+    pub backend: Arc<DeoxysBackend>,
+    pub chain_handle: ChainHandle,
+}
+
+impl RpcExtensionContext {
+    pub fn new(backend: &Arc<DeoxysBackend>) -> Self {
+        Self { backend: Arc::clone(backend), chain_handle: ChainHandle::from_backend(backend) }
+    }
+}
+
+/// The extensions to serve. Name collisions are detected when a module is added.
+#[derive(Clone, Default)]
+pub struct RpcExtensions {
+    methods: Methods,
+}
+
+impl RpcExtensions {
+    /// Adds the methods of `module`. Every method must be in a namespace of its own: not a [reserved
+    /// one](RESERVED_NAMESPACES), and not used by another method already added.
+    pub fn with_extra_module<Context: Send + Sync + 'static>(
+        mut self,
+        module: RpcModule<Context>,
+    ) -> Result<Self, RpcExtensionError> {
+        for name in module.method_names() {
+            let (namespace, _) = name.split_once('_').ok_or(RpcExtensionError::MissingNamespace(name))?;
+            if let Some(reserved) = RESERVED_NAMESPACES.iter().copied().find(|reserved| *reserved == namespace) {
+                return Err(RpcExtensionError::ReservedNamespace(name, reserved));
+            }
+            if self.methods.method(name).is_some() {
+                return Err(RpcExtensionError::MethodAlreadyRegistered(name));
+            }
+        }
+        self.methods.merge(module).map_err(|err| RpcExtensionError::Register(err.to_string()))?;
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.methods.method_names().next().is_none()
+    }
+
+    pub fn method_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.methods.method_names()
+    }
+
+    /// Adds the extensions to the modules of the node.
+    pub fn merge_into(self, rpc_api: &mut RpcModule<()>) -> Result<(), RpcExtensionError> {
+        if let Some(name) = self.methods.method_names().find(|name| rpc_api.method(name).is_some()) {
+            return Err(RpcExtensionError::MethodAlreadyRegistered(name));
+        }
+        rpc_api.merge(self.methods).map_err(|err| RpcExtensionError::Register(err.to_string()))
+    }
+}
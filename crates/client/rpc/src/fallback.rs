@@ -0,0 +1,309 @@
+//! Traces of the transactions the node cannot re-execute, asked to the sequencer feeder gateway.
+//!
+//! The transactions of the blocks older than [`FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW`] cannot be re-executed with
+//! the versioned constants we ship. `starknet_traceTransaction` forwards them to the feeder gateway instead, within
+//! the limits of the [`FallbackPolicy`] of the node: a public node must not let its users spend the gateway budget of
+//! the operator, nor get the node rate limited by the sequencer.
+//!
+//! [`FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW`]: crate::methods::trace::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use dc_metrics::{Counter, MetricsRegistry, PrometheusError, U64};
+use dp_utils::lock::MutexExt;
+use jsonrpsee::core::async_trait;
+use starknet_core::types::{Felt, TransactionTrace};
+use starknet_providers::{Provider, SequencerGatewayProvider, Url};
+use tokio::sync::Semaphore;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::providers::provider_error_to_rpc;
+use crate::utils::ResultExt;
+use crate::ChainConfig;
+
+#[derive(Debug, Clone)]
+pub struct FallbackPolicy {
+    /// When disabled, the node never calls the sequencer and tracing an old transaction returns
+    /// [`StarknetRpcApiError::UnsupportedTraceVersion`].
+    pub enabled: bool,
+    /// Feeder gateway asked for the traces, instead of the one of the network.
+    pub gateway_url: Option<Url>,
+    /// Sent to the gateway in the `X-Throttling-Bypass` header.
+    pub api_key: Option<String>,
+    /// The calls over this limit wait for a running call to finish.
+    pub max_concurrent_requests: usize,
+    /// The calls over this budget are rejected with [`StarknetRpcApiError::ServerBusy`]. The budget is refilled
+    /// continuously, a burst can spend a whole minute of it at once.
+    pub requests_per_minute: u32,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        Self { enabled: true, gateway_url: None, api_key: None, max_concurrent_requests: 4, requests_per_minute: 60 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FallbackMetrics {
+    pub calls: Counter<U64>,
+    pub rejected: Counter<U64>,
+}
+
+impl FallbackMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            calls: registry.register(Counter::new(
+                "deoxys_trace_fallback_calls",
+                "Counter of the transaction traces asked to the sequencer gateway",
+            )?)?,
+            rejected: registry.register(Counter::new(
+                "deoxys_trace_fallback_rejected",
+                "Counter of the transaction traces not asked to the sequencer gateway because the budget was spent",
+            )?)?,
+        })
+    }
+}
+
+/// Source of the traces of the transactions the node cannot re-execute.
+#[async_trait]
+pub trait TraceFallbackProvider: Send + Sync {
+    async fn trace_transaction(&self, transaction_hash: Felt) -> StarknetRpcResult<TransactionTrace>;
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync> TraceFallbackProvider for P {
+    async fn trace_transaction(&self, transaction_hash: Felt) -> StarknetRpcResult<TransactionTrace> {
+        Provider::trace_transaction(self, transaction_hash)
+            .await
+            .map_err(|e| provider_error_to_rpc(e, "Failed to get the transaction trace from the sequencer"))
+    }
+}
+
+/// Refilled continuously up to `capacity` tokens, one call spends one token.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(requests: u32, now: Instant) -> Self {
+        let capacity = f64::from(requests);
+        Self { capacity, tokens: capacity, refill_per_sec: capacity / 60.0, last_refill: now }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Asks the traces to a [`TraceFallbackProvider`], within the concurrency and the budget of a [`FallbackPolicy`].
+pub struct SequencerFallback {
+    provider: Arc<dyn TraceFallbackProvider>,
+    concurrency: Semaphore,
+    budget: Mutex<TokenBucket>,
+    metrics: Option<FallbackMetrics>,
+}
+
+impl SequencerFallback {
+    pub fn new(policy: &FallbackPolicy, provider: Arc<dyn TraceFallbackProvider>) -> Self {
+        Self {
+            provider,
+            concurrency: Semaphore::new(policy.max_concurrent_requests.max(1)),
+            budget: Mutex::new(TokenBucket::per_minute(policy.requests_per_minute, Instant::now())),
+            metrics: None,
+        }
+    }
+
+    /// The fallback to the feeder gateway of the chain, or `None` when the policy disables it.
+    pub fn from_policy(policy: &FallbackPolicy, chain_config: &ChainConfig, chain_id: Felt) -> Option<Self> {
+        if !policy.enabled {
+            return None;
+        }
+        let feeder_gateway = policy.gateway_url.clone().unwrap_or_else(|| chain_config.feeder_gateway.clone());
+        let provider = SequencerGatewayProvider::new(chain_config.gateway.clone(), feeder_gateway, chain_id);
+        let provider = match &policy.api_key {
+            Some(api_key) => provider.with_header("X-Throttling-Bypass".to_string(), api_key.clone()),
+            None => provider,
+        };
+        Some(Self::new(policy, Arc::new(provider)))
+    }
+
+    pub fn with_metrics(self, metrics: FallbackMetrics) -> Self {
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    /// Waits for a slot when too many calls are running, and fails right away when the budget is spent.
+    pub async fn trace_transaction(&self, transaction_hash: Felt) -> StarknetRpcResult<TransactionTrace> {
+        if !self.budget.lock_or_recover().try_take(Instant::now()) {
+            log::debug!("Trace fallback budget spent, not asking the sequencer for {transaction_hash:#x}");
+            if let Some(metrics) = &self.metrics {
+                metrics.rejected.inc();
+            }
+            return Err(StarknetRpcApiError::ServerBusy);
+        }
+
+        let _permit = self.concurrency.acquire().await.or_internal_server_error("Trace fallback semaphore closed")?;
+        if let Some(metrics) = &self.metrics {
+            metrics.calls.inc();
+        }
+        self.provider.trace_transaction(transaction_hash).await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::future::{poll_fn, Future};
+    use std::pin::pin;
+    use std::task::Poll;
+    use std::time::Duration;
+
+    use starknet_core::types::{
+        ComputationResources, DataAvailabilityResources, DataResources, DeclareTransactionTrace, ExecutionResources,
+    };
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    pub(crate) fn declare_trace() -> TransactionTrace {
+        TransactionTrace::Declare(DeclareTransactionTrace {
+            validate_invocation: None,
+            fee_transfer_invocation: None,
+            state_diff: None,
+            execution_resources: ExecutionResources {
+                computation_resources: ComputationResources {
+                    steps: 100,
+                    memory_holes: None,
+                    range_check_builtin_applications: None,
+                    pedersen_builtin_applications: None,
+                    poseidon_builtin_applications: None,
+                    ec_op_builtin_applications: None,
+                    ecdsa_builtin_applications: None,
+                    bitwise_builtin_applications: None,
+                    keccak_builtin_applications: None,
+                    segment_arena_builtin: None,
+                },
+                data_resources: DataResources {
+                    data_availability: DataAvailabilityResources { l1_gas: 0, l1_data_gas: 0 },
+                },
+            },
+        })
+    }
+
+    /// Tells when each call reaches the gateway, and holds it until it is released.
+    struct BlockingProvider {
+        started: mpsc::UnboundedSender<Felt>,
+        release: Semaphore,
+    }
+
+    impl BlockingProvider {
+        fn new() -> (Arc<Self>, mpsc::UnboundedReceiver<Felt>) {
+            let (started, started_rx) = mpsc::unbounded_channel();
+            (Arc::new(Self { started, release: Semaphore::new(0) }), started_rx)
+        }
+    }
+
+    #[async_trait]
+    impl TraceFallbackProvider for BlockingProvider {
+        async fn trace_transaction(&self, transaction_hash: Felt) -> StarknetRpcResult<TransactionTrace> {
+            self.started.send(transaction_hash).unwrap();
+            self.release.acquire().await.unwrap().forget();
+            Ok(declare_trace())
+        }
+    }
+
+    fn policy(max_concurrent_requests: usize, requests_per_minute: u32) -> FallbackPolicy {
+        FallbackPolicy { max_concurrent_requests, requests_per_minute, ..Default::default() }
+    }
+
+    #[test]
+    fn budget_is_refilled_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::per_minute(2, start);
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+
+        // One token every 30 seconds.
+        assert!(!bucket.try_take(start + Duration::from_secs(29)));
+        assert!(bucket.try_take(start + Duration::from_secs(30)));
+        assert!(!bucket.try_take(start + Duration::from_secs(30)));
+
+        // The budget does not grow over its capacity while unused.
+        let later = start + Duration::from_secs(600);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[tokio::test]
+    async fn calls_over_the_budget_are_rejected() {
+        let registry = dc_metrics::MetricsService::new(true, false, 0).unwrap().registry();
+        let metrics = FallbackMetrics::register(&registry).unwrap();
+        let (provider, mut started) = BlockingProvider::new();
+        let fallback = Arc::new(SequencerFallback::new(&policy(4, 2), provider.clone()).with_metrics(metrics.clone()));
+
+        let running: Vec<_> = [Felt::ONE, Felt::TWO]
+            .into_iter()
+            .map(|tx_hash| {
+                let fallback = Arc::clone(&fallback);
+                tokio::spawn(async move { fallback.trace_transaction(tx_hash).await })
+            })
+            .collect();
+
+        // Both calls are within the budget, the third one is rejected without reaching the gateway.
+        let mut reached = [started.recv().await.unwrap(), started.recv().await.unwrap()];
+        reached.sort();
+        assert_eq!(reached, [Felt::ONE, Felt::TWO]);
+        assert!(matches!(fallback.trace_transaction(Felt::THREE).await, Err(StarknetRpcApiError::ServerBusy)));
+        assert!(started.try_recv().is_err());
+
+        provider.release.add_permits(2);
+        for call in running {
+            call.await.unwrap().unwrap();
+        }
+        assert_eq!((metrics.calls.get(), metrics.rejected.get()), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn calls_over_the_concurrency_limit_wait() {
+        let (provider, mut started) = BlockingProvider::new();
+        let fallback = Arc::new(SequencerFallback::new(&policy(1, 60), provider.clone()));
+
+        let first = tokio::spawn({
+            let fallback = Arc::clone(&fallback);
+            async move { fallback.trace_transaction(Felt::ONE).await }
+        });
+        assert_eq!(started.recv().await.unwrap(), Felt::ONE);
+
+        // The second call waits for the first one to finish before reaching the gateway.
+        let mut second = pin!(fallback.trace_transaction(Felt::TWO));
+        assert!(poll_fn(|cx| Poll::Ready(second.as_mut().poll(cx).is_pending())).await);
+        assert!(started.try_recv().is_err());
+
+        provider.release.add_permits(1);
+        first.await.unwrap().unwrap();
+        provider.release.add_permits(1);
+        second.await.unwrap();
+        assert_eq!(started.recv().await.unwrap(), Felt::TWO);
+    }
+
+    #[test]
+    fn disabled_policy_builds_no_fallback() {
+        let url: Url = "http://localhost:1".parse().unwrap();
+        let chain_config = ChainConfig { feeder_gateway: url.clone(), gateway: url };
+        let disabled = FallbackPolicy { enabled: false, ..Default::default() };
+        assert!(SequencerFallback::from_policy(&disabled, &chain_config, Felt::ONE).is_none());
+        assert!(SequencerFallback::from_policy(&FallbackPolicy::default(), &chain_config, Felt::ONE).is_some());
+    }
+}
@@ -2,27 +2,47 @@
 //!
 //! It uses the deoxys client and backend in order to answer queries.
 
+mod chain_handle;
 mod constants;
 mod errors;
 mod methods;
-mod types;
+pub mod types;
 pub mod utils;
 
+pub mod call_cache;
+pub mod extensions;
+pub mod fallback;
+pub mod limits;
 pub mod mempool_provider;
+pub mod propagation;
 pub mod providers;
+#[cfg(test)]
+mod test_utils;
 
 use std::sync::Arc;
 
-use dc_db::db_block_id::DbBlockIdResolvable;
+use call_cache::CallCache;
+pub use chain_handle::ChainHandle;
+pub use constants::DEFAULT_SUGGESTED_GAS_PRICE_PERCENT;
+use dc_db::compaction::CompactionSchedule;
+use dc_db::db_block_id::{DbBlockId, DbBlockIdResolvable};
 use dc_db::DeoxysBackend;
-use dp_block::{DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo};
+use dc_exec::{ExecutionContext, StateReadMetrics};
+use dc_mempool::preview::BlockPreviewHandle;
+use dc_mempool::Mempool;
+use dp_block::header::PendingHeader;
+use dp_block::{BlockN, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, DeoxysPendingBlock};
+use dp_transactions::TransactionWithHash;
+use dp_utils::compute_pool::{spawn_compute, ComputePool};
 use errors::{StarknetRpcApiError, StarknetRpcResult};
+use fallback::SequencerFallback;
 use jsonrpsee::core::RpcResult;
 use jsonrpsee::proc_macros::rpc;
+use limits::RpcLimitsConfig;
 use providers::AddTransactionProvider;
 use starknet_core::types::Felt;
 use starknet_core::types::{
-    BlockHashAndNumber, BlockId, BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction,
+    BlockHashAndNumber, BlockId, BlockTag, BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction,
     BroadcastedInvokeTransaction, BroadcastedTransaction, ContractClass, DeclareTransactionResult,
     DeployAccountTransactionResult, EventFilterWithPage, EventsPage, FeeEstimate, FunctionCall,
     InvokeTransactionResult, MaybePendingBlockWithReceipts, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs,
@@ -30,6 +50,12 @@ use starknet_core::types::{
     SyncStatusType, Transaction, TransactionReceiptWithBlockInfo, TransactionStatus, TransactionTraceWithHash,
 };
 use starknet_providers::Url;
+use types::{
+    BlockVerificationStatus, BlockWithExtensions, ClassDeclarationInfo, ClassesPage, ContractsPage, DeployedContract,
+    GasPriceHistory, LenientFelts, MempoolTransactionsPage, NodeStatus, NonceHistoryPage, PendingBlockPreview,
+    ReceiptWithExtensions, SyncStallReason,
+};
+use utils::block::L1Finality;
 use utils::ResultExt;
 
 // Starknet RPC API trait and types
@@ -45,21 +71,21 @@ pub trait StarknetWriteRpcApi {
     #[method(name = "addInvokeTransaction")]
     async fn add_invoke_transaction(
         &self,
-        invoke_transaction: BroadcastedInvokeTransaction,
+        invoke_transaction: LenientFelts<BroadcastedInvokeTransaction>,
     ) -> RpcResult<InvokeTransactionResult>;
 
     /// Submit a new class declaration transaction
     #[method(name = "addDeployAccountTransaction")]
     async fn add_deploy_account_transaction(
         &self,
-        deploy_account_transaction: BroadcastedDeployAccountTransaction,
+        deploy_account_transaction: LenientFelts<BroadcastedDeployAccountTransaction>,
     ) -> RpcResult<DeployAccountTransactionResult>;
 
     /// Submit a new deploy account transaction
     #[method(name = "addDeclareTransaction")]
     async fn add_declare_transaction(
         &self,
-        declare_transaction: BroadcastedDeclareTransaction,
+        declare_transaction: LenientFelts<BroadcastedDeclareTransaction>,
     ) -> RpcResult<DeclareTransactionResult>;
 }
 
@@ -79,7 +105,7 @@ pub trait StarknetReadRpcApi {
 
     /// Call a contract function at a given block id
     #[method(name = "call")]
-    fn call(&self, request: FunctionCall, block_id: BlockId) -> RpcResult<Vec<Felt>>;
+    async fn call(&self, request: FunctionCall, block_id: BlockId) -> RpcResult<Vec<Felt>>;
 
     /// Get the chain id
     #[method(name = "chainId")]
@@ -93,7 +119,7 @@ pub trait StarknetReadRpcApi {
     #[method(name = "estimateFee")]
     async fn estimate_fee(
         &self,
-        request: Vec<BroadcastedTransaction>,
+        request: LenientFelts<Vec<BroadcastedTransaction>>,
         simulation_flags: Vec<SimulationFlagForEstimateFee>,
         block_id: BlockId,
     ) -> RpcResult<Vec<FeeEstimate>>;
@@ -104,15 +130,21 @@ pub trait StarknetReadRpcApi {
 
     /// Get block information with full transactions and receipts given the block id
     #[method(name = "getBlockWithReceipts")]
-    async fn get_block_with_receipts(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithReceipts>;
+    async fn get_block_with_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithReceipts>>;
 
     /// Get block information with transaction hashes given the block id
     #[method(name = "getBlockWithTxHashes")]
-    fn get_block_with_tx_hashes(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxHashes>;
+    fn get_block_with_tx_hashes(
+        &self,
+        block_id: BlockId,
+    ) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithTxHashes>>;
 
     /// Get block information with full transactions given the block id
     #[method(name = "getBlockWithTxs")]
-    fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxs>;
+    fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithTxs>>;
 
     /// Get the contract class at a given contract address for a given block id
     #[method(name = "getClassAt")]
@@ -149,7 +181,10 @@ pub trait StarknetReadRpcApi {
 
     /// Returns the receipt of a transaction by transaction hash.
     #[method(name = "getTransactionReceipt")]
-    async fn get_transaction_receipt(&self, transaction_hash: Felt) -> RpcResult<TransactionReceiptWithBlockInfo>;
+    async fn get_transaction_receipt(
+        &self,
+        transaction_hash: Felt,
+    ) -> RpcResult<ReceiptWithExtensions<TransactionReceiptWithBlockInfo>>;
 
     /// Gets the Transaction Status, Including Mempool Status and Execution Details
     #[method(name = "getTransactionStatus")]
@@ -171,7 +206,7 @@ pub trait StarknetTraceRpcApi {
     async fn simulate_transactions(
         &self,
         block_id: BlockId,
-        transactions: Vec<BroadcastedTransaction>,
+        transactions: LenientFelts<Vec<BroadcastedTransaction>>,
         simulation_flags: Vec<SimulationFlag>,
     ) -> RpcResult<Vec<SimulatedTransaction>>;
 
@@ -184,9 +219,100 @@ pub trait StarknetTraceRpcApi {
     async fn trace_transaction(&self, transaction_hash: Felt) -> RpcResult<TransactionTraceWithHash>;
 }
 
+/// Deoxys-specific read rpc interface, served along with the Starknet read methods. These methods are not part of
+/// the Starknet specification.
+#[rpc(server, namespace = "deoxys")]
+pub trait DeoxysReadRpcApi {
+    /// Get the class hashes of many contracts in the given block, `null` for the contracts that are not deployed
+    #[method(name = "getClassHashesAt")]
+    fn get_class_hashes_at(&self, block_id: BlockId, contract_addresses: Vec<Felt>) -> RpcResult<Vec<Option<Felt>>>;
+
+    /// Get the reason why the sync stopped importing blocks, or null if it did not stop
+    #[method(name = "getSyncStall")]
+    fn get_sync_stall(&self) -> RpcResult<Option<SyncStallReason>>;
+
+    /// Get what the node verified itself about a block
+    #[method(name = "getBlockVerification")]
+    fn get_block_verification(&self, block_id: BlockId) -> RpcResult<BlockVerificationStatus>;
+
+    /// Get the gas prices of the last `n` blocks, with their percentiles and a suggested maximum L1 gas price
+    #[method(name = "getGasPriceHistory")]
+    fn get_gas_price_history(&self, n: u64) -> RpcResult<GasPriceHistory>;
+
+    /// Get the blocks which changed the nonce of a contract in a range of blocks, with the nonce they set
+    #[method(name = "getNonceHistory")]
+    fn get_nonce_history(
+        &self,
+        contract_address: Felt,
+        from_block: u64,
+        to_block: u64,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+    ) -> RpcResult<NonceHistoryPage>;
+
+    /// Get the block and the transaction which declared a class
+    #[method(name = "getClassDeclarationInfo")]
+    fn get_class_declaration_info(&self, class_hash: Felt) -> RpcResult<ClassDeclarationInfo>;
+
+    /// Get the contracts whose class is the given class, with the block which deployed them
+    #[method(name = "getContractsByClassHash")]
+    fn get_contracts_by_class_hash(
+        &self,
+        class_hash: Felt,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+    ) -> RpcResult<ContractsPage>;
+
+    /// Get the contracts deployed by a block, with their class
+    #[method(name = "getDeployedContracts")]
+    fn get_deployed_contracts(&self, block_id: BlockId) -> RpcResult<Vec<DeployedContract>>;
+}
+
+/// Deoxys-specific rpc interface, meant for node operators. These methods are not part of the Starknet
+/// specification.
+#[rpc(server, namespace = "deoxys")]
+pub trait DeoxysRpcApi {
+    /// Get the transactions currently sitting in the mempool, along with statistics about the mempool
+    #[method(name = "getMempoolTransactions")]
+    fn get_mempool_transactions(&self, offset: Option<u64>, limit: Option<u64>) -> RpcResult<MempoolTransactionsPage>;
+
+    /// Get what the block being produced would look like if it was closed now
+    #[method(name = "previewPendingBlock")]
+    fn preview_pending_block(&self) -> RpcResult<PendingBlockPreview>;
+
+    /// Get the schedule of the database background jobs
+    #[method(name = "getCompactionSchedule")]
+    fn get_compaction_schedule(&self) -> RpcResult<CompactionSchedule>;
+
+    /// Replace the schedule of the database background jobs, it is applied right away
+    #[method(name = "setCompactionSchedule")]
+    fn set_compaction_schedule(&self, schedule: CompactionSchedule) -> RpcResult<()>;
+
+    /// Compact the given database columns, or all of them, in the background. Returns the names of the columns
+    #[method(name = "compactDatabase")]
+    fn compact_database(&self, columns: Option<Vec<String>>) -> RpcResult<Vec<String>>;
+
+    /// List the classes declared in the confirmed blocks, ordered by class hash
+    #[method(name = "listClasses")]
+    fn list_classes(
+        &self,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+        declared_after_block: Option<u64>,
+    ) -> RpcResult<ClassesPage>;
+
+    /// Get the definitions of many classes declared in the confirmed blocks, `null` for the classes that are not
+    /// declared
+    #[method(name = "getClassesBatch")]
+    fn get_classes_batch(&self, class_hashes: Vec<Felt>) -> RpcResult<Vec<Option<ContractClass>>>;
+
+    /// Get an overview of the node: version, chain, sync and L1 progress, and liveness of its workers
+    #[method(name = "status")]
+    fn status(&self) -> RpcResult<NodeStatus>;
+}
+
 #[derive(Clone)]
 pub struct ChainConfig {
-    pub chain_id: starknet_types_core::felt::Felt,
     pub feeder_gateway: Url,
     pub gateway: Url,
 }
@@ -196,16 +322,112 @@ pub struct ChainConfig {
 pub struct Starknet {
     backend: Arc<DeoxysBackend>,
     chain_config: ChainConfig,
+    chain: ChainHandle,
     pub(crate) add_transaction_provider: Arc<dyn AddTransactionProvider>,
+    /// `None` when the node does not run a mempool, for example when it forwards transactions to the gateway.
+    pub(crate) mempool: Option<Arc<Mempool>>,
+    /// `None` when the node does not produce blocks.
+    pub(crate) block_preview: Option<BlockPreviewHandle>,
+    exec_metrics: Option<StateReadMetrics>,
+    /// `None` when the node does not ask the sequencer for the traces it cannot re-execute.
+    pub(crate) trace_fallback: Option<Arc<SequencerFallback>>,
+    /// `None` when the results of `starknet_call` are not cached.
+    pub(crate) call_cache: Option<Arc<CallCache>>,
+    /// Reported by `deoxys_status`.
+    pub(crate) node_version: Option<String>,
+    /// Margin of the L1 gas price suggested by `deoxys_getGasPriceHistory`, in percent of the p90.
+    pub(crate) suggested_gas_price_percent: u64,
+    pub(crate) limits: RpcLimitsConfig,
+    /// `None` when the transactions are executed on the tasks of the RPC server.
+    compute_pool: Option<ComputePool>,
 }
 
 impl Starknet {
     pub fn new(
         backend: Arc<DeoxysBackend>,
         chain_config: ChainConfig,
+        chain: ChainHandle,
         add_transaction_provider: Arc<dyn AddTransactionProvider>,
+        mempool: Option<Arc<Mempool>>,
+        block_preview: Option<BlockPreviewHandle>,
     ) -> Self {
-        Self { backend, add_transaction_provider, chain_config }
+        Self {
+            backend,
+            add_transaction_provider,
+            chain_config,
+            chain,
+            mempool,
+            block_preview,
+            exec_metrics: None,
+            trace_fallback: None,
+            call_cache: None,
+            node_version: None,
+            suggested_gas_price_percent: constants::DEFAULT_SUGGESTED_GAS_PRICE_PERCENT,
+            limits: Default::default(),
+            compute_pool: None,
+        }
+    }
+
+    /// Exports the state reads of the executions as metrics.
+    pub fn with_exec_metrics(self, metrics: StateReadMetrics) -> Self {
+        Self { exec_metrics: Some(metrics), ..self }
+    }
+
+    /// Asks the sequencer for the traces of the transactions that are too old to be re-executed.
+    pub fn with_trace_fallback(self, fallback: SequencerFallback) -> Self {
+        Self { trace_fallback: Some(Arc::new(fallback)), ..self }
+    }
+
+    /// Caches the results of `starknet_call` on closed blocks.
+    pub fn with_call_cache(self, cache: CallCache) -> Self {
+        Self { call_cache: Some(Arc::new(cache)), ..self }
+    }
+
+    /// The version of the node reported by `deoxys_status`.
+    pub fn with_node_version(self, version: String) -> Self {
+        Self { node_version: Some(version), ..self }
+    }
+
+    /// The maximum L1 gas price suggested by `deoxys_getGasPriceHistory` is the p90 of the latest blocks times
+    /// `percent` / 100.
+    pub fn with_suggested_gas_price_percent(self, percent: u64) -> Self {
+        Self { suggested_gas_price_percent: percent, ..self }
+    }
+
+    /// Declare transactions whose class is larger than `limits` are rejected before they are handed to the
+    /// transaction provider.
+    pub fn with_limits(self, limits: RpcLimitsConfig) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Executes the calls and the transactions of the trace, simulation and fee estimation methods on `pool`, away from
+    /// the block conversion and hashing of the sync.
+    pub fn with_compute_pool(self, pool: ComputePool) -> Self {
+        Self { compute_pool: Some(pool), ..self }
+    }
+
+    /// Runs the execution `func` on the compute pool of the RPC, or in place when there is none.
+    pub(crate) async fn run_execution<F, R>(&self, func: F) -> R
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        match &self.compute_pool {
+            Some(pool) => spawn_compute(pool, func).await,
+            None => func(),
+        }
+    }
+
+    /// The context to execute transactions and calls on top of the state of a block.
+    pub(crate) fn execution_context(
+        &self,
+        block_info: &DeoxysMaybePendingBlockInfo,
+    ) -> Result<ExecutionContext, dc_exec::Error> {
+        let exec_context = ExecutionContext::new(Arc::clone(&self.backend), block_info)?;
+        Ok(match &self.exec_metrics {
+            Some(metrics) => exec_context.with_metrics(metrics.clone()),
+            None => exec_context,
+        })
     }
 
     pub fn clone_backend(&self) -> Arc<DeoxysBackend> {
@@ -222,7 +444,7 @@ impl Starknet {
             .ok_or(StarknetRpcApiError::BlockNotFound)
     }
 
-    pub fn get_block_n(&self, block_id: &impl DbBlockIdResolvable) -> StarknetRpcResult<u64> {
+    pub fn get_block_n(&self, block_id: &impl DbBlockIdResolvable) -> StarknetRpcResult<BlockN> {
         self.backend
             .get_block_n(block_id)
             .or_internal_server_error("Error getting block from storage")?
@@ -236,23 +458,82 @@ impl Starknet {
             .ok_or(StarknetRpcApiError::BlockNotFound)
     }
 
+    /// Like [`Starknet::get_block`], but the pending tag always resolves to a pending block: when the node has no
+    /// pending block, this is an empty pending block on top of the latest block.
+    pub fn get_block_or_empty_pending(&self, block_id: &BlockId) -> StarknetRpcResult<DeoxysMaybePendingBlock> {
+        if !matches!(block_id, BlockId::Tag(BlockTag::Pending)) {
+            return self.get_block(block_id);
+        }
+        match self.backend.get_block(&DbBlockId::Pending).or_internal_server_error("Error getting pending block")? {
+            Some(block) => Ok(block),
+            None => Ok(self.empty_pending_block()?.into()),
+        }
+    }
+
+    /// Like [`Starknet::get_block_info`], with the pending tag resolved as in [`Starknet::get_block_or_empty_pending`].
+    pub fn get_block_info_or_empty_pending(
+        &self,
+        block_id: &BlockId,
+    ) -> StarknetRpcResult<DeoxysMaybePendingBlockInfo> {
+        if !matches!(block_id, BlockId::Tag(BlockTag::Pending)) {
+            return self.get_block_info(block_id);
+        }
+        match self
+            .backend
+            .get_block_info(&DbBlockId::Pending)
+            .or_internal_server_error("Error getting pending block")?
+        {
+            Some(info) => Ok(info),
+            None => Ok(self.empty_pending_block()?.info.into()),
+        }
+    }
+
+    fn empty_pending_block(&self) -> StarknetRpcResult<DeoxysPendingBlock> {
+        let DeoxysMaybePendingBlockInfo::NotPending(latest) = self.get_block_info(&BlockId::Tag(BlockTag::Latest))?
+        else {
+            return Err(StarknetRpcApiError::InternalServerError);
+        };
+        Ok(DeoxysPendingBlock::new_empty(PendingHeader {
+            parent_block_hash: latest.block_hash,
+            sequencer_address: latest.header.sequencer_address,
+            block_timestamp: latest.header.block_timestamp,
+            protocol_version: latest.header.protocol_version,
+            l1_gas_price: latest.header.l1_gas_price,
+            l1_da_mode: latest.header.l1_da_mode,
+        }))
+    }
+
+    /// The transaction with this hash, if it is waiting in the mempool of the node.
+    pub fn get_mempool_transaction(&self, transaction_hash: Felt) -> Option<TransactionWithHash> {
+        self.mempool.as_ref()?.get_transaction(transaction_hash)
+    }
+
     pub fn chain_id(&self) -> Felt {
-        self.chain_config.chain_id
+        self.chain.chain_id()
     }
 
+    pub fn chain(&self) -> &ChainHandle {
+        &self.chain
+    }
+
+    /// The tip stored in the database, which is updated along with the block: `NO_BLOCKS` before the genesis block.
     pub fn current_block_number(&self) -> StarknetRpcResult<u64> {
-        self.get_block_n(&dp_block::BlockId::Tag(dp_block::BlockTag::Latest))
+        self.backend
+            .get_latest_block_n()
+            .or_internal_server_error("Error getting the latest block number")?
+            .ok_or(StarknetRpcApiError::NoBlocks)
     }
 
     pub fn current_spec_version(&self) -> String {
-        "0.7.1".to_string()
+        constants::SPEC_VERSION.to_string()
     }
 
-    pub fn get_l1_last_confirmed_block(&self) -> StarknetRpcResult<u64> {
-        Ok(self
+    /// The L1 finality of the blocks, from the last block number confirmed on L1 stored by the L1 sync.
+    pub(crate) fn l1_finality(&self) -> StarknetRpcResult<L1Finality> {
+        let last_confirmed_block = self
             .backend
             .get_l1_last_confirmed_block()
-            .or_internal_server_error("Error getting L1 last confirmed block")?
-            .unwrap_or_default())
+            .or_internal_server_error("Error getting L1 last confirmed block")?;
+        Ok(L1Finality::new(last_confirmed_block))
     }
 }
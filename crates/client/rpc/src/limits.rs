@@ -0,0 +1,189 @@
+//! Limits on the classes declared through the RPC.
+//!
+//! A declared class is compiled to CASM before its transaction is added to the mempool, and stored along with its CASM
+//! once it is included in a block. The size of the class is checked before any of this happens, the compilation is
+//! given a deadline, and the size of the CASM is checked once it is compiled. Only a few classes are compiled at the
+//! same time, the other declare transactions wait for their turn within their deadline.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dp_class::ConvertedClass;
+use dp_utils::compute_pool::{spawn_compute, ComputePool};
+use starknet_core::types::BroadcastedDeclareTransaction;
+use tokio::sync::Semaphore;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+
+/// The default maximum length of the Sierra program of a declared class, in felts.
+pub const DEFAULT_MAX_SIERRA_PROGRAM_LENGTH: usize = 200_000;
+/// The default maximum length of the ABI of a declared Sierra class, in KiB.
+pub const DEFAULT_MAX_ABI_LENGTH_KIB: usize = 512;
+/// The default maximum size of the compressed program of a declared legacy class, in KiB.
+pub const DEFAULT_MAX_LEGACY_PROGRAM_SIZE_KIB: usize = 4 * 1024;
+/// The default maximum size of a declared class once compiled, in KiB.
+pub const DEFAULT_MAX_COMPILED_CLASS_SIZE_KIB: usize = 8 * 1024;
+/// The default time given to the compilation of a declared class, in seconds.
+pub const DEFAULT_COMPILATION_TIMEOUT_SECS: u64 = 30;
+/// The default number of declared classes compiled at the same time.
+pub const DEFAULT_MAX_CONCURRENT_COMPILATIONS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcLimitsConfig {
+    /// Length of the Sierra program of a declared class, in felts.
+    pub max_sierra_program_length: usize,
+    /// Length of the ABI of a declared Sierra class, in bytes.
+    pub max_abi_length: usize,
+    /// Size of the compressed program of a declared legacy class, in bytes.
+    pub max_legacy_program_size: usize,
+    /// Size of a declared class once compiled, in bytes.
+    pub max_compiled_class_size: usize,
+    /// Time given to the compilation of a declared class, including the wait for a compilation slot.
+    pub compilation_timeout: Duration,
+    /// Number of declared classes compiled at the same time.
+    pub max_concurrent_compilations: usize,
+}
+
+impl Default for RpcLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_sierra_program_length: DEFAULT_MAX_SIERRA_PROGRAM_LENGTH,
+            max_abi_length: DEFAULT_MAX_ABI_LENGTH_KIB * 1024,
+            max_legacy_program_size: DEFAULT_MAX_LEGACY_PROGRAM_SIZE_KIB * 1024,
+            max_compiled_class_size: DEFAULT_MAX_COMPILED_CLASS_SIZE_KIB * 1024,
+            compilation_timeout: Duration::from_secs(DEFAULT_COMPILATION_TIMEOUT_SECS),
+            max_concurrent_compilations: DEFAULT_MAX_CONCURRENT_COMPILATIONS,
+        }
+    }
+}
+
+impl RpcLimitsConfig {
+    /// Checks the class of a declare transaction before it is compiled. The program of a legacy class is checked while
+    /// it is still compressed, before it is decompressed for the compilation.
+    pub fn check_declared_class(&self, tx: &BroadcastedDeclareTransaction) -> StarknetRpcResult<()> {
+        let class = match tx {
+            BroadcastedDeclareTransaction::V1(tx) => {
+                if tx.contract_class.program.len() > self.max_legacy_program_size {
+                    return Err(StarknetRpcApiError::ContractClassSizeTooLarge);
+                }
+                return Ok(());
+            }
+            BroadcastedDeclareTransaction::V2(tx) => &tx.contract_class,
+            BroadcastedDeclareTransaction::V3(tx) => &tx.contract_class,
+        };
+        if class.sierra_program.len() > self.max_sierra_program_length || class.abi.len() > self.max_abi_length {
+            return Err(StarknetRpcApiError::ContractClassSizeTooLarge);
+        }
+        Ok(())
+    }
+
+    pub fn check_compiled_class(&self, class: &ConvertedClass) -> StarknetRpcResult<()> {
+        if class.class_compiled.1.len() > self.max_compiled_class_size {
+            return Err(StarknetRpcApiError::ContractClassSizeTooLarge);
+        }
+        Ok(())
+    }
+
+    /// The compilation slots shared by the declare transactions, see [`Self::compile_with_deadline`].
+    pub fn compilation_slots(&self) -> Arc<Semaphore> {
+        Arc::new(Semaphore::new(self.max_concurrent_compilations.max(1)))
+    }
+
+    /// Runs `compile` on `pool`, or on the global rayon thread pool when there is none, once it gets one of the
+    /// compilation `slots`, and gives up on it after the compilation timeout. The compilation cannot be interrupted:
+    /// it keeps its thread and its slot until it is done, but its result is dropped. The abandoned compilations count
+    /// against the slots, so that they cannot pile up on the thread pool.
+    pub async fn compile_with_deadline<T: Send + 'static>(
+        &self,
+        slots: &Arc<Semaphore>,
+        pool: Option<&ComputePool>,
+        compile: impl FnOnce() -> T + Send + 'static,
+    ) -> StarknetRpcResult<T> {
+        let compilation = async {
+            let slot = Arc::clone(slots).acquire_owned().await.expect("The compilation slots are never closed");
+            let compile = move || {
+                let _slot = slot;
+                compile()
+            };
+            match pool {
+                Some(pool) => spawn_compute(pool, compile).await,
+                None => dp_utils::spawn_rayon_task(compile).await,
+            }
+        };
+        tokio::time::timeout(self.compilation_timeout, compilation)
+            .await
+            .map_err(|_| StarknetRpcApiError::CompilationTimeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_core::types::{
+        BroadcastedDeclareTransactionV1, BroadcastedDeclareTransactionV2, CompressedLegacyContractClass,
+        EntryPointsByType, FlattenedSierraClass, LegacyEntryPointsByType,
+    };
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+
+    fn declare_transaction(sierra_program_length: usize, abi_length: usize) -> BroadcastedDeclareTransaction {
+        BroadcastedDeclareTransaction::V2(BroadcastedDeclareTransactionV2 {
+            sender_address: Felt::from(0x1234),
+            compiled_class_hash: Felt::ONE,
+            max_fee: Felt::ONE,
+            signature: vec![],
+            nonce: Felt::ZERO,
+            contract_class: Arc::new(FlattenedSierraClass {
+                sierra_program: vec![Felt::ONE; sierra_program_length],
+                contract_class_version: "0.1.0".into(),
+                entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+                abi: "a".repeat(abi_length),
+            }),
+            is_query: false,
+        })
+    }
+
+    fn legacy_declare_transaction(program_size: usize) -> BroadcastedDeclareTransaction {
+        BroadcastedDeclareTransaction::V1(BroadcastedDeclareTransactionV1 {
+            sender_address: Felt::from(0x1234),
+            max_fee: Felt::ONE,
+            signature: vec![],
+            nonce: Felt::ZERO,
+            // Not a compressed program: it is never decompressed when it is too large.
+            contract_class: Arc::new(CompressedLegacyContractClass {
+                program: vec![0; program_size],
+                entry_points_by_type: LegacyEntryPointsByType {
+                    constructor: vec![],
+                    external: vec![],
+                    l1_handler: vec![],
+                },
+                abi: None,
+            }),
+            is_query: false,
+        })
+    }
+
+    #[test]
+    fn test_check_declared_class() {
+        let limits = RpcLimitsConfig { max_sierra_program_length: 100, max_abi_length: 10, ..Default::default() };
+        assert!(limits.check_declared_class(&declare_transaction(100, 10)).is_ok());
+        assert!(matches!(
+            limits.check_declared_class(&declare_transaction(101, 10)),
+            Err(StarknetRpcApiError::ContractClassSizeTooLarge)
+        ));
+        assert!(matches!(
+            limits.check_declared_class(&declare_transaction(100, 11)),
+            Err(StarknetRpcApiError::ContractClassSizeTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_check_declared_legacy_class() {
+        let limits = RpcLimitsConfig { max_legacy_program_size: 100, ..Default::default() };
+        assert!(limits.check_declared_class(&legacy_declare_transaction(100)).is_ok());
+        assert!(matches!(
+            limits.check_declared_class(&legacy_declare_transaction(101)),
+            Err(StarknetRpcApiError::ContractClassSizeTooLarge)
+        ));
+    }
+}
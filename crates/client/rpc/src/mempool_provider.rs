@@ -1,26 +1,48 @@
 use std::sync::Arc;
 
 use super::providers::AddTransactionProvider;
-use crate::{bail_internal_server_error, errors::StarknetRpcApiError};
+use crate::limits::RpcLimitsConfig;
+use crate::{bail_internal_server_error, errors::StarknetRpcApiError, ChainHandle};
+use blockifier::blockifier::stateful_validator::StatefulValidatorError;
 use blockifier::transaction::account_transaction::AccountTransaction;
 use blockifier::transaction::transaction_execution::Transaction;
+use dc_exec::ContractErrorData;
 use dc_mempool::Mempool;
 use dp_class::ConvertedClass;
-use dp_transactions::broadcasted_to_blockifier;
+use dp_transactions::{broadcasted_to_blockifier, validate_submitted_transaction};
+use dp_utils::compute_pool::ComputePool;
 use jsonrpsee::core::{async_trait, RpcResult};
 use starknet_core::types::{
     BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction, BroadcastedInvokeTransaction,
     BroadcastedTransaction, DeclareTransactionResult, DeployAccountTransactionResult, Felt, InvokeTransactionResult,
 };
+use tokio::sync::Semaphore;
 
 /// This [`AddTransactionProvider`] adds the received transactions to a mempool.
 pub struct MempoolProvider {
     mempool: Arc<Mempool>,
+    chain: ChainHandle,
+    limits: RpcLimitsConfig,
+    compilation_slots: Arc<Semaphore>,
+    compute_pool: Option<ComputePool>,
 }
 
 impl MempoolProvider {
-    pub fn new(mempool: Arc<Mempool>) -> Self {
-        Self { mempool }
+    pub fn new(mempool: Arc<Mempool>, chain: ChainHandle) -> Self {
+        let limits = RpcLimitsConfig::default();
+        let compilation_slots = limits.compilation_slots();
+        Self { mempool, chain, limits, compilation_slots, compute_pool: None }
+    }
+
+    /// Limits on the classes compiled for the declare transactions.
+    pub fn with_limits(self, limits: RpcLimitsConfig) -> Self {
+        let compilation_slots = limits.compilation_slots();
+        Self { limits, compilation_slots, ..self }
+    }
+
+    /// Compiles the classes of the declare transactions on `pool`, away from the sync.
+    pub fn with_compute_pool(self, pool: ComputePool) -> Self {
+        Self { compute_pool: Some(pool), ..self }
     }
 }
 
@@ -30,19 +52,27 @@ impl AddTransactionProvider for MempoolProvider {
         &self,
         declare_transaction: BroadcastedDeclareTransaction,
     ) -> RpcResult<DeclareTransactionResult> {
-        Ok(add_declare_transaction(&self.mempool, declare_transaction)?)
+        Ok(add_declare_transaction(
+            &self.mempool,
+            &self.limits,
+            &self.compilation_slots,
+            self.compute_pool.as_ref(),
+            self.chain.chain_id(),
+            declare_transaction,
+        )
+        .await?)
     }
     async fn add_deploy_account_transaction(
         &self,
         deploy_account_transaction: BroadcastedDeployAccountTransaction,
     ) -> RpcResult<DeployAccountTransactionResult> {
-        Ok(add_deploy_account_transaction(&self.mempool, deploy_account_transaction)?)
+        Ok(add_deploy_account_transaction(&self.mempool, self.chain.chain_id(), deploy_account_transaction)?)
     }
     async fn add_invoke_transaction(
         &self,
         invoke_transaction: BroadcastedInvokeTransaction,
     ) -> RpcResult<InvokeTransactionResult> {
-        Ok(add_invoke_transaction(&self.mempool, invoke_transaction)?)
+        Ok(add_invoke_transaction(&self.mempool, self.chain.chain_id(), invoke_transaction)?)
     }
 }
 
@@ -71,63 +101,195 @@ fn deployed_contract_address(tx: &Transaction) -> Option<Felt> {
     }
 }
 
+/// Converts a broadcasted transaction to blockifier, along with the transaction as it was submitted: the RPC serves it
+/// while it waits in the mempool.
+pub(crate) fn broadcasted_to_mempool_tx(
+    tx: BroadcastedTransaction,
+    chain_id: Felt,
+) -> RpcResult<(Transaction, Option<ConvertedClass>, dp_transactions::Transaction)> {
+    validate_submitted_transaction(&tx).map_err(|err| StarknetRpcApiError::from_broadcasted_conversion(0, err))?;
+    let (blockifier_tx, converted_class) = broadcasted_to_blockifier(tx.clone(), chain_id, None)
+        .map_err(|err| StarknetRpcApiError::from_broadcasted_conversion(0, err))?;
+    let submitted = dp_transactions::Transaction::from_broadcasted(tx, declare_class_hash(&blockifier_tx));
+    Ok((blockifier_tx, converted_class, submitted))
+}
+
 fn add_tx_to_mempool(
     mempool: &Arc<Mempool>,
     tx: Transaction,
     converted_class: Option<ConvertedClass>,
+    submitted: dp_transactions::Transaction,
 ) -> RpcResult<()> {
     let Transaction::AccountTransaction(tx) = tx else {
         bail_internal_server_error!("Created transaction should be an account transaction")
     };
 
-    mempool
-        .accept_account_tx(tx, converted_class)
-        .map_err(|err| StarknetRpcApiError::TxnExecutionError { tx_index: 0, error: format!("{err:#}") })?;
+    mempool.accept_account_tx(tx, converted_class, Some(submitted)).map_err(|err| match err {
+        dc_mempool::Error::DuplicateTx(_) => StarknetRpcApiError::DuplicateTxn,
+        // The validation of the account failed, the call stack tells which call panicked.
+        dc_mempool::Error::Validation(StatefulValidatorError::TransactionExecutionError(ref exec_err)) => {
+            StarknetRpcApiError::TxnExecutionError {
+                tx_index: 0,
+                error: format!("{err:#}"),
+                call_stack: ContractErrorData::from_execution_error(exec_err).call_stack,
+            }
+        }
+        err => StarknetRpcApiError::TxnExecutionError { tx_index: 0, error: format!("{err:#}"), call_stack: vec![] },
+    })?;
     Ok(())
 }
 
-fn add_declare_transaction(
+/// The class is compiled on the compute pool, once its size is checked and it got one of the compilation slots.
+async fn add_declare_transaction(
     mempool: &Arc<Mempool>,
+    limits: &RpcLimitsConfig,
+    compilation_slots: &Arc<Semaphore>,
+    compute_pool: Option<&ComputePool>,
+    chain_id: Felt,
     declare_transaction: BroadcastedDeclareTransaction,
 ) -> RpcResult<DeclareTransactionResult> {
-    let (tx, classes) =
-        broadcasted_to_blockifier(BroadcastedTransaction::Declare(declare_transaction), mempool.chain_id(), None)
-            .map_err(|err| StarknetRpcApiError::TxnExecutionError { tx_index: 0, error: format!("{err:#}") })?;
+    limits.check_declared_class(&declare_transaction)?;
+    let (tx, classes, submitted) = limits
+        .compile_with_deadline(compilation_slots, compute_pool, move || {
+            broadcasted_to_mempool_tx(BroadcastedTransaction::Declare(declare_transaction), chain_id)
+        })
+        .await??;
+    if let Some(class) = &classes {
+        limits.check_compiled_class(class)?;
+    }
 
     let res = DeclareTransactionResult {
         transaction_hash: transaction_hash(&tx),
         class_hash: declare_class_hash(&tx).expect("Created transaction should be declare"),
     };
-    add_tx_to_mempool(mempool, tx, classes)?;
+    add_tx_to_mempool(mempool, tx, classes, submitted)?;
     Ok(res)
 }
 fn add_deploy_account_transaction(
     mempool: &Arc<Mempool>,
+    chain_id: Felt,
     deploy_account_transaction: BroadcastedDeployAccountTransaction,
 ) -> RpcResult<DeployAccountTransactionResult> {
-    let (tx, classes) = broadcasted_to_blockifier(
-        BroadcastedTransaction::DeployAccount(deploy_account_transaction),
-        mempool.chain_id(),
-        None,
-    )
-    .map_err(|err| StarknetRpcApiError::TxnExecutionError { tx_index: 0, error: format!("{err:#}") })?;
+    let (tx, classes, submitted) =
+        broadcasted_to_mempool_tx(BroadcastedTransaction::DeployAccount(deploy_account_transaction), chain_id)?;
 
     let res = DeployAccountTransactionResult {
         transaction_hash: transaction_hash(&tx),
         contract_address: deployed_contract_address(&tx).expect("Created transaction should be deploy account"),
     };
-    add_tx_to_mempool(mempool, tx, classes)?;
+    add_tx_to_mempool(mempool, tx, classes, submitted)?;
     Ok(res)
 }
 fn add_invoke_transaction(
     mempool: &Arc<Mempool>,
+    chain_id: Felt,
     invoke_transaction: BroadcastedInvokeTransaction,
 ) -> RpcResult<InvokeTransactionResult> {
-    let (tx, classes) =
-        broadcasted_to_blockifier(BroadcastedTransaction::Invoke(invoke_transaction), mempool.chain_id(), None)
-            .map_err(|err| StarknetRpcApiError::TxnExecutionError { tx_index: 0, error: format!("{err:#}") })?;
+    let (tx, classes, submitted) =
+        broadcasted_to_mempool_tx(BroadcastedTransaction::Invoke(invoke_transaction), chain_id)?;
 
     let res = InvokeTransactionResult { transaction_hash: transaction_hash(&tx) };
-    add_tx_to_mempool(mempool, tx, classes)?;
+    add_tx_to_mempool(mempool, tx, classes, submitted)?;
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use dc_db::testing::temp_database;
+    use dc_mempool::testing::{store_test_genesis, test_invoke_transaction, MockL1DataProvider};
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_convert::ToFelt;
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{BroadcastedDeclareTransactionV2, EntryPointsByType, FlattenedSierraClass};
+
+    use super::*;
+
+    /// A declare transaction whose program is not valid Sierra.
+    fn sierra_declare(sierra_program_length: usize) -> BroadcastedDeclareTransaction {
+        BroadcastedDeclareTransaction::V2(BroadcastedDeclareTransactionV2 {
+            sender_address: Felt::from(0x1234),
+            compiled_class_hash: Felt::ONE,
+            max_fee: Felt::from(0x100),
+            signature: vec![],
+            nonce: Felt::ZERO,
+            contract_class: Arc::new(FlattenedSierraClass {
+                sierra_program: vec![Felt::ONE; sierra_program_length],
+                contract_class_version: "0.1.0".into(),
+                entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+                abi: String::new(),
+            }),
+            is_query: false,
+        })
+    }
+
+    fn assert_duplicate(res: RpcResult<InvokeTransactionResult>) {
+        let err = res.unwrap_err();
+        assert_eq!(err.code(), i32::from(&StarknetRpcApiError::DuplicateTxn), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_transactions_are_rejected() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_test_genesis(&backend);
+        let chain_id = backend.chain_config().chain_id.clone().to_felt();
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+
+        // The first submission was accepted.
+        add_invoke_transaction(&mempool, chain_id, test_invoke_transaction(0)).unwrap();
+        assert_duplicate(add_invoke_transaction(&mempool, chain_id, test_invoke_transaction(0)));
+        assert_eq!(mempool.tx_count(), 1);
+
+        // The transaction is already on chain.
+        let (tx, ..) =
+            broadcasted_to_mempool_tx(BroadcastedTransaction::Invoke(test_invoke_transaction(1)), chain_id).unwrap();
+        let header = Header { block_number: 1, ..Default::default() };
+        let info = DeoxysBlockInfo::new(header, vec![transaction_hash(&tx)], Felt::ONE);
+        let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+        backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        assert_duplicate(add_invoke_transaction(&mempool, chain_id, test_invoke_transaction(1)));
+        assert_eq!(mempool.tx_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_classes_are_rejected_before_compilation() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+        let limits = RpcLimitsConfig { max_sierra_program_length: 100, ..Default::default() };
+        let provider =
+            MempoolProvider::new(Arc::clone(&mempool), ChainHandle::from_backend(&backend)).with_limits(limits);
+
+        // The program is not valid Sierra, it only fails with the size error if it never reaches the compiler.
+        let err = provider.add_declare_transaction(sierra_declare(101)).await.unwrap_err();
+        assert_eq!(err.code(), i32::from(&StarknetRpcApiError::ContractClassSizeTooLarge), "{err:?}");
+        assert_eq!(mempool.tx_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_declare_times_out_while_the_compilation_slots_are_taken() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+        let limits = RpcLimitsConfig {
+            compilation_timeout: Duration::from_millis(50),
+            max_concurrent_compilations: 1,
+            ..Default::default()
+        };
+        let provider =
+            MempoolProvider::new(Arc::clone(&mempool), ChainHandle::from_backend(&backend)).with_limits(limits);
+
+        // A compilation that never ends holds the only slot.
+        let slot = Arc::clone(&provider.compilation_slots).acquire_owned().await.unwrap();
+        let err = provider.add_declare_transaction(sierra_declare(10)).await.unwrap_err();
+        assert_eq!(err.code(), i32::from(&StarknetRpcApiError::CompilationTimeout), "{err:?}");
+
+        // Once the slot is released, the class reaches the compiler, which rejects it: the program is not valid Sierra.
+        drop(slot);
+        let err = provider.add_declare_transaction(sierra_declare(10)).await.unwrap_err();
+        assert_ne!(err.code(), i32::from(&StarknetRpcApiError::CompilationTimeout), "{err:?}");
+        assert_eq!(mempool.tx_count(), 0);
+    }
+}
@@ -0,0 +1,57 @@
+use dc_db::{Column, DeoxysStorageError};
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Starts compacting database columns in the background, and returns their names. The progress is logged by the node.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `columns` - The names of the columns to compact, such as `contract_storage`. All of them when not given.
+pub fn compact_database(starknet: &Starknet, columns: Option<Vec<String>>) -> StarknetRpcResult<Vec<String>> {
+    let columns = columns
+        .map(|columns| columns.iter().map(|column| column.parse::<Column>()).collect::<Result<Vec<_>, _>>())
+        .transpose()
+        .map_err(|data| StarknetRpcApiError::ErrUnexpectedError { data })?;
+    let names = columns.as_deref().unwrap_or(Column::ALL).iter().map(Column::to_string).collect();
+
+    match starknet.backend.compact(columns) {
+        Err(DeoxysStorageError::CompactionRunning) => {
+            Err(StarknetRpcApiError::ErrUnexpectedError { data: "A compaction is already running".into() })
+        }
+        // The compaction goes on in the background.
+        res => res.map(|_compaction| names).or_internal_server_error("Error starting the compaction"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::test_starknet;
+
+    #[tokio::test]
+    async fn test_compact_database() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        assert!(matches!(
+            compact_database(&starknet, Some(vec!["contract_storages".into()])),
+            Err(StarknetRpcApiError::ErrUnexpectedError { data }) if data == "Unknown column `contract_storages`"
+        ));
+        assert!(!starknet.backend.is_compacting());
+
+        let names = compact_database(&starknet, Some(vec!["contract_storage".into(), "meta".into()])).unwrap();
+        assert_eq!(names, vec!["contract_storage".to_string(), "meta".to_string()]);
+        while starknet.backend.is_compacting() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(compact_database(&starknet, None).unwrap().len(), Column::ALL.len());
+        while starknet.backend.is_compacting() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
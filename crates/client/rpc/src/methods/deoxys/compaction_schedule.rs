@@ -0,0 +1,28 @@
+use dc_db::compaction::CompactionSchedule;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Returns the schedule of the database background jobs: how many of them run, during and outside of the quiet hours.
+///
+/// This is not part of the Starknet specification.
+pub fn get_compaction_schedule(starknet: &Starknet) -> CompactionSchedule {
+    starknet.backend.compaction_schedule()
+}
+
+/// Replaces the schedule of the database background jobs. It is applied right away, and kept until the node is
+/// restarted.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `schedule` - The number of background jobs outside of the quiet hours, during the quiet hours, and the quiet
+///   hours as UTC time windows like `08:00-20:00`. The numbers of background jobs must not be zero.
+pub fn set_compaction_schedule(starknet: &Starknet, schedule: CompactionSchedule) -> StarknetRpcResult<()> {
+    if schedule.background_jobs == 0 || schedule.quiet_background_jobs == 0 {
+        return Err(StarknetRpcApiError::ErrUnexpectedError { data: "At least one background job is needed".into() });
+    }
+    starknet.backend.set_compaction_schedule(schedule).or_internal_server_error("Error applying compaction schedule")
+}
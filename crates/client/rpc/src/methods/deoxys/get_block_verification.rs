@@ -0,0 +1,124 @@
+use starknet_core::types::BlockId;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::BlockVerificationStatus;
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Returns what the node verified itself about a block, instead of trusting the feeder. The checks that were skipped,
+/// or that failed while the sync was told to import the blocks anyway, are not set. Checks delayed after the import are
+/// set once they pass.
+///
+/// This is not part of the Starknet specification, the status of the block is unaffected.
+///
+/// ### Arguments
+///
+/// * `block_id` - The hash of the requested block, or number (height) of the requested block, or a block tag. Nothing
+///   is verified about the pending block.
+///
+/// ### Returns
+///
+/// * `verification` - Whether the block hash, the commitments of the header and the state root were computed again,
+///   and whether the signature of the block was checked.
+pub fn get_block_verification(starknet: &Starknet, block_id: BlockId) -> StarknetRpcResult<BlockVerificationStatus> {
+    let verification = starknet
+        .backend
+        .get_block_verification(&block_id)
+        .or_internal_server_error("Error getting block verification")?
+        .ok_or(StarknetRpcApiError::BlockNotFound)?;
+    Ok(verification.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::header::PendingHeader;
+    use dp_block::{
+        BlockVerification, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, DeoxysPendingBlock, Header, StarknetVersion,
+    };
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{BlockTag, Felt, MaybePendingBlockWithTxHashes};
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+    use crate::types::BlockWithExtensions;
+    use crate::StarknetReadRpcApiServer;
+
+    #[tokio::test]
+    async fn test_get_block_verification() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let info = DeoxysBlockInfo::new(Header::default(), vec![], Felt::ONE)
+            .with_verification(BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS);
+        backend
+            .store_block(
+                DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![])).into(),
+                StateDiff::default(),
+                vec![],
+            )
+            .unwrap();
+        let pending =
+            DeoxysPendingBlock::new_empty(PendingHeader { parent_block_hash: Felt::ONE, ..Default::default() });
+        backend.store_block(pending.into(), StateDiff::default(), vec![]).unwrap();
+
+        let starknet = starknet_over(Arc::clone(&backend), None);
+
+        let expected = BlockVerificationStatus {
+            verified_hash: true,
+            verified_commitments: true,
+            verified_root: false,
+            signature_checked: false,
+        };
+        assert_eq!(get_block_verification(&starknet, BlockId::Number(0)).unwrap(), expected);
+        assert_eq!(get_block_verification(&starknet, BlockId::Hash(Felt::ONE)).unwrap(), expected);
+        assert_eq!(
+            get_block_verification(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap(),
+            BlockVerificationStatus::default()
+        );
+        assert!(matches!(
+            get_block_verification(&starknet, BlockId::Number(1)),
+            Err(StarknetRpcApiError::BlockNotFound)
+        ));
+
+        // The verification is added to the block as an extension field, the status of the block is unaffected.
+        let block = starknet.get_block_with_tx_hashes(BlockId::Number(0)).unwrap();
+        let json = serde_json::to_value(&block).unwrap();
+        assert_eq!(json["status"], "ACCEPTED_ON_L2");
+        assert_eq!(json["deoxys_verification"], serde_json::to_value(expected).unwrap());
+        let block: BlockWithExtensions<MaybePendingBlockWithTxHashes> = serde_json::from_value(json).unwrap();
+        assert!(matches!(block.block, MaybePendingBlockWithTxHashes::Block(block) if block.block_hash == Felt::ONE));
+
+        // A verification delayed after the import shows up once it passes.
+        backend.add_block_verification(0, BlockVerification::VERIFIED_ROOT, |_| {}).unwrap();
+        let block = starknet.get_block_with_tx_hashes(BlockId::Number(0)).unwrap();
+        assert_eq!(block.deoxys_verification, Some(BlockVerificationStatus { verified_root: true, ..expected }));
+
+        let pending = starknet.get_block_with_tx_hashes(BlockId::Tag(BlockTag::Pending)).unwrap();
+        assert!(serde_json::to_value(&pending).unwrap().get("deoxys_verification").is_none());
+        assert!(serde_json::to_value(&pending).unwrap().get("deoxys_receipt_commitment").is_none());
+
+        // The receipt commitment is only served for the blocks of 0.13.2 and after.
+        assert_eq!(block.deoxys_receipt_commitment, None);
+        let header = Header {
+            block_number: 1,
+            parent_block_hash: Felt::ONE,
+            protocol_version: StarknetVersion::STARKNET_VERSION_0_13_2,
+            receipt_commitment: Felt::from(0x4ec),
+            ..Default::default()
+        };
+        let info = DeoxysBlockInfo::new(header, vec![], Felt::TWO);
+        backend
+            .store_block(
+                DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![])).into(),
+                StateDiff::default(),
+                vec![],
+            )
+            .unwrap();
+        let json = serde_json::to_value(starknet.get_block_with_tx_hashes(BlockId::Number(1)).unwrap()).unwrap();
+        assert_eq!(json["deoxys_receipt_commitment"], "0x4ec");
+        let block = starknet.get_block_with_receipts(BlockId::Number(1)).await.unwrap();
+        assert_eq!(block.deoxys_receipt_commitment, Some(Felt::from(0x4ec)));
+    }
+}
@@ -0,0 +1,66 @@
+use starknet_core::types::Felt;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::ClassDeclarationInfo;
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Returns the block and the transaction which declared a class.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Returns
+///
+/// * `block_number` - The block which first declared the class.
+/// * `transaction_hash` - The declare transaction, or `null` for the classes declared without one, such as the classes
+///   of a genesis block.
+///
+/// ### Errors
+///
+/// * `CLASS_HASH_NOT_FOUND` - If the class is not declared by the confirmed blocks.
+pub fn get_class_declaration_info(starknet: &Starknet, class_hash: Felt) -> StarknetRpcResult<ClassDeclarationInfo> {
+    let declaration = starknet
+        .backend
+        .get_class_declaration(&class_hash)
+        .or_internal_server_error("Error getting class declaration")?
+        .ok_or(StarknetRpcApiError::ClassHashNotFound)?;
+    Ok(declaration.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_state_update::StateDiff;
+    use dp_transactions::{DeclareTransaction, DeclareTransactionV0, Transaction};
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::test_starknet;
+
+    #[tokio::test]
+    async fn test_get_class_declaration_info() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let block_n = starknet.backend.get_latest_block_n().unwrap().map_or(0, |tip| tip + 1);
+        let declare = Transaction::Declare(DeclareTransaction::V0(DeclareTransactionV0 {
+            sender_address: Felt::ONE,
+            max_fee: Felt::ZERO,
+            signature: vec![],
+            class_hash: Felt::from(0xc1a55),
+        }));
+        let header = Header { block_number: block_n, ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![Felt::from(0xdec1)], Felt::from(0xb10c)).into(),
+            inner: DeoxysBlockInner::new(vec![declare], vec![]),
+        };
+        let state_diff = StateDiff::new(vec![], vec![Felt::from(0xc1a55)], vec![], vec![], vec![], vec![]);
+        starknet.backend.store_block(block, state_diff, vec![]).unwrap();
+
+        assert_eq!(
+            get_class_declaration_info(&starknet, Felt::from(0xc1a55)).unwrap(),
+            ClassDeclarationInfo { block_number: block_n, transaction_hash: Some(Felt::from(0xdec1)) }
+        );
+        assert!(matches!(
+            get_class_declaration_info(&starknet, Felt::from(0x404)),
+            Err(StarknetRpcApiError::ClassHashNotFound)
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use dc_db::db_block_id::DbBlockIdResolvable;
+use starknet_core::types::BlockId;
+use starknet_types_core::felt::Felt;
+
+use crate::constants::MAX_CLASS_HASHES_AT_ADDRESSES;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Get the class hashes of many contracts in the given block, in a single request. This is meant for wallets
+/// resolving many accounts on load.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `block_id` - The hash of the requested block, or number (height) of the requested block, or a
+///   block tag
+/// * `contract_addresses` - The addresses of the contracts whose class hashes will be returned
+///
+/// ### Returns
+///
+/// * `class_hashes` - The class hash of every contract, in the order of `contract_addresses`, or `null` for the
+///   contracts that are not deployed in the given block. Returns `PAGE_SIZE_TOO_BIG` when there are more than
+///   [`MAX_CLASS_HASHES_AT_ADDRESSES`] addresses.
+pub fn get_class_hashes_at(
+    starknet: &Starknet,
+    block_id: BlockId,
+    contract_addresses: Vec<Felt>,
+) -> StarknetRpcResult<Vec<Option<Felt>>> {
+    if contract_addresses.len() > MAX_CLASS_HASHES_AT_ADDRESSES {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+
+    let block_id = block_id
+        .resolve_db_block_id(&starknet.backend)
+        .or_internal_server_error("Error resolving block id")?
+        .ok_or(StarknetRpcApiError::BlockNotFound)?;
+    // As for `starknet_getClassHashAt`, the pending block falls back to the latest block when there is none.
+    if !block_id.is_pending() {
+        starknet.get_block_info(&block_id)?;
+    }
+
+    starknet
+        .backend
+        .get_contract_class_hashes_at(&block_id, &contract_addresses)
+        .or_internal_server_error("Error getting contract class hashes at")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::header::PendingHeader;
+    use dp_block::{
+        DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo,
+        DeoxysPendingBlockInfo, Header,
+    };
+    use dp_state_update::{DeployedContractItem, StateDiff};
+    use starknet_core::types::BlockTag;
+
+    use super::*;
+    use crate::methods::read::get_class_hash_at::get_class_hash_at;
+    use crate::test_utils::starknet_over;
+
+    fn deploy(address: u64, class_hash: u64) -> StateDiff {
+        StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![DeployedContractItem { address: Felt::from(address), class_hash: Felt::from(class_hash) }],
+            vec![],
+            vec![],
+        )
+    }
+
+    /// Contract 0x1 is deployed in block 0, and contract 0x2 in the pending block.
+    async fn test_starknet() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(Header::default(), vec![], Felt::ONE)),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, deploy(0x1, 0x10), vec![]).unwrap();
+        let pending = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::Pending(DeoxysPendingBlockInfo::new(PendingHeader::default(), vec![])),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(pending, deploy(0x2, 0x20), vec![]).unwrap();
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    #[tokio::test]
+    async fn test_get_class_hashes_at() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        // Deployed, pending-deployed and undeployed contracts.
+        let addresses = vec![Felt::from(0x1), Felt::from(0x2), Felt::from(0x3)];
+
+        let pending = get_class_hashes_at(&starknet, BlockId::Tag(BlockTag::Pending), addresses.clone()).unwrap();
+        assert_eq!(pending, [Some(Felt::from(0x10)), Some(Felt::from(0x20)), None]);
+        for (address, class_hash) in addresses.iter().zip(&pending) {
+            let single = get_class_hash_at(&starknet, BlockId::Tag(BlockTag::Pending), *address).ok();
+            assert_eq!(single, *class_hash);
+        }
+
+        let latest = get_class_hashes_at(&starknet, BlockId::Tag(BlockTag::Latest), addresses.clone()).unwrap();
+        assert_eq!(latest, [Some(Felt::from(0x10)), None, None]);
+        assert_eq!(get_class_hashes_at(&starknet, BlockId::Number(0), addresses).unwrap(), latest);
+    }
+
+    #[tokio::test]
+    async fn test_get_class_hashes_at_errors() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let res = get_class_hashes_at(&starknet, BlockId::Number(5), vec![Felt::from(0x1)]);
+        assert!(matches!(res, Err(StarknetRpcApiError::BlockNotFound)));
+
+        let too_many = vec![Felt::ONE; MAX_CLASS_HASHES_AT_ADDRESSES + 1];
+        let res = get_class_hashes_at(&starknet, BlockId::Tag(BlockTag::Pending), too_many);
+        assert!(matches!(res, Err(StarknetRpcApiError::PageSizeTooBig)));
+    }
+}
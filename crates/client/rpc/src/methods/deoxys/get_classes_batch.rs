@@ -0,0 +1,33 @@
+use starknet_core::types::ContractClass;
+use starknet_types_core::felt::Felt;
+
+use crate::constants::MAX_CLASSES_BATCH_SIZE;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Get the definitions of many classes declared in the confirmed blocks, in a single request. The class hashes
+/// usually come from `deoxys_listClasses`.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `class_hashes` - The hashes of the requested classes
+///
+/// ### Returns
+///
+/// * `classes` - The definition of every class, in the order of `class_hashes`, or `null` for the classes that are
+///   not declared. Returns `PAGE_SIZE_TOO_BIG` when there are more than [`MAX_CLASSES_BATCH_SIZE`] class hashes.
+pub fn get_classes_batch(
+    starknet: &Starknet,
+    class_hashes: Vec<Felt>,
+) -> StarknetRpcResult<Vec<Option<ContractClass>>> {
+    if class_hashes.len() > MAX_CLASSES_BATCH_SIZE {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+
+    let classes_info =
+        starknet.backend.get_classes_info(&class_hashes).or_internal_server_error("Error getting classes info")?;
+    Ok(classes_info.into_iter().map(|info| info.map(|info| info.contract_class.into())).collect())
+}
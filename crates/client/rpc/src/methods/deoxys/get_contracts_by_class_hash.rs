@@ -0,0 +1,189 @@
+use starknet_types_core::felt::Felt;
+
+use crate::constants::MAX_CONTRACTS_PAGE_SIZE;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::{ClassContract, ContractsPage};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Lists the contracts of a class in the confirmed blocks, ordered by address. A contract whose class was replaced is
+/// listed under its new class only.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `class_hash` - The hash of the class
+/// * `continuation_token` - The token returned with the previous page, `null` for the first page.
+/// * `limit` - Maximum number of contracts to return. Defaults to the maximum page size.
+///
+/// ### Returns
+///
+/// A page of contract addresses with the block which deployed them, and the token of the next page. Returns
+/// `PAGE_SIZE_TOO_BIG` if the limit exceeds the maximum page size, or `INVALID_CONTINUATION_TOKEN`. Returns an
+/// unexpected error while the contracts stored before the index existed are being indexed.
+pub fn get_contracts_by_class_hash(
+    starknet: &Starknet,
+    class_hash: Felt,
+    continuation_token: Option<String>,
+    limit: Option<u64>,
+) -> StarknetRpcResult<ContractsPage> {
+    let limit = limit.unwrap_or(MAX_CONTRACTS_PAGE_SIZE as u64);
+    if limit > MAX_CONTRACTS_PAGE_SIZE as u64 {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+    // The token is the address of the last contract of the previous page.
+    let start_after = continuation_token
+        .as_deref()
+        .map(Felt::from_hex)
+        .transpose()
+        .map_err(|_| StarknetRpcApiError::InvalidContinuationToken)?;
+    if !starknet.backend.are_class_contracts_indexed().or_internal_server_error("Error reading the contracts index")? {
+        return Err(StarknetRpcApiError::ErrUnexpectedError {
+            data: "The contracts of the classes are still being indexed".into(),
+        });
+    }
+    if limit == 0 {
+        return Ok(ContractsPage { contracts: vec![], continuation_token });
+    }
+
+    let mut contracts = starknet
+        .backend
+        .iter_class_contracts(&class_hash, start_after.as_ref())
+        .map(|res| res.map(|(contract_address, deployed_at_block)| ClassContract { contract_address, deployed_at_block }))
+        // One more contract tells whether there is a next page.
+        .take(limit as usize + 1)
+        .collect::<Result<Vec<_>, _>>()
+        .or_internal_server_error("Error reading the contracts of the class")?;
+
+    let continuation_token = if contracts.len() > limit as usize {
+        contracts.pop();
+        contracts.last().map(|contract| format!("{:#x}", contract.contract_address))
+    } else {
+        None
+    };
+    Ok(ContractsPage { contracts, continuation_token })
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_state_update::{DeployedContractItem, ReplacedClassItem, StateDiff};
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::test_starknet;
+
+    const CLASS_A: Felt = Felt::from_hex_unchecked("0xc1a55a");
+    const CLASS_B: Felt = Felt::from_hex_unchecked("0xc1a55b");
+
+    fn store_block(starknet: &Starknet, block_n: u64, state_diff: StateDiff) {
+        let header = Header { block_number: block_n, ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![], Felt::from(block_n)).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        starknet.backend.store_block(block, state_diff, vec![]).unwrap();
+    }
+
+    /// Lists all the pages, and returns the contracts along with the size of every page.
+    fn list_all(starknet: &Starknet, class_hash: Felt, limit: u64) -> (Vec<ClassContract>, Vec<usize>) {
+        let (mut contracts, mut page_sizes) = (vec![], vec![]);
+        let mut continuation_token = None;
+        loop {
+            let page = get_contracts_by_class_hash(starknet, class_hash, continuation_token, Some(limit)).unwrap();
+            page_sizes.push(page.contracts.len());
+            contracts.extend(page.contracts);
+            match page.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return (contracts, page_sizes),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_contracts_by_class_hash() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let first_block = starknet.backend.get_latest_block_n().unwrap().map_or(0, |tip| tip + 1);
+        // Contracts 0x1 to 0x5 are deployed with class A and contracts 0x6 to 0x8 with class B, then contract 0x2 is
+        // upgraded to class B.
+        let state_diffs = [
+            StateDiff::new(
+                vec![],
+                vec![],
+                vec![],
+                (1..=5u64)
+                    .map(|address| DeployedContractItem { address: Felt::from(address), class_hash: CLASS_A })
+                    .collect(),
+                vec![],
+                vec![],
+            ),
+            StateDiff::new(
+                vec![],
+                vec![],
+                vec![],
+                (6..=8u64)
+                    .map(|address| DeployedContractItem { address: Felt::from(address), class_hash: CLASS_B })
+                    .collect(),
+                vec![ReplacedClassItem { contract_address: Felt::TWO, class_hash: CLASS_B }],
+                vec![],
+            ),
+        ];
+        for (i, state_diff) in state_diffs.into_iter().enumerate() {
+            store_block(&starknet, first_block + i as u64, state_diff);
+        }
+
+        let contract = |address: u64, deployed_at_block: u64| ClassContract {
+            contract_address: Felt::from(address),
+            deployed_at_block,
+        };
+        let (contracts, page_sizes) = list_all(&starknet, CLASS_A, 2);
+        assert_eq!(
+            contracts,
+            vec![
+                contract(1, first_block),
+                contract(3, first_block),
+                contract(4, first_block),
+                contract(5, first_block)
+            ]
+        );
+        assert_eq!(page_sizes, vec![2, 2]);
+
+        let (contracts, page_sizes) = list_all(&starknet, CLASS_B, 3);
+        assert_eq!(
+            contracts,
+            vec![
+                contract(2, first_block),
+                contract(6, first_block + 1),
+                contract(7, first_block + 1),
+                contract(8, first_block + 1)
+            ]
+        );
+        assert_eq!(page_sizes, vec![3, 1]);
+
+        // The token of a page stays valid when contracts are deployed after it was returned.
+        let page = get_contracts_by_class_hash(&starknet, CLASS_B, None, Some(1)).unwrap();
+        assert_eq!(page.continuation_token.as_deref(), Some("0x2"));
+        let deployed = DeployedContractItem { address: Felt::from(9), class_hash: CLASS_B };
+        store_block(&starknet, first_block + 2, StateDiff::new(vec![], vec![], vec![], vec![deployed], vec![], vec![]));
+        let page = get_contracts_by_class_hash(&starknet, CLASS_B, page.continuation_token, Some(10)).unwrap();
+        assert_eq!(
+            page.contracts,
+            vec![
+                contract(6, first_block + 1),
+                contract(7, first_block + 1),
+                contract(8, first_block + 1),
+                contract(9, first_block + 2)
+            ]
+        );
+        assert_eq!(page.continuation_token, None);
+
+        assert!(matches!(
+            get_contracts_by_class_hash(&starknet, CLASS_A, None, Some(MAX_CONTRACTS_PAGE_SIZE as u64 + 1)),
+            Err(StarknetRpcApiError::PageSizeTooBig)
+        ));
+        assert!(matches!(
+            get_contracts_by_class_hash(&starknet, CLASS_A, Some("not a felt".into()), None),
+            Err(StarknetRpcApiError::InvalidContinuationToken)
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use starknet_core::types::BlockId;
+
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::DeployedContract;
+use crate::utils::{OptionExt, ResultExt};
+use crate::Starknet;
+
+/// Lists the contracts deployed by a block, with their class.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `block_id` - The hash of the requested block, or number (height) of the requested block, or a block tag.
+///
+/// ### Returns
+///
+/// The contracts deployed by the block, in the order of its state diff. The contracts whose class the block replaced
+/// are not listed. Returns `BLOCK_NOT_FOUND` if the block is not found.
+pub fn get_deployed_contracts(starknet: &Starknet, block_id: BlockId) -> StarknetRpcResult<Vec<DeployedContract>> {
+    let block_id = starknet
+        .backend
+        .resolve_block_id(&block_id)
+        .or_internal_server_error("Error resolving block id")?
+        .ok_or(StarknetRpcApiError::BlockNotFound)?;
+    let state_diff = starknet
+        .backend
+        .get_block_state_diff(&block_id)
+        .or_internal_server_error("Error getting block state diff")?
+        .ok_or_internal_server_error("Block has no state diff")?;
+
+    Ok(state_diff
+        .deployed_contracts()
+        .iter()
+        .map(|item| DeployedContract { contract_address: item.address, class_hash: item.class_hash })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_state_update::{DeployedContractItem, ReplacedClassItem, StateDiff};
+    use starknet_core::types::{BlockTag, Felt};
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::test_starknet;
+
+    #[tokio::test]
+    async fn test_get_deployed_contracts() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let block_n = starknet.backend.get_latest_block_n().unwrap().map_or(0, |tip| tip + 1);
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![
+                DeployedContractItem { address: Felt::from(0xc0), class_hash: Felt::from(0xa) },
+                DeployedContractItem { address: Felt::from(0xc1), class_hash: Felt::from(0xb) },
+            ],
+            vec![ReplacedClassItem { contract_address: Felt::from(0xc2), class_hash: Felt::from(0xb) }],
+            vec![],
+        );
+        let header = Header { block_number: block_n, ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![], Felt::from(block_n)).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        starknet.backend.store_block(block, state_diff, vec![]).unwrap();
+
+        let expected = vec![
+            DeployedContract { contract_address: Felt::from(0xc0), class_hash: Felt::from(0xa) },
+            DeployedContract { contract_address: Felt::from(0xc1), class_hash: Felt::from(0xb) },
+        ];
+        assert_eq!(get_deployed_contracts(&starknet, BlockId::Number(block_n)).unwrap(), expected);
+        assert_eq!(get_deployed_contracts(&starknet, BlockId::Tag(BlockTag::Latest)).unwrap(), expected);
+        assert!(matches!(
+            get_deployed_contracts(&starknet, BlockId::Number(block_n + 1)),
+            Err(StarknetRpcApiError::BlockNotFound)
+        ));
+    }
+}
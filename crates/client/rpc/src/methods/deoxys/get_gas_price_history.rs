@@ -0,0 +1,171 @@
+use dp_block::header::GasPrices;
+use starknet_core::types::ResourcePrice;
+
+use crate::constants::MAX_GAS_PRICE_HISTORY_BLOCKS;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::{BlockGasPrices, GasPriceHistory, GasPricesPercentile};
+use crate::Starknet;
+
+/// Get the gas prices of the latest blocks, with their median and 90th percentile, for wallets to suggest the maximum
+/// fee of a transaction.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `n` - The number of blocks, counted back from the latest block
+///
+/// ### Returns
+///
+/// * `history` - The gas prices of the last `n` blocks, or of all the blocks when there are fewer. The suggested
+///   maximum L1 gas price is the p90 raised by the margin configured on the node. Returns `PAGE_SIZE_TOO_BIG` when `n`
+///   is more than [`MAX_GAS_PRICE_HISTORY_BLOCKS`], and `NO_BLOCKS` when there is no block. With `n` zero, the history
+///   is empty and its prices are zero.
+pub fn get_gas_price_history(starknet: &Starknet, n: u64) -> StarknetRpcResult<GasPriceHistory> {
+    let n = usize::try_from(n).unwrap_or(usize::MAX);
+    if n > MAX_GAS_PRICE_HISTORY_BLOCKS {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+    let history = starknet.backend.gas_price_history().last(n);
+    if history.is_empty() && n > 0 {
+        return Err(StarknetRpcApiError::NoBlocks);
+    }
+
+    let prices: Vec<_> = history.iter().map(|(_, prices)| prices).collect();
+    let (p50, p90) = if prices.is_empty() {
+        (GasPrices::default(), GasPrices::default())
+    } else {
+        (percentile(&prices, 50), percentile(&prices, 90))
+    };
+    let suggested_max_l1_gas_price = ResourcePrice {
+        price_in_fri: with_margin(p90.strk_l1_gas_price, starknet.suggested_gas_price_percent).into(),
+        price_in_wei: with_margin(p90.eth_l1_gas_price, starknet.suggested_gas_price_percent).into(),
+    };
+
+    Ok(GasPriceHistory {
+        blocks: history
+            .iter()
+            .map(|(block_number, prices)| BlockGasPrices {
+                block_number: *block_number,
+                l1_gas_price: prices.l1_gas_price(),
+                l1_data_gas_price: prices.l1_data_gas_price(),
+            })
+            .collect(),
+        p50: GasPricesPercentile { l1_gas_price: p50.l1_gas_price(), l1_data_gas_price: p50.l1_data_gas_price() },
+        p90: GasPricesPercentile { l1_gas_price: p90.l1_gas_price(), l1_data_gas_price: p90.l1_data_gas_price() },
+        suggested_max_l1_gas_price,
+    })
+}
+
+/// Nearest-rank percentile of every price, `prices` must not be empty.
+fn percentile(prices: &[&GasPrices], percent: usize) -> GasPrices {
+    let rank = (prices.len() * percent).div_ceil(100).max(1);
+    let nth = |price: fn(&GasPrices) -> u128| {
+        let mut values: Vec<u128> = prices.iter().map(|prices| price(*prices)).collect();
+        *values.select_nth_unstable(rank - 1).1
+    };
+    GasPrices {
+        eth_l1_gas_price: nth(|prices| prices.eth_l1_gas_price),
+        strk_l1_gas_price: nth(|prices| prices.strk_l1_gas_price),
+        eth_l1_data_gas_price: nth(|prices| prices.eth_l1_data_gas_price),
+        strk_l1_data_gas_price: nth(|prices| prices.strk_l1_data_gas_price),
+    }
+}
+
+fn with_margin(price: u128, percent: u64) -> u128 {
+    price.saturating_mul(percent.into()) / 100
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::Felt;
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+
+    fn prices(eth: u128, strk: u128) -> GasPrices {
+        GasPrices {
+            eth_l1_gas_price: eth,
+            strk_l1_gas_price: strk,
+            eth_l1_data_gas_price: eth / 10,
+            strk_l1_data_gas_price: strk / 10,
+        }
+    }
+
+    fn resource_price(wei: u128, fri: u128) -> ResourcePrice {
+        ResourcePrice { price_in_wei: wei.into(), price_in_fri: fri.into() }
+    }
+
+    #[test]
+    fn test_percentile() {
+        // Shuffled, the eth and strk prices go in opposite directions.
+        let history: Vec<_> = [7, 2, 10, 4, 1, 9, 3, 6, 8, 5].map(|n| prices(n * 100, (11 - n) * 1000)).to_vec();
+        let history: Vec<_> = history.iter().collect();
+        assert_eq!(percentile(&history, 50), prices(500, 5000));
+        assert_eq!(percentile(&history, 90), prices(900, 9000));
+        assert_eq!(percentile(&history[..1], 90), *history[0]);
+        // The rank is rounded up.
+        assert_eq!(percentile(&history[..3], 50), prices(700, 4000));
+        assert_eq!(with_margin(900, 150), 1350);
+        assert_eq!(with_margin(u128::MAX, 150), u128::MAX / 100);
+    }
+
+    #[tokio::test]
+    async fn test_get_gas_price_history() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let starknet = starknet_over(Arc::clone(&backend), None).with_suggested_gas_price_percent(200);
+
+        assert!(matches!(get_gas_price_history(&starknet, 10), Err(StarknetRpcApiError::NoBlocks)));
+        // Nothing is asked for, even without blocks.
+        let empty = get_gas_price_history(&starknet, 0).unwrap();
+        assert!(empty.blocks.is_empty());
+        assert_eq!(empty.suggested_max_l1_gas_price, resource_price(0, 0));
+
+        // A price spike on the first blocks, which are evicted from the requested range.
+        let price_of = |block_number: u64| match block_number {
+            0..=9 => 1_000_000,
+            _ => 100 + (block_number % 10) as u128 * 10,
+        };
+        let n_blocks = MAX_GAS_PRICE_HISTORY_BLOCKS as u64 + 10;
+        for block_number in 0..n_blocks {
+            let price = price_of(block_number);
+            let header = Header { block_number, l1_gas_price: prices(price, price * 3), ..Default::default() };
+            let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_number));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+
+        let history = get_gas_price_history(&starknet, 20).unwrap();
+        let block_numbers: Vec<_> = history.blocks.iter().map(|block| block.block_number).collect();
+        assert_eq!(block_numbers, (n_blocks - 20..n_blocks).collect::<Vec<_>>());
+        let price = price_of(n_blocks - 20);
+        assert_eq!(history.blocks[0].l1_gas_price, resource_price(price, price * 3));
+        assert_eq!(history.blocks[0].l1_data_gas_price, resource_price(price / 10, price * 3 / 10));
+        assert_eq!(
+            history.p50,
+            GasPricesPercentile { l1_gas_price: resource_price(140, 420), l1_data_gas_price: resource_price(14, 42) }
+        );
+        assert_eq!(
+            history.p90,
+            GasPricesPercentile { l1_gas_price: resource_price(180, 540), l1_data_gas_price: resource_price(18, 54) }
+        );
+        assert_eq!(history.suggested_max_l1_gas_price, resource_price(360, 1080));
+
+        // The whole history no longer holds the spike.
+        let history = get_gas_price_history(&starknet, MAX_GAS_PRICE_HISTORY_BLOCKS as u64).unwrap();
+        assert_eq!(history.blocks.len(), MAX_GAS_PRICE_HISTORY_BLOCKS);
+        assert_eq!(history.blocks[0].block_number, 10);
+        assert_eq!(history.p90.l1_gas_price, resource_price(180, 540));
+
+        assert!(matches!(
+            get_gas_price_history(&starknet, MAX_GAS_PRICE_HISTORY_BLOCKS as u64 + 1),
+            Err(StarknetRpcApiError::PageSizeTooBig)
+        ));
+    }
+}
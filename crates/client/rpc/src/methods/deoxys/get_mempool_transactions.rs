@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+
+use dc_mempool::MempoolTxSummary;
+
+use crate::constants::MAX_MEMPOOL_PAGE_SIZE;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::{unix_millis, MempoolStats, MempoolTransactionsPage};
+use crate::Starknet;
+
+/// Returns the transactions currently sitting in the mempool, for debugging purposes.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `offset` - Number of transactions to skip, in arrival order. Defaults to 0.
+/// * `limit` - Maximum number of transactions to return. Defaults to the maximum page size.
+///
+/// ### Returns
+///
+/// A page of transaction summaries ordered by arrival time, along with statistics about the
+/// whole mempool. Returns `PAGE_SIZE_TOO_BIG` if the limit exceeds the maximum page size, or an
+/// unexpected error when the node does not run a mempool.
+pub fn get_mempool_transactions(
+    starknet: &Starknet,
+    offset: Option<u64>,
+    limit: Option<u64>,
+) -> StarknetRpcResult<MempoolTransactionsPage> {
+    let mempool = starknet
+        .mempool
+        .as_ref()
+        .ok_or_else(|| StarknetRpcApiError::ErrUnexpectedError { data: "This node does not run a mempool".into() })?;
+    // The snapshot is taken before any serialization happens, so the mempool lock is not held for long.
+    mempool_page(mempool.snapshot(), offset, limit)
+}
+
+fn mempool_page(
+    snapshot: Vec<MempoolTxSummary>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+) -> StarknetRpcResult<MempoolTransactionsPage> {
+    let limit = limit.unwrap_or(MAX_MEMPOOL_PAGE_SIZE as u64);
+    if limit > MAX_MEMPOOL_PAGE_SIZE as u64 {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+    let offset = usize::try_from(offset.unwrap_or(0)).unwrap_or(usize::MAX);
+
+    let stats = MempoolStats {
+        tx_count: snapshot.len() as u64,
+        distinct_senders: snapshot.iter().map(|tx| tx.sender_address).collect::<HashSet<_>>().len() as u64,
+        oldest_arrived_at: snapshot.iter().map(|tx| tx.arrived_at).min().map(unix_millis),
+    };
+    let transactions = snapshot.into_iter().skip(offset).take(limit as usize).map(Into::into).collect();
+
+    Ok(MempoolTransactionsPage { transactions, stats })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use blockifier::transaction::transaction_types::TransactionType;
+    use starknet_core::types::Felt;
+
+    use super::*;
+    use crate::types::{MempoolTransactionSummary, MempoolTxType};
+
+    fn summary(n: u64, sender: u64, tx_type: TransactionType) -> MempoolTxSummary {
+        MempoolTxSummary {
+            tx_hash: Felt::from(n),
+            sender_address: Felt::from(sender),
+            nonce: Felt::from(n),
+            tx_type,
+            arrived_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1000 + n),
+            class_hash: (tx_type == TransactionType::Declare).then_some(Felt::from(0x1234)),
+        }
+    }
+
+    fn snapshot() -> Vec<MempoolTxSummary> {
+        vec![
+            summary(1, 10, TransactionType::Declare),
+            summary(2, 20, TransactionType::DeployAccount),
+            summary(3, 10, TransactionType::InvokeFunction),
+        ]
+    }
+
+    #[test]
+    fn mempool_page_summaries() {
+        let page = mempool_page(snapshot(), None, None).unwrap();
+
+        assert_eq!(page.stats, MempoolStats { tx_count: 3, distinct_senders: 2, oldest_arrived_at: Some(1001) });
+        assert_eq!(
+            page.transactions,
+            vec![
+                MempoolTransactionSummary {
+                    transaction_hash: Felt::from(1),
+                    sender_address: Felt::from(10),
+                    nonce: Felt::from(1),
+                    r#type: MempoolTxType::Declare,
+                    arrived_at: 1001,
+                    class_hash: Some(Felt::from(0x1234)),
+                },
+                MempoolTransactionSummary {
+                    transaction_hash: Felt::from(2),
+                    sender_address: Felt::from(20),
+                    nonce: Felt::from(2),
+                    r#type: MempoolTxType::DeployAccount,
+                    arrived_at: 1002,
+                    class_hash: None,
+                },
+                MempoolTransactionSummary {
+                    transaction_hash: Felt::from(3),
+                    sender_address: Felt::from(10),
+                    nonce: Felt::from(3),
+                    r#type: MempoolTxType::Invoke,
+                    arrived_at: 1003,
+                    class_hash: None,
+                },
+            ]
+        );
+
+        let json = serde_json::to_value(&page.transactions[0]).unwrap();
+        assert_eq!(json["type"], "DECLARE");
+        assert_eq!(json["class_hash"], "0x1234");
+        assert!(serde_json::to_value(&page.transactions[1]).unwrap().get("class_hash").is_none());
+    }
+
+    #[test]
+    fn mempool_page_pagination() {
+        let page = mempool_page(snapshot(), Some(1), Some(1)).unwrap();
+        assert_eq!(page.transactions.len(), 1);
+        assert_eq!(page.transactions[0].transaction_hash, Felt::from(2));
+        // Stats always cover the whole mempool.
+        assert_eq!(page.stats.tx_count, 3);
+
+        let page = mempool_page(snapshot(), Some(3), None).unwrap();
+        assert!(page.transactions.is_empty());
+        let page = mempool_page(snapshot(), Some(u64::MAX), None).unwrap();
+        assert!(page.transactions.is_empty());
+
+        assert!(matches!(
+            mempool_page(snapshot(), None, Some(MAX_MEMPOOL_PAGE_SIZE as u64 + 1)),
+            Err(StarknetRpcApiError::PageSizeTooBig)
+        ));
+    }
+
+    #[test]
+    fn mempool_page_empty() {
+        let page = mempool_page(vec![], None, None).unwrap();
+        assert!(page.transactions.is_empty());
+        assert_eq!(page.stats, MempoolStats { tx_count: 0, distinct_senders: 0, oldest_arrived_at: None });
+    }
+}
@@ -0,0 +1,142 @@
+use starknet_types_core::felt::Felt;
+
+use crate::constants::MAX_NONCE_HISTORY_PAGE_SIZE;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::{NonceChange, NonceHistoryPage};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Get the blocks which changed the nonce of a contract, with the nonce they set. This is meant for finding out why the
+/// transactions of an account are stuck.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `contract_address` - The address of the contract
+/// * `from_block` - The first block of the range, included
+/// * `to_block` - The last block of the range, included
+/// * `continuation_token` - The token returned with the previous page, `null` for the first page.
+/// * `limit` - Maximum number of changes to return. Defaults to the maximum page size.
+///
+/// ### Returns
+///
+/// A page of the nonce changes in the confirmed blocks of the range, in block order, and the token of the next page.
+/// The nonce before `from_block` is returned by `starknet_getNonce`. Returns `PAGE_SIZE_TOO_BIG` if the limit exceeds
+/// the maximum page size, or `INVALID_CONTINUATION_TOKEN`.
+pub fn get_nonce_history(
+    starknet: &Starknet,
+    contract_address: Felt,
+    from_block: u64,
+    to_block: u64,
+    continuation_token: Option<String>,
+    limit: Option<u64>,
+) -> StarknetRpcResult<NonceHistoryPage> {
+    let limit = limit.unwrap_or(MAX_NONCE_HISTORY_PAGE_SIZE as u64);
+    if limit > MAX_NONCE_HISTORY_PAGE_SIZE as u64 {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+    // The token is the block of the last change of the previous page.
+    let start_after = continuation_token
+        .as_deref()
+        .map(str::parse::<u64>)
+        .transpose()
+        .map_err(|_| StarknetRpcApiError::InvalidContinuationToken)?;
+    let from_block = match start_after {
+        Some(block_n) => from_block.max(block_n.saturating_add(1)),
+        None => from_block,
+    };
+    if limit == 0 {
+        return Ok(NonceHistoryPage { changes: vec![], continuation_token });
+    }
+
+    // One more change tells whether there is a next page.
+    let mut changes = starknet
+        .backend
+        .get_contract_nonce_history(&contract_address, from_block..=to_block, limit as usize + 1)
+        .or_internal_server_error("Error getting contract nonce history")?;
+
+    let continuation_token = if changes.len() > limit as usize {
+        changes.pop();
+        changes.last().map(|(block_n, _)| block_n.to_string())
+    } else {
+        None
+    };
+    let changes = changes.into_iter().map(|(block_number, nonce)| NonceChange { block_number, nonce }).collect();
+    Ok(NonceHistoryPage { changes, continuation_token })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::{NonceUpdate, StateDiff};
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+
+    const ACCOUNT: Felt = Felt::from_hex_unchecked("0xacc");
+
+    fn changes(page: &NonceHistoryPage) -> Vec<(u64, Felt)> {
+        page.changes.iter().map(|change| (change.block_number, change.nonce)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_get_nonce_history() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let changed_at = [1u64, 4, 5, 9, 13, 14];
+        for block_number in 0..16 {
+            let info =
+                DeoxysBlockInfo::new(Header { block_number, ..Default::default() }, vec![], Felt::from(block_number));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+            let nonces = changed_at
+                .iter()
+                .position(|changed| *changed == block_number)
+                .map(|nonce| NonceUpdate { contract_address: ACCOUNT, nonce: Felt::from(nonce as u64 + 1) })
+                .into_iter()
+                .collect();
+            backend
+                .store_block(block.into(), StateDiff::new(vec![], vec![], vec![], vec![], vec![], nonces), vec![])
+                .unwrap();
+        }
+
+        let starknet = starknet_over(backend, None);
+        let expected: Vec<_> =
+            changed_at.iter().zip(1u64..).map(|(block_n, nonce)| (*block_n, Felt::from(nonce))).collect();
+
+        let page = get_nonce_history(&starknet, ACCOUNT, 0, 100, None, None).unwrap();
+        assert_eq!(changes(&page), expected);
+        assert_eq!(page.continuation_token, None);
+
+        // Starting in the middle of the history, both ends included.
+        let page = get_nonce_history(&starknet, ACCOUNT, 5, 13, None, None).unwrap();
+        assert_eq!(changes(&page), expected[2..5]);
+        let page = get_nonce_history(&starknet, ACCOUNT, 6, 8, None, None).unwrap();
+        assert_eq!(changes(&page), vec![]);
+
+        // Pages of 2 changes from block 2.
+        let mut token = None;
+        let mut pages = vec![];
+        loop {
+            let page = get_nonce_history(&starknet, ACCOUNT, 2, 15, token, Some(2)).unwrap();
+            pages.push(changes(&page));
+            token = page.continuation_token;
+            if token.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, vec![expected[1..3].to_vec(), expected[3..5].to_vec(), expected[5..].to_vec()]);
+
+        assert!(matches!(
+            get_nonce_history(&starknet, ACCOUNT, 0, 15, Some("0x1".into()), None),
+            Err(StarknetRpcApiError::InvalidContinuationToken)
+        ));
+        assert!(matches!(
+            get_nonce_history(&starknet, ACCOUNT, 0, 15, None, Some(MAX_NONCE_HISTORY_PAGE_SIZE as u64 + 1)),
+            Err(StarknetRpcApiError::PageSizeTooBig)
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::errors::StarknetRpcResult;
+use crate::types::SyncStallReason;
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Returns why the sync stopped importing blocks. While it is stopped on a mismatched state root, `starknet_syncing`
+/// reports the block it could not import as the highest block.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Returns
+///
+/// * `stall` - The block the sync stopped at and the reason, or `null` when the sync did not stop. The stall is
+///   cleared once the sync imports a block again, after the node is restarted, or once the node operator reverts the
+///   chain after a deep reorg.
+pub fn get_sync_stall(starknet: &Starknet) -> StarknetRpcResult<Option<SyncStallReason>> {
+    let stall = starknet.backend.get_sync_stall().or_internal_server_error("Error getting sync stall")?;
+    Ok(stall.map(Into::into))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::block_db::SyncStall;
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, Header};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{Felt, SyncStatusType};
+
+    use super::*;
+    use crate::methods::read::syncing::syncing;
+    use crate::test_utils::starknet_over;
+
+    #[tokio::test]
+    async fn test_get_sync_stall() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(Header::default(), vec![], Felt::ONE)),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+
+        let starknet = starknet_over(Arc::clone(&backend), None);
+
+        assert_eq!(get_sync_stall(&starknet).unwrap(), None);
+
+        let stall =
+            SyncStall::MismatchedStateRoot { block_n: 1, block_hash: Felt::TWO, expected: Felt::ONE, got: Felt::TWO };
+        backend.write_sync_stall(&stall).unwrap();
+        assert_eq!(
+            get_sync_stall(&starknet).unwrap(),
+            Some(SyncStallReason::MismatchedStateRoot {
+                block_number: 1,
+                block_hash: Felt::TWO,
+                expected_state_root: Felt::ONE,
+                computed_state_root: Felt::TWO,
+            })
+        );
+        let SyncStatusType::Syncing(status) = syncing(&starknet).await.unwrap() else { panic!("Not syncing") };
+        assert_eq!((status.current_block_num, status.highest_block_num), (0, 1));
+        assert_eq!(status.highest_block_hash, Felt::TWO);
+
+        backend.write_sync_stall(&SyncStall::DeepReorg { tip_block_n: 0, common_ancestor: None }).unwrap();
+        assert_eq!(
+            get_sync_stall(&starknet).unwrap(),
+            Some(SyncStallReason::DeepReorg { tip_block_number: 0, common_ancestor: None })
+        );
+        let SyncStatusType::Syncing(status) = syncing(&starknet).await.unwrap() else { panic!("Not syncing") };
+        assert_eq!((status.current_block_num, status.highest_block_num), (0, 0));
+
+        backend.clear_sync_stall().unwrap();
+        assert_eq!(get_sync_stall(&starknet).unwrap(), None);
+    }
+}
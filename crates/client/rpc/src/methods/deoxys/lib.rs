@@ -0,0 +1,109 @@
+use dc_db::compaction::CompactionSchedule;
+use jsonrpsee::core::RpcResult;
+use starknet_core::types::{BlockId, ContractClass, Felt};
+
+use super::compact_database::*;
+use super::compaction_schedule::*;
+use super::get_block_verification::*;
+use super::get_class_declaration_info::*;
+use super::get_class_hashes_at::*;
+use super::get_classes_batch::*;
+use super::get_contracts_by_class_hash::*;
+use super::get_deployed_contracts::*;
+use super::get_gas_price_history::*;
+use super::get_mempool_transactions::*;
+use super::get_nonce_history::*;
+use super::get_sync_stall::*;
+use super::list_classes::*;
+use super::preview_pending_block::*;
+use super::status::*;
+use crate::types::{
+    BlockVerificationStatus, ClassDeclarationInfo, ClassesPage, ContractsPage, DeployedContract, GasPriceHistory,
+    MempoolTransactionsPage, NodeStatus, NonceHistoryPage, PendingBlockPreview, SyncStallReason,
+};
+use crate::{DeoxysReadRpcApiServer, DeoxysRpcApiServer, Starknet};
+
+impl DeoxysReadRpcApiServer for Starknet {
+    fn get_class_hashes_at(&self, block_id: BlockId, contract_addresses: Vec<Felt>) -> RpcResult<Vec<Option<Felt>>> {
+        Ok(get_class_hashes_at(self, block_id, contract_addresses)?)
+    }
+
+    fn get_sync_stall(&self) -> RpcResult<Option<SyncStallReason>> {
+        Ok(get_sync_stall(self)?)
+    }
+
+    fn get_block_verification(&self, block_id: BlockId) -> RpcResult<BlockVerificationStatus> {
+        Ok(get_block_verification(self, block_id)?)
+    }
+
+    fn get_gas_price_history(&self, n: u64) -> RpcResult<GasPriceHistory> {
+        Ok(get_gas_price_history(self, n)?)
+    }
+
+    fn get_nonce_history(
+        &self,
+        contract_address: Felt,
+        from_block: u64,
+        to_block: u64,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+    ) -> RpcResult<NonceHistoryPage> {
+        Ok(get_nonce_history(self, contract_address, from_block, to_block, continuation_token, limit)?)
+    }
+
+    fn get_class_declaration_info(&self, class_hash: Felt) -> RpcResult<ClassDeclarationInfo> {
+        Ok(get_class_declaration_info(self, class_hash)?)
+    }
+
+    fn get_contracts_by_class_hash(
+        &self,
+        class_hash: Felt,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+    ) -> RpcResult<ContractsPage> {
+        Ok(get_contracts_by_class_hash(self, class_hash, continuation_token, limit)?)
+    }
+
+    fn get_deployed_contracts(&self, block_id: BlockId) -> RpcResult<Vec<DeployedContract>> {
+        Ok(get_deployed_contracts(self, block_id)?)
+    }
+}
+
+impl DeoxysRpcApiServer for Starknet {
+    fn get_mempool_transactions(&self, offset: Option<u64>, limit: Option<u64>) -> RpcResult<MempoolTransactionsPage> {
+        Ok(get_mempool_transactions(self, offset, limit)?)
+    }
+
+    fn preview_pending_block(&self) -> RpcResult<PendingBlockPreview> {
+        Ok(preview_pending_block(self)?)
+    }
+
+    fn get_compaction_schedule(&self) -> RpcResult<CompactionSchedule> {
+        Ok(get_compaction_schedule(self))
+    }
+
+    fn set_compaction_schedule(&self, schedule: CompactionSchedule) -> RpcResult<()> {
+        Ok(set_compaction_schedule(self, schedule)?)
+    }
+
+    fn compact_database(&self, columns: Option<Vec<String>>) -> RpcResult<Vec<String>> {
+        Ok(compact_database(self, columns)?)
+    }
+
+    fn list_classes(
+        &self,
+        continuation_token: Option<String>,
+        limit: Option<u64>,
+        declared_after_block: Option<u64>,
+    ) -> RpcResult<ClassesPage> {
+        Ok(list_classes(self, continuation_token, limit, declared_after_block)?)
+    }
+
+    fn get_classes_batch(&self, class_hashes: Vec<Felt>) -> RpcResult<Vec<Option<ContractClass>>> {
+        Ok(get_classes_batch(self, class_hashes)?)
+    }
+
+    fn status(&self) -> RpcResult<NodeStatus> {
+        Ok(status(self)?)
+    }
+}
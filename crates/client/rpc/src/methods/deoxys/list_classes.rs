@@ -0,0 +1,213 @@
+use dp_class::ClassInfo;
+use starknet_types_core::felt::Felt;
+
+use crate::constants::MAX_CLASSES_PAGE_SIZE;
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::{ClassesPage, DeclaredClass};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Lists the classes declared in the confirmed blocks, ordered by class hash. This is meant for tooling pulling every
+/// class known by the node, along with `deoxys_getClassesBatch`.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Arguments
+///
+/// * `continuation_token` - The token returned with the previous page, `null` for the first page.
+/// * `limit` - Maximum number of classes to return. Defaults to the maximum page size.
+/// * `declared_after_block` - Only list the classes declared after this block.
+///
+/// ### Returns
+///
+/// A page of class hashes with the block which declared them, and the token of the next page. Returns
+/// `PAGE_SIZE_TOO_BIG` if the limit exceeds the maximum page size, or `INVALID_CONTINUATION_TOKEN`.
+pub fn list_classes(
+    starknet: &Starknet,
+    continuation_token: Option<String>,
+    limit: Option<u64>,
+    declared_after_block: Option<u64>,
+) -> StarknetRpcResult<ClassesPage> {
+    let limit = limit.unwrap_or(MAX_CLASSES_PAGE_SIZE as u64);
+    if limit > MAX_CLASSES_PAGE_SIZE as u64 {
+        return Err(StarknetRpcApiError::PageSizeTooBig);
+    }
+    // The token is the hash of the last class of the previous page.
+    let start_after = continuation_token
+        .as_deref()
+        .map(Felt::from_hex)
+        .transpose()
+        .map_err(|_| StarknetRpcApiError::InvalidContinuationToken)?;
+    if limit == 0 {
+        return Ok(ClassesPage { classes: vec![], continuation_token });
+    }
+
+    let declared = |block_n: u64| declared_after_block.map_or(true, |after| block_n > after);
+    let mut classes = starknet
+        .backend
+        .iter_classes(start_after.as_ref())
+        .or_internal_server_error("Error iterating classes")?
+        .filter_map(|res| match res {
+            Ok((class_hash, ClassInfo { block_number: Some(declared_at_block), .. })) if declared(declared_at_block) => {
+                Some(Ok(DeclaredClass { class_hash, declared_at_block }))
+            }
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+        // One more class tells whether there is a next page.
+        .take(limit as usize + 1)
+        .collect::<Result<Vec<_>, _>>()
+        .or_internal_server_error("Error reading classes")?;
+
+    let continuation_token = if classes.len() > limit as usize {
+        classes.pop();
+        classes.last().map(|class| format!("{:#x}", class.class_hash))
+    } else {
+        None
+    };
+    Ok(ClassesPage { classes, continuation_token })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_class::{CompiledClass, ContractClass, ConvertedClass, EntryPointsByType, FlattenedSierraClass};
+    use dp_state_update::StateDiff;
+
+    use super::*;
+    use crate::constants::MAX_CLASSES_BATCH_SIZE;
+    use crate::methods::deoxys::get_classes_batch::get_classes_batch;
+    use crate::test_utils::starknet_over;
+
+    fn converted_class(class_hash: Felt, block_n: u64) -> ConvertedClass {
+        let contract_class = ContractClass::Sierra(FlattenedSierraClass {
+            sierra_program: vec![class_hash],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: String::new(),
+        });
+        ConvertedClass {
+            class_infos: (
+                class_hash,
+                ClassInfo { contract_class, compiled_class_hash: Felt::ONE, block_number: Some(block_n) },
+            ),
+            class_compiled: (class_hash, serde_json::from_str::<CompiledClass>(r#"{"Sierra":[]}"#).unwrap()),
+        }
+    }
+
+    /// Blocks 0 to 3 each declare 10 classes: block `n` declares the classes `0x100 * (n + 1) + i`.
+    async fn test_starknet() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        for block_n in 0..4u64 {
+            let classes: Vec<_> =
+                (0..10).map(|i| converted_class(Felt::from(0x100 * (block_n + 1) + i), block_n)).collect();
+            let header = Header { block_number: block_n, ..Default::default() };
+            let block = DeoxysMaybePendingBlock {
+                info: DeoxysBlockInfo::new(header, vec![], Felt::from(block_n)).into(),
+                inner: DeoxysBlockInner::new(vec![], vec![]),
+            };
+            backend.store_block(block, StateDiff::default(), classes).unwrap();
+        }
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    /// Lists all the pages, and returns the class hashes along with the size of every page.
+    fn list_all(starknet: &Starknet, limit: u64, declared_after_block: Option<u64>) -> (Vec<Felt>, Vec<usize>) {
+        let (mut class_hashes, mut page_sizes) = (vec![], vec![]);
+        let mut continuation_token = None;
+        loop {
+            let page = list_classes(starknet, continuation_token, Some(limit), declared_after_block).unwrap();
+            page_sizes.push(page.classes.len());
+            class_hashes.extend(page.classes.iter().map(|class| class.class_hash));
+            match page.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return (class_hashes, page_sizes),
+            }
+        }
+    }
+
+    fn class_hashes(blocks: std::ops::Range<u64>) -> Vec<Felt> {
+        blocks.flat_map(|block_n| (0..10).map(move |i| Felt::from(0x100 * (block_n + 1) + i))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_classes_pagination() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let page = list_classes(&starknet, None, None, None).unwrap();
+        assert_eq!(page.classes.len(), 40);
+        assert_eq!(page.classes[0], DeclaredClass { class_hash: Felt::from(0x100), declared_at_block: 0 });
+        assert_eq!(page.classes[39], DeclaredClass { class_hash: Felt::from(0x409), declared_at_block: 3 });
+        assert_eq!(page.continuation_token, None);
+
+        // Pages ending exactly on the last class, one before it and one after it.
+        assert_eq!(list_all(&starknet, 10, None), (class_hashes(0..4), vec![10, 10, 10, 10]));
+        assert_eq!(list_all(&starknet, 13, None), (class_hashes(0..4), vec![13, 13, 13, 1]));
+        assert_eq!(list_all(&starknet, 39, None), (class_hashes(0..4), vec![39, 1]));
+        assert_eq!(list_all(&starknet, 40, None), (class_hashes(0..4), vec![40]));
+        assert_eq!(list_all(&starknet, 1, None).1.len(), 40);
+
+        // A page resumes right after the class of the token, even if that class is not declared.
+        let page = list_classes(&starknet, Some("0x150".into()), Some(3), None).unwrap();
+        let listed: Vec<_> = page.classes.iter().map(|class| class.class_hash).collect();
+        assert_eq!(listed, [Felt::from(0x200), Felt::from(0x201), Felt::from(0x202)]);
+        assert_eq!(page.continuation_token.as_deref(), Some("0x202"));
+    }
+
+    #[tokio::test]
+    async fn test_list_classes_declared_after() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        assert_eq!(list_all(&starknet, 7, Some(1)).0, class_hashes(2..4));
+        assert_eq!(list_all(&starknet, 7, Some(0)).0, class_hashes(1..4));
+        assert_eq!(list_all(&starknet, 7, Some(3)), (vec![], vec![0]));
+    }
+
+    #[tokio::test]
+    async fn test_list_classes_errors() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let res = list_classes(&starknet, None, Some(MAX_CLASSES_PAGE_SIZE as u64 + 1), None);
+        assert!(matches!(res, Err(StarknetRpcApiError::PageSizeTooBig)));
+        let res = list_classes(&starknet, Some("not a class hash".into()), None, None);
+        assert!(matches!(res, Err(StarknetRpcApiError::InvalidContinuationToken)));
+    }
+
+    #[tokio::test]
+    async fn test_listed_classes_batch() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let page = list_classes(&starknet, None, Some(5), Some(2)).unwrap();
+        let mut class_hashes: Vec<_> = page.classes.iter().map(|class| class.class_hash).collect();
+        class_hashes.push(Felt::from(0xdead));
+        let classes = get_classes_batch(&starknet, class_hashes.clone()).unwrap();
+
+        assert_eq!(classes.len(), 6);
+        assert!(classes[5].is_none());
+        for (class_hash, class) in class_hashes.iter().zip(&classes[..5]) {
+            let starknet_core::types::ContractClass::Sierra(class) = class.as_ref().unwrap() else {
+                panic!("Expected a Sierra class")
+            };
+            assert_eq!(class.sierra_program, [*class_hash]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_classes_batch() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let classes =
+            get_classes_batch(&starknet, vec![Felt::from(0x305), Felt::from(0x1000), Felt::from(0x100)]).unwrap();
+        assert!(matches!(&classes[..], [Some(_), None, Some(_)]));
+        assert!(get_classes_batch(&starknet, vec![]).unwrap().is_empty());
+
+        let too_many = vec![Felt::ONE; MAX_CLASSES_BATCH_SIZE + 1];
+        assert!(matches!(get_classes_batch(&starknet, too_many), Err(StarknetRpcApiError::PageSizeTooBig)));
+    }
+}
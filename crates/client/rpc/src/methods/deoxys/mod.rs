@@ -0,0 +1,16 @@
+pub mod compact_database;
+pub mod compaction_schedule;
+pub mod get_block_verification;
+pub mod get_class_declaration_info;
+pub mod get_class_hashes_at;
+pub mod get_classes_batch;
+pub mod get_contracts_by_class_hash;
+pub mod get_deployed_contracts;
+pub mod get_gas_price_history;
+pub mod get_mempool_transactions;
+pub mod get_nonce_history;
+pub mod get_sync_stall;
+pub mod lib;
+pub mod list_classes;
+pub mod preview_pending_block;
+pub mod status;
@@ -0,0 +1,25 @@
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::types::PendingBlockPreview;
+use crate::Starknet;
+
+/// Returns what the block being produced would look like if it was closed now, for block production tooling.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Returns
+///
+/// The header of the pending block with its commitments computed on the spot, its transactions, the resources they
+/// use against the bouncer limits and the fees they pay. Neither the pending block nor the global state are modified:
+/// the state root and the block hash are left out, as computing them commits the state diff of the block.
+///
+/// Returns an unexpected error when the node does not produce blocks, or has not started producing them yet.
+pub fn preview_pending_block(starknet: &Starknet) -> StarknetRpcResult<PendingBlockPreview> {
+    let block_preview = starknet
+        .block_preview
+        .as_ref()
+        .ok_or_else(|| StarknetRpcApiError::ErrUnexpectedError { data: "This node does not produce blocks".into() })?;
+    let preview = block_preview.preview().ok_or_else(|| StarknetRpcApiError::ErrUnexpectedError {
+        data: "Block production has not started yet".into(),
+    })?;
+    Ok(preview.into())
+}
@@ -0,0 +1,99 @@
+use std::time::SystemTime;
+
+use dc_db::db_block_id::DbBlockId;
+
+use crate::errors::StarknetRpcResult;
+use crate::types::{unix_millis, NodeStatus};
+use crate::utils::ResultExt;
+use crate::Starknet;
+
+/// Returns an overview of the node: its version and chain, how far the sync and the L1 workers are, and whether its
+/// long-running workers still make progress.
+///
+/// This is not part of the Starknet specification.
+///
+/// ### Returns
+///
+/// * `status` - The state of the node. A worker is reported as not alive when it did not iterate for several of its
+///   poll intervals: it is stuck, or it stopped without taking the node down.
+pub fn status(starknet: &Starknet) -> StarknetRpcResult<NodeStatus> {
+    let backend = &starknet.backend;
+    let chain_head = backend.chain_head().get();
+    let pending_block_transaction_count = backend
+        .get_block_info(&DbBlockId::Pending)
+        .or_internal_server_error("Error getting pending block")?
+        .map(|info| info.tx_hashes().len() as u64);
+    let l1_last_polled_at = backend
+        .sync_status()
+        .l1_last_connected()
+        .and_then(|at| SystemTime::now().checked_sub(at.elapsed()))
+        .map(unix_millis);
+
+    Ok(NodeStatus {
+        version: starknet.node_version.clone(),
+        chain_id: starknet.chain_id(),
+        current_block_number: chain_head.latest_block_n(),
+        highest_block_number: chain_head.highest_known,
+        pending_block_transaction_count,
+        mempool_transaction_count: starknet.mempool.as_ref().map(|mempool| mempool.tx_count() as u64),
+        database_size: backend.column_sizes().into_iter().map(|(_, size)| size).sum(),
+        l1_last_confirmed_block_number: chain_head.l1_confirmed,
+        l1_last_polled_at,
+        workers: backend.worker_health().report().into_iter().map(Into::into).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use dc_db::testing::temp_database;
+    use dc_db::worker_health::{WORKER_ETH_STATE, WORKER_SYNC};
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::Felt;
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+    use crate::types::WorkerHealthStatus;
+
+    #[tokio::test]
+    async fn test_status() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(Header { block_number: 0, ..Default::default() }, vec![], Felt::ONE).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+
+        let starknet = starknet_over(Arc::clone(&backend), None).with_node_version("0.1.0-test".into());
+
+        backend.chain_head().update_highest_known_block(5);
+        backend.write_last_confirmed_block(0).unwrap();
+        // The sync worker is stuck, the L1 worker makes progress.
+        let health = backend.worker_health();
+        health.register_at(WORKER_SYNC, Duration::from_secs(1), Instant::now() - Duration::from_secs(600));
+        health.register(WORKER_ETH_STATE, Duration::from_secs(12));
+
+        let status = status(&starknet).unwrap();
+        assert_eq!(status.version.as_deref(), Some("0.1.0-test"));
+        assert_eq!(status.chain_id, starknet.chain_id());
+        assert_eq!((status.current_block_number, status.highest_block_number), (Some(0), Some(5)));
+        assert_eq!(status.pending_block_transaction_count, None);
+        assert_eq!(status.mempool_transaction_count, None);
+        assert_eq!((status.l1_last_confirmed_block_number, status.l1_last_polled_at), (Some(0), None));
+        assert_eq!(
+            status.workers,
+            [
+                WorkerHealthStatus { name: WORKER_ETH_STATE.into(), alive: true, secs_since_last_activity: 0 },
+                WorkerHealthStatus { name: WORKER_SYNC.into(), alive: false, secs_since_last_activity: 600 },
+            ]
+        );
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["workers"][1]["alive"], false);
+        assert!(json["database_size"].is_u64());
+    }
+}
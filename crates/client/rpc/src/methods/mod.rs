@@ -1,3 +1,4 @@
+pub mod deoxys;
 pub mod read;
 pub mod trace;
 pub mod write;
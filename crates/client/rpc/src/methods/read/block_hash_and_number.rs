@@ -1,8 +1,9 @@
 use dp_block::{BlockId, BlockTag};
 use starknet_core::types::BlockHashAndNumber;
 
-use crate::errors::StarknetRpcResult;
-use crate::{utils::OptionExt, Starknet};
+use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::{OptionExt, ResultExt};
+use crate::Starknet;
 
 /// Get the Most Recent Accepted Block Hash and Number
 ///
@@ -13,10 +14,72 @@ use crate::{utils::OptionExt, Starknet};
 /// ### Returns
 ///
 /// * `block_hash_and_number` - A tuple containing the latest block hash and number of the current
-///   network.
+///   network. The tip is read from the database, where it is updated along with the block: this is the block
+///   `getBlockWithTxHashes` returns for the `latest` tag. `NO_BLOCKS` before the genesis block is stored.
 pub fn block_hash_and_number(starknet: &Starknet) -> StarknetRpcResult<BlockHashAndNumber> {
-    let block_info = starknet.get_block_info(&BlockId::Tag(BlockTag::Latest))?;
+    let block_info = starknet
+        .backend
+        .get_block_info(&BlockId::Tag(BlockTag::Latest))
+        .or_internal_server_error("Error getting the latest block")?
+        .ok_or(StarknetRpcApiError::NoBlocks)?;
     let block_info = block_info.as_nonpending().ok_or_internal_server_error("Latest block is pending")?;
 
     Ok(BlockHashAndNumber { block_hash: block_info.block_hash, block_number: block_info.header.block_number })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{Felt, MaybePendingBlockWithTxHashes};
+
+    use super::*;
+    use crate::methods::read::get_block_with_tx_hashes::get_block_with_tx_hashes;
+    use crate::test_utils::starknet_over;
+    use crate::StarknetReadRpcApiServer;
+
+    fn store_block(starknet: &Starknet, block_number: u64) {
+        let header = Header { block_number, ..Default::default() };
+        let info = DeoxysBlockInfo::new(header, vec![], Felt::from(0x100 + block_number));
+        let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+        starknet.backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+    }
+
+    fn latest_with_tx_hashes(starknet: &Starknet) -> BlockHashAndNumber {
+        let latest = starknet_core::types::BlockId::Tag(starknet_core::types::BlockTag::Latest);
+        match get_block_with_tx_hashes(starknet, latest).unwrap() {
+            MaybePendingBlockWithTxHashes::Block(block) => {
+                BlockHashAndNumber { block_hash: block.block_hash, block_number: block.block_number }
+            }
+            MaybePendingBlockWithTxHashes::PendingBlock(_) => panic!("Latest block is pending"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_and_number_follow_the_tip() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let starknet = starknet_over(backend, None);
+
+        // Before the genesis block.
+        assert!(matches!(block_hash_and_number(&starknet), Err(StarknetRpcApiError::NoBlocks)));
+        assert!(matches!(starknet.current_block_number(), Err(StarknetRpcApiError::NoBlocks)));
+
+        store_block(&starknet, 0);
+        let tip = BlockHashAndNumber { block_hash: Felt::from(0x100), block_number: 0 };
+        assert_eq!(block_hash_and_number(&starknet).unwrap(), tip);
+        assert_eq!(starknet.current_block_number().unwrap(), 0);
+        assert_eq!(latest_with_tx_hashes(&starknet), tip);
+
+        // The tip moves between two calls.
+        store_block(&starknet, 1);
+        let tip = BlockHashAndNumber { block_hash: Felt::from(0x101), block_number: 1 };
+        assert_eq!(block_hash_and_number(&starknet).unwrap(), tip);
+        assert_eq!(StarknetReadRpcApiServer::block_number(&starknet).unwrap(), 1);
+        assert_eq!(StarknetReadRpcApiServer::block_hash_and_number(&starknet).unwrap(), tip);
+        assert_eq!(latest_with_tx_hashes(&starknet), tip);
+    }
+}
@@ -1,11 +1,10 @@
+use crate::call_cache::CallKey;
 use crate::errors::StarknetRpcApiError;
 use crate::errors::StarknetRpcResult;
 use crate::methods::trace::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW;
 use crate::Starknet;
-use dc_exec::ExecutionContext;
 use starknet_core::types::Felt;
 use starknet_core::types::{BlockId, FunctionCall};
-use std::sync::Arc;
 
 /// Call a Function in a Contract Without Creating a Transaction
 ///
@@ -19,7 +18,8 @@ use std::sync::Arc;
 /// ### Returns
 ///
 /// * `result` - The function's return value, as defined in the Cairo output. This is an array of
-///   field elements (`Felt`).
+///   field elements (`Felt`). The results of the calls on closed blocks may come from the
+///   [`crate::call_cache::CallCache`] of the node, the other calls are executed on the compute pool of the RPC.
 ///
 /// ### Errors
 ///
@@ -27,17 +27,114 @@ use std::sync::Arc;
 /// * `CONTRACT_NOT_FOUND` - If the specified contract address does not exist.
 /// * `CONTRACT_ERROR` - If there is an error with the contract or the function call.
 /// * `BLOCK_NOT_FOUND` - If the specified block does not exist in the blockchain.
-pub fn call(starknet: &Starknet, request: FunctionCall, block_id: BlockId) -> StarknetRpcResult<Vec<Felt>> {
+pub async fn call(starknet: &Starknet, request: FunctionCall, block_id: BlockId) -> StarknetRpcResult<Vec<Felt>> {
     let block_info = starknet.get_block_info(&block_id)?;
 
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block_info)?;
-
     if block_info.protocol_version() < &FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW {
         return Err(StarknetRpcApiError::UnsupportedTxnVersion);
     }
 
     let FunctionCall { contract_address, entry_point_selector, calldata } = request;
-    let results = exec_context.call_contract(&contract_address, &entry_point_selector, &calldata)?;
+    let key = block_info
+        .as_nonpending()
+        .map(|block| CallKey::new(block.block_hash, contract_address, entry_point_selector, &calldata));
+    let execute = async {
+        let exec_context = starknet.execution_context(&block_info)?;
+        let result = starknet
+            .run_execution(move || exec_context.call_contract(&contract_address, &entry_point_selector, &calldata))
+            .await?;
+        Ok::<_, StarknetRpcApiError>(result)
+    };
+
+    match (&starknet.call_cache, key) {
+        (Some(cache), Some(key)) => cache.get_or_call(key, execute).await,
+        _ => execute.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use dc_db::testing::temp_database;
+    use dc_exec::{CallFrame, CallStackEntry};
+    use dc_mempool::genesis::GenesisBuilder;
+    use dc_mempool::testing::{
+        store_test_genesis, test_contract_class, MockL1DataProvider, TEST_ACCOUNT_ADDRESS, TEST_CONTRACT_CLASS_HASH,
+    };
+    use dp_utils::clock::SystemClock;
+    use jsonrpsee::rpc_params;
+    use starknet_core::types::BlockTag;
+    use starknet_core::utils::get_selector_from_name;
+
+    use super::*;
+    use crate::call_cache::{CallCache, CallCacheConfig, CallCacheStats};
+    use crate::test_utils::starknet_over;
+    use crate::StarknetReadRpcApiServer;
 
-    Ok(results)
+    #[tokio::test]
+    async fn test_calls_on_closed_blocks_are_cached() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_test_genesis(&backend);
+        let config = CallCacheConfig { capacity: 16, ttl: Duration::from_secs(60) };
+        let cache = CallCache::new(config, backend.subscribe_latest_header());
+        let starknet = starknet_over(backend, None).with_call_cache(cache);
+        let rpc = StarknetReadRpcApiServer::into_rpc(starknet.clone());
+
+        // The test account accepts every class, its validation function returns nothing.
+        let request = FunctionCall {
+            contract_address: TEST_ACCOUNT_ADDRESS,
+            entry_point_selector: get_selector_from_name("__validate_declare__").unwrap(),
+            calldata: vec![Felt::ONE],
+        };
+        for block_id in [BlockId::Tag(BlockTag::Latest), BlockId::Number(0)] {
+            let result: Vec<Felt> = rpc.call("starknet_call", rpc_params![request.clone(), block_id]).await.unwrap();
+            assert_eq!(result, Vec::<Felt>::new());
+        }
+        let stats = || starknet.call_cache.as_ref().unwrap().stats();
+        assert_eq!(stats(), CallCacheStats { hits: 1, misses: 1 });
+
+        // Errors are not cached.
+        let unknown = FunctionCall { contract_address: Felt::from(0xdead), ..request };
+        for _ in 0..2 {
+            assert!(call(&starknet, unknown.clone(), BlockId::Number(0)).await.is_err());
+        }
+        assert_eq!(stats(), CallCacheStats { hits: 1, misses: 3 });
+    }
+
+    /// The call stack is read from a real execution, in which a contract calls another contract that fails.
+    #[tokio::test]
+    async fn test_failed_nested_call_reports_its_call_stack() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let (caller, callee) = (Felt::from(0xca11), Felt::from(0xca1e));
+        GenesisBuilder::new()
+            .declare(test_contract_class())
+            .deploy(caller, TEST_CONTRACT_CLASS_HASH)
+            .deploy(callee, TEST_CONTRACT_CLASS_HASH)
+            .store(&backend, &MockL1DataProvider, &SystemClock)
+            .unwrap();
+        let starknet = starknet_over(backend, None);
+
+        let test_call_contract = get_selector_from_name("test_call_contract").unwrap();
+        let foo = get_selector_from_name("foo").unwrap();
+        let request = FunctionCall {
+            contract_address: caller,
+            entry_point_selector: test_call_contract,
+            // The called contract, its entry point, which fails an assertion, and its empty calldata.
+            calldata: vec![callee, foo, Felt::ZERO],
+        };
+        let Err(StarknetRpcApiError::ContractError { data }) = call(&starknet, request, BlockId::Number(0)).await
+        else {
+            panic!("Expected a contract error");
+        };
+
+        let frame = |contract_address, selector| {
+            CallStackEntry::Call(CallFrame { contract_address, class_hash: TEST_CONTRACT_CLASS_HASH, selector })
+        };
+        assert_eq!(data.call_stack, vec![frame(caller, Some(test_call_contract)), frame(callee, Some(foo))]);
+        assert!(data.revert_error.contains("ASSERT_EQ"), "{}", data.revert_error);
+    }
 }
@@ -1,11 +1,8 @@
 use crate::errors::StarknetRpcResult;
-use crate::utils::ResultExt;
 use crate::Starknet;
 use crate::{errors::StarknetRpcApiError, methods::trace::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW};
-use dc_exec::ExecutionContext;
 use dp_transactions::broadcasted_to_blockifier;
 use starknet_core::types::{BlockId, BroadcastedTransaction, FeeEstimate, SimulationFlagForEstimateFee};
-use std::sync::Arc;
 
 /// Estimate the fee associated with transaction
 ///
@@ -29,17 +26,26 @@ pub async fn estimate_fee(
         return Err(StarknetRpcApiError::UnsupportedTxnVersion);
     }
 
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block_info)?;
+    let exec_context = starknet.execution_context(&block_info)?;
 
     let transactions = request
         .into_iter()
-        .map(|tx| broadcasted_to_blockifier(tx, starknet.chain_id(), block_info.block_n()).map(|(tx, _)| tx))
-        .collect::<Result<Vec<_>, _>>()
-        .or_internal_server_error("Failed to convert BroadcastedTransaction to AccountTransaction")?;
+        .enumerate()
+        .map(|(tx_index, tx)| {
+            broadcasted_to_blockifier(tx, starknet.chain_id(), block_info.block_n())
+                .map(|(tx, _)| tx)
+                .map_err(|err| StarknetRpcApiError::from_broadcasted_conversion(tx_index, err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     let validate = !simulation_flags.contains(&SimulationFlagForEstimateFee::SkipValidate);
 
-    let execution_results = exec_context.re_execute_transactions([], transactions, validate, true)?;
+    let (exec_context, execution_results) = starknet
+        .run_execution(move || {
+            let results = exec_context.re_execute_transactions([], transactions, validate, true);
+            results.map(|results| (exec_context, results))
+        })
+        .await?;
 
     let fee_estimates =
         execution_results.iter().map(|result| exec_context.execution_result_to_fee_estimate(result)).collect();
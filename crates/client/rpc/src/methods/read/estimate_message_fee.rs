@@ -3,12 +3,10 @@ use crate::errors::StarknetRpcResult;
 use crate::methods::trace::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW;
 use crate::utils::OptionExt;
 use crate::Starknet;
-use dc_exec::ExecutionContext;
-use dp_transactions::L1HandlerTransaction;
+use dp_transactions::{L1HandlerTransaction, TransactionApiError};
 use starknet_api::transaction::{Fee, TransactionHash};
 use starknet_core::types::{BlockId, FeeEstimate, MsgFromL1};
 use starknet_types_core::felt::Felt;
-use std::sync::Arc;
 
 /// Estimate the L2 fee of a message sent on L1
 ///
@@ -37,11 +35,18 @@ pub async fn estimate_message_fee(
         return Err(StarknetRpcApiError::UnsupportedTxnVersion);
     }
 
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block_info)?;
+    let exec_context = starknet.execution_context(&block_info)?;
 
-    let transaction = convert_message_into_transaction(message, starknet.chain_id());
-    let execution_result = exec_context
-        .re_execute_transactions([], [transaction], false, true)?
+    // An address out of the contract address range cannot hold a contract.
+    let transaction = convert_message_into_transaction(message, starknet.chain_id())
+        .map_err(|_| StarknetRpcApiError::ContractNotFound)?;
+    let (exec_context, mut execution_results) = starknet
+        .run_execution(move || {
+            let results = exec_context.re_execute_transactions([], [transaction], false, true);
+            results.map(|results| (exec_context, results))
+        })
+        .await?;
+    let execution_result = execution_results
         .pop()
         .ok_or_internal_server_error("Failed to convert BroadcastedTransaction to AccountTransaction")?;
 
@@ -53,15 +58,15 @@ pub async fn estimate_message_fee(
 pub fn convert_message_into_transaction(
     message: MsgFromL1,
     chain_id: Felt,
-) -> blockifier::transaction::transaction_execution::Transaction {
+) -> Result<blockifier::transaction::transaction_execution::Transaction, TransactionApiError> {
     let l1_handler: L1HandlerTransaction = message.into();
     let tx_hash = l1_handler.compute_hash(chain_id, false, false);
-    let tx: starknet_api::transaction::L1HandlerTransaction = (&l1_handler).try_into().unwrap();
+    let tx: starknet_api::transaction::L1HandlerTransaction = (&l1_handler).try_into()?;
 
     let tx = blockifier::transaction::transactions::L1HandlerTransaction {
         tx,
         tx_hash: TransactionHash(tx_hash),
         paid_fee_on_l1: Fee(1),
     };
-    blockifier::transaction::transaction_execution::Transaction::L1HandlerTransaction(tx)
+    Ok(blockifier::transaction::transaction_execution::Transaction::L1HandlerTransaction(tx))
 }
@@ -12,14 +12,15 @@ use crate::{errors::StarknetRpcResult, Starknet};
 ///
 /// ### Returns
 ///
-/// * `transaction_count` - The number of transactions in the specified block.
+/// * `transaction_count` - The number of transactions in the specified block. The pending tag counts the transactions
+///   of the pending block so far, or none when the node has no pending block.
 ///
 /// ### Errors
 ///
 /// This function may return a `BLOCK_NOT_FOUND` error if the specified block does not exist in
 /// the blockchain.
 pub fn get_block_transaction_count(starknet: &Starknet, block_id: BlockId) -> StarknetRpcResult<u128> {
-    let block = starknet.get_block_info(&block_id)?;
+    let block = starknet.get_block_info_or_empty_pending(&block_id)?;
 
     let tx_count = match block {
         DeoxysMaybePendingBlockInfo::Pending(block) => block.tx_hashes.len(),
@@ -28,3 +29,60 @@ pub fn get_block_transaction_count(starknet: &Starknet, block_id: BlockId) -> St
 
     Ok(tx_count as _)
 }
+
+#[cfg(test)]
+mod tests {
+    use jsonrpsee::{rpc_params, RpcModule};
+    use starknet_core::types::{BlockTag, Felt};
+
+    use super::*;
+    use crate::errors::StarknetRpcApiError;
+    use crate::methods::read::get_block_with_receipts::tests::{store_pending_block, test_starknet, PENDING_TX_HASHES};
+    use crate::StarknetReadRpcApiServer;
+
+    /// Blocks 0 to 2 have one transaction each, the hash of block `n` is `n + 1`.
+    async fn test_rpc() -> (tempfile::TempDir, Starknet, RpcModule<Starknet>) {
+        let (temp_dir, starknet) = test_starknet().await;
+        let rpc = StarknetReadRpcApiServer::into_rpc(starknet.clone());
+        (temp_dir, starknet, rpc)
+    }
+
+    async fn transaction_count(rpc: &RpcModule<Starknet>, block_id: BlockId) -> u128 {
+        rpc.call("starknet_getBlockTransactionCount", rpc_params![block_id]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transaction_count_by_block_id() {
+        let (_temp_dir, starknet, rpc) = test_rpc().await;
+
+        assert_eq!(transaction_count(&rpc, BlockId::Number(0)).await, 1);
+        assert_eq!(transaction_count(&rpc, BlockId::Hash(Felt::from(2))).await, 1);
+        assert_eq!(transaction_count(&rpc, BlockId::Tag(BlockTag::Latest)).await, 1);
+        // No pending block yet.
+        assert_eq!(transaction_count(&rpc, BlockId::Tag(BlockTag::Pending)).await, 0);
+
+        for block_id in [BlockId::Number(3), BlockId::Hash(Felt::from(4))] {
+            assert!(matches!(
+                get_block_transaction_count(&starknet, block_id),
+                Err(StarknetRpcApiError::BlockNotFound)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_count_of_pending_block() {
+        let (_temp_dir, starknet, rpc) = test_rpc().await;
+        store_pending_block(&starknet);
+
+        assert_eq!(transaction_count(&rpc, BlockId::Tag(BlockTag::Pending)).await, PENDING_TX_HASHES.len() as u128);
+        assert_eq!(transaction_count(&rpc, BlockId::Tag(BlockTag::Latest)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_spec_version() {
+        let (_temp_dir, _starknet, rpc) = test_rpc().await;
+        let spec_version: String = rpc.call("starknet_specVersion", rpc_params![]).await.unwrap();
+        assert_eq!(spec_version, crate::constants::SPEC_VERSION);
+        assert_eq!(spec_version, "0.7.1");
+    }
+}
@@ -1,31 +1,34 @@
-use dp_block::DeoxysMaybePendingBlockInfo;
+use dp_block::{DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo};
 use starknet_core::types::{
-    BlockId, BlockStatus, BlockWithReceipts, MaybePendingBlockWithReceipts, PendingBlockWithReceipts,
-    TransactionFinalityStatus, TransactionWithReceipt,
+    BlockId, BlockWithReceipts, MaybePendingBlockWithReceipts, PendingBlockWithReceipts, TransactionWithReceipt,
 };
 
 use crate::errors::StarknetRpcResult;
+use crate::utils::receipt::receipt_to_rpc;
 use crate::Starknet;
 
 pub fn get_block_with_receipts(
     starknet: &Starknet,
     block_id: BlockId,
 ) -> StarknetRpcResult<MaybePendingBlockWithReceipts> {
-    let block = starknet.get_block(&block_id)?;
+    let block = starknet.get_block_or_empty_pending(&block_id)?;
+    block_with_receipts(starknet, block)
+}
 
+/// Converts a block loaded by [`get_block_with_receipts`].
+pub(crate) fn block_with_receipts(
+    starknet: &Starknet,
+    block: DeoxysMaybePendingBlock,
+) -> StarknetRpcResult<MaybePendingBlockWithReceipts> {
     let transactions_core = Iterator::zip(block.inner.transactions.iter(), block.info.tx_hashes())
         .map(|(tx, hash)| tx.clone().to_core(*hash));
 
-    let is_on_l1 = if let Some(block_n) = block.info.block_n() {
-        block_n <= starknet.get_l1_last_confirmed_block()?
-    } else {
-        false
-    };
+    let l1_finality = starknet.l1_finality()?;
+    let finality_status = l1_finality.finality_status(block.info.block_n());
 
-    let finality_status =
-        if is_on_l1 { TransactionFinalityStatus::AcceptedOnL1 } else { TransactionFinalityStatus::AcceptedOnL2 };
-
-    let receipts = block.inner.receipts.iter().map(|receipt| receipt.clone().to_starknet_core(finality_status));
+    let protocol_version = *block.info.protocol_version();
+    let receipts = Iterator::zip(block.inner.receipts.iter(), block.inner.transactions.iter())
+        .map(|(receipt, tx)| receipt_to_rpc(receipt.clone(), tx, protocol_version, finality_status));
 
     let transactions_with_receipts = Iterator::zip(transactions_core, receipts)
         .map(|(transaction, receipt)| TransactionWithReceipt { transaction, receipt })
@@ -45,7 +48,7 @@ pub fn get_block_with_receipts(
             }))
         }
         DeoxysMaybePendingBlockInfo::NotPending(block) => {
-            let status = if is_on_l1 { BlockStatus::AcceptedOnL1 } else { BlockStatus::AcceptedOnL2 };
+            let status = l1_finality.block_status(Some(block.header.block_number));
             Ok(MaybePendingBlockWithReceipts::Block(BlockWithReceipts {
                 status,
                 block_hash: block.block_hash,
@@ -63,3 +66,270 @@ pub fn get_block_with_receipts(
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::header::{GasPrices, L1DataAvailabilityMode, PendingHeader};
+    use dp_block::{
+        DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysPendingBlock, DeoxysPendingBlockInfo, Header,
+        StarknetVersion,
+    };
+    use dp_receipt::{
+        DataAvailabilityResources, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt,
+        PriceUnit, TransactionReceipt,
+    };
+    use dp_state_update::StateDiff;
+    use dp_transactions::{
+        DataAvailabilityMode, InvokeTransaction, InvokeTransactionV1, InvokeTransactionV3, Transaction,
+    };
+    use serde_json::json;
+    use starknet_core::types::{BlockStatus, BlockTag, Felt};
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+
+    pub(crate) fn invoke_v1() -> Transaction {
+        Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+            sender_address: Felt::ONE,
+            calldata: vec![],
+            max_fee: Felt::from(100),
+            signature: vec![],
+            nonce: Felt::ZERO,
+        }))
+    }
+
+    fn invoke_v3() -> Transaction {
+        Transaction::Invoke(InvokeTransaction::V3(InvokeTransactionV3 {
+            sender_address: Felt::ONE,
+            calldata: vec![],
+            signature: vec![],
+            nonce: Felt::ONE,
+            resource_bounds: Default::default(),
+            tip: 0,
+            paymaster_data: vec![],
+            account_deployment_data: vec![],
+            nonce_data_availability_mode: DataAvailabilityMode::L1,
+            fee_data_availability_mode: DataAvailabilityMode::L1,
+        }))
+    }
+
+    /// A receipt as stored in the database. Its fee is in `WEI`, the unit served is the one of the transaction.
+    pub(crate) fn stored_receipt(tx_hash: Felt) -> TransactionReceipt {
+        TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            transaction_hash: tx_hash,
+            actual_fee: FeePayment { amount: Felt::from(2), unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events: vec![],
+            execution_resources: ExecutionResources {
+                steps: 9,
+                memory_holes: Some(10),
+                range_check_builtin_applications: Some(11),
+                pedersen_builtin_applications: Some(12),
+                poseidon_builtin_applications: None,
+                ec_op_builtin_applications: None,
+                ecdsa_builtin_applications: None,
+                bitwise_builtin_applications: Some(16),
+                keccak_builtin_applications: None,
+                segment_arena_builtin: None,
+                data_availability: DataAvailabilityResources { l1_gas: 19, l1_data_gas: 20 },
+                total_gas_consumed: DataAvailabilityResources { l1_gas: 21, l1_data_gas: 22 },
+            },
+            execution_result: ExecutionResult::Succeeded,
+        })
+    }
+
+    /// One block per era: block 0 is a 0.10.3 block, block 1 a 0.12.3 block and block 2 a 0.13.1 block with a v3
+    /// transaction.
+    pub(crate) async fn test_starknet() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        for (block_number, (protocol_version, tx)) in
+            [("0.10.3", invoke_v1()), ("0.12.3", invoke_v1()), ("0.13.1", invoke_v3())].into_iter().enumerate()
+        {
+            let tx_hash = Felt::from(0x100 + block_number as u64);
+            let header = Header {
+                block_number: block_number as u64,
+                protocol_version: protocol_version.parse().unwrap(),
+                transaction_count: 1,
+                ..Default::default()
+            };
+            let block = DeoxysMaybePendingBlock {
+                info: DeoxysBlockInfo::new(header, vec![tx_hash], Felt::from(block_number as u64 + 1)).into(),
+                inner: DeoxysBlockInner::new(vec![tx], vec![stored_receipt(tx_hash)]),
+            };
+            backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+        }
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    pub(crate) const PENDING_TX_HASHES: [Felt; 2] =
+        [Felt::from_hex_unchecked("0x200"), Felt::from_hex_unchecked("0x201")];
+
+    /// A pending block with two transactions on top of block 2, with a header that differs from the one of block 2.
+    pub(crate) fn pending_header() -> PendingHeader {
+        PendingHeader {
+            parent_block_hash: Felt::from(3),
+            sequencer_address: Felt::from(0x5e0),
+            block_timestamp: 1_700_000_042,
+            protocol_version: StarknetVersion::STARKNET_VERSION_0_13_2,
+            l1_gas_price: GasPrices {
+                eth_l1_gas_price: 7,
+                strk_l1_gas_price: 8,
+                eth_l1_data_gas_price: 9,
+                strk_l1_data_gas_price: 10,
+            },
+            l1_da_mode: L1DataAvailabilityMode::Blob,
+        }
+    }
+
+    pub(crate) fn store_pending_block(starknet: &Starknet) {
+        let pending = DeoxysPendingBlock::new(
+            DeoxysPendingBlockInfo::new(pending_header(), PENDING_TX_HASHES.to_vec()),
+            DeoxysBlockInner::new(
+                vec![invoke_v1(), invoke_v3()],
+                PENDING_TX_HASHES.iter().map(|tx_hash| stored_receipt(*tx_hash)).collect(),
+            ),
+        );
+        starknet.clone_backend().store_block(pending.into(), StateDiff::default(), vec![]).unwrap();
+    }
+
+    fn served_receipt(starknet: &Starknet, block_n: u64) -> serde_json::Value {
+        let MaybePendingBlockWithReceipts::Block(block) =
+            get_block_with_receipts(starknet, BlockId::Number(block_n)).unwrap()
+        else {
+            panic!("Block {block_n} is pending")
+        };
+        assert_eq!(block.transactions.len(), 1);
+        serde_json::to_value(&block.transactions[0].receipt).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_receipts_before_0_11() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let receipt = served_receipt(&starknet, 0);
+
+        // The resources are served as they were stored.
+        assert_eq!(receipt["actual_fee"], json!({ "amount": "0x2", "unit": "WEI" }));
+        assert_eq!(
+            receipt["execution_resources"],
+            json!({
+                "steps": 9,
+                "memory_holes": 10,
+                "range_check_builtin_applications": 11,
+                "pedersen_builtin_applications": 12,
+                "bitwise_builtin_applications": 16,
+                "data_availability": { "l1_gas": 0, "l1_data_gas": 0 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receipts_before_0_13_1() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let receipt = served_receipt(&starknet, 1);
+
+        assert_eq!(receipt["actual_fee"], json!({ "amount": "0x2", "unit": "WEI" }));
+        assert_eq!(
+            receipt["execution_resources"],
+            json!({
+                "steps": 9,
+                "memory_holes": 10,
+                "range_check_builtin_applications": 11,
+                "pedersen_builtin_applications": 12,
+                "bitwise_builtin_applications": 16,
+                "data_availability": { "l1_gas": 0, "l1_data_gas": 0 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receipts_of_v3_transactions() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let receipt = served_receipt(&starknet, 2);
+
+        assert_eq!(receipt["actual_fee"], json!({ "amount": "0x2", "unit": "FRI" }));
+        assert_eq!(
+            receipt["execution_resources"],
+            json!({
+                "steps": 9,
+                "memory_holes": 10,
+                "range_check_builtin_applications": 11,
+                "pedersen_builtin_applications": 12,
+                "bitwise_builtin_applications": 16,
+                "data_availability": { "l1_gas": 19, "l1_data_gas": 20 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_block_with_receipts() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        store_pending_block(&starknet);
+
+        let MaybePendingBlockWithReceipts::PendingBlock(block) =
+            get_block_with_receipts(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        let tx_hashes: Vec<_> = block.transactions.iter().map(|tx| *tx.transaction.transaction_hash()).collect();
+        assert_eq!(tx_hashes, PENDING_TX_HASHES);
+        assert!(block.transactions.iter().all(|tx| tx.receipt.transaction_hash() == tx.transaction.transaction_hash()));
+
+        let header = pending_header();
+        assert_eq!(block.parent_hash, header.parent_block_hash);
+        assert_eq!(block.timestamp, header.block_timestamp);
+        assert_eq!(block.sequencer_address, header.sequencer_address);
+        assert_eq!(block.l1_gas_price, header.l1_gas_price.l1_gas_price());
+        assert_eq!(block.l1_data_gas_price, header.l1_gas_price.l1_data_gas_price());
+        assert_eq!(block.starknet_version, "0.13.2");
+    }
+
+    #[tokio::test]
+    async fn test_pending_tag_without_pending_block() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        // An empty pending block on top of block 2.
+        let MaybePendingBlockWithReceipts::PendingBlock(block) =
+            get_block_with_receipts(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        assert!(block.transactions.is_empty());
+        assert_eq!(block.parent_hash, Felt::from(3));
+        assert_eq!(block.starknet_version, "0.13.1");
+    }
+
+    #[tokio::test]
+    async fn test_status_follows_l1_confirmation() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        // The status of the blocks, and the finality status of their transaction.
+        let statuses = |starknet: &Starknet| -> Vec<(BlockStatus, serde_json::Value)> {
+            (0..3)
+                .map(|block_n| {
+                    let MaybePendingBlockWithReceipts::Block(block) =
+                        get_block_with_receipts(starknet, BlockId::Number(block_n)).unwrap()
+                    else {
+                        panic!("Block {block_n} is pending")
+                    };
+                    (
+                        block.status,
+                        serde_json::to_value(&block.transactions[0].receipt).unwrap()["finality_status"].clone(),
+                    )
+                })
+                .collect()
+        };
+        let on_l1 = (BlockStatus::AcceptedOnL1, json!("ACCEPTED_ON_L1"));
+        let on_l2 = (BlockStatus::AcceptedOnL2, json!("ACCEPTED_ON_L2"));
+
+        // Not even the genesis block is accepted on L1 before the first state update.
+        assert_eq!(statuses(&starknet), [on_l2.clone(), on_l2.clone(), on_l2.clone()]);
+
+        starknet.clone_backend().write_last_confirmed_block(1).unwrap();
+        assert_eq!(statuses(&starknet), [on_l1.clone(), on_l1, on_l2]);
+    }
+}
@@ -2,7 +2,7 @@ use starknet_core::types::{BlockId, MaybePendingBlockWithTxHashes};
 
 use crate::errors::StarknetRpcResult;
 use dp_block::DeoxysMaybePendingBlockInfo;
-use starknet_core::types::{BlockStatus, BlockWithTxHashes, PendingBlockWithTxHashes};
+use starknet_core::types::{BlockWithTxHashes, PendingBlockWithTxHashes};
 
 use crate::Starknet;
 
@@ -17,14 +17,22 @@ use crate::Starknet;
 ///
 /// Returns block information with transaction hashes. This includes either a confirmed block or
 /// a pending block with transaction hashes, depending on the state of the requested block.
-/// In case the block is not found, returns a `StarknetRpcApiError` with `BlockNotFound`.
+/// In case the block is not found, returns a `StarknetRpcApiError` with `BlockNotFound`. The pending tag returns an
+/// empty pending block on top of the latest block when the node has no pending block.
 
 pub fn get_block_with_tx_hashes(
     starknet: &Starknet,
     block_id: BlockId,
 ) -> StarknetRpcResult<MaybePendingBlockWithTxHashes> {
-    let block = starknet.get_block_info(&block_id)?;
+    let block = starknet.get_block_info_or_empty_pending(&block_id)?;
+    block_with_tx_hashes(starknet, block)
+}
 
+/// Converts a block loaded by [`get_block_with_tx_hashes`].
+pub(crate) fn block_with_tx_hashes(
+    starknet: &Starknet,
+    block: DeoxysMaybePendingBlockInfo,
+) -> StarknetRpcResult<MaybePendingBlockWithTxHashes> {
     let block_txs_hashes = block.tx_hashes().to_vec();
 
     match block {
@@ -41,11 +49,7 @@ pub fn get_block_with_tx_hashes(
             }))
         }
         DeoxysMaybePendingBlockInfo::NotPending(block) => {
-            let status = if block.header.block_number <= starknet.get_l1_last_confirmed_block()? {
-                BlockStatus::AcceptedOnL1
-            } else {
-                BlockStatus::AcceptedOnL2
-            };
+            let status = starknet.l1_finality()?.block_status(Some(block.header.block_number));
             Ok(MaybePendingBlockWithTxHashes::Block(BlockWithTxHashes {
                 transactions: block_txs_hashes,
                 status,
@@ -63,3 +67,44 @@ pub fn get_block_with_tx_hashes(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet_core::types::{BlockTag, Felt};
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::{
+        pending_header, store_pending_block, test_starknet, PENDING_TX_HASHES,
+    };
+
+    #[tokio::test]
+    async fn test_pending_block() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        store_pending_block(&starknet);
+
+        let MaybePendingBlockWithTxHashes::PendingBlock(block) =
+            get_block_with_tx_hashes(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        assert_eq!(block.transactions, PENDING_TX_HASHES);
+        let header = pending_header();
+        assert_eq!(block.parent_hash, header.parent_block_hash);
+        assert_eq!(block.timestamp, header.block_timestamp);
+        assert_eq!(block.l1_gas_price, header.l1_gas_price.l1_gas_price());
+        assert_eq!(block.starknet_version, "0.13.2");
+    }
+
+    #[tokio::test]
+    async fn test_pending_tag_without_pending_block() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let MaybePendingBlockWithTxHashes::PendingBlock(block) =
+            get_block_with_tx_hashes(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        assert!(block.transactions.is_empty());
+        assert_eq!(block.parent_hash, Felt::from(3));
+    }
+}
@@ -1,8 +1,8 @@
 use starknet_core::types::{BlockId, MaybePendingBlockWithTxs};
 
-use dp_block::DeoxysMaybePendingBlockInfo;
+use dp_block::{DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo};
 use jsonrpsee::core::RpcResult;
-use starknet_core::types::{BlockStatus, BlockWithTxs, PendingBlockWithTxs};
+use starknet_core::types::{BlockWithTxs, PendingBlockWithTxs};
 
 use crate::Starknet;
 
@@ -23,10 +23,18 @@ use crate::Starknet;
 /// Returns detailed block information along with full transactions. Depending on the state of
 /// the block, this can include either a confirmed block or a pending block with its
 /// transactions. In case the specified block is not found, returns a `StarknetRpcApiError` with
-/// `BlockNotFound`.
+/// `BlockNotFound`. The pending tag returns an empty pending block on top of the latest block when the node has no
+/// pending block.
 pub fn get_block_with_txs(starknet: &Starknet, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxs> {
-    let block = starknet.get_block(&block_id)?;
+    let block = starknet.get_block_or_empty_pending(&block_id)?;
+    block_with_txs(starknet, block)
+}
 
+/// Converts a block loaded by [`get_block_with_txs`].
+pub(crate) fn block_with_txs(
+    starknet: &Starknet,
+    block: DeoxysMaybePendingBlock,
+) -> RpcResult<MaybePendingBlockWithTxs> {
     let transactions_core = Iterator::zip(block.inner.transactions.iter(), block.info.tx_hashes())
         .map(|(transaction, hash)| transaction.clone().to_core(*hash))
         .collect();
@@ -45,11 +53,7 @@ pub fn get_block_with_txs(starknet: &Starknet, block_id: BlockId) -> RpcResult<M
             }))
         }
         DeoxysMaybePendingBlockInfo::NotPending(block) => {
-            let status = if block.header.block_number <= starknet.get_l1_last_confirmed_block()? {
-                BlockStatus::AcceptedOnL1
-            } else {
-                BlockStatus::AcceptedOnL2
-            };
+            let status = starknet.l1_finality()?.block_status(Some(block.header.block_number));
             Ok(MaybePendingBlockWithTxs::Block(BlockWithTxs {
                 status,
                 block_hash: block.block_hash,
@@ -67,3 +71,44 @@ pub fn get_block_with_txs(starknet: &Starknet, block_id: BlockId) -> RpcResult<M
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet_core::types::{BlockTag, Felt};
+
+    use super::*;
+    use crate::methods::read::get_block_with_receipts::tests::{
+        pending_header, store_pending_block, test_starknet, PENDING_TX_HASHES,
+    };
+
+    #[tokio::test]
+    async fn test_pending_block() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        store_pending_block(&starknet);
+
+        let MaybePendingBlockWithTxs::PendingBlock(block) =
+            get_block_with_txs(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        assert_eq!(block.transactions.iter().map(|tx| *tx.transaction_hash()).collect::<Vec<_>>(), PENDING_TX_HASHES);
+        let header = pending_header();
+        assert_eq!(block.parent_hash, header.parent_block_hash);
+        assert_eq!(block.timestamp, header.block_timestamp);
+        assert_eq!(block.l1_gas_price, header.l1_gas_price.l1_gas_price());
+        assert_eq!(block.starknet_version, "0.13.2");
+    }
+
+    #[tokio::test]
+    async fn test_pending_tag_without_pending_block() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let MaybePendingBlockWithTxs::PendingBlock(block) =
+            get_block_with_txs(&starknet, BlockId::Tag(BlockTag::Pending)).unwrap()
+        else {
+            panic!("The pending tag returned a closed block")
+        };
+        assert!(block.transactions.is_empty());
+        assert_eq!(block.parent_hash, Felt::from(3));
+    }
+}
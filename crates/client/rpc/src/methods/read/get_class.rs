@@ -13,3 +13,115 @@ pub fn get_class(starknet: &Starknet, block_id: BlockId, class_hash: Felt) -> St
 
     Ok(class_data.contract_class.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_class::{ClassHash, ClassInfo, CompiledClass, ConvertedClass};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{
+        BlockTag, CompressedLegacyContractClass, EntryPointsByType, FlattenedSierraClass, LegacyContractEntryPoint,
+        LegacyEntryPointsByType, SierraEntryPoint,
+    };
+    use starknet_providers::{Provider, SequencerGatewayProvider};
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+
+    /// Stores the classes in block 0, under their class hashes.
+    async fn starknet_with_classes(classes: Vec<(Felt, ContractClass)>) -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let classes = classes
+            .into_iter()
+            .map(|(class_hash, contract_class)| {
+                let compiled = match contract_class {
+                    ContractClass::Sierra(_) => r#"{"Sierra":[]}"#,
+                    ContractClass::Legacy(_) => r#"{"Legacy":[]}"#,
+                };
+                let class_info = ClassInfo {
+                    contract_class: contract_class.into(),
+                    compiled_class_hash: Felt::ONE,
+                    block_number: Some(0),
+                };
+                ConvertedClass {
+                    class_infos: (class_hash, class_info),
+                    class_compiled: (class_hash, serde_json::from_str::<CompiledClass>(compiled).unwrap()),
+                }
+            })
+            .collect();
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(Header::default(), vec![], Felt::ZERO).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, StateDiff::default(), classes).unwrap();
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    #[tokio::test]
+    async fn test_get_class_returns_the_declared_class() {
+        let sierra = ContractClass::Sierra(FlattenedSierraClass {
+            sierra_program: vec![Felt::ONE, Felt::TWO, Felt::THREE],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType {
+                constructor: vec![SierraEntryPoint { selector: Felt::from(0x10), function_idx: 0 }],
+                external: vec![SierraEntryPoint { selector: Felt::from(0x11), function_idx: 1 }],
+                l1_handler: vec![SierraEntryPoint { selector: Felt::from(0x12), function_idx: 2 }],
+            },
+            abi: r#"[{"type":"function","name":"foo"}]"#.into(),
+        });
+        // The program of the legacy classes is served gzipped, as it was fetched.
+        let legacy = ContractClass::Legacy(CompressedLegacyContractClass {
+            program: vec![0x1f, 0x8b, 0x08, 0x00],
+            entry_points_by_type: LegacyEntryPointsByType {
+                constructor: vec![],
+                external: vec![LegacyContractEntryPoint { offset: 12, selector: Felt::from(0x20) }],
+                l1_handler: vec![],
+            },
+            abi: None,
+        });
+        let (_temp_dir, starknet) =
+            starknet_with_classes(vec![(Felt::from(0x51e), sierra.clone()), (Felt::from(0x1e9), legacy.clone())]).await;
+
+        let latest = BlockId::Tag(BlockTag::Latest);
+        assert_eq!(get_class(&starknet, latest, Felt::from(0x51e)).unwrap(), sierra);
+        assert_eq!(get_class(&starknet, latest, Felt::from(0x1e9)).unwrap(), legacy);
+        assert!(matches!(get_class(&starknet, latest, Felt::from(0x404)), Err(StarknetRpcApiError::ClassHashNotFound)));
+    }
+
+    /// Fetches the class from the mainnet feeder gateway, stores it and checks the class served by `starknet_getClass`
+    /// hashes to the requested class hash.
+    // TODO: record these two classes from the feeder gateway as test resources, so that the class hashes are checked
+    // offline and these tests no longer need to be ignored.
+    async fn assert_served_class_hash(class_hash: Felt) {
+        let provider = SequencerGatewayProvider::starknet_alpha_mainnet();
+        let class = provider.get_class(starknet_core::types::BlockId::Tag(BlockTag::Latest), class_hash).await.unwrap();
+        let (_temp_dir, starknet) = starknet_with_classes(vec![(class_hash, class)]).await;
+
+        let served = get_class(&starknet, BlockId::Tag(BlockTag::Latest), class_hash).unwrap();
+        assert_eq!(served.class_hash().unwrap(), class_hash);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_get_class_sierra_class_hash() {
+        assert_served_class_hash(Felt::from_hex_unchecked(
+            "0x816dd0297efc55dc1e7559020a3a825e81ef734b558f03c83325d4da7e6253",
+        ))
+        .await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_get_class_legacy_class_hash() {
+        assert_served_class_hash(Felt::from_hex_unchecked(
+            "0x25ec026985a3bf9d0cc1fe17326b245dfdc3ff89b8fde106542a3ea56c5a918",
+        ))
+        .await;
+    }
+}
@@ -1,9 +1,10 @@
-use dp_block::{DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo};
+use dp_block::{BlockN, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, EventIndex};
 use starknet_core::types::{BlockId, BlockTag, EmittedEvent, EventFilterWithPage, EventsPage, Felt};
 
 use crate::constants::{MAX_EVENTS_CHUNK_SIZE, MAX_EVENTS_KEYS};
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
 use crate::types::ContinuationToken;
+use crate::utils::{OptionExt, ResultExt};
 use crate::Starknet;
 
 /// Returns all events matching the given filter.
@@ -44,7 +45,7 @@ pub async fn get_events(starknet: &Starknet, filter: EventFilterWithPage) -> Sta
 
     let continuation_token = match filter.result_page_request.continuation_token {
         Some(token) => ContinuationToken::parse(token).map_err(|_| StarknetRpcApiError::InvalidContinuationToken)?,
-        None => ContinuationToken { block_n: from_block, event_n: 0 },
+        None => ContinuationToken { block_n: from_block, event_n: EventIndex(0) },
     };
 
     // Verify that the requested range is valid
@@ -54,8 +55,73 @@ pub async fn get_events(starknet: &Starknet, filter: EventFilterWithPage) -> Sta
 
     let from_block = continuation_token.block_n;
     let mut filtered_events: Vec<EmittedEvent> = Vec::new();
+    let mut current_block = from_block;
+
+    // The events of a single contract are read from the contract events index up to its tip, when it is enabled.
+    let contract_events_tip = starknet
+        .backend
+        .get_contract_events_tip()
+        .or_internal_server_error("Error getting the contract events index tip")?;
+    let indexed_to = contract_events_tip.filter(|tip| *tip >= from_block).map(|tip| tip.min(to_block));
+    if let (Some(address), Some(indexed_to), true) = (from_address, indexed_to, keys.is_empty() && chunk_size > 0) {
+        let skip = usize::from(continuation_token.event_n);
+        let events = starknet
+            .backend
+            .events_for_contract(&address, from_block, indexed_to, skip, chunk_size as usize)
+            .or_internal_server_error("Error reading the contract events index")?
+            .ok_or(StarknetRpcApiError::InvalidContinuationToken)?;
+
+        let mut block_hash = None;
+        for event in &events {
+            let hash = match block_hash {
+                Some((block_n, hash)) if block_n == event.block_n => hash,
+                _ => {
+                    let hash = starknet
+                        .backend
+                        .get_block_hash(&BlockId::Number(event.block_n))
+                        .or_internal_server_error("Error getting the block hash")?
+                        .ok_or_internal_server_error("Indexed event of a block that is not stored")?;
+                    block_hash = Some((event.block_n, hash));
+                    hash
+                }
+            };
+            filtered_events.push(EmittedEvent {
+                from_address: address,
+                keys: event.keys.clone(),
+                data: event.data.clone(),
+                block_hash: Some(hash),
+                block_number: Some(event.block_n),
+                transaction_hash: event.transaction_hash,
+            });
+        }
+
+        if let Some(last) = events.last().filter(|_| events.len() == chunk_size as usize) {
+            let in_last_block = events.iter().filter(|event| event.block_n == last.block_n).count();
+            let event_n = if last.block_n == from_block { skip + in_last_block } else { in_last_block };
+            let event_n = EventIndex::try_from(event_n).or_internal_server_error("Event index out of range")?;
+            let token = Some(ContinuationToken { block_n: last.block_n, event_n }.to_string());
+            return Ok(EventsPage { events: filtered_events, continuation_token: token });
+        }
+        current_block = indexed_to + 1;
+    }
+
+    // The blocks up to the index tip are skipped when the event index rules them out, the following ones are read.
+    let index_tip =
+        starknet.backend.get_event_index_tip().or_internal_server_error("Error getting the event index tip")?;
+
+    while current_block <= to_block {
+        // The continuation token may point inside its block, which is always read.
+        let indexed_to = index_tip.filter(|tip| *tip >= current_block).map(|tip| tip.min(to_block));
+        if let Some(indexed_to) = indexed_to.filter(|_| current_block != from_block) {
+            match next_candidate_block(starknet, current_block, indexed_to, from_address.as_ref(), &keys)? {
+                Some(block_n) => current_block = block_n,
+                None => {
+                    current_block = indexed_to + 1;
+                    continue;
+                }
+            }
+        }
 
-    for current_block in from_block..=to_block {
         let (_pending, block) = if current_block <= latest_block {
             (false, starknet.get_block(&BlockId::Number(current_block))?)
         } else {
@@ -67,14 +133,14 @@ pub async fn get_events(starknet: &Starknet, filter: EventFilterWithPage) -> Sta
             .filter(|event| event_match_filter(event, from_address, &keys))
             .collect();
 
-        if current_block == from_block && (block_filtered_events.len() as u64) < continuation_token.event_n {
+        if current_block == from_block && block_filtered_events.len() < usize::from(continuation_token.event_n) {
             return Err(StarknetRpcApiError::InvalidContinuationToken);
         }
 
         #[allow(clippy::iter_skip_zero)]
         let block_filtered_reduced_events: Vec<EmittedEvent> = block_filtered_events
             .into_iter()
-            .skip(if current_block == from_block { continuation_token.event_n as usize } else { 0 })
+            .skip(if current_block == from_block { usize::from(continuation_token.event_n) } else { 0 })
             .take(chunk_size as usize - filtered_events.len())
             .collect();
 
@@ -83,16 +149,51 @@ pub async fn get_events(starknet: &Starknet, filter: EventFilterWithPage) -> Sta
         filtered_events.extend(block_filtered_reduced_events);
 
         if filtered_events.len() == chunk_size as usize {
-            let event_n =
-                if current_block == from_block { continuation_token.event_n + chunk_size } else { num_events as u64 };
+            let event_n = if current_block == from_block {
+                usize::from(continuation_token.event_n) + chunk_size as usize
+            } else {
+                num_events
+            };
+            let event_n = EventIndex::try_from(event_n).or_internal_server_error("Event index out of range")?;
             let token = Some(ContinuationToken { block_n: current_block, event_n }.to_string());
 
             return Ok(EventsPage { events: filtered_events, continuation_token: token });
         }
+        current_block += 1;
     }
     Ok(EventsPage { events: filtered_events, continuation_token: None })
 }
 
+/// The first block of `from..=to`, which are indexed, that may have events matching the filter.
+fn next_candidate_block(
+    starknet: &Starknet,
+    from: u64,
+    to: u64,
+    address: Option<&Felt>,
+    keys: &[Vec<Felt>],
+) -> StarknetRpcResult<Option<u64>> {
+    let backend = &starknet.backend;
+    let mut block_n = from;
+    while block_n <= to {
+        if let Some(address) = address {
+            match backend
+                .next_block_with_events_from(address, block_n, to)
+                .or_internal_server_error("Error reading the event index")?
+            {
+                Some(next) => block_n = next,
+                None => return Ok(None),
+            }
+        }
+        let bloom = backend.get_event_bloom(block_n).or_internal_server_error("Error reading the event index")?;
+        // The index of the block may have been removed by a revert since the tip was read.
+        if bloom.map_or(true, |bloom| bloom.may_match(address, keys)) {
+            return Ok(Some(block_n));
+        }
+        block_n += 1;
+    }
+    Ok(None)
+}
+
 #[inline]
 fn event_match_filter(event: &EmittedEvent, address: Option<Felt>, keys: &[Vec<Felt>]) -> bool {
     let match_from_address = address.map_or(true, |addr| addr == event.from_address);
@@ -109,17 +210,20 @@ fn block_range(
     to_block: Option<BlockId>,
 ) -> StarknetRpcResult<(u64, u64, u64)> {
     let latest_block_n = starknet.get_block_n(&BlockId::Tag(BlockTag::Latest))?;
+    // The pending block comes right after the latest one.
+    let pending_block_n = latest_block_n.checked_next().ok_or(StarknetRpcApiError::BlockNotFound)?;
     let from_block_n = match from_block {
-        Some(BlockId::Tag(BlockTag::Pending)) => latest_block_n + 1,
+        Some(BlockId::Tag(BlockTag::Pending)) => pending_block_n,
         Some(block_id) => starknet.get_block_n(&block_id)?,
-        None => 0,
+        None => BlockN::GENESIS,
     };
     let to_block_n = match to_block {
-        Some(BlockId::Tag(BlockTag::Pending)) => latest_block_n + 1,
+        Some(BlockId::Tag(BlockTag::Pending)) => pending_block_n,
         Some(block_id) => starknet.get_block_n(&block_id)?,
         None => latest_block_n,
     };
-    Ok((from_block_n, to_block_n, latest_block_n))
+    // The events are served with the block numbers of the RPC.
+    Ok((from_block_n.into(), to_block_n.into(), latest_block_n.into()))
 }
 
 fn get_block_events(_starknet: &Starknet, block: &DeoxysMaybePendingBlock) -> Vec<EmittedEvent> {
@@ -144,3 +248,212 @@ fn get_block_events(_starknet: &Starknet, block: &DeoxysMaybePendingBlock) -> Ve
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_receipt::{Event, ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit, TransactionReceipt};
+    use dp_state_update::StateDiff;
+    use starknet_core::types::{EventFilter, ResultPageRequest};
+
+    use super::*;
+    use crate::test_utils::starknet_over;
+
+    /// Block `n` has an event from contract 1 on even blocks, and one from contract 2 with the key `n`.
+    async fn test_starknet(n_blocks: u64) -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        for block_n in 0..n_blocks {
+            let mut events = vec![Event { from_address: Felt::TWO, keys: vec![block_n.into()], data: vec![] }];
+            if block_n % 2 == 0 {
+                events.insert(0, Event { from_address: Felt::ONE, keys: vec![Felt::ZERO], data: vec![] });
+            }
+            let receipt = TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                transaction_hash: Felt::from(0x100 + block_n),
+                actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                messages_sent: vec![],
+                events,
+                execution_resources: Default::default(),
+                execution_result: ExecutionResult::Succeeded,
+            });
+            let header = Header { block_number: block_n, ..Default::default() };
+            let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_n));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![receipt]));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    /// The blocks and keys of all the pages of events matching the filter.
+    async fn all_events(starknet: &Starknet, address: Option<Felt>, keys: Vec<Vec<Felt>>) -> Vec<(u64, Felt)> {
+        let mut events = vec![];
+        let mut continuation_token = None;
+        loop {
+            let filter = EventFilterWithPage {
+                event_filter: EventFilter { from_block: None, to_block: None, address, keys: Some(keys.clone()) },
+                result_page_request: ResultPageRequest { continuation_token, chunk_size: 2 },
+            };
+            let page = get_events(starknet, filter).await.unwrap();
+            events.extend(page.events.iter().map(|event| (event.block_number.unwrap(), event.keys[0])));
+            match page.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return events,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_events_while_the_index_lags() {
+        let (_temp_dir, starknet) = test_starknet(6).await;
+        let queries = [
+            (Some(Felt::ONE), vec![]),
+            (Some(Felt::TWO), vec![vec![Felt::ONE, Felt::from(4)]]),
+            (None, vec![vec![Felt::from(3), Felt::ZERO]]),
+            (Some(Felt::THREE), vec![]),
+        ];
+        let expected = [
+            vec![(0, Felt::ZERO), (2, Felt::ZERO), (4, Felt::ZERO)],
+            vec![(1, Felt::ONE), (4, Felt::from(4))],
+            vec![(0, Felt::ZERO), (0, Felt::ZERO), (2, Felt::ZERO), (3, Felt::from(3)), (4, Felt::ZERO)],
+            vec![],
+        ];
+
+        // Nothing is indexed, every block is read.
+        for ((address, keys), expected) in queries.iter().zip(&expected) {
+            assert_eq!(&all_events(&starknet, *address, keys.clone()).await, expected);
+        }
+
+        // The indexed blocks are skipped when they have no matching events, the following ones are still read.
+        assert_eq!(starknet.backend.index_block_events(3).unwrap(), 3);
+        for ((address, keys), expected) in queries.iter().zip(&expected) {
+            assert_eq!(&all_events(&starknet, *address, keys.clone()).await, expected);
+        }
+
+        starknet.backend.index_block_events(u64::MAX).unwrap();
+        assert_eq!(starknet.backend.get_event_index_tip().unwrap(), Some(5));
+        for ((address, keys), expected) in queries.iter().zip(&expected) {
+            assert_eq!(&all_events(&starknet, *address, keys.clone()).await, expected);
+        }
+
+        // The events of the reverted blocks are gone from the index.
+        starknet.backend.revert_to(1, false).unwrap();
+        assert_eq!(starknet.backend.get_event_index_tip().unwrap(), Some(1));
+        assert_eq!(all_events(&starknet, Some(Felt::ONE), vec![]).await, [(0, Felt::ZERO)]);
+    }
+
+    /// Each block has three transactions with events of the contracts 1, 2 and 3, interleaved.
+    fn store_interleaved_blocks(backend: &dc_db::DeoxysBackend, n_blocks: u64) {
+        let event = |from_address: u64, block_n: u64, i: u64| Event {
+            from_address: from_address.into(),
+            keys: vec![],
+            data: vec![block_n.into(), i.into()],
+        };
+        for block_n in 0..n_blocks {
+            let txs_events = [
+                vec![event(1, block_n, 0), event(2, block_n, 1), event(1, block_n, 2)],
+                vec![event(2, block_n, 3)],
+                if block_n % 2 == 1 { vec![event(1, block_n, 4), event(3, block_n, 5)] } else { vec![] },
+            ];
+            let receipts = txs_events
+                .into_iter()
+                .enumerate()
+                .map(|(tx_index, events)| {
+                    TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                        transaction_hash: Felt::from(0x100 * block_n + tx_index as u64),
+                        actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                        messages_sent: vec![],
+                        events,
+                        execution_resources: Default::default(),
+                        execution_result: ExecutionResult::Succeeded,
+                    })
+                })
+                .collect();
+            let header = Header { block_number: block_n, ..Default::default() };
+            let info = DeoxysBlockInfo::new(header, vec![], Felt::from(0x1000 + block_n));
+            let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], receipts));
+            backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+        }
+    }
+
+    /// All the pages of events of `address`, for every page size.
+    async fn contract_events_pages(starknet: &Starknet, address: Felt) -> Vec<Vec<EmittedEvent>> {
+        let mut pages = vec![];
+        for chunk_size in 1..=4 {
+            let mut events = vec![];
+            let mut continuation_token = None;
+            loop {
+                let filter = EventFilterWithPage {
+                    event_filter: EventFilter { from_block: None, to_block: None, address: Some(address), keys: None },
+                    result_page_request: ResultPageRequest { continuation_token, chunk_size },
+                };
+                let page = get_events(starknet, filter).await.unwrap();
+                assert!(page.events.len() <= chunk_size as usize);
+                events.push(page.events);
+                match page.continuation_token {
+                    Some(token) => continuation_token = Some(token),
+                    None => break,
+                }
+            }
+            pages.push(events.concat());
+        }
+        pages
+    }
+
+    #[tokio::test]
+    async fn test_get_events_from_the_contract_events_index() {
+        let (_temp_dir, starknet) = test_starknet(0).await;
+        store_interleaved_blocks(&starknet.backend, 6);
+        let addresses = [Felt::ONE, Felt::TWO, Felt::THREE, Felt::from(4)];
+
+        // The blocks are read.
+        let mut scanned = vec![];
+        for address in addresses {
+            scanned.push(contract_events_pages(&starknet, address).await);
+        }
+        assert_eq!(scanned[0][0].len(), 6 * 2 + 3);
+        assert_eq!(scanned[0][0][2].data, [Felt::ONE, Felt::ZERO]);
+        assert_eq!(scanned[0][0][2].transaction_hash, Felt::from(0x100));
+        assert_eq!(scanned[0][0][2].block_hash, Some(Felt::from(0x1001)));
+        assert_eq!(scanned[3][0], []);
+
+        // The events of the indexed blocks are read from the index, the following blocks are read.
+        assert_eq!(starknet.backend.index_contract_events(4).unwrap(), 4);
+        for (address, scanned) in addresses.iter().zip(&scanned) {
+            assert_eq!(&contract_events_pages(&starknet, *address).await, scanned);
+        }
+        assert_eq!(starknet.backend.index_contract_events(u64::MAX).unwrap(), 2);
+        assert_eq!(starknet.backend.get_contract_events_tip().unwrap(), Some(5));
+        for (address, scanned) in addresses.iter().zip(&scanned) {
+            assert_eq!(&contract_events_pages(&starknet, *address).await, scanned);
+        }
+        let events = starknet.backend.events_for_contract(&Felt::ONE, 1, 1, 1, 10).unwrap().unwrap();
+        assert_eq!(
+            events.iter().map(|event| (event.block_n, event.tx_index.0, event.data[1])).collect::<Vec<_>>(),
+            [(1, 0, Felt::TWO), (1, 2, Felt::from(4))]
+        );
+
+        // A continuation token past the events of its block is refused, as when the blocks are read.
+        let token = ContinuationToken { block_n: 2, event_n: EventIndex(3) }.to_string();
+        let filter = EventFilterWithPage {
+            event_filter: EventFilter { from_block: None, to_block: None, address: Some(Felt::ONE), keys: None },
+            result_page_request: ResultPageRequest { continuation_token: Some(token), chunk_size: 2 },
+        };
+        assert!(matches!(get_events(&starknet, filter).await, Err(StarknetRpcApiError::InvalidContinuationToken)));
+
+        // The events of the reverted blocks are removed from the index.
+        starknet.backend.revert_to(2, false).unwrap();
+        assert_eq!(starknet.backend.get_contract_events_tip().unwrap(), Some(2));
+        assert_eq!(starknet.backend.events_for_contract(&Felt::ONE, 3, 5, 0, 10).unwrap(), Some(vec![]));
+        let expected: Vec<_> = scanned[0][0].iter().filter(|event| event.block_number <= Some(2)).cloned().collect();
+        assert_eq!(contract_events_pages(&starknet, Felt::ONE).await[0], expected);
+
+        starknet.backend.clear_contract_events().unwrap();
+        assert_eq!(starknet.backend.get_contract_events_tip().unwrap(), None);
+        assert_eq!(starknet.backend.events_for_contract(&Felt::ONE, 0, 2, 0, 10).unwrap(), Some(vec![]));
+    }
+}
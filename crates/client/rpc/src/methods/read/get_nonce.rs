@@ -1,4 +1,4 @@
-use starknet_core::types::BlockId;
+use starknet_core::types::{BlockId, BlockTag};
 use starknet_types_core::felt::Felt;
 
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
@@ -21,13 +21,131 @@ use crate::Starknet;
 /// count or other contract-specific operations. In case of errors, such as
 /// `BLOCK_NOT_FOUND` or `CONTRACT_NOT_FOUND`, returns a `StarknetRpcApiError` indicating the
 /// specific issue.
-
+///
+/// The pending nonce includes the pending block, and, when the node runs a mempool, the transactions of the account
+/// waiting in the mempool that can be executed next: it is the nonce of the next transaction the account should send.
 pub fn get_nonce(starknet: &Starknet, block_id: BlockId, contract_address: Felt) -> StarknetRpcResult<Felt> {
     let nonce = starknet
         .backend
         .get_contract_nonce_at(&block_id, &contract_address)
-        .or_internal_server_error("Error getting nonce")?
-        .ok_or(StarknetRpcApiError::ContractNotFound)?;
+        .or_internal_server_error("Error getting nonce")?;
+    let nonce = match nonce {
+        Some(nonce) => nonce,
+        // The contracts which never sent a transaction have no nonce in the database, the ones deployed in the pending
+        // block in particular.
+        None if starknet
+            .backend
+            .is_contract_deployed_at(&block_id, &contract_address)
+            .or_internal_server_error("Error checking if contract is deployed")? =>
+        {
+            Felt::ZERO
+        }
+        None => return Err(StarknetRpcApiError::ContractNotFound),
+    };
+
+    let Some(mempool) = starknet.mempool.as_ref().filter(|_| block_id == BlockId::Tag(BlockTag::Pending)) else {
+        return Ok(nonce);
+    };
+    let next_nonce =
+        mempool.next_nonce_hint(contract_address).or_internal_server_error("Error getting mempool nonce")?;
+    Ok(next_nonce.map_or(nonce, |next_nonce| next_nonce.max(nonce)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dc_mempool::testing::{store_test_genesis, test_invoke_transaction, MockL1DataProvider, TEST_ACCOUNT_ADDRESS};
+    use dc_mempool::Mempool;
+    use dp_block::header::{GasPrices, PendingHeader};
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysPendingBlockInfo, Header};
+    use dp_state_update::{DeployedContractItem, NonceUpdate, StateDiff};
+
+    use super::*;
+    use crate::mempool_provider::MempoolProvider;
+    use crate::methods::read::get_block_with_receipts::tests::pending_header;
+    use crate::providers::AddTransactionProvider;
+    use crate::test_utils::starknet_over;
+    use crate::ChainHandle;
+
+    const ACCOUNT: Felt = Felt::from_hex_unchecked("0xacc");
+    const BUSY_ACCOUNT: Felt = TEST_ACCOUNT_ADDRESS;
+    const CONTRACT: Felt = Felt::from_hex_unchecked("0xc0");
+    const PENDING_CONTRACT: Felt = Felt::from_hex_unchecked("0xd0");
+
+    fn deployed(address: Felt) -> DeployedContractItem {
+        DeployedContractItem { address, class_hash: Felt::from(0xc1a55) }
+    }
+
+    fn nonce_update(contract_address: Felt, nonce: u64) -> NonceUpdate {
+        NonceUpdate { contract_address, nonce: Felt::from(nonce) }
+    }
+
+    /// The test genesis deploys the busy account. Block 1 deploys the other account and contract 0xc0, which never
+    /// sends a transaction, and sets the nonce of both accounts to 1. The pending block sends a transaction of each
+    /// account and deploys contract 0xd0. The transactions of the busy account with nonces 2, 3 and 5 are added to
+    /// the mempool.
+    async fn starknet_with_mempool() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_test_genesis(&backend);
+
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(Header { block_number: 1, ..Default::default() }, vec![], Felt::ONE).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![deployed(ACCOUNT), deployed(CONTRACT)],
+            vec![],
+            vec![nonce_update(ACCOUNT, 1), nonce_update(BUSY_ACCOUNT, 1)],
+        );
+        backend.store_block(block, state_diff, vec![]).unwrap();
+
+        // The transactions are validated at the gas prices of the pending block.
+        let header = PendingHeader { l1_gas_price: GasPrices::default(), ..pending_header() };
+        let pending = DeoxysMaybePendingBlock {
+            info: DeoxysPendingBlockInfo::new(header, vec![]).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        let state_diff = StateDiff::new(
+            vec![],
+            vec![],
+            vec![],
+            vec![deployed(PENDING_CONTRACT)],
+            vec![],
+            vec![nonce_update(ACCOUNT, 2), nonce_update(BUSY_ACCOUNT, 2)],
+        );
+        backend.store_block(pending, state_diff, vec![]).unwrap();
+
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+        let provider = MempoolProvider::new(Arc::clone(&mempool), ChainHandle::from_backend(&backend));
+        for nonce in [2, 3, 5] {
+            provider.add_invoke_transaction(test_invoke_transaction(nonce)).await.unwrap();
+        }
+        assert_eq!(mempool.tx_count(), 3);
+
+        (temp_dir, starknet_over(backend, Some(mempool)))
+    }
+
+    #[tokio::test]
+    async fn test_get_nonce() {
+        let (_temp_dir, starknet) = starknet_with_mempool().await;
+        let (latest, pending) = (BlockId::Tag(BlockTag::Latest), BlockId::Tag(BlockTag::Pending));
+
+        assert_eq!(get_nonce(&starknet, latest, ACCOUNT).unwrap(), Felt::ONE);
+        assert_eq!(get_nonce(&starknet, pending, ACCOUNT).unwrap(), Felt::TWO);
+
+        // The transactions waiting in the mempool are counted up to the missing nonce 4, in the pending block only.
+        assert_eq!(get_nonce(&starknet, BlockId::Number(1), BUSY_ACCOUNT).unwrap(), Felt::ONE);
+        assert_eq!(get_nonce(&starknet, pending, BUSY_ACCOUNT).unwrap(), Felt::from(4));
 
-    Ok(nonce)
+        assert_eq!(get_nonce(&starknet, latest, CONTRACT).unwrap(), Felt::ZERO);
+        assert_eq!(get_nonce(&starknet, pending, PENDING_CONTRACT).unwrap(), Felt::ZERO);
+        assert!(matches!(get_nonce(&starknet, latest, PENDING_CONTRACT), Err(StarknetRpcApiError::ContractNotFound)));
+        assert!(matches!(get_nonce(&starknet, pending, Felt::from(0x404)), Err(StarknetRpcApiError::ContractNotFound)));
+    }
 }
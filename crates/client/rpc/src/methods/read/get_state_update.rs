@@ -5,6 +5,7 @@ use crate::utils::OptionExt;
 use crate::utils::ResultExt;
 use crate::Starknet;
 use dc_db::db_block_id::DbBlockId;
+use dp_block::BlockN;
 
 /// Get the information about the result of executing the requested block.
 ///
@@ -60,8 +61,8 @@ pub fn get_state_update(starknet: &Starknet, block_id: BlockId) -> StarknetRpcRe
             let block_info = block_info.as_nonpending().ok_or_internal_server_error("Block should not be pending")?;
 
             // Get the old root from the previous block if it exists, otherwise default to zero.
-            let old_root = if let Some(val) = block_info.header.block_number.checked_sub(1) {
-                let prev_block_info = &starknet.get_block_info(&DbBlockId::BlockN(val))?;
+            let old_root = if let Some(parent) = BlockN(block_info.header.block_number).parent() {
+                let prev_block_info = &starknet.get_block_info(&DbBlockId::BlockN(parent))?;
                 let prev_block_info =
                     prev_block_info.as_nonpending().ok_or_internal_server_error("Block should not be pending")?;
 
@@ -1,6 +1,7 @@
 use starknet_core::types::{BlockId, Transaction};
 
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
+use crate::utils::{OptionExt, ResultExt};
 use crate::Starknet;
 
 /// Get the details of a transaction by a given block id and index.
@@ -28,9 +29,14 @@ pub fn get_transaction_by_block_id_and_index(
     block_id: BlockId,
     index: u64,
 ) -> StarknetRpcResult<Transaction> {
-    let block = starknet.get_block(&block_id)?;
-    let transaction_hash = block.info.tx_hashes().get(index as usize).ok_or(StarknetRpcApiError::InvalidTxnIndex)?;
-    let transaction = block.inner.transactions.get(index as usize).ok_or(StarknetRpcApiError::InvalidTxnIndex)?;
+    let block = starknet.get_block_info(&block_id)?;
+    let transaction_hash = *block.tx_hashes().get(index as usize).ok_or(StarknetRpcApiError::InvalidTxnIndex)?;
+    // Only this transaction is decoded.
+    let transaction = starknet
+        .backend
+        .get_transaction(&block.as_block_id(), index)
+        .or_internal_server_error("Error getting transaction from storage")?
+        .ok_or_internal_server_error("Storage block transaction mismatch")?;
 
-    Ok(transaction.clone().to_core(*transaction_hash))
+    Ok(transaction.to_core(transaction_hash))
 }
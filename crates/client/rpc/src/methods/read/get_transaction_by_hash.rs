@@ -1,7 +1,7 @@
 use starknet_core::types::{Felt, Transaction};
 
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
-use crate::utils::{OptionExt, ResultExt};
+use crate::utils::ResultExt;
 use crate::Starknet;
 
 /// Get the details and status of a submitted transaction.
@@ -21,7 +21,8 @@ use crate::Starknet;
 /// recipient, and other transaction details. The information is encapsulated in a `Transaction`
 /// type, which is a combination of the `TXN` schema and additional properties, such as the
 /// `transaction_hash`. In case the specified transaction hash is not found, returns a
-/// `StarknetRpcApiError` with `TXN_HASH_NOT_FOUND`.
+/// `StarknetRpcApiError` with `TXN_HASH_NOT_FOUND`. Transactions waiting in the mempool are returned as they were
+/// submitted.
 ///
 /// ### Errors
 ///
@@ -32,15 +33,213 @@ use crate::Starknet;
 /// - `TOO_MANY_KEYS_IN_FILTER` if there are too many keys in the filter, which may exceed the
 ///   system's capacity.
 pub fn get_transaction_by_hash(starknet: &Starknet, transaction_hash: Felt) -> StarknetRpcResult<Transaction> {
-    let (block, tx_index) = starknet
+    let Some((_block, transaction)) = starknet
         .backend
-        .find_tx_hash_block(&transaction_hash)
-        .or_internal_server_error("Error getting block from tx hash")?
-        .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
-    let transaction = block
-        .inner
-        .transactions
-        .get(tx_index.0 as usize)
-        .ok_or_internal_server_error("Storage block transaction mismatch")?;
-    Ok(transaction.clone().to_core(transaction_hash))
+        .find_tx_hash_transaction(&transaction_hash)
+        .or_internal_server_error("Error getting transaction from tx hash")?
+    else {
+        let tx = starknet.get_mempool_transaction(transaction_hash).ok_or(StarknetRpcApiError::TxnHashNotFound)?;
+        return Ok(tx.transaction.to_core(tx.hash));
+    };
+    Ok(transaction.to_core(transaction_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dc_mempool::testing::{store_test_genesis, MockL1DataProvider, TEST_ACCOUNT_ADDRESS};
+    use dc_mempool::Mempool;
+    use starknet_core::types::{
+        BroadcastedDeclareTransaction, BroadcastedDeclareTransactionV1, BroadcastedDeclareTransactionV2,
+        BroadcastedDeclareTransactionV3, BroadcastedDeployAccountTransaction, BroadcastedDeployAccountTransactionV1,
+        BroadcastedDeployAccountTransactionV3, BroadcastedInvokeTransaction, BroadcastedInvokeTransactionV1,
+        BroadcastedInvokeTransactionV3, BroadcastedTransaction, CompressedLegacyContractClass, DataAvailabilityMode,
+        EntryPointsByType, FlattenedSierraClass, LegacyEntryPointsByType, ResourceBounds, ResourceBoundsMapping,
+        TransactionStatus,
+    };
+
+    use super::*;
+    use crate::mempool_provider::MempoolProvider;
+    use crate::methods::read::get_transaction_status::get_transaction_status;
+    use crate::providers::AddTransactionProvider;
+    use crate::test_utils::starknet_over;
+    use crate::ChainHandle;
+
+    async fn starknet_with_mempool() -> (tempfile::TempDir, Starknet, MempoolProvider) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        store_test_genesis(&backend);
+        let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+
+        let provider = MempoolProvider::new(Arc::clone(&mempool), ChainHandle::from_backend(&backend));
+        let starknet = starknet_over(backend, Some(mempool));
+        (temp_dir, starknet, provider)
+    }
+
+    fn resource_bounds() -> ResourceBoundsMapping {
+        ResourceBoundsMapping {
+            // Above the minimal gas of the transactions, for the invoke transactions to pass the validation.
+            l1_gas: ResourceBounds { max_amount: 0x10000, max_price_per_unit: 0x200 },
+            l2_gas: ResourceBounds { max_amount: 0, max_price_per_unit: 0 },
+        }
+    }
+
+    /// Invoke transactions of the test account. Their calldata is a call to `(0x1, 0x2)`, as expected by the Cairo 0
+    /// accounts, and their signature is not checked.
+    fn invoke_transactions() -> Vec<BroadcastedInvokeTransaction> {
+        vec![
+            BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+                sender_address: TEST_ACCOUNT_ADDRESS,
+                calldata: vec![Felt::ONE, Felt::TWO, Felt::ONE, Felt::THREE],
+                max_fee: Felt::from(0x100),
+                signature: vec![Felt::THREE, Felt::from(0x4)],
+                nonce: Felt::ZERO,
+                is_query: false,
+            }),
+            BroadcastedInvokeTransaction::V3(BroadcastedInvokeTransactionV3 {
+                sender_address: TEST_ACCOUNT_ADDRESS,
+                calldata: vec![Felt::ONE, Felt::TWO, Felt::ZERO],
+                signature: vec![Felt::TWO],
+                nonce: Felt::ONE,
+                resource_bounds: resource_bounds(),
+                tip: 1,
+                paymaster_data: vec![Felt::from(0x9)],
+                account_deployment_data: vec![Felt::from(0xa)],
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L2,
+                is_query: false,
+            }),
+        ]
+    }
+
+    /// Every field of the broadcasted transaction is returned unchanged, except for the class of declare transactions.
+    fn assert_fields_match(broadcasted: &BroadcastedTransaction, tx: &Transaction) {
+        let broadcasted = serde_json::to_value(broadcasted).unwrap();
+        let tx = serde_json::to_value(tx).unwrap();
+        for (field, value) in broadcasted.as_object().unwrap() {
+            if field != "contract_class" {
+                assert_eq!(&tx[field], value, "Field {field} of {broadcasted}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mempool_transactions_are_served_as_submitted() {
+        let (_temp_dir, starknet, provider) = starknet_with_mempool().await;
+
+        for invoke in invoke_transactions() {
+            let tx_hash = provider.add_invoke_transaction(invoke.clone()).await.unwrap().transaction_hash;
+
+            let tx = get_transaction_by_hash(&starknet, tx_hash).unwrap();
+            assert_eq!(tx.transaction_hash(), &tx_hash);
+            assert_fields_match(&BroadcastedTransaction::Invoke(invoke), &tx);
+            assert!(matches!(get_transaction_status(&starknet, tx_hash), Ok(TransactionStatus::Received)));
+        }
+
+        assert!(matches!(
+            get_transaction_by_hash(&starknet, Felt::from(0xdead)),
+            Err(StarknetRpcApiError::TxnHashNotFound)
+        ));
+        assert!(matches!(
+            get_transaction_status(&starknet, Felt::from(0xdead)),
+            Err(StarknetRpcApiError::TxnHashNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_deploy_account_transactions_are_kept_as_submitted() {
+        let deploy_accounts = [
+            BroadcastedDeployAccountTransaction::V1(BroadcastedDeployAccountTransactionV1 {
+                max_fee: Felt::from(0x100),
+                signature: vec![Felt::ONE],
+                nonce: Felt::ZERO,
+                contract_address_salt: Felt::from(0x42),
+                constructor_calldata: vec![Felt::TWO],
+                class_hash: Felt::from(0xc1a55),
+                is_query: false,
+            }),
+            BroadcastedDeployAccountTransaction::V3(BroadcastedDeployAccountTransactionV3 {
+                signature: vec![Felt::ONE],
+                nonce: Felt::ZERO,
+                contract_address_salt: Felt::from(0x43),
+                constructor_calldata: vec![Felt::THREE],
+                class_hash: Felt::from(0xc1a55),
+                resource_bounds: resource_bounds(),
+                tip: 0,
+                paymaster_data: vec![],
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                is_query: false,
+            }),
+        ];
+
+        // The mempool executes the deploy account transactions, fee transfer included, and the test genesis has no fee
+        // token contract: only the form kept for the RPC is checked here.
+        for deploy_account in deploy_accounts {
+            let broadcasted = BroadcastedTransaction::DeployAccount(deploy_account);
+            let tx = dp_transactions::Transaction::from_broadcasted(broadcasted.clone(), None).to_core(Felt::from(0x1));
+            assert_fields_match(&broadcasted, &tx);
+        }
+    }
+
+    #[test]
+    fn test_declare_transactions_are_kept_as_submitted() {
+        let sierra_class = Arc::new(FlattenedSierraClass {
+            sierra_program: vec![],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: String::new(),
+        });
+        let legacy_class = Arc::new(CompressedLegacyContractClass {
+            program: vec![],
+            entry_points_by_type: LegacyEntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: None,
+        });
+        let declares = [
+            BroadcastedDeclareTransaction::V1(BroadcastedDeclareTransactionV1 {
+                max_fee: Felt::from(0x100),
+                signature: vec![Felt::ONE, Felt::TWO],
+                nonce: Felt::ZERO,
+                contract_class: legacy_class,
+                sender_address: Felt::from(0x1234),
+                is_query: false,
+            }),
+            BroadcastedDeclareTransaction::V2(BroadcastedDeclareTransactionV2 {
+                sender_address: Felt::from(0x1234),
+                compiled_class_hash: Felt::from(0xc0),
+                max_fee: Felt::from(0x100),
+                signature: vec![Felt::THREE],
+                nonce: Felt::ONE,
+                contract_class: Arc::clone(&sierra_class),
+                is_query: false,
+            }),
+            BroadcastedDeclareTransaction::V3(BroadcastedDeclareTransactionV3 {
+                sender_address: Felt::from(0x1234),
+                compiled_class_hash: Felt::from(0xc0),
+                signature: vec![Felt::THREE],
+                nonce: Felt::TWO,
+                contract_class: sierra_class,
+                resource_bounds: resource_bounds(),
+                tip: 2,
+                paymaster_data: vec![Felt::ONE],
+                account_deployment_data: vec![],
+                nonce_data_availability_mode: DataAvailabilityMode::L2,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                is_query: false,
+            }),
+        ];
+
+        // Declare transactions need their class to be compiled before they reach the mempool, only the form kept for
+        // the RPC is checked here.
+        for declare in declares {
+            let broadcasted = BroadcastedTransaction::Declare(declare);
+            let class_hash = Felt::from(0xc1a55);
+            let tx = dp_transactions::Transaction::from_broadcasted(broadcasted.clone(), Some(class_hash))
+                .to_core(Felt::from(0x1));
+            assert_fields_match(&broadcasted, &tx);
+            assert_eq!(serde_json::to_value(&tx).unwrap()["class_hash"], format!("{class_hash:#x}"));
+        }
+    }
 }
@@ -1,8 +1,9 @@
 use dp_block::DeoxysMaybePendingBlockInfo;
-use starknet_core::types::{Felt, TransactionFinalityStatus, TransactionReceiptWithBlockInfo};
+use starknet_core::types::{Felt, TransactionReceiptWithBlockInfo};
 
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
-
+use crate::types::ReceiptWithExtensions;
+use crate::utils::receipt::receipt_to_rpc;
 use crate::utils::ResultExt;
 use crate::Starknet;
 
@@ -24,6 +25,9 @@ use crate::Starknet;
 /// - `PendingTransactionReceipt` if the transaction is pending and the receipt is not yet
 ///   available.
 ///
+/// The rejected transactions are reverted in the receipt, the `deoxys_execution_status` extension field gives the
+/// status reported by the sequencer.
+///
 /// ### Errors
 ///
 /// The function may return a `TXN_HASH_NOT_FOUND` error if the specified transaction hash is
@@ -31,31 +35,18 @@ use crate::Starknet;
 pub async fn get_transaction_receipt(
     starknet: &Starknet,
     transaction_hash: Felt,
-) -> StarknetRpcResult<TransactionReceiptWithBlockInfo> {
-    let (block, tx_index) = starknet
+) -> StarknetRpcResult<ReceiptWithExtensions<TransactionReceiptWithBlockInfo>> {
+    let (block, (transaction, receipt)) = starknet
         .backend
-        .find_tx_hash_block(&transaction_hash)
-        .or_internal_server_error("Error getting block from tx_hash")?
+        .find_tx_hash_receipt(&transaction_hash)
+        .or_internal_server_error("Error getting receipt from tx hash")?
         .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
 
-    let is_on_l1 = if let Some(block_n) = block.info.block_n() {
-        block_n <= starknet.get_l1_last_confirmed_block()?
-    } else {
-        false
-    };
-
-    let finality_status =
-        if is_on_l1 { TransactionFinalityStatus::AcceptedOnL1 } else { TransactionFinalityStatus::AcceptedOnL2 };
-
-    let receipt = block
-        .inner
-        .receipts
-        .get(tx_index.0 as usize)
-        .ok_or(StarknetRpcApiError::TxnHashNotFound)?
-        .clone()
-        .to_starknet_core(finality_status);
+    let finality_status = starknet.l1_finality()?.finality_status(block.block_n());
+    let deoxys_execution_status = Some(receipt.execution_result().into());
+    let receipt = receipt_to_rpc(receipt, &transaction, *block.protocol_version(), finality_status);
 
-    let block = match block.info {
+    let block = match block {
         DeoxysMaybePendingBlockInfo::Pending(_) => starknet_core::types::ReceiptBlock::Pending,
         DeoxysMaybePendingBlockInfo::NotPending(block) => starknet_core::types::ReceiptBlock::Block {
             block_hash: block.block_hash,
@@ -63,5 +54,110 @@ pub async fn get_transaction_receipt(
         },
     };
 
-    Ok(TransactionReceiptWithBlockInfo { receipt, block })
+    Ok(ReceiptWithExtensions { receipt: TransactionReceiptWithBlockInfo { receipt, block }, deoxys_execution_status })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_receipt::{ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit, TransactionReceipt};
+    use dp_state_update::StateDiff;
+    use dp_transactions::{InvokeTransaction, InvokeTransactionV1, Transaction};
+    use serde_json::json;
+    use starknet_core::types::{TransactionExecutionStatus, TransactionStatus};
+
+    use super::*;
+    use crate::methods::read::get_transaction_status::get_transaction_status;
+    use crate::test_utils::starknet_over;
+
+    /// Block 0 holds a transaction for each execution result, with the hashes 0x100, 0x101 and 0x102.
+    async fn test_starknet() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let results = [
+            ExecutionResult::Succeeded,
+            ExecutionResult::Reverted { reason: "reverted".into() },
+            ExecutionResult::Rejected { reason: "rejected".into() },
+        ];
+        let tx_hashes: Vec<_> = (0..results.len() as u64).map(|i| Felt::from(0x100 + i)).collect();
+        let receipts = Iterator::zip(tx_hashes.iter(), results)
+            .map(|(tx_hash, execution_result)| {
+                TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                    transaction_hash: *tx_hash,
+                    actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Wei },
+                    messages_sent: vec![],
+                    events: vec![],
+                    execution_resources: Default::default(),
+                    execution_result,
+                })
+            })
+            .collect();
+        let tx = Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+            sender_address: Felt::ONE,
+            calldata: vec![],
+            max_fee: Felt::from(100),
+            signature: vec![],
+            nonce: Felt::ZERO,
+        }));
+        let header = Header { protocol_version: "0.11.0".parse().unwrap(), ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, tx_hashes, Felt::ONE).into(),
+            inner: DeoxysBlockInner::new(vec![tx; 3], receipts),
+        };
+        backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    async fn served_receipt(starknet: &Starknet, tx_hash: u64) -> serde_json::Value {
+        serde_json::to_value(get_transaction_receipt(starknet, Felt::from(tx_hash)).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_receipt_execution_status() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let receipt = served_receipt(&starknet, 0x100).await;
+        assert_eq!(receipt["execution_status"], "SUCCEEDED");
+        assert_eq!(receipt["deoxys_execution_status"], "SUCCEEDED");
+        assert!(receipt.get("revert_reason").is_none());
+
+        let receipt = served_receipt(&starknet, 0x101).await;
+        assert_eq!(receipt["execution_status"], "REVERTED");
+        assert_eq!(receipt["revert_reason"], "reverted");
+        assert_eq!(receipt["deoxys_execution_status"], "REVERTED");
+
+        // The specification has no rejected status, the extension field tells it apart.
+        let receipt = served_receipt(&starknet, 0x102).await;
+        assert_eq!(receipt["execution_status"], "REVERTED");
+        assert_eq!(receipt["revert_reason"], "rejected");
+        assert_eq!(receipt["deoxys_execution_status"], "REJECTED");
+        assert_eq!(receipt["block_hash"], json!("0x1"));
+
+        assert!(matches!(get_transaction_status(&starknet, Felt::from(0x102)), Ok(TransactionStatus::Rejected)));
+    }
+
+    #[tokio::test]
+    async fn test_finality_follows_l1_confirmation() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let tx_hash = Felt::from(0x100);
+
+        // Nothing is confirmed on L1 yet.
+        assert_eq!(served_receipt(&starknet, 0x100).await["finality_status"], "ACCEPTED_ON_L2");
+        assert!(matches!(
+            get_transaction_status(&starknet, tx_hash),
+            Ok(TransactionStatus::AcceptedOnL2(TransactionExecutionStatus::Succeeded))
+        ));
+
+        starknet.clone_backend().write_last_confirmed_block(0).unwrap();
+        assert_eq!(served_receipt(&starknet, 0x100).await["finality_status"], "ACCEPTED_ON_L1");
+        assert!(matches!(
+            get_transaction_status(&starknet, tx_hash),
+            Ok(TransactionStatus::AcceptedOnL1(TransactionExecutionStatus::Succeeded))
+        ));
+    }
 }
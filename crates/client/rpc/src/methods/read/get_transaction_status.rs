@@ -25,28 +25,33 @@ use crate::Starknet;
 ///   - `execution_status`: The execution status of the transaction, providing details on the
 ///     execution outcome if the transaction has been processed.
 pub fn get_transaction_status(starknet: &Starknet, transaction_hash: Felt) -> StarknetRpcResult<TransactionStatus> {
-    let (block, tx_index) = starknet
+    let Some((block, (_transaction, tx_receipt))) = starknet
         .backend
-        .find_tx_hash_block(&transaction_hash)
-        .or_internal_server_error("Error find tx hash block info from db")?
-        .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
-
-    let tx_receipt = block.inner.receipts.get(tx_index.0 as usize).ok_or(StarknetRpcApiError::TxnHashNotFound)?;
+        .find_tx_hash_receipt(&transaction_hash)
+        .or_internal_server_error("Error getting receipt from tx hash")?
+    else {
+        // The transaction is waiting in the mempool.
+        return starknet
+            .get_mempool_transaction(transaction_hash)
+            .map(|_| TransactionStatus::Received)
+            .ok_or(StarknetRpcApiError::TxnHashNotFound);
+    };
 
     let tx_execution_status = match tx_receipt.execution_result() {
         ExecutionResult::Reverted { .. } => TransactionExecutionStatus::Reverted,
         ExecutionResult::Succeeded => TransactionExecutionStatus::Succeeded,
+        ExecutionResult::Rejected { .. } => return Ok(TransactionStatus::Rejected),
     };
 
-    match block.info {
+    match block {
         DeoxysMaybePendingBlockInfo::Pending(_) => {
             Ok(TransactionStatus::Received) // TODO(merge): is that correct?
         }
         DeoxysMaybePendingBlockInfo::NotPending(block) => {
-            if block.header.block_number <= starknet.get_l1_last_confirmed_block()? {
-                Ok(TransactionStatus::AcceptedOnL2(tx_execution_status))
-            } else {
+            if starknet.l1_finality()?.is_accepted_on_l1(Some(block.header.block_number)) {
                 Ok(TransactionStatus::AcceptedOnL1(tx_execution_status))
+            } else {
+                Ok(TransactionStatus::AcceptedOnL2(tx_execution_status))
             }
         }
     }
@@ -1,3 +1,4 @@
+use dp_block::{DeoxysMaybePendingBlockInfo, StarknetVersion};
 use jsonrpsee::core::{async_trait, RpcResult};
 use starknet_core::types::{
     BlockHashAndNumber, BlockId, BroadcastedTransaction, ContractClass, EventFilterWithPage, EventsPage, FeeEstimate,
@@ -27,8 +28,23 @@ use super::get_transaction_by_hash::*;
 use super::get_transaction_receipt::*;
 use super::get_transaction_status::*;
 use super::syncing::*;
+use crate::errors::StarknetRpcResult;
+use crate::types::{BlockVerificationStatus, BlockWithExtensions, LenientFelts, ReceiptWithExtensions};
 use crate::{Starknet, StarknetReadRpcApiServer};
 
+/// The `deoxys_verification` field of a block, taken from the block info it is converted from. Absent from the pending
+/// block.
+fn deoxys_verification(info: &DeoxysMaybePendingBlockInfo) -> Option<BlockVerificationStatus> {
+    info.as_nonpending().map(|info| info.verification.into())
+}
+
+/// The `deoxys_receipt_commitment` field of a block. The receipt commitment is part of the block since 0.13.2.
+fn deoxys_receipt_commitment(info: &DeoxysMaybePendingBlockInfo) -> Option<Felt> {
+    info.as_nonpending()
+        .filter(|info| info.header.protocol_version >= StarknetVersion::STARKNET_VERSION_0_13_2)
+        .map(|info| info.header.receipt_commitment)
+}
+
 #[async_trait]
 impl StarknetReadRpcApiServer for Starknet {
     fn block_number(&self) -> RpcResult<u64> {
@@ -43,8 +59,8 @@ impl StarknetReadRpcApiServer for Starknet {
         Ok(block_hash_and_number(self)?)
     }
 
-    fn call(&self, request: FunctionCall, block_id: BlockId) -> RpcResult<Vec<Felt>> {
-        Ok(call(self, request, block_id)?)
+    async fn call(&self, request: FunctionCall, block_id: BlockId) -> RpcResult<Vec<Felt>> {
+        Ok(call(self, request, block_id).await?)
     }
 
     fn chain_id(&self) -> RpcResult<Felt> {
@@ -57,27 +73,50 @@ impl StarknetReadRpcApiServer for Starknet {
 
     async fn estimate_fee(
         &self,
-        request: Vec<BroadcastedTransaction>,
+        request: LenientFelts<Vec<BroadcastedTransaction>>,
         simulation_flags: Vec<SimulationFlagForEstimateFee>,
         block_id: BlockId,
     ) -> RpcResult<Vec<FeeEstimate>> {
-        Ok(estimate_fee(self, request, simulation_flags, block_id).await?)
+        Ok(estimate_fee(self, request.0, simulation_flags, block_id).await?)
     }
 
     async fn estimate_message_fee(&self, message: MsgFromL1, block_id: BlockId) -> RpcResult<FeeEstimate> {
         Ok(estimate_message_fee(self, message, block_id).await?)
     }
 
-    async fn get_block_with_receipts(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithReceipts> {
-        Ok(get_block_with_receipts(self, block_id)?)
-    }
-
-    fn get_block_with_tx_hashes(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxHashes> {
-        Ok(get_block_with_tx_hashes(self, block_id)?)
+    async fn get_block_with_receipts(
+        &self,
+        block_id: BlockId,
+    ) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithReceipts>> {
+        let block = self.get_block_or_empty_pending(&block_id)?;
+        let (deoxys_verification, deoxys_receipt_commitment) =
+            (deoxys_verification(&block.info), deoxys_receipt_commitment(&block.info));
+        Ok(BlockWithExtensions {
+            block: block_with_receipts(self, block)?,
+            deoxys_verification,
+            deoxys_receipt_commitment,
+        })
+    }
+
+    fn get_block_with_tx_hashes(
+        &self,
+        block_id: BlockId,
+    ) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithTxHashes>> {
+        let info = self.get_block_info_or_empty_pending(&block_id)?;
+        let (deoxys_verification, deoxys_receipt_commitment) =
+            (deoxys_verification(&info), deoxys_receipt_commitment(&info));
+        Ok(BlockWithExtensions {
+            block: block_with_tx_hashes(self, info)?,
+            deoxys_verification,
+            deoxys_receipt_commitment,
+        })
     }
 
-    fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<MaybePendingBlockWithTxs> {
-        get_block_with_txs(self, block_id)
+    fn get_block_with_txs(&self, block_id: BlockId) -> RpcResult<BlockWithExtensions<MaybePendingBlockWithTxs>> {
+        let block = self.get_block_or_empty_pending(&block_id)?;
+        let (deoxys_verification, deoxys_receipt_commitment) =
+            (deoxys_verification(&block.info), deoxys_receipt_commitment(&block.info));
+        Ok(BlockWithExtensions { block: block_with_txs(self, block)?, deoxys_verification, deoxys_receipt_commitment })
     }
 
     fn get_class_at(&self, block_id: BlockId, contract_address: Felt) -> RpcResult<ContractClass> {
@@ -112,7 +151,10 @@ impl StarknetReadRpcApiServer for Starknet {
         Ok(get_transaction_by_hash(self, transaction_hash)?)
     }
 
-    async fn get_transaction_receipt(&self, transaction_hash: Felt) -> RpcResult<TransactionReceiptWithBlockInfo> {
+    async fn get_transaction_receipt(
+        &self,
+        transaction_hash: Felt,
+    ) -> RpcResult<ReceiptWithExtensions<TransactionReceiptWithBlockInfo>> {
         Ok(get_transaction_receipt(self, transaction_hash).await?)
     }
 
@@ -1,4 +1,5 @@
-use dp_block::{BlockId, BlockTag};
+use dc_db::block_db::SyncStall;
+use dp_block::BlockId;
 use starknet_core::types::{SyncStatus, SyncStatusType};
 
 use crate::errors::StarknetRpcResult;
@@ -18,28 +19,29 @@ use crate::Starknet;
 pub async fn syncing(starknet: &Starknet) -> StarknetRpcResult<SyncStatusType> {
     // obtain best seen (highest) block number
 
-    let Some(current_block_info) = starknet
-        .backend
-        .get_block_info(&BlockId::Tag(BlockTag::Latest))
-        .or_internal_server_error("Error getting latest block")?
-    else {
+    let Some((current_block_hash, current_block_num)) = starknet.backend.chain_head().get().latest else {
         return Ok(SyncStatusType::NotSyncing); // TODO: This doesn't really make sense? This can only happen when there are no block in the db at all.
     };
-    let current_block_info =
-        current_block_info.as_nonpending().ok_or_internal_server_error("Latest block cannot be pending")?;
     let starting_block_num = 0; // TODO(rpc): fix this // starknet.starting_block;
     let starting_block_info = starknet.get_block_info(&BlockId::Number(starting_block_num))?;
     let starting_block_info =
         starting_block_info.as_nonpending().ok_or_internal_server_error("Block cannot be pending")?;
     let starting_block_hash = starting_block_info.block_hash;
-    let current_block_num = current_block_info.header.block_number;
-    let current_block_hash = current_block_info.block_hash;
+
+    // When the sync stopped, the node stays behind the block it could not import, `deoxys_getSyncStall` tells why.
+    let (highest_block_num, highest_block_hash) =
+        match starknet.backend.get_sync_stall().or_internal_server_error("Error getting sync stall")? {
+            Some(SyncStall::MismatchedStateRoot { block_n, block_hash, .. }) => (block_n, block_hash),
+            // The blocks of the feeder after the fork are not on our chain until the node operator reverts it.
+            Some(SyncStall::DeepReorg { .. }) => (current_block_num, current_block_hash),
+            None => (current_block_num, current_block_hash), // TODO(merge): is this correct,,?
+        };
 
     Ok(SyncStatusType::Syncing(SyncStatus {
         starting_block_num,
         starting_block_hash,
-        highest_block_num: current_block_num, // TODO(merge): is this correct,,?
-        highest_block_hash: current_block_hash,
+        highest_block_num,
+        highest_block_hash,
         current_block_num,
         current_block_hash,
     }))
@@ -6,6 +6,7 @@ use starknet_core::types::{
 use super::simulate_transactions::simulate_transactions;
 use super::trace_block_transactions::trace_block_transactions;
 use super::trace_transaction::trace_transaction;
+use crate::types::LenientFelts;
 use crate::{Starknet, StarknetTraceRpcApiServer};
 
 #[async_trait]
@@ -13,10 +14,10 @@ impl StarknetTraceRpcApiServer for Starknet {
     async fn simulate_transactions(
         &self,
         block_id: BlockId,
-        transactions: Vec<BroadcastedTransaction>,
+        transactions: LenientFelts<Vec<BroadcastedTransaction>>,
         simulation_flags: Vec<SimulationFlag>,
     ) -> RpcResult<Vec<SimulatedTransaction>> {
-        Ok(simulate_transactions(self, block_id, transactions, simulation_flags).await?)
+        Ok(simulate_transactions(self, block_id, transactions.0, simulation_flags).await?)
     }
 
     async fn trace_block_transactions(&self, block_id: BlockId) -> RpcResult<Vec<TransactionTraceWithHash>> {
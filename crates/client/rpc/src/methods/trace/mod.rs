@@ -2,3 +2,25 @@ pub mod lib;
 pub mod simulate_transactions;
 pub mod trace_block_transactions;
 pub mod trace_transaction;
+
+use dc_exec::{execution_result_to_tx_trace, ExecutionResult};
+use starknet_core::types::TransactionTrace;
+
+use crate::constants::TRACE_STATE_DIFFS;
+use crate::errors::StarknetRpcResult;
+use crate::utils::ResultExt;
+
+/// The trace of an executed transaction, with the optional fields of the specification version served by the node.
+pub(crate) fn to_rpc_trace(result: &ExecutionResult) -> StarknetRpcResult<TransactionTrace> {
+    let mut trace =
+        execution_result_to_tx_trace(result).or_internal_server_error("Converting execution infos to tx trace")?;
+    if !TRACE_STATE_DIFFS {
+        match &mut trace {
+            TransactionTrace::Invoke(trace) => trace.state_diff = None,
+            TransactionTrace::DeployAccount(trace) => trace.state_diff = None,
+            TransactionTrace::L1Handler(trace) => trace.state_diff = None,
+            TransactionTrace::Declare(trace) => trace.state_diff = None,
+        }
+    }
+    Ok(trace)
+}
@@ -1,11 +1,9 @@
+use super::to_rpc_trace;
 use super::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW;
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
-use crate::utils::ResultExt;
 use crate::Starknet;
-use dc_exec::{execution_result_to_tx_trace, ExecutionContext};
 use dp_transactions::broadcasted_to_blockifier;
 use starknet_core::types::{BlockId, BroadcastedTransaction, SimulatedTransaction, SimulationFlag};
-use std::sync::Arc;
 
 pub async fn simulate_transactions(
     starknet: &Starknet,
@@ -18,25 +16,33 @@ pub async fn simulate_transactions(
     if block_info.protocol_version() < &FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW {
         return Err(StarknetRpcApiError::UnsupportedTxnVersion);
     }
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block_info)?;
+    let exec_context = starknet.execution_context(&block_info)?;
 
     let charge_fee = !simulation_flags.contains(&SimulationFlag::SkipFeeCharge);
     let validate = !simulation_flags.contains(&SimulationFlag::SkipValidate);
 
     let user_transactions = transactions
         .into_iter()
-        .map(|tx| broadcasted_to_blockifier(tx, starknet.chain_id(), block_info.block_n()).map(|(tx, _)| tx))
-        .collect::<Result<Vec<_>, _>>()
-        .or_internal_server_error("Failed to convert broadcasted transaction to blockifier")?;
+        .enumerate()
+        .map(|(tx_index, tx)| {
+            broadcasted_to_blockifier(tx, starknet.chain_id(), block_info.block_n())
+                .map(|(tx, _)| tx)
+                .map_err(|err| StarknetRpcApiError::from_broadcasted_conversion(tx_index, err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let execution_resuls = exec_context.re_execute_transactions([], user_transactions, charge_fee, validate)?;
+    let (exec_context, execution_resuls) = starknet
+        .run_execution(move || {
+            let results = exec_context.re_execute_transactions([], user_transactions, charge_fee, validate);
+            results.map(|results| (exec_context, results))
+        })
+        .await?;
 
     let simulated_transactions = execution_resuls
         .iter()
         .map(|result| {
             Ok(SimulatedTransaction {
-                transaction_trace: execution_result_to_tx_trace(result)
-                    .or_internal_server_error("Converting execution infos to tx trace")?,
+                transaction_trace: to_rpc_trace(result)?,
                 fee_estimation: exec_context.execution_result_to_fee_estimate(result),
             })
         })
@@ -1,13 +1,11 @@
+use super::to_rpc_trace;
 use super::trace_transaction::FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW;
 use crate::errors::{StarknetRpcApiError, StarknetRpcResult};
 use crate::utils::transaction::to_blockifier_transactions;
-use crate::utils::ResultExt;
 use crate::Starknet;
-use dc_exec::{execution_result_to_tx_trace, ExecutionContext};
 use dp_convert::ToFelt;
 use starknet_api::transaction::TransactionHash;
 use starknet_core::types::{BlockId, TransactionTraceWithHash};
-use std::sync::Arc;
 
 pub async fn trace_block_transactions(
     starknet: &Starknet,
@@ -19,7 +17,7 @@ pub async fn trace_block_transactions(
         return Err(StarknetRpcApiError::UnsupportedTxnVersion);
     }
 
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block.info)?;
+    let exec_context = starknet.execution_context(&block.info)?;
 
     let transactions: Vec<_> = block
         .inner
@@ -29,14 +27,14 @@ pub async fn trace_block_transactions(
         .map(|(tx, hash)| to_blockifier_transactions(starknet, block_id.into(), tx, &TransactionHash(*hash)))
         .collect::<Result<_, _>>()?;
 
-    let executions_results = exec_context.re_execute_transactions([], transactions, true, true)?;
+    let executions_results =
+        starknet.run_execution(move || exec_context.re_execute_transactions([], transactions, true, true)).await?;
 
     let traces = executions_results
         .into_iter()
         .map(|result| {
             let transaction_hash = result.hash.to_felt();
-            let trace_root = execution_result_to_tx_trace(&result)
-                .or_internal_server_error("Converting execution infos to tx trace")?;
+            let trace_root = to_rpc_trace(&result)?;
             Ok(TransactionTraceWithHash { trace_root, transaction_hash })
         })
         .collect::<Result<Vec<_>, StarknetRpcApiError>>()?;
@@ -1,17 +1,16 @@
+use super::to_rpc_trace;
 use crate::errors::StarknetRpcApiError;
 use crate::errors::StarknetRpcResult;
 use crate::utils::transaction::to_blockifier_transactions;
 use crate::utils::{OptionExt, ResultExt};
 use crate::Starknet;
-use dc_exec::execution_result_to_tx_trace;
-use dc_exec::ExecutionContext;
 use dp_block::StarknetVersion;
 use starknet_api::transaction::TransactionHash;
 use starknet_core::types::Felt;
 use starknet_core::types::TransactionTraceWithHash;
-use std::sync::Arc;
 
 // For now, we fallback to the sequencer - that is what pathfinder and juno do too, but this is temporary
+// The fallback is limited by the [`crate::fallback::FallbackPolicy`] of the node.
 pub const FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW: StarknetVersion = StarknetVersion::STARKNET_VERSION_0_13_0;
 
 pub async fn trace_transaction(
@@ -25,28 +24,104 @@ pub async fn trace_transaction(
         .ok_or(StarknetRpcApiError::TxnHashNotFound)?;
 
     if block.info.protocol_version() < &FALLBACK_TO_SEQUENCER_WHEN_VERSION_BELOW {
-        return Err(StarknetRpcApiError::UnsupportedTxnVersion);
+        let Some(fallback) = &starknet.trace_fallback else {
+            return Err(StarknetRpcApiError::UnsupportedTraceVersion {
+                protocol_version: block.info.protocol_version().to_string(),
+            });
+        };
+        let trace = fallback.trace_transaction(transaction_hash).await?;
+        return Ok(TransactionTraceWithHash { transaction_hash, trace_root: trace });
     }
 
-    let exec_context = ExecutionContext::new(Arc::clone(&starknet.backend), &block.info)?;
+    let exec_context = starknet.execution_context(&block.info)?;
 
     let mut block_txs = Iterator::zip(block.inner.transactions.iter(), block.info.tx_hashes())
         .map(|(tx, hash)| to_blockifier_transactions(starknet, block.info.as_block_id(), tx, &TransactionHash(*hash)));
 
     // takes up until not including last tx
-    let transactions_before: Vec<_> = block_txs.by_ref().take(tx_index.0 as usize).collect::<Result<_, _>>()?;
+    let transactions_before: Vec<_> = block_txs.by_ref().take(usize::from(tx_index)).collect::<Result<_, _>>()?;
     // the one we're interested in comes next in the iterator
     let transaction =
         block_txs.next().ok_or_internal_server_error("There should be at least one transaction in the block")??;
 
-    let mut executions_results =
-        exec_context.re_execute_transactions(transactions_before, [transaction], true, true)?;
+    let mut executions_results = starknet
+        .run_execution(move || exec_context.re_execute_transactions(transactions_before, [transaction], true, true))
+        .await?;
 
     let execution_result =
         executions_results.pop().ok_or_internal_server_error("No execution info returned for the last transaction")?;
 
-    let trace = execution_result_to_tx_trace(&execution_result)
-        .or_internal_server_error("Converting execution infos to tx trace")?;
+    let trace = to_rpc_trace(&execution_result)?;
 
     Ok(TransactionTraceWithHash { transaction_hash, trace_root: trace })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use dc_db::testing::temp_database;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, Header};
+    use dp_state_update::StateDiff;
+    use jsonrpsee::core::async_trait;
+    use starknet_core::types::TransactionTrace;
+
+    use super::*;
+    use crate::fallback::tests::declare_trace;
+    use crate::fallback::{FallbackPolicy, SequencerFallback, TraceFallbackProvider};
+    use crate::test_utils::starknet_over;
+
+    /// Block 0 is a 0.12.3 block, with the transaction `0x100`.
+    async fn test_starknet() -> (tempfile::TempDir, Starknet) {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        let header = Header { block_number: 0, protocol_version: "0.12.3".parse().unwrap(), ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysBlockInfo::new(header, vec![Felt::from(0x100)], Felt::ONE).into(),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        backend.store_block(block, StateDiff::default(), vec![]).unwrap();
+
+        (temp_dir, starknet_over(backend, None))
+    }
+
+    #[derive(Default)]
+    struct RecordingProvider {
+        asked: Mutex<Vec<Felt>>,
+    }
+
+    #[async_trait]
+    impl TraceFallbackProvider for RecordingProvider {
+        async fn trace_transaction(&self, transaction_hash: Felt) -> StarknetRpcResult<TransactionTrace> {
+            self.asked.lock().unwrap().push(transaction_hash);
+            Ok(declare_trace())
+        }
+    }
+
+    #[tokio::test]
+    async fn old_transactions_are_not_traced_without_fallback() {
+        let (_temp_dir, starknet) = test_starknet().await;
+
+        let err = trace_transaction(&starknet, Felt::from(0x100)).await.unwrap_err();
+        assert!(
+            matches!(&err, StarknetRpcApiError::UnsupportedTraceVersion { protocol_version } if protocol_version == "0.12.3")
+        );
+        let err = jsonrpsee::types::ErrorObjectOwned::from(err);
+        assert_eq!(err.code(), 10001);
+        assert_eq!(err.data().unwrap().get(), r#"{"starknet_version":"0.12.3"}"#);
+    }
+
+    #[tokio::test]
+    async fn old_transactions_are_traced_by_the_fallback() {
+        let (_temp_dir, starknet) = test_starknet().await;
+        let provider = Arc::new(RecordingProvider::default());
+        let starknet =
+            starknet.with_trace_fallback(SequencerFallback::new(&FallbackPolicy::default(), provider.clone()));
+
+        let trace = trace_transaction(&starknet, Felt::from(0x100)).await.unwrap();
+        assert_eq!(trace.transaction_hash, Felt::from(0x100));
+        assert_eq!(trace.trace_root, declare_trace());
+        assert_eq!(*provider.asked.lock().unwrap(), [Felt::from(0x100)]);
+    }
+}
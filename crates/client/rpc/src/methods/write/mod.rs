@@ -4,6 +4,7 @@ use starknet_core::types::{
     DeclareTransactionResult, DeployAccountTransactionResult, InvokeTransactionResult,
 };
 
+use crate::types::LenientFelts;
 use crate::{Starknet, StarknetWriteRpcApiServer};
 
 #[async_trait]
@@ -16,12 +17,15 @@ impl StarknetWriteRpcApiServer for Starknet {
     ///
     /// # Returns
     ///
-    /// * `declare_transaction_result` - the result of the declare transaction
+    /// * `declare_transaction_result` - the result of the declare transaction. Returns
+    ///   `CONTRACT_CLASS_SIZE_IS_TOO_LARGE` when the class exceeds the limits of the node, without handing it to the
+    ///   transaction provider.
     async fn add_declare_transaction(
         &self,
-        declare_transaction: BroadcastedDeclareTransaction,
+        declare_transaction: LenientFelts<BroadcastedDeclareTransaction>,
     ) -> RpcResult<DeclareTransactionResult> {
-        Ok(self.add_transaction_provider.add_declare_transaction(declare_transaction).await?)
+        self.limits.check_declared_class(&declare_transaction.0)?;
+        Ok(self.add_transaction_provider.add_declare_transaction(declare_transaction.0).await?)
     }
 
     /// Add an Deploy Account Transaction
@@ -36,9 +40,9 @@ impl StarknetWriteRpcApiServer for Starknet {
     /// * `contract_address` - address of the deployed contract account
     async fn add_deploy_account_transaction(
         &self,
-        deploy_account_transaction: BroadcastedDeployAccountTransaction,
+        deploy_account_transaction: LenientFelts<BroadcastedDeployAccountTransaction>,
     ) -> RpcResult<DeployAccountTransactionResult> {
-        Ok(self.add_transaction_provider.add_deploy_account_transaction(deploy_account_transaction).await?)
+        Ok(self.add_transaction_provider.add_deploy_account_transaction(deploy_account_transaction.0).await?)
     }
 
     /// Add an Invoke Transaction to invoke a contract function
@@ -52,8 +56,8 @@ impl StarknetWriteRpcApiServer for Starknet {
     /// * `transaction_hash` - transaction hash corresponding to the invocation
     async fn add_invoke_transaction(
         &self,
-        invoke_transaction: BroadcastedInvokeTransaction,
+        invoke_transaction: LenientFelts<BroadcastedInvokeTransaction>,
     ) -> RpcResult<InvokeTransactionResult> {
-        Ok(self.add_transaction_provider.add_invoke_transaction(invoke_transaction).await?)
+        Ok(self.add_transaction_provider.add_invoke_transaction(invoke_transaction.0).await?)
     }
 }
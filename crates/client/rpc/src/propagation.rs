@@ -0,0 +1,469 @@
+//! Forwarding of the transactions accepted by the mempool to other nodes, so that several block producers share their
+//! mempool until the nodes can gossip them over P2P.
+//!
+//! Every node forwards the transactions it accepts to its peers, including the ones it received from them. The loop is
+//! broken by the hashes of the transactions seen recently: a transaction that comes in again is not added nor forwarded
+//! a second time.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dc_metrics::{Counter, MetricsRegistry, PrometheusError, U64};
+use dp_transactions::{Transaction, TransactionWithHash};
+use dp_utils::lock::MutexExt;
+use jsonrpsee::core::{async_trait, RpcResult};
+use starknet_core::types::{
+    BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction, BroadcastedInvokeTransaction,
+    BroadcastedTransaction, DeclareTransactionResult, DeployAccountTransactionResult, Felt, InvokeTransactionResult,
+};
+use starknet_providers::jsonrpc::{HttpTransport, JsonRpcClient};
+use starknet_providers::{Provider, ProviderError, Url};
+use tokio::sync::mpsc;
+
+use crate::errors::StarknetRpcApiError;
+use crate::providers::AddTransactionProvider;
+
+/// Number of transaction hashes remembered to recognize the transactions coming back from the peers.
+pub const SEEN_TXS_CAPACITY: usize = 16_384;
+/// Number of transactions waiting to be sent to a peer. The transactions accepted while the queue is full are not
+/// forwarded to that peer.
+pub const PROPAGATION_QUEUE_SIZE: usize = 1024;
+/// Number of times a transaction is sent again to a peer that could not be reached.
+const PROPAGATION_MAX_RETRIES: u32 = 3;
+const PROPAGATION_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Called with every transaction once it is accepted by the mempool.
+pub trait TxPropagator: Send + Sync {
+    /// This must not wait for the transaction to be sent.
+    fn propagate(&self, tx_hash: Felt, tx: BroadcastedTransaction);
+}
+
+#[derive(Clone, Debug)]
+pub struct PropagationMetrics {
+    pub propagated: Counter<U64>,
+    pub deduplicated: Counter<U64>,
+    pub dropped: Counter<U64>,
+}
+
+impl PropagationMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            propagated: registry.register(Counter::new(
+                "deoxys_txs_propagated",
+                "Counter of the transactions sent to a peer node, once per peer",
+            )?)?,
+            deduplicated: registry.register(Counter::new(
+                "deoxys_txs_deduplicated",
+                "Counter of the transactions received again, which were not added nor propagated",
+            )?)?,
+            dropped: registry.register(Counter::new(
+                "deoxys_txs_propagation_dropped",
+                "Counter of the transactions that could not be sent to a peer node, once per peer",
+            )?)?,
+        })
+    }
+}
+
+/// The hashes of the transactions seen recently, the least recently seen one is forgotten first.
+struct SeenTxs {
+    capacity: usize,
+    next_age: u64,
+    by_hash: HashMap<Felt, u64>,
+    by_age: BTreeMap<u64, Felt>,
+}
+
+impl SeenTxs {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, next_age: 0, by_hash: Default::default(), by_age: Default::default() }
+    }
+
+    /// Marks `tx_hash` as the most recently seen. Returns whether it was seen already.
+    fn see(&mut self, tx_hash: Felt) -> bool {
+        let seen = match self.by_hash.insert(tx_hash, self.next_age) {
+            Some(age) => self.by_age.remove(&age).is_some(),
+            None => false,
+        };
+        self.by_age.insert(self.next_age, tx_hash);
+        self.next_age += 1;
+
+        if self.by_age.len() > self.capacity {
+            if let Some((_, oldest)) = self.by_age.pop_first() {
+                self.by_hash.remove(&oldest);
+            }
+        }
+        seen
+    }
+
+    fn forget(&mut self, tx_hash: &Felt) {
+        if let Some(age) = self.by_hash.remove(tx_hash) {
+            self.by_age.remove(&age);
+        }
+    }
+}
+
+/// The hash of a transaction being added, marked as seen so that the same transaction received meanwhile is not added
+/// a second time. The hash is forgotten again unless the transaction is accepted, so that a rejected transaction can
+/// be sent again.
+struct Reservation<'a> {
+    seen: &'a Mutex<SeenTxs>,
+    tx_hash: Felt,
+    accepted: bool,
+}
+
+impl Reservation<'_> {
+    fn accept(mut self) {
+        self.accepted = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.accepted {
+            self.seen.lock_or_recover().forget(&self.tx_hash);
+        }
+    }
+}
+
+/// A transaction received by the node.
+enum Inbound<'a> {
+    /// Queries are only validated, they are never propagated.
+    Query,
+    New(Reservation<'a>),
+    /// Seen recently or being added, it is not added again.
+    Duplicate {
+        tx_hash: Felt,
+        class_hash: Option<Felt>,
+        contract_address: Option<Felt>,
+    },
+}
+
+/// This [`AddTransactionProvider`] hands the transactions over to the `inner` provider, usually the mempool, and then
+/// to a [`TxPropagator`]. Transactions seen recently are not handed over again.
+pub struct PropagatingProvider<P: AddTransactionProvider> {
+    inner: P,
+    chain_id: Felt,
+    propagator: Arc<dyn TxPropagator>,
+    seen: Mutex<SeenTxs>,
+    metrics: PropagationMetrics,
+}
+
+impl<P: AddTransactionProvider> PropagatingProvider<P> {
+    pub fn new(inner: P, chain_id: Felt, propagator: Arc<dyn TxPropagator>, metrics: PropagationMetrics) -> Self {
+        Self { inner, chain_id, propagator, seen: Mutex::new(SeenTxs::new(SEEN_TXS_CAPACITY)), metrics }
+    }
+
+    fn inbound(&self, tx: &BroadcastedTransaction) -> RpcResult<Inbound<'_>> {
+        let (is_query, class_hash) = match tx {
+            BroadcastedTransaction::Invoke(BroadcastedInvokeTransaction::V1(tx)) => (tx.is_query, None),
+            BroadcastedTransaction::Invoke(BroadcastedInvokeTransaction::V3(tx)) => (tx.is_query, None),
+            BroadcastedTransaction::DeployAccount(BroadcastedDeployAccountTransaction::V1(tx)) => (tx.is_query, None),
+            BroadcastedTransaction::DeployAccount(BroadcastedDeployAccountTransaction::V3(tx)) => (tx.is_query, None),
+            BroadcastedTransaction::Declare(BroadcastedDeclareTransaction::V1(tx)) => {
+                let class_hash =
+                    tx.contract_class.class_hash().map_err(|_| StarknetRpcApiError::InvalidContractClass)?;
+                (tx.is_query, Some(class_hash))
+            }
+            BroadcastedTransaction::Declare(BroadcastedDeclareTransaction::V2(tx)) => {
+                (tx.is_query, Some(tx.contract_class.class_hash()))
+            }
+            BroadcastedTransaction::Declare(BroadcastedDeclareTransaction::V3(tx)) => {
+                (tx.is_query, Some(tx.contract_class.class_hash()))
+            }
+        };
+        if is_query {
+            return Ok(Inbound::Query);
+        }
+
+        let TransactionWithHash { transaction, hash: tx_hash } =
+            TransactionWithHash::from_broadcasted(tx.clone(), self.chain_id, class_hash);
+        // Checked and marked at once: the same transaction received twice at once is only added once.
+        if !self.seen.lock_or_recover().see(tx_hash) {
+            return Ok(Inbound::New(Reservation { seen: &self.seen, tx_hash, accepted: false }));
+        }
+        log::debug!("Transaction {tx_hash:#x} was already received, it is not added again");
+        self.metrics.deduplicated.inc();
+        let contract_address = match transaction {
+            Transaction::DeployAccount(tx) => Some(tx.calculate_contract_address()),
+            _ => None,
+        };
+        Ok(Inbound::Duplicate { tx_hash, class_hash, contract_address })
+    }
+
+    fn accepted(&self, reservation: Reservation<'_>, tx: BroadcastedTransaction) {
+        self.propagator.propagate(reservation.tx_hash, tx);
+        reservation.accept();
+    }
+}
+
+#[async_trait]
+impl<P: AddTransactionProvider> AddTransactionProvider for PropagatingProvider<P> {
+    async fn add_declare_transaction(
+        &self,
+        declare_transaction: BroadcastedDeclareTransaction,
+    ) -> RpcResult<DeclareTransactionResult> {
+        let tx = BroadcastedTransaction::Declare(declare_transaction.clone());
+        match self.inbound(&tx)? {
+            Inbound::Duplicate { tx_hash, class_hash, .. } => Ok(DeclareTransactionResult {
+                transaction_hash: tx_hash,
+                class_hash: class_hash.expect("Declare transactions have a class hash"),
+            }),
+            Inbound::Query => self.inner.add_declare_transaction(declare_transaction).await,
+            Inbound::New(reservation) => {
+                let res = self.inner.add_declare_transaction(declare_transaction).await?;
+                self.accepted(reservation, tx);
+                Ok(res)
+            }
+        }
+    }
+
+    async fn add_deploy_account_transaction(
+        &self,
+        deploy_account_transaction: BroadcastedDeployAccountTransaction,
+    ) -> RpcResult<DeployAccountTransactionResult> {
+        let tx = BroadcastedTransaction::DeployAccount(deploy_account_transaction.clone());
+        match self.inbound(&tx)? {
+            Inbound::Duplicate { tx_hash, contract_address, .. } => Ok(DeployAccountTransactionResult {
+                transaction_hash: tx_hash,
+                contract_address: contract_address.expect("Deploy account transactions have a contract address"),
+            }),
+            Inbound::Query => self.inner.add_deploy_account_transaction(deploy_account_transaction).await,
+            Inbound::New(reservation) => {
+                let res = self.inner.add_deploy_account_transaction(deploy_account_transaction).await?;
+                self.accepted(reservation, tx);
+                Ok(res)
+            }
+        }
+    }
+
+    async fn add_invoke_transaction(
+        &self,
+        invoke_transaction: BroadcastedInvokeTransaction,
+    ) -> RpcResult<InvokeTransactionResult> {
+        let tx = BroadcastedTransaction::Invoke(invoke_transaction.clone());
+        match self.inbound(&tx)? {
+            Inbound::Duplicate { tx_hash, .. } => Ok(InvokeTransactionResult { transaction_hash: tx_hash }),
+            Inbound::Query => self.inner.add_invoke_transaction(invoke_transaction).await,
+            Inbound::New(reservation) => {
+                let res = self.inner.add_invoke_transaction(invoke_transaction).await?;
+                self.accepted(reservation, tx);
+                Ok(res)
+            }
+        }
+    }
+}
+
+/// This [`TxPropagator`] sends the transactions to the `starknet_addXxxTransaction` methods of the RPC of its peers.
+/// Each peer has its own queue, a slow peer does not hold the others back.
+pub struct HttpRebroadcastPropagator {
+    peers: Vec<(Url, mpsc::Sender<(Felt, Arc<BroadcastedTransaction>)>)>,
+    metrics: PropagationMetrics,
+}
+
+impl HttpRebroadcastPropagator {
+    /// Spawns a task sending the transactions to each peer, this must be called within a tokio runtime.
+    pub fn new(peers: Vec<Url>, metrics: PropagationMetrics) -> Self {
+        let peers = peers
+            .into_iter()
+            .map(|url| {
+                let (sender, receiver) = mpsc::channel(PROPAGATION_QUEUE_SIZE);
+                let client = JsonRpcClient::new(HttpTransport::new(url.clone()));
+                tokio::spawn(rebroadcast_task(client, url.clone(), receiver, metrics.clone()));
+                (url, sender)
+            })
+            .collect();
+        Self { peers, metrics }
+    }
+}
+
+impl TxPropagator for HttpRebroadcastPropagator {
+    fn propagate(&self, tx_hash: Felt, tx: BroadcastedTransaction) {
+        let tx = Arc::new(tx);
+        for (url, sender) in &self.peers {
+            if sender.try_send((tx_hash, Arc::clone(&tx))).is_err() {
+                log::warn!("The propagation queue of peer {url} is full, transaction {tx_hash:#x} is not sent to it");
+                self.metrics.dropped.inc();
+            }
+        }
+    }
+}
+
+async fn rebroadcast_task(
+    client: JsonRpcClient<HttpTransport>,
+    url: Url,
+    mut receiver: mpsc::Receiver<(Felt, Arc<BroadcastedTransaction>)>,
+    metrics: PropagationMetrics,
+) {
+    while let Some((tx_hash, tx)) = receiver.recv().await {
+        let mut attempt = 0;
+        loop {
+            match send_transaction(&client, &tx).await {
+                Ok(()) => {
+                    log::debug!("Sent transaction {tx_hash:#x} to peer {url}");
+                    metrics.propagated.inc();
+                }
+                // The peer has rejected the transaction, it would do it again.
+                Err(ProviderError::StarknetError(err)) => {
+                    log::debug!("Peer {url} has rejected transaction {tx_hash:#x}: {err:?}");
+                }
+                Err(err) if attempt < PROPAGATION_MAX_RETRIES => {
+                    let delay = PROPAGATION_RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    log::debug!(
+                        "Failed to send transaction {tx_hash:#x} to peer {url}: {err:#}, retrying in {delay:?}"
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                Err(err) => {
+                    log::warn!("Failed to send transaction {tx_hash:#x} to peer {url}: {err:#}");
+                    metrics.dropped.inc();
+                }
+            }
+            break;
+        }
+    }
+}
+
+async fn send_transaction(
+    client: &JsonRpcClient<HttpTransport>,
+    tx: &BroadcastedTransaction,
+) -> Result<(), ProviderError> {
+    match tx {
+        BroadcastedTransaction::Invoke(tx) => client.add_invoke_transaction(tx).await.map(|_| ()),
+        BroadcastedTransaction::Declare(tx) => client.add_declare_transaction(tx).await.map(|_| ()),
+        BroadcastedTransaction::DeployAccount(tx) => client.add_deploy_account_transaction(tx).await.map(|_| ()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dc_db::testing::temp_database;
+    use dc_mempool::testing::{store_test_genesis, test_invoke_transaction, MockL1DataProvider};
+    use dc_mempool::Mempool;
+    use dc_metrics::MetricsService;
+    use dp_convert::ToFelt;
+    use starknet_core::types::BroadcastedInvokeTransactionV1;
+
+    use super::*;
+    use crate::mempool_provider::MempoolProvider;
+    use crate::{ChainHandle, Starknet, StarknetWriteRpcApiServer};
+
+    #[test]
+    fn test_seen_txs_forgets_the_least_recently_seen() {
+        let mut seen = SeenTxs::new(2);
+        assert!(!seen.see(Felt::ONE));
+        assert!(!seen.see(Felt::TWO));
+        assert!(seen.see(Felt::ONE));
+
+        // Transaction 2 is the least recently seen.
+        assert!(!seen.see(Felt::THREE));
+        assert!(!seen.by_hash.contains_key(&Felt::TWO));
+        assert!(seen.by_hash.contains_key(&Felt::ONE) && seen.by_hash.contains_key(&Felt::THREE));
+        assert_eq!((seen.by_hash.len(), seen.by_age.len()), (2, 2));
+
+        seen.forget(&Felt::ONE);
+        assert!(!seen.see(Felt::ONE));
+        assert_eq!((seen.by_hash.len(), seen.by_age.len()), (2, 2));
+    }
+
+    struct TestNode {
+        _temp_dir: tempfile::TempDir,
+        _server: jsonrpsee::server::ServerHandle,
+        url: Url,
+        mempool: Arc<Mempool>,
+        metrics: PropagationMetrics,
+    }
+
+    /// Nodes serving the write methods of the RPC, each one propagates the transactions its mempool accepts to the
+    /// others. They all start from the same genesis state.
+    async fn start_nodes(n_nodes: usize) -> Vec<TestNode> {
+        let mut servers = vec![];
+        for _ in 0..n_nodes {
+            let server = jsonrpsee::server::Server::builder().build("127.0.0.1:0").await.unwrap();
+            let url: Url = format!("http://{}", server.local_addr().unwrap()).parse().unwrap();
+            servers.push((server, url));
+        }
+        let urls: Vec<_> = servers.iter().map(|(_, url)| url.clone()).collect();
+
+        let mut nodes = vec![];
+        for (server, url) in servers {
+            let (temp_dir, db) = temp_database().await;
+            let backend = Arc::clone(db.backend());
+            store_test_genesis(&backend);
+            let chain_id = backend.chain_config().chain_id.clone().to_felt();
+            let chain = ChainHandle::from_backend(&backend);
+            let mempool = Arc::new(Mempool::new(Arc::clone(&backend), Arc::new(MockL1DataProvider)));
+
+            let metrics =
+                PropagationMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+            let peers = urls.iter().filter(|peer| **peer != url).cloned().collect();
+            let propagator = Arc::new(HttpRebroadcastPropagator::new(peers, metrics.clone()));
+            let add_transaction_provider = Arc::new(PropagatingProvider::new(
+                MempoolProvider::new(Arc::clone(&mempool), chain.clone()),
+                chain_id,
+                propagator,
+                metrics.clone(),
+            ));
+
+            let gateway: Url = "http://localhost:1".parse().unwrap();
+            let chain_config = crate::ChainConfig { feeder_gateway: gateway.clone(), gateway };
+            let starknet =
+                Starknet::new(backend, chain_config, chain, add_transaction_provider, Some(Arc::clone(&mempool)), None);
+            let server = server.start(StarknetWriteRpcApiServer::into_rpc(starknet));
+            nodes.push(TestNode { _temp_dir: temp_dir, _server: server, url, mempool, metrics });
+        }
+        nodes
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Timed out waiting for the transactions to propagate");
+    }
+
+    #[tokio::test]
+    async fn test_transaction_is_propagated_once() {
+        let nodes = start_nodes(2).await;
+        let (a, b) = (&nodes[0], &nodes[1]);
+
+        // The same transaction submitted twice at once is only added once.
+        let client = JsonRpcClient::new(HttpTransport::new(a.url.clone()));
+        let (first, second) = tokio::join!(
+            client.add_invoke_transaction(test_invoke_transaction(0)),
+            client.add_invoke_transaction(test_invoke_transaction(0)),
+        );
+        let tx_hash = first.unwrap().transaction_hash;
+        assert_eq!(second.unwrap().transaction_hash, tx_hash);
+
+        // Node b sends the transaction back to node a, which does not add it again.
+        wait_until(|| a.metrics.deduplicated.get() == 2).await;
+        assert!(a.mempool.contains_tx_hash(&tx_hash) && b.mempool.contains_tx_hash(&tx_hash));
+        assert_eq!((a.mempool.tx_count(), b.mempool.tx_count()), (1, 1));
+        assert_eq!((a.metrics.propagated.get(), b.metrics.propagated.get()), (1, 1));
+        assert_eq!(b.metrics.deduplicated.get(), 0);
+
+        // The transaction is not added again when it is submitted to the other node.
+        let client = JsonRpcClient::new(HttpTransport::new(b.url.clone()));
+        let res = client.add_invoke_transaction(test_invoke_transaction(0)).await.unwrap();
+        assert_eq!(res.transaction_hash, tx_hash);
+        assert_eq!(b.metrics.deduplicated.get(), 1);
+        assert_eq!(b.mempool.tx_count(), 1);
+
+        // A transaction rejected by the mempool is not marked as seen, it is rejected again.
+        let BroadcastedInvokeTransaction::V1(tx) = test_invoke_transaction(1) else { unreachable!() };
+        let invalid = BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+            sender_address: Felt::from(0xdead),
+            ..tx
+        });
+        for _ in 0..2 {
+            client.add_invoke_transaction(invalid.clone()).await.unwrap_err();
+        }
+        assert_eq!(b.metrics.deduplicated.get(), 1);
+        assert_eq!((b.mempool.tx_count(), b.metrics.propagated.get()), (1, 1));
+    }
+}
@@ -1,8 +1,8 @@
-use crate::{bail_internal_server_error, errors::StarknetRpcApiError};
+use crate::errors::StarknetRpcApiError;
 use jsonrpsee::core::{async_trait, RpcResult};
 use starknet_core::types::{
     BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction, BroadcastedInvokeTransaction,
-    DeclareTransactionResult, DeployAccountTransactionResult, InvokeTransactionResult,
+    DeclareTransactionResult, DeployAccountTransactionResult, Felt, InvokeTransactionResult,
 };
 use starknet_providers::{Provider, ProviderError};
 
@@ -24,6 +24,8 @@ pub trait AddTransactionProvider: Send + Sync {
     ) -> RpcResult<InvokeTransactionResult>;
 }
 
+/// This [`AddTransactionProvider`] forwards the received transactions to another node, usually the sequencer
+/// gateway. This is used by full nodes that are not producing blocks.
 pub struct ForwardToProvider<P: Provider + Send + Sync> {
     provider: P,
 }
@@ -34,19 +36,32 @@ impl<P: Provider + Send + Sync> ForwardToProvider<P> {
     }
 }
 
+/// Gateway error codes known to the provider (`INSUFFICIENT_MAX_FEE`, `DUPLICATED_TRANSACTION`, `VALIDATE_FAILURE`...)
+/// are already translated into [`starknet_core::types::StarknetError`]s, we only need to forward them.
+pub(crate) fn provider_error_to_rpc(err: ProviderError, context: &str) -> StarknetRpcApiError {
+    match err {
+        ProviderError::StarknetError(err) => err.into(),
+        ProviderError::RateLimited => {
+            StarknetRpcApiError::ErrUnexpectedError { data: "Rate limited by the sequencer gateway".into() }
+        }
+        err => {
+            log::error!(target: "rpc_errors", "{context}: {err:#}");
+            StarknetRpcApiError::InternalServerError
+        }
+    }
+}
+
 #[async_trait]
 impl<P: Provider + Send + Sync> AddTransactionProvider for ForwardToProvider<P> {
     async fn add_declare_transaction(
         &self,
         declare_transaction: BroadcastedDeclareTransaction,
     ) -> RpcResult<DeclareTransactionResult> {
-        let sequencer_response = match self.provider.add_declare_transaction(declare_transaction).await {
-            Ok(response) => response,
-            Err(ProviderError::StarknetError(e)) => {
-                return Err(StarknetRpcApiError::from(e).into());
-            }
-            Err(e) => bail_internal_server_error!("Failed to add declare transaction to sequencer: {e}"),
-        };
+        let sequencer_response = self
+            .provider
+            .add_declare_transaction(declare_transaction)
+            .await
+            .map_err(|e| provider_error_to_rpc(e, "Failed to add declare transaction to sequencer"))?;
 
         Ok(sequencer_response)
     }
@@ -54,13 +69,11 @@ impl<P: Provider + Send + Sync> AddTransactionProvider for ForwardToProvider<P>
         &self,
         deploy_account_transaction: BroadcastedDeployAccountTransaction,
     ) -> RpcResult<DeployAccountTransactionResult> {
-        let sequencer_response = match self.provider.add_deploy_account_transaction(deploy_account_transaction).await {
-            Ok(response) => response,
-            Err(ProviderError::StarknetError(e)) => {
-                return Err(StarknetRpcApiError::from(e).into());
-            }
-            Err(e) => bail_internal_server_error!("Failed to add deploy account transaction to sequencer: {e}"),
-        };
+        let sequencer_response = self
+            .provider
+            .add_deploy_account_transaction(deploy_account_transaction)
+            .await
+            .map_err(|e| provider_error_to_rpc(e, "Failed to add deploy account transaction to sequencer"))?;
 
         Ok(sequencer_response)
     }
@@ -69,14 +82,208 @@ impl<P: Provider + Send + Sync> AddTransactionProvider for ForwardToProvider<P>
         &self,
         invoke_transaction: BroadcastedInvokeTransaction,
     ) -> RpcResult<InvokeTransactionResult> {
-        let sequencer_response = match self.provider.add_invoke_transaction(invoke_transaction).await {
-            Ok(response) => response,
-            Err(ProviderError::StarknetError(e)) => {
-                return Err(StarknetRpcApiError::from(e).into());
-            }
-            Err(e) => bail_internal_server_error!("Failed to add invoke transaction to sequencer: {e}"),
-        };
+        let sequencer_response = self
+            .provider
+            .add_invoke_transaction(invoke_transaction)
+            .await
+            .map_err(|e| provider_error_to_rpc(e, "Failed to add invoke transaction to sequencer"))?;
 
         Ok(sequencer_response)
     }
 }
+
+/// This [`AddTransactionProvider`] forwards the received transactions to the sequencer gateway, and keeps the ones it
+/// accepted in the local mempool until the synced blocks include them. The gateway already accepted the transaction
+/// when it is added locally, so a local rejection is only logged.
+pub struct ForwardAndKeepProvider<F: AddTransactionProvider, L: AddTransactionProvider> {
+    forward: F,
+    local: L,
+}
+
+impl<F: AddTransactionProvider, L: AddTransactionProvider> ForwardAndKeepProvider<F, L> {
+    pub fn new(forward: F, local: L) -> Self {
+        Self { forward, local }
+    }
+}
+
+fn log_not_kept<T>(res: RpcResult<T>, tx_hash: Felt) {
+    if let Err(err) = res {
+        log::debug!("Transaction {tx_hash:#x} accepted by the gateway is not kept in the mempool: {}", err.message());
+    }
+}
+
+#[async_trait]
+impl<F: AddTransactionProvider, L: AddTransactionProvider> AddTransactionProvider for ForwardAndKeepProvider<F, L> {
+    async fn add_declare_transaction(
+        &self,
+        declare_transaction: BroadcastedDeclareTransaction,
+    ) -> RpcResult<DeclareTransactionResult> {
+        let res = self.forward.add_declare_transaction(declare_transaction.clone()).await?;
+        log_not_kept(self.local.add_declare_transaction(declare_transaction).await, res.transaction_hash);
+        Ok(res)
+    }
+
+    async fn add_deploy_account_transaction(
+        &self,
+        deploy_account_transaction: BroadcastedDeployAccountTransaction,
+    ) -> RpcResult<DeployAccountTransactionResult> {
+        let res = self.forward.add_deploy_account_transaction(deploy_account_transaction.clone()).await?;
+        log_not_kept(self.local.add_deploy_account_transaction(deploy_account_transaction).await, res.transaction_hash);
+        Ok(res)
+    }
+
+    async fn add_invoke_transaction(
+        &self,
+        invoke_transaction: BroadcastedInvokeTransaction,
+    ) -> RpcResult<InvokeTransactionResult> {
+        let res = self.forward.add_invoke_transaction(invoke_transaction.clone()).await?;
+        log_not_kept(self.local.add_invoke_transaction(invoke_transaction).await, res.transaction_hash);
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use rstest::rstest;
+    use serde_json::json;
+    use starknet_core::types::BroadcastedInvokeTransactionV1;
+    use starknet_providers::SequencerGatewayProvider;
+    use std::sync::Mutex;
+
+    fn invoke_transaction() -> BroadcastedInvokeTransaction {
+        BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+            sender_address: Felt::from(0x1234),
+            calldata: vec![Felt::ONE, Felt::TWO],
+            max_fee: Felt::from(0x100),
+            signature: vec![Felt::THREE],
+            nonce: Felt::ZERO,
+            is_query: false,
+        })
+    }
+
+    fn forward_to(server: &mockito::ServerGuard) -> ForwardToProvider<SequencerGatewayProvider> {
+        let url: url::Url = server.url().parse().unwrap();
+        ForwardToProvider::new(SequencerGatewayProvider::new(
+            url.join("gateway").unwrap(),
+            url.join("feeder_gateway").unwrap(),
+            Felt::from_bytes_be_slice(b"SN_MAIN"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn forward_invoke_transaction() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gateway/add_transaction")
+            .match_body(Matcher::PartialJson(json!({
+                "type": "INVOKE_FUNCTION",
+                "sender_address": "0x1234",
+                "calldata": ["0x1", "0x2"],
+                "signature": ["0x3"],
+            })))
+            .with_body(json!({ "code": "TRANSACTION_RECEIVED", "transaction_hash": "0xabcdef" }).to_string())
+            .create_async()
+            .await;
+
+        let res = forward_to(&server).add_invoke_transaction(invoke_transaction()).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(res.transaction_hash, Felt::from(0xabcdef));
+    }
+
+    #[rstest]
+    #[case("StarknetErrorCode.INSUFFICIENT_MAX_FEE", StarknetRpcApiError::InsufficientMaxFee)]
+    #[case("StarknetErrorCode.DUPLICATED_TRANSACTION", StarknetRpcApiError::DuplicateTxn)]
+    #[case("StarknetErrorCode.VALIDATE_FAILURE", StarknetRpcApiError::ValidationFailure)]
+    #[tokio::test]
+    async fn forward_invoke_transaction_error(#[case] code: &str, #[case] expected: StarknetRpcApiError) {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gateway/add_transaction")
+            .with_status(400)
+            .with_body(json!({ "code": code, "message": "gateway error" }).to_string())
+            .create_async()
+            .await;
+
+        let err = forward_to(&server).add_invoke_transaction(invoke_transaction()).await.unwrap_err();
+
+        mock.assert_async().await;
+        assert_eq!(err.code(), i32::from(&expected));
+    }
+
+    /// Keeps the invoke transactions it receives, or rejects them all.
+    #[derive(Default)]
+    struct LocalMempool {
+        kept: Mutex<Vec<BroadcastedInvokeTransaction>>,
+        rejects: bool,
+    }
+
+    #[async_trait]
+    impl AddTransactionProvider for LocalMempool {
+        async fn add_declare_transaction(
+            &self,
+            _declare_transaction: BroadcastedDeclareTransaction,
+        ) -> RpcResult<DeclareTransactionResult> {
+            unreachable!()
+        }
+
+        async fn add_deploy_account_transaction(
+            &self,
+            _deploy_account_transaction: BroadcastedDeployAccountTransaction,
+        ) -> RpcResult<DeployAccountTransactionResult> {
+            unreachable!()
+        }
+
+        async fn add_invoke_transaction(
+            &self,
+            invoke_transaction: BroadcastedInvokeTransaction,
+        ) -> RpcResult<InvokeTransactionResult> {
+            if self.rejects {
+                return Err(StarknetRpcApiError::ValidationFailure.into());
+            }
+            self.kept.lock().unwrap().push(invoke_transaction);
+            Ok(InvokeTransactionResult { transaction_hash: Felt::from(0xabcdef) })
+        }
+    }
+
+    #[rstest]
+    #[case::kept(false)]
+    #[case::rejected_locally(true)]
+    #[tokio::test]
+    async fn forward_and_keep_invoke_transaction(#[case] rejects: bool) {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gateway/add_transaction")
+            .with_body(json!({ "code": "TRANSACTION_RECEIVED", "transaction_hash": "0xabcdef" }).to_string())
+            .create_async()
+            .await;
+        let provider = ForwardAndKeepProvider::new(forward_to(&server), LocalMempool { rejects, ..Default::default() });
+
+        let res = provider.add_invoke_transaction(invoke_transaction()).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(res.transaction_hash, Felt::from(0xabcdef));
+        let expected = if rejects { vec![] } else { vec![invoke_transaction()] };
+        assert_eq!(*provider.local.kept.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn forward_and_keep_rejected_by_gateway() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gateway/add_transaction")
+            .with_status(400)
+            .with_body(json!({ "code": "StarknetErrorCode.VALIDATE_FAILURE", "message": "gateway error" }).to_string())
+            .create_async()
+            .await;
+        let provider = ForwardAndKeepProvider::new(forward_to(&server), LocalMempool::default());
+
+        let err = provider.add_invoke_transaction(invoke_transaction()).await.unwrap_err();
+
+        mock.assert_async().await;
+        assert_eq!(err.code(), i32::from(&StarknetRpcApiError::ValidationFailure));
+        assert!(provider.local.kept.lock().unwrap().is_empty());
+    }
+}
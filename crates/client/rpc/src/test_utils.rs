@@ -0,0 +1,25 @@
+//! Fixtures shared by the tests of the RPC methods.
+
+use std::sync::Arc;
+
+use dc_db::DeoxysBackend;
+use dc_mempool::Mempool;
+use dp_convert::ToFelt;
+use starknet_providers::SequencerGatewayProvider;
+
+use crate::providers::ForwardToProvider;
+use crate::{ChainHandle, Starknet};
+
+/// The RPC over `backend`. The transactions it does not add to `mempool` are forwarded to a gateway that is never
+/// reached.
+pub(crate) fn starknet_over(backend: Arc<DeoxysBackend>, mempool: Option<Arc<Mempool>>) -> Starknet {
+    let url: url::Url = "http://localhost:1".parse().unwrap();
+    let add_transaction_provider = Arc::new(ForwardToProvider::new(SequencerGatewayProvider::new(
+        url.join("gateway").unwrap(),
+        url.join("feeder_gateway").unwrap(),
+        backend.chain_config().chain_id.clone().to_felt(),
+    )));
+    let chain_config = crate::ChainConfig { feeder_gateway: url.clone(), gateway: url };
+    let chain = ChainHandle::from_backend(&backend);
+    Starknet::new(backend, chain_config, chain, add_transaction_provider, mempool, None)
+}
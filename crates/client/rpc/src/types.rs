@@ -1,10 +1,26 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::num::ParseIntError;
+use std::time::UNIX_EPOCH;
+
+use blockifier::bouncer::BouncerWeights;
+use blockifier::transaction::transaction_types::TransactionType;
+use dc_db::block_db::SyncStall;
+use dc_db::class_db::ClassDeclaration;
+use dc_db::worker_health::WorkerStatus;
+use dc_mempool::preview::BlockPreview;
+use dc_mempool::{ArrivedAtTimestamp, MempoolTxSummary};
+use dp_block::{BlockVerification, EventIndex};
+use dp_receipt::ExecutionResult;
+use serde::{Deserialize, Serialize};
+use starknet_core::types::{Felt, L1DataAvailabilityMode, ResourcePrice};
+
+pub use crate::utils::broadcasted::LenientFelts;
 
 #[derive(PartialEq, Eq, Debug, Default)]
 pub struct ContinuationToken {
     pub block_n: u64,
-    pub event_n: u64,
+    pub event_n: EventIndex,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -26,12 +42,202 @@ impl ContinuationToken {
             return Err(ParseTokenError::WrongToken);
         }
         let block_n = arr[0].parse::<u64>().map_err(ParseTokenError::ParseFailed)?;
-        let event_n = arr[1].parse::<u64>().map_err(ParseTokenError::ParseFailed)?;
+        let event_n = arr[1].parse::<u32>().map(EventIndex).map_err(ParseTokenError::ParseFailed)?;
 
         Ok(ContinuationToken { block_n, event_n })
     }
 }
 
+/// Type of a transaction sitting in the mempool.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MempoolTxType {
+    Declare,
+    DeployAccount,
+    Invoke,
+    L1Handler,
+}
+
+impl From<TransactionType> for MempoolTxType {
+    fn from(value: TransactionType) -> Self {
+        match value {
+            TransactionType::Declare => Self::Declare,
+            TransactionType::DeployAccount => Self::DeployAccount,
+            TransactionType::InvokeFunction => Self::Invoke,
+            TransactionType::L1Handler => Self::L1Handler,
+        }
+    }
+}
+
+/// A transaction sitting in the mempool, as returned by `deoxys_getMempoolTransactions`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct MempoolTransactionSummary {
+    pub transaction_hash: Felt,
+    pub sender_address: Felt,
+    pub nonce: Felt,
+    pub r#type: MempoolTxType,
+    /// Arrival time, in milliseconds since the unix epoch.
+    pub arrived_at: u64,
+    /// The declared class hash, for declare transactions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_hash: Option<Felt>,
+}
+
+impl From<MempoolTxSummary> for MempoolTransactionSummary {
+    fn from(value: MempoolTxSummary) -> Self {
+        Self {
+            transaction_hash: value.tx_hash,
+            sender_address: value.sender_address,
+            nonce: value.nonce,
+            r#type: value.tx_type.into(),
+            arrived_at: unix_millis(value.arrived_at),
+            class_hash: value.class_hash,
+        }
+    }
+}
+
+/// Statistics about the whole mempool.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct MempoolStats {
+    pub tx_count: u64,
+    pub distinct_senders: u64,
+    /// Arrival time of the oldest transaction, in milliseconds since the unix epoch.
+    pub oldest_arrived_at: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct MempoolTransactionsPage {
+    pub transactions: Vec<MempoolTransactionSummary>,
+    pub stats: MempoolStats,
+}
+
+/// Resources of a block, as counted by the bouncer.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BlockResources {
+    pub gas: u64,
+    pub n_steps: u64,
+    pub n_events: u64,
+    pub message_segment_length: u64,
+    pub state_diff_size: u64,
+    /// Builtin usage, by builtin name.
+    pub builtins: BTreeMap<String, u64>,
+}
+
+impl From<BouncerWeights> for BlockResources {
+    fn from(value: BouncerWeights) -> Self {
+        let builtins = &value.builtin_count;
+        let builtins = [
+            ("add_mod", builtins.add_mod),
+            ("bitwise", builtins.bitwise),
+            ("ecdsa", builtins.ecdsa),
+            ("ec_op", builtins.ec_op),
+            ("keccak", builtins.keccak),
+            ("mul_mod", builtins.mul_mod),
+            ("pedersen", builtins.pedersen),
+            ("poseidon", builtins.poseidon),
+            ("range_check", builtins.range_check),
+            ("range_check96", builtins.range_check96),
+        ];
+        Self {
+            gas: value.gas as u64,
+            n_steps: value.n_steps as u64,
+            n_events: value.n_events as u64,
+            message_segment_length: value.message_segment_length as u64,
+            state_diff_size: value.state_diff_size as u64,
+            builtins: builtins.into_iter().map(|(name, count)| (name.to_owned(), count as u64)).collect(),
+        }
+    }
+}
+
+/// Sum of the actual fees of the transactions of a block, by fee unit.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct FeesCollected {
+    pub wei: Felt,
+    pub fri: Felt,
+}
+
+/// The block being produced, as it would be if it was closed now. Returned by `deoxys_previewPendingBlock`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PendingBlockPreview {
+    pub block_number: u64,
+    /// The hash the block would get if it was closed now.
+    pub block_hash: Felt,
+    pub parent_hash: Felt,
+    /// The global state root after the block.
+    pub new_root: Felt,
+    pub sequencer_address: Felt,
+    /// The timestamp the block would get if it was closed now.
+    pub timestamp: u64,
+    pub starknet_version: String,
+    pub l1_gas_price: ResourcePrice,
+    pub l1_data_gas_price: ResourcePrice,
+    pub l1_da_mode: L1DataAvailabilityMode,
+    pub transaction_count: u64,
+    pub transaction_commitment: Felt,
+    pub event_count: u64,
+    pub event_commitment: Felt,
+    pub receipt_commitment: Felt,
+    pub state_diff_length: u64,
+    pub state_diff_commitment: Felt,
+    /// Hashes of the transactions of the block, in execution order.
+    pub transactions: Vec<Felt>,
+    pub resources_used: BlockResources,
+    /// The bouncer limits of a block.
+    pub resources_limit: BlockResources,
+    /// Serialized size of the transactions and receipts of the block, in bytes.
+    pub size: u64,
+    /// Size above which new transactions are deferred to the next block.
+    pub size_limit: u64,
+    pub fees_collected: FeesCollected,
+}
+
+impl From<BlockPreview> for PendingBlockPreview {
+    fn from(value: BlockPreview) -> Self {
+        let BlockPreview {
+            block_number,
+            header,
+            commitments,
+            global_state_root,
+            block_hash,
+            bouncer_weights,
+            block_max_capacity,
+            block_size,
+            max_block_size,
+            fees_wei,
+            fees_fri,
+        } = value;
+        Self {
+            block_number,
+            block_hash,
+            parent_hash: header.parent_block_hash,
+            new_root: global_state_root,
+            sequencer_address: header.sequencer_address,
+            timestamp: header.block_timestamp,
+            starknet_version: header.protocol_version.to_string(),
+            l1_gas_price: header.l1_gas_price.l1_gas_price(),
+            l1_data_gas_price: header.l1_gas_price.l1_data_gas_price(),
+            l1_da_mode: header.l1_da_mode.into(),
+            transaction_count: commitments.transaction_count,
+            transaction_commitment: commitments.transaction_commitment,
+            event_count: commitments.event_count,
+            event_commitment: commitments.event_commitment,
+            receipt_commitment: commitments.receipt_commitment,
+            state_diff_length: commitments.state_diff_length,
+            state_diff_commitment: commitments.state_diff_commitment,
+            transactions: commitments.tx_hashes,
+            resources_used: bouncer_weights.into(),
+            resources_limit: block_max_capacity.into(),
+            size: block_size,
+            size_limit: max_block_size,
+            fees_collected: FeesCollected { wei: fees_wei, fri: fees_fri },
+        }
+    }
+}
+
+pub(crate) fn unix_millis(timestamp: ArrivedAtTimestamp) -> u64 {
+    timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
@@ -43,8 +249,8 @@ mod tests {
     #[case(1, 4, "1-4")]
     #[case(2, 4, "2-4")]
     #[case(0, 4, "0-4")]
-    fn to_string_works(#[case] block_n: u64, #[case] event_n: u64, #[case] expected: String) {
-        let token = ContinuationToken { block_n, event_n };
+    fn to_string_works(#[case] block_n: u64, #[case] event_n: u32, #[case] expected: String) {
+        let token = ContinuationToken { block_n, event_n: EventIndex(event_n) };
         assert_eq!(expected, token.to_string())
     }
 
@@ -52,8 +258,8 @@ mod tests {
     #[case("0-0", 0, 0)]
     #[case("1-4", 1, 4)]
     #[case("2-4", 2, 4)]
-    fn parse_works(#[case] string_token: String, #[case] block_n: u64, #[case] event_n: u64) {
-        let expected = ContinuationToken { block_n, event_n };
+    fn parse_works(#[case] string_token: String, #[case] block_n: u64, #[case] event_n: u32) {
+        let expected = ContinuationToken { block_n, event_n: EventIndex(event_n) };
         assert_eq!(expected, ContinuationToken::parse(string_token).unwrap());
     }
 
@@ -70,8 +276,233 @@ mod tests {
     #[case("2y,4")]
     #[case("30,255g")]
     #[case("1,1,")]
+    #[case("1-4294967296")]
     fn parse_u64_should_fail(#[case] string_token: String) {
         let result = ContinuationToken::parse(string_token);
         assert!(result.is_err());
     }
 }
+
+/// Why the sync stopped importing blocks, as returned by `deoxys_getSyncStall`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SyncStallReason {
+    /// The state root computed from the state diff of the block does not match the one in its header.
+    MismatchedStateRoot { block_number: u64, block_hash: Felt, expected_state_root: Felt, computed_state_root: Felt },
+    /// The chain of the feeder forks from ours deeper than the sync reverts on its own. The common ancestor is `None`
+    /// when the fork cannot be reverted.
+    DeepReorg { tip_block_number: u64, common_ancestor: Option<u64> },
+}
+
+impl From<SyncStall> for SyncStallReason {
+    fn from(value: SyncStall) -> Self {
+        match value {
+            SyncStall::MismatchedStateRoot { block_n, block_hash, expected, got } => Self::MismatchedStateRoot {
+                block_number: block_n,
+                block_hash,
+                expected_state_root: expected,
+                computed_state_root: got,
+            },
+            SyncStall::DeepReorg { tip_block_n, common_ancestor } => {
+                Self::DeepReorg { tip_block_number: tip_block_n, common_ancestor }
+            }
+        }
+    }
+}
+
+/// What the node verified itself about a block, as returned by `deoxys_getBlockVerification`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct BlockVerificationStatus {
+    pub verified_hash: bool,
+    pub verified_commitments: bool,
+    pub verified_root: bool,
+    pub signature_checked: bool,
+}
+
+impl From<BlockVerification> for BlockVerificationStatus {
+    fn from(value: BlockVerification) -> Self {
+        Self {
+            verified_hash: value.contains(BlockVerification::VERIFIED_HASH),
+            verified_commitments: value.contains(BlockVerification::VERIFIED_COMMITMENTS),
+            verified_root: value.contains(BlockVerification::VERIFIED_ROOT),
+            signature_checked: value.contains(BlockVerification::SIGNATURE_CHECKED),
+        }
+    }
+}
+
+/// Gas prices of a block, as returned by `deoxys_getGasPriceHistory`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BlockGasPrices {
+    pub block_number: u64,
+    pub l1_gas_price: ResourcePrice,
+    pub l1_data_gas_price: ResourcePrice,
+}
+
+/// A percentile of the gas prices of a range of blocks. Every price is computed on its own: they do not necessarily
+/// come from the same block.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GasPricesPercentile {
+    pub l1_gas_price: ResourcePrice,
+    pub l1_data_gas_price: ResourcePrice,
+}
+
+/// The gas prices of the latest blocks, returned by `deoxys_getGasPriceHistory`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GasPriceHistory {
+    /// Oldest block first.
+    pub blocks: Vec<BlockGasPrices>,
+    pub p50: GasPricesPercentile,
+    pub p90: GasPricesPercentile,
+    /// The p90 of the L1 gas price with a margin, to be used as the maximum L1 gas price of a transaction.
+    pub suggested_max_l1_gas_price: ResourcePrice,
+}
+
+/// A block as defined by the Starknet specification, along with the fields this node adds to it. The clients following
+/// the specification ignore them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlockWithExtensions<B> {
+    #[serde(flatten)]
+    pub block: B,
+    /// Absent from the pending block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deoxys_verification: Option<BlockVerificationStatus>,
+    /// The receipt commitment of the header, which the specification does not include. Absent from the pending block
+    /// and from the blocks before Starknet 0.13.2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deoxys_receipt_commitment: Option<Felt>,
+}
+
+/// The execution status of a transaction, as the sequencer reported it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExecutionStatus {
+    Succeeded,
+    Reverted,
+    Rejected,
+}
+
+impl From<&ExecutionResult> for ExecutionStatus {
+    fn from(value: &ExecutionResult) -> Self {
+        match value {
+            ExecutionResult::Succeeded => Self::Succeeded,
+            ExecutionResult::Reverted { .. } => Self::Reverted,
+            ExecutionResult::Rejected { .. } => Self::Rejected,
+        }
+    }
+}
+
+/// A transaction receipt as defined by the Starknet specification, along with the fields this node adds to it. The
+/// specification has no rejected status: the rejected transactions are reverted in the receipt, and
+/// `deoxys_execution_status` tells them apart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReceiptWithExtensions<R> {
+    #[serde(flatten)]
+    pub receipt: R,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deoxys_execution_status: Option<ExecutionStatus>,
+}
+
+/// A class declared in a confirmed block, as listed by `deoxys_listClasses`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct DeclaredClass {
+    pub class_hash: Felt,
+    pub declared_at_block: u64,
+}
+
+/// A page of the classes known by the node, ordered by class hash. The continuation token is `None` on the last page.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ClassesPage {
+    pub classes: Vec<DeclaredClass>,
+    pub continuation_token: Option<String>,
+}
+
+/// A contract of a class, as listed by `deoxys_getContractsByClassHash`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ClassContract {
+    pub contract_address: Felt,
+    pub deployed_at_block: u64,
+}
+
+/// A page of the contracts of a class, ordered by address. The continuation token is `None` on the last page.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ContractsPage {
+    pub contracts: Vec<ClassContract>,
+    pub continuation_token: Option<String>,
+}
+
+/// A contract deployed by a block, as listed by `deoxys_getDeployedContracts`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct DeployedContract {
+    pub contract_address: Felt,
+    pub class_hash: Felt,
+}
+
+/// Where a class was declared, as returned by `deoxys_getClassDeclarationInfo`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ClassDeclarationInfo {
+    pub block_number: u64,
+    /// `None` for the classes declared without a declare transaction, such as the classes of a genesis block.
+    pub transaction_hash: Option<Felt>,
+}
+
+impl From<ClassDeclaration> for ClassDeclarationInfo {
+    fn from(value: ClassDeclaration) -> Self {
+        Self { block_number: value.block_n, transaction_hash: value.transaction_hash }
+    }
+}
+
+/// A block which changed the nonce of a contract, as listed by `deoxys_getNonceHistory`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NonceChange {
+    pub block_number: u64,
+    /// The nonce of the contract once the block is applied.
+    pub nonce: Felt,
+}
+
+/// A page of the nonce changes of a contract, in block order. The continuation token is `None` on the last page.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NonceHistoryPage {
+    pub changes: Vec<NonceChange>,
+    pub continuation_token: Option<String>,
+}
+
+/// Overview of the node, as returned by `deoxys_status`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NodeStatus {
+    /// `None` when the node did not tell its version to the RPC server.
+    pub version: Option<String>,
+    pub chain_id: Felt,
+    /// `None` when the database holds no block.
+    pub current_block_number: Option<u64>,
+    /// Highest block seen on the feeder gateway, `None` until the sync reaches it.
+    pub highest_block_number: Option<u64>,
+    /// `None` when the node has no pending block.
+    pub pending_block_transaction_count: Option<u64>,
+    /// `None` when the node does not run a mempool.
+    pub mempool_transaction_count: Option<u64>,
+    /// Size of every column of the database on disk, in bytes.
+    pub database_size: u64,
+    pub l1_last_confirmed_block_number: Option<u64>,
+    /// Last time the L1 endpoint answered the node, in milliseconds since the unix epoch.
+    pub l1_last_polled_at: Option<u64>,
+    pub workers: Vec<WorkerHealthStatus>,
+}
+
+/// Liveness of a long-running worker of the node.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WorkerHealthStatus {
+    pub name: String,
+    /// `false` when the worker did not make progress for too long.
+    pub alive: bool,
+    pub secs_since_last_activity: u64,
+}
+
+impl From<WorkerStatus> for WorkerHealthStatus {
+    fn from(value: WorkerStatus) -> Self {
+        Self {
+            name: value.name.into(),
+            alive: value.alive,
+            secs_since_last_activity: value.since_last_activity.as_secs(),
+        }
+    }
+}
@@ -1 +1,56 @@
+use starknet_core::types::{BlockStatus, TransactionFinalityStatus};
 
+/// The L1 finality of the blocks, as verified by the L1 sync of the node. It is read once per request, so that all the
+/// blocks and transactions of a response agree on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1Finality {
+    /// `None` when no state update was verified on L1 yet.
+    last_confirmed_block: Option<u64>,
+}
+
+impl L1Finality {
+    pub fn new(last_confirmed_block: Option<u64>) -> Self {
+        Self { last_confirmed_block }
+    }
+
+    /// `block_n` is `None` for the pending block, which is never accepted on L1.
+    pub fn is_accepted_on_l1(&self, block_n: Option<u64>) -> bool {
+        matches!((block_n, self.last_confirmed_block), (Some(block_n), Some(last)) if block_n <= last)
+    }
+
+    pub fn block_status(&self, block_n: Option<u64>) -> BlockStatus {
+        match block_n {
+            None => BlockStatus::Pending,
+            Some(_) if self.is_accepted_on_l1(block_n) => BlockStatus::AcceptedOnL1,
+            Some(_) => BlockStatus::AcceptedOnL2,
+        }
+    }
+
+    pub fn finality_status(&self, block_n: Option<u64>) -> TransactionFinalityStatus {
+        if self.is_accepted_on_l1(block_n) {
+            TransactionFinalityStatus::AcceptedOnL1
+        } else {
+            TransactionFinalityStatus::AcceptedOnL2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_l1_boundary() {
+        let finality = L1Finality::new(Some(10));
+        assert_eq!(finality.block_status(Some(9)), BlockStatus::AcceptedOnL1);
+        assert_eq!(finality.block_status(Some(10)), BlockStatus::AcceptedOnL1);
+        assert_eq!(finality.block_status(Some(11)), BlockStatus::AcceptedOnL2);
+        assert_eq!(finality.block_status(None), BlockStatus::Pending);
+        assert_eq!(finality.finality_status(None), TransactionFinalityStatus::AcceptedOnL2);
+
+        // Nothing is verified on L1 yet, not even the genesis block.
+        let finality = L1Finality::new(None);
+        assert_eq!(finality.block_status(Some(0)), BlockStatus::AcceptedOnL2);
+        assert_eq!(finality.finality_status(Some(0)), TransactionFinalityStatus::AcceptedOnL2);
+    }
+}
@@ -0,0 +1,388 @@
+//! Some SDKs send the felts of the broadcasted transactions as decimal strings, which the gateway accepts. They are
+//! converted to canonical hex before the transactions are deserialized, so that they are not read as hex.
+
+use num_bigint::BigUint;
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use starknet_types_core::felt::Felt;
+
+/// Fields of the broadcasted transactions holding a felt.
+const FELT_FIELDS: &[&str] = &[
+    "sender_address",
+    "max_fee",
+    "version",
+    "nonce",
+    "compiled_class_hash",
+    "contract_address_salt",
+    "class_hash",
+    "tip",
+];
+/// Fields of the broadcasted transactions holding a list of felts.
+const FELT_LIST_FIELDS: &[&str] =
+    &["calldata", "signature", "constructor_calldata", "paymaster_data", "account_deployment_data"];
+/// Fields of each resource of `resource_bounds`.
+const RESOURCE_BOUNDS_FIELDS: &[&str] = &["max_amount", "max_price_per_unit"];
+
+/// A broadcasted transaction, or a list of them, whose felts are `0x`-prefixed hex or decimal strings. The contract
+/// class of a declare transaction is read as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LenientFelts<T>(pub T);
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for LenientFelts<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        match &mut value {
+            Value::Array(transactions) => transactions.iter_mut().try_for_each(normalize_transaction),
+            transaction => normalize_transaction(transaction),
+        }
+        .map_err(D::Error::custom)?;
+        serde_json::from_value(value).map(Self).map_err(D::Error::custom)
+    }
+}
+
+fn normalize_transaction(transaction: &mut Value) -> Result<(), String> {
+    let Value::Object(transaction) = transaction else { return Ok(()) };
+    for (field, value) in transaction.iter_mut() {
+        let field = field.as_str();
+        if FELT_FIELDS.contains(&field) {
+            normalize_felt(field, value)?;
+        } else if FELT_LIST_FIELDS.contains(&field) {
+            if let Value::Array(values) = value {
+                values.iter_mut().try_for_each(|value| normalize_felt(field, value))?;
+            }
+        } else if field == "resource_bounds" {
+            let Value::Object(resources) = value else { continue };
+            for resource in resources.values_mut() {
+                let Value::Object(bounds) = resource else { continue };
+                for (field, value) in bounds.iter_mut() {
+                    if RESOURCE_BOUNDS_FIELDS.contains(&field.as_str()) {
+                        normalize_felt(field, value)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a felt string with its canonical hex. The other values are left for the deserialization to reject.
+fn normalize_felt(field: &str, value: &mut Value) -> Result<(), String> {
+    let Value::String(string) = value else { return Ok(()) };
+    let felt = parse_felt(string).ok_or_else(|| format!("Invalid felt `{string}` for `{field}`"))?;
+    *value = Value::String(format!("{felt:#x}"));
+    Ok(())
+}
+
+/// A `0x`-prefixed hex or a decimal string, below the field modulus.
+fn parse_felt(string: &str) -> Option<Felt> {
+    let (digits, radix) = match string.strip_prefix("0x").or_else(|| string.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (string, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let n = BigUint::parse_bytes(digits.as_bytes(), radix)?;
+    (n <= BigUint::from_bytes_be(&Felt::MAX.to_bytes_be())).then(|| Felt::from_bytes_be_slice(&n.to_bytes_be()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use starknet_core::types::{
+        BroadcastedDeclareTransaction, BroadcastedDeployAccountTransaction, BroadcastedInvokeTransaction,
+        BroadcastedTransaction,
+    };
+
+    use super::*;
+
+    /// The field modulus.
+    const P: &str = "3618502788666131213697322783095070105623107215331596699973092056135872020481";
+
+    fn sierra_class() -> Value {
+        json!({
+            "sierra_program": ["0x1", "0x2"],
+            "contract_class_version": "0.1.0",
+            "entry_points_by_type": { "CONSTRUCTOR": [], "EXTERNAL": [], "L1_HANDLER": [] },
+            "abi": "[]"
+        })
+    }
+
+    fn legacy_class() -> Value {
+        json!({
+            "program": "H4sIAAAAAAAA/6uuBQBDv6ajAgAAAA==",
+            "entry_points_by_type": { "CONSTRUCTOR": [], "EXTERNAL": [], "L1_HANDLER": [] },
+            "abi": []
+        })
+    }
+
+    /// Payloads of starknet.js, with hex felts, and the same transactions with the decimal felts of starknet.py.
+    fn invoke_v1() -> (Value, Value) {
+        (
+            json!({
+                "type": "INVOKE",
+                "sender_address": "0x4a6b2d1b4e7ae1e4c0b6b8a54a6c0f22b5e2bb35c1a2e4d5c3a1f9e7d6b5c4a",
+                "calldata": ["0x1", "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7", "0x3"],
+                "max_fee": "0x2386f26fc10000",
+                "version": "0x1",
+                "signature": ["0x1f4", "0x2a"],
+                "nonce": "0xb"
+            }),
+            json!({
+                "type": "INVOKE",
+                "sender_address": "2103889009834040018209364713858787316998219880545399468932372606651513207882",
+                "calldata": ["1", "2087021424722619777119509474943472645767659996348769578120564519014510906823", "3"],
+                "max_fee": "10000000000000000",
+                "version": "1",
+                "signature": ["500", "42"],
+                "nonce": "11"
+            }),
+        )
+    }
+
+    fn resource_bounds_hex() -> Value {
+        json!({
+            "l1_gas": { "max_amount": "0x186a0", "max_price_per_unit": "0x5af3107a4000" },
+            "l2_gas": { "max_amount": "0x0", "max_price_per_unit": "0x0" }
+        })
+    }
+
+    fn resource_bounds_dec() -> Value {
+        json!({
+            "l1_gas": { "max_amount": "100000", "max_price_per_unit": "100000000000000" },
+            "l2_gas": { "max_amount": "0", "max_price_per_unit": "0" }
+        })
+    }
+
+    fn invoke_v3() -> (Value, Value) {
+        (
+            json!({
+                "type": "INVOKE",
+                "sender_address": "0x4a6b2d1b4e7ae1e4c0b6b8a54a6c0f22b5e2bb35c1a2e4d5c3a1f9e7d6b5c4a",
+                "calldata": ["0x1", "0x2"],
+                "version": "0x3",
+                "signature": ["0x1f4", "0x2a"],
+                "nonce": "0xb",
+                "resource_bounds": resource_bounds_hex(),
+                "tip": "0x0",
+                "paymaster_data": [],
+                "account_deployment_data": ["0x5"],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+            json!({
+                "type": "INVOKE",
+                "sender_address": "2103889009834040018209364713858787316998219880545399468932372606651513207882",
+                "calldata": ["1", "2"],
+                "version": "3",
+                "signature": ["500", "42"],
+                "nonce": "11",
+                "resource_bounds": resource_bounds_dec(),
+                "tip": "0",
+                "paymaster_data": [],
+                "account_deployment_data": ["5"],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+        )
+    }
+
+    fn declare_v1() -> (Value, Value) {
+        (
+            json!({
+                "type": "DECLARE",
+                "sender_address": "0x4a6b2d1b4e7ae1e4c0b6b8a54a6c0f22b5e2bb35c1a2e4d5c3a1f9e7d6b5c4a",
+                "max_fee": "0x2386f26fc10000",
+                "version": "0x1",
+                "signature": ["0x1f4"],
+                "nonce": "0xb",
+                "contract_class": legacy_class()
+            }),
+            json!({
+                "type": "DECLARE",
+                "sender_address": "2103889009834040018209364713858787316998219880545399468932372606651513207882",
+                "max_fee": "10000000000000000",
+                "version": "1",
+                "signature": ["500"],
+                "nonce": "11",
+                "contract_class": legacy_class()
+            }),
+        )
+    }
+
+    fn declare_v2() -> (Value, Value) {
+        (
+            json!({
+                "type": "DECLARE",
+                "sender_address": "0x4a6b2d1b4e7ae1e4c0b6b8a54a6c0f22b5e2bb35c1a2e4d5c3a1f9e7d6b5c4a",
+                "compiled_class_hash": "0x3e9",
+                "max_fee": "0x2386f26fc10000",
+                "version": "0x2",
+                "signature": ["0x1f4"],
+                "nonce": "0xb",
+                "contract_class": sierra_class()
+            }),
+            json!({
+                "type": "DECLARE",
+                "sender_address": "2103889009834040018209364713858787316998219880545399468932372606651513207882",
+                "compiled_class_hash": "1001",
+                "max_fee": "10000000000000000",
+                "version": "2",
+                "signature": ["500"],
+                "nonce": "11",
+                "contract_class": sierra_class()
+            }),
+        )
+    }
+
+    fn declare_v3() -> (Value, Value) {
+        (
+            json!({
+                "type": "DECLARE",
+                "sender_address": "0x4a6b2d1b4e7ae1e4c0b6b8a54a6c0f22b5e2bb35c1a2e4d5c3a1f9e7d6b5c4a",
+                "compiled_class_hash": "0x3e9",
+                "version": "0x3",
+                "signature": ["0x1f4"],
+                "nonce": "0xb",
+                "contract_class": sierra_class(),
+                "resource_bounds": resource_bounds_hex(),
+                "tip": "0x0",
+                "paymaster_data": [],
+                "account_deployment_data": [],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+            json!({
+                "type": "DECLARE",
+                "sender_address": "2103889009834040018209364713858787316998219880545399468932372606651513207882",
+                "compiled_class_hash": "1001",
+                "version": "3",
+                "signature": ["500"],
+                "nonce": "11",
+                "contract_class": sierra_class(),
+                "resource_bounds": resource_bounds_dec(),
+                "tip": "0",
+                "paymaster_data": [],
+                "account_deployment_data": [],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+        )
+    }
+
+    fn deploy_account_v1() -> (Value, Value) {
+        (
+            json!({
+                "type": "DEPLOY_ACCOUNT",
+                "max_fee": "0x2386f26fc10000",
+                "version": "0x1",
+                "signature": ["0x1f4", "0x2a"],
+                "nonce": "0x0",
+                "contract_address_salt": "0x7b",
+                "constructor_calldata": ["0x7b"],
+                "class_hash": "0x1a736d6ed154502257f02b1ccdf4d9d1089f80811cd6acad48e6b6a9d1f2003"
+            }),
+            json!({
+                "type": "DEPLOY_ACCOUNT",
+                "max_fee": "10000000000000000",
+                "version": "1",
+                "signature": ["500", "42"],
+                "nonce": "0",
+                "contract_address_salt": "123",
+                "constructor_calldata": ["123"],
+                "class_hash": "744836003106232087497488960604506339453657541981089838546656591592622784515"
+            }),
+        )
+    }
+
+    fn deploy_account_v3() -> (Value, Value) {
+        (
+            json!({
+                "type": "DEPLOY_ACCOUNT",
+                "version": "0x3",
+                "signature": ["0x1f4", "0x2a"],
+                "nonce": "0x0",
+                "contract_address_salt": "0x7b",
+                "constructor_calldata": ["0x7b"],
+                "class_hash": "0x1a736d6ed154502257f02b1ccdf4d9d1089f80811cd6acad48e6b6a9d1f2003",
+                "resource_bounds": resource_bounds_hex(),
+                "tip": "0x0",
+                "paymaster_data": [],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+            json!({
+                "type": "DEPLOY_ACCOUNT",
+                "version": "3",
+                "signature": ["500", "42"],
+                "nonce": "0",
+                "contract_address_salt": "123",
+                "constructor_calldata": ["123"],
+                "class_hash": "744836003106232087497488960604506339453657541981089838546656591592622784515",
+                "resource_bounds": resource_bounds_dec(),
+                "tip": "0",
+                "paymaster_data": [],
+                "nonce_data_availability_mode": "L1",
+                "fee_data_availability_mode": "L1"
+            }),
+        )
+    }
+
+    /// Both payloads are read as the transaction the hex payload is read as without normalization.
+    fn assert_same_transaction<T: DeserializeOwned + serde::Serialize>((hex, dec): (Value, Value)) {
+        let expected = serde_json::to_value(serde_json::from_value::<T>(hex.clone()).unwrap()).unwrap();
+        let from_hex = serde_json::from_value::<LenientFelts<T>>(hex).unwrap().0;
+        let from_dec = serde_json::from_value::<LenientFelts<T>>(dec).unwrap().0;
+        assert_eq!(serde_json::to_value(from_hex).unwrap(), expected);
+        assert_eq!(serde_json::to_value(from_dec).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_invoke() {
+        assert_same_transaction::<BroadcastedInvokeTransaction>(invoke_v1());
+        assert_same_transaction::<BroadcastedInvokeTransaction>(invoke_v3());
+    }
+
+    #[test]
+    fn test_declare() {
+        assert_same_transaction::<BroadcastedDeclareTransaction>(declare_v1());
+        assert_same_transaction::<BroadcastedDeclareTransaction>(declare_v2());
+        assert_same_transaction::<BroadcastedDeclareTransaction>(declare_v3());
+    }
+
+    #[test]
+    fn test_deploy_account() {
+        assert_same_transaction::<BroadcastedDeployAccountTransaction>(deploy_account_v1());
+        assert_same_transaction::<BroadcastedDeployAccountTransaction>(deploy_account_v3());
+    }
+
+    #[test]
+    fn test_transaction_list() {
+        let (hex, dec): (Vec<_>, Vec<_>) = [invoke_v1(), declare_v2(), deploy_account_v3()].into_iter().unzip();
+        assert_same_transaction::<Vec<BroadcastedTransaction>>((Value::Array(hex), Value::Array(dec)));
+    }
+
+    #[test]
+    fn test_felts_out_of_the_field_are_rejected() {
+        let max = "3618502788666131213697322783095070105623107215331596699973092056135872020480";
+        assert_eq!(parse_felt(max), Some(Felt::MAX));
+        assert_eq!(parse_felt(&format!("{:#x}", Felt::MAX)), Some(Felt::MAX));
+        assert_eq!(parse_felt(P), None);
+        assert_eq!(parse_felt("0x800000000000011000000000000000000000000000000000000000000000001"), None);
+
+        let (_, mut dec) = invoke_v1();
+        dec["nonce"] = P.into();
+        let err = serde_json::from_value::<LenientFelts<BroadcastedInvokeTransaction>>(dec).unwrap_err();
+        assert_eq!(err.to_string(), format!("Invalid felt `{P}` for `nonce`"));
+    }
+
+    #[test]
+    fn test_malformed_felts_are_rejected() {
+        for malformed in ["", "0x", "-1", "+1", "1_000", "1e3", "0xg", "12ab"] {
+            assert_eq!(parse_felt(malformed), None, "{malformed}");
+        }
+        assert_eq!(parse_felt("0X1F"), Some(Felt::from(31)));
+        assert_eq!(parse_felt("007"), Some(Felt::from(7)));
+    }
+}
@@ -1,8 +1,12 @@
 pub(crate) mod block;
+pub(crate) mod broadcasted;
+pub(crate) mod receipt;
 pub(crate) mod transaction;
 
 use std::fmt;
 
+use dc_db::DeoxysStorageError;
+
 use crate::StarknetRpcApiError;
 
 #[macro_export]
@@ -21,13 +25,23 @@ macro_rules! bail_internal_server_error {
     };
 }
 
+/// The database errors that go away on their own are returned as [`StarknetRpcApiError::ServerBusy`], so that the
+/// client retries the request.
+pub(crate) fn internal_server_error(context: impl fmt::Display, err: anyhow::Error) -> StarknetRpcApiError {
+    if DeoxysStorageError::is_transient_source(err.as_ref()) {
+        log::warn!(target: "rpc_errors", "{}: {:#}", context, err);
+        return StarknetRpcApiError::ServerBusy;
+    }
+    log::error!(target: "rpc_errors", "{}: {:#}", context, err);
+    StarknetRpcApiError::InternalServerError
+}
+
 pub trait ResultExt<T, E> {
     fn or_internal_server_error<C: fmt::Display>(self, context: C) -> Result<T, StarknetRpcApiError>;
     fn or_else_internal_server_error<C: fmt::Display, F: FnOnce() -> C>(
         self,
         context_fn: F,
     ) -> Result<T, StarknetRpcApiError>;
-    fn or_contract_error<C: fmt::Display>(self, context: C) -> Result<T, StarknetRpcApiError>;
 }
 
 impl<T, E: Into<anyhow::Error>> ResultExt<T, E> for Result<T, E> {
@@ -35,10 +49,7 @@ impl<T, E: Into<anyhow::Error>> ResultExt<T, E> for Result<T, E> {
     fn or_internal_server_error<C: fmt::Display>(self, context: C) -> Result<T, StarknetRpcApiError> {
         match self {
             Ok(val) => Ok(val),
-            Err(err) => {
-                log::error!(target: "rpc_errors", "{}: {:#}", context, E::into(err));
-                Err(StarknetRpcApiError::InternalServerError)
-            }
+            Err(err) => Err(internal_server_error(context, E::into(err))),
         }
     }
 
@@ -49,22 +60,7 @@ impl<T, E: Into<anyhow::Error>> ResultExt<T, E> for Result<T, E> {
     ) -> Result<T, StarknetRpcApiError> {
         match self {
             Ok(val) => Ok(val),
-            Err(err) => {
-                log::error!(target: "rpc_errors", "{}: {:#}", context_fn(), E::into(err));
-                Err(StarknetRpcApiError::InternalServerError)
-            }
-        }
-    }
-
-    // TODO: should this be a thing?
-    #[inline]
-    fn or_contract_error<C: fmt::Display>(self, context: C) -> Result<T, StarknetRpcApiError> {
-        match self {
-            Ok(val) => Ok(val),
-            Err(err) => {
-                log::error!(target: "rpc_errors", "Contract storage error: {context}: {:#}", E::into(err));
-                Err(StarknetRpcApiError::ContractError)
-            }
+            Err(err) => Err(internal_server_error(context_fn(), E::into(err))),
         }
     }
 }
@@ -111,3 +107,35 @@ impl<T> OptionExt<T> for Option<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error(kind: std::io::ErrorKind) -> DeoxysStorageError {
+        DeoxysStorageError::Bincode(Box::new(bincode::ErrorKind::Io(kind.into())))
+    }
+
+    #[test]
+    fn test_transient_storage_errors_are_retryable() {
+        let res: Result<(), _> = Err(io_error(std::io::ErrorKind::TimedOut));
+        assert!(matches!(res.or_internal_server_error("Getting block"), Err(StarknetRpcApiError::ServerBusy)));
+        let res: Result<(), _> = Err(anyhow::Error::from(io_error(std::io::ErrorKind::WouldBlock)).context("Reading"));
+        assert!(matches!(res.or_else_internal_server_error(|| "Getting block"), Err(StarknetRpcApiError::ServerBusy)));
+        assert!(matches!(
+            StarknetRpcApiError::from(io_error(std::io::ErrorKind::TimedOut)),
+            StarknetRpcApiError::ServerBusy
+        ));
+
+        let res: Result<(), _> = Err(io_error(std::io::ErrorKind::InvalidData));
+        assert!(matches!(res.or_internal_server_error("Getting block"), Err(StarknetRpcApiError::InternalServerError)));
+        let res: Result<(), _> = Err(anyhow::anyhow!("Not a storage error"));
+        assert!(matches!(res.or_internal_server_error("Getting block"), Err(StarknetRpcApiError::InternalServerError)));
+        assert!(matches!(
+            StarknetRpcApiError::from(DeoxysStorageError::MissingChainInfo),
+            StarknetRpcApiError::ErrUnexpectedError { .. }
+        ));
+
+        assert_eq!(i32::from(&StarknetRpcApiError::ServerBusy), 503);
+    }
+}
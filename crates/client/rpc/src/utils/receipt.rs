@@ -0,0 +1,29 @@
+use blockifier::transaction::objects::FeeType;
+use dp_block::StarknetVersion;
+use dp_receipt::{DataAvailabilityResources, PriceUnit, TransactionReceipt};
+use dp_transactions::Transaction;
+use starknet_core::types::TransactionFinalityStatus;
+
+/// Converts a stored receipt to the RPC format. Blocks of every era live in the same database, and their receipts are
+/// stored with the same shape.
+///
+/// - The execution resources are the ones stored with the receipt, whatever the version of its block.
+/// - Before 0.13.1, the data availability resources did not exist, they are zero as the spec requires them.
+/// - The fee unit is the one of the transaction: `FRI` for v3 transactions, `WEI` otherwise.
+pub(crate) fn receipt_to_rpc(
+    mut receipt: TransactionReceipt,
+    transaction: &Transaction,
+    protocol_version: StarknetVersion,
+    finality_status: TransactionFinalityStatus,
+) -> starknet_core::types::TransactionReceipt {
+    receipt.actual_fee_mut().unit = match transaction.fee_type() {
+        FeeType::Eth => PriceUnit::Wei,
+        FeeType::Strk => PriceUnit::Fri,
+    };
+
+    if protocol_version < StarknetVersion::STARKNET_VERSION_0_13_1 {
+        receipt.execution_resources_mut().data_availability = DataAvailabilityResources::default();
+    }
+
+    receipt.to_starknet_core(finality_status)
+}
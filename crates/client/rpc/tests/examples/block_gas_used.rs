@@ -0,0 +1,28 @@
+//! `example_getBlockGasUsed`: the L1 gas consumed by the transactions of a block.
+
+use dc_rpc::extensions::RpcExtensionContext;
+use jsonrpsee::types::{ErrorObject, ErrorObjectOwned};
+use jsonrpsee::RpcModule;
+use starknet_core::types::BlockId;
+
+const BLOCK_NOT_FOUND: i32 = 24;
+
+pub fn module(context: RpcExtensionContext) -> RpcModule<RpcExtensionContext> {
+    let mut module = RpcModule::new(context);
+    module
+        .register_blocking_method("example_getBlockGasUsed", |params, context| {
+            let block_id: BlockId = params.one()?;
+            block_gas_used(&context, &block_id)
+        })
+        .expect("Registering example_getBlockGasUsed");
+    module
+}
+
+fn block_gas_used(context: &RpcExtensionContext, block_id: &BlockId) -> Result<u64, ErrorObjectOwned> {
+    let block = context
+        .backend
+        .get_block(block_id)
+        .map_err(|err| ErrorObject::owned(jsonrpsee::types::error::INTERNAL_ERROR_CODE, err.to_string(), None::<()>))?
+        .ok_or_else(|| ErrorObject::owned(BLOCK_NOT_FOUND, "Block not found", None::<()>))?;
+    Ok(block.inner.receipts.iter().map(|receipt| receipt.total_gas_consumed().l1_gas).sum())
+}
@@ -0,0 +1,3 @@
+//! Extensions an app-chain could embed in its node.
+
+pub mod block_gas_used;
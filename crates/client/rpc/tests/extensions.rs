@@ -0,0 +1,96 @@
+mod examples;
+
+use std::sync::Arc;
+
+use dc_db::testing::temp_database;
+use dc_rpc::extensions::{RpcExtensionContext, RpcExtensionError, RpcExtensions};
+use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, DeoxysMaybePendingBlock, DeoxysMaybePendingBlockInfo, Header};
+use dp_receipt::{
+    DataAvailabilityResources, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit,
+    TransactionReceipt,
+};
+use jsonrpsee::{rpc_params, RpcModule};
+use starknet_core::types::BlockId;
+use starknet_types_core::felt::Felt;
+
+fn receipt(transaction_hash: u64, l1_gas: u64) -> TransactionReceipt {
+    TransactionReceipt::Invoke(InvokeTransactionReceipt {
+        transaction_hash: Felt::from(transaction_hash),
+        actual_fee: FeePayment { amount: Felt::ZERO, unit: PriceUnit::Fri },
+        messages_sent: vec![],
+        events: vec![],
+        execution_resources: ExecutionResources {
+            total_gas_consumed: DataAvailabilityResources { l1_gas, l1_data_gas: 0 },
+            ..Default::default()
+        },
+        execution_result: ExecutionResult::Succeeded,
+    })
+}
+
+/// Block 0 has two transactions, which consumed 100 and 250 L1 gas.
+async fn test_context() -> (tempfile::TempDir, RpcExtensionContext) {
+    let (temp_dir, db) = temp_database().await;
+    let backend = Arc::clone(db.backend());
+
+    let block = DeoxysMaybePendingBlock {
+        info: DeoxysMaybePendingBlockInfo::NotPending(DeoxysBlockInfo::new(
+            Header::default(),
+            vec![Felt::ONE, Felt::TWO],
+            Felt::ONE,
+        )),
+        inner: DeoxysBlockInner::new(vec![], vec![receipt(1, 100), receipt(2, 250)]),
+    };
+    backend.store_block(block, Default::default(), vec![]).unwrap();
+
+    (temp_dir, RpcExtensionContext::new(&backend))
+}
+
+#[tokio::test]
+async fn test_extension_is_served_with_the_node_methods() {
+    let (_temp_dir, context) = test_context().await;
+    let extensions = RpcExtensions::default().with_extra_module(examples::block_gas_used::module(context)).unwrap();
+    assert_eq!(extensions.method_names().collect::<Vec<_>>(), ["example_getBlockGasUsed"]);
+
+    let mut rpc_api = RpcModule::new(());
+    rpc_api.register_method("starknet_specVersion", |_, _| "0.7.1").unwrap();
+    extensions.merge_into(&mut rpc_api).unwrap();
+
+    let gas_used: u64 = rpc_api.call("example_getBlockGasUsed", rpc_params![BlockId::Number(0)]).await.unwrap();
+    assert_eq!(gas_used, 350);
+    assert!(rpc_api.call::<_, u64>("example_getBlockGasUsed", rpc_params![BlockId::Number(1)]).await.is_err());
+    let spec_version: String = rpc_api.call("starknet_specVersion", rpc_params![]).await.unwrap();
+    assert_eq!(spec_version, "0.7.1");
+}
+
+#[tokio::test]
+async fn test_namespace_collisions_are_rejected() {
+    let (_temp_dir, context) = test_context().await;
+
+    let mut module = RpcModule::new(context.clone());
+    module.register_method("starknet_blockNumber", |_, _| 0u64).unwrap();
+    assert!(matches!(
+        RpcExtensions::default().with_extra_module(module),
+        Err(RpcExtensionError::ReservedNamespace("starknet_blockNumber", "starknet"))
+    ));
+
+    let mut module = RpcModule::new(());
+    module.register_method("getBlockGasUsed", |_, _| 0u64).unwrap();
+    assert!(matches!(
+        RpcExtensions::default().with_extra_module(module),
+        Err(RpcExtensionError::MissingNamespace("getBlockGasUsed"))
+    ));
+
+    let extensions =
+        RpcExtensions::default().with_extra_module(examples::block_gas_used::module(context.clone())).unwrap();
+    assert!(matches!(
+        extensions.clone().with_extra_module(examples::block_gas_used::module(context)),
+        Err(RpcExtensionError::MethodAlreadyRegistered("example_getBlockGasUsed"))
+    ));
+
+    let mut rpc_api = RpcModule::new(());
+    rpc_api.register_method("example_getBlockGasUsed", |_, _| 0u64).unwrap();
+    assert!(matches!(
+        extensions.merge_into(&mut rpc_api),
+        Err(RpcExtensionError::MethodAlreadyRegistered("example_getBlockGasUsed"))
+    ));
+}
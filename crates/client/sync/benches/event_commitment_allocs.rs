@@ -0,0 +1,94 @@
+//! Counts the heap allocations made while computing the commitments of a block with 5000 events.
+//!
+//! Run with `cargo bench -p dc-sync --bench event_commitment_allocs`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dc_sync::convert::compute_commitments_for_block;
+use dp_block::{DeoxysBlockInner, StarknetVersion};
+use dp_receipt::{Event, ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit, TransactionReceipt};
+use dp_state_update::StateDiff;
+use starknet_types_core::felt::Felt;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const N_RECEIPTS: u64 = 500;
+const EVENTS_PER_RECEIPT: u64 = 10;
+
+fn receipts() -> Vec<TransactionReceipt> {
+    (0..N_RECEIPTS)
+        .map(|i| {
+            TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                transaction_hash: Felt::from(i),
+                actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Fri },
+                messages_sent: vec![],
+                events: (0..EVENTS_PER_RECEIPT)
+                    .map(|j| Event {
+                        from_address: Felt::from(j),
+                        keys: vec![Felt::from(i), Felt::from(j)],
+                        data: vec![Felt::from(i * j); 4],
+                    })
+                    .collect(),
+                execution_resources: Default::default(),
+                execution_result: ExecutionResult::Succeeded,
+            })
+        })
+        .collect()
+}
+
+/// Runs `f` and returns the number of allocations and of bytes allocated meanwhile, on every thread.
+fn count_allocations<R>(f: impl FnOnce() -> R) -> (usize, usize) {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+    std::hint::black_box(f());
+    (ALLOCATIONS.load(Ordering::Relaxed) - allocations, ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes)
+}
+
+fn main() {
+    let block_inner = DeoxysBlockInner::new(vec![], receipts());
+    let state_diff = StateDiff::default();
+    let n_events: usize = block_inner.receipts.iter().map(|receipt| receipt.events().len()).sum();
+
+    // Warm up the rayon thread pool so that its own allocations are not counted.
+    compute_commitments_for_block(&block_inner, &state_diff, StarknetVersion::STARKNET_VERSION_0_13_2, Felt::ZERO, 0)
+        .unwrap();
+
+    let (allocations, bytes) = count_allocations(|| {
+        compute_commitments_for_block(
+            &block_inner,
+            &state_diff,
+            StarknetVersion::STARKNET_VERSION_0_13_2,
+            Felt::ZERO,
+            0,
+        )
+    });
+    println!("block commitments ({n_events} events): {allocations} allocations, {bytes} bytes");
+
+    // What collecting owned copies of the events used to cost on top of that.
+    let (allocations, bytes) = count_allocations(|| {
+        block_inner
+            .receipts
+            .iter()
+            .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event.clone())))
+            .collect::<Vec<_>>()
+    });
+    println!("cloning the events (avoided): {allocations} allocations, {bytes} bytes");
+}
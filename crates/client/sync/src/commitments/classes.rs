@@ -13,18 +13,22 @@ use starknet_types_core::hash::{Poseidon, StarkHash};
 const CONTRACT_CLASS_HASH_VERSION: Felt =
     Felt::from_raw([115292049744600508, 18444375821049509847, 12057587991035439952, 9331882290187415277]);
 
+/// The hash of the leaf of a class in the class trie.
+pub(crate) fn class_leaf_hash(compiled_class_hash: &Felt) -> Felt {
+    Poseidon::hash(&CONTRACT_CLASS_HASH_VERSION, compiled_class_hash)
+}
+
 pub fn class_trie_root(
     backend: &DeoxysBackend,
     declared_classes: &[DeclaredClassItem],
     block_number: u64,
 ) -> Result<Felt, DeoxysStorageError> {
-    let mut class_trie = backend.class_trie();
+    let mut class_trie = backend.class_trie_mut();
 
     let updates: Vec<_> = declared_classes
         .into_par_iter()
         .map(|DeclaredClassItem { class_hash, compiled_class_hash }| {
-            let hash = Poseidon::hash(&CONTRACT_CLASS_HASH_VERSION, compiled_class_hash);
-            (*class_hash, hash)
+            (*class_hash, class_leaf_hash(compiled_class_hash))
         })
         .collect();
 
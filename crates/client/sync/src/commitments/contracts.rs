@@ -4,9 +4,10 @@ use bitvec::order::Msb0;
 use bitvec::vec::BitVec;
 use bitvec::view::AsBits;
 use bonsai_trie::id::BasicId;
+use dc_db::db_block_id::DbBlockId;
 use dc_db::DeoxysBackend;
 use dc_db::{bonsai_identifier, DeoxysStorageError};
-use dp_block::{BlockId, BlockTag};
+use dp_block::BlockN;
 use dp_state_update::{ContractStorageDiffItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StorageEntry};
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::{Pedersen, StarkHash};
@@ -38,7 +39,7 @@ pub fn contract_trie_root(
 ) -> Result<Felt, DeoxysStorageError> {
     let mut contract_leafs: HashMap<Felt, ContractLeaf> = HashMap::new();
 
-    let mut contract_storage_trie = backend.contract_storage_trie();
+    let mut contract_storage_trie = backend.contract_storage_trie_mut();
 
     log::debug!("contract_storage_trie inserting");
 
@@ -70,13 +71,13 @@ pub fn contract_trie_root(
         contract_leafs.entry(*contract_address).or_default().class_hash = Some(*class_hash);
     }
 
-    let mut contract_trie = backend.contract_trie();
+    let mut contract_trie = backend.contract_trie_mut();
 
     for (contract_address, mut leaf) in contract_leafs {
         let storage_root = contract_storage_trie.root_hash(&contract_address.to_bytes_be())?;
         leaf.storage_root = Some(storage_root);
         // TODO: parrallelize this with rayon
-        let leaf_hash = contract_state_leaf_hash(backend, &contract_address, &leaf)?;
+        let leaf_hash = contract_state_leaf_hash(backend, &contract_address, &leaf, block_number)?;
         let bytes = contract_address.to_bytes_be();
         let bv: BitVec<u8, Msb0> = bytes.as_bits()[5..].to_owned();
         contract_trie.insert(bonsai_identifier::CONTRACT, &bv, &leaf_hash)?;
@@ -96,9 +97,10 @@ pub fn contract_trie_root(
 ///
 /// # Arguments
 ///
-/// * `csd`             - Commitment state diff for the current block.
 /// * `contract_address` - The contract address.
-/// * `storage_root`     - The storage root of the contract.
+/// * `contract_leaf`    - The changes of the current block to the contract.
+/// * `block_number`     - The current block number. The fields of the leaf that the block does not change are read
+///   at the block before it.
 ///
 /// # Returns
 ///
@@ -107,23 +109,32 @@ fn contract_state_leaf_hash(
     backend: &DeoxysBackend,
     contract_address: &Felt,
     contract_leaf: &ContractLeaf,
+    block_number: u64,
 ) -> Result<Felt, DeoxysStorageError> {
-    let nonce = contract_leaf.nonce.unwrap_or(
-        backend.get_contract_nonce_at(&BlockId::Tag(BlockTag::Latest), contract_address)?.unwrap_or(Felt::ZERO),
-    );
+    // The block is not stored yet when the tries are updated during the sync, but it is when they are rebuilt.
+    let previous_block = block_number.checked_sub(1).map(|block_n| DbBlockId::BlockN(BlockN(block_n)));
 
-    let class_hash = contract_leaf.class_hash.unwrap_or(
-        backend.get_contract_class_hash_at(&BlockId::Tag(BlockTag::Latest), contract_address)?.unwrap_or(Felt::ZERO), // .ok_or(DeoxysStorageError::InconsistentStorage("Class hash not found".into()))?
-    );
+    let nonce = match (contract_leaf.nonce, &previous_block) {
+        (Some(nonce), _) => nonce,
+        (None, Some(id)) => backend.get_contract_nonce_at(id, contract_address)?.unwrap_or(Felt::ZERO),
+        (None, None) => Felt::ZERO,
+    };
 
-    let storage_root = contract_leaf
-        .storage_root
-        .ok_or(DeoxysStorageError::InconsistentStorage("Storage root need to be set".into()))?;
+    let class_hash = match (contract_leaf.class_hash, &previous_block) {
+        (Some(class_hash), _) => class_hash,
+        (None, Some(id)) => backend.get_contract_class_hash_at(id, contract_address)?.unwrap_or(Felt::ZERO),
+        (None, None) => Felt::ZERO,
+    };
 
-    // computes the contract state leaf hash
-    let contract_state_hash = Pedersen::hash(&class_hash, &storage_root);
-    let contract_state_hash = Pedersen::hash(&contract_state_hash, &nonce);
-    let contract_state_hash = Pedersen::hash(&contract_state_hash, &Felt::ZERO);
+    let storage_root =
+        contract_leaf.storage_root.ok_or_else(|| DeoxysStorageError::inconsistent("Storage root need to be set"))?;
+
+    Ok(contract_state_hash(&class_hash, &storage_root, &nonce))
+}
 
-    Ok(contract_state_hash)
+/// The hash of the leaf of a contract in the contract trie.
+pub(crate) fn contract_state_hash(class_hash: &Felt, storage_root: &Felt, nonce: &Felt) -> Felt {
+    let contract_state_hash = Pedersen::hash(class_hash, storage_root);
+    let contract_state_hash = Pedersen::hash(&contract_state_hash, nonce);
+    Pedersen::hash(&contract_state_hash, &Felt::ZERO)
 }
@@ -4,19 +4,23 @@ use rayon::prelude::*;
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::{Pedersen, Poseidon};
 
-use super::compute_root;
+use super::{compute_root, CommitmentError};
 
 /// Calculate the event commitment in memory using HashMapDb (which is more efficient for this
 /// usecase).
 ///
 /// # Arguments
 ///
-/// * `events` - The events of the block
+/// * `events_with_tx_hash` - The events of the block, borrowed from the receipts, along with the hash of the
+///   transaction that emitted them
 ///
 /// # Returns
 ///
 /// The event commitment as `Felt`.
-pub fn memory_event_commitment(events_with_tx_hash: &[(Felt, Event)], starknet_version: StarknetVersion) -> Felt {
+pub fn memory_event_commitment(
+    events_with_tx_hash: &[(Felt, &Event)],
+    starknet_version: StarknetVersion,
+) -> Result<Felt, CommitmentError> {
     if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
         memory_event_commitment_pedersen(events_with_tx_hash)
     } else {
@@ -24,28 +28,23 @@ pub fn memory_event_commitment(events_with_tx_hash: &[(Felt, Event)], starknet_v
     }
 }
 
-fn memory_event_commitment_pedersen(events_with_tx_hash: &[(Felt, Event)]) -> Felt {
+fn memory_event_commitment_pedersen(events_with_tx_hash: &[(Felt, &Event)]) -> Result<Felt, CommitmentError> {
     if events_with_tx_hash.is_empty() {
-        return Felt::ZERO;
+        return Ok(Felt::ZERO);
     }
 
-    // event hashes are computed in parallel
-    let events_hash =
-        events_with_tx_hash.into_par_iter().map(|(_, event)| event.compute_hash_pedersen()).collect::<Vec<_>>();
-
-    // once event hashes have finished computing, they are inserted into the local Bonsai db
-    compute_root::<Pedersen>(&events_hash)
+    // event hashes are computed in parallel, and inserted into the local Bonsai db as they are computed
+    compute_root::<Pedersen>("event", events_with_tx_hash.par_iter().map(|(_, event)| event.compute_hash_pedersen()))
 }
 
-fn memory_event_commitment_poseidon(events_with_tx_hash: &[(Felt, Event)]) -> Felt {
+fn memory_event_commitment_poseidon(events_with_tx_hash: &[(Felt, &Event)]) -> Result<Felt, CommitmentError> {
     if events_with_tx_hash.is_empty() {
-        return Felt::ZERO;
+        return Ok(Felt::ZERO);
     }
 
-    // event hashes are computed in parallel
-    let events_hash =
-        events_with_tx_hash.into_par_iter().map(|(hash, event)| event.compute_hash_poseidon(hash)).collect::<Vec<_>>();
-
-    // once event hashes have finished computing, they are inserted into the local Bonsai db
-    compute_root::<Poseidon>(&events_hash)
+    // event hashes are computed in parallel, and inserted into the local Bonsai db as they are computed
+    compute_root::<Poseidon>(
+        "event",
+        events_with_tx_hash.par_iter().map(|(hash, event)| event.compute_hash_poseidon(hash)),
+    )
 }
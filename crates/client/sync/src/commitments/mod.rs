@@ -4,12 +4,21 @@ mod events;
 mod receipts;
 mod transactions;
 
+use std::fmt;
+use std::sync::mpsc;
+
 use bitvec::vec::BitVec;
+use bonsai_trie::databases::HashMapDb;
+use bonsai_trie::id::{BasicId, BasicIdBuilder};
+use bonsai_trie::{BonsaiDatabase, BonsaiStorage, BonsaiStorageConfig, BonsaiStorageError};
+pub(crate) use classes::class_leaf_hash;
 use classes::class_trie_root;
+pub(crate) use contracts::contract_state_hash;
 use contracts::contract_trie_root;
-use dc_db::DeoxysBackend;
+use dc_db::{bonsai_identifier, DeoxysBackend, DeoxysStorageError};
 use dp_state_update::StateDiff;
 pub use events::memory_event_commitment;
+use rayon::prelude::*;
 pub use receipts::memory_receipt_commitment;
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::{Poseidon, StarkHash};
@@ -40,6 +49,13 @@ pub fn calculate_state_root(contracts_trie_root: Felt, classes_trie_root: Felt)
     }
 }
 
+/// The state root of the global tries as they are committed.
+pub(crate) fn global_tries_root(backend: &DeoxysBackend) -> Result<Felt, DeoxysStorageError> {
+    let contract_root = backend.contract_trie().root_hash(bonsai_identifier::CONTRACT)?;
+    let class_root = backend.class_trie().root_hash(bonsai_identifier::CLASS)?;
+    Ok(calculate_state_root(contract_root, class_root))
+}
+
 /// Update the state commitment hash value.
 ///
 /// The state commitment is the digest that uniquely (up to hash collisions) encodes the state.
@@ -54,66 +70,414 @@ pub fn calculate_state_root(contracts_trie_root: Felt, classes_trie_root: Felt)
 ///
 /// The updated state root as a `Felt`.
 pub fn update_tries_and_compute_state_root(backend: &DeoxysBackend, state_diff: &StateDiff, block_number: u64) -> Felt {
-    let StateDiff {
-        storage_diffs,
-        deprecated_declared_classes: _,
-        declared_classes,
-        deployed_contracts,
-        replaced_classes,
-        nonces,
-    } = state_diff;
+    update_tries(backend, state_diff, block_number).expect("Failed to compute the state root")
+}
+
+/// The state root of block `block_number` if its state diff was `state_diff`, without keeping the diff in the global
+/// tries. `block_number` must be the block following the last one committed to the tries.
+///
+/// The diff is committed to the tries then reverted: readers of the tries may see it in the meantime. The caller must
+/// hold [`DeoxysBackend::lock_tries_update`], so that no block is committed in between.
+pub fn preview_state_root(
+    backend: &DeoxysBackend,
+    state_diff: &StateDiff,
+    block_number: u64,
+) -> Result<Felt, DeoxysStorageError> {
+    let state_root = update_tries(backend, state_diff, block_number);
+    let reverted = backend.revert_tries_to(block_number.checked_sub(1), block_number);
+    let state_root = state_root?;
+    reverted?;
+    Ok(state_root)
+}
+
+fn update_tries(
+    backend: &DeoxysBackend,
+    state_diff: &StateDiff,
+    block_number: u64,
+) -> Result<Felt, DeoxysStorageError> {
+    let (storage_diffs, declared_classes, deployed_contracts, replaced_classes, nonces) = (
+        state_diff.storage_diffs(),
+        state_diff.declared_classes(),
+        state_diff.deployed_contracts(),
+        state_diff.replaced_classes(),
+        state_diff.nonces(),
+    );
 
     // Update contract and its storage tries
     let (contract_trie_root, class_trie_root) = rayon::join(
-        || {
-            contract_trie_root(backend, deployed_contracts, replaced_classes, nonces, storage_diffs, block_number)
-                .expect("Failed to compute contract root")
-        },
-        || class_trie_root(backend, declared_classes, block_number).expect("Failed to compute class root"),
+        || contract_trie_root(backend, deployed_contracts, replaced_classes, nonces, storage_diffs, block_number),
+        || class_trie_root(backend, declared_classes, block_number),
     );
+    if contract_trie_root.is_err() || class_trie_root.is_err() {
+        // The tries are kept between the blocks, the changes of this one must not remain in them.
+        backend.reset_tries();
+    }
+
+    Ok(calculate_state_root(contract_trie_root?, class_trie_root?))
+}
+
+/// Identifier of the in-memory commitment tries.
+//TODO: replace the identifier by an empty slice when bonsai will support it
+const IDENTIFIER: &[u8] = b"0xinmemory";
+
+#[derive(thiserror::Error, Debug)]
+pub enum CommitmentError {
+    #[error("Failed to insert {leaf} {index} into the commitment trie: {message}")]
+    Insert { leaf: &'static str, index: usize, message: String },
+    #[error("Failed to compute the root of the {leaf} commitment trie: {message}")]
+    Root { leaf: &'static str, message: String },
+}
+
+/// The trie a commitment is computed with, the leaves being keyed by their index.
+pub trait CommitmentTrie: Send {
+    type Error: fmt::Display;
+
+    fn insert(&mut self, index: usize, leaf: &Felt) -> Result<(), Self::Error>;
+    fn root(self) -> Result<Felt, Self::Error>;
+}
+
+/// Commitment trie in a `HashMapDb`, which is more efficient than the database for this usecase.
+pub type MemoryTrie<H> = BonsaiStorage<BasicId, HashMapDb<BasicId>, H>;
+
+/// A new empty [`MemoryTrie`], for the commitment of the `leaf` values.
+pub fn memory_trie<H: StarkHash + Send + Sync>(leaf: &'static str) -> Result<MemoryTrie<H>, CommitmentError> {
+    BonsaiStorage::new(HashMapDb::default(), BonsaiStorageConfig::default())
+        .map_err(|err| CommitmentError::Root { leaf, message: err.to_string() })
+}
 
-    calculate_state_root(contract_trie_root, class_trie_root)
+impl<H: StarkHash + Send + Sync> CommitmentTrie for MemoryTrie<H> {
+    type Error = BonsaiStorageError<<HashMapDb<BasicId> as BonsaiDatabase>::DatabaseError>;
+
+    fn insert(&mut self, index: usize, leaf: &Felt) -> Result<(), Self::Error> {
+        let key = BitVec::from_vec(index.to_be_bytes().to_vec());
+        BonsaiStorage::insert(self, IDENTIFIER, key.as_bitslice(), leaf)
+    }
+
+    fn root(mut self) -> Result<Felt, Self::Error> {
+        // Note that committing changes still has the greatest performance hit
+        // as this is where the root hash is calculated. Due to the Merkle structure
+        // of Bonsai Tries, this results in a trie size that grows very rapidly with
+        // each new insertion. It seems that the only vector of optimization here
+        // would be to optimize the tree traversal and hash computation.
+        self.commit(BasicIdBuilder::new().new_id())?;
+        self.root_hash(IDENTIFIER)
+    }
 }
 
-/// Compute the root hash of a list of values.
-// The `HashMapDb` can't fail, so we can safely unwrap the results.
-pub fn compute_root<H>(values: &[Felt]) -> Felt
+/// Compute the root hash of a list of values, in a [`MemoryTrie`].
+///
+/// `leaf` names the values in the errors.
+pub fn compute_root<H>(
+    leaf: &'static str,
+    values: impl IndexedParallelIterator<Item = Felt>,
+) -> Result<Felt, CommitmentError>
 where
     H: StarkHash + Send + Sync,
 {
-    //TODO: replace the identifier by an empty slice when bonsai will support it
-    const IDENTIFIER: &[u8] = b"0xinmemory";
-    let config = bonsai_trie::BonsaiStorageConfig::default();
-    let bonsai_db = bonsai_trie::databases::HashMapDb::<bonsai_trie::id::BasicId>::default();
-    let mut bonsai_storage =
-        bonsai_trie::BonsaiStorage::<_, _, H>::new(bonsai_db, config).expect("Failed to create bonsai storage");
-
-    values.iter().enumerate().for_each(|(id, value)| {
-        let key = BitVec::from_vec(id.to_be_bytes().to_vec());
-        bonsai_storage.insert(IDENTIFIER, key.as_bitslice(), value).expect("Failed to insert into bonsai storage");
-    });
+    let (root, _) = compute_root_collect(memory_trie::<H>(leaf)?, leaf, values.map(|value| (value, ())))?;
+    Ok(root)
+}
 
-    // Note that committing changes still has the greatest performance hit
-    // as this is where the root hash is calculated. Due to the Merkle structure
-    // of Bonsai Tries, this results in a trie size that grows very rapidly with
-    // each new insertion. It seems that the only vector of optimization here
-    // would be to optimize the tree traversal and hash computation.
-    let id = bonsai_trie::id::BasicIdBuilder::new().new_id();
+/// Compute the root hash of the trie of the values, along with the data computed with each of them, in order.
+///
+/// The values are computed on the rayon thread pool and sent through a channel, with their index, to be inserted into
+/// the trie as soon as they are ready: they are never collected.
+pub fn compute_root_collect<T: CommitmentTrie, R: Send>(
+    mut trie: T,
+    leaf: &'static str,
+    values: impl IndexedParallelIterator<Item = (Felt, R)>,
+) -> Result<(Felt, Vec<R>), CommitmentError> {
+    let (sender, receiver) = mpsc::channel::<(usize, Felt)>();
 
-    // run in a blocking-safe thread to avoid starving the thread pool
-    bonsai_storage.commit(id).expect("Failed to commit to bonsai storage");
-    bonsai_storage.root_hash(IDENTIFIER).expect("Failed to get root hash")
+    // The trie is filled on its own thread rather than on the thread pool: waiting for the values there could block
+    // the very thread the remaining values are scheduled on.
+    let (inserted, collected) = std::thread::scope(|scope| {
+        let trie = &mut trie;
+        let inserter = scope.spawn(move || {
+            receiver.into_iter().try_for_each(|(index, value)| {
+                trie.insert(index, &value).map_err(|err| CommitmentError::Insert {
+                    leaf,
+                    index,
+                    message: err.to_string(),
+                })
+            })
+        });
+        let collected = values
+            .enumerate()
+            .map_with(sender, |sender, (index, (value, data))| {
+                // The receiver is only dropped once an insertion failed, which is returned below.
+                let _ = sender.send((index, value));
+                data
+            })
+            .collect::<Vec<_>>();
+        (inserter.join().expect("Commitment trie thread panicked"), collected)
+    });
+    inserted?;
+
+    let root = trie.root().map_err(|err| CommitmentError::Root { leaf, message: err.to_string() })?;
+    Ok((root, collected))
 }
 
 #[cfg(test)]
 mod tests {
+    use dp_block::StarknetVersion;
+    use dp_receipt::{
+        DataAvailabilityResources, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt, MsgToL1,
+        PriceUnit, TransactionReceipt,
+    };
+    use dp_transactions::{InvokeTransaction, InvokeTransactionV1, L1HandlerTransaction, Transaction, MAIN_CHAIN_ID};
+    use starknet_types_core::hash::Pedersen;
+
     use super::*;
 
+    /// A trie whose backend fails when the value at `fail_at` is inserted.
+    struct FailingTrie<T> {
+        inner: T,
+        fail_at: usize,
+    }
+
+    impl<T: CommitmentTrie> CommitmentTrie for FailingTrie<T> {
+        type Error = String;
+
+        fn insert(&mut self, index: usize, leaf: &Felt) -> Result<(), Self::Error> {
+            if index == self.fail_at {
+                return Err("injected backend failure".into());
+            }
+            self.inner.insert(index, leaf).map_err(|err| err.to_string())
+        }
+
+        fn root(self) -> Result<Felt, Self::Error> {
+            self.inner.root().map_err(|err| err.to_string())
+        }
+    }
+
+    fn l1_handler_transactions(n_transactions: u64) -> Vec<Transaction> {
+        (0..n_transactions)
+            .map(|nonce| {
+                Transaction::L1Handler(L1HandlerTransaction {
+                    version: Felt::ZERO,
+                    nonce,
+                    contract_address: Felt::ONE,
+                    entry_point_selector: Felt::TWO,
+                    calldata: vec![Felt::from(nonce)],
+                })
+            })
+            .collect()
+    }
+
     #[test]
     fn test_compute_root() {
         let values = vec![Felt::ONE, Felt::TWO, Felt::THREE];
-        let root = compute_root::<Poseidon>(&values);
+        let root = compute_root::<Poseidon>("value", values.into_par_iter()).unwrap();
 
         assert_eq!(root, Felt::from_hex_unchecked("0x3b5cc7f1292eb3847c3f902d048a7e5dc7702d1c191ccd17c2d33f797e6fc32"));
     }
+
+    #[test]
+    fn test_compute_root_collect() {
+        let values: Vec<_> = (0..100u64).map(Felt::from).collect();
+        let (root, collected) = compute_root_collect(
+            memory_trie::<Pedersen>("value").unwrap(),
+            "value",
+            values.par_iter().map(|value| (*value, value.double())),
+        )
+        .unwrap();
+
+        assert_eq!(root, compute_root::<Pedersen>("value", values.par_iter().copied()).unwrap());
+        assert_eq!(collected, values.iter().map(Felt::double).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_transaction_commitment_insert_failure() {
+        let transactions = l1_handler_transactions(8);
+        let trie = FailingTrie { inner: memory_trie::<Poseidon>("transaction").unwrap(), fail_at: 5 };
+
+        let err = transactions::transaction_commitment(
+            trie,
+            &transactions,
+            MAIN_CHAIN_ID,
+            StarknetVersion::STARKNET_VERSION_0_13_2,
+            700_000,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, CommitmentError::Insert { leaf: "transaction", index: 5, .. }), "{err:?}");
+        let err = crate::l2::L2SyncError::Commitment { block_n: 700_000, source: err };
+        assert_eq!(
+            err.to_string(),
+            "Failed to compute the commitments of block 700000: Failed to insert transaction 5 into the commitment \
+             trie: injected backend failure"
+        );
+    }
+
+    #[test]
+    fn test_transaction_commitment_unchanged_by_streaming() {
+        let transactions = l1_handler_transactions(40);
+        let version = StarknetVersion::STARKNET_VERSION_0_13_2;
+        let (leaves, hashes): (Vec<_>, Vec<_>) = transactions
+            .iter()
+            .map(|tx| transactions::calculate_transaction_leaf_with_hash(tx, MAIN_CHAIN_ID, version, 700_000))
+            .unzip();
+
+        let (root, tx_hashes) =
+            transactions::memory_transaction_commitment(&transactions, MAIN_CHAIN_ID, version, 700_000).unwrap();
+
+        assert_eq!(root, compute_root::<Poseidon>("transaction", leaves.into_par_iter()).unwrap());
+        assert_eq!(tx_hashes, hashes);
+    }
+
+    fn invoke_transaction(signature: Vec<Felt>) -> Transaction {
+        Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+            sender_address: Felt::ONE,
+            calldata: vec![Felt::TWO],
+            max_fee: Felt::THREE,
+            signature,
+            nonce: Felt::ZERO,
+        }))
+    }
+
+    // TODO: these only check the 0.13.2 leaves and commitments against their definition. They should also be checked
+    // against the header of a real 0.13.2 block, captured from the feeder gateway.
+    #[test]
+    fn test_transaction_leaf_v0_13_2() {
+        let version = StarknetVersion::STARKNET_VERSION_0_13_2;
+
+        let signed = invoke_transaction(vec![Felt::from(5), Felt::from(6)]);
+        let (leaf, tx_hash) = transactions::calculate_transaction_leaf_with_hash(&signed, MAIN_CHAIN_ID, version, 0);
+        assert_eq!(leaf, Poseidon::hash_array(&[tx_hash, Felt::from(5), Felt::from(6)]));
+
+        // A missing signature is committed to as a single zero, for the transactions that may be signed too.
+        let unsigned = invoke_transaction(vec![]);
+        let (leaf, tx_hash) = transactions::calculate_transaction_leaf_with_hash(&unsigned, MAIN_CHAIN_ID, version, 0);
+        assert_eq!(leaf, Poseidon::hash_array(&[tx_hash, Felt::ZERO]));
+
+        let l1_handler = &l1_handler_transactions(1)[0];
+        let (leaf, tx_hash) = transactions::calculate_transaction_leaf_with_hash(l1_handler, MAIN_CHAIN_ID, version, 0);
+        assert_eq!(leaf, Poseidon::hash_array(&[tx_hash, Felt::ZERO]));
+    }
+
+    #[test]
+    fn test_transaction_leaf_pre_v0_13_2() {
+        let version = StarknetVersion::STARKNET_VERSION_0_13_1;
+
+        let signed = invoke_transaction(vec![Felt::from(5), Felt::from(6)]);
+        let (leaf, tx_hash) = transactions::calculate_transaction_leaf_with_hash(&signed, MAIN_CHAIN_ID, version, 0);
+        assert_eq!(leaf, Pedersen::hash(&tx_hash, &Pedersen::hash_array(&[Felt::from(5), Felt::from(6)])));
+
+        let unsigned = invoke_transaction(vec![]);
+        let (leaf, tx_hash) = transactions::calculate_transaction_leaf_with_hash(&unsigned, MAIN_CHAIN_ID, version, 0);
+        assert_eq!(leaf, Pedersen::hash(&tx_hash, &Pedersen::hash_array(&[])));
+    }
+
+    #[test]
+    fn test_transaction_commitment_hash_by_version() {
+        let transactions = vec![invoke_transaction(vec![Felt::from(5)]), invoke_transaction(vec![])];
+        for (version, poseidon) in
+            [(StarknetVersion::STARKNET_VERSION_0_13_1, false), (StarknetVersion::STARKNET_VERSION_0_13_2, true)]
+        {
+            let leaves: Vec<_> = transactions
+                .iter()
+                .map(|tx| transactions::calculate_transaction_leaf_with_hash(tx, MAIN_CHAIN_ID, version, 0).0)
+                .collect();
+            let expected = if poseidon {
+                compute_root::<Poseidon>("transaction", leaves.into_par_iter()).unwrap()
+            } else {
+                compute_root::<Pedersen>("transaction", leaves.into_par_iter()).unwrap()
+            };
+
+            let (root, _) =
+                transactions::memory_transaction_commitment(&transactions, MAIN_CHAIN_ID, version, 0).unwrap();
+            assert_eq!(root, expected, "{version}");
+        }
+    }
+
+    fn receipt(transaction_hash: u64, execution_result: ExecutionResult) -> TransactionReceipt {
+        TransactionReceipt::Invoke(InvokeTransactionReceipt {
+            transaction_hash: Felt::from(transaction_hash),
+            actual_fee: FeePayment { amount: Felt::from(transaction_hash * 100), unit: PriceUnit::Fri },
+            messages_sent: vec![MsgToL1 { from_address: Felt::ONE, to_address: Felt::TWO, payload: vec![Felt::THREE] }],
+            events: vec![],
+            execution_resources: ExecutionResources {
+                total_gas_consumed: DataAvailabilityResources { l1_gas: 10, l1_data_gas: 20 },
+                ..Default::default()
+            },
+            execution_result,
+        })
+    }
+
+    // TODO: this only checks the receipt leaf against the layout of the 0.13.2 spec, and the commitment against the
+    // properties it must have. Both should also be checked against the receipt commitment of a real 0.13.2 testnet
+    // block, captured from the feeder gateway.
+    #[test]
+    fn test_receipt_commitment() {
+        let receipts = vec![
+            receipt(1, ExecutionResult::Succeeded),
+            receipt(2, ExecutionResult::Reverted { reason: "aborted".into() }),
+            receipt(3, ExecutionResult::Succeeded),
+        ];
+        // The transaction hash, the fee, the messages sent (their count, then the sender, recipient, payload length
+        // and payload of each), the hash of the revert reason, and the L2, L1 and L1 data gas consumed.
+        let messages_sent = Poseidon::hash_array(&[Felt::ONE, Felt::ONE, Felt::TWO, Felt::ONE, Felt::THREE]);
+        let revert_reason = starknet_core::utils::starknet_keccak(b"aborted");
+        let leaf = Poseidon::hash_array(&[
+            Felt::TWO,
+            Felt::from(200),
+            messages_sent,
+            revert_reason,
+            Felt::ZERO,
+            Felt::from(10),
+            Felt::from(20),
+        ]);
+        assert_eq!(receipts[1].compute_hash(), leaf);
+
+        // The receipts are committed to in order, along with their revert reason.
+        let swapped = vec![receipts[1].clone(), receipts[0].clone(), receipts[2].clone()];
+        assert_ne!(memory_receipt_commitment(&swapped).unwrap(), memory_receipt_commitment(&receipts).unwrap());
+        let mut succeeded = receipts.clone();
+        succeeded[1] = receipt(2, ExecutionResult::Succeeded);
+        assert_ne!(memory_receipt_commitment(&succeeded).unwrap(), memory_receipt_commitment(&receipts).unwrap());
+
+        assert_eq!(memory_receipt_commitment(&[]).unwrap(), Felt::ZERO);
+    }
+
+    fn storage_diff(value: u64) -> StateDiff {
+        StateDiff::new(
+            vec![dp_state_update::ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![dp_state_update::StorageEntry { key: Felt::ONE, value: Felt::from(value) }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_preview_state_root_leaves_the_tries_unchanged() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = dc_db::DatabaseService::new(
+            temp_dir.path(),
+            None,
+            false,
+            std::sync::Arc::new(dp_block::chain_config::ChainConfig::test_config()),
+        )
+        .await
+        .unwrap();
+        let backend = db.backend();
+        let contract_root = || backend.contract_trie().root_hash(dc_db::bonsai_identifier::CONTRACT).unwrap();
+
+        // Block 0, from empty tries.
+        let preview_0 = preview_state_root(backend, &storage_diff(1), 0).unwrap();
+        assert_eq!(contract_root(), Felt::ZERO);
+        assert_eq!(update_tries_and_compute_state_root(backend, &storage_diff(1), 0), preview_0);
+        let root_0 = contract_root();
+
+        // Block 1, on top of block 0.
+        let preview_1 = preview_state_root(backend, &storage_diff(2), 1).unwrap();
+        assert_ne!(preview_1, preview_0);
+        assert_eq!(contract_root(), root_0);
+        assert_eq!(update_tries_and_compute_state_root(backend, &storage_diff(2), 1), preview_1);
+    }
 }
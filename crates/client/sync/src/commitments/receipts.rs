@@ -3,9 +3,8 @@ use rayon::prelude::*;
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::Poseidon;
 
-use super::compute_root;
+use super::{compute_root, CommitmentError};
 
-pub fn memory_receipt_commitment(receipts: &[TransactionReceipt]) -> Felt {
-    let receipts_hash = receipts.par_iter().map(TransactionReceipt::compute_hash).collect::<Vec<_>>();
-    compute_root::<Poseidon>(&receipts_hash)
+pub fn memory_receipt_commitment(receipts: &[TransactionReceipt]) -> Result<Felt, CommitmentError> {
+    compute_root::<Poseidon>("receipt", receipts.par_iter().map(TransactionReceipt::compute_hash))
 }
@@ -1,14 +1,11 @@
 use dp_block::StarknetVersion;
 use dp_transactions::Transaction;
-use dp_transactions::LEGACY_BLOCK_NUMBER;
-use dp_transactions::MAIN_CHAIN_ID;
-use dp_transactions::V0_7_BLOCK_NUMBER;
 use rayon::prelude::*;
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::Poseidon;
 use starknet_types_core::hash::{Pedersen, StarkHash};
 
-use super::compute_root;
+use super::{compute_root_collect, memory_trie, CommitmentError, CommitmentTrie};
 
 /// Compute transaction hash, without signatures.
 ///
@@ -20,16 +17,7 @@ use super::compute_root;
 ///
 /// The transaction hash.
 pub fn calculate_transaction_hash(transaction: &Transaction, chain_id: Felt, block_number: Option<u64>) -> Felt {
-    let legacy =
-        block_number.is_some_and(|block_number| block_number < LEGACY_BLOCK_NUMBER && chain_id == MAIN_CHAIN_ID);
-    let is_pre_v0_7 =
-        block_number.is_some_and(|block_number| block_number < V0_7_BLOCK_NUMBER && chain_id == MAIN_CHAIN_ID);
-
-    if is_pre_v0_7 {
-        transaction.compute_hash_pre_v0_7(chain_id, false)
-    } else {
-        transaction.compute_hash(chain_id, false, legacy)
-    }
+    transaction.compute_hash_at(chain_id, block_number, false)
 }
 
 /// Compute the combined hash of the transaction hash and the signature.
@@ -54,53 +42,21 @@ pub fn calculate_transaction_leaf_with_hash(
     let include_signature = starknet_version >= StarknetVersion::STARKNET_VERSION_0_11_1;
     let tx_hash = calculate_transaction_hash(transaction, chain_id, Some(block_number));
 
-    let leaf = match transaction {
-        Transaction::Invoke(tx) => {
-            // Include signatures for Invoke transactions or for all transactions
-            if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-                let signature_hash = tx.compute_hash_signature::<Pedersen>();
-                Pedersen::hash(&tx_hash, &signature_hash)
-            } else {
-                let elements: Vec<Felt> = std::iter::once(tx_hash).chain(tx.signature().iter().copied()).collect();
-                Poseidon::hash_array(&elements)
-            }
-        }
-        Transaction::Declare(tx) => {
-            if include_signature {
-                if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-                    let signature_hash = tx.compute_hash_signature::<Pedersen>();
-                    Pedersen::hash(&tx_hash, &signature_hash)
-                } else {
-                    let elements: Vec<Felt> = std::iter::once(tx_hash).chain(tx.signature().iter().copied()).collect();
-                    Poseidon::hash_array(&elements)
-                }
-            } else {
-                let signature_hash = Pedersen::hash_array(&[]);
-                Pedersen::hash(&tx_hash, &signature_hash)
-            }
-        }
-        Transaction::DeployAccount(tx) => {
-            if include_signature {
-                if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-                    let signature_hash = tx.compute_hash_signature::<Pedersen>();
-                    Pedersen::hash(&tx_hash, &signature_hash)
-                } else {
-                    let elements: Vec<Felt> = std::iter::once(tx_hash).chain(tx.signature().iter().copied()).collect();
-                    Poseidon::hash_array(&elements)
-                }
-            } else {
-                let signature_hash = Pedersen::hash_array(&[]);
-                Pedersen::hash(&tx_hash, &signature_hash)
-            }
-        }
-        _ => {
-            if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-                let signature_hash = Pedersen::hash_array(&[]);
-                Pedersen::hash(&tx_hash, &signature_hash)
-            } else {
-                Poseidon::hash_array(&[tx_hash, Felt::ZERO])
-            }
-        }
+    let signature = match transaction {
+        Transaction::Invoke(tx) => tx.signature(),
+        Transaction::Declare(tx) if include_signature => tx.signature(),
+        Transaction::DeployAccount(tx) if include_signature => tx.signature(),
+        _ => &[],
+    };
+
+    let leaf = if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
+        Pedersen::hash(&tx_hash, &Pedersen::hash_array(signature))
+    } else if signature.is_empty() {
+        // Since 0.13.2, a missing signature is committed to as a single zero.
+        Poseidon::hash_array(&[tx_hash, Felt::ZERO])
+    } else {
+        let elements: Vec<Felt> = std::iter::once(tx_hash).chain(signature.iter().copied()).collect();
+        Poseidon::hash_array(&elements)
     };
 
     (leaf, tx_hash)
@@ -117,25 +73,36 @@ pub fn calculate_transaction_leaf_with_hash(
 ///
 /// # Returns
 ///
-/// The transaction commitment as `Felt`.
+/// The transaction commitment as `Felt`, along with the transaction hashes.
 pub fn memory_transaction_commitment(
     transactions: &[Transaction],
     chain_id: Felt,
     starknet_version: StarknetVersion,
     block_number: u64,
-) -> (Felt, Vec<Felt>) {
-    // transaction hashes are computed in parallel
-    let (leafs, txs_hashes): (Vec<Felt>, Vec<Felt>) = transactions
-        .par_iter()
-        .map(|tx| calculate_transaction_leaf_with_hash(tx, chain_id, starknet_version, block_number))
-        .unzip();
-
-    // once transaction hashes have finished computing, they are inserted into the local Bonsai db
-    let root = if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-        compute_root::<Pedersen>(&leafs)
+) -> Result<(Felt, Vec<Felt>), CommitmentError> {
+    if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
+        let trie = memory_trie::<Pedersen>("transaction")?;
+        transaction_commitment(trie, transactions, chain_id, starknet_version, block_number)
     } else {
-        compute_root::<Poseidon>(&leafs)
-    };
+        let trie = memory_trie::<Poseidon>("transaction")?;
+        transaction_commitment(trie, transactions, chain_id, starknet_version, block_number)
+    }
+}
 
-    (root, txs_hashes)
+/// Calculate the transaction commitment in `trie`. The index of the transactions is reported in the errors.
+pub fn transaction_commitment(
+    trie: impl CommitmentTrie,
+    transactions: &[Transaction],
+    chain_id: Felt,
+    starknet_version: StarknetVersion,
+    block_number: u64,
+) -> Result<(Felt, Vec<Felt>), CommitmentError> {
+    // transaction hashes are computed in parallel, and inserted into the trie as they are computed
+    compute_root_collect(
+        trie,
+        "transaction",
+        transactions
+            .par_iter()
+            .map(|tx| calculate_transaction_leaf_with_hash(tx, chain_id, starknet_version, block_number)),
+    )
 }
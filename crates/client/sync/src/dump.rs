@@ -0,0 +1,373 @@
+//! Block dumps, to bootstrap a node without access to the feeder gateway. A dump holds consecutive blocks along with
+//! their state diffs and the classes they declare, as exported from a synced database: each record is the length of
+//! its bincode encoding as a little-endian `u64`, followed by the bincode encoding of an [`ImportedBlock`]. The whole
+//! file may be compressed with zstd, which the reader detects.
+//!
+//! Nothing in a dump is trusted: its blocks are imported with [`crate::import::import_blocks`], which verifies them.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use dc_db::db_block_id::DbBlockId;
+use dc_db::DeoxysBackend;
+use dp_block::{BlockN, DeoxysBlock};
+use dp_class::ConvertedClass;
+
+use crate::fetch::fetchers::is_skipped_class;
+use crate::import::ImportedBlock;
+
+/// The first bytes of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Records are read in memory before they are decoded: this rejects the lengths of corrupted dumps. The largest blocks
+/// and their classes take a few tens of MiB.
+const MAX_RECORD_LEN: u64 = 256 << 20;
+
+/// Reads the blocks of a dump lazily, in order.
+pub struct DumpReader {
+    reader: Box<dyn Read + Send>,
+}
+
+impl DumpReader {
+    pub fn new(mut reader: impl BufRead + Send + 'static) -> io::Result<Self> {
+        let compressed = reader.fill_buf()?.starts_with(&ZSTD_MAGIC);
+        let reader: Box<dyn Read + Send> =
+            if compressed { Box::new(zstd::Decoder::with_buffer(reader)?) } else { Box::new(reader) };
+        Ok(Self { reader })
+    }
+
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("Opening dump file {}", path.display()))?;
+        Ok(Self::new(BufReader::new(file))?)
+    }
+
+    fn read_record(&mut self) -> anyhow::Result<Option<ImportedBlock>> {
+        let mut len = [0u8; 8];
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..]) {
+                // The dump can only end between two records.
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => anyhow::bail!("The dump ends in the middle of a record length"),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err).context("Reading record length"),
+            }
+        }
+
+        let len = u64::from_le_bytes(len);
+        anyhow::ensure!(len <= MAX_RECORD_LEN, "Record length {len} is too large");
+        let mut record = vec![0u8; len as usize];
+        self.reader.read_exact(&mut record).context("Reading record")?;
+        Ok(Some(bincode::deserialize(&record).context("Decoding record")?))
+    }
+}
+
+impl Iterator for DumpReader {
+    type Item = anyhow::Result<ImportedBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Writes the records of a dump.
+pub struct DumpWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> DumpWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write_block(&mut self, block: &ImportedBlock) -> anyhow::Result<()> {
+        let record = bincode::serialize(block).context("Encoding record")?;
+        self.writer.write_all(&(record.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&record)?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a block of the database, with its state diff and the classes it declares.
+pub fn export_block(backend: &DeoxysBackend, block_n: u64) -> anyhow::Result<ImportedBlock> {
+    let id = DbBlockId::BlockN(BlockN(block_n));
+    let block = backend.get_block(&id)?.with_context(|| format!("Block {block_n} not found"))?;
+    let block = DeoxysBlock::try_from(block)?;
+    let state_diff = backend.get_block_state_diff(&id)?.with_context(|| format!("State diff {block_n} not found"))?;
+
+    let declared = state_diff
+        .deprecated_declared_classes()
+        .iter()
+        .chain(state_diff.declared_classes().iter().map(|item| &item.class_hash))
+        .filter(|class_hash| !is_skipped_class(class_hash));
+    let converted_classes = declared
+        .map(|class_hash| {
+            let (class_info, compiled_class) = backend
+                .get_class(&id, class_hash)?
+                .with_context(|| format!("Class {class_hash:#x} declared in block {block_n} not found"))?;
+            Ok(ConvertedClass { class_infos: (*class_hash, class_info), class_compiled: (*class_hash, compiled_class) })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(ImportedBlock { block, state_diff, converted_classes })
+}
+
+/// Exports the blocks `from..=to` of the database to a dump file, compressed with zstd when `compress` is set.
+pub fn export_blocks(backend: &DeoxysBackend, from: u64, to: u64, path: &Path, compress: bool) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("Creating dump file {}", path.display()))?;
+    let file = BufWriter::new(file);
+    if compress {
+        let encoder = zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        write_blocks(backend, from, to, DumpWriter::new(encoder))?.finish()?.flush()?;
+    } else {
+        write_blocks(backend, from, to, DumpWriter::new(file))?.flush()?;
+    }
+    Ok(())
+}
+
+fn write_blocks<W: Write>(backend: &DeoxysBackend, from: u64, to: u64, mut writer: DumpWriter<W>) -> anyhow::Result<W> {
+    for block_n in from..=to {
+        writer.write_block(&export_block(backend, block_n)?).with_context(|| format!("Writing block {block_n}"))?;
+        if block_n % 1000 == 0 {
+            log::info!("📤 Exported block {block_n}");
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::DatabaseService;
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::{BlockId, BlockTag, DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_class::{ClassHash, ClassInfo, CompiledClass, ContractClass, EntryPointsByType, FlattenedSierraClass};
+    use dp_state_update::{ContractStorageDiffItem, DeclaredClassItem, StateDiff, StorageEntry};
+    use dp_transactions::MAIN_CHAIN_ID;
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::commitments::update_tries_and_compute_state_root;
+    use crate::convert::compute_commitments_for_block;
+    use crate::import::{import_blocks, ImportError};
+
+    /// The state diff with its first storage write changed.
+    fn tampered(state_diff: StateDiff) -> StateDiff {
+        let (
+            mut storage_diffs,
+            deprecated_declared_classes,
+            declared_classes,
+            deployed_contracts,
+            replaced_classes,
+            nonces,
+        ) = state_diff.into_parts();
+        storage_diffs[0].storage_entries[0].value = Felt::from(0xbad);
+        StateDiff::new(
+            storage_diffs,
+            deprecated_declared_classes,
+            declared_classes,
+            deployed_contracts,
+            replaced_classes,
+            nonces,
+        )
+    }
+
+    fn contract_class() -> ContractClass {
+        ContractClass::Sierra(FlattenedSierraClass {
+            sierra_program: vec![],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: String::new(),
+        })
+    }
+
+    fn class_hash() -> Felt {
+        starknet_core::types::ContractClass::from(contract_class()).class_hash().unwrap()
+    }
+
+    fn converted_class(block_n: u64) -> ConvertedClass {
+        ConvertedClass {
+            class_infos: (
+                class_hash(),
+                ClassInfo {
+                    contract_class: contract_class(),
+                    compiled_class_hash: Felt::TWO,
+                    block_number: Some(block_n),
+                },
+            ),
+            class_compiled: (class_hash(), serde_json::from_str::<CompiledClass>(r#"{"Sierra":[]}"#).unwrap()),
+        }
+    }
+
+    /// Block `n` writes `n + 1` at key `n` of contract 0x1, and block 1 declares a class. The state roots are computed
+    /// with the tries of the backend, as the sync does.
+    fn sync_blocks(backend: &DeoxysBackend, n_blocks: u64) {
+        let mut parent_block_hash = Felt::ZERO;
+        for block_number in 0..n_blocks {
+            let (mut declared_classes, mut converted_classes) = (vec![], vec![]);
+            if block_number == 1 {
+                declared_classes.push(DeclaredClassItem { class_hash: class_hash(), compiled_class_hash: Felt::TWO });
+                converted_classes.push(converted_class(block_number));
+            }
+            let state_diff = StateDiff::new(
+                vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: block_number.into(), value: (block_number + 1).into() }],
+                }],
+                vec![],
+                declared_classes,
+                vec![],
+                vec![],
+                vec![],
+            );
+
+            let global_state_root = update_tries_and_compute_state_root(backend, &state_diff, block_number);
+            let inner = DeoxysBlockInner::new(vec![], vec![]);
+            let commitments =
+                compute_commitments_for_block(&inner, &state_diff, Default::default(), MAIN_CHAIN_ID, block_number)
+                    .unwrap();
+            let header = Header {
+                parent_block_hash,
+                block_number,
+                global_state_root,
+                block_timestamp: 1700000000 + block_number,
+                transaction_count: commitments.transaction_count,
+                transaction_commitment: commitments.transaction_commitment,
+                event_count: commitments.event_count,
+                event_commitment: commitments.event_commitment,
+                state_diff_length: commitments.state_diff_length,
+                state_diff_commitment: commitments.state_diff_commitment,
+                receipt_commitment: commitments.receipt_commitment,
+                ..Default::default()
+            };
+            let block_hash = header.compute_hash(MAIN_CHAIN_ID);
+            parent_block_hash = block_hash;
+            let block = DeoxysBlock::new(DeoxysBlockInfo::new(header, vec![], block_hash), inner);
+            backend.store_block(block.into(), state_diff, converted_classes).unwrap();
+        }
+    }
+
+    async fn backend(temp_dir: &tempfile::TempDir) -> DatabaseService {
+        DatabaseService::new(temp_dir.path(), None, false, Arc::new(ChainConfig::starknet_mainnet())).await.unwrap()
+    }
+
+    fn tip(backend: &DeoxysBackend) -> Option<DeoxysBlockInfo> {
+        let info = backend.get_block_info(&BlockId::Tag(BlockTag::Latest)).unwrap()?;
+        info.as_nonpending().cloned()
+    }
+
+    fn storage_at(backend: &DeoxysBackend, block_n: u64, key: u64) -> Option<Felt> {
+        backend.get_contract_storage_at(&BlockId::Number(block_n), &Felt::ONE, &key.into()).unwrap()
+    }
+
+    async fn export_and_import(compress: bool) {
+        let dump_dir = tempfile::TempDir::new().unwrap();
+        let dump = dump_dir.path().join("blocks.dump");
+        let (source_dir, target_dir) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (source, target) = (backend(&source_dir).await, backend(&target_dir).await);
+        sync_blocks(source.backend(), 4);
+
+        export_blocks(source.backend(), 0, 3, &dump, compress).unwrap();
+        let is_zstd = std::fs::read(&dump).unwrap().starts_with(&ZSTD_MAGIC);
+        assert_eq!(is_zstd, compress);
+        let imported = import_blocks(target.backend(), DumpReader::open(&dump).unwrap(), MAIN_CHAIN_ID).unwrap();
+
+        assert_eq!(imported, 4);
+        let (source, target) = (source.backend(), target.backend());
+        assert_eq!(tip(target).unwrap().block_hash, tip(source).unwrap().block_hash);
+        assert_eq!(tip(target).unwrap().header.global_state_root, tip(source).unwrap().header.global_state_root);
+        for (block_n, key) in [(0, 0), (2, 1), (2, 3), (3, 3)] {
+            assert_eq!(storage_at(target, block_n, key), storage_at(source, block_n, key));
+        }
+        assert_eq!(storage_at(target, 3, 2), Some(Felt::THREE));
+        assert_eq!(
+            target.get_class_info(&BlockId::Tag(BlockTag::Latest), &class_hash()).unwrap(),
+            Some(converted_class(1).class_infos.1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import() {
+        export_and_import(false).await;
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_compressed() {
+        export_and_import(true).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_a_tampered_dump() {
+        let (source_dir, target_dir) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (source, target) = (backend(&source_dir).await, backend(&target_dir).await);
+        sync_blocks(source.backend(), 3);
+
+        let mut writer = DumpWriter::new(Vec::new());
+        for block_n in 0..3 {
+            let mut block = export_block(source.backend(), block_n).unwrap();
+            // The state diff is not part of the hash of the blocks of this version, only of their state root.
+            if block_n == 2 {
+                block.state_diff = tampered(block.state_diff);
+            }
+            writer.write_block(&block).unwrap();
+        }
+
+        let reader = DumpReader::new(io::Cursor::new(writer.into_inner())).unwrap();
+        let res = import_blocks(target.backend(), reader, MAIN_CHAIN_ID);
+        assert!(matches!(res, Err(ImportError::MismatchedStateRoot { block_n: 2, .. })));
+        assert_eq!(target.backend().get_latest_block_n().unwrap(), Some(1));
+
+        // The tries were reverted: the import resumes with the genuine block.
+        let imported = import_blocks(target.backend(), [export_block(source.backend(), 2)], MAIN_CHAIN_ID).unwrap();
+        assert_eq!(imported, 1);
+        assert_eq!(tip(target.backend()).unwrap().block_hash, tip(source.backend()).unwrap().block_hash);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_a_tampered_class() {
+        let (source_dir, target_dir) = (tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap());
+        let (source, target) = (backend(&source_dir).await, backend(&target_dir).await);
+        sync_blocks(source.backend(), 2);
+
+        let mut block = export_block(source.backend(), 1).unwrap();
+        let ContractClass::Sierra(sierra) = &mut block.converted_classes[0].class_infos.1.contract_class else {
+            unreachable!()
+        };
+        sierra.abi = "[]".into();
+        let blocks = [export_block(source.backend(), 0), Ok(block)];
+
+        let res = import_blocks(target.backend(), blocks, MAIN_CHAIN_ID);
+        assert!(matches!(res, Err(ImportError::MismatchedClassHash { block_n: 1, .. })));
+        assert_eq!(target.backend().get_latest_block_n().unwrap(), Some(0));
+        assert_eq!(target.backend().get_class_info(&BlockId::Tag(BlockTag::Latest), &class_hash()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_truncated_dump() {
+        let mut writer = DumpWriter::new(Vec::new());
+        writer
+            .write_block(&ImportedBlock {
+                block: DeoxysBlock::new(
+                    DeoxysBlockInfo::new(Header::default(), vec![], Felt::ONE),
+                    DeoxysBlockInner::new(vec![], vec![]),
+                ),
+                state_diff: StateDiff::default(),
+                converted_classes: vec![],
+            })
+            .unwrap();
+        let dump = writer.into_inner();
+
+        assert_eq!(DumpReader::new(io::Cursor::new(dump.clone())).unwrap().count(), 1);
+        for len in [4, dump.len() - 1] {
+            let mut reader = DumpReader::new(io::Cursor::new(dump[..len].to_vec())).unwrap();
+            assert!(reader.next().unwrap().is_err());
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Fast sync of the blocks already finalized on L1.
+//!
+//! Committing the global tries at every block is what limits the speed of the sync. Below the fast sync threshold,
+//! the blocks are stored one by one as usual, but their state diffs are only committed to the global tries once per
+//! batch of blocks, and the state root is only verified against the header of the last block of each batch.
+//!
+//! The first block of the batch being built is recorded in the database. When the node stops in the middle of a batch,
+//! the state diffs of its stored blocks are read back and committed when the sync starts again, see
+//! [`commit_uncommitted_blocks`].
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use dc_db::DeoxysBackend;
+use dp_block::BlockId;
+use dp_state_update::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, StateDiff, StorageEntry,
+};
+use starknet_types_core::felt::Felt;
+
+use crate::commitments::{global_tries_root, update_tries_and_compute_state_root};
+use crate::l2::L2SyncError;
+
+/// Default number of blocks committed to the global tries at once during a fast sync.
+pub const DEFAULT_FAST_SYNC_BATCH_SIZE: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastSyncConfig {
+    /// The blocks below this one are fast synced. Only blocks finalized on L1 should be, as the fast synced blocks
+    /// cannot be reverted one by one.
+    pub until_block: u64,
+    /// Number of blocks committed to the global tries at once.
+    pub batch_size: u64,
+}
+
+impl FastSyncConfig {
+    pub fn applies_to(&self, block_n: u64) -> bool {
+        block_n < self.until_block
+    }
+
+    /// The batches are aligned on the block numbers, and the last one ends right before the threshold.
+    pub fn ends_batch(&self, block_n: u64) -> bool {
+        (block_n + 1) % self.batch_size.max(1) == 0 || block_n + 1 == self.until_block
+    }
+}
+
+/// The state diffs of the blocks of a batch, merged into the changes of the batch as a whole.
+#[derive(Debug, Default)]
+pub struct StateDiffBatch {
+    first_block: Option<u64>,
+    storage: HashMap<Felt, HashMap<Felt, Felt>>,
+    /// Deployed contracts and replaced classes: both set the class hash of a contract.
+    class_hashes: HashMap<Felt, Felt>,
+    nonces: HashMap<Felt, Felt>,
+    declared_classes: HashMap<Felt, Felt>,
+}
+
+impl StateDiffBatch {
+    /// The first block of the batch, `None` when it is empty.
+    pub fn first_block(&self) -> Option<u64> {
+        self.first_block
+    }
+
+    /// The state diffs must be pushed in block order.
+    pub fn push(&mut self, block_n: u64, state_diff: &StateDiff) {
+        self.first_block.get_or_insert(block_n);
+        for ContractStorageDiffItem { address, storage_entries } in state_diff.storage_diffs() {
+            let storage = self.storage.entry(*address).or_default();
+            storage.extend(storage_entries.iter().map(|StorageEntry { key, value }| (*key, *value)));
+        }
+        self.class_hashes.extend(state_diff.deployed_contracts().iter().map(|item| (item.address, item.class_hash)));
+        self.class_hashes
+            .extend(state_diff.replaced_classes().iter().map(|item| (item.contract_address, item.class_hash)));
+        self.nonces.extend(state_diff.nonces().iter().map(|item| (item.contract_address, item.nonce)));
+        self.declared_classes
+            .extend(state_diff.declared_classes().iter().map(|item| (item.class_hash, item.compiled_class_hash)));
+    }
+
+    /// The changes of the batch, to be committed to the global tries. The class changes of the contracts are all
+    /// listed as deployed contracts, the tries make no difference between the two.
+    pub fn into_state_diff(self) -> StateDiff {
+        StateDiff::new(
+            self.storage
+                .into_iter()
+                .map(|(address, storage)| ContractStorageDiffItem {
+                    address,
+                    storage_entries: storage.into_iter().map(|(key, value)| StorageEntry { key, value }).collect(),
+                })
+                .collect(),
+            vec![],
+            self.declared_classes
+                .into_iter()
+                .map(|(class_hash, compiled_class_hash)| DeclaredClassItem { class_hash, compiled_class_hash })
+                .collect(),
+            self.class_hashes
+                .into_iter()
+                .map(|(address, class_hash)| DeployedContractItem { address, class_hash })
+                .collect(),
+            vec![],
+            self.nonces.into_iter().map(|(contract_address, nonce)| NonceUpdate { contract_address, nonce }).collect(),
+        )
+    }
+}
+
+/// Commits to the global tries the blocks that a fast sync stored without committing them, when it stopped in the
+/// middle of a batch, and verifies the state root of the tip. Returns the tip when blocks were committed.
+///
+/// The tries are first brought back to the block before the batch: the node may have stopped once the batch was
+/// committed, but before its last block was stored. When the state root of the tip does not match, the batch is taken
+/// out of the tries again, so that a restart finds them as they were.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn commit_uncommitted_blocks(backend: &DeoxysBackend) -> anyhow::Result<Option<u64>> {
+    let Some(from) = backend.get_uncommitted_tries_from()? else { return Ok(None) };
+    let tip = backend.get_latest_block_n()?;
+    let _tries_update = backend.lock_tries_update();
+
+    let root = global_tries_root(backend)?;
+    if let Some(tip) = tip.filter(|tip| *tip >= from) {
+        if root == stored_state_root(backend, tip)? {
+            // The batch ended with the tip, and was committed along with it.
+            backend.clear_uncommitted_tries_from()?;
+            return Ok(None);
+        }
+    }
+    let before_batch = match from.checked_sub(1) {
+        Some(block_n) => stored_state_root(backend, block_n)?,
+        None => Felt::ZERO,
+    };
+    if root != before_batch {
+        // The batch ended with the block after the tip, which was committed to the tries but not stored.
+        let committed = tip.map_or(from, |tip| tip + 1);
+        log::info!("🌳 Taking block {committed} out of the global tries, the fast sync stopped before storing it");
+        backend.revert_tries_to(from.checked_sub(1), committed).context("Reverting the uncommitted batch")?;
+        let root = global_tries_root(backend)?;
+        if root != before_batch {
+            anyhow::bail!(
+                "The global tries have the state root {root:#x}, which is neither the one of the blocks before the \
+                 fast sync batch starting at block {from} nor the one of its stored blocks"
+            );
+        }
+    }
+    let Some(tip) = tip.filter(|tip| *tip >= from) else {
+        backend.clear_uncommitted_tries_from()?;
+        return Ok(None);
+    };
+
+    log::info!("🌳 Committing blocks {from}..={tip} to the global tries, the fast sync stopped before it");
+    let mut batch = StateDiffBatch::default();
+    for block_n in from..=tip {
+        let state_diff = backend
+            .get_block_state_diff(&BlockId::Number(block_n))?
+            .ok_or_else(|| anyhow::anyhow!("Block {block_n} has no state diff"))?;
+        batch.push(block_n, &state_diff);
+    }
+    let expected = stored_state_root(backend, tip)?;
+
+    let got = update_tries_and_compute_state_root(backend, &batch.into_state_diff(), tip);
+    if got != expected {
+        backend
+            .revert_tries_to(from.checked_sub(1), tip)
+            .context("Reverting the global tries after a state root mismatch")?;
+        return Err(L2SyncError::MismatchedStateRoot { block: tip, expected, got }.into());
+    }
+    backend.clear_uncommitted_tries_from()?;
+    Ok(Some(tip))
+}
+
+fn stored_state_root(backend: &DeoxysBackend, block_n: u64) -> anyhow::Result<Felt> {
+    backend
+        .get_block_info(&BlockId::Number(block_n))?
+        .as_ref()
+        .and_then(|info| info.as_nonpending())
+        .map(|info| info.header.global_state_root)
+        .ok_or_else(|| anyhow::anyhow!("Block {block_n} is not in the database"))
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_state_update::ReplacedClassItem;
+
+    use super::*;
+
+    #[test]
+    fn batch_boundaries() {
+        let config = FastSyncConfig { until_block: 10, batch_size: 4 };
+        let ends: Vec<_> =
+            (0..12).filter(|block_n| config.applies_to(*block_n) && config.ends_batch(*block_n)).collect();
+        assert_eq!(ends, vec![3, 7, 9]);
+        assert!(!config.applies_to(10));
+    }
+
+    #[test]
+    fn later_blocks_override_earlier_ones() {
+        let mut batch = StateDiffBatch::default();
+        assert_eq!(batch.first_block(), None);
+        batch.push(
+            5,
+            &StateDiff::new(
+                vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![
+                        StorageEntry { key: Felt::ONE, value: Felt::ONE },
+                        StorageEntry { key: Felt::TWO, value: Felt::ONE },
+                    ],
+                }],
+                vec![],
+                vec![],
+                vec![DeployedContractItem { address: Felt::ONE, class_hash: Felt::ONE }],
+                vec![],
+                vec![NonceUpdate { contract_address: Felt::ONE, nonce: Felt::ONE }],
+            ),
+        );
+        batch.push(
+            6,
+            &StateDiff::new(
+                vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::TWO, value: Felt::THREE }],
+                }],
+                vec![],
+                vec![DeclaredClassItem { class_hash: Felt::TWO, compiled_class_hash: Felt::THREE }],
+                vec![],
+                vec![ReplacedClassItem { contract_address: Felt::ONE, class_hash: Felt::TWO }],
+                vec![NonceUpdate { contract_address: Felt::ONE, nonce: Felt::TWO }],
+            ),
+        );
+        assert_eq!(batch.first_block(), Some(5));
+
+        let state_diff = batch.into_state_diff();
+        assert_eq!(
+            state_diff.storage_diffs(),
+            vec![ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![
+                    StorageEntry { key: Felt::ONE, value: Felt::ONE },
+                    StorageEntry { key: Felt::TWO, value: Felt::THREE },
+                ],
+            }]
+        );
+        assert_eq!(
+            state_diff.deployed_contracts(),
+            vec![DeployedContractItem { address: Felt::ONE, class_hash: Felt::TWO }]
+        );
+        assert_eq!(state_diff.nonces(), vec![NonceUpdate { contract_address: Felt::ONE, nonce: Felt::TWO }]);
+        assert_eq!(
+            state_diff.declared_classes(),
+            vec![DeclaredClassItem { class_hash: Felt::TWO, compiled_class_hash: Felt::THREE }]
+        );
+    }
+}
@@ -2,12 +2,21 @@
 use core::fmt;
 use core::time::Duration;
 use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
 
 use dc_db::storage_updates::DbClassUpdate;
 use dc_db::DeoxysBackend;
-use dp_block::{BlockId, BlockTag};
+use dc_metrics::{Counter, U64};
+use dp_block::{BlockId, BlockN, BlockTag};
+use dp_class::ConvertedClass;
 use dp_convert::ToStateUpdateCore;
+use dp_utils::compute_pool::{spawn_compute, ComputePool};
+use dp_utils::lock::MutexExt;
 use dp_utils::{stopwatch_end, wait_or_graceful_shutdown, PerfStopwatch};
+use futures::future::BoxFuture;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use rand::Rng;
 use starknet_api::core::ChainId;
 use starknet_core::types::{
     ContractClass, DeclaredClassItem, DeployedContractItem, StarknetError, StateDiff, StateUpdate,
@@ -15,9 +24,15 @@ use starknet_core::types::{
 use starknet_providers::sequencer::models::{self as p};
 use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
 use starknet_types_core::felt::Felt;
+use tokio::sync::OnceCell;
 use url::Url;
 
-use crate::l2::L2SyncError;
+use super::validation::validate_block_response;
+use crate::convert::convert_and_verify_class;
+use crate::fast_sync::FastSyncConfig;
+use crate::l2::{ClassRefetcher, L2SyncError};
+use crate::metrics::block_metrics::BlockMetrics;
+use crate::pipeline::PipelineConfig;
 
 /// The configuration of the worker responsible for fetching new blocks and state updates from the
 /// feeder.
@@ -35,6 +50,12 @@ pub struct FetchConfig {
     pub l1_core_address: dp_block::H160,
     /// Whether to check the root of the state update
     pub verify: bool,
+    /// Import the blocks whose state root does not match, instead of stopping the sync
+    pub ignore_state_root_mismatch: bool,
+    /// Commit the blocks below a threshold to the global tries in batches
+    pub fast_sync: Option<FastSyncConfig>,
+    /// Import the blocks of unsupported Starknet versions, instead of stopping the sync
+    pub allow_unsupported_protocol_version: bool,
     /// The optional API_KEY to avoid rate limiting from the sequencer gateway.
     pub api_key: Option<String>,
     /// Polling interval
@@ -43,16 +64,69 @@ pub struct FetchConfig {
     pub n_blocks_to_sync: Option<u64>,
     /// Disable l1 sync
     pub sync_l1_disabled: bool,
+    /// Number of L1 blocks on top of a state update before it is considered confirmed
+    pub l1_confirmations: u64,
+    /// Number of blocks fetched in parallel while catching up with the chain
+    pub concurrency: usize,
+    /// Number of times a request to the feeder gateway is retried before the sync fails
+    pub max_retries: usize,
+    /// Delay before the first retry, it doubles with every retry
+    pub retry_base_delay: Duration,
+    /// Maximum number of requests per second to the feeder gateway
+    pub rate_limit_per_second: Option<u32>,
+    /// Blocks held by the sync between their fetch and their storage
+    pub pipeline: PipelineConfig,
+}
+
+/// Retry delays never exceed this, before the jitter is added.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// The feeder gateway answered 429 Too Many Requests: wait at least this long before retrying.
+const RATE_LIMITED_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How the requests to the feeder gateway are retried and throttled. It is shared by all the fetches of the sync.
+pub struct FetchPolicy {
+    max_retries: usize,
+    retry_base_delay: Duration,
+    rate_limiter: Option<DefaultDirectRateLimiter>,
+}
+
+impl FetchPolicy {
+    pub fn new(max_retries: usize, retry_base_delay: Duration, rate_limit_per_second: Option<u32>) -> Self {
+        let rate_limiter = rate_limit_per_second
+            .and_then(NonZeroU32::new)
+            .map(|rate_limit| RateLimiter::direct(Quota::per_second(rate_limit).allow_burst(NonZeroU32::MIN)));
+        Self { max_retries, retry_base_delay, rate_limiter }
+    }
+
+    pub fn from_config(config: &FetchConfig) -> Self {
+        Self::new(config.max_retries, config.retry_base_delay, config.rate_limit_per_second)
+    }
+
+    /// Exponential backoff, with up to 25% of jitter so that the parallel fetches do not retry all at once.
+    fn retry_delay(&self, attempt: usize, err: &L2SyncError) -> Duration {
+        let delay = self.retry_base_delay.saturating_mul(2u32.saturating_pow(attempt as u32)).min(MAX_RETRY_DELAY);
+        let delay = match err {
+            L2SyncError::Provider(ProviderError::RateLimited) => delay.max(RATE_LIMITED_RETRY_DELAY),
+            _ => delay,
+        };
+        delay.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..0.25))
+    }
+
+    async fn throttle(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.until_ready().await;
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum FetchBlockId {
-    BlockN(u64),
+    BlockN(BlockN),
     Pending,
 }
 
 impl FetchBlockId {
-    pub fn block_n(self) -> Option<u64> {
+    pub fn block_n(self) -> Option<BlockN> {
         match self {
             FetchBlockId::BlockN(block_n) => Some(block_n),
             FetchBlockId::Pending => None,
@@ -72,7 +146,7 @@ impl fmt::Debug for FetchBlockId {
 impl From<FetchBlockId> for p::BlockId {
     fn from(value: FetchBlockId) -> Self {
         match value {
-            FetchBlockId::BlockN(block_n) => p::BlockId::Number(block_n),
+            FetchBlockId::BlockN(block_n) => p::BlockId::Number(block_n.0),
             FetchBlockId::Pending => p::BlockId::Pending,
         }
     }
@@ -80,7 +154,7 @@ impl From<FetchBlockId> for p::BlockId {
 impl From<FetchBlockId> for starknet_core::types::BlockId {
     fn from(value: FetchBlockId) -> Self {
         match value {
-            FetchBlockId::BlockN(block_n) => starknet_core::types::BlockId::Number(block_n),
+            FetchBlockId::BlockN(block_n) => starknet_core::types::BlockId::Number(block_n.0),
             FetchBlockId::Pending => starknet_core::types::BlockId::Tag(starknet_core::types::BlockTag::Pending),
         }
     }
@@ -93,49 +167,112 @@ pub struct L2BlockAndUpdates {
     pub class_update: Vec<DbClassUpdate>,
 }
 
+/// The classes downloaded for the blocks being fetched. It is shared by the concurrent block fetches, so that a class
+/// needed by several of them is only downloaded once.
+///
+/// A class is kept until the block that needs it is [released](ClassDownloads::release), the next blocks then find it
+/// in the database.
+pub struct ClassDownloads {
+    classes: Mutex<HashMap<Felt, Arc<OnceCell<ContractClass>>>>,
+    fetched: Counter<U64>,
+    skipped: Counter<U64>,
+}
+
+impl ClassDownloads {
+    pub fn new(block_metrics: &BlockMetrics) -> Self {
+        Self {
+            classes: Default::default(),
+            fetched: block_metrics.l2_classes_fetched.clone(),
+            skipped: block_metrics.l2_classes_skipped.clone(),
+        }
+    }
+
+    /// Downloads the class, unless another block fetch already did or is doing it.
+    async fn get_or_fetch(
+        &self,
+        class_hash: Felt,
+        block_id: FetchBlockId,
+        provider: &SequencerGatewayProvider,
+        policy: &FetchPolicy,
+    ) -> Result<ContractClass, L2SyncError> {
+        let download = Arc::clone(self.classes.lock_or_recover().entry(class_hash).or_default());
+        let mut downloaded = false;
+        let fetch = || async move {
+            let (_, contract_class) = retry(policy, || fetch_class(class_hash, block_id, provider)).await?;
+            Ok::<_, L2SyncError>(contract_class)
+        };
+        let contract_class = download
+            .get_or_try_init(|| {
+                downloaded = true;
+                fetch()
+            })
+            .await?;
+
+        if downloaded {
+            self.fetched.inc();
+        } else {
+            self.skipped.inc();
+        }
+        Ok(contract_class.clone())
+    }
+
+    /// Forgets the classes of a block that is handed over for import.
+    pub fn release(&self, class_update: &[DbClassUpdate]) {
+        self.release_classes(class_update.iter().map(|update| update.class_hash));
+    }
+
+    /// Forgets classes that no block is expected to need anymore, such as the ones that left the pending block.
+    pub fn release_classes(&self, class_hashes: impl IntoIterator<Item = Felt>) {
+        let mut classes = self.classes.lock_or_recover();
+        for class_hash in class_hashes {
+            classes.remove(&class_hash);
+        }
+    }
+}
+
 pub async fn fetch_block_and_updates(
     backend: &DeoxysBackend,
     block_id: FetchBlockId,
     provider: &SequencerGatewayProvider,
+    policy: &FetchPolicy,
+    class_downloads: &ClassDownloads,
 ) -> Result<L2BlockAndUpdates, L2SyncError> {
-    const MAX_RETRY: u32 = 15;
-    let base_delay = Duration::from_secs(1);
-
     let sw = PerfStopwatch::new();
-    let (state_update, block) =
-        retry(|| fetch_state_update_with_block(provider, block_id), MAX_RETRY, base_delay).await?;
-    let class_update = fetch_class_updates(backend, &state_update, block_id, provider).await?;
+    let (state_update, block) = retry(policy, || fetch_state_update_with_block(provider, block_id)).await?;
+    let class_update =
+        fetch_class_updates(backend, &state_update.state_diff, block_id, provider, policy, class_downloads).await?;
 
     stopwatch_end!(sw, "fetching {:?}: {:?}", block_id);
     Ok(L2BlockAndUpdates { block_id, block, state_diff: state_update.state_diff, class_update })
 }
 
-async fn retry<F, Fut, T>(mut f: F, max_retries: u32, base_delay: Duration) -> Result<T, ProviderError>
+async fn retry<F, Fut, T>(policy: &FetchPolicy, mut f: F) -> Result<T, L2SyncError>
 where
     F: FnMut() -> Fut,
-    Fut: std::future::Future<Output = Result<T, ProviderError>>,
+    Fut: std::future::Future<Output = Result<T, L2SyncError>>,
 {
     let mut attempt = 0;
     loop {
+        policy.throttle().await;
         match f().await {
             Ok(res) => return Ok(res),
-            Err(ProviderError::StarknetError(StarknetError::BlockNotFound)) => {
-                break Err(ProviderError::StarknetError(StarknetError::BlockNotFound));
+            Err(L2SyncError::Provider(ProviderError::StarknetError(StarknetError::BlockNotFound))) => {
+                break Err(ProviderError::StarknetError(StarknetError::BlockNotFound).into());
             }
             Err(err) => {
-                let delay = base_delay * 2_u32.pow(attempt).min(6); // Cap to prevent overly long delays
-                attempt += 1;
-                if attempt > max_retries {
+                if attempt >= policy.max_retries {
                     break Err(err);
                 }
+                let delay = policy.retry_delay(attempt, &err);
+                attempt += 1;
                 match err {
-                    ProviderError::RateLimited => {
+                    L2SyncError::Provider(ProviderError::RateLimited) => {
                         log::info!("The fetching process has been rate limited, retrying in {:?}", delay)
                     }
                     _ => log::warn!("The provider has returned an error: {}, retrying in {:?}", err, delay),
                 }
                 if wait_or_graceful_shutdown(tokio::time::sleep(delay)).await.is_none() {
-                    return Err(ProviderError::StarknetError(StarknetError::BlockNotFound));
+                    return Err(ProviderError::StarknetError(StarknetError::BlockNotFound).into());
                     // :/
                 }
             }
@@ -143,69 +280,101 @@ where
     }
 }
 
-/// retrieves state update with block from Starknet sequencer in only one request
+/// retrieves state update with block from Starknet sequencer in only one request, rejecting implausible responses
 async fn fetch_state_update_with_block(
     provider: &SequencerGatewayProvider,
     block_id: FetchBlockId,
-) -> Result<(StateUpdate, p::Block), ProviderError> {
+) -> Result<(StateUpdate, p::Block), L2SyncError> {
     #[allow(deprecated)] // Sequencer-specific functions are deprecated. Use it via the Provider trait instead.
     let state_update_with_block = provider.get_state_update_with_block(block_id.into()).await?;
 
-    Ok((state_update_with_block.state_update.to_state_update_core(), state_update_with_block.block))
+    let state_update = state_update_with_block.state_update.to_state_update_core();
+    validate_block_response(block_id, &state_update_with_block.block, &state_update.state_diff)?;
+    Ok((state_update, state_update_with_block.block))
 }
 
-/// retrieves class updates from Starknet sequencer
+/// retrieves the classes of a state diff that are not in the database from Starknet sequencer
 async fn fetch_class_updates(
     backend: &DeoxysBackend,
-    state_update: &StateUpdate,
+    state_diff: &StateDiff,
     block_id: FetchBlockId,
     provider: &SequencerGatewayProvider,
+    policy: &FetchPolicy,
+    class_downloads: &ClassDownloads,
 ) -> Result<Vec<DbClassUpdate>, L2SyncError> {
     let missing_classes: Vec<_> = std::iter::empty()
         .chain(
-            state_update
-                .state_diff
+            state_diff
                 .deployed_contracts
                 .iter()
                 .map(|DeployedContractItem { address: _, class_hash }| (class_hash, &Felt::ZERO)),
         )
-        .chain(state_update.state_diff.deprecated_declared_classes.iter().map(|felt| (felt, &Felt::ZERO)))
+        .chain(state_diff.deprecated_declared_classes.iter().map(|felt| (felt, &Felt::ZERO)))
         .chain(
-            state_update
-                .state_diff
+            state_diff
                 .declared_classes
                 .iter()
                 .map(|DeclaredClassItem { class_hash, compiled_class_hash }| (class_hash, compiled_class_hash)),
         )
         .collect::<HashMap<_, _>>() // unique() by key
         .into_iter()
+        .filter(|(class_hash, _)| !is_skipped_class(class_hash))
         .filter_map(|(class_hash, compiled_class_hash)| {
             match backend.contains_class(&BlockId::Tag(BlockTag::Latest), class_hash) {
                 Ok(false) => Some(Ok((class_hash, compiled_class_hash))),
-                Ok(true) => None,
+                Ok(true) => {
+                    class_downloads.skipped.inc();
+                    None
+                }
                 Err(e) => Some(Err(e)),
             }
         })
         .collect::<Result<_, _>>()?;
 
-    let classes = futures::future::try_join_all(missing_classes.into_iter().map(
-        |(&class_hash, &compiled_class_hash)| async move {
-            // TODO(correctness): Skip what appears to be a broken Sierra class definition (quick fix)
-            if class_hash
-                != Felt::from_hex("0x024f092a79bdff4efa1ec86e28fa7aa7d60c89b30924ec4dab21dbfd4db73698").unwrap()
-            {
-                // Fetch the class definition in parallel, retrying up to 15 times for each class
-                let (class_hash, contract_class) =
-                    retry(|| fetch_class(class_hash, block_id, provider), 15, Duration::from_secs(1)).await?;
-                Ok::<_, L2SyncError>(Some(DbClassUpdate { class_hash, contract_class, compiled_class_hash }))
-            } else {
-                Ok(None)
-            }
-        },
-    ))
-    .await?;
+    // Fetch the class definitions in parallel, each of them is retried on its own
+    futures::future::try_join_all(missing_classes.into_iter().map(|(&class_hash, &compiled_class_hash)| async move {
+        let contract_class = class_downloads.get_or_fetch(class_hash, block_id, provider, policy).await?;
+        Ok::<_, L2SyncError>(DbClassUpdate { class_hash, contract_class, compiled_class_hash })
+    }))
+    .await
+}
 
-    Ok(classes.into_iter().flatten().collect())
+/// Classes that are not fetched, nor expected to be stored.
+pub(crate) fn is_skipped_class(class_hash: &Felt) -> bool {
+    // TODO(correctness): Skip what appears to be a broken Sierra class definition (quick fix)
+    *class_hash == Felt::from_hex_unchecked("0x024f092a79bdff4efa1ec86e28fa7aa7d60c89b30924ec4dab21dbfd4db73698")
+}
+
+/// Fetches the classes missing from a block from the feeder gateway, with the fetch policy of the sync, and compiles
+/// them on the compute pool of the sync.
+pub struct GatewayClassRefetcher {
+    provider: Arc<SequencerGatewayProvider>,
+    policy: Arc<FetchPolicy>,
+    compute_pool: ComputePool,
+}
+
+impl GatewayClassRefetcher {
+    pub fn new(provider: Arc<SequencerGatewayProvider>, policy: Arc<FetchPolicy>, compute_pool: ComputePool) -> Self {
+        Self { provider, policy, compute_pool }
+    }
+}
+
+impl ClassRefetcher for GatewayClassRefetcher {
+    fn refetch(&self, block_n: u64, classes: Vec<(Felt, Felt)>) -> BoxFuture<'_, anyhow::Result<Vec<ConvertedClass>>> {
+        Box::pin(async move {
+            let block_id = FetchBlockId::BlockN(BlockN(block_n));
+            let class_updates = futures::future::try_join_all(classes.into_iter().map(
+                |(class_hash, compiled_class_hash)| async move {
+                    let (class_hash, contract_class) =
+                        retry(&self.policy, || fetch_class(class_hash, block_id, &self.provider)).await?;
+                    Ok::<_, L2SyncError>(DbClassUpdate { class_hash, contract_class, compiled_class_hash })
+                },
+            ))
+            .await?;
+            Ok(spawn_compute(&self.compute_pool, move || convert_and_verify_class(class_updates, Some(block_n), None))
+                .await?)
+        })
+    }
 }
 
 /// Downloads a class definition from the Starknet sequencer. Note that because
@@ -214,7 +383,138 @@ async fn fetch_class(
     class_hash: Felt,
     block_id: FetchBlockId,
     provider: &SequencerGatewayProvider,
-) -> Result<(Felt, ContractClass), ProviderError> {
+) -> Result<(Felt, ContractClass), L2SyncError> {
     let contract_class = provider.get_class(starknet_core::types::BlockId::from(block_id), class_hash).await?;
     Ok((class_hash, contract_class))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use dc_metrics::MetricsService;
+    use mockito::Matcher;
+    use tokio::time::Instant;
+
+    use super::*;
+
+    /// Fails `failures` times with `err`, then succeeds. Returns the number of calls.
+    async fn flaky_fetch(
+        policy: &FetchPolicy,
+        failures: usize,
+        err: fn() -> L2SyncError,
+    ) -> (Result<(), L2SyncError>, usize) {
+        let calls = AtomicUsize::new(0);
+        let res = retry(policy, || async {
+            if calls.fetch_add(1, Ordering::Relaxed) < failures {
+                Err(err())
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        (res, calls.into_inner())
+    }
+
+    fn transient_error() -> L2SyncError {
+        L2SyncError::BlockFormat("truncated response".into())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transient_errors_are_retried_with_backoff() {
+        let policy = FetchPolicy::new(5, Duration::from_secs(1), None);
+        let start = Instant::now();
+        let (res, calls) = flaky_fetch(&policy, 3, transient_error).await;
+        assert!(res.is_ok());
+        assert_eq!(calls, 4);
+        // 1s, 2s then 4s, plus up to 25% of jitter.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(7) && elapsed < Duration::from_millis(8750), "{elapsed:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_are_limited() {
+        let policy = FetchPolicy::new(2, Duration::from_millis(100), None);
+        let (res, calls) = flaky_fetch(&policy, usize::MAX, transient_error).await;
+        assert!(matches!(res, Err(L2SyncError::BlockFormat(_))));
+        assert_eq!(calls, 3);
+
+        // Missing blocks are not retried, the caller is caught up.
+        let (res, calls) =
+            flaky_fetch(&policy, 1, || ProviderError::StarknetError(StarknetError::BlockNotFound).into()).await;
+        assert!(matches!(res, Err(L2SyncError::Provider(ProviderError::StarknetError(StarknetError::BlockNotFound)))));
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_requests_wait_longer() {
+        let policy = FetchPolicy::new(5, Duration::from_millis(100), None);
+        let start = Instant::now();
+        let (res, calls) = flaky_fetch(&policy, 1, || ProviderError::RateLimited.into()).await;
+        assert!(res.is_ok());
+        assert_eq!(calls, 2);
+        assert!(start.elapsed() >= RATE_LIMITED_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let policy = FetchPolicy::new(0, Duration::ZERO, Some(20));
+        let start = std::time::Instant::now();
+        for _ in 0..5 {
+            let (res, _) = flaky_fetch(&policy, 0, transient_error).await;
+            assert!(res.is_ok());
+        }
+        // One request every 50ms, the first one is immediate.
+        assert!(start.elapsed() >= Duration::from_millis(190), "{:?}", start.elapsed());
+    }
+
+    const SIERRA_CLASS: &str = r#"{
+        "sierra_program": [],
+        "contract_class_version": "0.1.0",
+        "entry_points_by_type": { "CONSTRUCTOR": [], "EXTERNAL": [], "L1_HANDLER": [] },
+        "abi": ""
+    }"#;
+
+    #[tokio::test]
+    async fn test_concurrent_block_fetches_download_a_class_once() {
+        let mut server = mockito::Server::new_async().await;
+        let class_requests = server
+            .mock("GET", Matcher::Regex("get_class_by_hash".into()))
+            .match_query(Matcher::Any)
+            .with_body(SIERRA_CLASS)
+            .expect(3)
+            .create_async()
+            .await;
+        let url: Url = server.url().parse().unwrap();
+        let provider =
+            SequencerGatewayProvider::new(url.join("gateway").unwrap(), url.join("feeder_gateway").unwrap(), Felt::ONE);
+        let policy = FetchPolicy::new(0, Duration::ZERO, None);
+        let registry = MetricsService::new(true, false, 9615).unwrap().registry();
+        let block_metrics = BlockMetrics::register(&registry).unwrap();
+        let class_downloads = ClassDownloads::new(&block_metrics);
+        let counts = || (block_metrics.l2_classes_fetched.get(), block_metrics.l2_classes_skipped.get());
+
+        let fetch = |block_n| {
+            class_downloads.get_or_fetch(Felt::from(0x123), FetchBlockId::BlockN(BlockN(block_n)), &provider, &policy)
+        };
+        let (first, second) = tokio::join!(fetch(1), fetch(2));
+        let contract_class = first.unwrap();
+        second.unwrap();
+        assert_eq!(counts(), (1, 1));
+
+        // Once the block is handed over, the class is expected in the database.
+        class_downloads.release(&[DbClassUpdate {
+            class_hash: Felt::from(0x123),
+            contract_class,
+            compiled_class_hash: Felt::ONE,
+        }]);
+        fetch(3).await.unwrap();
+        assert_eq!(counts(), (2, 1));
+
+        // The class left the pending block.
+        class_downloads.release_classes([Felt::from(0x123)]);
+        fetch(4).await.unwrap();
+        assert_eq!(counts(), (3, 1));
+        class_requests.assert_async().await;
+    }
+}
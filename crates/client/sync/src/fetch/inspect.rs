@@ -0,0 +1,227 @@
+//! Fetches a single block from the feeder gateway and verifies it, without a database. This backs the `fetch-block`
+//! command of the node, which is the first thing to run when investigating a sync failure.
+
+use dp_block::{BlockN, DeoxysBlock};
+use dp_convert::ToStateUpdateCore;
+use dp_state_update::StateDiff;
+use dp_utils::spawn_rayon_task;
+use starknet_providers::sequencer::models as p;
+use starknet_providers::SequencerGatewayProvider;
+use starknet_types_core::felt::Felt;
+
+use super::fetchers::FetchBlockId;
+use super::validation::validate_block_response;
+use crate::convert::{block_hash_mismatch_allowed, convert_block};
+
+/// A header field of the feeder gateway that differs from the one computed from the content of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMismatch {
+    pub field: &'static str,
+    pub fetched: String,
+    pub computed: String,
+}
+
+pub struct FetchedBlock {
+    /// The block returned by the feeder gateway, as parsed by the gateway client.
+    pub fetched_block: String,
+    /// The state update returned by the feeder gateway, as parsed by the gateway client.
+    pub fetched_state_update: String,
+    pub block: DeoxysBlock,
+    pub state_diff: StateDiff,
+    /// The block hash computed from the header.
+    pub computed_block_hash: Felt,
+    /// Empty when the block verifies. The fields missing from older blocks are not compared.
+    pub mismatches: Vec<FieldMismatch>,
+}
+
+/// Fetches block `block_n` along with its state update from the feeder gateway of `provider`, converts it and computes
+/// its header again.
+pub async fn fetch_and_verify_block(
+    provider: &SequencerGatewayProvider,
+    block_n: u64,
+    chain_id: Felt,
+) -> anyhow::Result<FetchedBlock> {
+    let block_id = FetchBlockId::BlockN(BlockN(block_n));
+    #[allow(deprecated)] // Sequencer-specific functions are deprecated. Use it via the Provider trait instead.
+    let state_update_with_block = provider.get_state_update_with_block(block_id.into()).await?;
+    let (block, state_update) = (state_update_with_block.block, state_update_with_block.state_update);
+    let (fetched_block, fetched_state_update) = (format!("{block:#?}"), format!("{state_update:#?}"));
+    let state_diff = state_update.to_state_update_core().state_diff;
+    validate_block_response(block_id, &block, &state_diff)?;
+
+    let fetched = FetchedHeader::of(&block);
+    let (block, state_diff) = spawn_rayon_task(move || convert_block(block, state_diff, chain_id)).await?;
+    let computed_block_hash = block.info.header.compute_hash(chain_id);
+    let mismatches = header_mismatches(&fetched, &block, computed_block_hash, chain_id);
+
+    Ok(FetchedBlock { fetched_block, fetched_state_update, block, state_diff, computed_block_hash, mismatches })
+}
+
+/// The fields of the header of the feeder gateway that are computed again from the content of the block. The
+/// gateway client parses the response, so they can't be malformed: they are only absent from the older blocks.
+// TODO: compare the receipt commitment of 0.13.2 blocks too, the gateway client does not parse it from the header yet.
+// The block hash commits to it in the meantime.
+struct FetchedHeader {
+    block_hash: Option<Felt>,
+    transaction_commitment: Option<Felt>,
+    event_commitment: Option<Felt>,
+}
+
+impl FetchedHeader {
+    fn of(block: &p::Block) -> Self {
+        Self {
+            block_hash: block.block_hash,
+            transaction_commitment: block.transaction_commitment,
+            event_commitment: block.event_commitment,
+        }
+    }
+}
+
+fn header_mismatches(
+    fetched: &FetchedHeader,
+    block: &DeoxysBlock,
+    computed_block_hash: Felt,
+    chain_id: Felt,
+) -> Vec<FieldMismatch> {
+    let header = &block.info.header;
+    // A legacy block may have been hashed with another variant than the one of its version. The sync accepts the blocks
+    // whose hash can't be computed again.
+    let fetched_block_hash = fetched.block_hash.filter(|block_hash| {
+        !header.verify_hash(chain_id, *block_hash) && !block_hash_mismatch_allowed(header.block_number, chain_id)
+    });
+    [
+        ("block_hash", fetched_block_hash, computed_block_hash),
+        ("transaction_commitment", fetched.transaction_commitment, header.transaction_commitment),
+        ("event_commitment", fetched.event_commitment, header.event_commitment),
+    ]
+    .into_iter()
+    .filter_map(|(field, fetched, computed)| {
+        let fetched = fetched?;
+        (fetched != computed).then(|| FieldMismatch {
+            field,
+            fetched: format!("{fetched:#x}"),
+            computed: format!("{computed:#x}"),
+        })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const CHAIN_ID: Felt = Felt::from_hex_unchecked("0x534e5f4d41494e");
+
+    fn block_json() -> Value {
+        json!({
+            "block_hash": "0x0",
+            "block_number": 12,
+            "parent_block_hash": "0x1233",
+            "timestamp": 1700000000,
+            "sequencer_address": "0x1",
+            "state_root": "0x5678",
+            "status": "ACCEPTED_ON_L2",
+            "l1_da_mode": "CALLDATA",
+            "l1_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "l1_data_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "transactions": [],
+            "transaction_receipts": [],
+            "starknet_version": "0.13.2",
+        })
+    }
+
+    fn state_update_json() -> Value {
+        json!({
+            "block_hash": "0x0",
+            "new_root": "0x5678",
+            "old_root": "0x1234",
+            "state_diff": {
+                "storage_diffs": {},
+                "deployed_contracts": [],
+                "old_declared_contracts": [],
+                "declared_classes": [],
+                "replaced_classes": [],
+                "nonces": {},
+            },
+        })
+    }
+
+    /// The block with the header it computes, in the format of the feeder gateway.
+    // TODO: replace it with a block recorded from the feeder gateway, along with its state update.
+    fn recorded_block() -> Value {
+        let mut block = block_json();
+        let state_update: p::StateUpdate = serde_json::from_value(state_update_json()).unwrap();
+        let (converted, _) = convert_block(
+            serde_json::from_value(block.clone()).unwrap(),
+            state_update.to_state_update_core().state_diff,
+            CHAIN_ID,
+        )
+        .unwrap();
+        let header = &converted.info.header;
+        block["block_hash"] = json!(format!("{:#x}", header.compute_hash(CHAIN_ID)));
+        block["transaction_commitment"] = json!(format!("{:#x}", header.transaction_commitment));
+        block["event_commitment"] = json!(format!("{:#x}", header.event_commitment));
+        block["receipt_commitment"] = json!(format!("{:#x}", header.receipt_commitment));
+        block["state_diff_commitment"] = json!(format!("{:#x}", header.state_diff_commitment));
+        block["state_diff_length"] = json!(header.state_diff_length);
+        block
+    }
+
+    async fn fetch(block: Value) -> anyhow::Result<FetchedBlock> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/feeder_gateway/get_state_update")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("blockNumber".into(), "12".into()),
+                Matcher::UrlEncoded("includeBlock".into(), "true".into()),
+            ]))
+            .with_body(json!({ "block": block, "state_update": state_update_json() }).to_string())
+            .create_async()
+            .await;
+        let provider = SequencerGatewayProvider::new(
+            format!("{}/gateway", server.url()).parse().unwrap(),
+            format!("{}/feeder_gateway", server.url()).parse().unwrap(),
+            CHAIN_ID,
+        );
+        let fetched = fetch_and_verify_block(&provider, 12, CHAIN_ID).await;
+        mock.assert_async().await;
+        fetched
+    }
+
+    #[tokio::test]
+    async fn test_fetch_block_verifies() {
+        let block = recorded_block();
+        let fetched = fetch(block).await.unwrap();
+        assert_eq!(fetched.mismatches, vec![]);
+        assert_eq!(fetched.block.info.header.block_number, 12);
+        assert_eq!(fetched.computed_block_hash, fetched.block.info.block_hash);
+        assert!(fetched.fetched_block.contains("block_number"), "{}", fetched.fetched_block);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_block_reports_the_diverging_field() {
+        let mut block = recorded_block();
+        block["event_commitment"] = json!("0x1");
+        let fetched = fetch(block).await.unwrap();
+        let [mismatch] = &fetched.mismatches[..] else { panic!("Expected one mismatch: {:?}", fetched.mismatches) };
+        assert_eq!(mismatch.field, "event_commitment");
+        assert_eq!(mismatch.fetched, "0x1");
+
+        // A field of the header that is not a commitment only shows in the block hash.
+        let mut block = recorded_block();
+        block["timestamp"] = json!(1700000001);
+        let fetched = fetch(block).await.unwrap();
+        let fields: Vec<_> = fetched.mismatches.iter().map(|mismatch| mismatch.field).collect();
+        assert_eq!(fields, ["block_hash"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_block_fails_on_a_malformed_field() {
+        let mut block = recorded_block();
+        block["event_commitment"] = json!("not a felt");
+        assert!(fetch(block).await.is_err());
+    }
+}
@@ -1,46 +1,95 @@
+use std::pin::pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use anyhow::Context;
+use dc_db::worker_health::WORKER_SYNC;
 use dc_db::DeoxysBackend;
+use dp_block::BlockN;
 use dp_utils::{channel_wait_or_graceful_shutdown, wait_or_graceful_shutdown};
 use fetchers::FetchBlockId;
 use futures::prelude::*;
 use starknet_core::types::StarknetError;
-use starknet_providers::{ProviderError, SequencerGatewayProvider};
-use tokio::sync::{mpsc, oneshot};
+use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time::Interval;
 
 use self::fetchers::L2BlockAndUpdates;
-use crate::fetch::fetchers::fetch_block_and_updates;
+use crate::fetch::fetchers::{fetch_block_and_updates, ClassDownloads, FetchPolicy};
 use crate::l2::L2SyncError;
+use crate::metrics::block_metrics::{BlockMetrics, ImportStage};
+use crate::pipeline::{approx_block_size, InFlightBlock, PipelineBudget};
 
 pub mod fetchers;
+pub mod inspect;
+mod validation;
+
+/// Within this many blocks of the tip of the feeder, the blocks are fetched one at a time: the next ones may not
+/// exist yet.
+const NEAR_TIP_BLOCKS: u64 = 20;
+
+/// The first block to fetch one at a time, in the `first_block..last_block` range.
+fn near_tip_start(first_block: u64, last_block: u64, feeder_tip: Option<u64>) -> u64 {
+    feeder_tip.map_or(last_block, |tip| tip.saturating_sub(NEAR_TIP_BLOCKS)).clamp(first_block, last_block)
+}
 
 #[allow(clippy::too_many_arguments)]
 pub async fn l2_fetch_task(
     backend: Arc<DeoxysBackend>,
     first_block: u64,
     n_blocks_to_sync: Option<u64>,
-    fetch_stream_sender: mpsc::Sender<L2BlockAndUpdates>,
+    concurrency: usize,
+    fetch_stream_sender: mpsc::Sender<(L2BlockAndUpdates, InFlightBlock)>,
     provider: Arc<SequencerGatewayProvider>,
+    policy: Arc<FetchPolicy>,
+    class_downloads: Arc<ClassDownloads>,
     sync_polling_interval: Option<Duration>,
     once_caught_up_callback: oneshot::Sender<()>,
+    catch_up_notify: Arc<Notify>,
+    block_metrics: BlockMetrics,
+    pipeline: PipelineBudget,
 ) -> anyhow::Result<()> {
     // First, catch up with the chain
     let backend = &backend;
+    let (provider, policy, class_downloads, block_metrics) = (&provider, &policy, &class_downloads, &block_metrics);
+    let fetch_timed = |block_id: FetchBlockId| async move {
+        let started = Instant::now();
+        let res = fetch_block_and_updates(backend, block_id, provider, policy, class_downloads).await;
+        if res.is_ok() {
+            block_metrics.observe_import_stage(ImportStage::Fetch, started.elapsed());
+        }
+        res
+    };
 
-    let mut next_block = first_block;
+    let mut next_block = BlockN(first_block);
+    backend.worker_health().register(WORKER_SYNC, sync_polling_interval.unwrap_or_default());
 
     {
-        // Fetch blocks and updates in parallel one time before looping
-        let fetch_stream = (first_block..).take(n_blocks_to_sync.unwrap_or(u64::MAX) as _).map(|block_n| {
-            let provider = Arc::clone(&provider);
-            async move { (block_n, fetch_block_and_updates(backend, FetchBlockId::BlockN(block_n), &provider).await) }
-        });
-
-        // Have 10 fetches in parallel at once, using futures Buffered
-        let mut fetch_stream = stream::iter(fetch_stream).buffered(10);
+        let last_block = n_blocks_to_sync.map_or(u64::MAX, |n_blocks| first_block.saturating_add(n_blocks));
+        let feeder_tip = match provider.block_number().await {
+            Ok(feeder_tip) => {
+                backend.chain_head().update_highest_known_block(feeder_tip);
+                Some(feeder_tip)
+            }
+            Err(err) => {
+                log::warn!("Could not get the tip of the feeder gateway, fetching {concurrency} blocks at once: {err}");
+                None
+            }
+        };
+        let near_tip = near_tip_start(first_block, last_block, feeder_tip);
+
+        let fetch = |block_n: u64| async move {
+            let block_n = BlockN(block_n);
+            (block_n, fetch_timed(FetchBlockId::BlockN(block_n)).await)
+        };
+        // Fetch blocks and updates in parallel one time before looping, using futures Buffered
+        let fetch_stream = stream::iter(first_block..near_tip)
+            .map(fetch)
+            .buffered(concurrency.max(1))
+            .chain(stream::iter(near_tip..last_block).map(fetch).buffered(1));
+        let mut fetch_stream = pin!(fetch_stream);
         while let Some((block_n, val)) = channel_wait_or_graceful_shutdown(fetch_stream.next()).await {
-            log::debug!("got {:?}", block_n);
+            log::debug!("got #{}", block_n);
 
             match val {
                 Err(L2SyncError::Provider(ProviderError::StarknetError(StarknetError::BlockNotFound))) => {
@@ -48,42 +97,84 @@ pub async fn l2_fetch_task(
                     break;
                 }
                 val => {
-                    if fetch_stream_sender.send(val?).await.is_err() {
+                    let val = val?;
+                    class_downloads.release(&val.class_update);
+                    backend.chain_head().update_highest_known_block(block_n.0);
+                    backend.worker_health().touch(WORKER_SYNC);
+                    // The next blocks are not fetched while the pipeline is full.
+                    let in_flight = pipeline.reserve_or_warn(block_n.0, approx_block_size(&val)).await;
+                    if fetch_stream_sender.send((val, in_flight)).await.is_err() {
                         // join error
                         break;
                     }
                 }
             }
 
-            next_block = block_n + 1;
+            next_block = block_n.checked_next().context("Block number overflow")?;
         }
     };
 
     log::debug!("caught up with tip");
     let _ = once_caught_up_callback.send(());
+    if sync_polling_interval.is_none() {
+        // Done, the sync does not iterate anymore.
+        backend.worker_health().unregister(WORKER_SYNC);
+    }
 
     if let Some(sync_polling_interval) = sync_polling_interval {
         // Polling
 
         let mut interval = tokio::time::interval(sync_polling_interval);
         interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-        while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+        while wait_or_graceful_shutdown(next_poll(&mut interval, &catch_up_notify)).await.is_some() {
+            backend.worker_health().touch(WORKER_SYNC);
             loop {
-                match fetch_block_and_updates(backend, FetchBlockId::BlockN(next_block), &provider).await {
+                match fetch_timed(FetchBlockId::BlockN(next_block)).await {
                     Err(L2SyncError::Provider(ProviderError::StarknetError(StarknetError::BlockNotFound))) => {
                         break;
                     }
                     val => {
-                        if fetch_stream_sender.send(val?).await.is_err() {
+                        let val = val?;
+                        class_downloads.release(&val.class_update);
+                        backend.chain_head().update_highest_known_block(next_block.0);
+                        let in_flight = pipeline.reserve_or_warn(next_block.0, approx_block_size(&val)).await;
+                        if fetch_stream_sender.send((val, in_flight)).await.is_err() {
                             // stream closed
                             break;
                         }
                     }
                 }
 
-                next_block += 1;
+                next_block = next_block.checked_next().context("Block number overflow")?;
             }
         }
     }
     Ok(())
 }
+
+/// Waits for the next polling tick. The pending block task may wake us up early when it sees that the feeder is
+/// ahead of us.
+async fn next_poll(interval: &mut Interval, catch_up_notify: &Notify) {
+    tokio::select! {
+        _ = interval.tick() => {}
+        _ = catch_up_notify.notified() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_tip_start() {
+        // Far from the tip, until 20 blocks before it.
+        assert_eq!(near_tip_start(0, u64::MAX, Some(1000)), 980);
+        // Already close to the tip.
+        assert_eq!(near_tip_start(990, u64::MAX, Some(1000)), 990);
+        assert_eq!(near_tip_start(0, u64::MAX, Some(5)), 0);
+        // Only a few blocks to sync, all of them far from the tip.
+        assert_eq!(near_tip_start(0, 100, Some(1000)), 100);
+        // Unknown tip.
+        assert_eq!(near_tip_start(0, 100, None), 100);
+    }
+}
@@ -0,0 +1,172 @@
+//! Sanity checks on the feeder gateway responses, before they reach conversion.
+//!
+//! A flaky proxy in front of the feeder gateway can return truncated or mixed up responses that still deserialize.
+//! Rejecting them here makes the fetcher retry, instead of failing deep in the conversion or, worse, storing a
+//! corrupted block.
+
+use std::collections::HashSet;
+
+use starknet_core::types::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff,
+};
+use starknet_providers::sequencer::models as p;
+use starknet_types_core::felt::Felt;
+
+use super::fetchers::FetchBlockId;
+use crate::l2::L2SyncError;
+
+/// Contract addresses live in `[0, 2**251 - 256)`.
+const L2_ADDRESS_UPPER_BOUND: Felt =
+    Felt::from_hex_unchecked("0x7ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00");
+
+/// Checks that the block and state diff returned by the feeder gateway for `block_id` are plausible.
+pub(crate) fn validate_block_response(
+    block_id: FetchBlockId,
+    block: &p::Block,
+    state_diff: &StateDiff,
+) -> Result<(), L2SyncError> {
+    let invalid = |field| Err(L2SyncError::ProviderResponseInvalid { field, block: block_id });
+
+    if let Some(requested) = block_id.block_n() {
+        if block.block_number != Some(requested.0) {
+            return invalid("block_number");
+        }
+        if requested.0 != 0 && block.timestamp == 0 {
+            return invalid("timestamp");
+        }
+    }
+    if block.transaction_receipts.len() != block.transactions.len() {
+        return invalid("transaction_receipts");
+    }
+
+    let addresses = std::iter::empty()
+        .chain(state_diff.storage_diffs.iter().map(|ContractStorageDiffItem { address, .. }| address))
+        .chain(state_diff.deployed_contracts.iter().map(|DeployedContractItem { address, .. }| address))
+        .chain(state_diff.replaced_classes.iter().map(|ReplacedClassItem { contract_address, .. }| contract_address))
+        .chain(state_diff.nonces.iter().map(|NonceUpdate { contract_address, .. }| contract_address));
+    for address in addresses {
+        if *address >= L2_ADDRESS_UPPER_BOUND {
+            return invalid("state_diff.address");
+        }
+    }
+
+    if !all_unique(state_diff.declared_classes.iter().map(|DeclaredClassItem { class_hash, .. }| class_hash)) {
+        return invalid("state_diff.declared_classes");
+    }
+    if !all_unique(state_diff.deprecated_declared_classes.iter()) {
+        return invalid("state_diff.old_declared_contracts");
+    }
+
+    Ok(())
+}
+
+fn all_unique<'a>(mut hashes: impl Iterator<Item = &'a Felt>) -> bool {
+    let mut seen = HashSet::new();
+    hashes.all(|hash| seen.insert(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use dp_block::BlockN;
+
+    use super::*;
+
+    fn block(block_number: u64, timestamp: u64, n_receipts: u64) -> p::Block {
+        let receipts: Vec<_> = (0..n_receipts)
+            .map(|i| {
+                serde_json::json!({
+                    "transaction_hash": format!("{:#x}", i),
+                    "transaction_index": i,
+                    "execution_status": "SUCCEEDED",
+                    "l2_to_l1_messages": [],
+                    "events": [],
+                    "actual_fee": "0x0",
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "block_hash": "0x1234",
+            "block_number": block_number,
+            "parent_block_hash": "0x1233",
+            "timestamp": timestamp,
+            "sequencer_address": "0x1",
+            "state_root": "0x5678",
+            "status": "ACCEPTED_ON_L2",
+            "l1_da_mode": "CALLDATA",
+            "l1_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "l1_data_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "transactions": [],
+            "transaction_receipts": receipts,
+            "starknet_version": "0.13.1",
+        }))
+        .unwrap()
+    }
+
+    fn empty_state_diff() -> StateDiff {
+        StateDiff {
+            storage_diffs: vec![],
+            deprecated_declared_classes: vec![],
+            declared_classes: vec![],
+            deployed_contracts: vec![],
+            replaced_classes: vec![],
+            nonces: vec![],
+        }
+    }
+
+    fn field_of(res: Result<(), L2SyncError>) -> &'static str {
+        match res {
+            Err(L2SyncError::ProviderResponseInvalid { field, .. }) => field,
+            res => panic!("Expected an invalid response, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_valid_response() {
+        let id = FetchBlockId::BlockN(BlockN(12));
+        let mut state_diff = empty_state_diff();
+        state_diff.deployed_contracts =
+            vec![DeployedContractItem { address: Felt::from(0x100), class_hash: Felt::ONE }];
+        state_diff.declared_classes = vec![
+            DeclaredClassItem { class_hash: Felt::ONE, compiled_class_hash: Felt::TWO },
+            DeclaredClassItem { class_hash: Felt::TWO, compiled_class_hash: Felt::THREE },
+        ];
+        validate_block_response(id, &block(12, 1700000000, 0), &state_diff).unwrap();
+        // The genesis block may have a zero timestamp.
+        validate_block_response(FetchBlockId::BlockN(BlockN(0)), &block(0, 0, 0), &empty_state_diff()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_block() {
+        let id = FetchBlockId::BlockN(BlockN(12));
+        let state_diff = empty_state_diff();
+
+        assert_eq!(field_of(validate_block_response(id, &block(11, 1700000000, 0), &state_diff)), "block_number");
+        assert_eq!(field_of(validate_block_response(id, &block(12, 0, 0), &state_diff)), "timestamp");
+        assert_eq!(
+            field_of(validate_block_response(id, &block(12, 1700000000, 2), &state_diff)),
+            "transaction_receipts"
+        );
+    }
+
+    #[test]
+    fn test_invalid_state_diff() {
+        let id = FetchBlockId::BlockN(BlockN(12));
+        let block = block(12, 1700000000, 0);
+
+        let mut state_diff = empty_state_diff();
+        state_diff.replaced_classes =
+            vec![ReplacedClassItem { contract_address: L2_ADDRESS_UPPER_BOUND, class_hash: Felt::ONE }];
+        assert_eq!(field_of(validate_block_response(id, &block, &state_diff)), "state_diff.address");
+
+        let mut state_diff = empty_state_diff();
+        state_diff.declared_classes = vec![
+            DeclaredClassItem { class_hash: Felt::ONE, compiled_class_hash: Felt::TWO },
+            DeclaredClassItem { class_hash: Felt::ONE, compiled_class_hash: Felt::THREE },
+        ];
+        assert_eq!(field_of(validate_block_response(id, &block, &state_diff)), "state_diff.declared_classes");
+
+        let mut state_diff = empty_state_diff();
+        state_diff.deprecated_declared_classes = vec![Felt::TWO, Felt::TWO];
+        assert_eq!(field_of(validate_block_response(id, &block, &state_diff)), "state_diff.old_declared_contracts");
+    }
+}
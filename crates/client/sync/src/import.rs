@@ -0,0 +1,295 @@
+//! Imports blocks that are already converted, such as the blocks of another database or of a dump file. Each block
+//! must follow the tip of the database, its hash is computed again from its content, its classes are checked against
+//! their class hash and the state root is computed again from its state diff before it is stored: the import stops at
+//! the first block that diverges.
+//!
+//! The blocks must already be in the current types.
+//!
+//! TODO: a `migrate-legacy-db` command reading the databases of the Substrate-based versions, with its decoder behind
+//! a cargo feature and a round-trip test over a checked-in legacy database fixture. It needs the legacy column
+//! encoding and a real legacy database to check the decoder against.
+
+use std::collections::HashMap;
+
+use dc_db::{DeoxysBackend, DeoxysStorageError};
+use dp_block::{BlockId, BlockTag, BlockVerification, DeoxysBlock, DeoxysMaybePendingBlock, Header};
+use dp_class::{ClassHash, ContractClass, ConvertedClass};
+use dp_state_update::StateDiff;
+use rayon::prelude::*;
+use starknet_types_core::felt::Felt;
+
+use crate::commitments::{update_tries_and_compute_state_root, CommitmentError};
+use crate::convert::{block_hash_mismatch_allowed, compute_commitments_for_block, BlockCommitments};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ImportedBlock {
+    pub block: DeoxysBlock,
+    pub state_diff: StateDiff,
+    pub converted_classes: Vec<ConvertedClass>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("Expected block {expected}, got block {got}")]
+    UnexpectedBlockNumber { expected: u64, got: u64 },
+    #[error("Block {block_n} diverges: its hash is {block_hash:#x}, but its content hashes to {computed_hash:#x}")]
+    Divergence { block_n: u64, block_hash: Felt, computed_hash: Felt },
+    #[error("Block {block_n} builds on block {parent_block_hash:#x}, but the block before it is {expected:#x}")]
+    ParentMismatch { block_n: u64, parent_block_hash: Felt, expected: Felt },
+    #[error("Block {block_n} has state root {expected:#x}, but its state diff leads to {got:#x}")]
+    MismatchedStateRoot { block_n: u64, expected: Felt, got: Felt },
+    #[error("Block {block_n} comes with class {class_hash:#x}, which it does not declare")]
+    UndeclaredClass { block_n: u64, class_hash: Felt },
+    #[error("Block {block_n} declares class {class_hash:#x}, but its definition hashes to {computed_hash:#x}")]
+    MismatchedClassHash { block_n: u64, class_hash: Felt, computed_hash: Felt },
+    #[error("Block {block_n} declares class {class_hash:#x} with compiled class hash {expected:#x}, got {got:#x}")]
+    MismatchedCompiledClassHash { block_n: u64, class_hash: Felt, expected: Felt, got: Felt },
+    #[error("Failed to compute the hash of class {class_hash:#x} of block {block_n}: {source:#}")]
+    ClassHash { block_n: u64, class_hash: Felt, source: anyhow::Error },
+    #[error("Failed to compute the commitments of block {block_n}: {source}")]
+    Commitment { block_n: u64, source: CommitmentError },
+    #[error("Reading block {block_n}: {source:#}")]
+    Source { block_n: u64, source: anyhow::Error },
+    #[error("Storage error: {0:#}")]
+    Storage(#[from] DeoxysStorageError),
+}
+
+/// Imports the blocks in order, until they run out or one of them fails. Returns the number of blocks imported.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn import_blocks(
+    backend: &DeoxysBackend,
+    blocks: impl IntoIterator<Item = anyhow::Result<ImportedBlock>>,
+    chain_id: Felt,
+) -> Result<u64, ImportError> {
+    let mut next_block_n = backend.get_latest_block_n()?.map(|block_n| block_n + 1).unwrap_or(0);
+    let first_block_n = next_block_n;
+    let mut tip_hash = backend
+        .get_block_info(&BlockId::Tag(BlockTag::Latest))?
+        .and_then(|info| info.as_nonpending().map(|info| info.block_hash));
+
+    for block in blocks {
+        let ImportedBlock { mut block, state_diff, converted_classes } =
+            block.map_err(|source| ImportError::Source { block_n: next_block_n, source })?;
+        let block_n = block.info.header.block_number;
+        if block_n != next_block_n {
+            return Err(ImportError::UnexpectedBlockNumber { expected: next_block_n, got: block_n });
+        }
+        let verification = verify_block_hash(&block, &state_diff, chain_id)?;
+        let parent_block_hash = block.info.header.parent_block_hash;
+        if let Some(expected) = tip_hash.filter(|tip_hash| *tip_hash != parent_block_hash) {
+            return Err(ImportError::ParentMismatch { block_n, parent_block_hash, expected });
+        }
+        verify_classes(block_n, &state_diff, &converted_classes)?;
+
+        // This commits the block to the global tries.
+        let tries_update = backend.lock_tries_update();
+        let state_root = update_tries_and_compute_state_root(backend, &state_diff, block_n);
+        if state_root != block.info.header.global_state_root {
+            // The block is not stored, the import can start again from the tip.
+            backend.revert_tries_to(block_n.checked_sub(1), block_n)?;
+            return Err(ImportError::MismatchedStateRoot {
+                block_n,
+                expected: block.info.header.global_state_root,
+                got: state_root,
+            });
+        }
+        drop(tries_update);
+
+        // The verification of the source is not trusted.
+        block.info.verification = verification | BlockVerification::VERIFIED_ROOT;
+        tip_hash = Some(block.info.block_hash);
+        backend.store_block(DeoxysMaybePendingBlock::from(block), state_diff, converted_classes)?;
+        next_block_n += 1;
+        if next_block_n % 1000 == 0 {
+            log::info!("📥 Imported block {block_n}");
+        }
+    }
+
+    Ok(next_block_n - first_block_n)
+}
+
+/// Checks that the classes of the block are the ones its state diff declares, and that their definitions hash to their
+/// class hash.
+///
+/// The hash of some legacy classes is known not to be computed right yet, see [`crate::convert::convert_and_verify_class`]:
+/// a mismatch of a legacy class is only logged.
+fn verify_classes(
+    block_n: u64,
+    state_diff: &StateDiff,
+    converted_classes: &[ConvertedClass],
+) -> Result<(), ImportError> {
+    let declared: HashMap<_, _> = state_diff
+        .deprecated_declared_classes()
+        .iter()
+        .map(|class_hash| (*class_hash, None))
+        .chain(state_diff.declared_classes().iter().map(|item| (item.class_hash, Some(item.compiled_class_hash))))
+        .collect();
+
+    converted_classes.par_iter().try_for_each(
+        |ConvertedClass { class_infos: (class_hash, class_info), class_compiled }| {
+            let class_hash = *class_hash;
+            let Some(&declared_compiled_class_hash) = declared.get(&class_hash) else {
+                return Err(ImportError::UndeclaredClass { block_n, class_hash });
+            };
+            if class_compiled.0 != class_hash {
+                return Err(ImportError::UndeclaredClass { block_n, class_hash: class_compiled.0 });
+            }
+            if let Some(expected) =
+                declared_compiled_class_hash.filter(|expected| *expected != class_info.compiled_class_hash)
+            {
+                let got = class_info.compiled_class_hash;
+                return Err(ImportError::MismatchedCompiledClassHash { block_n, class_hash, expected, got });
+            }
+
+            let computed_hash = starknet_core::types::ContractClass::from(class_info.contract_class.clone())
+                .class_hash()
+                .map_err(|source| ImportError::ClassHash { block_n, class_hash, source })?;
+            match &class_info.contract_class {
+                _ if computed_hash == class_hash => Ok(()),
+                ContractClass::Legacy(_) => {
+                    log::warn!("⚠️  Legacy class {class_hash:#x} of block {block_n} hashes to {computed_hash:#x}");
+                    Ok(())
+                }
+                ContractClass::Sierra(_) => {
+                    Err(ImportError::MismatchedClassHash { block_n, class_hash, computed_hash })
+                }
+            }
+        },
+    )
+}
+
+/// Computes the commitments of the header again, and checks that the block hash matches them. Returns what was
+/// verified: nothing for the blocks whose hash is allowed not to match.
+pub(crate) fn verify_block_hash(
+    block: &DeoxysBlock,
+    state_diff: &StateDiff,
+    chain_id: Felt,
+) -> Result<BlockVerification, ImportError> {
+    let header = &block.info.header;
+    let BlockCommitments {
+        transaction_commitment,
+        transaction_count,
+        event_commitment,
+        event_count,
+        state_diff_length,
+        state_diff_commitment,
+        receipt_commitment,
+        tx_hashes: _,
+    } = compute_commitments_for_block(&block.inner, state_diff, header.protocol_version, chain_id, header.block_number)
+        .map_err(|source| ImportError::Commitment { block_n: header.block_number, source })?;
+    let computed_header = Header {
+        transaction_count,
+        transaction_commitment,
+        event_count,
+        event_commitment,
+        state_diff_length,
+        state_diff_commitment,
+        receipt_commitment,
+        ..header.clone()
+    };
+
+    if !computed_header.verify_hash(chain_id, block.info.block_hash) {
+        if block_hash_mismatch_allowed(header.block_number, chain_id) {
+            log::warn!(
+                "⚠️  Block {} has a block hash that cannot be verified, importing it unverified",
+                header.block_number
+            );
+            return Ok(BlockVerification::NONE);
+        }
+        return Err(ImportError::Divergence {
+            block_n: header.block_number,
+            block_hash: block.info.block_hash,
+            computed_hash: computed_header.compute_hash(chain_id),
+        });
+    }
+    Ok(BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::DatabaseService;
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner};
+    use dp_transactions::MAIN_CHAIN_ID;
+
+    use super::*;
+
+    /// Empty blocks chained to each other.
+    fn blocks(n_blocks: u64) -> Vec<ImportedBlock> {
+        let mut parent_block_hash = Felt::ZERO;
+        (0..n_blocks)
+            .map(|block_number| {
+                let inner = DeoxysBlockInner::new(vec![], vec![]);
+                let state_diff = StateDiff::default();
+                let commitments =
+                    compute_commitments_for_block(&inner, &state_diff, Default::default(), MAIN_CHAIN_ID, block_number)
+                        .unwrap();
+                let header = Header {
+                    parent_block_hash,
+                    block_number,
+                    block_timestamp: 1700000000 + block_number,
+                    transaction_commitment: commitments.transaction_commitment,
+                    event_commitment: commitments.event_commitment,
+                    state_diff_commitment: commitments.state_diff_commitment,
+                    receipt_commitment: commitments.receipt_commitment,
+                    ..Default::default()
+                };
+                let block_hash = header.compute_hash(MAIN_CHAIN_ID);
+                parent_block_hash = block_hash;
+                ImportedBlock {
+                    block: DeoxysBlock::new(DeoxysBlockInfo::new(header, vec![], block_hash), inner),
+                    state_diff,
+                    converted_classes: vec![],
+                }
+            })
+            .collect()
+    }
+
+    async fn backend(temp_dir: &tempfile::TempDir) -> DatabaseService {
+        DatabaseService::new(temp_dir.path(), None, false, Arc::new(ChainConfig::starknet_mainnet())).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_blocks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = backend(&temp_dir).await;
+
+        let imported = import_blocks(db.backend(), blocks(5).into_iter().take(3).map(Ok), MAIN_CHAIN_ID).unwrap();
+        assert_eq!(imported, 3);
+        assert_eq!(db.backend().get_latest_block_n().unwrap(), Some(2));
+        assert_eq!(
+            db.backend().get_block_verification(&BlockId::Number(2)).unwrap(),
+            Some(
+                BlockVerification::VERIFIED_HASH
+                    | BlockVerification::VERIFIED_COMMITMENTS
+                    | BlockVerification::VERIFIED_ROOT
+            )
+        );
+
+        // The import resumes from the tip.
+        let res = import_blocks(db.backend(), blocks(5).into_iter().take(3).map(Ok), MAIN_CHAIN_ID);
+        assert!(matches!(res, Err(ImportError::UnexpectedBlockNumber { expected: 3, got: 0 })));
+        let imported = import_blocks(db.backend(), blocks(5).into_iter().skip(3).map(Ok), MAIN_CHAIN_ID);
+        assert_eq!(imported.unwrap(), 2);
+        assert_eq!(db.backend().get_latest_block_n().unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_import_stops_at_the_first_divergence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = backend(&temp_dir).await;
+        let mut tampered = blocks(4);
+        tampered[2].block.info.header.global_state_root = Felt::ONE;
+        let block_hash = tampered[2].block.info.block_hash;
+
+        let res = import_blocks(db.backend(), tampered.into_iter().map(Ok), MAIN_CHAIN_ID);
+        assert!(matches!(
+            res,
+            Err(ImportError::Divergence { block_n: 2, block_hash: hash, .. }) if hash == block_hash
+        ));
+        assert_eq!(db.backend().get_latest_block_n().unwrap(), Some(1));
+    }
+}
@@ -1,32 +1,54 @@
 //! Contains the code required to sync data from the feeder efficiently.
-use crate::commitments::update_tries_and_compute_state_root;
-use crate::convert::{convert_and_verify_block, convert_and_verify_class};
-use crate::fetch::fetchers::{fetch_block_and_updates, FetchBlockId, L2BlockAndUpdates};
+use crate::commitments::{update_tries_and_compute_state_root, CommitmentError};
+use crate::convert::{
+    convert_and_verify_block, convert_and_verify_class, ConvertClassError, TransactionConversionError,
+};
+use crate::fast_sync::{commit_uncommitted_blocks, FastSyncConfig, StateDiffBatch};
+use crate::fetch::fetchers::{
+    fetch_block_and_updates, is_skipped_class, ClassDownloads, FetchBlockId, FetchPolicy, GatewayClassRefetcher,
+    L2BlockAndUpdates,
+};
 use crate::fetch::l2_fetch_task;
-use crate::metrics::block_metrics::BlockMetrics;
+use crate::metrics::block_metrics::{BlockMetrics, ImportStage};
+use crate::pipeline::{InFlightBlock, PipelineBudget, PipelineConfig};
+use crate::reorgs::pending::{pending_parent_decision, PendingParentDecision};
+use crate::reorgs::{
+    find_common_ancestor, reorg_decision, repair_interrupted_revert, FeederChain, GatewayFeederChain, ReorgDecision,
+};
 use crate::utility::trim_hash;
-use anyhow::{bail, Context};
+use crate::verification::delayed_verification_task;
+use anyhow::Context;
+use dc_db::block_db::SyncStall;
 use dc_db::db_metrics::DbMetrics;
+use dc_db::worker_health::WORKER_SYNC_APPLY;
 use dc_db::DeoxysBackend;
 use dc_db::DeoxysStorageError;
 use dc_telemetry::{TelemetryHandle, VerbosityLevel};
-use dp_block::{BlockId, BlockTag, DeoxysBlock, DeoxysMaybePendingBlockInfo, StarknetVersionError};
+use dp_block::{
+    BlockId, BlockN, BlockTag, BlockVerification, DeoxysBlock, DeoxysBlockInfo, DeoxysMaybePendingBlockInfo,
+    StarknetVersion, StarknetVersionError,
+};
 use dp_block::{DeoxysMaybePendingBlock, Header};
 use dp_class::ConvertedClass;
 use dp_state_update::StateDiff;
-use dp_transactions::TransactionTypeError;
+use dp_utils::clock::Clock;
+use dp_utils::compute_pool::{spawn_compute, ComputePool};
+use dp_utils::error_reporting::{self, ErrorReporter, InstalledReporter, Severity};
 use dp_utils::{
     channel_wait_or_graceful_shutdown, spawn_rayon_task, stopwatch_end, wait_or_graceful_shutdown, PerfStopwatch,
 };
+use futures::future::BoxFuture;
 use futures::{stream, StreamExt};
 use num_traits::FromPrimitive;
-use starknet_providers::{ProviderError, SequencerGatewayProvider};
+use starknet_core::types::{MaybePendingBlockWithTxHashes, StarknetError};
+use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
 use starknet_types_core::felt::Felt;
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::pin::pin;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinSet;
 use tokio::time::Duration;
 
@@ -39,14 +61,114 @@ pub enum L2SyncError {
     Db(#[from] DeoxysStorageError),
     #[error("Malformated block: {0}")]
     BlockFormat(Cow<'static, str>),
+    #[error("Failed to compute the commitments of block {block_n}: {source}")]
+    Commitment { block_n: u64, source: CommitmentError },
     #[error("Mismatched block hash for block {0}")]
     MismatchedBlockHash(u64),
+    #[error("Verified state root: {got:#x} doesn't match fetched state root: {expected:#x} for block {block}")]
+    MismatchedStateRoot { block: u64, expected: Felt, got: Felt },
+    #[error("Block {block_n} builds on block {parent_block_hash:#x}, but the block before it is {expected:#x}")]
+    ParentMismatch { block_n: u64, parent_block_hash: Felt, expected: Felt },
+    #[error("Cannot start syncing from block {first_block}, the database tip is block {tip}")]
+    FirstBlockConflict { first_block: u64, tip: u64 },
     #[error("Gas price is too high: 0x{0:x}")]
     GasPriceOutOfBounds(Felt),
     #[error("Invalid Starknet version: {0}")]
     InvalidStarknetVersion(#[from] StarknetVersionError),
-    #[error("Invalid transaction: {0}")]
-    InvalidTransaction(#[from] TransactionTypeError),
+    #[error(
+        "Block {1} is at Starknet version {0}, the latest supported version is {}",
+        StarknetVersion::LATEST_SUPPORTED
+    )]
+    UnsupportedProtocolVersion(StarknetVersion, u64),
+    #[error(transparent)]
+    TransactionConversion(#[from] TransactionConversionError),
+    #[error("Invalid `{field}` in the provider response for block {block:?}")]
+    ProviderResponseInvalid { field: &'static str, block: FetchBlockId },
+    #[error(transparent)]
+    ClassConversion(#[from] ConvertClassError),
+    #[error("Block {block_n} declares class {class_hash:#x}, which could not be fetched")]
+    MissingClass { block_n: u64, class_hash: Felt },
+    #[error("The chain of the feeder forks from ours after block {common_ancestor}, reverted the blocks up to {tip}")]
+    Reverted { tip: u64, common_ancestor: u64 },
+    #[error("The chain of the feeder forks from ours more than {max_auto_reorg_depth} blocks below our tip {tip}")]
+    DeepReorg { tip: u64, common_ancestor: Option<u64>, max_auto_reorg_depth: u64 },
+}
+
+/// Called by the sync after every imported block, once it is stored.
+pub trait BlockImportHook: Send + Sync {
+    fn on_block_imported(&self, block_info: &DeoxysBlockInfo, state_diff: &StateDiff) -> anyhow::Result<()>;
+}
+
+/// Fetches again the classes declared by a block that did not come with it.
+pub trait ClassRefetcher: Send + Sync {
+    /// `classes` are the class hashes with their compiled class hash, which is zero for legacy classes.
+    fn refetch(&self, block_n: u64, classes: Vec<(Felt, Felt)>) -> BoxFuture<'_, anyhow::Result<Vec<ConvertedClass>>>;
+}
+
+/// Backoff of the refetches of the classes missing from a block. The gateway ends up serving them: the sync waits for
+/// them instead of halting.
+#[derive(Debug, Clone, Copy)]
+struct RefetchBackoff {
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+const CLASS_REFETCH_BACKOFF: RefetchBackoff =
+    RefetchBackoff { base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(60) };
+
+impl RefetchBackoff {
+    /// The delay before the refetch following `attempt` failed ones.
+    fn delay(&self, attempt: u32) -> Duration {
+        if attempt == 0 {
+            return Duration::ZERO;
+        }
+        self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(self.max_delay)
+    }
+}
+
+/// How often the apply stage touches its worker while no block is fetched. A block storing for ten times this long,
+/// retries included, makes the stage stale.
+const APPLY_IDLE_TOUCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Backoff of the block imports that fail with a transient database error, see [`DeoxysStorageError::is_transient`].
+/// The other errors stop the sync.
+#[derive(Debug, Clone, Copy)]
+struct StorageRetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+const STORAGE_RETRY_POLICY: StorageRetryPolicy =
+    StorageRetryPolicy { max_retries: 8, base_delay: Duration::from_millis(100), max_delay: Duration::from_secs(10) };
+
+impl StorageRetryPolicy {
+    /// The delay before retrying an import that failed `attempt` times, the last time with `err`. `None` when the import
+    /// should not be retried.
+    fn retry_delay(&self, attempt: u32, err: &anyhow::Error) -> Option<Duration> {
+        if attempt == 0 || attempt > self.max_retries || !DeoxysStorageError::is_transient_source(err.as_ref()) {
+            return None;
+        }
+        Some(self.base_delay.saturating_mul(2u32.saturating_pow(attempt - 1)).min(self.max_delay))
+    }
+}
+
+/// Stores a block of the chain and runs the import hook.
+///
+/// NB: This functions needs to run on the rayon thread pool
+fn import_block(
+    backend: &DeoxysBackend,
+    block_import_hook: Option<&dyn BlockImportHook>,
+    block: &DeoxysBlock,
+    state_diff: &StateDiff,
+    converted_classes: &[ConvertedClass],
+) -> anyhow::Result<()> {
+    backend.store_closed_block(block, state_diff, converted_classes).context("Storing new block")?;
+
+    if let Some(hook) = block_import_hook {
+        hook.on_block_imported(&block.info, state_diff).context("Running block import hook")?;
+    }
+    Ok(())
 }
 
 /// Contains the latest Starknet verified state on L2
@@ -57,72 +179,339 @@ pub struct L2StateUpdate {
     pub block_hash: Felt,
 }
 
+/// A mismatch means that either the feeder or our state is wrong: this is reported to the node operator.
+fn verify_state_root(
+    reporter: &dyn ErrorReporter,
+    block_n: u64,
+    fetched_state_root: Felt,
+    computed_state_root: Felt,
+) -> Result<(), L2SyncError> {
+    if fetched_state_root != computed_state_root {
+        let err =
+            L2SyncError::MismatchedStateRoot { block: block_n, expected: fetched_state_root, got: computed_state_root };
+        error_reporting::report_to(reporter, Severity::Critical, "sync.verify", &err.to_string());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// The classes declared by the block that are neither stored with it nor already in the database.
+fn missing_classes(
+    backend: &DeoxysBackend,
+    state_diff: &StateDiff,
+    converted_classes: &[ConvertedClass],
+) -> Result<Vec<(Felt, Felt)>, L2SyncError> {
+    let converted: HashSet<_> = converted_classes.iter().map(|class| class.class_infos.0).collect();
+    let declared = state_diff
+        .deprecated_declared_classes()
+        .iter()
+        .map(|class_hash| (*class_hash, Felt::ZERO))
+        .chain(state_diff.declared_classes().iter().map(|item| (item.class_hash, item.compiled_class_hash)));
+
+    let mut missing = vec![];
+    for (class_hash, compiled_class_hash) in declared {
+        if converted.contains(&class_hash) || is_skipped_class(&class_hash) {
+            continue;
+        }
+        if !backend.contains_class(&BlockId::Tag(BlockTag::Latest), &class_hash)? {
+            missing.push((class_hash, compiled_class_hash));
+        }
+    }
+    Ok(missing)
+}
+
+/// A block is only stored along with every class it declares. The missing ones are fetched again a few times before
+/// the import fails.
+/// Returns `None` when the node shuts down before the classes are fetched.
+async fn complete_classes(
+    backend: &DeoxysBackend,
+    class_refetcher: Option<&dyn ClassRefetcher>,
+    backoff: RefetchBackoff,
+    block_metrics: &BlockMetrics,
+    block_n: u64,
+    state_diff: &StateDiff,
+    mut converted_classes: Vec<ConvertedClass>,
+) -> Result<Option<Vec<ConvertedClass>>, L2SyncError> {
+    let mut attempt = 0;
+    loop {
+        let missing = missing_classes(backend, state_diff, &converted_classes)?;
+        let Some(&(class_hash, _)) = missing.first() else { return Ok(Some(converted_classes)) };
+        let Some(class_refetcher) = class_refetcher else {
+            return Err(L2SyncError::MissingClass { block_n, class_hash });
+        };
+
+        let delay = backoff.delay(attempt);
+        log::warn!(
+            "⚠️  Block {block_n} declares {} classes that were not fetched, fetching them again in {delay:?}",
+            missing.len()
+        );
+        if wait_or_graceful_shutdown(tokio::time::sleep(delay)).await.is_none() {
+            return Ok(None);
+        }
+        attempt += 1;
+        block_metrics.l2_class_refetches.inc();
+        match class_refetcher.refetch(block_n, missing).await {
+            Ok(classes) => converted_classes.extend(classes),
+            // Compiling the class again would fail the same way.
+            Err(err) => match err.downcast::<ConvertClassError>() {
+                Ok(err) => return Err(err.into()),
+                Err(err) => log::warn!("⚠️  Fetching the classes of block {block_n} again: {err:#}"),
+            },
+        }
+    }
+}
+
+/// Lets the verification task stop the pending block task before it reverts the chain, so that no pending block of the
+/// reverted chain is stored in the meantime.
+#[derive(Clone, Default)]
+pub(crate) struct PendingTaskControl {
+    stopped: Arc<tokio::sync::Mutex<bool>>,
+}
+
+impl PendingTaskControl {
+    /// Waits for the poll of the pending block task in flight, and stops the task. The task does not store anything
+    /// while the guard is held.
+    async fn stop(&self) -> tokio::sync::MutexGuard<'_, bool> {
+        let mut stopped = self.stopped.lock().await;
+        *stopped = true;
+        stopped
+    }
+
+    /// Held by the pending block task during a poll, `None` once the task is stopped.
+    async fn poll(&self) -> Option<tokio::sync::MutexGuard<'_, bool>> {
+        let stopped = self.stopped.lock().await;
+        (!*stopped).then_some(stopped)
+    }
+}
+
+/// Reverts our chain to its common ancestor with the chain of the feeder, or halts the sync when they fork deeper than
+/// `max_auto_reorg_depth` blocks below our tip. Returns the error that stops the current sync.
+async fn handle_reorg(
+    backend: &Arc<DeoxysBackend>,
+    feeder_chain: &dyn FeederChain,
+    pending_task: &PendingTaskControl,
+    revert_tries: bool,
+    block_metrics: &BlockMetrics,
+) -> anyhow::Result<L2SyncError> {
+    let tip = backend.get_latest_block_n()?.context("Looking for a fork of an empty chain")?;
+    let common_ancestor = find_common_ancestor(backend, feeder_chain, tip).await?;
+    let max_auto_reorg_depth = backend.chain_config().max_auto_reorg_depth;
+
+    match reorg_decision(tip, common_ancestor, max_auto_reorg_depth) {
+        ReorgDecision::Revert { common_ancestor } => {
+            // The sync starts over once the chain is reverted, with a new pending block task.
+            let _pending_task = pending_task.stop().await;
+            let backend = Arc::clone(backend);
+            spawn_rayon_task(move || backend.revert_to(common_ancestor.0, revert_tries))
+                .await
+                .context("Reverting the chain")?;
+            block_metrics.l2_reorgs.inc();
+            Ok(L2SyncError::Reverted { tip, common_ancestor: common_ancestor.0 })
+        }
+        ReorgDecision::Halt => {
+            let common_ancestor = common_ancestor.map(|block_n| block_n.0);
+            backend.write_sync_stall(&SyncStall::DeepReorg { tip_block_n: tip, common_ancestor })?;
+            block_metrics.l2_sync_stalled.set(1.0);
+            let err = L2SyncError::DeepReorg { tip, common_ancestor, max_auto_reorg_depth };
+            error_reporting::report(Severity::Critical, "sync.reorg", &err.to_string());
+            Ok(err)
+        }
+    }
+}
+
+/// How the verification and apply task handles the blocks it receives.
+#[derive(Clone, Default)]
+struct ApplyConfig {
+    verify: bool,
+    ignore_state_root_mismatch: bool,
+    fast_sync: Option<FastSyncConfig>,
+    backup_every_n_blocks: Option<u64>,
+    /// The first block of this run, the sync speed is measured from it.
+    starting_block: u64,
+    block_import_hook: Option<Arc<dyn BlockImportHook>>,
+    /// Fetches the classes that failed to convert again, `None` to fail on them.
+    class_refetcher: Option<Arc<dyn ClassRefetcher>>,
+    /// Checks a block that does not build on our tip against the feeder's chain, `None` to fail on it.
+    feeder_chain: Option<Arc<dyn FeederChain>>,
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn l2_verify_and_apply_task(
     backend: Arc<DeoxysBackend>,
     mut updates_receiver: mpsc::Receiver<L2ConvertedBlockAndUpdates>,
-    verify: bool,
-    backup_every_n_blocks: Option<u64>,
+    config: ApplyConfig,
     block_metrics: BlockMetrics,
     db_metrics: DbMetrics,
-    starting_block: u64,
     sync_timer: Arc<Mutex<Option<Instant>>>,
     telemetry: TelemetryHandle,
+    pending_task: PendingTaskControl,
+    compute_pool: ComputePool,
 ) -> anyhow::Result<()> {
-    while let Some(L2ConvertedBlockAndUpdates { converted_block, converted_state_diff, converted_classes }) =
-        channel_wait_or_graceful_shutdown(pin!(updates_receiver.recv())).await
+    let ApplyConfig {
+        verify,
+        ignore_state_root_mismatch,
+        fast_sync,
+        backup_every_n_blocks,
+        starting_block,
+        block_import_hook,
+        class_refetcher,
+        feeder_chain,
+    } = config;
+    // The stall of a previous run is cleared once the sync moves past it.
+    let mut stalled = backend.get_sync_stall()?.is_some();
+    block_metrics.l2_sync_stalled.set(if stalled { 1.0 } else { 0.0 });
+    // Every block must build on the one stored before it.
+    let mut tip_hash = backend
+        .get_block_info(&BlockId::Tag(BlockTag::Latest))
+        .context("Getting latest block in db")?
+        .and_then(|info| info.as_nonpending().map(|info| info.block_hash));
+    let fast_sync = fast_sync.filter(|_| verify);
+    if verify {
+        let backend = Arc::clone(&backend);
+        if let Some(tip) = spawn_compute(&compute_pool, move || {
+            if repair_interrupted_revert(&backend)? {
+                log::info!("🌳 Rebuilt the global tries after an interrupted revert");
+            }
+            commit_uncommitted_blocks(&backend)
+        })
+        .await?
+        {
+            log::info!("🌳 Committed the blocks up to {tip} to the global tries");
+        }
+    }
+    // The state diffs of the fast synced blocks not committed to the global tries yet.
+    let mut batch = StateDiffBatch::default();
+    backend.worker_health().register(WORKER_SYNC_APPLY, APPLY_IDLE_TOUCH_INTERVAL);
+    while let Some(L2ConvertedBlockAndUpdates {
+        mut converted_block,
+        converted_state_diff,
+        converted_classes,
+        in_flight: _in_flight,
+    }) = channel_wait_or_graceful_shutdown(pin!(next_update(&backend, &mut updates_receiver))).await
     {
         let block_n = converted_block.info.header.block_number;
         let block_hash = converted_block.info.block_hash;
         let global_state_root = converted_block.info.header.global_state_root;
 
-        let state_diff = if verify {
-            let state_diff = Arc::new(converted_state_diff);
-            let state_diff_1 = Arc::clone(&state_diff);
+        let parent_block_hash = converted_block.info.header.parent_block_hash;
+        if let Some(expected) = tip_hash.filter(|tip_hash| *tip_hash != parent_block_hash) {
+            let err = L2SyncError::ParentMismatch { block_n, parent_block_hash, expected };
+            let Some(feeder_chain) = feeder_chain.as_deref() else { return Err(err.into()) };
+            log::warn!("⚠️  {err}, looking for a fork of the chain");
+            return Err(handle_reorg(&backend, feeder_chain, &pending_task, verify, &block_metrics).await?.into());
+        }
+        // Before the state root computation, which commits the block to the global tries.
+        let Some(converted_classes) = complete_classes(
+            &backend,
+            class_refetcher.as_deref(),
+            CLASS_REFETCH_BACKOFF,
+            &block_metrics,
+            block_n,
+            &converted_state_diff,
+            converted_classes,
+        )
+        .await?
+        else {
+            return Ok(());
+        };
+
+        // The changes committed to the global tries with this block, from the first block they include: its own state
+        // diff, the ones of the whole batch when it ends a fast sync batch, or none in the middle of a batch.
+        let mut ends_batch = false;
+        let tries_update = match fast_sync {
+            Some(fast_sync) if fast_sync.applies_to(block_n) => {
+                if batch.first_block().is_none() {
+                    // Before the block is stored, for the batch to be committed again after a crash.
+                    backend.write_uncommitted_tries_from(block_n)?;
+                }
+                batch.push(block_n, &converted_state_diff);
+                ends_batch = fast_sync.ends_batch(block_n);
+                ends_batch.then(|| {
+                    let batch = std::mem::take(&mut batch);
+                    (batch.first_block().unwrap_or(block_n), batch.into_state_diff())
+                })
+            }
+            _ if verify => Some((block_n, converted_state_diff.clone())),
+            _ => None,
+        };
+
+        if let Some((tries_from, tries_update)) = tries_update {
             let backend = Arc::clone(&backend);
 
-            let state_root = spawn_rayon_task(move || {
+            let started = Instant::now();
+            let state_root = spawn_compute(&compute_pool, move || {
+                let _tries_update = backend.lock_tries_update();
                 let sw = PerfStopwatch::new();
-                let state_root = update_tries_and_compute_state_root(&backend, &state_diff, block_n);
+                let state_root = update_tries_and_compute_state_root(&backend, &tries_update, block_n);
                 stopwatch_end!(sw, "verify_l2: {:?}");
 
+                if state_root != global_state_root && !ignore_state_root_mismatch {
+                    // The block is not stored: the tries go back to the last block that is, for the sync to start
+                    // again from it.
+                    backend
+                        .revert_tries_to(tries_from.checked_sub(1), block_n)
+                        .context("Reverting the global tries after a state root mismatch")?;
+                }
                 anyhow::Ok(state_root)
             })
             .await?;
+            block_metrics.observe_import_stage(ImportStage::Verify, started.elapsed());
 
-            if global_state_root != state_root {
-                bail!(
-                    "Verified state root: {:#x} doesn't match fetched state root: {:#x}",
-                    state_root,
-                    global_state_root
-                );
+            match verify_state_root(&InstalledReporter, block_n, global_state_root, state_root) {
+                Ok(()) => converted_block.info.verification |= BlockVerification::VERIFIED_ROOT,
+                Err(err) if ignore_state_root_mismatch => log::warn!("⚠️  {err}, importing the block anyway"),
+                Err(err) => {
+                    backend.write_sync_stall(&SyncStall::MismatchedStateRoot {
+                        block_n,
+                        block_hash,
+                        expected: global_state_root,
+                        got: state_root,
+                    })?;
+                    block_metrics.l2_sync_stalled.set(1.0);
+                    return Err(err.into());
+                }
             }
-
-            // UNWRAP: we need a 'static future as we are spawning tokio tasks further down the line
-            //         this is a hack to achieve that, we put the update in an arc and then unwrap it at the end
-            //         this will not panic as the Arc should not be aliased.
-            Arc::try_unwrap(state_diff_1).unwrap()
-        } else {
-            converted_state_diff
-        };
+        }
+        let state_diff = converted_state_diff;
 
         let block_header = converted_block.info.header.clone();
-        let backend_ = Arc::clone(&backend);
-        spawn_rayon_task(move || {
-            backend_
-                .store_block(
-                    DeoxysMaybePendingBlock {
-                        info: DeoxysMaybePendingBlockInfo::NotPending(converted_block.info),
-                        inner: converted_block.inner,
-                    },
-                    state_diff,
-                    converted_classes,
-                )
-                .context("Storing new block")?;
+        block_metrics.l2_state_diff_size.set(state_diff.len() as f64);
+        let started = Instant::now();
+        // Shared with every attempt, the block is kept until it is stored.
+        let import = Arc::new((converted_block, state_diff, converted_classes));
+        let mut attempt = 0;
+        loop {
+            let backend_ = Arc::clone(&backend);
+            let block_import_hook = block_import_hook.clone();
+            let import = Arc::clone(&import);
+            let Err(err) = spawn_compute(&compute_pool, move || {
+                let (block, state_diff, converted_classes) = &*import;
+                import_block(&backend_, block_import_hook.as_deref(), block, state_diff, converted_classes)
+            })
+            .await
+            else {
+                break;
+            };
 
-            anyhow::Ok(())
-        })
-        .await?;
+            attempt += 1;
+            let Some(delay) = STORAGE_RETRY_POLICY.retry_delay(attempt, &err) else { return Err(err) };
+            log::warn!("⚠️  Failed to import block {block_n}: {err:#}, retrying in {delay:?}");
+            if wait_or_graceful_shutdown(tokio::time::sleep(delay)).await.is_none() {
+                return Ok(());
+            }
+        }
+        block_metrics.observe_import_stage(ImportStage::Commit, started.elapsed());
+        tip_hash = Some(block_hash);
+        if ends_batch {
+            backend.clear_uncommitted_tries_from()?;
+            log::debug!("Committed the fast synced blocks up to {block_n} to the global tries");
+        }
+
+        if stalled {
+            backend.clear_sync_stall()?;
+            block_metrics.l2_sync_stalled.set(0.0);
+            stalled = false;
+        }
 
         update_sync_metrics(
             block_n,
@@ -140,7 +529,10 @@ async fn l2_verify_and_apply_task(
             stopwatch_end!(sw, "flush db: {:?}");
         }
 
-        log::info!(
+        tracing::info!(
+            block_number = block_n,
+            block_hash = format!("{block_hash:#x}"),
+            state_root = format!("{global_state_root:#x}"),
             "✨ Imported #{} ({}) and updated state root ({})",
             block_n,
             trim_hash(&block_hash),
@@ -164,7 +556,9 @@ async fn l2_verify_and_apply_task(
             backend.backup().await.context("backing up database")?;
             log::info!("✅ Database backup is done ({:?})", sw.elapsed());
         }
+        backend.worker_health().touch(WORKER_SYNC_APPLY);
     }
+    backend.worker_health().unregister(WORKER_SYNC_APPLY);
 
     let sw = PerfStopwatch::new();
     if backend.maybe_flush(true)? {
@@ -174,38 +568,65 @@ async fn l2_verify_and_apply_task(
     Ok(())
 }
 
+/// The next block to store. The apply stage keeps touching its worker while it waits for the fetch, it is only stale
+/// when a block takes too long to be stored.
+async fn next_update(
+    backend: &DeoxysBackend,
+    updates_receiver: &mut mpsc::Receiver<L2ConvertedBlockAndUpdates>,
+) -> Option<L2ConvertedBlockAndUpdates> {
+    loop {
+        match tokio::time::timeout(APPLY_IDLE_TOUCH_INTERVAL, updates_receiver.recv()).await {
+            Ok(update) => return update,
+            Err(_elapsed) => backend.worker_health().touch(WORKER_SYNC_APPLY),
+        }
+    }
+}
+
 pub struct L2ConvertedBlockAndUpdates {
     pub converted_block: DeoxysBlock,
     pub converted_state_diff: StateDiff,
     pub converted_classes: Vec<ConvertedClass>,
+    /// Given back to the pipeline budget once the block is stored, `None` when the block is not counted by one.
+    pub in_flight: Option<InFlightBlock>,
 }
 
 async fn l2_block_conversion_task(
-    updates_receiver: mpsc::Receiver<L2BlockAndUpdates>,
+    updates_receiver: mpsc::Receiver<(L2BlockAndUpdates, InFlightBlock)>,
     output: mpsc::Sender<L2ConvertedBlockAndUpdates>,
     chain_id: Felt,
+    allow_unsupported_protocol_version: bool,
+    block_metrics: BlockMetrics,
+    compute_pool: ComputePool,
 ) -> anyhow::Result<()> {
+    let (block_metrics, compute_pool) = (&block_metrics, &compute_pool);
     // Items of this stream are futures that resolve to blocks, which becomes a regular stream of blocks
     // using futures buffered.
     let conversion_stream = stream::unfold((updates_receiver, chain_id), |(mut updates_recv, chain_id)| async move {
         channel_wait_or_graceful_shutdown(updates_recv.recv()).await.map(
-            |L2BlockAndUpdates { block, state_diff, class_update, .. }| {
+            |(L2BlockAndUpdates { block, state_diff, class_update, .. }, in_flight)| {
+                let block_metrics = block_metrics.clone();
                 (
-                    spawn_rayon_task(move || {
+                    spawn_compute(compute_pool, move || {
                         let sw = PerfStopwatch::new();
                         let block_n = block.block_number;
-                        let task_convert_block =
-                            || convert_and_verify_block(block, state_diff, chain_id).context("Converting block");
-                        let task_convert_classes =
-                            || convert_and_verify_class(class_update, block_n).context("Converting classes");
+                        let task_convert_block = || {
+                            convert_and_verify_block(block, state_diff, chain_id, allow_unsupported_protocol_version)
+                                .context("Converting block")
+                        };
+                        let task_convert_classes = || {
+                            convert_and_verify_class(class_update, block_n, Some(&block_metrics))
+                                .map_err(L2SyncError::from)
+                        };
                         let (converted_block_with_state_diff, converted_classes) =
                             rayon::join(task_convert_block, task_convert_classes);
                         stopwatch_end!(sw, "convert_block_and_class {:?}: {:?}", block_n);
+                        block_metrics.observe_import_stage(ImportStage::Convert, sw.elapsed());
                         let (converted_block, converted_state_diff) = converted_block_with_state_diff?;
                         anyhow::Ok(L2ConvertedBlockAndUpdates {
                             converted_block,
                             converted_state_diff,
                             converted_classes: converted_classes?,
+                            in_flight: Some(in_flight),
                         })
                     }),
                     (updates_recv, chain_id),
@@ -228,8 +649,14 @@ async fn l2_pending_block_task(
     backend: Arc<DeoxysBackend>,
     sync_finished_cb: oneshot::Receiver<()>,
     provider: Arc<SequencerGatewayProvider>,
+    policy: Arc<FetchPolicy>,
+    class_downloads: Arc<ClassDownloads>,
     chain_id: Felt,
     pending_block_poll_interval: Duration,
+    clock: Arc<dyn Clock>,
+    catch_up_notify: Arc<Notify>,
+    control: PendingTaskControl,
+    compute_pool: ComputePool,
 ) -> anyhow::Result<()> {
     // clear pending status
     {
@@ -245,48 +672,99 @@ async fn l2_pending_block_task(
 
     log::debug!("start pending block poll");
 
-    let mut interval = tokio::time::interval(pending_block_poll_interval);
-    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    while wait_or_graceful_shutdown(interval.tick()).await.is_some() {
+    // The classes fetched with the pending block stay in the downloads for the block that includes them, which releases
+    // them. The ones that leave the pending block may never be part of a block: they are released by the next poll.
+    let mut pending_classes = HashSet::new();
+    while wait_or_graceful_shutdown(clock.sleep(pending_block_poll_interval)).await.is_some() {
+        // The chain is being reverted.
+        let Some(_poll) = control.poll().await else { break };
         log::debug!("getting pending block...");
 
         let L2BlockAndUpdates { block_id: _, block, state_diff, class_update } =
-            fetch_block_and_updates(&backend, FetchBlockId::Pending, &provider)
+            fetch_block_and_updates(&backend, FetchBlockId::Pending, &provider, &policy, &class_downloads)
                 .await
                 .context("Getting pending block from sequencer")?;
+        let classes: HashSet<_> = class_update.iter().map(|update| update.class_hash).collect();
+        class_downloads.release_classes(pending_classes.difference(&classes).copied());
+        pending_classes = classes;
 
-        let block_hash_best = backend
-            .get_block_hash(&BlockId::Tag(BlockTag::Latest))
+        let tip = backend
+            .get_block_info(&BlockId::Tag(BlockTag::Latest))
             .context("Getting latest block in db")?
             .context("No block in db")?;
+        let tip = tip.as_nonpending().context("Latest block in db is pending")?;
+        let tip = (BlockN(tip.header.block_number), tip.block_hash);
+        let parent_hash = block.parent_block_hash;
 
-        log::debug!("pending block hash parent hash: {:#x}", block.parent_block_hash);
+        log::debug!("pending block hash parent hash: {:#x}", parent_hash);
 
-        if block.parent_block_hash == block_hash_best {
-            log::debug!("pending block parent block hash matches chain tip, writing pending block");
+        let (parent_in_db, parent_on_feeder) = if parent_hash == tip.1 {
+            (None, None)
+        } else {
+            let parent_in_db =
+                backend.get_block_n(&BlockId::Hash(parent_hash)).context("Getting pending block parent in db")?;
+            let parent_on_feeder = match parent_in_db {
+                Some(_) => None,
+                None => feeder_block_n(&provider, parent_hash).await?,
+            };
+            (parent_in_db, parent_on_feeder)
+        };
 
-            let backend_ = Arc::clone(&backend);
-            spawn_rayon_task(move || {
-                let (block, converted_state_diff) =
-                    crate::convert::convert_pending(block, state_diff, chain_id).context("Converting pending block")?;
-                let convert_classes = convert_and_verify_class(class_update, None).context("Converting classes")?;
-
-                backend_
-                    .store_block(
-                        DeoxysMaybePendingBlock {
-                            info: DeoxysMaybePendingBlockInfo::Pending(block.info),
-                            inner: block.inner,
-                        },
-                        converted_state_diff,
-                        convert_classes,
-                    )
-                    .context("Storing new block")?;
+        let decision = pending_parent_decision(tip, parent_hash, parent_in_db, parent_on_feeder);
+        match decision {
+            PendingParentDecision::Store => {
+                log::debug!("pending block parent block hash matches chain tip, writing pending block");
 
-                anyhow::Ok(())
-            })
-            .await?;
-        } else {
-            log::debug!("pending block parent hash does not match latest block, clearing pending block");
+                let backend_ = Arc::clone(&backend);
+                spawn_compute(&compute_pool, move || {
+                    let (block, converted_state_diff) = crate::convert::convert_pending(block, state_diff, chain_id)
+                        .context("Converting pending block")?;
+                    let convert_classes =
+                        convert_and_verify_class(class_update, None, None).context("Converting classes")?;
+
+                    backend_
+                        .store_block(
+                            DeoxysMaybePendingBlock {
+                                info: DeoxysMaybePendingBlockInfo::Pending(block.info),
+                                inner: block.inner,
+                            },
+                            converted_state_diff,
+                            convert_classes,
+                        )
+                        .context("Storing new block")?;
+
+                    anyhow::Ok(())
+                })
+                .await?;
+            }
+            PendingParentDecision::FeederLagging { parent_block_n } => {
+                log::debug!(
+                    "pending block builds on block #{parent_block_n} while our tip is #{}: the feeder is lagging \
+                     behind, keeping our pending block",
+                    tip.0
+                );
+            }
+            PendingParentDecision::CatchUp { parent_block_n } => {
+                log::info!(
+                    "⏩ The pending block builds on block #{parent_block_n} while our tip is #{}: clearing the pending \
+                     block and fetching the missing blocks",
+                    tip.0
+                );
+                backend.chain_head().update_highest_known_block(parent_block_n.0);
+                catch_up_notify.notify_one();
+            }
+            PendingParentDecision::InvestigateReorg => {
+                log::warn!(
+                    "The pending block builds on unknown block {:#x} while our tip is #{} ({:#x}): the chain may \
+                     have been reorganized upstream, clearing the pending block",
+                    parent_hash,
+                    tip.0,
+                    tip.1
+                );
+            }
+        }
+
+        if decision.clears_local_pending() {
             backend.clear_pending_block().context("Clearing pending block")?;
         }
     }
@@ -294,13 +772,51 @@ async fn l2_pending_block_task(
     Ok(())
 }
 
+/// Number of the block with this hash on the feeder, `None` if the feeder does not know it.
+async fn feeder_block_n(provider: &SequencerGatewayProvider, block_hash: Felt) -> anyhow::Result<Option<BlockN>> {
+    match provider.get_block_with_tx_hashes(starknet_core::types::BlockId::Hash(block_hash)).await {
+        Ok(MaybePendingBlockWithTxHashes::Block(block)) => Ok(Some(BlockN(block.block_number))),
+        Ok(MaybePendingBlockWithTxHashes::PendingBlock(_)) => Ok(None),
+        Err(ProviderError::StarknetError(StarknetError::BlockNotFound)) => Ok(None),
+        Err(err) => Err(err).context("Getting pending block parent from the feeder"),
+    }
+}
+
 pub struct L2SyncConfig {
-    pub first_block: u64,
+    /// The sync resumes from the block after the database tip. This can only override it when the database is empty.
+    pub first_block: Option<u64>,
     pub n_blocks_to_sync: Option<u64>,
+    /// Number of blocks fetched in parallel while catching up with the chain.
+    pub fetch_concurrency: usize,
+    pub fetch_policy: Arc<FetchPolicy>,
     pub verify: bool,
+    /// Import the blocks whose state root does not match instead of stopping the sync. Only used when `verify` is set.
+    pub ignore_state_root_mismatch: bool,
+    /// Commit the blocks below a threshold to the global tries in batches. Only used when `verify` is set.
+    pub fast_sync: Option<FastSyncConfig>,
+    /// Import the blocks of the Starknet versions newer than [`StarknetVersion::LATEST_SUPPORTED`] instead of stopping
+    /// the sync.
+    pub allow_unsupported_protocol_version: bool,
     pub sync_polling_interval: Option<Duration>,
     pub backup_every_n_blocks: Option<u64>,
     pub pending_block_poll_interval: Duration,
+    /// Paces the polls of the pending block.
+    pub clock: Arc<dyn Clock>,
+    pub block_import_hook: Option<Arc<dyn BlockImportHook>>,
+    pub pipeline: PipelineConfig,
+    /// Converts and hashes the blocks, and computes their state root.
+    pub compute_pool: ComputePool,
+}
+
+/// The block after the database tip. `first_block` can only be another block when the database is empty.
+fn resolve_first_block(backend: &DeoxysBackend, first_block: Option<u64>) -> Result<u64, L2SyncError> {
+    match (backend.get_latest_block_n()?, first_block) {
+        (Some(tip), Some(first_block)) if first_block != tip + 1 => {
+            Err(L2SyncError::FirstBlockConflict { first_block, tip })
+        }
+        (Some(tip), _) => Ok(tip + 1),
+        (None, first_block) => Ok(first_block.unwrap_or(0)),
+    }
 }
 
 /// Spawns workers to fetch blocks and state updates from the feeder.
@@ -311,61 +827,145 @@ pub async fn sync(
     config: L2SyncConfig,
     block_metrics: BlockMetrics,
     db_metrics: DbMetrics,
-    starting_block: u64,
     chain_id: Felt,
     telemetry: TelemetryHandle,
 ) -> anyhow::Result<()> {
-    let (fetch_stream_sender, fetch_stream_receiver) = mpsc::channel(8);
-    let (block_conv_sender, block_conv_receiver) = mpsc::channel(4);
     let provider = Arc::new(provider);
-    let sync_timer = Arc::new(Mutex::new(None));
-    let (once_caught_up_cb_sender, once_caught_up_cb_receiver) = oneshot::channel();
-
-    // [Fetch task] ==new blocks and updates=> [Block conversion task] ======> [Verification and apply
-    // task]
-    // - Fetch task does parallel fetching
-    // - Block conversion is compute heavy and parallel wrt. the next few blocks,
-    // - Verification is sequential and does a lot of compute when state root verification is enabled.
-    //   DB updates happen here too.
-
-    // we are using separate tasks so that fetches don't get clogged up if by any chance the verify task
-    // starves the tokio worker
-
-    let mut join_set = JoinSet::new();
-    join_set.spawn(l2_fetch_task(
-        Arc::clone(backend),
-        config.first_block,
-        config.n_blocks_to_sync,
-        fetch_stream_sender,
-        Arc::clone(&provider),
-        config.sync_polling_interval,
-        once_caught_up_cb_sender,
-    ));
-    join_set.spawn(l2_block_conversion_task(fetch_stream_receiver, block_conv_sender, chain_id));
-    join_set.spawn(l2_verify_and_apply_task(
-        Arc::clone(backend),
-        block_conv_receiver,
-        config.verify,
-        config.backup_every_n_blocks,
-        block_metrics,
-        db_metrics,
-        starting_block,
-        Arc::clone(&sync_timer),
-        telemetry,
-    ));
-    join_set.spawn(l2_pending_block_task(
-        Arc::clone(backend),
-        once_caught_up_cb_receiver,
-        provider,
-        chain_id,
-        config.pending_block_poll_interval,
-    ));
+    let feeder_chain: Arc<dyn FeederChain> = Arc::new(GatewayFeederChain::new(Arc::clone(&provider)));
+    let mut requested_first_block = config.first_block;
 
-    while let Some(res) = join_set.join_next().await {
-        res.context("task was dropped")??;
-    }
+    // The sync starts over from the new tip after a reorg is reverted.
+    loop {
+        let first_block = resolve_first_block(backend, requested_first_block.take())?;
+        log::info!("⛓️  Starting L2 sync from block {}", first_block);
 
-    Ok(())
+        // The pipeline budget bounds the blocks held by all the stages, the channels never wait before it.
+        let pipeline = PipelineBudget::new(config.pipeline);
+        pipeline.set_metrics(block_metrics.clone());
+        let channel_capacity = config.pipeline.max_blocks.max(1);
+        let (fetch_stream_sender, fetch_stream_receiver) = mpsc::channel(channel_capacity);
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(channel_capacity);
+        let sync_timer = Arc::new(Mutex::new(None));
+        let (once_caught_up_cb_sender, once_caught_up_cb_receiver) = oneshot::channel();
+        let catch_up_notify = Arc::new(Notify::new());
+        // The classes of the pending block are released when the block is fetched again once closed.
+        let class_downloads = Arc::new(ClassDownloads::new(&block_metrics));
+        let pending_task = PendingTaskControl::default();
+
+        // [Fetch task] ==new blocks and updates=> [Block conversion task] ======> [Verification and apply
+        // task]
+        // - Fetch task does parallel fetching
+        // - Block conversion is compute heavy and parallel wrt. the next few blocks,
+        // - Verification is sequential and does a lot of compute when state root verification is enabled.
+        //   DB updates happen here too.
+
+        // we are using separate tasks so that fetches don't get clogged up if by any chance the verify task
+        // starves the tokio worker
+
+        let mut join_set = JoinSet::new();
+        join_set.spawn(l2_fetch_task(
+            Arc::clone(backend),
+            first_block,
+            config.n_blocks_to_sync,
+            config.fetch_concurrency,
+            fetch_stream_sender,
+            Arc::clone(&provider),
+            Arc::clone(&config.fetch_policy),
+            Arc::clone(&class_downloads),
+            config.sync_polling_interval,
+            once_caught_up_cb_sender,
+            Arc::clone(&catch_up_notify),
+            block_metrics.clone(),
+            pipeline,
+        ));
+        join_set.spawn(l2_block_conversion_task(
+            fetch_stream_receiver,
+            block_conv_sender,
+            chain_id,
+            config.allow_unsupported_protocol_version,
+            block_metrics.clone(),
+            config.compute_pool.clone(),
+        ));
+        join_set.spawn(l2_verify_and_apply_task(
+            Arc::clone(backend),
+            block_conv_receiver,
+            ApplyConfig {
+                verify: config.verify,
+                ignore_state_root_mismatch: config.ignore_state_root_mismatch,
+                fast_sync: config.fast_sync,
+                backup_every_n_blocks: config.backup_every_n_blocks,
+                starting_block: first_block,
+                block_import_hook: config.block_import_hook.clone(),
+                class_refetcher: Some(Arc::new(GatewayClassRefetcher::new(
+                    Arc::clone(&provider),
+                    Arc::clone(&config.fetch_policy),
+                    config.compute_pool.clone(),
+                ))),
+                feeder_chain: Some(Arc::clone(&feeder_chain)),
+            },
+            block_metrics.clone(),
+            db_metrics.clone(),
+            Arc::clone(&sync_timer),
+            telemetry.clone(),
+            pending_task.clone(),
+            config.compute_pool.clone(),
+        ));
+        join_set.spawn(l2_pending_block_task(
+            Arc::clone(backend),
+            once_caught_up_cb_receiver,
+            Arc::clone(&provider),
+            Arc::clone(&config.fetch_policy),
+            class_downloads,
+            chain_id,
+            config.pending_block_poll_interval,
+            Arc::clone(&config.clock),
+            catch_up_notify,
+            pending_task,
+            config.compute_pool.clone(),
+        ));
+        join_set.spawn(delayed_verification_task(
+            Arc::clone(backend),
+            block_metrics.clone(),
+            config.compute_pool.clone(),
+        ));
+
+        let mut reverted = false;
+        while let Some(res) = join_set.join_next().await {
+            match res.context("task was dropped")? {
+                // The node keeps serving the blocks it has, and reports the stall.
+                Err(err) if matches!(err.downcast_ref(), Some(L2SyncError::MismatchedStateRoot { .. })) => {
+                    log::error!(
+                        "🛑 L2 sync stopped: {err}. Restart the node with `--unsafe-ignore-state-root-mismatch` to \
+                         import this block anyway"
+                    );
+                    return Ok(());
+                }
+                Err(err) if matches!(err.downcast_ref(), Some(L2SyncError::UnsupportedProtocolVersion(..))) => {
+                    log::error!(
+                        "🛑 L2 sync stopped: {err}. Update the node, or restart it with \
+                         `--unsafe-allow-unsupported-protocol-version` to import the blocks anyway"
+                    );
+                    return Ok(());
+                }
+                Err(err) if matches!(err.downcast_ref(), Some(L2SyncError::DeepReorg { .. })) => {
+                    log::error!(
+                        "🛑 L2 sync stopped: {err}. Stop the node and revert the chain with `deoxys db force-reorg \
+                         --to-block <BLOCK>` to sync the chain of the feeder"
+                    );
+                    return Ok(());
+                }
+                Err(err) if matches!(err.downcast_ref(), Some(L2SyncError::Reverted { .. })) => {
+                    log::warn!("↩️  {err}");
+                    join_set.shutdown().await;
+                    reverted = true;
+                }
+                res => res?,
+            }
+        }
+        if !reverted {
+            return Ok(());
+        }
+    }
 }
 
 async fn update_sync_metrics(
@@ -378,28 +978,31 @@ async fn update_sync_metrics(
     backend: &DeoxysBackend,
 ) -> anyhow::Result<()> {
     // Update Block sync time metrics
+    // Time since the previous block was imported, there is none for the first block.
     let elapsed_time = {
         let mut timer_guard = sync_timer.lock().unwrap();
-        *timer_guard = Some(Instant::now());
-        if let Some(start_time) = *timer_guard {
-            start_time.elapsed().as_secs_f64()
-        } else {
-            // For the first block, there is no previous timer set
-            0.0
-        }
+        let previous = timer_guard.replace(Instant::now());
+        previous.map_or(0.0, |previous| previous.elapsed().as_secs_f64())
     };
 
     let sync_time = block_metrics.l2_sync_time.get() + elapsed_time;
     block_metrics.l2_sync_time.set(sync_time);
     block_metrics.l2_latest_sync_time.set(elapsed_time);
-    block_metrics.l2_avg_sync_time.set(block_metrics.l2_sync_time.get() / (block_number - starting_block) as f64);
+    block_metrics.l2_avg_sync_time.set(sync_time / (block_number - starting_block + 1) as f64);
 
     block_metrics.l2_block_number.set(block_header.block_number as f64);
+    if let Some(highest_known_block) = backend.chain_head().get().highest_known {
+        block_metrics.l2_sync_lag.set(highest_known_block.saturating_sub(block_number) as f64);
+    }
     block_metrics.transaction_count.set(f64::from_u64(block_header.transaction_count).unwrap_or(0f64));
     block_metrics.event_count.set(f64::from_u64(block_header.event_count).unwrap_or(0f64));
 
-    block_metrics.l1_gas_price_wei.set(f64::from_u128(block_header.l1_gas_price.eth_l1_gas_price).unwrap_or(0f64));
-    block_metrics.l1_gas_price_strk.set(f64::from_u128(block_header.l1_gas_price.strk_l1_gas_price).unwrap_or(0f64));
+    block_metrics
+        .l2_block_eth_gas_price
+        .set(f64::from_u128(block_header.l1_gas_price.eth_l1_gas_price).unwrap_or(0f64));
+    block_metrics
+        .l2_block_strk_gas_price
+        .set(f64::from_u128(block_header.l1_gas_price.strk_l1_gas_price).unwrap_or(0f64));
 
     if block_number % 200 == 0 {
         let storage_size = backend.get_storage_size(db_metrics);
@@ -409,3 +1012,926 @@ async fn update_sync_metrics(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use dc_db::storage_updates::DbClassUpdate;
+    use dc_db::testing::temp_database;
+    use dc_db::DatabaseService;
+    use dc_metrics::MetricsService;
+    use dc_telemetry::TelemetryService;
+    use dp_block::chain_config::ChainConfig;
+    use dp_block::header::PendingHeader;
+    use dp_block::{DeoxysBlockInner, DeoxysPendingBlockInfo};
+    use dp_class::{ClassInfo, CompiledClass, ContractClass, EntryPointsByType, FlattenedSierraClass};
+    use dp_state_update::{
+        ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StorageEntry,
+    };
+    use dp_utils::error_reporting::ErrorReport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::reorgs::{force_reorg, repair_tries_after_revert};
+    use futures::FutureExt;
+
+    #[derive(Default)]
+    struct MockReporter(Mutex<Vec<ErrorReport>>);
+
+    impl ErrorReporter for MockReporter {
+        fn report(&self, report: &ErrorReport) {
+            self.0.lock().unwrap().push(report.clone());
+        }
+    }
+
+    #[test]
+    fn test_state_root_mismatch_is_reported() {
+        let reporter = MockReporter::default();
+
+        verify_state_root(&reporter, 12, Felt::ONE, Felt::ONE).unwrap();
+        assert!(reporter.0.lock().unwrap().is_empty());
+
+        assert!(matches!(
+            verify_state_root(&reporter, 12, Felt::ONE, Felt::TWO),
+            Err(L2SyncError::MismatchedStateRoot { block: 12, expected, got }) if (expected, got) == (Felt::ONE, Felt::TWO)
+        ));
+        let reports = reporter.0.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!((reports[0].severity, reports[0].source), (Severity::Critical, "sync.verify"));
+        assert_eq!(reports[0].message, "Verified state root: 0x2 doesn't match fetched state root: 0x1 for block 12");
+    }
+
+    /// An empty block, whose state root is the one of an empty state. The hash of block `n` is `n`.
+    fn empty_block(block_number: u64) -> L2ConvertedBlockAndUpdates {
+        let parent_block_hash = block_number.checked_sub(1).map(Felt::from).unwrap_or_default();
+        let header = Header { block_number, parent_block_hash, ..Default::default() };
+        L2ConvertedBlockAndUpdates {
+            converted_block: DeoxysBlock::new(
+                DeoxysBlockInfo::new(header, vec![], Felt::from(block_number)),
+                DeoxysBlockInner::new(vec![], vec![]),
+            ),
+            converted_state_diff: StateDiff::default(),
+            converted_classes: vec![],
+            in_flight: None,
+        }
+    }
+
+    fn storage_write(address: Felt, key: Felt, value: Felt) -> StateDiff {
+        StateDiff::new(
+            vec![ContractStorageDiffItem { address, storage_entries: vec![StorageEntry { key, value }] }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn declaration(class_hash: Felt) -> StateDiff {
+        StateDiff::new(
+            vec![],
+            vec![],
+            vec![DeclaredClassItem { class_hash, compiled_class_hash: Felt::ONE }],
+            vec![],
+            vec![],
+            vec![],
+        )
+    }
+
+    /// Blocks 0 to 3, the state diff of block 2 has a write that its header does not commit to.
+    fn blocks_with_corrupted_state_diff() -> Vec<L2ConvertedBlockAndUpdates> {
+        let mut blocks: Vec<_> = (0..4).map(empty_block).collect();
+        blocks[2].converted_state_diff = storage_write(Felt::ONE, Felt::ONE, Felt::ONE);
+        blocks
+    }
+
+    async fn verify_and_apply(
+        backend: &Arc<DeoxysBackend>,
+        blocks: Vec<L2ConvertedBlockAndUpdates>,
+        ignore_state_root_mismatch: bool,
+    ) -> anyhow::Result<()> {
+        verify_and_apply_with(
+            backend,
+            blocks,
+            ApplyConfig { verify: true, ignore_state_root_mismatch, ..Default::default() },
+        )
+        .await
+    }
+
+    async fn verify_and_apply_with(
+        backend: &Arc<DeoxysBackend>,
+        blocks: Vec<L2ConvertedBlockAndUpdates>,
+        config: ApplyConfig,
+    ) -> anyhow::Result<()> {
+        let (sender, receiver) = mpsc::channel(blocks.len());
+        for block in blocks {
+            sender.send(block).await.unwrap();
+        }
+        drop(sender);
+
+        let registry = MetricsService::new(true, false, 9615).unwrap().registry();
+        l2_verify_and_apply_task(
+            Arc::clone(backend),
+            receiver,
+            config,
+            BlockMetrics::register(&registry).unwrap(),
+            DbMetrics::register(&registry).unwrap(),
+            Arc::new(Mutex::new(None)),
+            TelemetryService::new(true, vec![]).unwrap().new_handle(),
+            PendingTaskControl::default(),
+            ComputePool::new("sync", 2).unwrap(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_sync_stops_at_the_corrupted_state_diff() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        let err = verify_and_apply(backend, blocks_with_corrupted_state_diff(), false).await.unwrap_err();
+        let Some(&L2SyncError::MismatchedStateRoot { block: 2, expected, got }) = err.downcast_ref() else {
+            panic!("Unexpected error: {err:#}")
+        };
+        assert_eq!(expected, Felt::ZERO);
+        assert_ne!(got, Felt::ZERO);
+
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+        assert_eq!(
+            backend.get_sync_stall().unwrap(),
+            Some(SyncStall::MismatchedStateRoot { block_n: 2, block_hash: Felt::TWO, expected, got })
+        );
+
+        // The changes of block 2 were taken out of the tries: the sync resumes once the feeder returns the right block.
+        verify_and_apply(backend, (2..4).map(empty_block).collect(), false).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(3));
+        assert_eq!(backend.get_sync_stall().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sync_ignores_the_corrupted_state_diff() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        verify_and_apply(backend, blocks_with_corrupted_state_diff(), true).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(3));
+        assert_eq!(backend.get_sync_stall().unwrap(), None);
+
+        // The block is served, without claiming that its state root was verified.
+        let verification = |block_n| backend.get_block_verification(&BlockId::Number(block_n)).unwrap().unwrap();
+        assert!(verification(1).contains(BlockVerification::VERIFIED_ROOT));
+        assert!(!verification(2).contains(BlockVerification::VERIFIED_ROOT));
+    }
+
+    #[tokio::test]
+    async fn test_block_imports_are_measured() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        backend.chain_head().update_highest_known_block(10);
+
+        let (sender, receiver) = mpsc::channel(3);
+        for block in blocks_with_corrupted_state_diff().into_iter().take(3) {
+            sender.send(block).await.unwrap();
+        }
+        drop(sender);
+        let registry = MetricsService::new(false, false, 0).unwrap().registry();
+        let block_metrics = BlockMetrics::register(&registry).unwrap();
+        l2_verify_and_apply_task(
+            Arc::clone(backend),
+            receiver,
+            ApplyConfig { verify: true, ignore_state_root_mismatch: true, ..Default::default() },
+            block_metrics.clone(),
+            DbMetrics::register(&registry).unwrap(),
+            Arc::new(Mutex::new(None)),
+            TelemetryService::new(true, vec![]).unwrap().new_handle(),
+            PendingTaskControl::default(),
+            ComputePool::new("sync", 2).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let families = registry.gather();
+        let family = |name: &str| {
+            families.iter().find(|family| family.get_name() == name).unwrap_or_else(|| panic!("{name} not registered"))
+        };
+        for name in ["deoxys_transaction_count", "deoxys_event_count", "deoxys_l2_block_eth_gas_price"] {
+            family(name);
+        }
+        assert_eq!(family("deoxys_l2_block_number").get_metric()[0].get_gauge().get_value(), 2.0);
+        assert_eq!(family("deoxys_l2_sync_lag").get_metric()[0].get_gauge().get_value(), 8.0);
+        assert_eq!(family("deoxys_l2_state_diff_size").get_metric()[0].get_gauge().get_value(), 1.0);
+
+        // The blocks were converted and fetched by the tests, only the verification and the commit are timed here.
+        let import_time = family("deoxys_l2_block_import_time");
+        let samples = |stage: &str| {
+            import_time
+                .get_metric()
+                .iter()
+                .find(|metric| metric.get_label()[0].get_value() == stage)
+                .map_or(0, |metric| metric.get_histogram().get_sample_count())
+        };
+        assert_eq!((samples("fetch"), samples("convert"), samples("verify"), samples("commit")), (0, 0, 3, 3));
+    }
+
+    #[tokio::test]
+    async fn test_sync_resumes_from_the_database_tip() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        assert_eq!(resolve_first_block(backend, None).unwrap(), 0);
+        assert_eq!(resolve_first_block(backend, Some(5)).unwrap(), 5);
+
+        verify_and_apply(backend, (0..3).map(empty_block).collect(), false).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(2));
+
+        // Restart.
+        assert_eq!(resolve_first_block(backend, None).unwrap(), 3);
+        assert_eq!(resolve_first_block(backend, Some(3)).unwrap(), 3);
+        for first_block in [0, 2, 7] {
+            let res = resolve_first_block(backend, Some(first_block));
+            assert!(
+                matches!(res, Err(L2SyncError::FirstBlockConflict { first_block: got, tip: 2 }) if got == first_block)
+            );
+        }
+        verify_and_apply(backend, (3..5).map(empty_block).collect(), false).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_sync_rejects_a_forged_parent_hash() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        verify_and_apply(backend, (0..3).map(empty_block).collect(), false).await.unwrap();
+
+        let mut forged = empty_block(3);
+        forged.converted_block.info.header.parent_block_hash = Felt::from(99);
+        let err = verify_and_apply(backend, vec![forged, empty_block(4)], false).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(&L2SyncError::ParentMismatch { block_n: 3, parent_block_hash, expected })
+                if (parent_block_hash, expected) == (Felt::from(99), Felt::TWO)
+        ));
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(2));
+    }
+
+    fn transient_storage_error() -> DeoxysStorageError {
+        DeoxysStorageError::Bincode(Box::new(bincode::ErrorKind::Io(std::io::ErrorKind::TimedOut.into())))
+    }
+
+    #[test]
+    fn test_storage_retry_policy() {
+        let policy = StorageRetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        let transient = anyhow::Error::from(transient_storage_error()).context("Storing new block");
+        let delays: Vec<_> = (1..=4).map(|attempt| policy.retry_delay(attempt, &transient)).collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                None
+            ]
+        );
+        let policy = StorageRetryPolicy { max_retries: 10, ..policy };
+        assert_eq!(policy.retry_delay(10, &transient), Some(Duration::from_secs(1)));
+
+        // Through the error of the sync.
+        let transient = anyhow::Error::from(L2SyncError::Db(transient_storage_error()));
+        assert_eq!(policy.retry_delay(1, &transient), Some(Duration::from_millis(100)));
+
+        for permanent in [
+            anyhow::Error::from(DeoxysStorageError::InconsistentStorage("Missing block".into())),
+            anyhow::Error::from(DeoxysStorageError::Bincode(Box::new(bincode::ErrorKind::SizeLimit))),
+            anyhow::anyhow!("Not a storage error"),
+        ] {
+            assert_eq!(policy.retry_delay(1, &permanent), None);
+        }
+    }
+
+    /// Fails the first `failures` imports with a transient database error.
+    struct FlakyImportHook {
+        failures: AtomicUsize,
+        imported: Mutex<Vec<u64>>,
+    }
+
+    impl BlockImportHook for FlakyImportHook {
+        fn on_block_imported(&self, block_info: &DeoxysBlockInfo, _state_diff: &StateDiff) -> anyhow::Result<()> {
+            if self.failures.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+                return Err(transient_storage_error().into());
+            }
+            self.imported.lock().unwrap().push(block_info.header.block_number);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_recovers_from_a_transient_storage_error() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        let hook = Arc::new(FlakyImportHook { failures: 2.into(), imported: Mutex::default() });
+        let block_import_hook = Some(Arc::clone(&hook) as Arc<dyn BlockImportHook>);
+        let blocks = (0..3).map(empty_block).collect();
+        verify_and_apply_with(backend, blocks, ApplyConfig { verify: true, block_import_hook, ..Default::default() })
+            .await
+            .unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(2));
+        assert_eq!(*hook.imported.lock().unwrap(), [0, 1, 2]);
+        assert_eq!(hook.failures.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_stops_on_a_permanent_storage_error() {
+        struct FailingImportHook;
+        impl BlockImportHook for FailingImportHook {
+            fn on_block_imported(&self, _block_info: &DeoxysBlockInfo, _state_diff: &StateDiff) -> anyhow::Result<()> {
+                Err(DeoxysStorageError::InconsistentStorage("Missing block".into()).into())
+            }
+        }
+
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        let block_import_hook = Some(Arc::new(FailingImportHook) as Arc<dyn BlockImportHook>);
+        let blocks = (0..3).map(empty_block).collect();
+        let err = verify_and_apply_with(
+            backend,
+            blocks,
+            ApplyConfig { verify: true, block_import_hook, ..Default::default() },
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(&DeoxysStorageError::InconsistentStorage(_))));
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+    }
+
+    /// A pending block on top of `parent_block_n`, as stored by the pending block task after a poll.
+    fn store_pending(backend: &DeoxysBackend, parent_block_n: u64, tx_hash: Felt, storage: (Felt, Felt)) {
+        let header = PendingHeader { parent_block_hash: Felt::from(parent_block_n), ..Default::default() };
+        let block = DeoxysMaybePendingBlock {
+            info: DeoxysMaybePendingBlockInfo::Pending(DeoxysPendingBlockInfo::new(header, vec![tx_hash])),
+            inner: DeoxysBlockInner::new(vec![], vec![]),
+        };
+        let state_diff = StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![StorageEntry { key: storage.0, value: storage.1 }],
+            }],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        );
+        backend.store_block(block, state_diff, vec![]).unwrap();
+    }
+
+    fn pending_tx_hashes(backend: &DeoxysBackend) -> Option<Vec<Felt>> {
+        let block = backend.get_block(&BlockId::Tag(BlockTag::Pending)).unwrap()?;
+        Some(block.info.tx_hashes().to_vec())
+    }
+
+    fn pending_storage(backend: &DeoxysBackend, key: Felt) -> Option<Felt> {
+        backend.get_contract_storage_at(&BlockId::Tag(BlockTag::Pending), &Felt::ONE, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pending_block_follows_block_imports() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        verify_and_apply(backend, (0..2).map(empty_block).collect(), false).await.unwrap();
+
+        store_pending(backend, 1, Felt::from(0x100), (Felt::ONE, Felt::ONE));
+        assert_eq!(pending_tx_hashes(backend), Some(vec![Felt::from(0x100)]));
+        assert_eq!(pending_storage(backend, Felt::ONE), Some(Felt::ONE));
+
+        // The next poll replaces the pending block and its state diff.
+        store_pending(backend, 1, Felt::from(0x101), (Felt::TWO, Felt::TWO));
+        assert_eq!(pending_tx_hashes(backend), Some(vec![Felt::from(0x101)]));
+        assert_eq!(pending_storage(backend, Felt::ONE), None);
+        assert_eq!(pending_storage(backend, Felt::TWO), Some(Felt::TWO));
+
+        // Block 2 supersedes the pending block.
+        verify_and_apply(backend, vec![empty_block(2)], false).await.unwrap();
+        assert_eq!(pending_tx_hashes(backend), None);
+        assert_eq!(pending_storage(backend, Felt::TWO), None);
+
+        store_pending(backend, 2, Felt::from(0x200), (Felt::ONE, Felt::THREE));
+        assert_eq!(pending_tx_hashes(backend), Some(vec![Felt::from(0x200)]));
+        assert_eq!(pending_storage(backend, Felt::ONE), Some(Felt::THREE));
+    }
+
+    fn converted_class(class_hash: Felt, block_n: u64) -> ConvertedClass {
+        let contract_class = ContractClass::Sierra(FlattenedSierraClass {
+            sierra_program: vec![],
+            contract_class_version: "0.1.0".into(),
+            entry_points_by_type: EntryPointsByType { constructor: vec![], external: vec![], l1_handler: vec![] },
+            abi: String::new(),
+        });
+        ConvertedClass {
+            class_infos: (
+                class_hash,
+                ClassInfo { contract_class, compiled_class_hash: Felt::ONE, block_number: Some(block_n) },
+            ),
+            class_compiled: (Felt::ONE, serde_json::from_str::<CompiledClass>(r#"{"Sierra":[]}"#).unwrap()),
+        }
+    }
+
+    /// Block 2 declares class 0x123, but the class did not come with it.
+    fn blocks_with_a_missing_class() -> Vec<L2ConvertedBlockAndUpdates> {
+        let mut blocks: Vec<_> = (0..3).map(empty_block).collect();
+        blocks[2].converted_state_diff = declaration(Felt::from(0x123));
+        blocks
+    }
+
+    /// Fails `failures` times, then returns the requested classes.
+    struct FlakyRefetcher {
+        failures: usize,
+        calls: AtomicUsize,
+    }
+
+    impl ClassRefetcher for FlakyRefetcher {
+        fn refetch(
+            &self,
+            block_n: u64,
+            classes: Vec<(Felt, Felt)>,
+        ) -> BoxFuture<'_, anyhow::Result<Vec<ConvertedClass>>> {
+            Box::pin(async move {
+                if self.calls.fetch_add(1, Ordering::Relaxed) < self.failures {
+                    anyhow::bail!("Gateway timeout")
+                }
+                Ok(classes.into_iter().map(|(class_hash, _)| converted_class(class_hash, block_n)).collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_class_is_fetched_again() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let refetcher = Arc::new(FlakyRefetcher { failures: 1, calls: AtomicUsize::new(0) });
+
+        // Declaring a class changes the state root.
+        let class_refetcher = Some(Arc::clone(&refetcher) as Arc<dyn ClassRefetcher>);
+        verify_and_apply_with(
+            backend,
+            blocks_with_a_missing_class(),
+            ApplyConfig { verify: true, ignore_state_root_mismatch: true, class_refetcher, ..Default::default() },
+        )
+        .await
+        .unwrap();
+        assert_eq!(refetcher.calls.load(Ordering::Relaxed), 2);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(2));
+        let class_info = backend.get_class_info(&BlockId::Tag(BlockTag::Latest), &Felt::from(0x123)).unwrap();
+        assert_eq!(class_info.unwrap().block_number, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_missing_class_is_fetched_again_until_it_is_served() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        verify_and_apply(backend, (0..2).map(empty_block).collect(), false).await.unwrap();
+        let refetcher = FlakyRefetcher { failures: 10, calls: AtomicUsize::new(0) };
+        let backoff = RefetchBackoff { base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(4) };
+        let block_metrics = BlockMetrics::register(&MetricsService::new(false, false, 0).unwrap().registry()).unwrap();
+
+        let classes = complete_classes(
+            backend,
+            Some(&refetcher),
+            backoff,
+            &block_metrics,
+            2,
+            &declaration(Felt::from(0x123)),
+            vec![],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(refetcher.calls.load(Ordering::Relaxed), 11);
+        assert_eq!(classes.len(), 1);
+        assert_eq!(classes[0].class_infos.0, Felt::from(0x123));
+    }
+
+    #[test]
+    fn test_class_refetch_backoff() {
+        let backoff = RefetchBackoff { base_delay: Duration::from_secs(1), max_delay: Duration::from_secs(5) };
+        let delays: Vec<_> = (0..5).map(|attempt| backoff.delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [0, 1, 2, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_block_with_a_missing_class_is_not_stored() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+
+        // Without a way to fetch the class again.
+        let err = verify_and_apply(backend, blocks_with_a_missing_class(), true).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(&L2SyncError::MissingClass { block_n: 2, class_hash }) if class_hash == Felt::from(0x123)
+        ));
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+        assert!(!backend.contains_class(&BlockId::Tag(BlockTag::Latest), &Felt::from(0x123)).unwrap());
+    }
+
+    /// Fetches a class whose Sierra program is garbage, the compiler rejects it.
+    struct CorruptedClassRefetcher {
+        calls: AtomicUsize,
+    }
+
+    impl ClassRefetcher for CorruptedClassRefetcher {
+        fn refetch(
+            &self,
+            block_n: u64,
+            classes: Vec<(Felt, Felt)>,
+        ) -> BoxFuture<'_, anyhow::Result<Vec<ConvertedClass>>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let class_updates = classes
+                .into_iter()
+                .map(|(class_hash, compiled_class_hash)| DbClassUpdate {
+                    class_hash,
+                    contract_class: starknet_core::types::ContractClass::Sierra(
+                        starknet_core::types::FlattenedSierraClass {
+                            sierra_program: vec![Felt::ONE, Felt::TWO, Felt::THREE],
+                            contract_class_version: "0.1.0".into(),
+                            entry_points_by_type: starknet_core::types::EntryPointsByType {
+                                constructor: vec![],
+                                external: vec![],
+                                l1_handler: vec![],
+                            },
+                            abi: String::new(),
+                        },
+                    ),
+                    compiled_class_hash,
+                })
+                .collect();
+            Box::pin(async move {
+                Ok(spawn_rayon_task(move || convert_and_verify_class(class_updates, Some(block_n), None)).await?)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_class_that_does_not_compile_fails_the_import() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let refetcher = Arc::new(CorruptedClassRefetcher { calls: AtomicUsize::new(0) });
+
+        let class_refetcher = Some(Arc::clone(&refetcher) as Arc<dyn ClassRefetcher>);
+        let err = verify_and_apply_with(
+            backend,
+            blocks_with_a_missing_class(),
+            ApplyConfig { verify: true, ignore_state_root_mismatch: true, class_refetcher, ..Default::default() },
+        )
+        .await
+        .unwrap_err();
+        let Some(L2SyncError::ClassConversion(ConvertClassError::Compilation(err))) = err.downcast_ref() else {
+            panic!("Unexpected error: {err:#}")
+        };
+        assert_eq!((err.class_hash, err.block_n), (Felt::from(0x123), Some(2)));
+        assert!(err.to_string().starts_with("Failed to compile class 0x123 declared in block 2: "), "{err}");
+        // The compilation is not retried.
+        assert_eq!(refetcher.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+        assert!(!backend.contains_class(&BlockId::Tag(BlockTag::Latest), &Felt::from(0x123)).unwrap());
+    }
+
+    /// The feeder's chain forks from ours after block `fork_after`: the hash of its block `n` is `0x100 + n` above.
+    struct ForkedFeeder {
+        fork_after: u64,
+    }
+
+    impl FeederChain for ForkedFeeder {
+        fn block_hash(&self, block_n: u64) -> BoxFuture<'_, anyhow::Result<Option<Felt>>> {
+            let hash = if block_n <= self.fork_after { block_n } else { 0x100 + block_n };
+            Box::pin(async move { Ok(Some(Felt::from(hash))) })
+        }
+    }
+
+    /// Block `block_n` of the chain of [`ForkedFeeder`].
+    fn forked_block(block_n: u64, fork_after: u64) -> L2ConvertedBlockAndUpdates {
+        let mut block = empty_block(block_n);
+        block.converted_block.info.block_hash = Felt::from(0x100 + block_n);
+        if block_n - 1 > fork_after {
+            block.converted_block.info.header.parent_block_hash = Felt::from(0x100 + block_n - 1);
+        }
+        block
+    }
+
+    /// Blocks 0 to 4, block 3 writes to the storage and block 4 declares class 0x123. Their state roots are not the
+    /// ones of their state diffs: they are imported ignoring the mismatch.
+    fn blocks_before_the_fork() -> Vec<L2ConvertedBlockAndUpdates> {
+        let mut blocks: Vec<_> = (0..5).map(empty_block).collect();
+        blocks[3].converted_state_diff = storage_write(Felt::ONE, Felt::ONE, Felt::ONE);
+        blocks[4].converted_state_diff = declaration(Felt::from(0x123));
+        blocks[4].converted_classes = vec![converted_class(Felt::from(0x123), 4)];
+        blocks
+    }
+
+    fn assert_reverted_to_block_2(backend: &DeoxysBackend) {
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(2));
+        assert_eq!(backend.get_block_hash(&BlockId::Number(3)).unwrap(), None);
+        assert_eq!(backend.get_block_n(&BlockId::Hash(Felt::from(4))).unwrap(), None);
+        let storage = backend.get_contract_storage_at(&BlockId::Tag(BlockTag::Latest), &Felt::ONE, &Felt::ONE);
+        assert_eq!(storage.unwrap(), None);
+        assert!(!backend.contains_class(&BlockId::Tag(BlockTag::Latest), &Felt::from(0x123)).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_shallow_reorg_is_reverted() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        verify_and_apply(backend, blocks_before_the_fork(), true).await.unwrap();
+        let feeder = Some(Arc::new(ForkedFeeder { fork_after: 2 }) as Arc<dyn FeederChain>);
+
+        let err = verify_and_apply_with(
+            backend,
+            vec![forked_block(5, 2)],
+            ApplyConfig { verify: true, feeder_chain: feeder.clone(), ..Default::default() },
+        )
+        .await;
+        assert!(matches!(err.unwrap_err().downcast_ref(), Some(&L2SyncError::Reverted { tip: 4, common_ancestor: 2 })));
+        assert_reverted_to_block_2(backend);
+        assert_eq!(backend.get_sync_stall().unwrap(), None);
+        assert_eq!(backend.get_interrupted_tries_revert().unwrap(), None);
+
+        // The tries were reverted too: the state roots of the empty blocks of the feeder match.
+        let blocks = (3..6).map(|block_n| forked_block(block_n, 2)).collect();
+        verify_and_apply_with(
+            backend,
+            blocks,
+            ApplyConfig { verify: true, feeder_chain: feeder, ..Default::default() },
+        )
+        .await
+        .unwrap();
+        assert_eq!(backend.get_block_hash(&BlockId::Tag(BlockTag::Latest)).unwrap(), Some(Felt::from(0x105)));
+    }
+
+    #[tokio::test]
+    async fn test_deep_reorg_halts_the_sync() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let chain_config = ChainConfig { max_auto_reorg_depth: 1, ..ChainConfig::test_config() };
+        let db = DatabaseService::new(temp_dir.path(), None, false, Arc::new(chain_config)).await.unwrap();
+        let backend = db.backend();
+        verify_and_apply(backend, blocks_before_the_fork(), true).await.unwrap();
+        let feeder = Some(Arc::new(ForkedFeeder { fork_after: 2 }) as Arc<dyn FeederChain>);
+
+        let err = verify_and_apply_with(
+            backend,
+            vec![forked_block(5, 2)],
+            ApplyConfig { verify: true, feeder_chain: feeder.clone(), ..Default::default() },
+        )
+        .await;
+        assert!(matches!(
+            err.unwrap_err().downcast_ref(),
+            Some(&L2SyncError::DeepReorg { tip: 4, common_ancestor: Some(2), max_auto_reorg_depth: 1 })
+        ));
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(4));
+        assert_eq!(
+            backend.get_sync_stall().unwrap(),
+            Some(SyncStall::DeepReorg { tip_block_n: 4, common_ancestor: Some(2) })
+        );
+
+        // The node operator confirms the revert.
+        let backend_ = Arc::clone(backend);
+        spawn_rayon_task(move || force_reorg(&backend_, 2)).await.unwrap();
+        assert_reverted_to_block_2(backend);
+        assert_eq!(backend.get_sync_stall().unwrap(), None);
+
+        let blocks = (3..6).map(|block_n| forked_block(block_n, 2)).collect();
+        verify_and_apply_with(
+            backend,
+            blocks,
+            ApplyConfig { verify: true, feeder_chain: feeder, ..Default::default() },
+        )
+        .await
+        .unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(5));
+    }
+
+    const FAST_SYNC_CONTRACTS: [u64; 3] = [0x100, 0x101, 0x102];
+
+    /// Blocks 0 to 2 each deploy a contract, the next ones write to their storage, bump their nonces and replace a
+    /// class: the changes of a batch overlap.
+    fn fast_sync_state_diff(block_n: u64) -> StateDiff {
+        let contract = Felt::from(FAST_SYNC_CONTRACTS[block_n as usize % 3]);
+        StateDiff::new(
+            vec![ContractStorageDiffItem {
+                address: contract,
+                storage_entries: vec![StorageEntry { key: Felt::from(block_n % 4), value: Felt::from(block_n + 1) }],
+            }],
+            vec![],
+            vec![],
+            if block_n < 3 {
+                vec![DeployedContractItem { address: contract, class_hash: Felt::from(0xc1a55) }]
+            } else {
+                vec![]
+            },
+            if block_n == 7 {
+                vec![ReplacedClassItem { contract_address: contract, class_hash: Felt::from(0xc1a56) }]
+            } else {
+                vec![]
+            },
+            if block_n % 2 == 1 {
+                vec![NonceUpdate { contract_address: contract, nonce: Felt::from(block_n) }]
+            } else {
+                vec![]
+            },
+        )
+    }
+
+    /// The blocks of [`fast_sync_state_diff`], with the state roots of a sync committing every block to the tries.
+    async fn fast_sync_blocks(until: u64) -> Vec<L2ConvertedBlockAndUpdates> {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        (0..until)
+            .map(|block_n| {
+                let mut block = empty_block(block_n);
+                block.converted_state_diff = fast_sync_state_diff(block_n);
+                block.converted_block.info.header.global_state_root =
+                    update_tries_and_compute_state_root(backend, &block.converted_state_diff, block_n);
+                backend
+                    .store_block(block.converted_block.clone().into(), block.converted_state_diff.clone(), vec![])
+                    .unwrap();
+                block
+            })
+            .collect()
+    }
+
+    fn global_tries_root(backend: &DeoxysBackend) -> Felt {
+        crate::commitments::global_tries_root(backend).unwrap()
+    }
+
+    async fn fast_sync(
+        backend: &Arc<DeoxysBackend>,
+        blocks: Vec<L2ConvertedBlockAndUpdates>,
+        until_block: u64,
+        batch_size: u64,
+    ) -> anyhow::Result<()> {
+        let fast_sync = Some(FastSyncConfig { until_block, batch_size });
+        verify_and_apply_with(backend, blocks, ApplyConfig { verify: true, fast_sync, ..Default::default() }).await
+    }
+
+    #[tokio::test]
+    async fn test_fast_sync_commits_the_tries_once_per_batch() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let blocks = fast_sync_blocks(10).await;
+        let tip_root = blocks[9].converted_block.info.header.global_state_root;
+
+        // Batches 0..=2, 3..=5 and 6..=7, then the blocks are committed one by one.
+        fast_sync(backend, blocks, 8, 3).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(9));
+        assert_eq!(global_tries_root(backend), tip_root);
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), None);
+
+        // Only the state roots of the last block of each batch are verified.
+        let verification = |block_n| backend.get_block_verification(&BlockId::Number(block_n)).unwrap().unwrap();
+        for block_n in 0..10 {
+            let verified = [2, 5, 7, 8, 9].contains(&block_n);
+            assert_eq!(verification(block_n).contains(BlockVerification::VERIFIED_ROOT), verified, "block {block_n}");
+        }
+        // The storage of the blocks in the middle of a batch is served.
+        let storage = backend.get_contract_storage_at(&BlockId::Number(4), &Felt::from(0x101), &Felt::ZERO);
+        assert_eq!(storage.unwrap(), Some(Felt::from(5)));
+    }
+
+    #[tokio::test]
+    async fn test_fast_sync_commits_the_stored_blocks_after_a_restart() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let mut blocks = fast_sync_blocks(8).await;
+        let roots: Vec<_> = blocks.iter().map(|block| block.converted_block.info.header.global_state_root).collect();
+
+        // The sync stops in the middle of the second batch.
+        let next_blocks = blocks.split_off(6);
+        fast_sync(backend, blocks, 100, 4).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(5));
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), Some(4));
+        assert_eq!(global_tries_root(backend), roots[3]);
+
+        // Blocks 4 and 5 are committed before the sync goes on.
+        fast_sync(backend, next_blocks, 100, 4).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(7));
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), None);
+        assert_eq!(global_tries_root(backend), roots[7]);
+    }
+
+    #[tokio::test]
+    async fn test_fast_sync_detects_a_corrupted_state_diff_at_the_end_of_the_batch() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let mut blocks = fast_sync_blocks(6).await;
+        let roots: Vec<_> = blocks.iter().map(|block| block.converted_block.info.header.global_state_root).collect();
+        // Block 4 only writes at key 0 of its contract.
+        blocks[4].converted_state_diff =
+            storage_write(Felt::from(FAST_SYNC_CONTRACTS[1]), Felt::ZERO, Felt::from(0xbad));
+
+        let err = fast_sync(backend, blocks, 100, 3).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(&L2SyncError::MismatchedStateRoot { block: 5, .. })));
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(4));
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), Some(3));
+        // The batch is taken out of the tries, to be committed again from block 3.
+        assert_eq!(global_tries_root(backend), roots[2]);
+
+        // The stored block 4 fails the check again after a restart, without leaving its batch in the tries.
+        for _ in 0..2 {
+            let err = fast_sync(backend, vec![], 100, 3).await.unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(&L2SyncError::MismatchedStateRoot { block: 4, .. })));
+            assert_eq!(global_tries_root(backend), roots[2]);
+        }
+
+        // Once the blocks of the batch are reverted, the sync starts again from block 3.
+        backend.revert_to(2, false).unwrap();
+        fast_sync(backend, fast_sync_blocks(6).await.split_off(3), 100, 3).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(5));
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), None);
+        assert_eq!(global_tries_root(backend), roots[5]);
+    }
+
+    #[tokio::test]
+    async fn test_fast_sync_takes_an_unstored_batch_out_of_the_tries_after_a_restart() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let mut blocks = fast_sync_blocks(6).await;
+        let roots: Vec<_> = blocks.iter().map(|block| block.converted_block.info.header.global_state_root).collect();
+        let next_blocks = blocks.split_off(5);
+        fast_sync(backend, blocks, 100, 3).await.unwrap();
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), Some(3));
+
+        // The batch 3..=5 is committed, then the node stops before block 5 is stored.
+        let mut batch = StateDiffBatch::default();
+        for block_n in 3..=5 {
+            batch.push(block_n, &fast_sync_state_diff(block_n));
+        }
+        assert_eq!(update_tries_and_compute_state_root(backend, &batch.into_state_diff(), 5), roots[5]);
+
+        // Block 5 is taken out of the tries before blocks 3 and 4 are committed again.
+        fast_sync(backend, next_blocks, 100, 3).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(5));
+        assert_eq!(backend.get_uncommitted_tries_from().unwrap(), None);
+        assert_eq!(global_tries_root(backend), roots[5]);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_revert_rebuilds_the_tries() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let blocks = fast_sync_blocks(6).await;
+        let roots: Vec<_> = blocks.iter().map(|block| block.converted_block.info.header.global_state_root).collect();
+        verify_and_apply(backend, blocks, false).await.unwrap();
+
+        // The blocks are removed, the revert stops before the tries.
+        backend.revert_to(3, false).unwrap();
+        assert_eq!(global_tries_root(backend), roots[5]);
+
+        let backend_ = Arc::clone(backend);
+        assert!(spawn_rayon_task(move || repair_tries_after_revert(&backend_, 3)).await.unwrap());
+        assert_eq!(global_tries_root(backend), roots[3]);
+        let backend_ = Arc::clone(backend);
+        assert!(!spawn_rayon_task(move || repair_tries_after_revert(&backend_, 3)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sync_stall_is_reported_after_a_restart() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        backend.write_sync_stall(&SyncStall::DeepReorg { tip_block_n: 4, common_ancestor: Some(2) }).unwrap();
+
+        let (sender, receiver) = mpsc::channel(1);
+        drop(sender);
+        let registry = MetricsService::new(false, false, 0).unwrap().registry();
+        let block_metrics = BlockMetrics::register(&registry).unwrap();
+        l2_verify_and_apply_task(
+            Arc::clone(backend),
+            receiver,
+            ApplyConfig { verify: true, ..Default::default() },
+            block_metrics.clone(),
+            DbMetrics::register(&registry).unwrap(),
+            Arc::new(Mutex::new(None)),
+            TelemetryService::new(true, vec![]).unwrap().new_handle(),
+            PendingTaskControl::default(),
+            ComputePool::new("sync", 2).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(block_metrics.l2_sync_stalled.get(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_pending_task_is_stopped_after_its_poll() {
+        let control = PendingTaskControl::default();
+        let poll = control.poll().await.unwrap();
+        // The reorg waits for the poll in flight.
+        assert!(control.stop().now_or_never().is_none());
+        drop(poll);
+
+        let stopped = control.stop().await;
+        assert!(control.poll().now_or_never().is_none());
+        drop(stopped);
+        assert!(control.poll().await.is_none());
+    }
+}
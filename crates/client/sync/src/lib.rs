@@ -1,11 +1,17 @@
-use crate::l2::L2SyncConfig;
+use crate::l2::{BlockImportHook, L2SyncConfig};
 
 pub mod commitments;
+pub mod dump;
+pub mod fast_sync;
 pub mod fetch;
+pub mod import;
 pub mod l2;
 pub mod metrics;
+pub mod pipeline;
 pub mod reorgs;
 pub mod utils;
+pub mod verification;
+pub mod verify_tries;
 
 #[cfg(feature = "m")]
 pub use utils::m;
@@ -14,12 +20,14 @@ pub use utils::{convert, utility};
 pub mod starknet_sync_worker {
     use super::*;
     use crate::metrics::block_metrics::BlockMetrics;
-    use anyhow::Context;
     use dc_db::{db_metrics::DbMetrics, DeoxysBackend};
     use dc_eth::client::EthereumClient;
+    use dc_eth::state_update::L1StateSyncConfig;
     use dc_telemetry::TelemetryHandle;
     use dp_convert::ToFelt;
-    use fetch::fetchers::FetchConfig;
+    use dp_utils::clock::SystemClock;
+    use dp_utils::compute_pool::ComputePool;
+    use fetch::fetchers::{FetchConfig, FetchPolicy};
 
     use starknet_providers::SequencerGatewayProvider;
     use std::{sync::Arc, time::Duration};
@@ -35,50 +43,86 @@ pub mod starknet_sync_worker {
         db_metrics: DbMetrics,
         telemetry: TelemetryHandle,
         pending_block_poll_interval: Duration,
+        block_import_hook: Option<Arc<dyn BlockImportHook>>,
+        compute_pool: ComputePool,
     ) -> anyhow::Result<()> {
-        let starting_block = if let Some(starting_block) = starting_block {
-            starting_block
-        } else {
-            backend
-                .get_block_n(&dp_block::BlockId::Tag(dp_block::BlockTag::Latest))
-                .context("getting sync tip")?
-                .map(|block_id| block_id + 1) // next block after the tip
-                .unwrap_or_default() as _ // or genesis
-        };
-
-        log::info!("⛓️  Starting L2 sync from block {}", starting_block);
-
-        let chain_id = fetch_config.chain_id.to_felt();
-        let provider =
-            SequencerGatewayProvider::new(fetch_config.gateway.clone(), fetch_config.feeder_gateway.clone(), chain_id);
-        let provider = match &fetch_config.api_key {
-            Some(api_key) => provider.with_header("X-Throttling-Bypass".to_string(), api_key.clone()),
-            None => provider,
-        };
-
-        let l1_fut = async { dc_eth::state_update::sync(backend, &eth_client, chain_id).await };
-
         tokio::try_join!(
-            l1_fut,
-            l2::sync(
+            l1_sync(backend, &fetch_config, &eth_client),
+            l2_sync(
                 backend,
-                provider,
-                L2SyncConfig {
-                    first_block: starting_block,
-                    n_blocks_to_sync: fetch_config.n_blocks_to_sync,
-                    verify: fetch_config.verify,
-                    sync_polling_interval: fetch_config.sync_polling_interval,
-                    backup_every_n_blocks,
-                    pending_block_poll_interval,
-                },
+                &fetch_config,
+                starting_block,
+                backup_every_n_blocks,
                 block_metrics,
                 db_metrics,
-                starting_block,
-                chain_id,
                 telemetry,
+                pending_block_poll_interval,
+                block_import_hook,
+                compute_pool,
             ),
         )?;
 
         Ok(())
     }
+
+    /// Follows the state updates of the core contract on L1, which confirm the blocks synced from L2.
+    pub async fn l1_sync(
+        backend: &DeoxysBackend,
+        fetch_config: &FetchConfig,
+        eth_client: &EthereumClient,
+    ) -> anyhow::Result<()> {
+        let l1_config = L1StateSyncConfig { confirmations: fetch_config.l1_confirmations, ..Default::default() };
+        dc_eth::state_update::sync(backend, eth_client, &l1_config).await
+    }
+
+    /// Syncs the blocks and the pending block from the feeder gateway. The sync resumes from the database tip, so
+    /// `starting_block` can only be given when the database is empty.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn l2_sync(
+        backend: &Arc<DeoxysBackend>,
+        fetch_config: &FetchConfig,
+        starting_block: Option<u64>,
+        backup_every_n_blocks: Option<u64>,
+        block_metrics: BlockMetrics,
+        db_metrics: DbMetrics,
+        telemetry: TelemetryHandle,
+        pending_block_poll_interval: Duration,
+        block_import_hook: Option<Arc<dyn BlockImportHook>>,
+        compute_pool: ComputePool,
+    ) -> anyhow::Result<()> {
+        let chain_id = fetch_config.chain_id.clone().to_felt();
+        let provider =
+            SequencerGatewayProvider::new(fetch_config.gateway.clone(), fetch_config.feeder_gateway.clone(), chain_id);
+        let provider = match &fetch_config.api_key {
+            Some(api_key) => provider.with_header("X-Throttling-Bypass".to_string(), api_key.clone()),
+            None => provider,
+        };
+
+        l2::sync(
+            backend,
+            provider,
+            L2SyncConfig {
+                first_block: starting_block,
+                n_blocks_to_sync: fetch_config.n_blocks_to_sync,
+                fetch_concurrency: fetch_config.concurrency,
+                fetch_policy: Arc::new(FetchPolicy::from_config(fetch_config)),
+                verify: fetch_config.verify,
+                ignore_state_root_mismatch: fetch_config.ignore_state_root_mismatch,
+                fast_sync: fetch_config.fast_sync,
+                allow_unsupported_protocol_version: fetch_config.allow_unsupported_protocol_version,
+                sync_polling_interval: fetch_config.sync_polling_interval,
+                backup_every_n_blocks,
+                pending_block_poll_interval,
+                clock: Arc::new(SystemClock),
+                block_import_hook,
+                pipeline: fetch_config.pipeline,
+                compute_pool,
+            },
+            block_metrics,
+            db_metrics,
+            chain_id,
+            telemetry,
+        )
+        .await
+    }
 }
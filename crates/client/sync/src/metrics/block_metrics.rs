@@ -1,4 +1,33 @@
-use dc_metrics::{Gauge, MetricsRegistry, PrometheusError, F64};
+use std::time::Duration;
+
+use dc_metrics::{
+    exponential_buckets, Counter, Gauge, Histogram, HistogramOpts, HistogramVec, MetricsRegistry, PrometheusError, F64,
+    U64,
+};
+
+/// Stages of the import of a block, timed by [`BlockMetrics::l2_block_import_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStage {
+    /// Fetching the block, its state update and its classes from the feeder gateway.
+    Fetch,
+    /// Converting the block and its classes, and computing the block commitments.
+    Convert,
+    /// Computing the state root of the block.
+    Verify,
+    /// Storing the block in the database.
+    Commit,
+}
+
+impl ImportStage {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Fetch => "fetch",
+            Self::Convert => "convert",
+            Self::Verify => "verify",
+            Self::Commit => "commit",
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct BlockMetrics {
@@ -10,10 +39,26 @@ pub struct BlockMetrics {
     pub l2_state_size: Gauge<F64>,
     pub transaction_count: Gauge<F64>,
     pub event_count: Gauge<F64>,
-    // L1 network metrics
-    // gas price is also define in eth/client.rs but this would be the gas used in the block and it's price
-    pub l1_gas_price_wei: Gauge<F64>,
-    pub l1_gas_price_strk: Gauge<F64>,
+    pub l2_class_refetches: Counter<U64>,
+    pub l2_classes_fetched: Counter<U64>,
+    pub l2_classes_skipped: Counter<U64>,
+    pub l2_reorgs: Counter<U64>,
+    pub l2_sync_stalled: Gauge<F64>,
+    pub l2_delayed_verifications: Counter<U64>,
+    /// Blocks between the last imported block and the highest block known by the sync.
+    pub l2_sync_lag: Gauge<F64>,
+    /// Number of entries of the state diff of the last imported block.
+    pub l2_state_diff_size: Gauge<F64>,
+    /// Time spent on each [`ImportStage`] of a block, in seconds.
+    pub l2_block_import_time: HistogramVec,
+    /// Time spent compiling each class declared by the imported blocks, in seconds.
+    pub l2_class_compilation_time: Histogram,
+    /// Blocks fetched and not stored yet, and their approximate size in bytes, see [`crate::pipeline`].
+    pub l2_pipeline_blocks: Gauge<F64>,
+    pub l2_pipeline_bytes: Gauge<F64>,
+    /// L1 gas prices of the last imported block. The prices sampled from L1 are in `dc_eth::client::L1BlockMetrics`.
+    pub l2_block_eth_gas_price: Gauge<F64>,
+    pub l2_block_strk_gas_price: Gauge<F64>,
 }
 
 impl BlockMetrics {
@@ -31,9 +76,67 @@ impl BlockMetrics {
             transaction_count: registry
                 .register(Gauge::new("deoxys_transaction_count", "Gauge for deoxys transaction count")?)?,
             event_count: registry.register(Gauge::new("deoxys_event_count", "Gauge for deoxys event count")?)?,
-            l1_gas_price_wei: registry.register(Gauge::new("deoxys_l1_gas_price", "Gauge for deoxys L1 gas price")?)?,
-            l1_gas_price_strk: registry
-                .register(Gauge::new("deoxys_l1_gas_price_strk", "Gauge for deoxys L1 gas price in strk")?)?,
+            l2_class_refetches: registry.register(Counter::new(
+                "deoxys_l2_class_refetches",
+                "Counter of the fetches of the classes missing from a block",
+            )?)?,
+            l2_classes_fetched: registry.register(Counter::new(
+                "deoxys_l2_classes_fetched",
+                "Counter of the classes downloaded by the sync",
+            )?)?,
+            l2_classes_skipped: registry.register(Counter::new(
+                "deoxys_l2_classes_skipped",
+                "Counter of the classes the sync did not download, as they were stored or downloaded already",
+            )?)?,
+            l2_reorgs: registry.register(Counter::new(
+                "deoxys_l2_reorgs",
+                "Counter of the forks of the chain the sync reverted on its own",
+            )?)?,
+            l2_sync_stalled: registry.register(Gauge::new(
+                "deoxys_l2_sync_stalled",
+                "Gauge set to 1 while the sync is stopped, `deoxys_getSyncStall` tells why",
+            )?)?,
+            l2_delayed_verifications: registry.register(Counter::new(
+                "deoxys_l2_delayed_verifications",
+                "Counter of the verification flags set on the blocks after they were stored",
+            )?)?,
+            l2_sync_lag: registry.register(Gauge::new(
+                "deoxys_l2_sync_lag",
+                "Gauge for the number of blocks between the last imported block and the highest known block",
+            )?)?,
+            l2_state_diff_size: registry.register(Gauge::new(
+                "deoxys_l2_state_diff_size",
+                "Gauge for the number of entries of the state diff of the last imported block",
+            )?)?,
+            l2_block_import_time: registry.register(HistogramVec::new(
+                HistogramOpts::new("deoxys_l2_block_import_time", "Time [s] spent on each stage of a block import")
+                    .buckets(exponential_buckets(0.001, 2.0, 16)?),
+                &["stage"],
+            )?)?,
+            l2_class_compilation_time: registry.register(Histogram::with_opts(
+                HistogramOpts::new("deoxys_l2_class_compilation_time", "Time [s] spent compiling a declared class")
+                    .buckets(exponential_buckets(0.001, 2.0, 16)?),
+            )?)?,
+            l2_pipeline_blocks: registry.register(Gauge::new(
+                "deoxys_l2_pipeline_blocks",
+                "Gauge for the number of blocks fetched by the sync and not stored yet",
+            )?)?,
+            l2_pipeline_bytes: registry.register(Gauge::new(
+                "deoxys_l2_pipeline_bytes",
+                "Gauge for the approximate size in bytes of the blocks fetched by the sync and not stored yet",
+            )?)?,
+            l2_block_eth_gas_price: registry.register(Gauge::new(
+                "deoxys_l2_block_eth_gas_price",
+                "Gauge for the L1 gas price in wei of the last imported block",
+            )?)?,
+            l2_block_strk_gas_price: registry.register(Gauge::new(
+                "deoxys_l2_block_strk_gas_price",
+                "Gauge for the L1 gas price in fri of the last imported block",
+            )?)?,
         })
     }
+
+    pub fn observe_import_stage(&self, stage: ImportStage, elapsed: Duration) {
+        self.l2_block_import_time.with_label_values(&[stage.label()]).observe(elapsed.as_secs_f64());
+    }
 }
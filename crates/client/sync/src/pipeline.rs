@@ -0,0 +1,227 @@
+//! Backpressure of the sync pipeline.
+//!
+//! The fetched blocks go through the conversion and the verification before they are stored, and a block with its
+//! classes can weigh tens of megabytes once converted. Every block takes an [`InFlightBlock`] from the
+//! [`PipelineBudget`] once it is fetched, and gives it back once it is stored: when the database falls behind, the
+//! budget runs out and the fetch task waits instead of piling blocks up in memory.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use starknet_core::types::ContractClass;
+use tokio::sync::Semaphore;
+
+use crate::fetch::fetchers::L2BlockAndUpdates;
+use crate::metrics::block_metrics::BlockMetrics;
+
+/// Approximate size of a transaction with its receipt, in bytes.
+const APPROX_TX_SIZE: usize = 2 * 1024;
+/// Approximate size of an entry of a state diff, in bytes.
+const APPROX_STATE_DIFF_ENTRY_SIZE: usize = 3 * 32;
+/// A converted class also holds its compiled class, which is a few times larger than the class itself.
+const CONVERTED_CLASS_FACTOR: usize = 4;
+/// The fetch task warns when it waits for the database for longer than this.
+const PIPELINE_FULL_WARNING: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Number of blocks fetched and not stored yet. The blocks being fetched are not counted, there are at most
+    /// `fetch_concurrency` of them.
+    pub max_blocks: usize,
+    /// Approximate size of the blocks fetched and not stored yet, in bytes. A block larger than this is let through
+    /// alone.
+    pub max_bytes: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self { max_blocks: 16, max_bytes: 1024 * 1024 * 1024 }
+    }
+}
+
+/// Approximate size of a block once converted, with its classes.
+pub fn approx_block_size(block: &L2BlockAndUpdates) -> usize {
+    let classes: usize = block
+        .class_update
+        .iter()
+        .map(|class| match &class.contract_class {
+            ContractClass::Sierra(class) => class.sierra_program.len() * 32 + class.abi.len(),
+            ContractClass::Legacy(class) => class.program.len(),
+        })
+        .sum();
+    block.block.transactions.len() * APPROX_TX_SIZE
+        + block.state_diff.len() * APPROX_STATE_DIFF_ENTRY_SIZE
+        + classes * CONVERTED_CLASS_FACTOR
+}
+
+#[derive(Debug)]
+struct BudgetInner {
+    blocks: Semaphore,
+    /// One permit per KiB.
+    kib: Semaphore,
+    max_kib: u32,
+    blocks_in_flight: AtomicUsize,
+    bytes_in_flight: AtomicUsize,
+    metrics: OnceLock<BlockMetrics>,
+}
+
+impl BudgetInner {
+    fn update_metrics(&self) {
+        if let Some(metrics) = self.metrics.get() {
+            metrics.l2_pipeline_blocks.set(self.blocks_in_flight.load(Ordering::Relaxed) as f64);
+            metrics.l2_pipeline_bytes.set(self.bytes_in_flight.load(Ordering::Relaxed) as f64);
+        }
+    }
+}
+
+/// The blocks and bytes the sync may hold between their fetch and their storage, shared by the stages of the pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineBudget {
+    inner: Arc<BudgetInner>,
+}
+
+impl PipelineBudget {
+    pub fn new(config: PipelineConfig) -> Self {
+        let max_kib = u32::try_from(config.max_bytes.div_ceil(1024)).unwrap_or(u32::MAX).max(1);
+        Self {
+            inner: Arc::new(BudgetInner {
+                blocks: Semaphore::new(config.max_blocks.clamp(1, Semaphore::MAX_PERMITS)),
+                kib: Semaphore::new(max_kib as usize),
+                max_kib,
+                blocks_in_flight: Default::default(),
+                bytes_in_flight: Default::default(),
+                metrics: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Exports the occupancy of the pipeline as metrics. Only the first metrics set are used.
+    pub fn set_metrics(&self, metrics: BlockMetrics) {
+        let _ = self.inner.metrics.set(metrics);
+        self.inner.update_metrics();
+    }
+
+    /// Waits until a block of `bytes` fits in the budget.
+    pub async fn reserve(&self, bytes: usize) -> InFlightBlock {
+        let kib = u32::try_from(bytes.div_ceil(1024)).unwrap_or(u32::MAX).clamp(1, self.inner.max_kib);
+        // The semaphores are never closed.
+        self.inner.blocks.acquire().await.expect("Pipeline budget closed").forget();
+        self.inner.kib.acquire_many(kib).await.expect("Pipeline budget closed").forget();
+
+        self.inner.blocks_in_flight.fetch_add(1, Ordering::Relaxed);
+        self.inner.bytes_in_flight.fetch_add(bytes, Ordering::Relaxed);
+        self.inner.update_metrics();
+        InFlightBlock { budget: Arc::clone(&self.inner), kib, bytes }
+    }
+
+    /// Like [`PipelineBudget::reserve`], with a warning every time the wait lasts longer than
+    /// [`PIPELINE_FULL_WARNING`]: the stages after the fetch, most likely the database, cannot keep up.
+    pub async fn reserve_or_warn(&self, block_n: u64, bytes: usize) -> InFlightBlock {
+        let mut reserve = std::pin::pin!(self.reserve(bytes));
+        let mut waited = Duration::ZERO;
+        loop {
+            match tokio::time::timeout(PIPELINE_FULL_WARNING, &mut reserve).await {
+                Ok(in_flight) => return in_flight,
+                Err(_) => {
+                    waited += PIPELINE_FULL_WARNING;
+                    log::warn!(
+                        "⏳ Block {block_n} waits for the blocks before it to be stored for {}s, the sync pipeline \
+                         holds {} blocks ({} MiB)",
+                        waited.as_secs(),
+                        self.blocks_in_flight(),
+                        self.bytes_in_flight() / (1024 * 1024)
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn blocks_in_flight(&self) -> usize {
+        self.inner.blocks_in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_in_flight(&self) -> usize {
+        self.inner.bytes_in_flight.load(Ordering::Relaxed)
+    }
+}
+
+/// The share of the [`PipelineBudget`] taken by a block, given back when this is dropped.
+#[derive(Debug)]
+pub struct InFlightBlock {
+    budget: Arc<BudgetInner>,
+    kib: u32,
+    bytes: usize,
+}
+
+impl Drop for InFlightBlock {
+    fn drop(&mut self) {
+        self.budget.blocks_in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.budget.bytes_in_flight.fetch_sub(self.bytes, Ordering::Relaxed);
+        self.budget.update_metrics();
+        self.budget.blocks.add_permits(1);
+        self.budget.kib.add_permits(self.kib as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// Sends `n_blocks` blocks of `bytes` through a channel as large as the chain, to a consumer storing a block every
+    /// 10ms. Returns the highest number of blocks held at once.
+    async fn run_slow_pipeline(config: PipelineConfig, n_blocks: u64, bytes: usize) -> usize {
+        let budget = PipelineBudget::new(config);
+        let (sender, mut receiver) = mpsc::channel(n_blocks as usize);
+
+        let consumer = tokio::spawn({
+            let budget = budget.clone();
+            async move {
+                let (mut peak, mut stored) = (0, 0);
+                while let Some(in_flight) = receiver.recv().await {
+                    peak = peak.max(budget.blocks_in_flight());
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    drop(in_flight);
+                    stored += 1;
+                }
+                (peak, stored)
+            }
+        });
+
+        for _ in 0..n_blocks {
+            let in_flight = budget.reserve(bytes).await;
+            assert!(budget.blocks_in_flight() <= config.max_blocks);
+            sender.send(in_flight).await.unwrap();
+        }
+        drop(sender);
+        let (peak, stored) = consumer.await.unwrap();
+        assert_eq!(stored, n_blocks);
+        assert_eq!((budget.blocks_in_flight(), budget.bytes_in_flight()), (0, 0));
+        peak
+    }
+
+    #[tokio::test]
+    async fn slow_storage_holds_the_fetch_back() {
+        let config = PipelineConfig { max_blocks: 3, max_bytes: usize::MAX };
+        let peak = run_slow_pipeline(config, 20, 1024).await;
+        assert!(peak <= 3, "{peak} blocks held at once");
+        assert!(peak >= 2, "The pipeline never filled up");
+    }
+
+    #[tokio::test]
+    async fn large_blocks_are_bounded_by_size() {
+        // Two blocks of 4 KiB fit in 10 KiB.
+        let config = PipelineConfig { max_blocks: 16, max_bytes: 10 * 1024 };
+        let peak = run_slow_pipeline(config, 10, 4 * 1024).await;
+        assert!(peak <= 2, "{peak} blocks held at once");
+    }
+
+    #[tokio::test]
+    async fn block_larger_than_the_budget_goes_alone() {
+        let config = PipelineConfig { max_blocks: 16, max_bytes: 4 * 1024 };
+        let peak = run_slow_pipeline(config, 4, 64 * 1024).await;
+        assert_eq!(peak, 1);
+    }
+}
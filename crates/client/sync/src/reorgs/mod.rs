@@ -1,34 +1,156 @@
-use starknet_providers::sequencer::models::Block as StarknetBlock;
+//! Handling of the reorgs of the Starknet chain.
+//!
+//! On Starknet with the current system relying on a single sequencer it's rare to see a reorg, but if the L1 reorgs
+//! the feeder serves a chain that forks from ours. The fork is detected when the next block does not build on our
+//! tip: the sync then looks for the last block we have in common with the feeder. A shallow fork is reverted right
+//! away, and the sync resumes from the common ancestor. A deeper one halts the sync until the node operator confirms
+//! the revert.
+pub mod pending;
 
-/// Check for a reorg on Starknet and fix the current state if detected.
-///
-/// On Starknet with the current system relying on a single sequencer it's rare to detect a reorg,
-/// but if the L1 reorgs we must handle it the following way:
-///
-/// 1. The last fetched block parent hash is not equal to the last synced block by Deoxys: a reorg
-///    is detected.
-/// 2. We remove the last synced substrate digest and the associated classes/state_update we stored
-///    until we reach the last common ancestor.
+use std::sync::Arc;
+
+use anyhow::Context;
+use dc_db::{DeoxysBackend, MAX_REVERTIBLE_BLOCKS};
+use dp_block::{BlockId, BlockN};
+use futures::future::BoxFuture;
+use starknet_core::types::{MaybePendingBlockWithTxHashes, StarknetError};
+use starknet_providers::{Provider, ProviderError, SequencerGatewayProvider};
+use starknet_types_core::felt::Felt;
+
+use crate::commitments::global_tries_root;
+use crate::verify_tries::rebuild_tries;
+
+/// Reads the chain of the feeder, to find where it forks from ours.
+pub trait FeederChain: Send + Sync {
+    /// Hash of the block `block_n` on the feeder, `None` if the feeder does not have it.
+    fn block_hash(&self, block_n: u64) -> BoxFuture<'_, anyhow::Result<Option<Felt>>>;
+}
+
+pub struct GatewayFeederChain {
+    provider: Arc<SequencerGatewayProvider>,
+}
+
+impl GatewayFeederChain {
+    pub fn new(provider: Arc<SequencerGatewayProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+impl FeederChain for GatewayFeederChain {
+    fn block_hash(&self, block_n: u64) -> BoxFuture<'_, anyhow::Result<Option<Felt>>> {
+        Box::pin(async move {
+            match self.provider.get_block_with_tx_hashes(starknet_core::types::BlockId::Number(block_n)).await {
+                Ok(MaybePendingBlockWithTxHashes::Block(block)) => Ok(Some(block.block_hash)),
+                Ok(MaybePendingBlockWithTxHashes::PendingBlock(_)) => Ok(None),
+                Err(ProviderError::StarknetError(StarknetError::BlockNotFound)) => Ok(None),
+                Err(err) => Err(err).with_context(|| format!("Getting block {block_n} from the feeder")),
+            }
+        })
+    }
+}
+
+/// The last block below `tip` that we have in common with the feeder. `None` when there is none in the last
+/// [`MAX_REVERTIBLE_BLOCKS`] blocks: the fork cannot be reverted.
+pub async fn find_common_ancestor(
+    backend: &DeoxysBackend,
+    feeder: &dyn FeederChain,
+    tip: u64,
+) -> anyhow::Result<Option<BlockN>> {
+    let lowest = tip.saturating_sub(MAX_REVERTIBLE_BLOCKS);
+    for block_n in (lowest..=tip).rev() {
+        let ours = backend
+            .get_block_hash(&BlockId::Number(block_n))
+            .context("Getting block hash")?
+            .with_context(|| format!("Block {block_n} is not stored"))?;
+        if feeder.block_hash(block_n).await? == Some(ours) {
+            return Ok(Some(BlockN(block_n)));
+        }
+    }
+    Ok(None)
+}
+
+/// What the sync does once the feeder's chain forks from ours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReorgDecision {
+    /// Revert our chain to the common ancestor, and sync the feeder's chain from there.
+    Revert { common_ancestor: BlockN },
+    /// The fork is deeper than the chain config allows to revert automatically: stop the sync until the node operator
+    /// confirms the revert.
+    Halt,
+}
+
+/// Decides what to do with a fork of the chain.
 ///
 /// ### Arguments
 ///
-/// * `block` - The last fetched block from the sequencer (before beeing converted).
+/// * `tip` - Number of our latest block.
+/// * `common_ancestor` - The last block we have in common with the feeder, if it was found.
+/// * `max_auto_reorg_depth` - Number of blocks the sync reverts without the confirmation of the node operator.
+pub fn reorg_decision(tip: u64, common_ancestor: Option<BlockN>, max_auto_reorg_depth: u64) -> ReorgDecision {
+    match common_ancestor {
+        Some(common_ancestor) if tip - common_ancestor.0 <= max_auto_reorg_depth => {
+            ReorgDecision::Revert { common_ancestor }
+        }
+        _ => ReorgDecision::Halt,
+    }
+}
+
+/// Reverts our chain to block `block_n` once the node operator confirmed it, after the sync halted on a deep reorg.
+/// The sync resumes from there on the next start of the node.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn force_reorg(backend: &DeoxysBackend, block_n: u64) -> anyhow::Result<()> {
+    let tip = backend.get_latest_block_n()?.context("The database is empty")?;
+    anyhow::ensure!(block_n < tip, "Block {block_n} is not below the tip of the chain, block {tip}");
+    backend.revert_to(block_n, true).context("Reverting the chain")?;
+    backend.clear_sync_stall()?;
+    log::info!("↩️  Reverted the blocks {} to {tip}", block_n + 1);
+    Ok(())
+}
+
+/// Repairs the global tries when a revert of the chain was interrupted after it removed the blocks, before the tries
+/// followed it. Returns whether the tries had to be rebuilt.
 ///
-/// ### Returns
-/// This function will return a `Bool` returning `true` if a reorg was detected and `false` if not.
-pub async fn reorg(_block: StarknetBlock) -> bool {
-    todo!()
-    // let last_synced_block_hash = DeoxysBackend::meta().get_latest_block_hash_and_number().unwrap().0;
-    // if block.parent_block_hash != last_synced_block_hash {
-    //     let mut new_lsbh = last_synced_block_hash;
-    //     while block.parent_block_hash != new_lsbh {
-    //         // 1. Remove the last synced block in the digest
-    //         // 2. Remove all the downloaded stuff from the state updates
-    //         new_lsbh = DeoxysBackend::meta().get_latest_block_hash_and_number().unwrap().0;
-    //     }
-    //     // 3. Revert the state commitment tries to the correct block number
-    //     true
-    // } else {
-    //     false
-    // }
+/// NB: This functions needs to run on the rayon thread pool
+pub fn repair_interrupted_revert(backend: &DeoxysBackend) -> anyhow::Result<bool> {
+    let Some(block_n) = backend.get_interrupted_tries_revert()? else { return Ok(false) };
+    let rebuilt = repair_tries_after_revert(backend, block_n)?;
+    backend.clear_interrupted_tries_revert()?;
+    Ok(rebuilt)
+}
+
+/// The tries are reverted one after the other, the class trie last: they are all reverted when the state root is the
+/// one of block `block_n`. Otherwise any of them may be halfway, and they are rebuilt from the state diffs.
+pub(crate) fn repair_tries_after_revert(backend: &DeoxysBackend, block_n: u64) -> anyhow::Result<bool> {
+    let expected = backend
+        .get_block_info(&BlockId::Number(block_n))?
+        .as_ref()
+        .and_then(|info| info.as_nonpending())
+        .map(|info| info.header.global_state_root)
+        .with_context(|| format!("Block {block_n} is not stored"))?;
+    if global_tries_root(backend)? == expected {
+        return Ok(false);
+    }
+
+    log::warn!("🌳 The revert of the chain to block {block_n} stopped before the global tries, rebuilding them");
+    rebuild_tries(backend).context("Rebuilding the global tries")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shallow_reorg_is_reverted() {
+        assert_eq!(reorg_decision(100, Some(BlockN(99)), 64), ReorgDecision::Revert { common_ancestor: BlockN(99) });
+        assert_eq!(reorg_decision(100, Some(BlockN(36)), 64), ReorgDecision::Revert { common_ancestor: BlockN(36) });
+    }
+
+    #[test]
+    fn test_deep_reorg_halts() {
+        assert_eq!(reorg_decision(100, Some(BlockN(35)), 64), ReorgDecision::Halt);
+        assert_eq!(reorg_decision(100, None, 64), ReorgDecision::Halt);
+        assert_eq!(reorg_decision(100, Some(BlockN(99)), 0), ReorgDecision::Halt);
+    }
 }
@@ -0,0 +1,101 @@
+//! Handling of a pending block from the feeder whose parent is not our chain tip.
+
+use dp_block::BlockN;
+use starknet_types_core::felt::Felt;
+
+/// What to do with the pending block fetched from the feeder, depending on its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingParentDecision {
+    /// The pending block builds on top of our tip: store it.
+    Store,
+    /// The parent is a block we already have, below our tip: the feeder lags behind us. Our local pending data
+    /// stays valid for our tip and is kept until the feeder catches up.
+    FeederLagging { parent_block_n: BlockN },
+    /// The parent is a block the feeder has and we have not synced yet. Our local pending data is stale: clear it,
+    /// and fetch the missing blocks right away.
+    CatchUp { parent_block_n: BlockN },
+    /// The parent is unknown, or the feeder has it at a height where we hold a different block. Clear our local
+    /// pending data: our chain may have been reorganized upstream.
+    InvestigateReorg,
+}
+
+impl PendingParentDecision {
+    pub fn clears_local_pending(&self) -> bool {
+        matches!(self, Self::CatchUp { .. } | Self::InvestigateReorg)
+    }
+}
+
+/// Decides what to do with the feeder's pending block.
+///
+/// ### Arguments
+///
+/// * `tip` - Number and hash of our latest block.
+/// * `parent_hash` - Parent hash of the feeder's pending block.
+/// * `parent_in_db` - Number of the block with hash `parent_hash` in our database, if any.
+/// * `parent_on_feeder` - Number of the block with hash `parent_hash` on the feeder, if it has one. Only looked at
+///   when the parent is not in our database.
+pub fn pending_parent_decision(
+    tip: (BlockN, Felt),
+    parent_hash: Felt,
+    parent_in_db: Option<BlockN>,
+    parent_on_feeder: Option<BlockN>,
+) -> PendingParentDecision {
+    let (tip_n, tip_hash) = tip;
+    if parent_hash == tip_hash {
+        return PendingParentDecision::Store;
+    }
+
+    match (parent_in_db, parent_on_feeder) {
+        (Some(parent_block_n), _) => PendingParentDecision::FeederLagging { parent_block_n },
+        (None, Some(parent_block_n)) if parent_block_n > tip_n => PendingParentDecision::CatchUp { parent_block_n },
+        (None, _) => PendingParentDecision::InvestigateReorg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tip() -> (BlockN, Felt) {
+        (BlockN(100), Felt::from_hex_unchecked("0x100"))
+    }
+
+    #[test]
+    fn test_parent_is_tip() {
+        let decision = pending_parent_decision(tip(), tip().1, Some(tip().0), None);
+        assert_eq!(decision, PendingParentDecision::Store);
+        assert!(!decision.clears_local_pending());
+    }
+
+    #[test]
+    fn test_parent_we_have() {
+        let decision = pending_parent_decision(tip(), Felt::from_hex_unchecked("0x99"), Some(BlockN(99)), None);
+        assert_eq!(decision, PendingParentDecision::FeederLagging { parent_block_n: BlockN(99) });
+        assert!(!decision.clears_local_pending());
+    }
+
+    #[test]
+    fn test_parent_ahead_of_us() {
+        let decision = pending_parent_decision(tip(), Felt::from_hex_unchecked("0x102"), None, Some(BlockN(102)));
+        assert_eq!(decision, PendingParentDecision::CatchUp { parent_block_n: BlockN(102) });
+        assert!(decision.clears_local_pending());
+    }
+
+    #[test]
+    fn test_parent_unknown() {
+        let unknown = Felt::from_hex_unchecked("0xdead");
+        let decision = pending_parent_decision(tip(), unknown, None, None);
+        assert_eq!(decision, PendingParentDecision::InvestigateReorg);
+        assert!(decision.clears_local_pending());
+
+        // The feeder has a different block than ours at a height we already synced.
+        assert_eq!(
+            pending_parent_decision(tip(), unknown, None, Some(BlockN(100))),
+            PendingParentDecision::InvestigateReorg
+        );
+        assert_eq!(
+            pending_parent_decision(tip(), unknown, None, Some(BlockN(42))),
+            PendingParentDecision::InvestigateReorg
+        );
+    }
+}
@@ -3,41 +3,76 @@
 use dc_db::storage_updates::DbClassUpdate;
 use dp_block::header::{GasPrices, L1DataAvailabilityMode, PendingHeader};
 use dp_block::{
-    DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, DeoxysPendingBlock, DeoxysPendingBlockInfo, Header, StarknetVersion,
+    BlockVerification, DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, DeoxysPendingBlock, DeoxysPendingBlockInfo,
+    Header, StarknetVersion,
 };
-use dp_class::{ClassInfo, ConvertedClass, ToCompiledClass};
+use dp_class::{ClassInfo, CompiledClass, ConvertedClass, ToCompiledClass};
 use dp_convert::felt_to_u128;
 use dp_receipt::{Event, TransactionReceipt};
 use dp_state_update::StateDiff;
-use dp_transactions::MAIN_CHAIN_ID;
+use dp_transactions::{Transaction, TransactionTypeError, MAIN_CHAIN_ID};
+use dp_utils::error_reporting::{self, Severity};
 use rayon::prelude::*;
 use starknet_types_core::felt::Felt;
+use std::panic::AssertUnwindSafe;
+use std::time::Instant;
 
 use crate::commitments::{
     calculate_transaction_hash, memory_event_commitment, memory_receipt_commitment, memory_transaction_commitment,
+    CommitmentError,
 };
 use crate::l2::L2SyncError;
+use crate::metrics::block_metrics::BlockMetrics;
 
 pub fn convert_inner(
     txs: Vec<starknet_providers::sequencer::models::TransactionType>,
     receipts: Vec<starknet_providers::sequencer::models::ConfirmedTransactionReceipt>,
+    block_n: Option<u64>,
 ) -> Result<DeoxysBlockInner, L2SyncError> {
+    if receipts.len() > txs.len() {
+        return Err(L2SyncError::BlockFormat(
+            format!("{} receipts for {} transactions", receipts.len(), txs.len()).into(),
+        ));
+    }
+
     // converts starknet_provider transactions and events to dp_transactions and starknet_api events
-    let transactions_receipts = Iterator::zip(receipts.into_iter(), txs.iter())
-        .map(|(tx_receipts, tx)| TransactionReceipt::from_provider(tx_receipts, tx))
-        .collect::<Vec<_>>();
-    let transactions = txs.into_iter().map(|tx| tx.try_into()).collect::<Result<_, _>>()?;
+    let mut receipts = receipts.into_iter();
+    let mut transactions = Vec::with_capacity(txs.len());
+    let mut transactions_receipts = Vec::with_capacity(txs.len());
+    for (tx_index, tx) in txs.into_iter().enumerate() {
+        let (tx_type, version, tx_hash) = describe_provider_tx(&tx);
+        let err = |source| TransactionConversionError { block_n, tx_index, tx_type, version, source };
+
+        let receipt = receipts.next().ok_or_else(|| err(TransactionConversionCause::MissingReceipt))?;
+        if receipt.transaction_hash != tx_hash {
+            return Err(err(TransactionConversionCause::MismatchedReceipt(receipt.transaction_hash)).into());
+        }
+        transactions_receipts.push(TransactionReceipt::from_provider(receipt, &tx));
+        transactions.push(Transaction::try_from(tx).map_err(|source| err(source.into()))?);
+    }
 
     Ok(DeoxysBlockInner::new(transactions, transactions_receipts))
 }
 
+/// The type, version and hash of a transaction served by the feeder gateway.
+fn describe_provider_tx(tx: &starknet_providers::sequencer::models::TransactionType) -> (&'static str, Felt, Felt) {
+    use starknet_providers::sequencer::models::TransactionType;
+    match tx {
+        TransactionType::Declare(tx) => ("DECLARE", tx.version, tx.transaction_hash),
+        TransactionType::Deploy(tx) => ("DEPLOY", tx.version, tx.transaction_hash),
+        TransactionType::DeployAccount(tx) => ("DEPLOY_ACCOUNT", tx.version, tx.transaction_hash),
+        TransactionType::InvokeFunction(tx) => ("INVOKE_FUNCTION", tx.version, tx.transaction_hash),
+        TransactionType::L1Handler(tx) => ("L1_HANDLER", tx.version, tx.transaction_hash),
+    }
+}
+
 /// This function only does tx hash computation.
 pub fn convert_pending(
     block: starknet_providers::sequencer::models::Block,
     state_diff: starknet_core::types::StateDiff,
     chain_id: Felt,
 ) -> Result<(DeoxysPendingBlock, StateDiff), L2SyncError> {
-    let block_inner = convert_inner(block.transactions, block.transaction_receipts)?;
+    let block_inner = convert_inner(block.transactions, block.transaction_receipts, None)?;
     let converted_state_diff = state_diff.into();
 
     let header = PendingHeader {
@@ -53,13 +88,16 @@ pub fn convert_pending(
     Ok((DeoxysPendingBlock::new(DeoxysPendingBlockInfo::new(header, tx_hashes), block_inner), converted_state_diff))
 }
 
+/// Converts the block and computes its header from its content. The block keeps the fetched block hash, its
+/// verification tells whether the computed header matches it.
+///
 /// Compute heavy, this should only be called in a rayon ctx
-pub fn convert_and_verify_block(
+pub fn convert_block(
     block: starknet_providers::sequencer::models::Block,
     state_diff: starknet_core::types::StateDiff,
     chain_id: Felt,
 ) -> Result<(DeoxysBlock, StateDiff), L2SyncError> {
-    let block_inner = convert_inner(block.transactions, block.transaction_receipts)?;
+    let block_inner = convert_inner(block.transactions, block.transaction_receipts, block.block_number)?;
     let converted_state_diff: StateDiff = state_diff.into();
 
     let block_hash = block.block_hash.ok_or(L2SyncError::BlockFormat("No block hash provided".into()))?;
@@ -78,7 +116,8 @@ pub fn convert_and_verify_block(
         transaction_count,
         event_count,
         state_diff_length,
-    } = compute_commitments_for_block(&block_inner, &converted_state_diff, starknet_version, chain_id, block_number);
+    } = compute_commitments_for_block(&block_inner, &converted_state_diff, starknet_version, chain_id, block_number)
+        .map_err(|source| L2SyncError::Commitment { block_n: block_number, source })?;
 
     let header = Header::new(
         block.parent_block_hash,
@@ -98,16 +137,72 @@ pub fn convert_and_verify_block(
         l1_da_mode(block.l1_da_mode),
     );
 
-    let computed_block_hash = header.compute_hash(chain_id);
+    // The header is made of the commitments we computed: they are verified along with the hash.
+    let verification = if header.verify_hash(chain_id, block_hash) {
+        BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS
+    } else {
+        BlockVerification::NONE
+    };
+
+    let info = DeoxysBlockInfo::new(header, tx_hashes, block_hash).with_verification(verification);
+    Ok((DeoxysBlock::new(info, block_inner), converted_state_diff))
+}
+
+/// Converts the block, and fails when its computed hash does not match the fetched one. The blocks of a Starknet
+/// version newer than the supported ones are rejected, as their hashes, commitments and fees may be computed in a way
+/// this node does not know about. With `allow_unsupported_protocol_version`, they are imported as they are, and their
+/// verification tells whether the computed hash matched.
+///
+/// Compute heavy, this should only be called in a rayon ctx
+pub fn convert_and_verify_block(
+    block: starknet_providers::sequencer::models::Block,
+    state_diff: starknet_core::types::StateDiff,
+    chain_id: Felt,
+    allow_unsupported_protocol_version: bool,
+) -> Result<(DeoxysBlock, StateDiff), L2SyncError> {
+    let (block, state_diff) = convert_block(block, state_diff, chain_id)?;
 
-    // mismatched block hash is allowed for blocks 1466..=2242 on mainnet
-    if computed_block_hash != block_hash && !((1466..=2242).contains(&block_number) && chain_id == MAIN_CHAIN_ID) {
+    let (block_number, block_hash) = (block.info.header.block_number, block.info.block_hash);
+    let protocol_version = block.info.header.protocol_version;
+    if !protocol_version.is_supported() {
+        if !allow_unsupported_protocol_version {
+            return Err(L2SyncError::UnsupportedProtocolVersion(protocol_version, block_number));
+        }
+        // The hash may be computed differently in this version, the block is imported unverified.
+        log::warn!(
+            "⚠️  Block {block_number} is at the unsupported Starknet version {protocol_version}, importing it anyway"
+        );
+    }
+    if !block.info.verification.contains(BlockVerification::VERIFIED_HASH)
+        && block_hash_mismatch_allowed(block_number, chain_id)
+    {
+        // No known variant of the block hash gives these back: the block is imported unverified.
+        log::warn!("⚠️  Block {block_number} has a block hash that cannot be verified, importing it unverified");
+    } else if !block.info.verification.contains(BlockVerification::VERIFIED_HASH) && protocol_version.is_supported() {
+        let computed_block_hash = block.info.header.compute_hash(chain_id);
+        error_reporting::report(
+            Severity::Critical,
+            "sync.verify",
+            format_args!(
+                "Computed block hash {computed_block_hash:#x} doesn't match fetched block hash {block_hash:#x} for \
+                 block {block_number}"
+            ),
+        );
         return Err(L2SyncError::MismatchedBlockHash(block_number));
     }
 
-    Ok((DeoxysBlock::new(DeoxysBlockInfo::new(header, tx_hashes, block_hash), block_inner), converted_state_diff))
+    Ok((block, state_diff))
+}
+
+/// Mismatched block hashes are allowed for blocks 1466..=2242 on mainnet. These blocks were re-orged and their hashes
+/// were only partly computed again by the sequencer, no variant of the block hash gives them back from the header.
+// TODO: check the headers of blocks 1466 and 2000 recorded from the feeder against the hash variants before removing
+// this exemption.
+pub fn block_hash_mismatch_allowed(block_number: u64, chain_id: Felt) -> bool {
+    (1466..=2242).contains(&block_number) && chain_id == MAIN_CHAIN_ID
 }
 
+#[derive(Debug, Clone)]
 pub struct BlockCommitments {
     pub transaction_commitment: Felt,
     pub transaction_count: u64,
@@ -125,7 +220,7 @@ pub fn compute_commitments_for_block(
     starknet_version: StarknetVersion,
     chain_id: Felt,
     block_number: u64,
-) -> BlockCommitments {
+) -> Result<BlockCommitments, CommitmentError> {
     let events_with_tx_hash = events_with_tx_hash(&block_inner.receipts);
 
     let tasks_tx_and_event_commitment = || {
@@ -136,19 +231,20 @@ pub fn compute_commitments_for_block(
     };
     let tasks_receipt_and_state_diff_commitment =
         || rayon::join(|| memory_receipt_commitment(&block_inner.receipts), || state_diff.compute_hash());
-    let (((transaction_commitment, tx_hashes), event_commitment), (receipt_commitment, state_diff_commitment)) =
+    let ((transaction_commitment, event_commitment), (receipt_commitment, state_diff_commitment)) =
         rayon::join(tasks_tx_and_event_commitment, tasks_receipt_and_state_diff_commitment);
+    let (transaction_commitment, tx_hashes) = transaction_commitment?;
 
-    BlockCommitments {
+    Ok(BlockCommitments {
         transaction_commitment,
         transaction_count: block_inner.transactions.len() as _,
-        event_commitment,
+        event_commitment: event_commitment?,
         event_count: events_with_tx_hash.len() as _,
-        receipt_commitment,
+        receipt_commitment: receipt_commitment?,
         state_diff_commitment,
         state_diff_length: state_diff.len() as _,
         tx_hashes,
-    }
+    })
 }
 
 fn protocol_version(version: Option<String>) -> Result<StarknetVersion, L2SyncError> {
@@ -184,26 +280,80 @@ fn l1_da_mode(mode: starknet_core::types::L1DataAvailabilityMode) -> L1DataAvail
     }
 }
 
-fn events_with_tx_hash(receipts: &[TransactionReceipt]) -> Vec<(Felt, Event)> {
+/// The events are borrowed from the receipts: only their hashes are needed for the event commitment.
+fn events_with_tx_hash(receipts: &[TransactionReceipt]) -> Vec<(Felt, &Event)> {
     receipts
         .iter()
-        .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event.clone())))
+        .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event)))
         .collect()
 }
 
+/// A transaction of a block, or its receipt, could not be converted. The sync stops there: the block cannot be imported
+/// until the node knows about this transaction.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "Failed to convert transaction {tx_index} of {} ({tx_type} transaction of version {version:#x}): {source}",
+    block_name(block_n)
+)]
+pub struct TransactionConversionError {
+    /// `None` for the transactions of the pending block.
+    pub block_n: Option<u64>,
+    pub tx_index: usize,
+    pub tx_type: &'static str,
+    pub version: Felt,
+    pub source: TransactionConversionCause,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TransactionConversionCause {
+    #[error(transparent)]
+    Transaction(#[from] TransactionTypeError),
+    #[error("The block has no receipt for it")]
+    MissingReceipt,
+    #[error("Its receipt is the one of transaction {0:#x}")]
+    MismatchedReceipt(Felt),
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ConvertClassError {
     #[error("Mismatched class hash, expected {expected:#x}; got {got:#x}")]
     MismatchedClassHash { expected: Felt, got: Felt },
     #[error("Compute class hash error: {0}")]
     ComputeClassHashError(String),
-    #[error("Compilation class error: {0}")]
-    CompilationClassError(String),
+    #[error(transparent)]
+    Compilation(#[from] ClassCompilationError),
+}
+
+/// A class of a block could not be compiled. The compilation of a class is deterministic: the block cannot be imported
+/// until the compiler is fixed.
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to compile class {class_hash:#x} declared in {}: {source:#}", block_name(block_n))]
+pub struct ClassCompilationError {
+    pub class_hash: Felt,
+    /// `None` for the classes of the pending block.
+    pub block_n: Option<u64>,
+    pub source: anyhow::Error,
 }
 
+fn block_name(block_n: &Option<u64>) -> String {
+    block_n.map_or_else(|| "the pending block".into(), |block_n| format!("block {block_n}"))
+}
+
+/// Compiles the class, a panic of the compiler is returned as an error instead of being reported as a crash.
+fn compile_isolated(contract_class: &starknet_core::types::ContractClass) -> anyhow::Result<CompiledClass> {
+    error_reporting::catch_panic(AssertUnwindSafe(|| contract_class.compile())).unwrap_or_else(|panic| {
+        Err(anyhow::anyhow!("The compiler panicked: {}", error_reporting::panic_message(&*panic)))
+    })
+}
+
+/// Converts the classes of a block, they are compiled in parallel on the rayon pool. The classes are only stored along
+/// with their block: when one of them fails, none is returned.
+///
+/// NB: This functions needs to run on the rayon thread pool
 pub fn convert_and_verify_class(
     classes: Vec<DbClassUpdate>,
     block_n: Option<u64>,
+    block_metrics: Option<&BlockMetrics>,
 ) -> Result<Vec<ConvertedClass>, ConvertClassError> {
     classes
         .into_par_iter()
@@ -218,8 +368,15 @@ pub fn convert_and_verify_class(
             //     // return Err(ConvertClassError::MismatchedClassHash { expected, got: class_hash });
             // }
 
-            let compiled_class =
-                contract_class.compile().map_err(|e| ConvertClassError::CompilationClassError(e.to_string()))?;
+            let started = Instant::now();
+            let compiled_class = compile_isolated(&contract_class).map_err(|source| ClassCompilationError {
+                class_hash,
+                block_n,
+                source,
+            })?;
+            if let Some(block_metrics) = block_metrics {
+                block_metrics.l2_class_compilation_time.observe(started.elapsed().as_secs_f64());
+            }
 
             let class_info =
                 ClassInfo { contract_class: contract_class.into(), block_number: block_n, compiled_class_hash };
@@ -228,3 +385,250 @@ pub fn convert_and_verify_class(
         })
         .collect::<Result<Vec<_>, _>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use dp_receipt::{ExecutionResult, FeePayment, InvokeTransactionReceipt, PriceUnit};
+    use starknet_types_core::hash::{Pedersen, Poseidon};
+
+    use super::*;
+    use crate::commitments::compute_root;
+
+    fn receipts(n_receipts: u64, events_per_receipt: u64) -> Vec<TransactionReceipt> {
+        (0..n_receipts)
+            .map(|i| {
+                TransactionReceipt::Invoke(InvokeTransactionReceipt {
+                    transaction_hash: Felt::from(i),
+                    actual_fee: FeePayment { amount: Felt::ONE, unit: PriceUnit::Fri },
+                    messages_sent: vec![],
+                    events: (0..events_per_receipt)
+                        .map(|j| Event {
+                            from_address: Felt::from(j),
+                            keys: vec![Felt::from(i), Felt::from(j)],
+                            data: vec![Felt::from(i * j); 3],
+                        })
+                        .collect(),
+                    execution_resources: Default::default(),
+                    execution_result: ExecutionResult::Succeeded,
+                })
+            })
+            .collect()
+    }
+
+    /// Event commitment computed from owned copies of the events, the way it was done before they were borrowed.
+    fn reference_event_commitment(receipts: &[TransactionReceipt], starknet_version: StarknetVersion) -> Felt {
+        let events: Vec<(Felt, Event)> = receipts
+            .iter()
+            .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event.clone())))
+            .collect();
+        if events.is_empty() {
+            return Felt::ZERO;
+        }
+        if starknet_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
+            let events_hash = events.iter().map(|(_, event)| event.compute_hash_pedersen()).collect::<Vec<_>>();
+            compute_root::<Pedersen>("event", events_hash.into_par_iter()).unwrap()
+        } else {
+            let events_hash = events.iter().map(|(hash, event)| event.compute_hash_poseidon(hash)).collect::<Vec<_>>();
+            compute_root::<Poseidon>("event", events_hash.into_par_iter()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_event_commitment_unchanged() {
+        let block_inner = DeoxysBlockInner::new(vec![], receipts(20, 7));
+        for starknet_version in [StarknetVersion::STARKNET_VERSION_0_13_1, StarknetVersion::STARKNET_VERSION_0_13_2] {
+            let commitments =
+                compute_commitments_for_block(&block_inner, &StateDiff::default(), starknet_version, MAIN_CHAIN_ID, 0)
+                    .unwrap();
+            assert_eq!(commitments.event_count, 140);
+            assert_eq!(
+                commitments.event_commitment,
+                reference_event_commitment(&block_inner.receipts, starknet_version)
+            );
+        }
+    }
+
+    #[test]
+    fn test_event_commitment_no_events() {
+        let block_inner = DeoxysBlockInner::new(vec![], receipts(3, 0));
+        let commitments = compute_commitments_for_block(
+            &block_inner,
+            &StateDiff::default(),
+            StarknetVersion::STARKNET_VERSION_0_13_2,
+            MAIN_CHAIN_ID,
+            0,
+        )
+        .unwrap();
+        assert_eq!(commitments.event_count, 0);
+        assert_eq!(commitments.event_commitment, Felt::ZERO);
+    }
+
+    fn empty_state_diff() -> starknet_core::types::StateDiff {
+        starknet_core::types::StateDiff {
+            storage_diffs: vec![],
+            deprecated_declared_classes: vec![],
+            declared_classes: vec![],
+            deployed_contracts: vec![],
+            replaced_classes: vec![],
+            nonces: vec![],
+        }
+    }
+
+    /// An empty block 12 at `starknet_version`, as served by the feeder gateway. Its block hash is zero.
+    fn block_json(starknet_version: &str) -> serde_json::Value {
+        serde_json::json!({
+            "block_hash": "0x0",
+            "block_number": 12,
+            "parent_block_hash": "0x1233",
+            "timestamp": 1700000000,
+            "sequencer_address": "0x1",
+            "state_root": "0x5678",
+            "status": "ACCEPTED_ON_L2",
+            "l1_da_mode": "CALLDATA",
+            "l1_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "l1_data_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x1" },
+            "transactions": [],
+            "transaction_receipts": [],
+            "starknet_version": starknet_version,
+        })
+    }
+
+    /// An empty block 12 at `starknet_version`, with its block hash.
+    fn block_at_version(starknet_version: &str) -> starknet_providers::sequencer::models::Block {
+        let mut block = block_json(starknet_version);
+        let (converted, _) =
+            convert_block(serde_json::from_value(block.clone()).unwrap(), empty_state_diff(), MAIN_CHAIN_ID).unwrap();
+        block["block_hash"] = format!("{:#x}", converted.info.header.compute_hash(MAIN_CHAIN_ID)).into();
+        serde_json::from_value(block).unwrap()
+    }
+
+    #[test]
+    fn test_unsupported_protocol_version() {
+        let (block, _) =
+            convert_and_verify_block(block_at_version("0.13.2.1"), empty_state_diff(), MAIN_CHAIN_ID, false).unwrap();
+        assert_eq!(block.info.header.protocol_version, StarknetVersion::new(0, 13, 2, 1));
+
+        let future_version = StarknetVersion::new(0, 14, 0, 0);
+        let res = convert_and_verify_block(block_at_version("0.14.0"), empty_state_diff(), MAIN_CHAIN_ID, false);
+        assert!(matches!(res, Err(L2SyncError::UnsupportedProtocolVersion(version, 12)) if version == future_version));
+
+        // The block is converted as a block of the latest supported version.
+        let (block, _) =
+            convert_and_verify_block(block_at_version("0.14.0"), empty_state_diff(), MAIN_CHAIN_ID, true).unwrap();
+        assert_eq!(block.info.header.protocol_version, future_version);
+        assert!(block.info.verification.contains(BlockVerification::VERIFIED_HASH));
+
+        // Its hash may not be computed the way this version does, the block is imported unverified.
+        let mut diverging = block_at_version("0.14.0");
+        diverging.block_hash = Some(Felt::ONE);
+        let (block, _) = convert_and_verify_block(diverging, empty_state_diff(), MAIN_CHAIN_ID, true).unwrap();
+        assert_eq!(block.info.verification, BlockVerification::NONE);
+    }
+
+    #[test]
+    fn test_block_hash_mismatch_allowed() {
+        let mut block = block_json("0.13.2");
+        block["block_hash"] = "0x1".into();
+        let res = convert_and_verify_block(
+            serde_json::from_value(block.clone()).unwrap(),
+            empty_state_diff(),
+            MAIN_CHAIN_ID,
+            false,
+        );
+        assert!(matches!(res, Err(L2SyncError::MismatchedBlockHash(12))));
+
+        // Blocks 1466..=2242 of mainnet are imported unverified.
+        block["block_number"] = 1466.into();
+        let (block, _) =
+            convert_and_verify_block(serde_json::from_value(block).unwrap(), empty_state_diff(), MAIN_CHAIN_ID, false)
+                .unwrap();
+        assert_eq!(block.info.verification, BlockVerification::NONE);
+        assert!(block_hash_mismatch_allowed(2242, MAIN_CHAIN_ID));
+        assert!(!block_hash_mismatch_allowed(2243, MAIN_CHAIN_ID));
+        assert!(!block_hash_mismatch_allowed(1466, dp_transactions::TEST_CHAIN_ID));
+    }
+
+    /// Block 12 with an invoke transaction of each of `versions`, the hash of transaction `i` is `i`. The receipt of
+    /// transaction `i` is the one of transaction `receipt_hashes[i]`.
+    fn block_with_invokes(versions: &[&str], receipt_hashes: &[u64]) -> starknet_providers::sequencer::models::Block {
+        let mut block = block_json("0.13.2");
+        block["transactions"] = versions
+            .iter()
+            .enumerate()
+            .map(|(i, version)| {
+                serde_json::json!({
+                    "type": "INVOKE_FUNCTION",
+                    "transaction_hash": format!("{i:#x}"),
+                    "sender_address": "0x456",
+                    "calldata": [],
+                    "signature": [],
+                    "nonce": "0x0",
+                    "max_fee": "0x0",
+                    "version": version,
+                })
+            })
+            .collect();
+        block["transaction_receipts"] = receipt_hashes
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| {
+                serde_json::json!({
+                    "transaction_hash": format!("{hash:#x}"),
+                    "transaction_index": i,
+                    "l2_to_l1_messages": [],
+                    "events": [],
+                    "actual_fee": "0x0",
+                    "execution_status": "SUCCEEDED",
+                })
+            })
+            .collect();
+        serde_json::from_value(block).unwrap()
+    }
+
+    #[test]
+    fn test_unknown_transaction_version_is_reported() {
+        // The first transaction is converted, the second one is of a version this node does not know about.
+        let block = block_with_invokes(&["0x1", "0x4"], &[0, 1]);
+        let Err(L2SyncError::TransactionConversion(err)) = convert_block(block, empty_state_diff(), MAIN_CHAIN_ID)
+        else {
+            panic!("The block was converted")
+        };
+        assert_eq!(
+            (err.block_n, err.tx_index, err.tx_type, err.version),
+            (Some(12), 1, "INVOKE_FUNCTION", Felt::from(4))
+        );
+        assert_eq!(err.source, TransactionConversionCause::Transaction(TransactionTypeError::InvalidVersion));
+        assert_eq!(
+            err.to_string(),
+            "Failed to convert transaction 1 of block 12 (INVOKE_FUNCTION transaction of version 0x4): Invalid version"
+        );
+
+        // Pending blocks have no block number.
+        let block = block_with_invokes(&["0x4"], &[0]);
+        let Err(L2SyncError::TransactionConversion(err)) = convert_pending(block, empty_state_diff(), MAIN_CHAIN_ID)
+        else {
+            panic!("The pending block was converted")
+        };
+        assert_eq!((err.block_n, err.tx_index), (None, 0));
+    }
+
+    #[test]
+    fn test_receipts_must_match_their_transactions() {
+        let block = block_with_invokes(&["0x1", "0x1"], &[1, 0]);
+        let Err(L2SyncError::TransactionConversion(err)) = convert_block(block, empty_state_diff(), MAIN_CHAIN_ID)
+        else {
+            panic!("The block was converted")
+        };
+        assert_eq!((err.tx_index, err.source), (0, TransactionConversionCause::MismatchedReceipt(Felt::ONE)));
+
+        let block = block_with_invokes(&["0x1", "0x1"], &[0]);
+        let Err(L2SyncError::TransactionConversion(err)) = convert_block(block, empty_state_diff(), MAIN_CHAIN_ID)
+        else {
+            panic!("The block was converted")
+        };
+        assert_eq!((err.tx_index, err.source), (1, TransactionConversionCause::MissingReceipt));
+
+        let block = block_with_invokes(&["0x1"], &[0, 1]);
+        assert!(matches!(convert_block(block, empty_state_diff(), MAIN_CHAIN_ID), Err(L2SyncError::BlockFormat(_))));
+    }
+}
@@ -0,0 +1,168 @@
+//! Checks of the blocks that run after they were stored. A block stored before all of its checks passed is served as
+//! unverified, [`dp_block::BlockVerification`] tells which checks are still missing.
+
+use std::sync::Arc;
+
+use dc_db::DeoxysBackend;
+use dp_block::{BlockId, BlockVerification, DeoxysBlock};
+use dp_convert::ToFelt;
+use dp_utils::compute_pool::{spawn_compute, ComputePool};
+use dp_utils::error_reporting::{self, Severity};
+use dp_utils::wait_or_graceful_shutdown;
+
+use crate::import::verify_block_hash;
+use crate::metrics::block_metrics::BlockMetrics;
+
+/// The progress of [`delayed_verification_task`] is saved every this many blocks.
+const SAVE_PROGRESS_EVERY: u64 = 1000;
+
+/// Computes the commitments and the hash of the stored block `block_n` again, and adds them to the verification of
+/// the block. The flags are counted in the metrics along with the update, so that each one is counted once. Returns
+/// the flags of the block, `None` when it is not stored or was reverted during its verification.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn verify_stored_block(
+    backend: &DeoxysBackend,
+    block_metrics: &BlockMetrics,
+    block_n: u64,
+) -> anyhow::Result<Option<BlockVerification>> {
+    let Some(block) = backend.get_block(&BlockId::Number(block_n))? else { return Ok(None) };
+    let Some(state_diff) = backend.get_block_state_diff(&BlockId::Number(block_n))? else { return Ok(None) };
+    let block = DeoxysBlock::try_from(block)?;
+
+    let chain_id = backend.chain_config().chain_id.clone().to_felt();
+    let verification = verify_block_hash(&block, &state_diff, chain_id)?;
+
+    Ok(backend.add_block_verification(block_n, verification, |added| {
+        block_metrics.l2_delayed_verifications.inc_by(added.bits().count_ones().into())
+    })?)
+}
+
+/// Verifies again the blocks stored by the versions that did not record what they verified, listed by
+/// [`DeoxysBackend::get_unverified_blocks`]. The progress is saved, the task resumes from it when the node restarts.
+/// The blocks that fail their verification are reported, and left unverified.
+pub async fn delayed_verification_task(
+    backend: Arc<DeoxysBackend>,
+    block_metrics: BlockMetrics,
+    compute_pool: ComputePool,
+) -> anyhow::Result<()> {
+    let Some(blocks) = backend.get_unverified_blocks()? else { return Ok(()) };
+    // The blocks past the tip were reverted, the sync verifies them when it imports them again.
+    let end = backend.get_latest_block_n()?.map_or(0, |tip| blocks.end.min(tip + 1));
+    log::info!("🔍 Verifying the blocks {} to {} stored by an older version", blocks.start, end.saturating_sub(1));
+
+    for block_n in blocks.start..end {
+        let (backend_, block_metrics_) = (Arc::clone(&backend), block_metrics.clone());
+        let verify = spawn_compute(&compute_pool, move || verify_stored_block(&backend_, &block_metrics_, block_n));
+        let Some(res) = wait_or_graceful_shutdown(verify).await else {
+            backend.set_unverified_blocks(block_n..blocks.end)?;
+            return Ok(());
+        };
+        if let Err(err) = res {
+            error_reporting::report(
+                Severity::Critical,
+                "sync.verify",
+                format_args!("Block {block_n} stored by an older version failed its verification: {err:#}"),
+            );
+        }
+        if (block_n + 1) % SAVE_PROGRESS_EVERY == 0 {
+            backend.set_unverified_blocks(block_n + 1..blocks.end)?;
+        }
+    }
+    backend.set_unverified_blocks(0..0)?;
+    log::info!("✅ Verified the blocks stored by an older version");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dc_db::testing::temp_database;
+    use dc_metrics::MetricsService;
+    use dp_block::{DeoxysBlockInfo, DeoxysBlockInner, Header};
+    use dp_state_update::StateDiff;
+
+    use super::*;
+    use crate::convert::compute_commitments_for_block;
+    use crate::import::ImportError;
+
+    /// Block 0, stored without any of its checks.
+    fn unverified_block(backend: &DeoxysBackend) -> DeoxysBlock {
+        let chain_id = backend.chain_config().chain_id.clone().to_felt();
+        let inner = DeoxysBlockInner::new(vec![], vec![]);
+        let commitments =
+            compute_commitments_for_block(&inner, &StateDiff::default(), Default::default(), chain_id, 0).unwrap();
+        let header = Header {
+            block_timestamp: 1700000000,
+            transaction_commitment: commitments.transaction_commitment,
+            event_commitment: commitments.event_commitment,
+            state_diff_commitment: commitments.state_diff_commitment,
+            receipt_commitment: commitments.receipt_commitment,
+            ..Default::default()
+        };
+        let block_hash = header.compute_hash(chain_id);
+        DeoxysBlock::new(DeoxysBlockInfo::new(header, vec![], block_hash), inner)
+    }
+
+    #[tokio::test]
+    async fn test_delayed_verification() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let block_metrics =
+            BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        backend.store_block(unverified_block(backend).into(), StateDiff::default(), vec![]).unwrap();
+        let verification = || backend.get_block_verification(&BlockId::Number(0)).unwrap().unwrap();
+        assert_eq!(verification(), BlockVerification::NONE);
+
+        let expected = BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS;
+        assert_eq!(verify_stored_block(backend, &block_metrics, 0).unwrap(), Some(expected));
+        assert_eq!(verification(), expected);
+        assert_eq!(block_metrics.l2_delayed_verifications.get(), 2);
+
+        // The flags are only counted the first time.
+        assert_eq!(verify_stored_block(backend, &block_metrics, 0).unwrap(), Some(expected));
+        assert_eq!(block_metrics.l2_delayed_verifications.get(), 2);
+
+        assert_eq!(verify_stored_block(backend, &block_metrics, 1).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delayed_verification_task() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let block_metrics =
+            BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        let compute_pool = ComputePool::new("test", 1).unwrap();
+
+        // Nothing to verify.
+        delayed_verification_task(Arc::clone(&backend), block_metrics.clone(), compute_pool.clone()).await.unwrap();
+        assert_eq!(block_metrics.l2_delayed_verifications.get(), 0);
+
+        backend.store_block(unverified_block(&backend).into(), StateDiff::default(), vec![]).unwrap();
+        backend.set_unverified_blocks(0..1).unwrap();
+        delayed_verification_task(Arc::clone(&backend), block_metrics.clone(), compute_pool).await.unwrap();
+        assert_eq!(
+            backend.get_block_verification(&BlockId::Number(0)).unwrap(),
+            Some(BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS)
+        );
+        assert_eq!(block_metrics.l2_delayed_verifications.get(), 2);
+        assert_eq!(backend.get_unverified_blocks().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delayed_verification_of_a_diverging_block() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        let block_metrics =
+            BlockMetrics::register(&MetricsService::new(true, false, 9615).unwrap().registry()).unwrap();
+        let mut block = unverified_block(backend);
+        block.info.header.block_timestamp += 1;
+        backend.store_block(block.into(), StateDiff::default(), vec![]).unwrap();
+
+        let err = verify_stored_block(backend, &block_metrics, 0).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ImportError::Divergence { block_n: 0, .. })));
+        assert_eq!(backend.get_block_verification(&BlockId::Number(0)).unwrap(), Some(BlockVerification::NONE));
+        assert_eq!(block_metrics.l2_delayed_verifications.get(), 0);
+    }
+}
@@ -0,0 +1,560 @@
+//! Verification of the global tries against the chain, after a crash or a migration of the database.
+//!
+//! The state before the first verified block is streamed from the stored history of the contracts and classes into
+//! tries kept in memory, the state diffs of the verified blocks are replayed on top of it, and the state root of every
+//! block is compared with the one of its header. The storage of a contract is only loaded in memory once a replayed
+//! block changes it. The global tries of the database only hold the state at the tip: they are compared with the tries
+//! in memory once the replay reaches it. When they diverge while the state diffs do not, the global tries can be rebuilt
+//! from the state diffs.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use bitvec::order::Msb0;
+use bitvec::vec::BitVec;
+use bitvec::view::AsBits;
+use bonsai_trie::databases::HashMapDb;
+use bonsai_trie::id::BasicId;
+use bonsai_trie::{BonsaiStorage, BonsaiStorageConfig};
+use dc_db::{bonsai_identifier, DeoxysBackend, DeoxysStorageError, MAX_REVERTIBLE_BLOCKS};
+use dp_block::BlockId;
+use dp_class::ContractClass;
+use dp_state_update::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff,
+    StorageEntry,
+};
+use rayon::prelude::*;
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+
+use crate::commitments::{
+    calculate_state_root, class_leaf_hash, contract_state_hash, global_tries_root, update_tries_and_compute_state_root,
+    MemoryTrie,
+};
+
+/// The progress is logged at most this often.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(thiserror::Error, Debug)]
+pub enum VerifyTriesError {
+    #[error("Block {block_n} is not in the database")]
+    MissingBlock { block_n: u64 },
+    #[error("Block {block_n} has state root {expected:#x}, but the state diffs up to it lead to {got:#x}")]
+    MismatchedStateRoot { block_n: u64, expected: Felt, got: Felt },
+    #[error("Failed to replay block {block_n} in memory: {message}")]
+    MemoryTrie { block_n: u64, message: String },
+    #[error("Storage error: {0:#}")]
+    Storage(#[from] DeoxysStorageError),
+}
+
+/// Outcome of [`verify_tries`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriesVerification {
+    /// Every verified block has the state root of its header, and the global tries hold the state at the tip when
+    /// the verification reaches it.
+    Valid,
+    /// The state diffs up to `block_n` do not lead to the state root of its header. The blocks of the database are
+    /// inconsistent from there: rebuilding the global tries from them does not help.
+    DivergentBlock { block_n: u64, expected: Felt, got: Felt },
+    /// The global tries do not hold the state at the tip, `tip`, although the state diffs lead to it. They can be
+    /// rebuilt with [`rebuild_tries`].
+    CorruptedTries { tip: u64, expected: Felt, got: Felt },
+}
+
+/// Loads the state before `from_block` from the stored history in memory, replays the state diffs of the blocks
+/// `from_block..=to_block` on top of it, and compares their state roots with their headers. The global tries are
+/// compared with the replay when `to_block` is the tip of the database. Stops at the first divergent block, which is
+/// the block before `from_block` when the stored state does not lead to its state root.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn verify_tries(
+    backend: &DeoxysBackend,
+    from_block: u64,
+    to_block: u64,
+) -> Result<TriesVerification, VerifyTriesError> {
+    let mut tries = match from_block.checked_sub(1) {
+        Some(base) => {
+            let tries = ReplayTries::load(backend, base)?;
+            let (_, expected) = block_state(backend, base)?;
+            let got = tries.state_root().map_err(|message| VerifyTriesError::MemoryTrie { block_n: base, message })?;
+            if got != expected {
+                return Ok(TriesVerification::DivergentBlock { block_n: base, expected, got });
+            }
+            tries
+        }
+        None => ReplayTries::new().map_err(|message| VerifyTriesError::MemoryTrie { block_n: 0, message })?,
+    };
+    let mut progress = Progress::new("Verified", from_block, to_block);
+
+    for block_n in from_block..=to_block {
+        let (state_diff, expected) = block_state(backend, block_n)?;
+        tries.load_storage(backend, &state_diff)?;
+        let got =
+            tries.apply(&state_diff, block_n).map_err(|message| VerifyTriesError::MemoryTrie { block_n, message })?;
+        if got != expected {
+            return Ok(TriesVerification::DivergentBlock { block_n, expected, got });
+        }
+        progress.block_done(block_n);
+    }
+
+    if backend.get_latest_block_n()? != Some(to_block) {
+        log::info!("🌳 The global tries are only verified when the verification goes up to the tip");
+        return Ok(TriesVerification::Valid);
+    }
+    let got = global_tries_root(backend)?;
+    let expected = tries.state_root().map_err(|message| VerifyTriesError::MemoryTrie { block_n: to_block, message })?;
+    if got != expected {
+        return Ok(TriesVerification::CorruptedTries { tip: to_block, expected, got });
+    }
+    Ok(TriesVerification::Valid)
+}
+
+/// Builds the global tries again from block `from_block`, the first block they diverge at: they are reverted to the
+/// block before it through their logs, then the state diffs from `from_block` up to the tip are applied. When the
+/// reverted tries do not have the state root of that block, or it is too deep to be reverted, they are deleted and
+/// built again from the genesis block. Every block is verified against its header on the way: the rebuild stops at
+/// the first block that diverges, whose state diff remains in the global tries. Use [`verify_tries`] first to make sure
+/// the state diffs lead to the tip.
+///
+/// NB: This functions needs to run on the rayon thread pool
+pub fn rebuild_tries(backend: &DeoxysBackend, from_block: u64) -> Result<(), VerifyTriesError> {
+    let Some(tip) = backend.get_latest_block_n()? else { return Ok(()) };
+    let from_block = revert_tries_before(backend, from_block.min(tip), tip)?;
+    let mut progress = Progress::new("Rebuilt the global tries up to", from_block, tip);
+
+    for block_n in from_block..=tip {
+        let (state_diff, expected) = block_state(backend, block_n)?;
+        let got = update_tries_and_compute_state_root(backend, &state_diff, block_n);
+        if got != expected {
+            return Err(VerifyTriesError::MismatchedStateRoot { block_n, expected, got });
+        }
+        progress.block_done(block_n);
+    }
+    Ok(())
+}
+
+/// Reverts the global tries to the block before `from_block`, and returns the first block to apply on top of them.
+/// They are cleared instead when they cannot be reverted to a state that matches the header of that block.
+fn revert_tries_before(backend: &DeoxysBackend, from_block: u64, tip: u64) -> Result<u64, VerifyTriesError> {
+    if let Some(base) = from_block.checked_sub(1).filter(|base| tip - base <= MAX_REVERTIBLE_BLOCKS) {
+        let reverted = backend.revert_tries_to(Some(base), tip).and_then(|()| global_tries_root(backend));
+        let (_, expected) = block_state(backend, base)?;
+        match reverted {
+            Ok(root) if root == expected => {
+                log::info!("🌳 Reverted the global tries to block {base}");
+                return Ok(from_block);
+            }
+            Ok(root) => log::warn!(
+                "⚠️  The global tries reverted to block {base} have state root {root:#x} instead of {expected:#x}, \
+                 rebuilding them from the genesis block"
+            ),
+            Err(err) => log::warn!(
+                "⚠️  The global tries could not be reverted to block {base}: {err:#}, rebuilding them from the genesis \
+                 block"
+            ),
+        }
+    }
+    backend.clear_tries()?;
+    Ok(0)
+}
+
+/// The state diff of a block, and the state root of its header.
+fn block_state(backend: &DeoxysBackend, block_n: u64) -> Result<(StateDiff, Felt), VerifyTriesError> {
+    let id = BlockId::Number(block_n);
+    let state_diff = backend.get_block_state_diff(&id)?.ok_or(VerifyTriesError::MissingBlock { block_n })?;
+    let info = backend.get_block_info(&id)?;
+    let info = info.as_ref().and_then(|info| info.as_nonpending()).ok_or(VerifyTriesError::MissingBlock { block_n })?;
+    Ok((state_diff, info.header.global_state_root))
+}
+
+struct Progress {
+    action: &'static str,
+    first_block: u64,
+    last_block: u64,
+    started: Instant,
+    logged: Instant,
+}
+
+impl Progress {
+    fn new(action: &'static str, first_block: u64, last_block: u64) -> Self {
+        let now = Instant::now();
+        Self { action, first_block, last_block, started: now, logged: now }
+    }
+
+    fn block_done(&mut self, block_n: u64) {
+        if self.logged.elapsed() >= PROGRESS_INTERVAL || block_n == self.last_block {
+            self.logged = Instant::now();
+            let blocks = (block_n + 1).saturating_sub(self.first_block);
+            let blocks_per_sec = blocks as f64 / self.started.elapsed().as_secs_f64().max(0.001);
+            log::info!("🌳 {} block {block_n}/{} ({blocks_per_sec:.1} blocks/s)", self.action, self.last_block);
+        }
+    }
+}
+
+fn trie_key(felt: &Felt) -> BitVec<u8, Msb0> {
+    felt.to_bytes_be().as_bits()[5..].to_owned()
+}
+
+fn replay_trie<H: StarkHash + Send + Sync>() -> Result<MemoryTrie<H>, String> {
+    // Nothing is ever reverted, the logs would only take memory.
+    let config =
+        BonsaiStorageConfig { max_saved_trie_logs: Some(0), max_saved_snapshots: Some(0), snapshot_interval: u64::MAX };
+    BonsaiStorage::new(HashMapDb::default(), config).map_err(|err| err.to_string())
+}
+
+/// The global tries, in memory.
+struct ReplayTries {
+    contract_storage: MemoryTrie<Pedersen>,
+    contract: MemoryTrie<Pedersen>,
+    class: MemoryTrie<Poseidon>,
+    /// The class hash and nonce of the contracts, for the leaves of the contracts whose storage changes.
+    contracts: HashMap<Felt, (Felt, Felt)>,
+    /// The block whose state was loaded from the stored history, `None` when the replay starts from the genesis block.
+    base: Option<u64>,
+    /// The contracts whose storage at `base` is in `contract_storage`.
+    loaded_storage: HashSet<Felt>,
+}
+
+impl ReplayTries {
+    fn new() -> Result<Self, String> {
+        Ok(Self {
+            contract_storage: replay_trie()?,
+            contract: replay_trie()?,
+            class: replay_trie()?,
+            contracts: HashMap::new(),
+            base: None,
+            loaded_storage: HashSet::new(),
+        })
+    }
+
+    /// The tries of the state after block `base`, streamed from the stored history. The storage of the contracts is read
+    /// one contract at a time, only the leaves of the contracts are kept.
+    fn load(backend: &DeoxysBackend, base: u64) -> Result<Self, VerifyTriesError> {
+        let memory_err = |message| VerifyTriesError::MemoryTrie { block_n: base, message };
+        let mut tries = Self::new().map_err(memory_err)?;
+        tries.base = Some(base);
+
+        for res in backend.iter_contract_class_hashes_at(base)? {
+            let (address, class_hash) = res?;
+            tries.contracts.entry(address).or_default().0 = class_hash;
+        }
+        for res in backend.iter_contract_nonces_at(base)? {
+            let (address, nonce) = res?;
+            tries.contracts.entry(address).or_default().1 = nonce;
+        }
+
+        let mut storage_roots = HashMap::new();
+        let mut storage: Option<(Felt, Vec<(Felt, Felt)>)> = None;
+        for res in backend.iter_contract_storage_at(None, base)? {
+            let (address, key, value) = res?;
+            if storage.as_ref().is_some_and(|(current, _)| *current != address) {
+                let (current, entries) = storage.take().expect("Checked above");
+                storage_roots.insert(current, storage_root(&current, &entries).map_err(memory_err)?);
+            }
+            storage.get_or_insert_with(|| (address, vec![])).1.push((key, value));
+        }
+        if let Some((current, entries)) = storage {
+            storage_roots.insert(current, storage_root(&current, &entries).map_err(memory_err)?);
+        }
+
+        let addresses: HashSet<Felt> = tries.contracts.keys().chain(storage_roots.keys()).copied().collect();
+        let leaves: Vec<_> = addresses
+            .into_par_iter()
+            .map(|address| {
+                let (class_hash, nonce) = tries.contracts.get(&address).copied().unwrap_or_default();
+                let storage_root = storage_roots.get(&address).copied().unwrap_or_default();
+                (address, contract_state_hash(&class_hash, &storage_root, &nonce))
+            })
+            .collect();
+        for (address, leaf_hash) in leaves {
+            tries
+                .contract
+                .insert(bonsai_identifier::CONTRACT, &trie_key(&address), &leaf_hash)
+                .map_err(|err| memory_err(err.to_string()))?;
+        }
+        tries.contract.commit(BasicId::new(base)).map_err(|err| memory_err(err.to_string()))?;
+
+        for res in backend.iter_classes(None)? {
+            let (class_hash, info) = res?;
+            // The legacy classes are not in the class trie.
+            if info.block_number.is_some_and(|block_n| block_n <= base)
+                && matches!(info.contract_class, ContractClass::Sierra(_))
+            {
+                let leaf_hash = class_leaf_hash(&info.compiled_class_hash);
+                tries
+                    .class
+                    .insert(bonsai_identifier::CLASS, &trie_key(&class_hash), &leaf_hash)
+                    .map_err(|err| memory_err(err.to_string()))?;
+            }
+        }
+        tries.class.commit(BasicId::new(base)).map_err(|err| memory_err(err.to_string()))?;
+        Ok(tries)
+    }
+
+    /// Loads the storage at the base block of the contracts changed by a block, before it is applied.
+    fn load_storage(&mut self, backend: &DeoxysBackend, state_diff: &StateDiff) -> Result<(), VerifyTriesError> {
+        let Some(base) = self.base else { return Ok(()) };
+        for address in updated_contracts(state_diff) {
+            if !self.loaded_storage.insert(address) {
+                continue;
+            }
+            for res in backend.iter_contract_storage_at(Some(&address), base)? {
+                let (_, key, value) = res?;
+                self.contract_storage
+                    .insert(&address.to_bytes_be(), &trie_key(&key), &value)
+                    .map_err(|err| VerifyTriesError::MemoryTrie { block_n: base, message: err.to_string() })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the state diff of a block, and returns the state root after it.
+    fn apply(&mut self, state_diff: &StateDiff, block_n: u64) -> Result<Felt, String> {
+        let Self { contract_storage, contract, class, contracts, .. } = self;
+        let (contract_root, class_root) = rayon::join(
+            || apply_contracts(contract_storage, contract, contracts, state_diff, block_n),
+            || apply_classes(class, state_diff.declared_classes(), block_n),
+        );
+        Ok(calculate_state_root(contract_root?, class_root?))
+    }
+
+    fn state_root(&self) -> Result<Felt, String> {
+        let contract_root = self.contract.root_hash(bonsai_identifier::CONTRACT).map_err(|err| err.to_string())?;
+        let class_root = self.class.root_hash(bonsai_identifier::CLASS).map_err(|err| err.to_string())?;
+        Ok(calculate_state_root(contract_root, class_root))
+    }
+}
+
+/// The root of the storage trie of a contract, from all of its storage.
+fn storage_root(address: &Felt, entries: &[(Felt, Felt)]) -> Result<Felt, String> {
+    let identifier = address.to_bytes_be();
+    let mut trie = replay_trie::<Pedersen>()?;
+    for (key, value) in entries {
+        trie.insert(&identifier, &trie_key(key), value).map_err(|err| err.to_string())?;
+    }
+    trie.commit(BasicId::new(0)).map_err(|err| err.to_string())?;
+    trie.root_hash(&identifier).map_err(|err| err.to_string())
+}
+
+/// The contracts whose leaf changes with a block, in address order.
+fn updated_contracts(state_diff: &StateDiff) -> Vec<Felt> {
+    let mut updated: Vec<Felt> = state_diff
+        .storage_diffs()
+        .iter()
+        .map(|diff| diff.address)
+        .chain(state_diff.deployed_contracts().iter().map(|item| item.address))
+        .chain(state_diff.replaced_classes().iter().map(|item| item.contract_address))
+        .chain(state_diff.nonces().iter().map(|item| item.contract_address))
+        .collect();
+    updated.sort_unstable();
+    updated.dedup();
+    updated
+}
+
+fn apply_contracts(
+    contract_storage: &mut MemoryTrie<Pedersen>,
+    contract: &mut MemoryTrie<Pedersen>,
+    contracts: &mut HashMap<Felt, (Felt, Felt)>,
+    state_diff: &StateDiff,
+    block_n: u64,
+) -> Result<Felt, String> {
+    let (storage_diffs, deployed_contracts, replaced_classes, nonces) = (
+        state_diff.storage_diffs(),
+        state_diff.deployed_contracts(),
+        state_diff.replaced_classes(),
+        state_diff.nonces(),
+    );
+
+    for ContractStorageDiffItem { address, storage_entries } in storage_diffs {
+        for StorageEntry { key, value } in storage_entries {
+            contract_storage.insert(&address.to_bytes_be(), &trie_key(key), value).map_err(|err| err.to_string())?;
+        }
+    }
+    contract_storage.commit(BasicId::new(block_n)).map_err(|err| err.to_string())?;
+
+    for DeployedContractItem { address, class_hash } in deployed_contracts {
+        contracts.entry(*address).or_default().0 = *class_hash;
+    }
+    for ReplacedClassItem { contract_address, class_hash } in replaced_classes {
+        contracts.entry(*contract_address).or_default().0 = *class_hash;
+    }
+    for NonceUpdate { contract_address, nonce } in nonces {
+        contracts.entry(*contract_address).or_default().1 = *nonce;
+    }
+
+    let leaves = updated_contracts(state_diff)
+        .into_iter()
+        .map(|address| {
+            let storage_root = contract_storage.root_hash(&address.to_bytes_be()).map_err(|err| err.to_string())?;
+            let (class_hash, nonce) = contracts.get(&address).copied().unwrap_or_default();
+            Ok((address, class_hash, storage_root, nonce))
+        })
+        .collect::<Result<Vec<_>, String>>()?
+        .into_par_iter()
+        .map(|(address, class_hash, storage_root, nonce)| {
+            (address, contract_state_hash(&class_hash, &storage_root, &nonce))
+        })
+        .collect::<Vec<_>>();
+
+    for (address, leaf_hash) in leaves {
+        contract.insert(bonsai_identifier::CONTRACT, &trie_key(&address), &leaf_hash).map_err(|err| err.to_string())?;
+    }
+    contract.commit(BasicId::new(block_n)).map_err(|err| err.to_string())?;
+    contract.root_hash(bonsai_identifier::CONTRACT).map_err(|err| err.to_string())
+}
+
+fn apply_classes(
+    class: &mut MemoryTrie<Poseidon>,
+    declared_classes: &[DeclaredClassItem],
+    block_n: u64,
+) -> Result<Felt, String> {
+    let leaves: Vec<_> = declared_classes
+        .par_iter()
+        .map(|DeclaredClassItem { class_hash, compiled_class_hash }| {
+            (*class_hash, class_leaf_hash(compiled_class_hash))
+        })
+        .collect();
+    for (class_hash, leaf_hash) in leaves {
+        class.insert(bonsai_identifier::CLASS, &trie_key(&class_hash), &leaf_hash).map_err(|err| err.to_string())?;
+    }
+    class.commit(BasicId::new(block_n)).map_err(|err| err.to_string())?;
+    class.root_hash(bonsai_identifier::CLASS).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use dc_db::testing::{lose_contract_trie_node_writes, temp_database};
+    use dp_block::{DeoxysBlock, DeoxysBlockInfo, DeoxysBlockInner, Header};
+
+    use super::*;
+
+    const CONTRACT: Felt = Felt::ONE;
+
+    /// Block 0 deploys the contract, block 1 only changes its nonce and the next blocks only write to its storage: the
+    /// leaf of the contract is computed from the fields changed by earlier blocks.
+    fn state_diff(block_n: u64) -> StateDiff {
+        let storage_diffs = vec![ContractStorageDiffItem {
+            address: CONTRACT,
+            storage_entries: vec![StorageEntry { key: block_n.into(), value: (block_n + 1).into() }],
+        }];
+        match block_n {
+            0 => StateDiff::new(
+                storage_diffs,
+                vec![],
+                vec![],
+                vec![DeployedContractItem { address: CONTRACT, class_hash: Felt::from_hex_unchecked("0xc1a55") }],
+                vec![],
+                vec![],
+            ),
+            1 => StateDiff::new(
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![NonceUpdate { contract_address: CONTRACT, nonce: Felt::ONE }],
+            ),
+            _ => StateDiff::new(storage_diffs, vec![], vec![], vec![], vec![], vec![]),
+        }
+    }
+
+    fn store_block(backend: &DeoxysBackend, block_number: u64, global_state_root: Felt) {
+        let header = Header { block_number, global_state_root, ..Default::default() };
+        let info = DeoxysBlockInfo::new(header, vec![], Felt::from(block_number));
+        let block = DeoxysBlock::new(info, DeoxysBlockInner::new(vec![], vec![]));
+        backend.store_block(block.into(), state_diff(block_number), vec![]).unwrap();
+    }
+
+    /// Stores the blocks, with the state roots computed with the global tries as the sync does.
+    fn sync_blocks(backend: &DeoxysBackend, blocks: std::ops::Range<u64>) {
+        for block_n in blocks {
+            let global_state_root = update_tries_and_compute_state_root(backend, &state_diff(block_n), block_n);
+            store_block(backend, block_n, global_state_root);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_tries_are_detected_and_rebuilt() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        sync_blocks(backend, 0..3);
+        // The nodes of the contract trie written for block 3 are lost, they are still the ones of block 2.
+        lose_contract_trie_node_writes(backend, || sync_blocks(backend, 3..4));
+        let (_, root_2) = block_state(backend, 2).unwrap();
+        let (_, tip_root) = block_state(backend, 3).unwrap();
+
+        assert_eq!(
+            verify_tries(backend, 0, 3).unwrap(),
+            TriesVerification::CorruptedTries { tip: 3, expected: tip_root, got: root_2 }
+        );
+        // The state before the first verified block is read from the stored history.
+        assert_eq!(
+            verify_tries(backend, 2, 3).unwrap(),
+            TriesVerification::CorruptedTries { tip: 3, expected: tip_root, got: root_2 }
+        );
+        // The global tries are not compared below the tip.
+        assert_eq!(verify_tries(backend, 0, 2).unwrap(), TriesVerification::Valid);
+        assert_eq!(verify_tries(backend, 1, 2).unwrap(), TriesVerification::Valid);
+
+        rebuild_tries(backend, 3).unwrap();
+        assert_eq!(global_tries_root(backend).unwrap(), tip_root);
+        assert_eq!(verify_tries(backend, 0, 3).unwrap(), TriesVerification::Valid);
+
+        // The sync goes on with the rebuilt tries.
+        sync_blocks(backend, 4..5);
+        assert_eq!(verify_tries(backend, 2, 4).unwrap(), TriesVerification::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_tries_are_rebuilt_from_the_divergent_block() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        sync_blocks(backend, 0..3);
+        // The global tries are updated with another state diff than the one of the stored block 3.
+        let global_state_root = update_tries_and_compute_state_root(backend, &state_diff(3), 3);
+        let other_state_diff = state_diff(4);
+        backend.revert_tries_to(Some(2), 3).unwrap();
+        update_tries_and_compute_state_root(backend, &other_state_diff, 3);
+        store_block(backend, 3, global_state_root);
+
+        let TriesVerification::CorruptedTries { tip: 3, expected, .. } = verify_tries(backend, 3, 3).unwrap() else {
+            panic!("The global tries are not reported");
+        };
+        assert_eq!(expected, global_state_root);
+
+        // Only block 3 is applied again, on top of the tries reverted to block 2.
+        rebuild_tries(backend, 3).unwrap();
+        assert_eq!(global_tries_root(backend).unwrap(), global_state_root);
+        assert_eq!(verify_tries(backend, 0, 3).unwrap(), TriesVerification::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_divergent_block_is_reported() {
+        let (_temp_dir, db) = temp_database().await;
+        let backend = db.backend();
+        sync_blocks(backend, 0..3);
+        // Block 3 is stored with a state root its state diff does not lead to.
+        store_block(backend, 3, Felt::from(0xbad_u64));
+
+        let TriesVerification::DivergentBlock { block_n: 3, expected, got } = verify_tries(backend, 0, 3).unwrap()
+        else {
+            panic!("Block 3 is not reported");
+        };
+        assert_eq!(expected, Felt::from(0xbad_u64));
+        assert_ne!(got, expected);
+        assert_eq!(verify_tries(backend, 0, 2).unwrap(), TriesVerification::Valid);
+        // From the state of block 1 in the stored history.
+        assert_eq!(
+            verify_tries(backend, 2, 3).unwrap(),
+            TriesVerification::DivergentBlock { block_n: 3, expected, got }
+        );
+
+        // The blocks cannot be the source of a rebuild past the divergent one.
+        assert!(matches!(
+            rebuild_tries(backend, 0),
+            Err(VerifyTriesError::MismatchedStateRoot { block_n: 3, expected, got: rebuilt })
+                if expected == Felt::from(0xbad_u64) && rebuilt == got
+        ));
+    }
+}
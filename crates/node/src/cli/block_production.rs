@@ -1,3 +1,21 @@
+use std::time::Duration;
+
+use dc_eth::l1_gas_price::{GasPriceProviderConfig, StrkPerEth};
+use dc_eth::l1_messaging::L1MessagingConfig;
+use dc_mempool::block_production::BlockProductionConfig;
+use dp_block::chain_config::ChainConfig;
+use dp_block::header::{GasPrices, L1DataAvailabilityMode};
+use url::Url;
+
+use crate::cli::SyncParams;
+
+/// Gas prices used until the L1 fees have been sampled, or for good when there is no L1 endpoint.
+const INITIAL_GAS_PRICES: GasPrices =
+    GasPrices { eth_l1_gas_price: 100, strk_l1_gas_price: 90, eth_l1_data_gas_price: 10, strk_l1_data_gas_price: 9 };
+
+/// The gas prices are reported as stale after this many failed samples in a row.
+const GAS_PRICE_STALE_AFTER_SAMPLES: u32 = 10;
+
 /// Parameters used to config telemetry.
 #[derive(Clone, Debug, clap::Parser)]
 pub struct BlockProductionParams {
@@ -5,4 +23,91 @@ pub struct BlockProductionParams {
     /// The block production service is only enabled with the authority (sequencer) mode.
     #[arg(long, alias = "no-disabled")]
     pub block_production_disabled: bool,
+
+    /// Time between two blocks, in seconds. Defaults to the block time of the chain config.
+    #[arg(long, value_name = "SECONDS")]
+    pub block_time: Option<u64>,
+
+    /// Interval at which the pending block is updated with new mempool transactions, in milliseconds.
+    /// Defaults to the pending block update time of the chain config.
+    #[arg(long, value_name = "MILLISECONDS")]
+    pub pending_block_update_interval: Option<u64>,
+
+    /// Do not close blocks without transactions. An empty block stays open until a transaction comes in, and is
+    /// then closed at the end of the current block time.
+    #[arg(long)]
+    pub no_empty_blocks: bool,
+
+    /// Interval at which the L1 fees are sampled to compute the gas prices of the produced blocks, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value_t = 12)]
+    pub gas_price_poll_interval: u64,
+
+    /// Number of L1 fee samples the gas prices are averaged over.
+    #[arg(long, value_name = "SAMPLES", default_value_t = 10)]
+    pub gas_price_ema_window: u32,
+
+    /// Fixed price of 1 ETH in STRK, used to derive the STRK gas prices from the ETH ones.
+    /// Without it, the STRK gas prices are not updated.
+    #[arg(long, value_name = "RATE")]
+    pub strk_per_eth: Option<f64>,
+
+    /// Number of L1 blocks per `eth_getLogs` call when fetching the L1 to L2 messages sent while the node was down.
+    /// Ranges are split further when the L1 provider returns too many logs.
+    #[arg(long, value_name = "BLOCKS", default_value_t = 5000)]
+    pub l1_messages_backfill_chunk_size: u64,
+
+    /// RPC urls of other nodes the transactions accepted by the mempool are sent to, separated by commas. The nodes
+    /// sending their transactions to each other share their mempool.
+    #[arg(long, value_name = "RPC URL", value_delimiter = ',')]
+    pub tx_propagation_peers: Vec<Url>,
+}
+
+impl BlockProductionParams {
+    pub fn block_production_config(&self, chain_config: &ChainConfig) -> anyhow::Result<BlockProductionConfig> {
+        let mut config = BlockProductionConfig::from_chain_config(chain_config);
+        if let Some(block_time) = self.block_time {
+            config.block_time = Duration::from_secs(block_time);
+        }
+        if let Some(pending_block_update_interval) = self.pending_block_update_interval {
+            config.pending_update_interval = Duration::from_millis(pending_block_update_interval);
+        }
+        config.allow_empty_blocks = !self.no_empty_blocks;
+
+        anyhow::ensure!(!config.block_time.is_zero(), "The block time must not be zero");
+        anyhow::ensure!(
+            !config.pending_update_interval.is_zero() && config.pending_update_interval <= config.block_time,
+            "The pending block update interval must be between 1ms and the block time"
+        );
+        Ok(config)
+    }
+
+    pub fn gas_price_provider_config(&self) -> anyhow::Result<GasPriceProviderConfig> {
+        anyhow::ensure!(self.gas_price_poll_interval > 0, "The gas price poll interval must not be zero");
+        anyhow::ensure!(self.gas_price_ema_window > 0, "The gas price EMA window must not be zero");
+        if let Some(rate) = self.strk_per_eth {
+            anyhow::ensure!(rate.is_finite() && rate > 0.0, "The ETH/STRK rate must be a positive number");
+        }
+
+        let poll_interval = Duration::from_secs(self.gas_price_poll_interval);
+        Ok(GasPriceProviderConfig {
+            poll_interval,
+            ema_window: self.gas_price_ema_window,
+            strk_per_eth: self.strk_per_eth.map(StrkPerEth::Fixed),
+            initial_gas_prices: INITIAL_GAS_PRICES,
+            da_mode: L1DataAvailabilityMode::Blob,
+            stale_after: poll_interval * GAS_PRICE_STALE_AFTER_SAMPLES,
+        })
+    }
+
+    pub fn l1_messaging_config(&self, sync_params: &SyncParams) -> anyhow::Result<L1MessagingConfig> {
+        anyhow::ensure!(
+            self.l1_messages_backfill_chunk_size > 0,
+            "The L1 messages backfill chunk size must not be zero"
+        );
+        Ok(L1MessagingConfig {
+            backfill_chunk_size: self.l1_messages_backfill_chunk_size,
+            confirmations: sync_params.l1_confirmations,
+            ..Default::default()
+        })
+    }
 }
@@ -1,5 +1,8 @@
 use std::path::PathBuf;
 
+use dc_db::compaction::{CompactionConfig, CompactionSchedule, TimeWindow};
+use dc_db::Column;
+
 #[derive(Clone, Debug, clap::Args)]
 pub struct DbParams {
     /// The path where deoxys will store the database. You should probably change it.
@@ -13,4 +16,98 @@ pub struct DbParams {
     /// Restore the database at startup from the latest backup version. Use it with `--backup-dir <PATH>`
     #[clap(long)]
     pub restore_from_latest_backup: bool,
+
+    /// Limit the disk writes of flushes and compactions, in bytes per second. The limit is shared by all the columns.
+    #[clap(long, value_name = "BYTES PER SECOND")]
+    pub db_compaction_rate_limit: Option<u64>,
+
+    /// Number of database background jobs, flushes and compactions. Defaults to the number of cores.
+    #[clap(long, value_name = "JOBS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub db_background_jobs: Option<u32>,
+
+    /// Daily time windows, in UTC, during which fewer compactions run, such as `08:00-20:00`. Several windows can be
+    /// given, separated by commas. Use it to keep the large compactions out of the peak RPC hours.
+    #[clap(long, value_name = "HH:MM-HH:MM", value_delimiter = ',')]
+    pub db_quiet_hours: Vec<TimeWindow>,
+
+    /// Number of database background jobs during the quiet hours.
+    #[clap(long, default_value = "2", value_name = "JOBS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub db_quiet_background_jobs: u32,
+
+    /// Index the events by contract, so that `starknet_getEvents` on a single contract without keys does not read the
+    /// blocks. The index is built from the first block when it is enabled on an existing database, and it is removed
+    /// when the node starts without this flag. It takes about as much disk space as the events.
+    #[clap(long)]
+    pub db_index_contract_events: bool,
+}
+
+impl DbParams {
+    pub fn compaction_config(&self) -> CompactionConfig {
+        let default = CompactionSchedule::default();
+        CompactionConfig {
+            rate_limit: self.db_compaction_rate_limit,
+            schedule: CompactionSchedule {
+                background_jobs: self.db_background_jobs.unwrap_or(default.background_jobs),
+                quiet_background_jobs: self.db_quiet_background_jobs,
+                quiet_hours: self.db_quiet_hours.clone(),
+            },
+        }
+    }
+}
+
+/// Commands run on the database instead of running the node.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum DbCommand {
+    /// Export blocks of the database to a dump file. Nodes without access to the feeder gateway can be bootstrapped
+    /// from it with `db import-blocks`.
+    ExportBlocks {
+        /// The first block to export.
+        #[arg(long, value_name = "BLOCK")]
+        from: u64,
+        /// The last block to export.
+        #[arg(long, value_name = "BLOCK")]
+        to: u64,
+        /// The dump file to write.
+        #[arg(long, value_name = "FILE")]
+        output: PathBuf,
+        /// Compress the dump with zstd.
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Import the blocks of a dump file on top of the database tip. Every block is verified before it is stored, and
+    /// the import stops at the first block that does not verify.
+    ImportBlocks {
+        /// The dump file to read, compressed or not.
+        #[arg(long, value_name = "FILE")]
+        input: PathBuf,
+    },
+    /// Revert the blocks after a block of the database. Use it to confirm the revert once the sync stopped on a reorg
+    /// deeper than it reverts on its own: `deoxys_getSyncStall` gives the last block in common with the feeder.
+    ForceReorg {
+        /// The block to revert to, which is kept.
+        #[arg(long, value_name = "BLOCK")]
+        to_block: u64,
+    },
+    /// Replay the state diffs of the blocks in memory and compare the state root of every block with its header, then
+    /// compare the global tries of the database with the state at the tip. Reports the first block that diverges.
+    VerifyTries {
+        /// The first block whose state root is compared. The state before it is read from the stored history of the
+        /// contracts and classes instead of being replayed.
+        #[arg(long, value_name = "BLOCK", default_value_t = 0)]
+        from: u64,
+        /// The last block to verify, defaults to the tip. The global tries are only verified along with the tip.
+        #[arg(long, value_name = "BLOCK")]
+        to: Option<u64>,
+        /// Rebuild the global tries from the state diffs of the blocks when they do not match the tip. They are reverted
+        /// to the block before the tip when their logs allow it, and rebuilt from the genesis block otherwise.
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Compact the database, dropping the tombstones of the deleted keys. Run it after a pruning or a migration, the
+    /// reads are slower until the background compactions get to them.
+    Compact {
+        /// The columns to compact, such as `contract_storage`, separated by commas. Defaults to all of them.
+        #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+        columns: Vec<Column>,
+    },
 }
@@ -0,0 +1,19 @@
+use clap::Args;
+
+/// Parameters used to run a local devnet.
+#[derive(Debug, Clone, Args)]
+pub struct DevnetParams {
+    /// Run a local devnet: the node produces its own blocks, as with `--authority`. On an empty database, the genesis
+    /// block deploys the fee tokens of the chain and funded accounts, which are printed at startup along with their
+    /// private keys.
+    #[arg(long)]
+    pub devnet: bool,
+
+    /// Seed the keys of the devnet accounts are derived from. The same seed always gives the same accounts.
+    #[arg(long, value_name = "SEED", default_value_t = 0, requires = "devnet")]
+    pub devnet_seed: u64,
+
+    /// Number of accounts deployed by the devnet genesis block.
+    #[arg(long, value_name = "COUNT", default_value_t = 10, requires = "devnet")]
+    pub devnet_accounts: u64,
+}
@@ -0,0 +1,21 @@
+use clap::Args;
+use dp_utils::error_reporting::Severity;
+use url::Url;
+
+/// Parameters used to report panics and critical errors to an external service.
+#[derive(Debug, Clone, Args)]
+pub struct ErrorReportingParams {
+    /// Url of a webhook the panics and critical errors are posted to, as JSON.
+    /// Secrets such as api keys are scrubbed from the reports.
+    #[arg(long, value_name = "URL")]
+    pub error_webhook: Option<Url>,
+
+    /// Minimum severity of the errors posted to the webhook: warning, error or critical.
+    #[arg(long, value_name = "SEVERITY", default_value = "critical")]
+    pub error_webhook_min_severity: Severity,
+
+    /// Maximum number of reports posted to the webhook per minute. Reports over the limit are dropped, and their
+    /// count is sent along with the next posted report.
+    #[arg(long, value_name = "REPORTS", default_value_t = 10)]
+    pub error_webhook_rate_limit: u32,
+}
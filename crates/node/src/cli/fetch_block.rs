@@ -0,0 +1,22 @@
+use url::Url;
+
+/// Fetch a block from the feeder gateway and verify it, without a database.
+#[derive(Clone, Debug, clap::Args)]
+pub struct FetchBlockCmd {
+    /// The block to fetch.
+    #[arg(long, value_name = "BLOCK NUMBER")]
+    pub block: u64,
+
+    /// Also show the state diff of the block.
+    #[arg(long)]
+    pub state_update: bool,
+
+    /// Print the response of the feeder gateway, as parsed by the gateway client, instead of the converted header. The
+    /// block is still verified.
+    #[arg(long)]
+    pub raw: bool,
+
+    /// Fetch the block from this feeder gateway instead of the one of the network.
+    #[arg(long, value_name = "URL")]
+    pub feeder_gateway_url: Option<Url>,
+}
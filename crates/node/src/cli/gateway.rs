@@ -0,0 +1,16 @@
+use clap::Args;
+
+/// Parameters used to config the feeder gateway.
+#[derive(Debug, Clone, Args)]
+pub struct GatewayParams {
+    /// Serve the synced blocks, state updates and classes with the feeder gateway API, so that other nodes can sync
+    /// from this one.
+    #[arg(long)]
+    pub gateway_enable: bool,
+    /// Listen on all network interfaces. This usually means the feeder gateway will be accessible externally.
+    #[arg(long)]
+    pub gateway_external: bool,
+    /// The port used by the feeder gateway.
+    #[arg(long, value_name = "PORT", default_value = "8080")]
+    pub gateway_port: u16,
+}
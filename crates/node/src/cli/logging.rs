@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, with the `timestamp`, `level`, `target` and `message` fields, along with the fields
+    /// of the event such as `block_number`.
+    Json,
+}
+
+/// Parameters used to configure the logs.
+#[derive(Debug, Clone, Args)]
+pub struct LogParams {
+    /// Log level directives, such as `info,dc_sync=debug,librocksdb_sys=warn`.
+    /// They replace the `RUST_LOG` environment variable, which is used otherwise.
+    #[arg(long = "log", alias = "log-level", value_name = "DIRECTIVES")]
+    pub log_directives: Option<String>,
+
+    /// Format of the logs.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Also write the logs to this file, in the same format.
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Size of the log file before it is rotated, in MiB.
+    #[arg(long, value_name = "MiB", default_value_t = 100)]
+    pub log_file_max_size: u64,
+
+    /// Number of rotated log files kept, the oldest one is deleted.
+    #[arg(long, value_name = "FILES", default_value_t = 5)]
+    pub log_file_max_files: usize,
+}
@@ -1,11 +1,21 @@
 pub mod block_production;
 pub mod db;
+#[cfg(any(test, feature = "devnet"))]
+pub mod devnet;
+pub mod error_reporting;
+pub mod fetch_block;
+pub mod gateway;
+pub mod logging;
 pub mod prometheus;
 pub mod rpc;
 pub mod sync;
 pub mod telemetry;
 
 pub use db::*;
+pub use error_reporting::*;
+pub use fetch_block::*;
+pub use gateway::*;
+pub use logging::*;
 pub use prometheus::*;
 pub use rpc::*;
 pub use sync::*;
@@ -40,18 +50,49 @@ pub struct RunCmd {
     #[clap(flatten)]
     pub rpc_params: RpcParams,
 
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub gateway_params: GatewayParams,
+
     #[allow(missing_docs)]
     #[clap(flatten)]
     pub block_production_params: BlockProductionParams,
 
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub error_reporting_params: ErrorReportingParams,
+
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub log_params: LogParams,
+
     /// Enable authority mode: the node will run as a sequencer and try and produce its own blocks.
     #[arg(long)]
     pub authority: bool,
 
+    #[allow(missing_docs)]
+    #[cfg(any(test, feature = "devnet"))]
+    #[clap(flatten)]
+    pub devnet_params: devnet::DevnetParams,
+
     /// Run the TUI dashboard
     #[cfg(feature = "tui")]
     #[clap(long)]
     pub tui: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Command {
+    /// Database maintenance commands, run instead of the node
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Fetch a block from the feeder gateway of the network, convert it and compute its header again, without a
+    /// database. Exits with an error naming the fields that diverge when the block does not verify: run it first when
+    /// investigating a sync failure.
+    FetchBlock(FetchBlockCmd),
 }
 
 impl RunCmd {
@@ -66,14 +107,4 @@ impl RunCmd {
         }
         self.name.as_ref().unwrap()
     }
-
-    pub async fn network(&mut self) -> &str {
-        if self.sync_params.network == NetworkType::Integration {
-            "Integration"
-        } else if self.sync_params.network == NetworkType::Test {
-            "Testnet"
-        } else {
-            "Mainnet"
-        }
-    }
 }
@@ -2,10 +2,20 @@ use std::convert::Infallible;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::num::NonZeroU32;
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::ValueEnum;
+use dc_rpc::call_cache::CallCacheConfig;
+use dc_rpc::fallback::FallbackPolicy;
+use dc_rpc::limits::{
+    RpcLimitsConfig, DEFAULT_COMPILATION_TIMEOUT_SECS, DEFAULT_MAX_ABI_LENGTH_KIB, DEFAULT_MAX_COMPILED_CLASS_SIZE_KIB,
+    DEFAULT_MAX_CONCURRENT_COMPILATIONS, DEFAULT_MAX_LEGACY_PROGRAM_SIZE_KIB, DEFAULT_MAX_SIERRA_PROGRAM_LENGTH,
+};
 use ip_network::IpNetwork;
 use jsonrpsee::server::BatchRequestConfig;
+use url::Url;
+
+use crate::service::{ApiKey, MethodAccess, ReadinessConfig};
 
 /// Available RPC methods.
 #[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
@@ -33,6 +43,20 @@ pub const RPC_DEFAULT_MAX_CONNECTIONS: u32 = 100;
 /// The default number of messages the RPC server
 /// is allowed to keep in memory per connection.
 pub const RPC_DEFAULT_MESSAGE_CAPACITY_PER_CONN: u32 = 64;
+/// The default time browsers may cache CORS preflight responses, in seconds.
+pub const RPC_DEFAULT_CORS_MAX_AGE_SECS: u64 = 600;
+/// The default number of `starknet_call` results kept by the call cache.
+pub const RPC_DEFAULT_CALL_CACHE_SIZE: usize = 1024;
+/// The default time a `starknet_call` result is kept, in seconds.
+pub const RPC_DEFAULT_CALL_CACHE_TTL_SECS: u64 = 2;
+/// The default number of transaction traces asked to the sequencer at the same time.
+pub const RPC_DEFAULT_TRACE_FALLBACK_MAX_CONCURRENT: usize = 4;
+/// The default number of transaction traces asked to the sequencer per minute.
+pub const RPC_DEFAULT_TRACE_FALLBACK_PER_MINUTE: u32 = 60;
+/// The default number of blocks the node may be behind the feeder gateway while ready.
+pub const RPC_DEFAULT_READY_MAX_BLOCKS_BEHIND: u64 = 10;
+/// The default time the node may go without reaching its L1 endpoint while ready, in minutes.
+pub const RPC_DEFAULT_READY_L1_MAX_AGE_MINS: u64 = 10;
 
 #[derive(Clone, Debug)]
 pub enum Cors {
@@ -152,10 +176,124 @@ pub struct RpcParams {
     /// By default, only browser requests from localhost will work.
     ///
     /// This argument is a comma separated list of origins, or the special `all` value.
+    /// Origins may contain `*` wildcards, for example `https://*.example.com` or `http://localhost:*`.
+    /// Browser requests, including websocket connections, coming from any other origin are rejected.
     ///
     /// Learn more about CORS and web security at <https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS>.
     #[arg(long, value_name = "ORIGINS")]
     pub rpc_cors: Option<Cors>,
+
+    /// HTTP methods allowed for cross-origin requests, as a comma separated list.
+    #[arg(long, value_name = "METHODS", value_delimiter = ',', default_values = ["GET", "POST"])]
+    pub rpc_cors_methods: Vec<String>,
+
+    /// Request headers allowed for cross-origin requests, as a comma separated list.
+    #[arg(long, value_name = "HEADERS", value_delimiter = ',', default_values = ["content-type"])]
+    pub rpc_cors_headers: Vec<String>,
+
+    /// How long browsers may cache the response to a CORS preflight request, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value_t = RPC_DEFAULT_CORS_MAX_AGE_SECS)]
+    pub rpc_cors_max_age: u64,
+
+    /// RPC methods that are only served to browsers when the page comes from a localhost origin.
+    ///
+    /// This argument is a comma separated list of method name prefixes, for example `deoxys_` to restrict
+    /// a whole namespace. Requests that do not carry an `Origin` header are not affected.
+    #[arg(long, value_name = "PREFIXES", value_delimiter = ',')]
+    pub rpc_local_origin_methods: Vec<String>,
+
+    /// RPC methods served to the requests without an API key, as a comma separated list of method name prefixes.
+    /// Every method is served by default.
+    #[arg(long, value_name = "PREFIXES", value_delimiter = ',')]
+    pub rpc_allowed_methods: Option<Vec<String>>,
+
+    /// RPC methods never served to the requests without an API key, as a comma separated list of method name
+    /// prefixes. Calls to these methods fail with the `-32604` error code. `deoxys_compactDatabase` is always denied to
+    /// these requests.
+    #[arg(long, value_name = "PREFIXES", value_delimiter = ',')]
+    pub rpc_denied_methods: Vec<String>,
+
+    /// An API key and the RPC methods it unlocks, as `<KEY>=<PREFIXES>` with a comma separated list of method name
+    /// prefixes. The key is sent in the `x-api-key` header, or in the `api_key` query parameter for websockets. This
+    /// argument can be repeated.
+    #[arg(long = "rpc-api-key", value_name = "KEY=PREFIXES")]
+    pub rpc_api_keys: Vec<ApiKey>,
+
+    /// Never ask the sequencer for the traces of the transactions that are too old to be re-executed by the node.
+    /// `starknet_traceTransaction` returns an error for these transactions instead.
+    #[arg(long)]
+    pub rpc_disable_trace_fallback: bool,
+
+    /// Feeder gateway asked for the traces of the old transactions, instead of the one of the network.
+    #[arg(long, value_name = "URL")]
+    pub rpc_trace_fallback_url: Option<Url>,
+
+    /// Gateway api key sent with the traces asked to the sequencer, to avoid rate limiting.
+    #[arg(long, value_name = "API KEY")]
+    pub rpc_trace_fallback_key: Option<String>,
+
+    /// Maximum number of traces asked to the sequencer at the same time. Other calls wait for their turn.
+    #[arg(long, value_name = "COUNT", default_value_t = RPC_DEFAULT_TRACE_FALLBACK_MAX_CONCURRENT)]
+    pub rpc_trace_fallback_max_concurrent: usize,
+
+    /// Maximum number of traces asked to the sequencer per minute. Calls over this budget are rejected.
+    #[arg(long, value_name = "COUNT", default_value_t = RPC_DEFAULT_TRACE_FALLBACK_PER_MINUTE)]
+    pub rpc_trace_fallback_per_minute: u32,
+
+    /// Number of `starknet_call` results on closed blocks kept in memory, to answer the calls polled by frontends
+    /// without executing them again. The cache is cleared every time a new block is imported. 0 disables the cache.
+    #[arg(long, value_name = "COUNT", default_value_t = RPC_DEFAULT_CALL_CACHE_SIZE)]
+    pub rpc_call_cache_size: usize,
+
+    /// How long a `starknet_call` result is kept, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value_t = RPC_DEFAULT_CALL_CACHE_TTL_SECS)]
+    pub rpc_call_cache_ttl: u64,
+
+    /// Maximum L1 gas price suggested to wallets by `deoxys_getGasPriceHistory`, in percent of the 90th percentile of
+    /// the gas prices of the requested blocks.
+    #[arg(long, value_name = "PERCENT", default_value_t = dc_rpc::DEFAULT_SUGGESTED_GAS_PRICE_PERCENT)]
+    pub rpc_suggested_gas_price_percent: u64,
+
+    /// Number of threads executing the calls and the transactions of the trace, simulation and fee estimation methods,
+    /// and compiling the declared classes. They are not shared with the sync. Defaults to half of the cores.
+    #[arg(long, value_name = "THREADS", default_value_t = 0)]
+    pub rpc_threads: usize,
+
+    /// Declare transactions whose Sierra program is longer than this many felts are rejected before their class is
+    /// compiled.
+    #[arg(long, value_name = "FELTS", default_value_t = DEFAULT_MAX_SIERRA_PROGRAM_LENGTH)]
+    pub rpc_max_sierra_program_length: usize,
+
+    /// Declare transactions whose class has an ABI longer than this, in KiB, are rejected before their class is
+    /// compiled.
+    #[arg(long, value_name = "KIB", default_value_t = DEFAULT_MAX_ABI_LENGTH_KIB)]
+    pub rpc_max_abi_length: usize,
+
+    /// Legacy declare transactions whose compressed program is larger than this, in KiB, are rejected before their
+    /// program is decompressed.
+    #[arg(long, value_name = "KIB", default_value_t = DEFAULT_MAX_LEGACY_PROGRAM_SIZE_KIB)]
+    pub rpc_max_legacy_program_size: usize,
+
+    /// Declare transactions whose class is larger than this once compiled, in KiB, are rejected.
+    #[arg(long, value_name = "KIB", default_value_t = DEFAULT_MAX_COMPILED_CLASS_SIZE_KIB)]
+    pub rpc_max_compiled_class_size: usize,
+
+    /// Declare transactions whose class takes longer than this to compile, in seconds, are rejected.
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_COMPILATION_TIMEOUT_SECS)]
+    pub rpc_compilation_timeout: u64,
+
+    /// Number of classes of declare transactions compiled at the same time. The other declare transactions wait for
+    /// their turn, within the compilation timeout.
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_MAX_CONCURRENT_COMPILATIONS)]
+    pub rpc_max_concurrent_compilations: usize,
+
+    /// `GET /ready` fails when the node is more than this many blocks behind the tip of the feeder gateway.
+    #[arg(long, value_name = "COUNT", default_value_t = RPC_DEFAULT_READY_MAX_BLOCKS_BEHIND)]
+    pub rpc_ready_max_blocks_behind: u64,
+
+    /// `GET /ready` fails when the L1 endpoint was not reached for this long, in minutes.
+    #[arg(long, value_name = "MINUTES", default_value_t = RPC_DEFAULT_READY_L1_MAX_AGE_MINS)]
+    pub rpc_ready_l1_max_age: u64,
 }
 
 impl RpcParams {
@@ -194,4 +332,42 @@ impl RpcParams {
             BatchRequestConfig::Unlimited
         }
     }
+
+    pub fn call_cache_config(&self) -> CallCacheConfig {
+        CallCacheConfig { capacity: self.rpc_call_cache_size, ttl: Duration::from_secs(self.rpc_call_cache_ttl) }
+    }
+
+    pub fn limits_config(&self) -> RpcLimitsConfig {
+        RpcLimitsConfig {
+            max_sierra_program_length: self.rpc_max_sierra_program_length,
+            max_abi_length: self.rpc_max_abi_length * 1024,
+            max_legacy_program_size: self.rpc_max_legacy_program_size * 1024,
+            max_compiled_class_size: self.rpc_max_compiled_class_size * 1024,
+            compilation_timeout: Duration::from_secs(self.rpc_compilation_timeout),
+            max_concurrent_compilations: self.rpc_max_concurrent_compilations,
+        }
+    }
+
+    pub fn method_access(&self) -> MethodAccess {
+        MethodAccess::new(self.rpc_allowed_methods.clone(), self.rpc_denied_methods.clone(), self.rpc_api_keys.clone())
+    }
+
+    /// The sync check only applies to the nodes following the feeder gateway, and the L1 check to the nodes
+    /// following the L1 state.
+    pub fn readiness_config(&self, follows_feeder: bool, follows_l1: bool) -> ReadinessConfig {
+        ReadinessConfig {
+            max_blocks_behind: follows_feeder.then_some(self.rpc_ready_max_blocks_behind),
+            l1_max_age: follows_l1.then(|| Duration::from_secs(self.rpc_ready_l1_max_age * 60)),
+        }
+    }
+
+    pub fn trace_fallback_policy(&self) -> FallbackPolicy {
+        FallbackPolicy {
+            enabled: !self.rpc_disable_trace_fallback,
+            gateway_url: self.rpc_trace_fallback_url.clone(),
+            api_key: self.rpc_trace_fallback_key.clone(),
+            max_concurrent_requests: self.rpc_trace_fallback_max_concurrent,
+            requests_per_minute: self.rpc_trace_fallback_per_minute,
+        }
+    }
 }
@@ -1,8 +1,8 @@
+use anyhow::Context;
+use dc_sync::fast_sync::{FastSyncConfig, DEFAULT_FAST_SYNC_BATCH_SIZE};
 use dc_sync::fetch::fetchers::FetchConfig;
-use dc_sync::utils::constant::starknet_core_address;
-use dp_block::chain_config::ChainConfig;
-use primitive_types::H160;
-use starknet_api::core::ChainId;
+use dc_sync::pipeline::PipelineConfig;
+use dp_block::chain_config::{ChainConfig, CHAIN_PRESETS};
 use std::sync::Arc;
 use std::time::Duration;
 use url::Url;
@@ -21,18 +21,30 @@ pub struct SyncParams {
     #[clap(long, alias = "no-l1-sync")]
     pub sync_l1_disabled: bool,
 
-    /// The L1 rpc endpoint url for state verification.
-    #[clap(long, value_parser = parse_url, value_name = "ETHEREUM RPC URL")]
-    pub l1_endpoint: Option<Url>,
+    /// The L1 rpc endpoint url for state verification. Several urls can be given, separated by commas: the next one
+    /// is used when the current one fails.
+    #[clap(long, value_parser = parse_url, value_name = "ETHEREUM RPC URL", value_delimiter = ',')]
+    pub l1_endpoint: Vec<Url>,
 
-    /// The block you want to start syncing from.
+    /// Number of L1 blocks built on top of a state update or of an L1 to L2 message before it is acted on. After an
+    /// L1 reorg, the L1 events are processed again from this many blocks before the reorged one.
+    #[clap(long, default_value = "12", value_name = "BLOCKS")]
+    pub l1_confirmations: u64,
+
+    /// The block you want to start syncing from. This is only allowed on an empty database, the sync otherwise
+    /// resumes from the block after the database tip.
     #[clap(long, value_name = "BLOCK NUMBER")]
     pub starting_block: Option<u64>,
 
     /// The network to connect to.
-    #[clap(long, short, default_value = "main")]
+    #[clap(long, short, default_value = "main", global = true)]
     pub network: NetworkType,
 
+    /// The chain to run, instead of the one of the network: the name of a preset (mainnet, sepolia, integration) or
+    /// the path of a TOML chain spec. The gateways are still the ones of the network.
+    #[clap(long, value_name = "PRESET OR PATH", global = true)]
+    pub chain_config: Option<String>,
+
     /// This will produce sound interpreted from the block hashes.
     #[cfg(feature = "m")]
     #[clap(long)]
@@ -44,10 +56,63 @@ pub struct SyncParams {
     #[clap(long)]
     pub disable_root: bool,
 
+    /// Keep importing the blocks whose verified state root does not match the one of the feeder, instead of stopping
+    /// the sync at the first one. The node then serves a state that it could not verify: only use this to get past a
+    /// known-bad block.
+    #[clap(long)]
+    pub unsafe_ignore_state_root_mismatch: bool,
+
+    /// Commit the blocks below this one to the global tries in batches instead of one by one, and only verify the
+    /// state root of the last block of every batch. This speeds up the sync of the blocks already finalized on L1:
+    /// use a block below the last one confirmed on L1, as the fast synced blocks cannot be reverted one by one.
+    #[clap(long, value_name = "BLOCK NUMBER")]
+    pub fast_sync_until: Option<u64>,
+
+    /// Number of blocks committed to the global tries at once during a fast sync.
+    #[clap(long, default_value_t = DEFAULT_FAST_SYNC_BATCH_SIZE, value_name = "BLOCKS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub fast_sync_batch_size: u64,
+
+    /// Keep importing the blocks of a Starknet version newer than the latest one this node supports, instead of
+    /// stopping the sync. Their hashes, commitments and fees are computed as for the latest supported version, which
+    /// may be wrong: only use this until the node is updated.
+    #[clap(long)]
+    pub unsafe_allow_unsupported_protocol_version: bool,
+
     /// Gateway api key to avoid rate limiting (optional).
     #[clap(long, value_name = "API KEY")]
     pub gateway_key: Option<String>,
 
+    /// Number of blocks fetched in parallel from the feeder gateway while catching up with the chain. Close to the tip
+    /// of the chain, the blocks are fetched one at a time.
+    #[clap(long, default_value = "10", value_name = "BLOCKS")]
+    pub fetch_concurrency: usize,
+
+    /// Number of blocks the sync holds between their fetch and their storage. The fetch waits when the database falls
+    /// behind.
+    #[clap(long, default_value = "16", value_name = "BLOCKS")]
+    pub sync_pipeline_max_blocks: usize,
+
+    /// Approximate size of the blocks the sync holds between their fetch and their storage, in MiB.
+    #[clap(long, default_value = "1024", value_name = "MIB")]
+    pub sync_pipeline_max_mib: usize,
+
+    /// Number of threads converting, hashing and storing the blocks, compiling their classes, and computing their state
+    /// root. They are not shared with the RPC executions. Defaults to half of the cores.
+    #[clap(long, default_value = "0", value_name = "THREADS")]
+    pub sync_threads: usize,
+
+    /// Number of times a failed request to the feeder gateway is retried before the sync stops.
+    #[clap(long, default_value = "15", value_name = "RETRIES")]
+    pub fetch_max_retries: usize,
+
+    /// Delay before retrying a failed request to the feeder gateway, in milliseconds. It doubles with every retry.
+    #[clap(long, default_value = "1000", value_name = "MILLISECONDS")]
+    pub fetch_retry_base_delay: u64,
+
+    /// Maximum number of requests per second to the feeder gateway.
+    #[clap(long, value_name = "REQUESTS PER SECOND")]
+    pub gateway_rate_limit: Option<u32>,
+
     /// Polling interval, in seconds. This only affects the sync service once it has caught up with the blockchain tip.
     #[clap(long, default_value = "4", value_name = "SECONDS")]
     pub sync_polling_interval: u64,
@@ -65,18 +130,34 @@ pub struct SyncParams {
     #[clap(long, value_name = "NUMBER OF BLOCKS")]
     pub n_blocks_to_sync: Option<u64>,
 
+    /// Keep the transactions received on the RPC Write endpoints in a local mempool, as well as forwarding them to the
+    /// sequencer gateway. They are served by the RPC until a synced block includes them, and are pruned when their
+    /// nonce is consumed.
+    #[clap(long)]
+    pub sync_mempool: bool,
+
     /// Periodically create a backup, for debugging purposes. Use it with `--backup-dir <PATH>`.
     #[clap(long, value_name = "NUMBER OF BLOCKS")]
     pub backup_every_n_blocks: Option<u64>,
 }
 
 impl SyncParams {
-    pub fn block_fetch_config(&self) -> FetchConfig {
-        let chain_id = self.network.chain_id();
+    pub fn chain_config(&self) -> anyhow::Result<Arc<ChainConfig>> {
+        let Some(chain_config) = &self.chain_config else { return Ok(self.network.db_chain_info()) };
+        let chain_config = if CHAIN_PRESETS.contains(&chain_config.as_str()) {
+            ChainConfig::from_preset(chain_config)?
+        } else {
+            ChainConfig::from_file(chain_config).with_context(|| format!("Loading the chain spec {chain_config}"))?
+        };
+        Ok(Arc::new(chain_config))
+    }
+
+    pub fn block_fetch_config(&self, chain_config: &ChainConfig) -> FetchConfig {
+        let chain_id = chain_config.chain_id.clone();
 
         let gateway = self.network.gateway();
         let feeder_gateway = self.network.feeder_gateway();
-        let l1_core_address = self.network.l1_core_address();
+        let l1_core_address = chain_config.eth_core_contract_address;
 
         let polling = if self.no_sync_polling { None } else { Some(Duration::from_secs(self.sync_polling_interval)) };
 
@@ -92,10 +173,24 @@ impl SyncParams {
             sound,
             l1_core_address,
             verify: !self.disable_root,
+            ignore_state_root_mismatch: self.unsafe_ignore_state_root_mismatch,
+            fast_sync: self
+                .fast_sync_until
+                .map(|until_block| FastSyncConfig { until_block, batch_size: self.fast_sync_batch_size }),
+            allow_unsupported_protocol_version: self.unsafe_allow_unsupported_protocol_version,
             api_key: self.gateway_key.clone(),
             sync_polling_interval: polling,
             n_blocks_to_sync: self.n_blocks_to_sync,
             sync_l1_disabled: self.sync_l1_disabled,
+            l1_confirmations: self.l1_confirmations,
+            concurrency: self.fetch_concurrency,
+            max_retries: self.fetch_max_retries,
+            retry_base_delay: Duration::from_millis(self.fetch_retry_base_delay),
+            rate_limit_per_second: self.gateway_rate_limit,
+            pipeline: PipelineConfig {
+                max_blocks: self.sync_pipeline_max_blocks,
+                max_bytes: self.sync_pipeline_max_mib.saturating_mul(1024 * 1024),
+            },
         }
     }
 }
@@ -139,20 +234,4 @@ impl NetworkType {
     pub fn feeder_gateway(&self) -> Url {
         format!("{}/feeder_gateway", self.uri()).parse().unwrap()
     }
-
-    pub fn chain_id(&self) -> ChainId {
-        match self {
-            NetworkType::Main => ChainId::Mainnet,
-            NetworkType::Test => ChainId::Sepolia,
-            NetworkType::Integration => ChainId::IntegrationSepolia,
-        }
-    }
-
-    pub fn l1_core_address(&self) -> H160 {
-        match self {
-            NetworkType::Main => starknet_core_address::MAINNET.parse().unwrap(),
-            NetworkType::Test => starknet_core_address::SEPOLIA_TESTNET.parse().unwrap(),
-            NetworkType::Integration => starknet_core_address::SEPOLIA_INTEGRATION.parse().unwrap(),
-        }
-    }
 }
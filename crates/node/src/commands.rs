@@ -0,0 +1,164 @@
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Context;
+use dc_db::DatabaseService;
+use dc_sync::dump::{export_blocks, DumpReader};
+use dc_sync::fetch::inspect::{fetch_and_verify_block, FetchedBlock, FieldMismatch};
+use dc_sync::import::import_blocks;
+use dc_sync::reorgs::force_reorg;
+use dc_sync::verify_tries::{rebuild_tries, verify_tries, TriesVerification};
+use dp_convert::ToFelt;
+use dp_utils::spawn_rayon_task;
+use starknet_providers::SequencerGatewayProvider;
+
+use crate::cli::{DbCommand, FetchBlockCmd, RunCmd};
+
+/// Runs a database command, on the database of the node.
+pub async fn run_db_command(command: DbCommand, run_cmd: &RunCmd) -> anyhow::Result<()> {
+    let db_service = DatabaseService::new(
+        &run_cmd.db_params.base_path,
+        run_cmd.db_params.backup_dir.clone(),
+        run_cmd.db_params.restore_from_latest_backup,
+        run_cmd.sync_params.chain_config()?,
+    )
+    .await
+    .context("Initializing db service")?;
+    let backend = Arc::clone(db_service.backend());
+
+    match command {
+        DbCommand::ExportBlocks { from, to, output, compress } => {
+            anyhow::ensure!(from <= to, "The first block {from} is after the last block {to}");
+            let tip = backend.get_latest_block_n()?.context("The database is empty")?;
+            anyhow::ensure!(to <= tip, "Block {to} is after the database tip, block {tip}");
+
+            log::info!("📤 Exporting blocks {from} to {to} to {}", output.display());
+            spawn_rayon_task(move || export_blocks(&backend, from, to, &output, compress)).await?;
+            log::info!("✅ Exported {} blocks", to - from + 1);
+        }
+        DbCommand::ImportBlocks { input } => {
+            let chain_id = backend.chain_config().chain_id.clone().to_felt();
+            let reader = DumpReader::open(&input)?;
+
+            log::info!("📥 Importing the blocks of {}", input.display());
+            let backend_ = Arc::clone(&backend);
+            let res = spawn_rayon_task(move || import_blocks(&backend_, reader, chain_id)).await;
+            // The blocks imported before a failure are kept.
+            backend.maybe_flush(true)?;
+            log::info!("✅ Imported {} blocks", res?);
+        }
+        DbCommand::ForceReorg { to_block } => {
+            let backend_ = Arc::clone(&backend);
+            spawn_rayon_task(move || force_reorg(&backend_, to_block)).await?;
+            backend.maybe_flush(true)?;
+        }
+        DbCommand::VerifyTries { from, to, rebuild } => {
+            let tip = backend.get_latest_block_n()?.context("The database is empty")?;
+            let to = to.unwrap_or(tip);
+            anyhow::ensure!(from <= to, "The first block {from} is after the last block {to}");
+            anyhow::ensure!(to <= tip, "Block {to} is after the database tip, block {tip}");
+
+            log::info!("🌳 Verifying the state roots of blocks {from} to {to}");
+            let backend_ = Arc::clone(&backend);
+            match spawn_rayon_task(move || verify_tries(&backend_, from, to)).await? {
+                TriesVerification::Valid => log::info!("✅ The state roots of blocks {from} to {to} match"),
+                TriesVerification::DivergentBlock { block_n, expected, got } => anyhow::bail!(
+                    "Block {block_n} has state root {expected:#x}, but the state diffs up to it lead to {got:#x}: the \
+                     blocks of the database diverge from there, revert them with `db force-reorg --to-block {}`",
+                    block_n.saturating_sub(1)
+                ),
+                TriesVerification::CorruptedTries { tip, expected, got } => {
+                    let message = format!(
+                        "The global tries have state root {got:#x}, but block {tip} has state root {expected:#x}"
+                    );
+                    anyhow::ensure!(rebuild, "{message}, rebuild them with `--rebuild`");
+                    log::warn!("⚠️  {message}, rebuilding them");
+                    let backend_ = Arc::clone(&backend);
+                    let res = spawn_rayon_task(move || rebuild_tries(&backend_, tip)).await;
+                    backend.maybe_flush(true)?;
+                    res?;
+                    log::info!("✅ Rebuilt the global tries up to block {tip}");
+                }
+            }
+        }
+        DbCommand::Compact { columns } => {
+            let compaction = backend.compact((!columns.is_empty()).then_some(columns))?;
+            tokio::task::spawn_blocking(move || compaction.join())
+                .await?
+                .map_err(|_| anyhow::anyhow!("The compaction thread panicked"))??;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches a block from the feeder gateway and prints it. Fails when the block does not verify.
+pub async fn run_fetch_block_command(command: FetchBlockCmd, run_cmd: &RunCmd) -> anyhow::Result<()> {
+    let network = run_cmd.sync_params.network;
+    let chain_id = run_cmd.sync_params.chain_config()?.chain_id.clone().to_felt();
+    let feeder_gateway = command.feeder_gateway_url.unwrap_or_else(|| network.feeder_gateway());
+    let provider = SequencerGatewayProvider::new(network.gateway(), feeder_gateway, chain_id);
+    let provider = match &run_cmd.sync_params.gateway_key {
+        Some(api_key) => provider.with_header("X-Throttling-Bypass".to_string(), api_key.clone()),
+        None => provider,
+    };
+    let fetched = fetch_and_verify_block(&provider, command.block, chain_id).await?;
+
+    if command.raw {
+        println!("{}", fetched.fetched_block);
+        if command.state_update {
+            println!("{}", fetched.fetched_state_update);
+        }
+    } else {
+        print_fetched_block(&fetched, command.state_update);
+    }
+
+    for FieldMismatch { field, fetched, computed } in &fetched.mismatches {
+        eprintln!("❌ {field}: fetched {fetched}, computed {computed}");
+    }
+    let fields: Vec<_> = fetched.mismatches.iter().map(|mismatch| mismatch.field).collect();
+    anyhow::ensure!(
+        fields.is_empty(),
+        "Block {} does not verify, diverging fields: {}",
+        command.block,
+        fields.join(", ")
+    );
+    Ok(())
+}
+
+fn print_fetched_block(fetched: &FetchedBlock, state_update: bool) {
+    let row = |name: &str, value: &dyn fmt::Display| println!("{name:<26}{value}");
+    let header = &fetched.block.info.header;
+
+    row("block_number", &header.block_number);
+    row("block_hash", &format_args!("{:#x}", fetched.block.info.block_hash));
+    row("computed_block_hash", &format_args!("{:#x}", fetched.computed_block_hash));
+    row("parent_block_hash", &format_args!("{:#x}", header.parent_block_hash));
+    row("state_root", &format_args!("{:#x}", header.global_state_root));
+    row("sequencer_address", &format_args!("{:#x}", header.sequencer_address));
+    row("timestamp", &header.block_timestamp);
+    row("starknet_version", &header.protocol_version);
+    row("transaction_count", &header.transaction_count);
+    row("transaction_commitment", &format_args!("{:#x}", header.transaction_commitment));
+    row("event_count", &header.event_count);
+    row("event_commitment", &format_args!("{:#x}", header.event_commitment));
+    row("receipt_commitment", &format_args!("{:#x}", header.receipt_commitment));
+    row("state_diff_length", &header.state_diff_length);
+    row("state_diff_commitment", &format_args!("{:#x}", header.state_diff_commitment));
+    row("l1_gas_price", &format_args!("{:?}", header.l1_gas_price));
+    row("l1_da_mode", &format_args!("{:?}", header.l1_da_mode));
+
+    if state_update {
+        let state_diff = &fetched.state_diff;
+        let storage_entries: usize = state_diff.storage_diffs().iter().map(|diff| diff.storage_entries.len()).sum();
+        row(
+            "storage_diffs",
+            &format_args!("{} contracts, {storage_entries} entries", state_diff.storage_diffs().len()),
+        );
+        row("deployed_contracts", &state_diff.deployed_contracts().len());
+        row("declared_classes", &state_diff.declared_classes().len());
+        row("old_declared_contracts", &state_diff.deprecated_declared_classes().len());
+        row("replaced_classes", &state_diff.replaced_classes().len());
+        row("nonces", &state_diff.nonces().len());
+    }
+}
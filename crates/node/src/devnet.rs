@@ -0,0 +1,95 @@
+//! Local devnet, run with `--devnet`: the node produces its own blocks on top of a genesis block deploying the fee
+//! tokens of the chain and funded accounts.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use dc_db::DeoxysBackend;
+use dc_mempool::genesis::DevnetAccount;
+use dc_mempool::testing::devnet_genesis;
+use dc_mempool::L1DataProvider;
+use dp_utils::clock::SystemClock;
+use dp_utils::spawn_rayon_task;
+
+use crate::cli::devnet::DevnetParams;
+
+/// Stores the devnet genesis block on an empty database, and returns the devnet accounts. A database which already has
+/// blocks is left as it is: its accounts are the ones of the seed it was created with.
+pub async fn init_devnet(
+    backend: &Arc<DeoxysBackend>,
+    l1_data_provider: Arc<dyn L1DataProvider>,
+    params: &DevnetParams,
+) -> anyhow::Result<Vec<DevnetAccount>> {
+    let genesis = devnet_genesis(backend.chain_config(), params.devnet_seed, params.devnet_accounts);
+    let accounts = genesis.accounts().to_vec();
+    if backend.get_latest_block_n()?.is_some() {
+        log::info!("🧪 The database already has blocks, the devnet genesis block is not stored again");
+        return Ok(accounts);
+    }
+
+    let backend_ = Arc::clone(backend);
+    let block = spawn_rayon_task(move || genesis.store(&backend_, l1_data_provider.as_ref(), &SystemClock))
+        .await
+        .context("Storing the devnet genesis block")?;
+    log::info!("🧪 Stored the devnet genesis block {:#x}", block.info.block_hash);
+    Ok(accounts)
+}
+
+/// Prints the devnet accounts along with their private keys.
+pub fn log_devnet_accounts(accounts: &[DevnetAccount]) {
+    log::info!("🧪 Devnet accounts:");
+    for (index, account) in accounts.iter().enumerate() {
+        log::info!("  #{index} address: {:#x}", account.address);
+        log::info!("     private key: {:#x}", account.private_key);
+        log::info!("     public key: {:#x}", account.public_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use dc_db::testing::temp_database;
+    use dc_mempool::genesis::erc20_balance_key;
+    use dc_mempool::testing::{MockL1DataProvider, DEVNET_ACCOUNT_BALANCE, TEST_ACCOUNT_CLASS_HASH};
+    use dp_block::{BlockId, BlockTag};
+    use dp_convert::ToFelt;
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::cli::RunCmd;
+
+    #[tokio::test]
+    async fn test_devnet_from_the_flag() {
+        let run_cmd = RunCmd::parse_from(["deoxys", "--devnet", "--devnet-accounts", "2"]);
+        assert!(run_cmd.devnet_params.devnet);
+        assert!(RunCmd::try_parse_from(["deoxys", "--devnet-seed", "1"]).is_err());
+
+        let (_temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+        let accounts = init_devnet(&backend, Arc::new(MockL1DataProvider), &run_cmd.devnet_params).await.unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+
+        let latest = BlockId::Tag(BlockTag::Latest);
+        let chain_config = backend.chain_config();
+        for account in &accounts {
+            assert_eq!(
+                backend.get_contract_class_hash_at(&latest, &account.address).unwrap(),
+                Some(TEST_ACCOUNT_CLASS_HASH)
+            );
+            let balance_key = erc20_balance_key(account.address);
+            for token in [chain_config.parent_fee_token_address, chain_config.native_fee_token_address] {
+                assert_eq!(
+                    backend.get_contract_storage_at(&latest, &token.to_felt(), &balance_key).unwrap(),
+                    Some(Felt::from(DEVNET_ACCOUNT_BALANCE))
+                );
+            }
+        }
+
+        // The node restarts on the same database: the accounts are the same, the genesis block is kept.
+        let genesis_hash = backend.get_block_hash(&latest).unwrap();
+        let restarted = init_devnet(&backend, Arc::new(MockL1DataProvider), &run_cmd.devnet_params).await.unwrap();
+        assert_eq!(restarted, accounts);
+        assert_eq!(backend.get_block_hash(&latest).unwrap(), genesis_hash);
+    }
+}
@@ -0,0 +1,299 @@
+//! Deoxys node. The `deoxys` binary runs it as is, app-chains embed it with [`run`] to serve their own RPC methods.
+#![warn(missing_docs)]
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::Parser;
+
+mod cli;
+mod commands;
+#[cfg(any(test, feature = "devnet"))]
+mod devnet;
+mod logging;
+mod service;
+mod util;
+
+use cli::{Command, RunCmd};
+use dc_db::compaction::CompactionMetrics;
+use dc_db::DatabaseService;
+use dc_exec::{ClassCacheMetrics, GlobalClassCache};
+use dc_gateway::GatewayService;
+use dc_mempool::{L1DataProvider, LatestBlockL1DataProvider, Mempool};
+use dc_metrics::MetricsService;
+pub use dc_rpc::extensions::{RpcExtensionContext, RpcExtensions};
+use dc_rpc::mempool_provider::MempoolProvider;
+use dc_rpc::propagation::{HttpRebroadcastPropagator, PropagatingProvider, PropagationMetrics};
+use dc_rpc::providers::{AddTransactionProvider, ForwardAndKeepProvider, ForwardToProvider};
+use dc_rpc::ChainHandle;
+use dc_sync::l2::BlockImportHook;
+use dc_telemetry::{SysInfo, TelemetryService};
+use dp_convert::ToFelt;
+use dp_utils::compute_pool::ComputePool;
+use dp_utils::error_reporting;
+use dp_utils::service::{Service, ServiceGroup};
+use dp_utils::supervisor::{Supervisor, SupervisorConfig};
+use service::{
+    BlockProductionService, ComputePoolMetrics, ErrorReportingService, GasPriceService, L1MessagingService, RpcService,
+    SupervisorMetrics, SyncService,
+};
+use starknet_providers::SequencerGatewayProvider;
+
+const GREET_IMPL_NAME: &str = "Deoxys";
+const GREET_SUPPORT_URL: &str = "https://github.com/KasarLabs/deoxys/issues";
+const GREET_AUTHORS: &[&str] = &["KasarLabs <https://kasar.io>"];
+
+/// Parses the command line and runs the node until it shuts down.
+///
+/// `rpc_extensions` builds the methods served alongside the built-in ones, once the database is open. See
+/// [`dc_rpc::extensions`] for the thread-safety expectations of their methods.
+pub async fn run(
+    rpc_extensions: impl FnOnce(RpcExtensionContext) -> anyhow::Result<RpcExtensions>,
+) -> anyhow::Result<()> {
+    let mut run_cmd: RunCmd = RunCmd::parse();
+    crate::logging::setup_logging(&run_cmd.log_params)?;
+    crate::util::setup_rayon_threadpool()?;
+    crate::util::raise_fdlimit();
+
+    match run_cmd.command.take() {
+        Some(Command::Db(command)) => return commands::run_db_command(command, &run_cmd).await,
+        Some(Command::FetchBlock(command)) => return commands::run_fetch_block_command(command, &run_cmd).await,
+        None => {}
+    }
+    // A devnet produces its own blocks.
+    #[cfg(any(test, feature = "devnet"))]
+    if run_cmd.devnet_params.devnet {
+        run_cmd.authority = true;
+    }
+
+    let node_name = run_cmd.node_name_or_provide().await.to_string();
+    let chain_config = run_cmd.sync_params.chain_config()?;
+    let node_version = env!("DEOXYS_BUILD_VERSION");
+
+    log::info!("👽 {} Node", GREET_IMPL_NAME);
+    log::info!("✌️  Version {}", node_version);
+    for author in GREET_AUTHORS {
+        log::info!("❤️  By {}", author);
+    }
+    log::info!("💁 Support URL: {}", GREET_SUPPORT_URL);
+    log::info!("🏷  Node Name: {}", node_name);
+    let role = if run_cmd.authority { "authority" } else { "full node" };
+    log::info!("👤 Role: {}", role);
+    log::info!("🌐 Network: {}", chain_config.chain_name);
+
+    let sys_info = SysInfo::probe();
+    sys_info.show();
+
+    // Services.
+
+    let error_reporting_service =
+        ErrorReportingService::new(&run_cmd.error_reporting_params).context("Initializing error reporting service")?;
+    if let Some(reporter) = error_reporting_service.reporter() {
+        error_reporting::set_error_reporter(reporter);
+    }
+    error_reporting::install_panic_hook();
+
+    let telemetry_service = TelemetryService::new(
+        run_cmd.telemetry_params.telemetry_disabled,
+        run_cmd.telemetry_params.telemetry_endpoints.clone(),
+    )
+    .context("Initializing telemetry service")?;
+    let prometheus_service = MetricsService::new(
+        run_cmd.prometheus_params.prometheus_disabled,
+        run_cmd.prometheus_params.prometheus_external,
+        run_cmd.prometheus_params.prometheus_port,
+    )
+    .context("Initializing prometheus metrics service")?;
+
+    // Restarts the long-running tasks of the services when they fail.
+    let supervisor_metrics =
+        SupervisorMetrics::register(&prometheus_service.registry()).context("Registering the supervisor metrics")?;
+    let supervisor = Supervisor::new(SupervisorConfig::default()).with_observer(Arc::new(supervisor_metrics));
+    let prometheus_service = prometheus_service.with_supervisor(supervisor.clone());
+
+    // The sync and the RPC do their compute heavy work on their own threads, so that neither starves the other.
+    let compute_pool_metrics = Arc::new(
+        ComputePoolMetrics::register(&prometheus_service.registry()).context("Registering the compute pool metrics")?,
+    );
+    let rpc_compute_pool =
+        ComputePool::new("rpc", run_cmd.rpc_params.rpc_threads)?.with_observer(compute_pool_metrics.clone());
+
+    let mut db_service = DatabaseService::new_with_compaction(
+        &run_cmd.db_params.base_path,
+        run_cmd.db_params.backup_dir.clone(),
+        run_cmd.db_params.restore_from_latest_backup,
+        Arc::clone(&chain_config),
+        run_cmd.db_params.compaction_config(),
+    )
+    .await
+    .context("Initializing db service")?;
+    db_service.index_contract_events(run_cmd.db_params.db_index_contract_events);
+    db_service.schedule_compactions(
+        CompactionMetrics::register(&prometheus_service.registry()).context("Registering compaction metrics")?,
+    );
+    GlobalClassCache::global().set_metrics(
+        ClassCacheMetrics::register(&prometheus_service.registry()).context("Registering class cache metrics")?,
+    );
+
+    // Block provider startup.
+    // When this node produces blocks, the mempool is returned so that the RPC Write endpoints can put the transactions in it,
+    // along with a view of the block being produced for the node operator endpoints.
+    let (block_provider_service, mempool, block_preview) = match run_cmd.authority {
+        // Block production service. (authority)
+        true => {
+            // The gas prices and the L1 messages share the client, whose metrics are registered once.
+            let eth_client =
+                crate::util::eth_client(&run_cmd.sync_params, &chain_config, prometheus_service.registry()).await?;
+            let gas_price_service =
+                GasPriceService::new(&run_cmd.block_production_params, eth_client.clone(), supervisor.clone())
+                    .context("Initializing gas price service")?;
+            let l1_data_provider: Arc<dyn L1DataProvider> = gas_price_service.provider();
+
+            // The genesis block is stored before the block production starts on top of it.
+            #[cfg(any(test, feature = "devnet"))]
+            if run_cmd.devnet_params.devnet {
+                let accounts =
+                    devnet::init_devnet(db_service.backend(), Arc::clone(&l1_data_provider), &run_cmd.devnet_params)
+                        .await
+                        .context("Initializing the devnet")?;
+                devnet::log_devnet_accounts(&accounts);
+            }
+
+            let mempool = Arc::new(Mempool::new(Arc::clone(db_service.backend()), Arc::clone(&l1_data_provider)));
+
+            let l1_messaging_service = L1MessagingService::new(
+                &run_cmd.block_production_params,
+                &run_cmd.sync_params,
+                &db_service,
+                Arc::clone(&mempool),
+                eth_client,
+                supervisor.clone(),
+            )
+            .context("Initializing L1 messaging service")?;
+
+            let block_production_service = BlockProductionService::new(
+                &run_cmd.block_production_params,
+                &db_service,
+                Arc::clone(&mempool),
+                Arc::clone(&l1_data_provider),
+                prometheus_service.registry(),
+                telemetry_service.new_handle(),
+                supervisor.clone(),
+            )?;
+
+            let mempool = (!run_cmd.block_production_params.block_production_disabled).then_some(mempool);
+            let block_preview = block_production_service.preview_handle();
+            let services = ServiceGroup::default()
+                .with(gas_price_service)
+                .with(l1_messaging_service)
+                .with(block_production_service);
+            (services, mempool, block_preview)
+        }
+        // Block sync service. (full node)
+        false => {
+            // The local mempool of a full node only keeps the transactions it forwards, until the synced blocks include them.
+            let mempool = run_cmd.sync_params.sync_mempool.then(|| {
+                let l1_data_provider = Arc::new(LatestBlockL1DataProvider::new(Arc::clone(db_service.backend())));
+                Arc::new(Mempool::new(Arc::clone(db_service.backend()), l1_data_provider))
+            });
+
+            // Feeder gateway sync service.
+            let compute_pool =
+                ComputePool::new("sync", run_cmd.sync_params.sync_threads)?.with_observer(compute_pool_metrics);
+            let sync_service = SyncService::new(
+                &run_cmd.sync_params,
+                &db_service,
+                prometheus_service.registry(),
+                telemetry_service.new_handle(),
+                supervisor.clone(),
+                compute_pool,
+                mempool.clone().map(|mempool| mempool as Arc<dyn BlockImportHook>),
+            )
+            .await
+            .context("Initializing sync service")?;
+
+            (ServiceGroup::default().with(sync_service), mempool, None)
+        }
+    };
+
+    // `rpc_add_txs_method_provider` is a trait object that tells the RPC task where to put the transactions when using the Write endpoints.
+    // Nodes that do not produce blocks forward them to the sequencer gateway.
+    let rpc_add_txs_method_provider: Arc<dyn AddTransactionProvider> = match (run_cmd.authority, &mempool) {
+        (true, Some(mempool)) => {
+            let provider = MempoolProvider::new(Arc::clone(mempool), ChainHandle::from_backend(db_service.backend()))
+                .with_limits(run_cmd.rpc_params.limits_config())
+                .with_compute_pool(rpc_compute_pool.clone());
+            let peers = run_cmd.block_production_params.tx_propagation_peers.clone();
+            if peers.is_empty() {
+                Arc::new(provider)
+            } else {
+                let metrics = PropagationMetrics::register(&prometheus_service.registry())
+                    .context("Registering the transaction propagation metrics")?;
+                let propagator = Arc::new(HttpRebroadcastPropagator::new(peers, metrics.clone()));
+                let chain_id = chain_config.chain_id.clone().to_felt();
+                Arc::new(PropagatingProvider::new(provider, chain_id, propagator, metrics))
+            }
+        }
+        (_, mempool) => {
+            let provider = SequencerGatewayProvider::new(
+                run_cmd.sync_params.network.gateway(),
+                run_cmd.sync_params.network.feeder_gateway(),
+                chain_config.chain_id.clone().to_felt(),
+            );
+            let provider = match &run_cmd.sync_params.gateway_key {
+                Some(api_key) => provider.with_header("X-Throttling-Bypass".to_string(), api_key.clone()),
+                None => provider,
+            };
+            let provider = ForwardToProvider::new(provider);
+            match mempool {
+                Some(mempool) => {
+                    let local =
+                        MempoolProvider::new(Arc::clone(mempool), ChainHandle::from_backend(db_service.backend()))
+                            .with_limits(run_cmd.rpc_params.limits_config())
+                            .with_compute_pool(rpc_compute_pool.clone());
+                    Arc::new(ForwardAndKeepProvider::new(provider, local))
+                }
+                None => Arc::new(provider),
+            }
+        }
+    };
+
+    // A node producing its own blocks follows neither the feeder gateway nor the L1 state.
+    let follows_l1 = !run_cmd.authority && !run_cmd.sync_params.sync_l1_disabled;
+    let readiness = run_cmd.rpc_params.readiness_config(!run_cmd.authority, follows_l1);
+    let rpc_service = RpcService::new(
+        &run_cmd.rpc_params,
+        &db_service,
+        run_cmd.sync_params.network,
+        prometheus_service.registry(),
+        rpc_add_txs_method_provider,
+        mempool,
+        block_preview,
+        rpc_extensions(RpcExtensionContext::new(db_service.backend())).context("Building the RPC extensions")?,
+        supervisor,
+        readiness,
+        rpc_compute_pool,
+    )
+    .context("Initializing rpc service")?;
+
+    let gateway_service = GatewayService::new(
+        run_cmd.gateway_params.gateway_enable,
+        run_cmd.gateway_params.gateway_external,
+        run_cmd.gateway_params.gateway_port,
+        Arc::clone(db_service.backend()),
+    );
+
+    telemetry_service.send_connected(&node_name, node_version, &chain_config.chain_name, &sys_info);
+
+    let app = ServiceGroup::default()
+        .with(error_reporting_service)
+        .with(db_service)
+        .with(block_provider_service)
+        .with(rpc_service)
+        .with(gateway_service)
+        .with(telemetry_service)
+        .with(prometheus_service);
+
+    app.start_and_drive_to_end().await?;
+    Ok(())
+}
@@ -0,0 +1,396 @@
+//! The logs of the node, on stderr and optionally in a rotated file.
+//!
+//! Logs are [`tracing`] events. The `log` macros used across the crates keep working: their records are forwarded to
+//! the same subscriber, with their own target and level.
+
+use std::fmt::{self, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{Local, SecondsFormat, Utc};
+use clap::builder::styling::{AnsiColor, Color, Style};
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::cli::{LogFormat, LogParams};
+
+const MIB: u64 = 1024 * 1024;
+
+pub fn setup_logging(params: &LogParams) -> anyhow::Result<()> {
+    let filter = match &params.log_directives {
+        Some(directives) => {
+            EnvFilter::try_new(directives).with_context(|| format!("Invalid log directives `{directives}`"))?
+        }
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env()
+            .context("Invalid log directives in RUST_LOG")?,
+    };
+
+    let file_layer = match &params.log_file {
+        Some(path) => {
+            let file =
+                RotatingFile::open(path, params.log_file_max_size.saturating_mul(MIB), params.log_file_max_files)
+                    .with_context(|| format!("Opening log file {}", path.display()))?;
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .event_format(NodeFormat(params.log_format));
+            Some(layer)
+        }
+        None => None,
+    };
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .event_format(NodeFormat(params.log_format));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr_layer)
+        .with(file_layer)
+        .try_init()
+        .context("Setting up the logs")
+}
+
+/// The message and the fields of an event. The fields added by the `log` compatibility layer are left out.
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    fields: serde_json::Map<String, Value>,
+}
+
+impl EventFields {
+    fn new(event: &Event<'_>) -> Self {
+        let mut fields = Self::default();
+        event.record(&mut fields);
+        fields
+    }
+
+    fn insert(&mut self, field: &Field, value: Value) {
+        if !field.name().starts_with("log.") {
+            self.fields.insert(field.name().into(), value);
+        }
+    }
+}
+
+impl Visit for EventFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            _ => self.insert(field, format!("{value:?}").into()),
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.into(),
+            _ => self.insert(field, value.into()),
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+}
+
+/// Formats the events in the [`LogFormat`] of the node.
+struct NodeFormat(LogFormat);
+
+impl<S, N> FormatEvent<S, N> for NodeFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, _ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        // The records of the `log` crate carry their target and level in fields.
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let fields = EventFields::new(event);
+        match self.0 {
+            LogFormat::Text => format_text(&mut writer, metadata.level(), metadata.target(), &fields),
+            LogFormat::Json => format_json(&mut writer, metadata.level(), metadata.target(), fields),
+        }
+    }
+}
+
+fn format_json(writer: &mut Writer<'_>, level: &Level, target: &str, fields: EventFields) -> fmt::Result {
+    let mut line = fields.fields;
+    line.insert("timestamp".into(), Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into());
+    line.insert("level".into(), level.to_string().into());
+    line.insert("target".into(), target.into());
+    line.insert("message".into(), fields.message.into());
+    writeln!(writer, "{}", Value::Object(line))
+}
+
+fn color(color: AnsiColor) -> Style {
+    Style::new().fg_color(Some(Color::Ansi(color)))
+}
+
+fn level_style(level: &Level) -> Style {
+    match *level {
+        Level::ERROR => color(AnsiColor::Red).bold(),
+        Level::WARN => color(AnsiColor::Yellow),
+        Level::INFO => color(AnsiColor::Green),
+        Level::DEBUG => color(AnsiColor::Blue),
+        _ => color(AnsiColor::Cyan),
+    }
+}
+
+/// The fields are left out of the text format, the messages already include them.
+fn format_text(writer: &mut Writer<'_>, level: &Level, target: &str, fields: &EventFields) -> fmt::Result {
+    let ansi = writer.has_ansi_escapes();
+    let paint = |style: Style, text: &dyn fmt::Display| {
+        if ansi {
+            format!("{style}{text}{style:#}")
+        } else {
+            text.to_string()
+        }
+    };
+    let ts = Local::now().format("%Y-%m-%d %H:%M:%S");
+    let (open, close) = (paint(color(AnsiColor::BrightBlack), &"["), paint(color(AnsiColor::BrightBlack), &"]"));
+    let message = &fields.message;
+
+    if *level == Level::INFO && target == "rpc_calls" {
+        let field = |name: &str| fields.fields.get(name);
+        if let (Some(method), Some(status), Some(res_len), Some(response_time)) = (
+            field("method").and_then(Value::as_str),
+            field("status").and_then(Value::as_i64),
+            field("res_len").and_then(Value::as_u64),
+            field("response_time").and_then(Value::as_u64),
+        ) {
+            let status = paint(color(if status == 200 { AnsiColor::Green } else { AnsiColor::Red }), &status);
+            let response_time = Duration::from_micros(response_time);
+            let time_style =
+                if response_time <= Duration::from_millis(5) { Style::new() } else { color(AnsiColor::Yellow) };
+            let response_time = paint(time_style, &format_args!("{response_time:?}"));
+            let http = paint(color(AnsiColor::Magenta), &"HTTP");
+            return writeln!(writer, "{open}{ts} {http}{close} 🌐 {method} {status} {res_len} bytes - {response_time}");
+        }
+    }
+
+    let level_name = paint(level_style(level), level);
+    if *level == Level::INFO {
+        writeln!(writer, "{open}{ts} {level_name}{close} {message}")
+    } else if *level == Level::WARN {
+        writeln!(writer, "{open}{ts} {level_name}{close} ⚠️ {message}")
+    } else if *level == Level::ERROR && target == "rpc_errors" {
+        writeln!(writer, "{open}{ts} {level_name}{close} ❗ {message}")
+    } else if *level == Level::ERROR {
+        writeln!(writer, "{open}{ts} {level_name} {target}{close} ❗ {message}")
+    } else {
+        writeln!(writer, "{open}{ts} {level_name} {target}{close} {message}")
+    }
+}
+
+/// A log file that is rotated once it reaches `max_size`: `deoxys.log` is renamed `deoxys.log.1`, the previous
+/// `deoxys.log.1` becomes `deoxys.log.2`, and so on. Only the last `max_files` rotated files are kept.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path: path.to_owned(), file, size, max_size, max_files })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let rotated = self.rotated_path(index);
+                if rotated.exists() {
+                    fs::rename(rotated, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The events are written all at once, a line is never split between two files.
+        if self.size > 0 && self.size.saturating_add(buf.len() as u64) > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    /// The lines logged by `f`.
+    fn capture(format: LogFormat, directives: &str, f: impl FnOnce()) -> Vec<String> {
+        // Forwards the `log` records to the subscriber of the test.
+        let _ = tracing_log::LogTracer::init();
+        let captured = Captured::default();
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(captured.clone())
+            .with_ansi(false)
+            .event_format(NodeFormat(format));
+        let subscriber = tracing_subscriber::registry().with(EnvFilter::new(directives)).with(layer);
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(String::from).collect()
+    }
+
+    fn log_import() {
+        tracing::info!(target: "dc_sync::l2", block_number = 12u64, "✨ Imported #12 (0x1234..5678)");
+    }
+
+    #[test]
+    fn test_json_sync_line() {
+        let lines = capture(LogFormat::Json, "info", log_import);
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "dc_sync::l2");
+        assert_eq!(line["message"], "✨ Imported #12 (0x1234..5678)");
+        assert_eq!(line["block_number"], 12);
+        assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_text_sync_line() {
+        let lines = capture(LogFormat::Text, "info", log_import);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with('['), "{}", lines[0]);
+        assert!(lines[0].ends_with(" INFO] ✨ Imported #12 (0x1234..5678)"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_json_hash_fields() {
+        let log_accepted_tx = || {
+            let tx_hash = starknet_types_core::felt::Felt::from(0xabcu64);
+            tracing::debug!(
+                target: "dc_mempool",
+                tx_hash = format!("{tx_hash:#x}"),
+                nonce = 3u64,
+                "Accepted transaction {tx_hash:#x} in the mempool"
+            );
+        };
+
+        let lines = capture(LogFormat::Json, "dc_mempool=debug", log_accepted_tx);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "DEBUG");
+        assert_eq!(line["message"], "Accepted transaction 0xabc in the mempool");
+        assert_eq!((&line["tx_hash"], &line["nonce"]), (&Value::from("0xabc"), &Value::from(3)));
+
+        // The text lines only carry the message.
+        let lines = capture(LogFormat::Text, "dc_mempool=debug", log_accepted_tx);
+        assert!(lines[0].ends_with(" DEBUG dc_mempool] Accepted transaction 0xabc in the mempool"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_log_records_keep_their_target() {
+        let lines = capture(LogFormat::Json, "info", || log::warn!(target: "dc_eth::client", "L1 endpoint #0 failed"));
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "dc_eth::client");
+        assert_eq!(line["message"], "L1 endpoint #0 failed");
+        assert!(line.as_object().unwrap().keys().all(|key| !key.starts_with("log.")), "{line}");
+    }
+
+    #[test]
+    fn test_per_target_directives() {
+        let lines = capture(LogFormat::Text, "warn,dc_sync=debug", || {
+            tracing::debug!(target: "dc_sync::fetch", "fetching #12");
+            tracing::debug!(target: "librocksdb_sys", "compaction started");
+            tracing::info!(target: "dc_rpc", "serving");
+        });
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with(" DEBUG dc_sync::fetch] fetching #12"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("deoxys.log");
+        let mut file = RotatingFile::open(&path, 100, 2).unwrap();
+        for line in 0..5 {
+            file.write_all(format!("{line:>59}\n").as_bytes()).unwrap();
+        }
+
+        // One line per file, the first two lines are dropped.
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), "4");
+        assert_eq!(fs::read_to_string(temp_dir.path().join("deoxys.log.1")).unwrap().trim(), "3");
+        assert_eq!(fs::read_to_string(temp_dir.path().join("deoxys.log.2")).unwrap().trim(), "2");
+        assert!(!temp_dir.path().join("deoxys.log.3").exists());
+    }
+}
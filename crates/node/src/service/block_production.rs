@@ -1,23 +1,22 @@
 use std::sync::Arc;
 
-use dc_db::{DatabaseService, DeoxysBackend};
-use dc_mempool::{block_production::BlockProductionTask, L1DataProvider, Mempool};
+use dc_db::DatabaseService;
+use dc_mempool::block_production::BlockProductionTask;
+use dc_mempool::preview::BlockPreviewHandle;
+use dc_mempool::L1DataProvider;
 use dc_metrics::MetricsRegistry;
 use dc_telemetry::TelemetryHandle;
 use dp_utils::service::Service;
+use dp_utils::supervisor::Supervisor;
 use tokio::task::JoinSet;
 
 use crate::cli::block_production::BlockProductionParams;
 
-struct StartParams {
-    backend: Arc<DeoxysBackend>,
-    mempool: Arc<Mempool>,
-    l1_data_provider: Arc<dyn L1DataProvider>,
-}
-
 pub struct BlockProductionService {
-    start: Option<StartParams>,
+    task: Option<BlockProductionTask>,
+    preview_handle: Option<BlockPreviewHandle>,
     enabled: bool,
+    supervisor: Supervisor,
 }
 impl BlockProductionService {
     pub fn new(
@@ -27,15 +26,25 @@ impl BlockProductionService {
         l1_data_provider: Arc<dyn L1DataProvider>,
         _metrics_handle: MetricsRegistry,
         _telemetry: TelemetryHandle,
+        supervisor: Supervisor,
     ) -> anyhow::Result<Self> {
         if config.block_production_disabled {
-            return Ok(Self { start: None, enabled: false });
+            return Ok(Self { task: None, preview_handle: None, enabled: false, supervisor });
         }
 
-        Ok(Self {
-            start: Some(StartParams { backend: Arc::clone(db_service.backend()), l1_data_provider, mempool }),
-            enabled: true,
-        })
+        let backend = Arc::clone(db_service.backend());
+        let config = config.block_production_config(backend.chain_config())?;
+
+        // The task is created right away, so that the RPC server can be given a view of the blocks it produces.
+        let task = BlockProductionTask::new(backend, mempool, l1_data_provider, config)?;
+        let preview_handle = task.preview_handle();
+
+        Ok(Self { task: Some(task), preview_handle: Some(preview_handle), enabled: true, supervisor })
+    }
+
+    /// A read-only view of the block being produced, when block production is enabled.
+    pub fn preview_handle(&self) -> Option<BlockPreviewHandle> {
+        self.preview_handle.clone()
     }
 }
 
@@ -46,11 +55,12 @@ impl Service for BlockProductionService {
         if !self.enabled {
             return Ok(());
         }
-        let StartParams { backend, l1_data_provider, mempool } = self.start.take().expect("Service already started");
+        // A restart keeps producing the pending block of the failed task.
+        let task = Arc::new(tokio::sync::Mutex::new(self.task.take().expect("Service already started")));
 
-        join_set.spawn(async move {
-            BlockProductionTask::new(backend, mempool, l1_data_provider)?.block_production_task().await?;
-            Ok(())
+        self.supervisor.spawn(join_set, "block_production", move || {
+            let task = Arc::clone(&task);
+            async move { task.lock().await.block_production_task().await }
         });
 
         Ok(())
@@ -0,0 +1,25 @@
+use dc_metrics::{IntGaugeVec, MetricsRegistry, Opts, PrometheusError};
+use dp_utils::compute_pool::ComputePoolObserver;
+
+/// Exports the queue depth of the [`dp_utils::compute_pool::ComputePool`]s of the sync and the RPC.
+#[derive(Debug, Clone)]
+pub struct ComputePoolMetrics {
+    queue_depth: IntGaugeVec,
+}
+
+impl ComputePoolMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            queue_depth: registry.register(IntGaugeVec::new(
+                Opts::new("compute_pool_queue_depth", "Number of tasks waiting for a thread of a compute pool"),
+                &["pool"],
+            )?)?,
+        })
+    }
+}
+
+impl ComputePoolObserver for ComputePoolMetrics {
+    fn on_queue_depth(&self, pool: &'static str, depth: usize) {
+        self.queue_depth.with_label_values(&[pool]).set(depth as i64);
+    }
+}
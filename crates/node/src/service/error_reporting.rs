@@ -0,0 +1,183 @@
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::Context;
+use dp_utils::channel_wait_or_graceful_shutdown;
+use dp_utils::error_reporting::{ErrorReport, ErrorReporter, Severity};
+use dp_utils::service::Service;
+use governor::clock::DefaultClock;
+use governor::middleware::NoOpMiddleware;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{Quota, RateLimiter};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use url::Url;
+
+use crate::cli::ErrorReportingParams;
+
+/// Reports waiting to be posted. Reports are dropped when the webhook is too slow to keep up.
+const REPORT_QUEUE_SIZE: usize = 64;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts the reports to a webhook, from the [`ErrorReportingService`] task.
+pub struct WebhookReporter {
+    min_severity: Severity,
+    rate_limiter: RateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>,
+    sender: mpsc::Sender<ErrorReport>,
+    /// Reports dropped since the last posted one, because of the rate limit or a full queue.
+    dropped: Arc<AtomicU64>,
+}
+
+impl ErrorReporter for WebhookReporter {
+    fn report(&self, report: &ErrorReport) {
+        if report.severity < self.min_severity {
+            return;
+        }
+        if self.rate_limiter.check().is_err() || self.sender.try_send(report.clone()).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct ErrorReportingService {
+    reporter: Option<Arc<WebhookReporter>>,
+    start: Option<(Url, mpsc::Receiver<ErrorReport>)>,
+}
+
+impl ErrorReportingService {
+    pub fn new(config: &ErrorReportingParams) -> anyhow::Result<Self> {
+        let Some(endpoint) = config.error_webhook.clone() else { return Ok(Self { reporter: None, start: None }) };
+        let rate_limit = NonZeroU32::new(config.error_webhook_rate_limit)
+            .context("The error webhook rate limit must not be zero")?;
+
+        let (sender, receiver) = mpsc::channel(REPORT_QUEUE_SIZE);
+        let reporter = WebhookReporter {
+            min_severity: config.error_webhook_min_severity,
+            rate_limiter: RateLimiter::direct(Quota::per_minute(rate_limit)),
+            sender,
+            dropped: Default::default(),
+        };
+
+        Ok(Self { reporter: Some(Arc::new(reporter)), start: Some((endpoint, receiver)) })
+    }
+
+    /// The reporter to install with [`dp_utils::error_reporting::set_error_reporter`], when a webhook is configured.
+    pub fn reporter(&self) -> Option<Arc<WebhookReporter>> {
+        self.reporter.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Service for ErrorReportingService {
+    async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+        let Some((endpoint, mut receiver)) = self.start.take() else { return Ok(()) };
+        let dropped = Arc::clone(&self.reporter.as_ref().expect("Reporter is set with the webhook").dropped);
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build().context("Building http client")?;
+
+        join_set.spawn(async move {
+            while let Some(report) = channel_wait_or_graceful_shutdown(receiver.recv()).await {
+                let body = report_json(&report, dropped.swap(0, Ordering::Relaxed));
+                // Failures are only logged: reporting them would loop.
+                match client.post(endpoint.clone()).json(&body).send().await.and_then(|res| res.error_for_status()) {
+                    Ok(_) => {}
+                    Err(err) => log::warn!("Failed to post error report to the webhook: {err:#}"),
+                }
+            }
+            Ok(())
+        });
+
+        Ok(())
+    }
+}
+
+fn report_json(report: &ErrorReport, dropped_reports: u64) -> serde_json::Value {
+    serde_json::json!({
+        "node": "deoxys",
+        "version": env!("DEOXYS_BUILD_VERSION"),
+        "severity": report.severity.to_string(),
+        "source": report.source,
+        "message": report.message,
+        "timestamp": report.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        "dropped_reports": dropped_reports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn service(min_severity: Severity, rate_limit: u32) -> ErrorReportingService {
+        ErrorReportingService::new(&ErrorReportingParams {
+            error_webhook: Some("http://localhost:9999/hook".parse().unwrap()),
+            error_webhook_min_severity: min_severity,
+            error_webhook_rate_limit: rate_limit,
+        })
+        .unwrap()
+    }
+
+    fn report(severity: Severity) -> ErrorReport {
+        ErrorReport { severity, source: "test", message: "Something broke".into(), timestamp: SystemTime::now() }
+    }
+
+    #[test]
+    fn test_min_severity() {
+        let mut service = service(Severity::Error, 10);
+        let reporter = service.reporter().unwrap();
+        let (_, receiver) = service.start.as_mut().unwrap();
+
+        reporter.report(&report(Severity::Warning));
+        reporter.report(&report(Severity::Critical));
+        reporter.report(&report(Severity::Error));
+
+        assert_eq!(receiver.try_recv().unwrap().severity, Severity::Critical);
+        assert_eq!(receiver.try_recv().unwrap().severity, Severity::Error);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(reporter.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut service = service(Severity::Warning, 3);
+        let reporter = service.reporter().unwrap();
+        let (_, receiver) = service.start.as_mut().unwrap();
+
+        // An error loop only gets the first reports through.
+        for _ in 0..100 {
+            reporter.report(&report(Severity::Critical));
+        }
+
+        let mut received = 0;
+        while receiver.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 3);
+        assert_eq!(reporter.dropped.load(Ordering::Relaxed), 97);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let report = ErrorReport { timestamp, ..report(Severity::Critical) };
+        let json = report_json(&report, 4);
+        assert_eq!(json["severity"], "critical");
+        assert_eq!(json["source"], "test");
+        assert_eq!(json["message"], "Something broke");
+        assert_eq!(json["timestamp"], 1_700_000_000);
+        assert_eq!(json["dropped_reports"], 4);
+    }
+
+    #[test]
+    fn test_no_webhook() {
+        let service = ErrorReportingService::new(&ErrorReportingParams {
+            error_webhook: None,
+            error_webhook_min_severity: Severity::Critical,
+            error_webhook_rate_limit: 10,
+        })
+        .unwrap();
+        assert!(service.reporter().is_none());
+    }
+}
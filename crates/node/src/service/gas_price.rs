@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use dc_eth::client::EthereumClient;
+use dc_eth::l1_gas_price::GasPriceProvider;
+use dp_utils::service::Service;
+use dp_utils::supervisor::Supervisor;
+use tokio::task::JoinSet;
+
+use crate::cli::block_production::BlockProductionParams;
+
+/// Samples the L1 fees to compute the gas prices of the blocks produced by this node.
+pub struct GasPriceService {
+    provider: Arc<GasPriceProvider>,
+    eth_client: Option<Arc<EthereumClient>>,
+    supervisor: Supervisor,
+}
+
+impl GasPriceService {
+    pub fn new(
+        config: &BlockProductionParams,
+        eth_client: Option<Arc<EthereumClient>>,
+        supervisor: Supervisor,
+    ) -> anyhow::Result<Self> {
+        let provider = GasPriceProvider::new(config.gas_price_provider_config()?);
+        let provider = match &eth_client {
+            Some(eth_client) => provider.with_metrics(eth_client.l1_block_metrics.clone()),
+            None => {
+                log::warn!("⚠️  No L1 endpoint provided: the produced blocks will use fixed gas prices");
+                provider
+            }
+        };
+
+        Ok(Self { provider: Arc::new(provider), eth_client, supervisor })
+    }
+
+    pub fn provider(&self) -> Arc<GasPriceProvider> {
+        Arc::clone(&self.provider)
+    }
+}
+
+#[async_trait::async_trait]
+impl Service for GasPriceService {
+    async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+        let Some(eth_client) = self.eth_client.take() else { return Ok(()) };
+        let provider = Arc::clone(&self.provider);
+
+        self.supervisor.spawn(join_set, "gas_prices", move || {
+            let (provider, eth_client) = (Arc::clone(&provider), EthereumClient::clone(&eth_client));
+            async move { provider.run(eth_client).await }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use dc_db::{DatabaseService, DeoxysBackend};
+use dc_eth::client::EthereumClient;
+use dc_eth::l1_messaging::{sync_l1_messages, L1MessagingConfig};
+use dc_mempool::Mempool;
+use dp_utils::service::Service;
+use dp_utils::supervisor::Supervisor;
+use tokio::task::JoinSet;
+
+use crate::cli::block_production::BlockProductionParams;
+use crate::cli::SyncParams;
+
+/// Turns the messages sent from L1 into L1 handler transactions in the mempool of this node.
+pub struct L1MessagingService {
+    backend: Arc<DeoxysBackend>,
+    mempool: Arc<Mempool>,
+    eth_client: Option<Arc<EthereumClient>>,
+    config: L1MessagingConfig,
+    supervisor: Supervisor,
+}
+
+impl L1MessagingService {
+    pub fn new(
+        config: &BlockProductionParams,
+        sync_params: &SyncParams,
+        db_service: &DatabaseService,
+        mempool: Arc<Mempool>,
+        eth_client: Option<Arc<EthereumClient>>,
+        supervisor: Supervisor,
+    ) -> anyhow::Result<Self> {
+        let backend = Arc::clone(db_service.backend());
+        let l1_messaging_config = config.l1_messaging_config(sync_params)?;
+        if config.block_production_disabled {
+            return Ok(Self { backend, mempool, eth_client: None, config: l1_messaging_config, supervisor });
+        }
+
+        if eth_client.is_none() {
+            log::warn!("⚠️  No L1 endpoint provided: messages sent from L1 will not be executed");
+        }
+
+        Ok(Self { backend, mempool, eth_client, config: l1_messaging_config, supervisor })
+    }
+}
+
+#[async_trait::async_trait]
+impl Service for L1MessagingService {
+    async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+        let Some(eth_client) = self.eth_client.take() else { return Ok(()) };
+        let backend = Arc::clone(&self.backend);
+        let mempool = Arc::clone(&self.mempool);
+        let config = self.config.clone();
+
+        self.supervisor.spawn(join_set, "l1_messaging", move || {
+            let (eth_client, backend, mempool, config) =
+                (Arc::clone(&eth_client), Arc::clone(&backend), Arc::clone(&mempool), config.clone());
+            async move { sync_l1_messages(&eth_client, &backend, mempool.as_ref(), &config).await }
+        });
+
+        Ok(())
+    }
+}
@@ -1,7 +1,17 @@
 mod block_production;
+mod compute_pool;
+mod error_reporting;
+mod gas_price;
+mod l1_messaging;
 mod rpc;
+mod supervisor;
 mod sync;
 
 pub use block_production::BlockProductionService;
-pub use rpc::RpcService;
+pub use compute_pool::ComputePoolMetrics;
+pub use error_reporting::ErrorReportingService;
+pub use gas_price::GasPriceService;
+pub use l1_messaging::L1MessagingService;
+pub use rpc::{ApiKey, MethodAccess, ReadinessConfig, RpcService};
+pub use supervisor::SupervisorMetrics;
 pub use sync::SyncService;
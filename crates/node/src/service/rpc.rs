@@ -1,34 +1,59 @@
 use crate::cli::{NetworkType, RpcMethods, RpcParams};
+use anyhow::Context;
+use cors::CorsConfig;
 use dc_db::DatabaseService;
+use dc_exec::StateReadMetrics;
+use dc_mempool::preview::BlockPreviewHandle;
+use dc_mempool::Mempool;
 use dc_metrics::MetricsRegistry;
 use dc_rpc::{
-    providers::AddTransactionProvider, ChainConfig, Starknet, StarknetReadRpcApiServer, StarknetTraceRpcApiServer,
-    StarknetWriteRpcApiServer,
+    call_cache::{CallCache, CallCacheMetrics},
+    extensions::RpcExtensions,
+    fallback::{FallbackMetrics, SequencerFallback},
+    providers::AddTransactionProvider,
+    ChainConfig, ChainHandle, DeoxysReadRpcApiServer, DeoxysRpcApiServer, Starknet, StarknetReadRpcApiServer,
+    StarknetTraceRpcApiServer, StarknetWriteRpcApiServer,
 };
-use dp_convert::ToFelt;
+use dp_utils::compute_pool::ComputePool;
 use dp_utils::service::Service;
+use dp_utils::supervisor::Supervisor;
+use health::HealthChecks;
 use jsonrpsee::server::ServerHandle;
 use jsonrpsee::RpcModule;
 use metrics::RpcMetrics;
 use server::{start_server, ServerConfig};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::task::JoinSet;
 
+mod access;
+mod cors;
+mod health;
 mod metrics;
 mod middleware;
 mod server;
 
+pub use access::{ApiKey, MethodAccess};
+pub use health::ReadinessConfig;
+
 pub struct RpcService {
     server_config: Option<ServerConfig>,
     server_handle: Option<ServerHandle>,
 }
 impl RpcService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: &RpcParams,
         db: &DatabaseService,
         network_type: NetworkType,
         metrics_handle: MetricsRegistry,
         add_txs_method_provider: Arc<dyn AddTransactionProvider>,
+        mempool: Option<Arc<Mempool>>,
+        block_preview: Option<BlockPreviewHandle>,
+        extensions: RpcExtensions,
+        supervisor: Supervisor,
+        readiness: ReadinessConfig,
+        compute_pool: ComputePool,
     ) -> anyhow::Result<Self> {
         if config.rpc_disabled {
             return Ok(Self { server_config: None, server_handle: None });
@@ -36,7 +61,7 @@ impl RpcService {
 
         let mut rpc_api = RpcModule::new(());
 
-        let (rpcs, _node_operator) = match (config.rpc_methods, config.rpc_external) {
+        let (rpcs, node_operator) = match (config.rpc_methods, config.rpc_external) {
             (RpcMethods::Safe, _) => (true, false),
             (RpcMethods::Unsafe, _) => (true, true),
             (RpcMethods::Auto, false) => (true, true),
@@ -50,16 +75,49 @@ impl RpcService {
         };
         let (read, write, trace) = (rpcs, rpcs, rpcs);
 
-        let chain_config = ChainConfig {
-            chain_id: network_type.chain_id().to_felt(),
-            feeder_gateway: network_type.feeder_gateway(),
-            gateway: network_type.gateway(),
-        };
+        let chain_config =
+            ChainConfig { feeder_gateway: network_type.feeder_gateway(), gateway: network_type.gateway() };
 
-        let starknet = Starknet::new(Arc::clone(db.backend()), chain_config.clone(), add_txs_method_provider);
+        let chain = ChainHandle::from_backend(db.backend());
+        let trace_fallback =
+            SequencerFallback::from_policy(&config.trace_fallback_policy(), &chain_config, chain.chain_id());
+
+        let starknet = Starknet::new(
+            Arc::clone(db.backend()),
+            chain_config.clone(),
+            chain,
+            add_txs_method_provider,
+            mempool,
+            block_preview,
+        )
+        .with_exec_metrics(StateReadMetrics::register(&metrics_handle)?)
+        .with_node_version(env!("DEOXYS_BUILD_VERSION").into())
+        .with_suggested_gas_price_percent(config.rpc_suggested_gas_price_percent)
+        .with_limits(config.limits_config())
+        .with_compute_pool(compute_pool);
+        let call_cache_config = config.call_cache_config();
+        let starknet = match call_cache_config.capacity {
+            0 => starknet,
+            _ => starknet.with_call_cache(
+                CallCache::new(call_cache_config, db.backend().subscribe_latest_header())
+                    .with_metrics(CallCacheMetrics::register(&metrics_handle)?),
+            ),
+        };
+        let starknet = match trace_fallback {
+            Some(fallback) => {
+                starknet.with_trace_fallback(fallback.with_metrics(FallbackMetrics::register(&metrics_handle)?))
+            }
+            None => {
+                log::info!(
+                    "🔒 Sequencer trace fallback disabled, transactions older than Starknet 0.13.0 cannot be traced"
+                );
+                starknet
+            }
+        };
 
         if read {
             rpc_api.merge(StarknetReadRpcApiServer::into_rpc(starknet.clone()))?;
+            rpc_api.merge(DeoxysReadRpcApiServer::into_rpc(starknet.clone()))?;
         }
         if write {
             rpc_api.merge(StarknetWriteRpcApiServer::into_rpc(starknet.clone()))?;
@@ -67,6 +125,10 @@ impl RpcService {
         if trace {
             rpc_api.merge(StarknetTraceRpcApiServer::into_rpc(starknet.clone()))?;
         }
+        if node_operator {
+            rpc_api.merge(DeoxysRpcApiServer::into_rpc(starknet.clone()))?;
+        }
+        extensions.merge_into(&mut rpc_api).context("Adding the RPC extensions")?;
 
         let metrics = RpcMetrics::register(&metrics_handle)?;
 
@@ -81,10 +143,18 @@ impl RpcService {
                 message_buffer_capacity: config.rpc_message_buffer_capacity_per_connection,
                 rpc_api,
                 metrics,
-                cors: config.cors(),
+                cors: CorsConfig {
+                    allowed_origins: config.cors(),
+                    allowed_methods: config.rpc_cors_methods.clone(),
+                    allowed_headers: config.rpc_cors_headers.clone(),
+                    max_age: Duration::from_secs(config.rpc_cors_max_age),
+                },
                 rate_limit: config.rpc_rate_limit,
                 rate_limit_whitelisted_ips: config.rpc_rate_limit_whitelisted_ips.clone(),
                 rate_limit_trust_proxy_headers: config.rpc_rate_limit_trust_proxy_headers,
+                local_origin_methods: config.rpc_local_origin_methods.clone(),
+                method_access: config.method_access(),
+                health: HealthChecks::new(Arc::clone(db.backend()), supervisor, readiness),
             }),
             server_handle: None,
         })
@@ -0,0 +1,161 @@
+//! Which RPC methods a request may call, from its API key.
+//!
+//! Methods are given by name prefix, as for the localhost-only methods: `starknet_trace` covers both trace methods.
+//! Requests without a valid key may call the allowed methods which are not denied. A key unlocks the methods of its
+//! tier on top of these, even the denied ones. The methods of [`KEY_ONLY_METHODS`] are only served to the keys that
+//! unlock them.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use hyper::{Body, Request};
+
+const X_API_KEY: &str = "x-api-key";
+/// Browsers cannot set headers on websocket connections, the key is passed in the query string instead.
+const API_KEY_QUERY_PARAM: &str = "api_key";
+
+/// Methods denied to the requests without a key whatever the allowed and denied methods, as they act on the node
+/// itself.
+pub const KEY_ONLY_METHODS: &[&str] = &["deoxys_compactDatabase"];
+
+/// An API key and the methods it unlocks, written `<KEY>=<PREFIXES>` with comma separated prefixes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub key: String,
+    pub methods: Vec<String>,
+}
+
+impl FromStr for ApiKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, methods) = s.split_once('=').ok_or_else(|| format!("Expected <KEY>=<PREFIXES>, got {s:?}"))?;
+        if key.is_empty() {
+            return Err("The API key is empty".into());
+        }
+        let methods = methods.split(',').filter(|method| !method.is_empty()).map(String::from).collect();
+        Ok(Self { key: key.into(), methods })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MethodAccess {
+    /// Methods served without a key, `None` to serve all of them.
+    pub allowed: Option<Vec<String>>,
+    /// Methods never served without a key.
+    pub denied: Vec<String>,
+    /// Methods unlocked by each key.
+    pub keys: HashMap<String, Vec<String>>,
+}
+
+fn matches(prefixes: &[String], method: &str) -> bool {
+    prefixes.iter().any(|prefix| method.starts_with(prefix.as_str()))
+}
+
+impl MethodAccess {
+    pub fn new(allowed: Option<Vec<String>>, denied: Vec<String>, keys: Vec<ApiKey>) -> Self {
+        Self { allowed, denied, keys: keys.into_iter().map(|ApiKey { key, methods }| (key, methods)).collect() }
+    }
+
+    /// An unknown key is ignored: the request gets the methods served without a key.
+    pub fn is_allowed(&self, api_key: Option<&str>, method: &str) -> bool {
+        if api_key.and_then(|key| self.keys.get(key)).is_some_and(|methods| matches(methods, method)) {
+            return true;
+        }
+        if KEY_ONLY_METHODS.contains(&method) {
+            return false;
+        }
+        self.allowed.as_deref().map_or(true, |allowed| matches(allowed, method)) && !matches(&self.denied, method)
+    }
+}
+
+/// The key of the `x-api-key` header, or of the `api_key` query parameter.
+pub fn request_api_key(req: &Request<Body>) -> Option<String> {
+    if let Some(key) = req.headers().get(X_API_KEY).and_then(|key| key.to_str().ok()) {
+        return Some(key.into());
+    }
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|param| param.strip_prefix(API_KEY_QUERY_PARAM)?.strip_prefix('='))
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefixes(prefixes: &[&str]) -> Vec<String> {
+        prefixes.iter().map(|prefix| prefix.to_string()).collect()
+    }
+
+    #[test]
+    fn denied_methods_need_a_key() {
+        let access = MethodAccess::new(
+            None,
+            prefixes(&["starknet_trace", "starknet_add"]),
+            vec!["trace-key=starknet_trace".parse().unwrap(), "admin-key=starknet_,deoxys_".parse().unwrap()],
+        );
+
+        assert!(access.is_allowed(None, "starknet_getNonce"));
+        assert!(!access.is_allowed(None, "starknet_traceTransaction"));
+        assert!(!access.is_allowed(None, "starknet_addInvokeTransaction"));
+        assert!(!access.is_allowed(Some("unknown-key"), "starknet_traceTransaction"));
+
+        // The trace tier only unlocks the traces.
+        assert!(access.is_allowed(Some("trace-key"), "starknet_traceTransaction"));
+        assert!(access.is_allowed(Some("trace-key"), "starknet_getNonce"));
+        assert!(!access.is_allowed(Some("trace-key"), "starknet_addInvokeTransaction"));
+        assert!(access.is_allowed(Some("admin-key"), "starknet_addInvokeTransaction"));
+    }
+
+    #[test]
+    fn allowlist_restricts_the_requests_without_a_key() {
+        let access = MethodAccess::new(
+            Some(prefixes(&["starknet_get", "starknet_blockNumber"])),
+            vec![],
+            vec!["key=deoxys_".parse().unwrap()],
+        );
+        assert!(access.is_allowed(None, "starknet_getNonce"));
+        assert!(access.is_allowed(None, "starknet_blockNumber"));
+        assert!(!access.is_allowed(None, "starknet_call"));
+        assert!(!access.is_allowed(None, "deoxys_status"));
+        assert!(access.is_allowed(Some("key"), "deoxys_status"));
+        assert!(!access.is_allowed(Some("key"), "starknet_call"));
+
+        assert!(MethodAccess::default().is_allowed(None, "starknet_call"));
+    }
+
+    #[test]
+    fn key_only_methods_need_a_key_that_unlocks_them() {
+        let access = MethodAccess::new(
+            None,
+            vec![],
+            vec!["reader=starknet_".parse().unwrap(), "admin=deoxys_".parse().unwrap()],
+        );
+        assert!(access.is_allowed(None, "deoxys_setCompactionSchedule"));
+        assert!(!access.is_allowed(None, "deoxys_compactDatabase"));
+        assert!(!access.is_allowed(Some("reader"), "deoxys_compactDatabase"));
+        assert!(access.is_allowed(Some("admin"), "deoxys_compactDatabase"));
+    }
+
+    #[test]
+    fn parse_api_key() {
+        assert_eq!(
+            "abc=starknet_trace,deoxys_".parse::<ApiKey>().unwrap(),
+            ApiKey { key: "abc".into(), methods: prefixes(&["starknet_trace", "deoxys_"]) }
+        );
+        assert!("abc".parse::<ApiKey>().is_err());
+        assert!("=starknet_".parse::<ApiKey>().is_err());
+    }
+
+    #[test]
+    fn api_key_from_header_or_query() {
+        let req = Request::builder().uri("/").header("x-api-key", "abc").body(Body::empty()).unwrap();
+        assert_eq!(request_api_key(&req).as_deref(), Some("abc"));
+        let req = Request::builder().uri("/?foo=bar&api_key=abc").body(Body::empty()).unwrap();
+        assert_eq!(request_api_key(&req).as_deref(), Some("abc"));
+        let req = Request::builder().uri("/?api_keys=abc").body(Body::empty()).unwrap();
+        assert_eq!(request_api_key(&req), None);
+    }
+}
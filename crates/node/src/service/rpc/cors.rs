@@ -0,0 +1,131 @@
+//! CORS and request-origin policies of the RPC server.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// CORS configuration of the RPC server.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// Allowed origins. Each entry may contain `*` wildcards, such as `http://localhost:*`.
+    /// `None` means every origin is allowed.
+    pub allowed_origins: Option<Vec<String>>,
+    /// HTTP methods advertised in preflight responses.
+    pub allowed_methods: Vec<String>,
+    /// Request headers advertised in preflight responses.
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache preflight responses.
+    pub max_age: Duration,
+}
+
+impl CorsConfig {
+    /// Whether requests coming from this `Origin` header value may be served at all.
+    pub fn is_origin_allowed(&self, origin: &HeaderValue) -> bool {
+        let Some(allowed_origins) = &self.allowed_origins else { return true };
+        let Ok(origin) = origin.to_str() else { return false };
+        allowed_origins.iter().any(|pattern| wildcard_match(pattern, origin))
+    }
+
+    pub fn layer(&self) -> anyhow::Result<CorsLayer> {
+        let methods = self
+            .allowed_methods
+            .iter()
+            .map(|method| Method::from_str(method).with_context(|| format!("Invalid CORS method: {method:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let headers = self
+            .allowed_headers
+            .iter()
+            .map(|header| HeaderName::from_str(header).with_context(|| format!("Invalid CORS header: {header:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let allow_origin = match &self.allowed_origins {
+            None => AllowOrigin::any(),
+            Some(_) => {
+                let this = self.clone();
+                AllowOrigin::predicate(move |origin, _| this.is_origin_allowed(origin))
+            }
+        };
+
+        Ok(CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .max_age(self.max_age))
+    }
+}
+
+impl std::fmt::Display for CorsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.allowed_origins {
+            Some(origins) => write!(f, "{:?}", origins),
+            None => write!(f, "{:?}", ["*"]),
+        }
+    }
+}
+
+/// Whether the `Origin` header value designates a page served from this machine.
+pub fn is_local_origin(origin: &HeaderValue) -> bool {
+    let Some(url) = origin.to_str().ok().and_then(|origin| url::Url::parse(origin).ok()) else { return false };
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+}
+
+/// Glob-like matching where `*` matches any sequence of characters, including an empty one.
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let (pattern, value) = (pattern.as_bytes(), value.as_bytes());
+    let (mut p, mut v) = (0, 0);
+    // Position of the last `*` in the pattern, and the value position it is currently matched up to.
+    let mut backtrack = None;
+
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == value[v] {
+            p += 1;
+            v += 1;
+        } else if let Some((star_p, star_v)) = backtrack {
+            // Let the last `*` swallow one more character.
+            backtrack = Some((star_p, star_v + 1));
+            p = star_p + 1;
+            v = star_v + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*", "https://example.com", true)]
+    #[case("http://localhost:*", "http://localhost:3000", true)]
+    #[case("http://localhost:*", "http://localhost.evil.com:3000", false)]
+    #[case("http://localhost:*", "https://localhost:3000", false)]
+    #[case("https://*.example.com", "https://app.example.com", true)]
+    #[case("https://*.example.com", "https://example.com", false)]
+    #[case("https://example.com", "https://example.com", true)]
+    #[case("https://example.com", "https://example.com.evil.com", false)]
+    fn test_wildcard_match(#[case] pattern: &str, #[case] value: &str, #[case] expected: bool) {
+        assert_eq!(wildcard_match(pattern, value), expected);
+    }
+
+    #[rstest]
+    #[case("http://localhost:3000", true)]
+    #[case("http://127.0.0.1", true)]
+    #[case("http://[::1]:8080", true)]
+    #[case("https://localhost.evil.com", false)]
+    #[case("null", false)]
+    fn test_is_local_origin(#[case] origin: &str, #[case] expected: bool) {
+        assert_eq!(is_local_origin(&HeaderValue::from_str(origin).unwrap()), expected);
+    }
+}
@@ -0,0 +1,112 @@
+//! `GET /health` and `GET /ready`, for the liveness and readiness probes of orchestrators.
+//!
+//! `/health` fails when the node cannot do anything useful anymore: the database cannot be read, or one of its
+//! subsystems failed too many times and is not restarted. `/ready` fails while the node should not receive traffic
+//! because its answers would be stale: the sync is too far behind the feeder gateway, or the L1 endpoint was not
+//! reached for too long. Both answer a JSON body describing each check.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dc_db::DeoxysBackend;
+use dp_utils::supervisor::Supervisor;
+use hyper::{Body, Response, StatusCode};
+use serde_json::{json, Map, Value};
+
+/// Thresholds of `GET /ready`. A `None` threshold disables its check.
+#[derive(Debug, Clone, Default)]
+pub struct ReadinessConfig {
+    /// The node is not ready when it is more than this many blocks behind the highest block known by the sync.
+    pub max_blocks_behind: Option<u64>,
+    /// The node is not ready when the L1 worker did not reach its endpoint for this long.
+    pub l1_max_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthChecks {
+    backend: Arc<DeoxysBackend>,
+    supervisor: Supervisor,
+    readiness: ReadinessConfig,
+}
+
+impl HealthChecks {
+    pub fn new(backend: Arc<DeoxysBackend>, supervisor: Supervisor, readiness: ReadinessConfig) -> Self {
+        Self { backend, supervisor, readiness }
+    }
+
+    /// The checks of `GET /health`.
+    pub fn liveness(&self) -> HealthReport {
+        let mut report = HealthReport::default();
+
+        match self.backend.check_readable() {
+            Ok(()) => report.check("database", true, json!({})),
+            Err(err) => report.check("database", false, json!({ "error": format!("{err:#}") })),
+        }
+
+        let degraded = self.supervisor.degraded();
+        report.check("subsystems", degraded.is_empty(), json!({ "degraded": degraded }));
+
+        report
+    }
+
+    /// The checks of `GET /ready`.
+    pub fn readiness(&self) -> HealthReport {
+        let mut report = HealthReport::default();
+        let sync_status = self.backend.sync_status();
+
+        if let Some(max_blocks_behind) = self.readiness.max_blocks_behind {
+            // The node is not ready before the sync knows where the tip of the chain is.
+            let chain_head = self.backend.chain_head().get();
+            let blocks_behind = chain_head.blocks_behind();
+            report.check(
+                "sync",
+                blocks_behind.is_some_and(|behind| behind <= max_blocks_behind),
+                json!({
+                    "current_block": chain_head.latest_block_n(),
+                    "highest_known_block": chain_head.highest_known,
+                    "blocks_behind": blocks_behind,
+                    "max_blocks_behind": max_blocks_behind,
+                }),
+            );
+        }
+
+        if let Some(l1_max_age) = self.readiness.l1_max_age {
+            let since_last_connected = sync_status.l1_last_connected().map(|at| at.elapsed());
+            report.check(
+                "l1",
+                since_last_connected.is_some_and(|elapsed| elapsed <= l1_max_age),
+                json!({
+                    "secs_since_last_connected": since_last_connected.map(|elapsed| elapsed.as_secs()),
+                    "max_secs_since_last_connected": l1_max_age.as_secs(),
+                }),
+            );
+        }
+
+        report
+    }
+}
+
+/// Outcome of the checks of an endpoint: `200 OK` when all of them pass, `503 Service Unavailable` otherwise.
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    failed: bool,
+    checks: Map<String, Value>,
+}
+
+impl HealthReport {
+    fn check(&mut self, name: &str, ok: bool, mut details: Value) {
+        self.failed |= !ok;
+        details["ok"] = ok.into();
+        self.checks.insert(name.into(), details);
+    }
+
+    pub fn into_response(self) -> Result<Response<Body>, hyper::http::Error> {
+        let (status, label) =
+            if self.failed { (StatusCode::SERVICE_UNAVAILABLE, "unavailable") } else { (StatusCode::OK, "ok") };
+        let body = json!({ "status": label, "checks": self.checks });
+        Response::builder()
+            .status(status)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+    }
+}
@@ -13,6 +13,7 @@ use jsonrpsee::server::middleware::rpc::RpcServiceT;
 use jsonrpsee::types::{ErrorObject, Request};
 use jsonrpsee::MethodResponse;
 
+use super::access::MethodAccess;
 pub use super::metrics::{Metrics, RpcMetrics};
 
 /// Rate limit middleware
@@ -36,6 +37,8 @@ const MAX_RETRIES: usize = 10;
 pub struct MiddlewareLayer {
     rate_limit: Option<RateLimit>,
     metrics: Option<Metrics>,
+    restricted_methods: Option<Arc<[String]>>,
+    method_access: Option<(Arc<MethodAccess>, Option<Arc<str>>)>,
 }
 
 impl MiddlewareLayer {
@@ -45,12 +48,22 @@ impl MiddlewareLayer {
 
     /// Enable new rate limit middleware enforced per minute.
     pub fn with_rate_limit_per_minute(self, n: NonZeroU32) -> Self {
-        Self { rate_limit: Some(RateLimit::new(n)), metrics: self.metrics }
+        Self { rate_limit: Some(RateLimit::new(n)), ..self }
     }
 
     /// Enable metrics middleware.
     pub fn with_metrics(self, metrics: Metrics) -> Self {
-        Self { rate_limit: self.rate_limit, metrics: Some(metrics) }
+        Self { metrics: Some(metrics), ..self }
+    }
+
+    /// Reject calls to methods whose name starts with one of these prefixes.
+    pub fn with_restricted_methods(self, prefixes: Arc<[String]>) -> Self {
+        Self { restricted_methods: Some(prefixes), ..self }
+    }
+
+    /// Reject calls to the methods which are not allowed with the API key of the request.
+    pub fn with_method_access(self, access: Arc<MethodAccess>, api_key: Option<Arc<str>>) -> Self {
+        Self { method_access: Some((access, api_key)), ..self }
     }
 
     /// Register a new websocket connection.
@@ -72,7 +85,13 @@ impl<S> tower::Layer<S> for MiddlewareLayer {
     type Service = Middleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        Middleware { service, rate_limit: self.rate_limit.clone(), metrics: self.metrics.clone() }
+        Middleware {
+            service,
+            rate_limit: self.rate_limit.clone(),
+            metrics: self.metrics.clone(),
+            restricted_methods: self.restricted_methods.clone(),
+            method_access: self.method_access.clone(),
+        }
     }
 }
 
@@ -80,6 +99,8 @@ pub struct Middleware<S> {
     service: S,
     rate_limit: Option<RateLimit>,
     metrics: Option<Metrics>,
+    restricted_methods: Option<Arc<[String]>>,
+    method_access: Option<(Arc<MethodAccess>, Option<Arc<str>>)>,
 }
 
 impl<'a, S> RpcServiceT<'a> for Middleware<S>
@@ -98,8 +119,23 @@ where
         let service = self.service.clone();
         let rate_limit = self.rate_limit.clone();
         let metrics = self.metrics.clone();
+        let restricted_methods = self.restricted_methods.clone();
+        let method_access = self.method_access.clone();
 
         async move {
+            if restricted_methods
+                .is_some_and(|prefixes| prefixes.iter().any(|prefix| req.method_name().starts_with(prefix.as_str())))
+            {
+                return MethodResponse::error(
+                    req.id,
+                    ErrorObject::owned(-32601, "Method is only available from localhost origins", None::<()>),
+                );
+            }
+
+            if method_access.is_some_and(|(access, key)| !access.is_allowed(key.as_deref(), req.method_name())) {
+                return MethodResponse::error(req.id, ErrorObject::owned(-32604, "Method not allowed", None::<()>));
+            }
+
             let mut is_rate_limited = false;
 
             if let Some(limit) = rate_limit.as_ref() {
@@ -132,12 +168,12 @@ where
             let res_len = rp.as_result().len();
             let response_time = now.elapsed();
 
-            log::info!(
+            tracing::info!(
                 target: "rpc_calls",
-                method = method,
-                status = status,
-                res_len = res_len,
-                response_time = response_time.as_micros();
+                method,
+                status,
+                res_len,
+                response_time = response_time.as_micros() as u64,
                 "{method} {status} {res_len} - {response_time:?}",
             );
 
@@ -5,12 +5,17 @@ use std::convert::Infallible;
 use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU32;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
+use super::access::{request_api_key, MethodAccess};
+use super::cors::{is_local_origin, CorsConfig};
+use super::health::HealthChecks;
+use super::middleware::{Metrics, MiddlewareLayer, RpcMetrics};
 use anyhow::Context;
 use dp_utils::wait_or_graceful_shutdown;
 use forwarded_header_value::ForwardedHeaderValue;
-use hyper::header::{HeaderName, HeaderValue};
+use hyper::header::{HeaderName, ORIGIN};
 use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, StatusCode};
@@ -23,9 +28,6 @@ use jsonrpsee::{Methods, RpcModule};
 use tokio::net::TcpListener;
 use tokio::task::JoinSet;
 use tower::Service;
-use tower_http::cors::{AllowOrigin, CorsLayer};
-
-use super::middleware::{Metrics, MiddlewareLayer, RpcMetrics};
 
 const MEGABYTE: u32 = 1024 * 1024;
 
@@ -33,7 +35,7 @@ const MEGABYTE: u32 = 1024 * 1024;
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub addr: SocketAddr,
-    pub cors: Option<Vec<String>>,
+    pub cors: CorsConfig,
     pub max_connections: u32,
     pub max_subs_per_conn: u32,
     pub max_payload_in_mb: u32,
@@ -49,6 +51,12 @@ pub struct ServerConfig {
     pub rate_limit_whitelisted_ips: Vec<IpNetwork>,
     /// Trust proxy headers for rate limiting.
     pub rate_limit_trust_proxy_headers: bool,
+    /// Method name prefixes only served to browser requests coming from a localhost origin.
+    pub local_origin_methods: Vec<String>,
+    /// Methods served depending on the API key of the request.
+    pub method_access: MethodAccess,
+    /// Served on `GET /health` and `GET /ready`.
+    pub health: HealthChecks,
 }
 
 #[derive(Debug, Clone)]
@@ -78,20 +86,25 @@ pub async fn start_server(
         rate_limit,
         rate_limit_whitelisted_ips,
         rate_limit_trust_proxy_headers,
+        local_origin_methods,
+        method_access,
+        health,
     } = config;
+    let local_origin_methods: Arc<[String]> = local_origin_methods.into();
+    let method_access = Arc::new(method_access);
 
     let std_listener = TcpListener::bind(addr)
         .await
         .and_then(|a| a.into_std())
         .with_context(|| format!("binding to address: {addr}"))?;
     let local_addr = std_listener.local_addr().ok();
-    let host_filter = host_filtering(cors.is_some(), local_addr);
+    let host_filter = host_filtering(cors.allowed_origins.is_some(), local_addr);
 
     let http_middleware = tower::ServiceBuilder::new()
 		.option_layer(host_filter)
 		// Proxy `GET /health` requests to internal `system_health` method.
 		// .layer(ProxyGetRequestLayer::new("/health", "system_health")?)
-		.layer(try_into_cors(cors.as_ref())?);
+		.layer(cors.layer()?);
 
     let builder = jsonrpsee::server::Server::builder()
         .max_request_body_size(max_payload_in_mb.saturating_mul(MEGABYTE))
@@ -117,9 +130,14 @@ pub async fn start_server(
         stop_handle: stop_handle.clone(),
     };
 
+    let cors_policy = cors.clone();
     let make_service = make_service_fn(move |addr: &AddrStream| {
         let cfg = cfg.clone();
         let rate_limit_whitelisted_ips = rate_limit_whitelisted_ips.clone();
+        let cors = cors_policy.clone();
+        let local_origin_methods = Arc::clone(&local_origin_methods);
+        let method_access = Arc::clone(&method_access);
+        let health = health.clone();
         let ip = addr.remote_addr().ip();
 
         async move {
@@ -127,6 +145,20 @@ pub async fn start_server(
             let rate_limit_whitelisted_ips = rate_limit_whitelisted_ips.clone();
 
             Ok::<_, Infallible>(service_fn(move |req| {
+                // This applies to websocket upgrade requests too: browsers do not enforce CORS on websockets.
+                let origin = req.headers().get(ORIGIN);
+                let origin_allowed = origin.map_or(true, |origin| cors.is_origin_allowed(origin));
+                // Requests without an origin do not come from a browser page, so they are not restricted here.
+                let restrict_methods =
+                    !local_origin_methods.is_empty() && origin.is_some_and(|origin| !is_local_origin(origin));
+                let api_key: Option<Arc<str>> = request_api_key(&req).map(Into::into);
+
+                let health_report = match req.uri().path() {
+                    "/health" => Some(health.liveness()),
+                    "/ready" => Some(health.readiness()),
+                    _ => None,
+                };
+
                 let proxy_ip = if rate_limit_trust_proxy_headers { get_proxy_ip(&req) } else { None };
 
                 let rate_limit_cfg = if rate_limit_whitelisted_ips
@@ -153,14 +185,22 @@ pub async fn start_server(
                         .with_metrics(Metrics::new(metrics, transport_label))
                         .with_rate_limit_per_minute(rate_limit),
                 };
+                let middleware_layer = if restrict_methods {
+                    middleware_layer.with_restricted_methods(Arc::clone(&local_origin_methods))
+                } else {
+                    middleware_layer
+                };
+                let middleware_layer = middleware_layer.with_method_access(Arc::clone(&method_access), api_key);
 
                 let rpc_middleware = RpcServiceBuilder::new().layer(middleware_layer.clone());
 
                 let mut svc = service_builder.set_rpc_middleware(rpc_middleware).build(methods, stop_handle);
 
                 async move {
-                    if req.uri().path() == "/health" {
-                        Ok(Response::builder().status(StatusCode::OK).body(Body::from("OK"))?)
+                    if !origin_allowed {
+                        Ok(Response::builder().status(StatusCode::FORBIDDEN).body(Body::from("Origin not allowed"))?)
+                    } else if let Some(health_report) = health_report {
+                        Ok(health_report.into_response()?)
                     } else {
                         if is_websocket {
                             let on_disconnect = svc.on_session_closed();
@@ -189,7 +229,7 @@ pub async fn start_server(
         log::info!(
             "📱 Running JSON-RPC server at {} (allowed origins={})",
             local_addr.map_or_else(|| "unknown".to_string(), |a| a.to_string()),
-            format_cors(cors.as_ref())
+            cors
         );
         server
             .with_graceful_shutdown(async {
@@ -236,27 +276,6 @@ pub(crate) fn build_rpc_api<M: Send + Sync + 'static>(mut rpc_api: RpcModule<M>)
     rpc_api
 }
 
-pub(crate) fn try_into_cors(maybe_cors: Option<&Vec<String>>) -> anyhow::Result<CorsLayer> {
-    if let Some(cors) = maybe_cors {
-        let mut list = Vec::new();
-        for origin in cors {
-            list.push(HeaderValue::from_str(origin)?);
-        }
-        Ok(CorsLayer::new().allow_origin(AllowOrigin::list(list)))
-    } else {
-        // allow all cors
-        Ok(CorsLayer::permissive())
-    }
-}
-
-pub(crate) fn format_cors(maybe_cors: Option<&Vec<String>>) -> String {
-    if let Some(cors) = maybe_cors {
-        format!("{:?}", cors)
-    } else {
-        format!("{:?}", ["*"])
-    }
-}
-
 /// Extracts the IP addr from the HTTP request.
 ///
 /// It is extracted in the following order:
@@ -291,3 +310,283 @@ pub(crate) fn get_proxy_ip(req: &Request<hyper::Body>) -> Option<IpAddr> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use dc_db::testing::temp_database;
+    use dc_db::DeoxysBackend;
+    use dc_metrics::MetricsService;
+    use dp_utils::supervisor::{Supervisor, SupervisorConfig};
+    use jsonrpsee::server::ServerHandle;
+    use reqwest::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+        ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+    };
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::service::{ApiKey, ReadinessConfig};
+
+    struct TestServer {
+        addr: SocketAddr,
+        backend: Arc<DeoxysBackend>,
+        /// Number of calls to `starknet_traceTransaction` which were executed.
+        traces: Arc<AtomicUsize>,
+        _handle: ServerHandle,
+        _join_set: JoinSet<anyhow::Result<()>>,
+        _temp_dir: TempDir,
+    }
+
+    async fn start_test_server() -> TestServer {
+        start_test_server_with_health(Supervisor::default(), ReadinessConfig::default()).await
+    }
+
+    async fn start_test_server_with_health(supervisor: Supervisor, readiness: ReadinessConfig) -> TestServer {
+        start_test_server_with(supervisor, readiness, MethodAccess::default()).await
+    }
+
+    async fn start_test_server_with(
+        supervisor: Supervisor,
+        readiness: ReadinessConfig,
+        method_access: MethodAccess,
+    ) -> TestServer {
+        let (temp_dir, db) = temp_database().await;
+        let backend = Arc::clone(db.backend());
+
+        // Reserve a free port.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let mut rpc_api = RpcModule::new(());
+        rpc_api.register_method("starknet_ping", |_, _| "pong").unwrap();
+        rpc_api.register_method("deoxys_ping", |_, _| "pong").unwrap();
+        let traces = Arc::new(AtomicUsize::new(0));
+        let traces_ = Arc::clone(&traces);
+        rpc_api
+            .register_method("starknet_traceTransaction", move |_, _| {
+                traces_.fetch_add(1, Ordering::SeqCst);
+                "trace"
+            })
+            .unwrap();
+
+        let config = ServerConfig {
+            addr,
+            cors: CorsConfig {
+                allowed_origins: Some(vec!["http://localhost:*".into(), "https://*.example.com".into()]),
+                allowed_methods: vec!["GET".into(), "POST".into()],
+                allowed_headers: vec!["content-type".into()],
+                max_age: Duration::from_secs(600),
+            },
+            max_connections: 10,
+            max_subs_per_conn: 10,
+            max_payload_in_mb: 1,
+            max_payload_out_mb: 1,
+            metrics: RpcMetrics::register(&MetricsService::new(true, false, 0).unwrap().registry()).unwrap(),
+            message_buffer_capacity: 16,
+            rpc_api,
+            batch_config: BatchRequestConfig::Unlimited,
+            rate_limit: None,
+            rate_limit_whitelisted_ips: vec![],
+            rate_limit_trust_proxy_headers: false,
+            local_origin_methods: vec!["deoxys_".into()],
+            method_access,
+            health: HealthChecks::new(Arc::clone(&backend), supervisor, readiness),
+        };
+
+        let mut join_set = JoinSet::new();
+        let handle = start_server(config, &mut join_set).await.unwrap();
+        TestServer { addr, backend, traces, _handle: handle, _join_set: join_set, _temp_dir: temp_dir }
+    }
+
+    async fn rpc_call(addr: SocketAddr, origin: Option<&str>, method: &str) -> reqwest::Response {
+        let mut request = reqwest::Client::new()
+            .post(format!("http://{addr}"))
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": [] }));
+        if let Some(origin) = origin {
+            request = request.header(reqwest::header::ORIGIN, origin);
+        }
+        request.send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn cors_preflight() {
+        let server = start_test_server().await;
+        let addr = server.addr;
+
+        let preflight = |origin: &'static str| {
+            reqwest::Client::new()
+                .request(reqwest::Method::OPTIONS, format!("http://{addr}"))
+                .header(reqwest::header::ORIGIN, origin)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+                .send()
+        };
+
+        let res = preflight("http://localhost:3000").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "http://localhost:3000");
+        assert!(res.headers()[ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+        assert_eq!(res.headers()[ACCESS_CONTROL_MAX_AGE], "600");
+
+        let res = preflight("https://evil.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn cross_origin_requests() {
+        let server = start_test_server().await;
+        let addr = server.addr;
+
+        let res = rpc_call(addr, Some("https://app.example.com"), "starknet_ping").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(res.json::<Value>().await.unwrap()["result"], "pong");
+
+        let res = rpc_call(addr, Some("https://evil.com"), "starknet_ping").await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Localhost-only methods.
+        let res = rpc_call(addr, Some("https://app.example.com"), "deoxys_ping").await;
+        assert_eq!(res.json::<Value>().await.unwrap()["error"]["code"], -32601);
+        let res = rpc_call(addr, Some("http://localhost:3000"), "deoxys_ping").await;
+        assert_eq!(res.json::<Value>().await.unwrap()["result"], "pong");
+        let res = rpc_call(addr, None, "deoxys_ping").await;
+        assert_eq!(res.json::<Value>().await.unwrap()["result"], "pong");
+    }
+
+    #[tokio::test]
+    async fn method_access() {
+        let api_key: ApiKey = "secret=starknet_trace".parse().unwrap();
+        let method_access = MethodAccess::new(None, vec!["starknet_trace".into()], vec![api_key]);
+        let server = start_test_server_with(Supervisor::default(), ReadinessConfig::default(), method_access).await;
+        let addr = server.addr;
+        let call = |key_header: Option<&'static str>, query: &'static str, method: &'static str| {
+            let mut request = reqwest::Client::new()
+                .post(format!("http://{addr}{query}"))
+                .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": [] }));
+            if let Some(key) = key_header {
+                request = request.header("x-api-key", key);
+            }
+            async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+        };
+
+        // Denied methods are not executed.
+        let res = call(None, "", "starknet_traceTransaction").await;
+        assert_eq!(res["error"]["code"], -32604);
+        assert_eq!(res["error"]["message"], "Method not allowed");
+        let res = call(Some("wrong"), "", "starknet_traceTransaction").await;
+        assert_eq!(res["error"]["code"], -32604);
+        assert_eq!(server.traces.load(Ordering::SeqCst), 0);
+
+        assert_eq!(call(None, "", "starknet_ping").await["result"], "pong");
+        // Never served without a key, even when it is not listed.
+        assert_eq!(call(None, "", "deoxys_compactDatabase").await["error"]["code"], -32604);
+
+        // The key unlocks its tier, from the header or the query string.
+        assert_eq!(call(Some("secret"), "", "starknet_traceTransaction").await["result"], "trace");
+        assert_eq!(call(None, "/?api_key=secret", "starknet_traceTransaction").await["result"], "trace");
+        assert_eq!(server.traces.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrade_origin() {
+        let server = start_test_server().await;
+        let addr = server.addr;
+
+        let upgrade = |origin: &'static str| {
+            reqwest::Client::new()
+                .get(format!("http://{addr}"))
+                .header(reqwest::header::ORIGIN, origin)
+                .header(reqwest::header::CONNECTION, "Upgrade")
+                .header(reqwest::header::UPGRADE, "websocket")
+                .header(reqwest::header::SEC_WEBSOCKET_VERSION, "13")
+                .header(reqwest::header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+                .send()
+        };
+
+        let res = upgrade("https://evil.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = upgrade("http://localhost:3000").await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+    }
+
+    #[tokio::test]
+    async fn health_reports_degraded_subsystems() {
+        let supervisor = Supervisor::new(SupervisorConfig { max_rapid_failures: 1, ..Default::default() });
+        let server = start_test_server_with_health(supervisor.clone(), ReadinessConfig::default()).await;
+        let addr = server.addr;
+        let health = || reqwest::get(format!("http://{addr}/health"));
+
+        let res = health().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.json::<Value>().await.unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["checks"]["database"]["ok"], true);
+
+        supervisor.supervise("l1_messaging", || async { anyhow::bail!("L1 endpoint unreachable") }).await.unwrap();
+        let res = health().await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = res.json::<Value>().await.unwrap();
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["checks"]["database"]["ok"], true);
+        assert_eq!(body["checks"]["subsystems"], json!({ "ok": false, "degraded": ["l1_messaging"] }));
+    }
+
+    #[tokio::test]
+    async fn readiness_follows_the_sync_status() {
+        let readiness = ReadinessConfig { max_blocks_behind: Some(5), l1_max_age: Some(Duration::from_secs(5 * 60)) };
+        let server = start_test_server_with_health(Supervisor::default(), readiness).await;
+        let addr = server.addr;
+        let (chain_head, sync_status) = (server.backend.chain_head(), server.backend.sync_status());
+        let ready = || async {
+            let res = reqwest::get(format!("http://{addr}/ready")).await.unwrap();
+            (res.status(), res.json::<Value>().await.unwrap())
+        };
+
+        // Nothing is known about the chain yet.
+        let (status, body) = ready().await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["sync"]["highest_known_block"], Value::Null);
+        assert_eq!(body["checks"]["l1"]["ok"], false);
+
+        // The database is empty: 4 blocks behind block 3.
+        chain_head.update_highest_known_block(3);
+        sync_status.record_l1_connected();
+        let (status, body) = ready().await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["checks"]["sync"]["blocks_behind"], 4);
+
+        // The L1 endpoint was last reached 10 minutes ago.
+        sync_status.record_l1_connected_at(std::time::Instant::now() - Duration::from_secs(10 * 60));
+        let (status, body) = ready().await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["sync"]["ok"], true);
+        assert_eq!(body["checks"]["l1"]["ok"], false);
+
+        sync_status.record_l1_connected();
+        assert_eq!(ready().await.0, StatusCode::OK);
+
+        // The feeder gateway is ahead.
+        chain_head.update_highest_known_block(10);
+        let (status, body) = ready().await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body["checks"]["sync"],
+            json!({
+                "ok": false,
+                "current_block": null,
+                "highest_known_block": 10,
+                "blocks_behind": 11,
+                "max_blocks_behind": 5,
+            })
+        );
+
+        // The liveness probe does not depend on the sync.
+        assert_eq!(reqwest::get(format!("http://{addr}/health")).await.unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,34 @@
+use dc_metrics::{CounterVec, IntGaugeVec, MetricsRegistry, Opts, PrometheusError, U64};
+use dp_utils::supervisor::{SubsystemState, SupervisorObserver};
+
+/// Exports the restarts and the degraded subsystems of the [`dp_utils::supervisor::Supervisor`].
+#[derive(Debug, Clone)]
+pub struct SupervisorMetrics {
+    restarts: CounterVec<U64>,
+    degraded: IntGaugeVec,
+}
+
+impl SupervisorMetrics {
+    pub fn register(registry: &MetricsRegistry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            restarts: registry.register(CounterVec::new(
+                Opts::new("subsystem_restarts", "Number of restarts of a subsystem after a failure"),
+                &["subsystem"],
+            )?)?,
+            degraded: registry.register(IntGaugeVec::new(
+                Opts::new("subsystem_degraded", "1 when a subsystem failed too many times and is not restarted"),
+                &["subsystem"],
+            )?)?,
+        })
+    }
+}
+
+impl SupervisorObserver for SupervisorMetrics {
+    fn on_restart(&self, subsystem: &'static str) {
+        self.restarts.with_label_values(&[subsystem]).inc();
+    }
+
+    fn on_state_change(&self, subsystem: &'static str, state: SubsystemState) {
+        self.degraded.with_label_values(&[subsystem]).set((state == SubsystemState::Degraded).into());
+    }
+}
@@ -1,14 +1,16 @@
 use crate::cli::SyncParams;
-use alloy::primitives::Address;
 use anyhow::Context;
 use dc_db::db_metrics::DbMetrics;
 use dc_db::{DatabaseService, DeoxysBackend};
 use dc_eth::client::EthereumClient;
 use dc_metrics::MetricsRegistry;
 use dc_sync::fetch::fetchers::FetchConfig;
+use dc_sync::l2::BlockImportHook;
 use dc_sync::metrics::block_metrics::BlockMetrics;
 use dc_telemetry::TelemetryHandle;
+use dp_utils::compute_pool::ComputePool;
 use dp_utils::service::Service;
+use dp_utils::supervisor::Supervisor;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinSet;
@@ -18,13 +20,16 @@ pub struct SyncService {
     db_backend: Arc<DeoxysBackend>,
     fetch_config: FetchConfig,
     backup_every_n_blocks: Option<u64>,
-    eth_client: EthereumClient,
+    eth_client: Option<Arc<EthereumClient>>,
     starting_block: Option<u64>,
     block_metrics: BlockMetrics,
     db_metrics: DbMetrics,
     start_params: Option<TelemetryHandle>,
     disabled: bool,
     pending_block_poll_interval: Duration,
+    supervisor: Supervisor,
+    compute_pool: ComputePool,
+    block_import_hook: Option<Arc<dyn BlockImportHook>>,
 }
 
 impl SyncService {
@@ -33,28 +38,24 @@ impl SyncService {
         db: &DatabaseService,
         metrics_handle: MetricsRegistry,
         telemetry: TelemetryHandle,
+        supervisor: Supervisor,
+        compute_pool: ComputePool,
+        block_import_hook: Option<Arc<dyn BlockImportHook>>,
     ) -> anyhow::Result<Self> {
         // TODO: create l1 metrics here
         let block_metrics = BlockMetrics::register(&metrics_handle)?;
         let db_metrics = DbMetrics::register(&metrics_handle)?;
-        let fetch_config = config.block_fetch_config();
+        let chain_config = db.backend().chain_config();
+        let fetch_config = config.block_fetch_config(chain_config);
 
-        let l1_endpoint = if !config.sync_l1_disabled {
-            if let Some(l1_rpc_url) = &config.l1_endpoint {
-                Some(l1_rpc_url.clone())
-            } else {
-                return Err(anyhow::anyhow!(
-                    "❗ No L1 endpoint provided. You must provide one in order to verify the synced state."
-                ));
-            }
-        } else {
-            None
-        };
+        if !config.sync_l1_disabled && config.l1_endpoint.is_empty() {
+            return Err(anyhow::anyhow!(
+                "❗ No L1 endpoint provided. You must provide one in order to verify the synced state."
+            ));
+        }
 
-        let core_address = Address::from_slice(config.network.l1_core_address().as_bytes());
-        let eth_client = EthereumClient::new(l1_endpoint.unwrap(), core_address, metrics_handle)
-            .await
-            .context("Creating ethereum client")?;
+        // There is no client when the L1 is not followed, the node may not have an endpoint.
+        let eth_client = crate::util::eth_client(config, chain_config, metrics_handle).await?;
 
         Ok(Self {
             db_backend: Arc::clone(db.backend()),
@@ -67,6 +68,9 @@ impl SyncService {
             start_params: Some(telemetry),
             disabled: config.sync_disabled,
             pending_block_poll_interval: Duration::from_secs(config.pending_block_poll_interval),
+            supervisor,
+            compute_pool,
+            block_import_hook,
         })
     }
 }
@@ -81,29 +85,50 @@ impl Service for SyncService {
             fetch_config,
             backup_every_n_blocks,
             eth_client,
-            starting_block,
+            mut starting_block,
             block_metrics,
             db_metrics,
             pending_block_poll_interval,
+            supervisor,
+            compute_pool,
+            block_import_hook,
             ..
         } = self.clone();
         let telemetry = self.start_params.take().context("Service already started")?;
 
-        let db_backend = Arc::clone(&self.db_backend);
+        if let Some(eth_client) = eth_client {
+            let db_backend = Arc::clone(&self.db_backend);
+            let l1_fetch_config = fetch_config.clone();
+            supervisor.spawn(join_set, "l1_state_updates", move || {
+                let (db_backend, fetch_config, eth_client) =
+                    (Arc::clone(&db_backend), l1_fetch_config.clone(), Arc::clone(&eth_client));
+                async move { dc_sync::starknet_sync_worker::l1_sync(&db_backend, &fetch_config, &eth_client).await }
+            });
+        } else {
+            log::info!("⏭️ L1 sync is disabled, the synced blocks will not be verified against the L1");
+        }
 
-        join_set.spawn(async move {
-            dc_sync::starknet_sync_worker::sync(
-                &db_backend,
-                fetch_config,
-                eth_client,
-                starting_block,
-                backup_every_n_blocks,
-                block_metrics,
-                db_metrics,
-                telemetry,
-                pending_block_poll_interval,
-            )
-            .await
+        let db_backend = Arc::clone(&self.db_backend);
+        supervisor.spawn(join_set, "l2_sync", move || {
+            let (db_backend, fetch_config) = (Arc::clone(&db_backend), fetch_config.clone());
+            let (block_metrics, db_metrics, telemetry) = (block_metrics.clone(), db_metrics.clone(), telemetry.clone());
+            // A restart resumes from the database tip.
+            let starting_block = starting_block.take();
+            async move {
+                dc_sync::starknet_sync_worker::l2_sync(
+                    &db_backend,
+                    &fetch_config,
+                    starting_block,
+                    backup_every_n_blocks,
+                    block_metrics,
+                    db_metrics,
+                    telemetry,
+                    pending_block_poll_interval,
+                    block_import_hook.clone(),
+                    compute_pool.clone(),
+                )
+                .await
+            }
         });
 
         Ok(())
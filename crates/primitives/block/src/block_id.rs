@@ -0,0 +1,141 @@
+//! Block ids given by users, on the command line or as strings in RPC parameters: a decimal block number, a `0x`
+//! prefixed block hash, or one of the `latest`, `pending` and `l1_accepted` tags.
+
+use std::fmt;
+use std::str::FromStr;
+
+use starknet_types_core::felt::Felt;
+
+use crate::{BlockId, BlockTag};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ParseBlockIdError {
+    #[error("Empty block id")]
+    Empty,
+    #[error("Invalid block number `{0}`")]
+    InvalidNumber(String),
+    #[error("Invalid block hash `{0}`")]
+    InvalidHash(String),
+    #[error("Unknown block tag `{0}`, expected `latest`, `pending` or `l1_accepted`")]
+    UnknownTag(String),
+}
+
+impl FromStr for BlockTag {
+    type Err = ParseBlockIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(BlockTag::Latest),
+            "pending" => Ok(BlockTag::Pending),
+            "l1_accepted" => Ok(BlockTag::L1Accepted),
+            _ => Err(ParseBlockIdError::UnknownTag(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for BlockTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockTag::Latest => write!(f, "latest"),
+            BlockTag::Pending => write!(f, "pending"),
+            BlockTag::L1Accepted => write!(f, "l1_accepted"),
+        }
+    }
+}
+
+impl FromStr for BlockId {
+    type Err = ParseBlockIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseBlockIdError::Empty);
+        }
+
+        if let Some(hex) = s.strip_prefix("0x") {
+            if hex.is_empty() || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(ParseBlockIdError::InvalidHash(s.into()));
+            }
+            return Felt::from_hex(s).map(BlockId::Hash).map_err(|_| ParseBlockIdError::InvalidHash(s.into()));
+        }
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return s.parse().map(BlockId::Number).map_err(|_| ParseBlockIdError::InvalidNumber(s.into()));
+        }
+        s.parse().map(BlockId::Tag)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockId::Hash(hash) => write!(f, "{hash:#x}"),
+            BlockId::Number(block_n) => write!(f, "{block_n}"),
+            BlockId::Tag(tag) => write!(f, "{tag}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_block_id() {
+        assert_eq!("12".parse(), Ok(BlockId::Number(12)));
+        assert_eq!(" 0 ".parse(), Ok(BlockId::Number(0)));
+        assert_eq!("0x1a2B".parse(), Ok(BlockId::Hash(Felt::from(0x1a2b))));
+        assert_eq!("0x0".parse(), Ok(BlockId::Hash(Felt::ZERO)));
+        assert_eq!("latest".parse(), Ok(BlockId::Tag(BlockTag::Latest)));
+        assert_eq!("pending".parse(), Ok(BlockId::Tag(BlockTag::Pending)));
+        assert_eq!("l1_accepted".parse(), Ok(BlockId::Tag(BlockTag::L1Accepted)));
+    }
+
+    #[test]
+    fn test_malformed_block_ids_are_rejected() {
+        let err = |s: &str| s.parse::<BlockId>().unwrap_err();
+        assert_eq!(err(""), ParseBlockIdError::Empty);
+        assert_eq!(err("   "), ParseBlockIdError::Empty);
+        assert_eq!(err("0x"), ParseBlockIdError::InvalidHash("0x".into()));
+        assert_eq!(err("0xg1"), ParseBlockIdError::InvalidHash("0xg1".into()));
+        assert_eq!(err("0x-1"), ParseBlockIdError::InvalidHash("0x-1".into()));
+        // More than 252 bits.
+        let too_big = format!("0x1{}", "0".repeat(64));
+        assert_eq!(err(&too_big), ParseBlockIdError::InvalidHash(too_big.clone()));
+        assert_eq!(err("12a"), ParseBlockIdError::InvalidNumber("12a".into()));
+        assert_eq!(err("18446744073709551616"), ParseBlockIdError::InvalidNumber("18446744073709551616".into()));
+        assert_eq!(err("-1"), ParseBlockIdError::UnknownTag("-1".into()));
+        assert_eq!(err("Latest"), ParseBlockIdError::UnknownTag("Latest".into()));
+        assert_eq!(err("l1-accepted"), ParseBlockIdError::UnknownTag("l1-accepted".into()));
+    }
+
+    fn block_id() -> impl Strategy<Value = BlockId> {
+        prop_oneof![
+            any::<u64>().prop_map(BlockId::Number),
+            any::<[u8; 31]>().prop_map(|bytes| BlockId::Hash(Felt::from_bytes_be_slice(&bytes))),
+            prop_oneof![Just(BlockTag::Latest), Just(BlockTag::Pending), Just(BlockTag::L1Accepted)]
+                .prop_map(BlockId::Tag),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_block_id_roundtrip(block_id in block_id()) {
+            prop_assert_eq!(block_id.to_string().parse::<BlockId>(), Ok(block_id));
+        }
+
+        /// Only an explicit zero hash parses to the zero hash, malformed inputs are errors.
+        #[test]
+        fn proptest_no_zero_hash_fallback(s in "\\PC{0,80}") {
+            if let Ok(BlockId::Hash(hash)) = s.parse::<BlockId>() {
+                prop_assert!(hash != Felt::ZERO || s.trim().trim_start_matches("0x").bytes().all(|byte| byte == b'0'));
+            }
+        }
+
+        #[test]
+        fn proptest_malformed_hashes_are_rejected(hex in "0x[0-9a-f]{0,8}[g-z_\\-][0-9a-z]{0,8}") {
+            prop_assert_eq!(hex.parse::<BlockId>(), Err(ParseBlockIdError::InvalidHash(hex.clone())));
+        }
+    }
+}
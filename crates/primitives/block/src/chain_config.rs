@@ -3,9 +3,15 @@ use blockifier::{
     bouncer::{BouncerConfig, BouncerWeights, BuiltinCount},
     versioned_constants::VersionedConstants,
 };
+use primitive_types::H160;
 use starknet_api::core::{ChainId, ContractAddress, PatriciaKey};
 use starknet_core::types::Felt;
-use std::{collections::BTreeMap, ops::Deref, time::Duration};
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 const BLOCKIFIER_VERSIONED_CONSTANTS_JSON_0_13_0: &[u8] = include_bytes!("../resources/versioned_constants_13_0.json");
 const BLOCKIFIER_VERSIONED_CONSTANTS_JSON_0_13_1: &[u8] = include_bytes!("../resources/versioned_constants_13_1.json");
@@ -32,6 +38,9 @@ pub struct ChainConfig {
     /// For starknet, this is the ETH ERC-20 contract on starknet.
     pub parent_fee_token_address: ContractAddress,
 
+    /// The Starknet core contract on L1, which the state updates and the L1 to L2 messages are read from.
+    pub eth_core_contract_address: H160,
+
     /// BTreeMap ensures order.
     pub versioned_constants: BTreeMap<StarknetVersion, VersionedConstants>,
     pub latest_protocol_version: StarknetVersion,
@@ -45,6 +54,10 @@ pub struct ChainConfig {
     /// The bouncer is in charge of limiting block sizes. This is where the max number of step per block, gas etc are.
     /// Only used for block production.
     pub bouncer_config: BouncerConfig,
+    /// Maximum serialized size of the transactions and receipts of a block, in bytes. The pending block is served
+    /// over RPC on every update: this keeps its reads cheap, whatever the bouncer budget left.
+    /// Only used for block production.
+    pub max_block_size: usize,
 
     /// Only used for block production.
     pub sequencer_address: ContractAddress,
@@ -53,28 +66,198 @@ pub struct ChainConfig {
     /// When deploying an account and invoking a contract at the same time, we want to skip the validation step for the invoke tx.
     /// This number is the maximum nonce the invoke tx can have to qualify for the validation skip.
     pub max_nonce_for_validation_skip: u64,
+
+    /// Deepest reorg the sync reverts on its own, in blocks. A deeper one stops the sync until the node operator
+    /// confirms the revert with the `db force-reorg` command.
+    pub max_auto_reorg_depth: u64,
 }
 
 #[derive(thiserror::Error, Debug)]
 #[error("Unsupported protocol version: {0}")]
 pub struct UnsupportedProtocolVersion(StarknetVersion);
 
+/// Names of the built-in chain configs, for [`ChainConfig::from_preset`].
+pub const CHAIN_PRESETS: &[&str] = &["mainnet", "sepolia", "integration"];
+
+/// The versioned constants bundled with the node, which a chain spec selects from.
+fn bundled_versioned_constants() -> [(StarknetVersion, &'static VersionedConstants); 4] {
+    [
+        (StarknetVersion::STARKNET_VERSION_0_13_0, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_0.deref()),
+        (StarknetVersion::STARKNET_VERSION_0_13_1, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_1.deref()),
+        (StarknetVersion::STARKNET_VERSION_0_13_1_1, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_1_1.deref()),
+        (StarknetVersion::STARKNET_VERSION_0_13_2, VersionedConstants::latest_constants()),
+    ]
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChainConfigError {
+    #[error("Unknown chain preset `{0}`, the presets are {CHAIN_PRESETS:?}")]
+    UnknownPreset(String),
+    #[error("Reading the chain spec {}: {error}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        error: std::io::Error,
+    },
+    #[error("Parsing the chain spec: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Invalid field `{field}` of the chain spec: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+/// A chain spec, as written in its TOML file. The fields that are not in the spec take the values of the mainnet
+/// preset.
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ChainSpec {
+    chain_name: String,
+    /// Short string, such as `SN_MAIN`.
+    chain_id: String,
+    native_fee_token_address: String,
+    parent_fee_token_address: String,
+    sequencer_address: String,
+    eth_core_contract_address: String,
+    /// Defaults to the latest version the node supports.
+    latest_protocol_version: Option<String>,
+    /// The protocol versions whose bundled versioned constants the chain uses, all of them by default.
+    versioned_constants: Option<Vec<String>>,
+    block_time_ms: Option<u64>,
+    pending_block_update_time_ms: Option<u64>,
+}
+
+fn invalid(field: &'static str, reason: impl ToString) -> ChainConfigError {
+    ChainConfigError::InvalidField { field, reason: reason.to_string() }
+}
+
+fn parse_contract_address(field: &'static str, value: &str) -> Result<ContractAddress, ChainConfigError> {
+    let felt = Felt::from_hex(value).map_err(|_| invalid(field, format!("`{value}` is not a hex felt")))?;
+    let key = PatriciaKey::try_from(felt).map_err(|err| invalid(field, err))?;
+    Ok(ContractAddress(key))
+}
+
+fn parse_version(field: &'static str, value: &str) -> Result<StarknetVersion, ChainConfigError> {
+    value.parse().map_err(|err| invalid(field, err))
+}
+
+impl TryFrom<ChainSpec> for ChainConfig {
+    type Error = ChainConfigError;
+
+    fn try_from(spec: ChainSpec) -> Result<Self, Self::Error> {
+        let chain_id = match spec.chain_id.as_str() {
+            "" => return Err(invalid("chain_id", "empty chain id")),
+            id if !id.is_ascii() || id.len() > 31 => {
+                return Err(invalid("chain_id", "the chain id must be a short string of at most 31 ascii characters"))
+            }
+            "SN_MAIN" => ChainId::Mainnet,
+            "SN_SEPOLIA" => ChainId::Sepolia,
+            "SN_INTEGRATION_SEPOLIA" => ChainId::IntegrationSepolia,
+            id => ChainId::Other(id.into()),
+        };
+
+        let eth_core_contract_address: H160 = spec.eth_core_contract_address.parse().map_err(|_| {
+            invalid("eth_core_contract_address", format!("`{}` is not an L1 address", spec.eth_core_contract_address))
+        })?;
+
+        let bundled = bundled_versioned_constants();
+        let versioned_constants: BTreeMap<_, _> = match &spec.versioned_constants {
+            None => bundled.iter().map(|(version, constants)| (*version, (*constants).clone())).collect(),
+            Some(versions) => versions
+                .iter()
+                .map(|version| {
+                    let version = parse_version("versioned_constants", version)?;
+                    let (_, constants) = bundled.iter().find(|(bundled, _)| *bundled == version).ok_or_else(|| {
+                        invalid("versioned_constants", format!("no versioned constants are bundled for {version}"))
+                    })?;
+                    Ok((version, (*constants).clone()))
+                })
+                .collect::<Result<_, ChainConfigError>>()?,
+        };
+        if versioned_constants.is_empty() {
+            return Err(invalid("versioned_constants", "no versioned constants are selected"));
+        }
+
+        let defaults = Self::starknet_mainnet();
+        let latest_protocol_version = match &spec.latest_protocol_version {
+            Some(version) => parse_version("latest_protocol_version", version)?,
+            None => defaults.latest_protocol_version,
+        };
+
+        let config = Self {
+            chain_name: spec.chain_name,
+            chain_id,
+            native_fee_token_address: parse_contract_address(
+                "native_fee_token_address",
+                &spec.native_fee_token_address,
+            )?,
+            parent_fee_token_address: parse_contract_address(
+                "parent_fee_token_address",
+                &spec.parent_fee_token_address,
+            )?,
+            eth_core_contract_address,
+            versioned_constants,
+            latest_protocol_version,
+            block_time: spec.block_time_ms.map(Duration::from_millis).unwrap_or(defaults.block_time),
+            pending_block_update_time: spec
+                .pending_block_update_time_ms
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.pending_block_update_time),
+            sequencer_address: parse_contract_address("sequencer_address", &spec.sequencer_address)?,
+            ..defaults
+        };
+
+        if config.exec_constants_by_protocol_version(latest_protocol_version).is_err() {
+            return Err(invalid(
+                "latest_protocol_version",
+                format!("no versioned constants are selected for {latest_protocol_version} or an older version"),
+            ));
+        }
+        if config.block_time.is_zero() {
+            return Err(invalid("block_time_ms", "the block time must not be zero"));
+        }
+        if config.pending_block_update_time.is_zero() || config.pending_block_update_time > config.block_time {
+            return Err(invalid(
+                "pending_block_update_time_ms",
+                "the pending block update time must not be zero nor longer than the block time",
+            ));
+        }
+        Ok(config)
+    }
+}
+
 impl ChainConfig {
-    /// This is the number of pending ticks (see [`ChainConfig::pending_block_update_time`]) in a block.
-    pub fn n_pending_ticks_per_block(&self) -> usize {
-        (self.block_time.as_millis() / self.pending_block_update_time.as_millis()) as usize
+    /// Loads a chain from its TOML spec, for chains that have no preset.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChainConfigError> {
+        let path = path.as_ref();
+        let spec =
+            std::fs::read_to_string(path).map_err(|error| ChainConfigError::Read { path: path.to_owned(), error })?;
+        Self::from_toml(&spec)
+    }
+
+    pub fn from_toml(spec: &str) -> Result<Self, ChainConfigError> {
+        toml::from_str::<ChainSpec>(spec)?.try_into()
     }
 
+    /// The built-in chain config named `name`, one of [`CHAIN_PRESETS`].
+    pub fn from_preset(name: &str) -> Result<Self, ChainConfigError> {
+        match name {
+            "mainnet" => Ok(Self::starknet_mainnet()),
+            "sepolia" => Ok(Self::starknet_sepolia()),
+            "integration" => Ok(Self::starknet_integration()),
+            _ => Err(ChainConfigError::UnknownPreset(name.into())),
+        }
+    }
+
+    /// The versioned constants a block of protocol `version` was executed with. A version without its own set uses
+    /// the set of the nearest older version.
     pub fn exec_constants_by_protocol_version(
         &self,
         version: StarknetVersion,
-    ) -> Result<VersionedConstants, UnsupportedProtocolVersion> {
-        for (k, constants) in self.versioned_constants.iter().rev() {
-            if k <= &version {
-                return Ok(constants.clone());
-            }
-        }
-        Err(UnsupportedProtocolVersion(version))
+    ) -> Result<&VersionedConstants, UnsupportedProtocolVersion> {
+        self.versioned_constants
+            .range(..=version)
+            .next_back()
+            .map(|(_, constants)| constants)
+            .ok_or(UnsupportedProtocolVersion(version))
     }
 
     pub fn starknet_mainnet() -> Self {
@@ -100,13 +283,12 @@ impl ChainConfig {
                 .unwrap(),
             ),
             chain_id: ChainId::Mainnet,
-            versioned_constants: [
-                (StarknetVersion::STARKNET_VERSION_0_13_0, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_0.deref().clone()),
-                (StarknetVersion::STARKNET_VERSION_0_13_1, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_1.deref().clone()),
-                (StarknetVersion::STARKNET_VERSION_0_13_1_1, BLOCKIFIER_VERSIONED_CONSTANTS_0_13_1_1.deref().clone()),
-                (StarknetVersion::STARKNET_VERSION_0_13_2, VersionedConstants::latest_constants().clone()),
-            ]
-            .into(),
+            // UNWRAP: this is a valid L1 address.
+            eth_core_contract_address: "0xc662c410C0ECf747543f5bA90660f6ABeBD9C8c4".parse().unwrap(),
+            versioned_constants: bundled_versioned_constants()
+                .into_iter()
+                .map(|(version, constants)| (version, constants.clone()))
+                .collect(),
             latest_protocol_version: StarknetVersion::STARKNET_VERSION_0_13_2,
             block_time: Duration::from_secs(6 * 60),
             pending_block_update_time: Duration::from_secs(2),
@@ -132,20 +314,30 @@ impl ChainConfig {
                     state_diff_size: 131072,
                 },
             },
+            max_block_size: 16 * 1024 * 1024,
             // We are not producing blocks for these chains.
             sequencer_address: ContractAddress::default(),
             max_nonce_for_validation_skip: 2,
+            max_auto_reorg_depth: 64,
         }
     }
 
     pub fn starknet_sepolia() -> Self {
-        Self { chain_name: "Starknet sepolia".into(), chain_id: ChainId::Sepolia, ..Self::starknet_mainnet() }
+        Self {
+            chain_name: "Starknet sepolia".into(),
+            chain_id: ChainId::Sepolia,
+            // UNWRAP: this is a valid L1 address.
+            eth_core_contract_address: "0xE2Bb56ee936fd6433DC0F6e7e3b8365C906AA057".parse().unwrap(),
+            ..Self::starknet_mainnet()
+        }
     }
 
     pub fn starknet_integration() -> Self {
         Self {
             chain_name: "Starknet integration".into(),
             chain_id: ChainId::IntegrationSepolia,
+            // UNWRAP: this is a valid L1 address.
+            eth_core_contract_address: "0x4737c0c1B4D5b1A687B42610DdabEE781152359c".parse().unwrap(),
             ..Self::starknet_mainnet()
         }
     }
@@ -170,6 +362,7 @@ mod tests {
             chain_name: "test".into(),
             native_fee_token_address: Default::default(),
             parent_fee_token_address: Default::default(),
+            eth_core_contract_address: Default::default(),
             chain_id: ChainId::Mainnet,
             block_time: Default::default(),
             pending_block_update_time: Default::default(),
@@ -189,7 +382,9 @@ mod tests {
             .into(),
             latest_protocol_version: StarknetVersion::new(0, 2, 0, 0),
             bouncer_config: BouncerConfig::max(),
+            max_block_size: usize::MAX,
             max_nonce_for_validation_skip: 2,
+            max_auto_reorg_depth: 64,
         };
 
         assert_eq!(
@@ -236,4 +431,93 @@ mod tests {
         );
         assert!(chain_config.exec_constants_by_protocol_version(StarknetVersion::new(0, 0, 0, 0)).is_err(),);
     }
+
+    const SPEC: &str = r#"
+        chain_name = "Appchain"
+        chain_id = "MY_APPCHAIN"
+        native_fee_token_address = "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"
+        parent_fee_token_address = "0x49d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7"
+        sequencer_address = "0x123"
+        eth_core_contract_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+        latest_protocol_version = "0.13.1"
+        versioned_constants = ["0.13.0", "0.13.1"]
+        block_time_ms = 30000
+    "#;
+
+    fn field_error(spec: &str) -> &'static str {
+        match ChainConfig::from_toml(spec) {
+            Err(ChainConfigError::InvalidField { field, .. }) => field,
+            res => panic!("Expected an invalid field: {res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_chain_spec() {
+        let config = ChainConfig::from_toml(SPEC).unwrap();
+        assert_eq!(config.chain_name, "Appchain");
+        assert_eq!(config.chain_id, ChainId::Other("MY_APPCHAIN".into()));
+        assert_eq!(**config.sequencer_address, Felt::from_hex_unchecked("0x123"));
+        assert_eq!(config.native_fee_token_address, ChainConfig::starknet_mainnet().native_fee_token_address);
+        assert_eq!(config.eth_core_contract_address, "0x5FbDB2315678afecb367f032d93F642f64180aa3".parse().unwrap());
+        assert_eq!(config.latest_protocol_version, StarknetVersion::STARKNET_VERSION_0_13_1);
+        let versions: Vec<_> = config.versioned_constants.keys().copied().collect();
+        assert_eq!(versions, [StarknetVersion::STARKNET_VERSION_0_13_0, StarknetVersion::STARKNET_VERSION_0_13_1]);
+        assert_eq!(config.block_time, Duration::from_secs(30));
+        // Not in the spec.
+        assert_eq!(config.pending_block_update_time, ChainConfig::starknet_mainnet().pending_block_update_time);
+
+        let config = ChainConfig::from_toml(&SPEC.replace("MY_APPCHAIN", "SN_SEPOLIA")).unwrap();
+        assert_eq!(config.chain_id, ChainId::Sepolia);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("chain.toml");
+        std::fs::write(&path, SPEC).unwrap();
+        assert_eq!(ChainConfig::from_file(&path).unwrap().chain_name, "Appchain");
+        assert!(matches!(
+            ChainConfig::from_file(temp_dir.path().join("missing.toml")),
+            Err(ChainConfigError::Read { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chain_spec_errors() {
+        assert_eq!(field_error(&SPEC.replace("\"0x123\"", "\"0xzz\"")), "sequencer_address");
+        assert_eq!(field_error(&SPEC.replace("\"0x123\"", &format!("\"{:#x}\"", Felt::MAX))), "sequencer_address");
+        assert_eq!(
+            field_error(&SPEC.replace("0x5FbDB2315678afecb367f032d93F642f64180aa3", "0x5FbD")),
+            "eth_core_contract_address"
+        );
+        assert_eq!(field_error(&SPEC.replace("MY_APPCHAIN", &"A".repeat(32))), "chain_id");
+        assert_eq!(field_error(&SPEC.replace("[\"0.13.0\", \"0.13.1\"]", "[\"0.12.3\"]")), "versioned_constants");
+        assert_eq!(field_error(&SPEC.replace("[\"0.13.0\", \"0.13.1\"]", "[]")), "versioned_constants");
+        assert_eq!(
+            field_error(&SPEC.replace("latest_protocol_version = \"0.13.1\"", "latest_protocol_version = \"0.12.0\"")),
+            "latest_protocol_version"
+        );
+        assert_eq!(field_error(&SPEC.replace("30000", "0")), "block_time_ms");
+        assert_eq!(
+            field_error(&format!("{SPEC}\npending_block_update_time_ms = 60000")),
+            "pending_block_update_time_ms"
+        );
+
+        // Missing and unknown fields are named by the parser.
+        let err = ChainConfig::from_toml(&SPEC.replace("sequencer_address = \"0x123\"", "")).unwrap_err();
+        assert!(matches!(err, ChainConfigError::Parse(_)));
+        assert!(err.to_string().contains("sequencer_address"), "{err}");
+        let err = ChainConfig::from_toml(&format!("{SPEC}\nblock_time = 3")).unwrap_err();
+        assert!(err.to_string().contains("block_time"), "{err}");
+    }
+
+    #[test]
+    fn test_chain_presets() {
+        for name in CHAIN_PRESETS {
+            ChainConfig::from_preset(name).unwrap();
+        }
+        assert_eq!(ChainConfig::from_preset("sepolia").unwrap().chain_id, ChainId::Sepolia);
+        assert_eq!(
+            ChainConfig::from_preset("mainnet").unwrap().eth_core_contract_address,
+            "0xc662c410C0ECf747543f5bA90660f6ABeBD9C8c4".parse().unwrap()
+        );
+        assert!(matches!(ChainConfig::from_preset("goerli"), Err(ChainConfigError::UnknownPreset(_))));
+    }
 }
@@ -49,6 +49,18 @@ pub struct PendingHeader {
     pub l1_da_mode: L1DataAvailabilityMode,
 }
 
+/// The ways the hash of a block was computed over the history of the chains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockHashVariant {
+    /// Mainnet before Starknet 0.7: the chain id is hashed instead of the sequencer address, the timestamp and the
+    /// events.
+    PreV0_7,
+    /// Pedersen hash of the header, before Starknet 0.13.2.
+    Pedersen,
+    /// Poseidon hash of the header, since Starknet 0.13.2.
+    Poseidon,
+}
+
 #[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
 /// Starknet header definition.
 pub struct Header {
@@ -81,7 +93,7 @@ pub struct Header {
     pub l1_da_mode: L1DataAvailabilityMode,
 }
 
-#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct GasPrices {
     pub eth_l1_gas_price: u128,
     pub strk_l1_gas_price: u128,
@@ -181,26 +193,43 @@ impl Header {
         }
     }
 
-    /// Compute the hash of the header.
+    /// Compute the hash of the header, with the variant of its block.
+    ///
+    /// The mainnet blocks before [`V0_7_BLOCK_NUMBER`] hash the chain id instead of the sequencer address, the
+    /// timestamp and the events. The Sepolia chains start after Starknet 0.12, their blocks never use this variant.
+    /// Missing fields of the older blocks are hashed as zero.
     pub fn compute_hash(&self, chain_id: Felt) -> Felt {
-        if self.block_number < V0_7_BLOCK_NUMBER && chain_id == MAIN_CHAIN_ID {
-            self.compute_hash_inner_pre_v0_7(chain_id)
+        self.compute_hash_with(self.hash_variants(chain_id)[0], chain_id)
+    }
+
+    /// The variants that may have been used for the hash of this block, the one of its version first.
+    ///
+    /// The legacy mainnet blocks without a protocol version, older than Starknet 0.9.1, were not all hashed the way
+    /// their version defines: some of the blocks re-orged after Starknet 0.7 had their hash computed again with an
+    /// older variant.
+    pub fn hash_variants(&self, chain_id: Felt) -> &'static [BlockHashVariant] {
+        if chain_id == MAIN_CHAIN_ID && self.block_number < V0_7_BLOCK_NUMBER {
+            &[BlockHashVariant::PreV0_7]
+        } else if chain_id == MAIN_CHAIN_ID && self.protocol_version == StarknetVersion::default() {
+            &[BlockHashVariant::Pedersen, BlockHashVariant::PreV0_7]
         } else if self.protocol_version < StarknetVersion::STARKNET_VERSION_0_13_2 {
-            Pedersen::hash_array(&[
-                Felt::from(self.block_number),      // block number
-                self.global_state_root,             // global state root
-                self.sequencer_address,             // sequencer address
-                Felt::from(self.block_timestamp),   // block timestamp
-                Felt::from(self.transaction_count), // number of transactions
-                self.transaction_commitment,        // transaction commitment
-                Felt::from(self.event_count),       // number of events
-                self.event_commitment,              // event commitment
-                Felt::ZERO,                         // reserved: protocol version
-                Felt::ZERO,                         // reserved: extra data
-                self.parent_block_hash,             // parent block hash
-            ])
+            &[BlockHashVariant::Pedersen]
         } else {
-            Poseidon::hash_array(&[
+            &[BlockHashVariant::Poseidon]
+        }
+    }
+
+    /// Whether `block_hash` is the hash of this header, with one of the variants of its block.
+    pub fn verify_hash(&self, chain_id: Felt, block_hash: Felt) -> bool {
+        self.hash_variants(chain_id).iter().any(|variant| self.compute_hash_with(*variant, chain_id) == block_hash)
+    }
+
+    /// The hash of the header with `variant`, whatever the version of its block.
+    pub fn compute_hash_with(&self, variant: BlockHashVariant, chain_id: Felt) -> Felt {
+        match variant {
+            BlockHashVariant::PreV0_7 => self.compute_hash_inner_pre_v0_7(chain_id),
+            BlockHashVariant::Pedersen => self.compute_hash_inner_pedersen(),
+            BlockHashVariant::Poseidon => Poseidon::hash_array(&[
                 Felt::from_bytes_be_slice(b"STARKNET_BLOCK_HASH0"),
                 Felt::from(self.block_number),
                 self.global_state_root,
@@ -218,10 +247,26 @@ impl Header {
                 Felt::from_bytes_be_slice(self.protocol_version.to_string().as_bytes()),
                 Felt::ZERO,
                 self.parent_block_hash,
-            ])
+            ]),
         }
     }
 
+    fn compute_hash_inner_pedersen(&self) -> Felt {
+        Pedersen::hash_array(&[
+            Felt::from(self.block_number),      // block number
+            self.global_state_root,             // global state root
+            self.sequencer_address,             // sequencer address
+            Felt::from(self.block_timestamp),   // block timestamp
+            Felt::from(self.transaction_count), // number of transactions
+            self.transaction_commitment,        // transaction commitment
+            Felt::from(self.event_count),       // number of events
+            self.event_commitment,              // event commitment
+            Felt::ZERO,                         // reserved: protocol version
+            Felt::ZERO,                         // reserved: extra data
+            self.parent_block_hash,             // parent block hash
+        ])
+    }
+
     fn compute_hash_inner_pre_v0_7(&self, chain_id: Felt) -> Felt {
         Pedersen::hash_array(&[
             Felt::from(self.block_number),
@@ -364,4 +409,78 @@ mod tests {
 
         assert_eq!(hash, Felt::from_hex_unchecked("0x6028bf0975e1d4c95713e021a0f0217e74d5a748a20691d881c86d9d62d1432"));
     }
+
+    #[test]
+    fn test_header_hash_variant_selection() {
+        let header = |block_number| Header {
+            parent_block_hash: Felt::from(1),
+            block_number,
+            global_state_root: Felt::from(3),
+            sequencer_address: Felt::from(4),
+            block_timestamp: 5,
+            transaction_count: 6,
+            transaction_commitment: Felt::from(7),
+            event_count: 8,
+            event_commitment: Felt::from(9),
+            ..Default::default()
+        };
+        let pre_v0_7 = |header: &Header, chain_id| header.compute_hash_inner_pre_v0_7(chain_id);
+
+        let last_legacy = header(V0_7_BLOCK_NUMBER - 1);
+        assert_eq!(last_legacy.compute_hash(MAIN_CHAIN_ID), pre_v0_7(&last_legacy, MAIN_CHAIN_ID));
+        // Only the chain id is hashed instead of the other fields.
+        assert_eq!(
+            Header { sequencer_address: Felt::ZERO, block_timestamp: 0, event_count: 0, ..last_legacy.clone() }
+                .compute_hash(MAIN_CHAIN_ID),
+            last_legacy.compute_hash(MAIN_CHAIN_ID)
+        );
+
+        let first_v0_7 = header(V0_7_BLOCK_NUMBER);
+        assert_ne!(first_v0_7.compute_hash(MAIN_CHAIN_ID), pre_v0_7(&first_v0_7, MAIN_CHAIN_ID));
+
+        for chain_id in [dp_transactions::TEST_CHAIN_ID, dp_transactions::INTEGRATION_CHAIN_ID] {
+            assert_ne!(last_legacy.compute_hash(chain_id), pre_v0_7(&last_legacy, chain_id));
+            assert_ne!(
+                Header { sequencer_address: Felt::ZERO, ..last_legacy.clone() }.compute_hash(chain_id),
+                last_legacy.compute_hash(chain_id)
+            );
+        }
+    }
+
+    // TODO: check the hashes of real mainnet headers (blocks 0, 1000, 1466 and 2000) against the feeder, once they are
+    // recorded as fixtures.
+    #[test]
+    fn test_legacy_mainnet_hash_variants() {
+        let header = Header {
+            parent_block_hash: Felt::from(1),
+            block_number: 1500,
+            global_state_root: Felt::from(3),
+            sequencer_address: Felt::from(4),
+            block_timestamp: 5,
+            transaction_count: 6,
+            transaction_commitment: Felt::from(7),
+            event_count: 8,
+            event_commitment: Felt::from(9),
+            ..Default::default()
+        };
+        let variants = [BlockHashVariant::Pedersen, BlockHashVariant::PreV0_7];
+        assert_eq!(header.hash_variants(MAIN_CHAIN_ID), variants);
+        assert_eq!(
+            header.compute_hash(MAIN_CHAIN_ID),
+            header.compute_hash_with(BlockHashVariant::Pedersen, MAIN_CHAIN_ID)
+        );
+        for variant in variants {
+            assert!(header.verify_hash(MAIN_CHAIN_ID, header.compute_hash_with(variant, MAIN_CHAIN_ID)));
+        }
+        assert!(!header.verify_hash(MAIN_CHAIN_ID, Felt::from(0x404)));
+
+        // The blocks with a protocol version, and the other chains, only use the variant of their version.
+        let versioned = Header { protocol_version: StarknetVersion::new(0, 9, 1, 0), ..header.clone() };
+        assert_eq!(versioned.hash_variants(MAIN_CHAIN_ID), [BlockHashVariant::Pedersen]);
+        assert!(!versioned
+            .verify_hash(MAIN_CHAIN_ID, versioned.compute_hash_with(BlockHashVariant::PreV0_7, MAIN_CHAIN_ID)));
+        let chain_id = dp_transactions::TEST_CHAIN_ID;
+        assert_eq!(header.hash_variants(chain_id), [BlockHashVariant::Pedersen]);
+        assert!(!header.verify_hash(chain_id, header.compute_hash_with(BlockHashVariant::PreV0_7, chain_id)));
+    }
 }
@@ -0,0 +1,191 @@
+use std::num::TryFromIntError;
+
+/// Height of a block in the chain.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct BlockN(pub u64);
+
+impl BlockN {
+    pub const GENESIS: BlockN = BlockN(0);
+
+    /// The parent of this block. Returns `None` for the genesis block.
+    pub fn parent(self) -> Option<BlockN> {
+        self.checked_prev()
+    }
+
+    pub fn checked_prev(self) -> Option<BlockN> {
+        self.0.checked_sub(1).map(BlockN)
+    }
+
+    pub fn checked_next(self) -> Option<BlockN> {
+        self.0.checked_add(1).map(BlockN)
+    }
+
+    /// Block numbers are stored on 32 bits in the history keys of the database.
+    pub fn to_u32(self) -> Result<u32, TryFromIntError> {
+        u32::try_from(self.0)
+    }
+
+    /// Suffix of the contract history keys of the database, big-endian so that the keys sort in block order.
+    pub fn to_history_key_suffix(self) -> Result<[u8; 4], TryFromIntError> {
+        self.to_u32().map(u32::to_be_bytes)
+    }
+
+    pub fn from_history_key_suffix(bytes: &[u8]) -> Option<BlockN> {
+        bytes.try_into().ok().map(|bytes| BlockN(u32::from_be_bytes(bytes).into()))
+    }
+
+    /// Big-endian encoding, for the database keys sorted in block order.
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+
+    pub fn from_be_bytes(bytes: &[u8]) -> Option<BlockN> {
+        bytes.try_into().ok().map(|bytes| BlockN(u64::from_be_bytes(bytes)))
+    }
+}
+
+impl From<u64> for BlockN {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<BlockN> for u64 {
+    fn from(value: BlockN) -> Self {
+        value.0
+    }
+}
+
+impl std::fmt::Display for BlockN {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Index of a transaction in its block.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct TxIndex(pub u32);
+
+/// Index of an event in its block.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct EventIndex(pub u32);
+
+macro_rules! impl_index {
+    ($ty:ident) => {
+        impl $ty {
+            pub fn checked_prev(self) -> Option<$ty> {
+                self.0.checked_sub(1).map($ty)
+            }
+
+            pub fn checked_next(self) -> Option<$ty> {
+                self.0.checked_add(1).map($ty)
+            }
+
+            /// Big-endian encoding, for the database keys sorted in index order.
+            pub fn to_be_bytes(self) -> [u8; 4] {
+                self.0.to_be_bytes()
+            }
+
+            pub fn from_be_bytes(bytes: &[u8]) -> Option<$ty> {
+                bytes.try_into().ok().map(|bytes| $ty(u32::from_be_bytes(bytes)))
+            }
+        }
+
+        impl TryFrom<usize> for $ty {
+            type Error = TryFromIntError;
+
+            fn try_from(value: usize) -> Result<Self, Self::Error> {
+                u32::try_from(value).map($ty)
+            }
+        }
+
+        impl TryFrom<u64> for $ty {
+            type Error = TryFromIntError;
+
+            fn try_from(value: u64) -> Result<Self, Self::Error> {
+                u32::try_from(value).map($ty)
+            }
+        }
+
+        impl From<$ty> for usize {
+            fn from(value: $ty) -> Self {
+                // usize is at least 32 bits on every target we support.
+                value.0 as usize
+            }
+        }
+
+        impl From<$ty> for u64 {
+            fn from(value: $ty) -> Self {
+                value.0.into()
+            }
+        }
+
+        impl std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+impl_index!(TxIndex);
+impl_index!(EventIndex);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_n_parent() {
+        assert_eq!(BlockN::GENESIS.parent(), None);
+        assert_eq!(BlockN(1).parent(), Some(BlockN::GENESIS));
+        assert_eq!(BlockN(u64::MAX).checked_prev(), Some(BlockN(u64::MAX - 1)));
+        assert_eq!(BlockN(u64::MAX).checked_next(), None);
+    }
+
+    #[test]
+    fn test_block_n_to_u32() {
+        assert_eq!(BlockN(u32::MAX as u64).to_u32(), Ok(u32::MAX));
+        assert!(BlockN(u32::MAX as u64 + 1).to_u32().is_err());
+    }
+
+    #[test]
+    fn test_key_encodings() {
+        assert_eq!(BlockN(0x0102).to_history_key_suffix(), Ok([0, 0, 1, 2]));
+        assert!(BlockN(u32::MAX as u64 + 1).to_history_key_suffix().is_err());
+        assert_eq!(BlockN::from_history_key_suffix(&[0, 0, 1, 2]), Some(BlockN(0x0102)));
+        assert_eq!(BlockN::from_history_key_suffix(&[1, 2]), None);
+
+        assert_eq!(BlockN(0x0102).to_be_bytes(), [0, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(BlockN::from_be_bytes(&BlockN(u64::MAX).to_be_bytes()), Some(BlockN(u64::MAX)));
+        assert_eq!(TxIndex::from_be_bytes(&TxIndex(7).to_be_bytes()), Some(TxIndex(7)));
+        assert_eq!(EventIndex::from_be_bytes(&[0, 0, 7]), None);
+        // Big-endian keys sort in block order.
+        assert!(BlockN(255).to_be_bytes() < BlockN(256).to_be_bytes());
+    }
+
+    #[test]
+    fn test_index_boundaries() {
+        assert_eq!(TxIndex(0).checked_prev(), None);
+        assert_eq!(TxIndex(u32::MAX).checked_next(), None);
+        assert_eq!(EventIndex(3).checked_prev(), Some(EventIndex(2)));
+
+        assert_eq!(TxIndex::try_from(u32::MAX as usize), Ok(TxIndex(u32::MAX)));
+        assert!(TxIndex::try_from(u32::MAX as u64 + 1).is_err());
+        assert!(EventIndex::try_from(u32::MAX as u64 + 1).is_err());
+        assert_eq!(usize::from(EventIndex(42)), 42);
+    }
+
+    #[test]
+    fn test_display_and_serde() {
+        assert_eq!(BlockN(662703).to_string(), "662703");
+        assert_eq!(TxIndex(7).to_string(), "7");
+
+        // The newtypes are transparent: database keys encoded from bare integers stay valid.
+        assert_eq!(serde_json::to_string(&BlockN(12)).unwrap(), "12");
+        assert_eq!(serde_json::from_str::<EventIndex>("5").unwrap(), EventIndex(5));
+    }
+}
@@ -1,16 +1,22 @@
 //! Starknet block primitives.
 
+mod block_id;
 pub mod chain_config;
 pub mod header;
+mod index;
 mod starknet_version;
+mod verification;
 
+pub use block_id::ParseBlockIdError;
 use dp_receipt::TransactionReceipt;
 use dp_transactions::Transaction;
 pub use header::Header;
 use header::PendingHeader;
+pub use index::{BlockN, EventIndex, TxIndex};
 pub use primitive_types::{H160, U256};
 use starknet_types_core::felt::Felt;
 pub use starknet_version::{StarknetVersion, StarknetVersionError};
+pub use verification::BlockVerification;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[allow(clippy::large_enum_variant)]
@@ -77,6 +83,9 @@ impl From<DeoxysBlockInfo> for DeoxysMaybePendingBlockInfo {
 pub enum BlockTag {
     Latest,
     Pending,
+    /// The latest block whose state update was verified on L1.
+    #[serde(rename = "l1_accepted")]
+    L1Accepted,
 }
 
 impl From<starknet_core::types::BlockTag> for BlockTag {
@@ -87,15 +96,23 @@ impl From<starknet_core::types::BlockTag> for BlockTag {
         }
     }
 }
-impl From<BlockTag> for starknet_core::types::BlockTag {
-    fn from(value: BlockTag) -> Self {
+impl TryFrom<BlockTag> for starknet_core::types::BlockTag {
+    type Error = UnsupportedBlockTag;
+
+    fn try_from(value: BlockTag) -> Result<Self, Self::Error> {
         match value {
-            BlockTag::Latest => starknet_core::types::BlockTag::Latest,
-            BlockTag::Pending => starknet_core::types::BlockTag::Pending,
+            BlockTag::Latest => Ok(starknet_core::types::BlockTag::Latest),
+            BlockTag::Pending => Ok(starknet_core::types::BlockTag::Pending),
+            BlockTag::L1Accepted => Err(UnsupportedBlockTag(value)),
         }
     }
 }
 
+/// The tag has no equivalent in the Starknet RPC types, it has to be resolved to a block number first.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Block tag `{0}` is only known to this node")]
+pub struct UnsupportedBlockTag(pub BlockTag);
+
 /// Block Id
 /// Block hash, number or tag
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -114,12 +131,14 @@ impl From<starknet_core::types::BlockId> for BlockId {
         }
     }
 }
-impl From<BlockId> for starknet_core::types::BlockId {
-    fn from(value: BlockId) -> Self {
+impl TryFrom<BlockId> for starknet_core::types::BlockId {
+    type Error = UnsupportedBlockTag;
+
+    fn try_from(value: BlockId) -> Result<Self, Self::Error> {
         match value {
-            BlockId::Hash(felt) => starknet_core::types::BlockId::Hash(felt),
-            BlockId::Number(number) => starknet_core::types::BlockId::Number(number),
-            BlockId::Tag(tag) => starknet_core::types::BlockId::Tag(tag.into()),
+            BlockId::Hash(felt) => Ok(starknet_core::types::BlockId::Hash(felt)),
+            BlockId::Number(number) => Ok(starknet_core::types::BlockId::Number(number)),
+            BlockId::Tag(tag) => Ok(starknet_core::types::BlockId::Tag(tag.try_into()?)),
         }
     }
 }
@@ -143,11 +162,18 @@ pub struct DeoxysBlockInfo {
     pub header: Header,
     pub block_hash: Felt,
     pub tx_hashes: Vec<Felt>,
+    /// Set by the paths importing the block, nothing is verified by default.
+    pub verification: BlockVerification,
 }
 
 impl DeoxysBlockInfo {
     pub fn new(header: Header, tx_hashes: Vec<Felt>, block_hash: Felt) -> Self {
-        Self { header, block_hash, tx_hashes }
+        Self { header, block_hash, tx_hashes, verification: BlockVerification::NONE }
+    }
+
+    pub fn with_verification(mut self, verification: BlockVerification) -> Self {
+        self.verification = verification;
+        self
     }
 }
 
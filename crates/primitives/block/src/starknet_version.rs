@@ -1,6 +1,12 @@
 use std::str::FromStr;
 
-#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Version of the Starknet protocol of a block, `major.minor.patch` with an optional build number. Versions are ordered
+/// component by component. The blocks older than 0.9.1 have no version, they are at version `0.0.0`.
+///
+/// The version is a dotted string in human readable formats such as JSON, and its four bytes in binary formats.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StarknetVersion([u8; 4]);
 
 #[derive(thiserror::Error, Debug, PartialEq)]
@@ -16,11 +22,22 @@ impl StarknetVersion {
         StarknetVersion([major, minor, patch, build])
     }
 
+    pub const STARKNET_VERSION_0_11_0: StarknetVersion = StarknetVersion([0, 11, 0, 0]);
     pub const STARKNET_VERSION_0_11_1: StarknetVersion = StarknetVersion([0, 11, 1, 0]);
     pub const STARKNET_VERSION_0_13_0: StarknetVersion = StarknetVersion([0, 13, 0, 0]);
     pub const STARKNET_VERSION_0_13_1: StarknetVersion = StarknetVersion([0, 13, 1, 0]);
     pub const STARKNET_VERSION_0_13_1_1: StarknetVersion = StarknetVersion([0, 13, 1, 1]);
     pub const STARKNET_VERSION_0_13_2: StarknetVersion = StarknetVersion([0, 13, 2, 0]);
+
+    /// Latest version the node knows how to verify the blocks of. The build versions of a release only fix the
+    /// sequencer, they are supported along with it.
+    pub const LATEST_SUPPORTED: StarknetVersion = Self::STARKNET_VERSION_0_13_2;
+
+    /// Whether the hashes, commitments and fees of the blocks of this version are computed as the protocol defines
+    /// them. A newer version may change any of them.
+    pub fn is_supported(&self) -> bool {
+        self.0[..3] <= Self::LATEST_SUPPORTED.0[..3]
+    }
 }
 
 impl std::fmt::Display for StarknetVersion {
@@ -59,6 +76,26 @@ impl FromStr for StarknetVersion {
     }
 }
 
+impl Serialize for StarknetVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StarknetVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+        } else {
+            Ok(Self(<[u8; 4]>::deserialize(deserializer)?))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +145,50 @@ mod tests {
         assert!(version_3 < version_4);
         assert!(version_4 < version_5);
     }
+
+    /// Every version of the blocks of mainnet so far.
+    const MAINNET_VERSIONS: &[&str] = &[
+        "0.9.1", "0.10.0", "0.10.1", "0.10.2", "0.10.3", "0.11.0", "0.11.0.2", "0.11.1", "0.11.2", "0.12.0", "0.12.1",
+        "0.12.2", "0.12.3", "0.13.0", "0.13.1", "0.13.1.1", "0.13.2", "0.13.2.1",
+    ];
+
+    #[test]
+    fn test_starknet_version_mainnet_versions() {
+        let versions: Vec<_> =
+            MAINNET_VERSIONS.iter().map(|version| StarknetVersion::from_str(version).unwrap()).collect();
+        for (version, version_str) in versions.iter().zip(MAINNET_VERSIONS) {
+            assert_eq!(&version.to_string(), version_str);
+            assert!(version.is_supported(), "{version} is supported");
+        }
+        assert!(versions.windows(2).all(|w| w[0] < w[1]));
+        assert!(versions.iter().all(|version| *version > StarknetVersion::default()));
+        assert!(StarknetVersion::default().is_supported());
+
+        assert_eq!(StarknetVersion::from_str("0.11.0.2").unwrap(), StarknetVersion::new(0, 11, 0, 2));
+        assert_eq!(StarknetVersion::from_str("0.13.1.1").unwrap(), StarknetVersion::STARKNET_VERSION_0_13_1_1);
+    }
+
+    #[test]
+    fn test_starknet_version_unknown_future_version() {
+        let version = StarknetVersion::from_str("0.14.0").unwrap();
+        assert_eq!(version, StarknetVersion::new(0, 14, 0, 0));
+        assert!(version > StarknetVersion::LATEST_SUPPORTED);
+        assert!(!version.is_supported());
+        assert!(!StarknetVersion::from_str("0.13.3").unwrap().is_supported());
+        assert!(!StarknetVersion::from_str("1.0.0").unwrap().is_supported());
+        assert!(StarknetVersion::from_str("0.13.2.7").unwrap().is_supported());
+    }
+
+    #[test]
+    fn test_starknet_version_serde() {
+        let version = StarknetVersion::STARKNET_VERSION_0_13_1_1;
+        assert_eq!(serde_json::to_value(version).unwrap(), serde_json::json!("0.13.1.1"));
+        assert_eq!(serde_json::from_value::<StarknetVersion>(serde_json::json!("0.13.1.1")).unwrap(), version);
+        assert!(serde_json::from_value::<StarknetVersion>(serde_json::json!("0.13.x")).is_err());
+
+        // The stored headers keep their encoding.
+        let encoded = bincode::serialize(&version).unwrap();
+        assert_eq!(encoded, [0, 13, 1, 1]);
+        assert_eq!(bincode::deserialize::<StarknetVersion>(&encoded).unwrap(), version);
+    }
 }
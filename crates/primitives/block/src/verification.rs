@@ -0,0 +1,113 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+/// What the node checked itself about a block, as opposed to trusting the feeder. The flags are stored as a single
+/// byte along with the header of the block. A block can be stored before some of the checks ran, when they are skipped
+/// or delayed: the flags are then added once the checks pass.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct BlockVerification(u8);
+
+impl BlockVerification {
+    pub const NONE: Self = Self(0);
+    /// The block hash was computed again from the header.
+    pub const VERIFIED_HASH: Self = Self(1 << 0);
+    /// The commitments of the header were computed again from the transactions, events, receipts and state diff.
+    pub const VERIFIED_COMMITMENTS: Self = Self(1 << 1);
+    /// The state root of the header was computed again by applying the state diff to the global tries.
+    pub const VERIFIED_ROOT: Self = Self(1 << 2);
+    /// The signature of the block by the sequencer was checked.
+    pub const SIGNATURE_CHECKED: Self = Self(1 << 3);
+    pub const ALL: Self =
+        Self(Self::VERIFIED_HASH.0 | Self::VERIFIED_COMMITMENTS.0 | Self::VERIFIED_ROOT.0 | Self::SIGNATURE_CHECKED.0);
+
+    const NAMES: [(Self, &'static str); 4] = [
+        (Self::VERIFIED_HASH, "VERIFIED_HASH"),
+        (Self::VERIFIED_COMMITMENTS, "VERIFIED_COMMITMENTS"),
+        (Self::VERIFIED_ROOT, "VERIFIED_ROOT"),
+        (Self::SIGNATURE_CHECKED, "SIGNATURE_CHECKED"),
+    ];
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Unknown bits are dropped.
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    /// Whether all the flags of `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The flags of `self` that are not set in `other`.
+    pub fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for BlockVerification {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for BlockVerification {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0
+    }
+}
+
+impl fmt::Debug for BlockVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = Self::NAMES.iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| name).peekable();
+        if set.peek().is_none() {
+            return write!(f, "NONE");
+        }
+        for (i, name) in set.enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{name}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_verification_flags() {
+        let mut verification = BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_COMMITMENTS;
+        assert!(verification.contains(BlockVerification::VERIFIED_HASH));
+        assert!(!verification.contains(BlockVerification::VERIFIED_ROOT));
+        assert_eq!(format!("{verification:?}"), "VERIFIED_HASH | VERIFIED_COMMITMENTS");
+
+        let added = (BlockVerification::VERIFIED_HASH | BlockVerification::VERIFIED_ROOT).difference(verification);
+        assert_eq!(added, BlockVerification::VERIFIED_ROOT);
+        verification |= added;
+        assert_eq!(verification.bits(), 0b0111);
+        assert!(!verification.contains(BlockVerification::ALL));
+
+        assert_eq!(BlockVerification::from_bits(0xff), BlockVerification::ALL);
+        assert_eq!(format!("{:?}", BlockVerification::NONE), "NONE");
+    }
+
+    #[test]
+    fn test_block_verification_is_a_byte() {
+        let verification = BlockVerification::VERIFIED_ROOT | BlockVerification::SIGNATURE_CHECKED;
+        let encoded = bincode::serialize(&verification).unwrap();
+        assert_eq!(encoded, [0b1100]);
+        assert_eq!(bincode::deserialize::<BlockVerification>(&encoded).unwrap(), verification);
+    }
+}
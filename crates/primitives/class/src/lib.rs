@@ -1,5 +1,6 @@
 use std::ops::Deref;
 
+use anyhow::Context;
 use starknet_types_core::felt::Felt;
 
 mod class_hash;
@@ -9,7 +10,7 @@ mod into_starknet_core;
 pub use class_hash::ClassHash;
 pub use compile::ToCompiledClass;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ConvertedClass {
     pub class_infos: (Felt, ClassInfo),
     pub class_compiled: (Felt, CompiledClass),
@@ -174,6 +175,15 @@ impl Deref for CompiledClass {
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct CompiledSierra(Vec<u8>);
 
+impl CompiledSierra {
+    /// Hash of the CASM, which the declare transactions of the class commit to.
+    pub fn compiled_class_hash(&self) -> anyhow::Result<Felt> {
+        let casm: starknet_core::types::contract::CompiledClass =
+            serde_json::from_slice(&self.0).context("Parsing the CASM")?;
+        casm.class_hash().context("Computing the compiled class hash")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct CompiledLegacy(Vec<u8>);
 
@@ -0,0 +1,153 @@
+use primitive_types::H160;
+use starknet_api::core::{ContractAddress, PatriciaKey};
+use starknet_api::state::StorageKey;
+use starknet_core::types::EthAddress;
+use starknet_types_core::felt::Felt;
+
+use crate::felt::{felt_to_u128, felt_to_u64};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Felt {felt:#x} is out of range for a {target}.")]
+pub struct FeltOutOfRange {
+    pub felt: Felt,
+    pub target: &'static str,
+}
+
+/// Conversion from a felt to a type that only holds a part of the felt range.
+pub trait TryFromFelt: Sized {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange>;
+
+    /// Converts every felt, and fails on the first one that is out of range.
+    fn try_from_felts(felts: impl IntoIterator<Item = Felt>) -> Result<Vec<Self>, FeltOutOfRange> {
+        felts.into_iter().map(Self::try_from_felt).collect()
+    }
+}
+
+fn out_of_range<T>(felt: Felt, target: &'static str) -> impl FnOnce(T) -> FeltOutOfRange {
+    move |_| FeltOutOfRange { felt, target }
+}
+
+/// Patricia keys are lower than 2**251.
+impl TryFromFelt for PatriciaKey {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        PatriciaKey::try_from(felt).map_err(out_of_range(felt, "PatriciaKey"))
+    }
+}
+
+impl TryFromFelt for ContractAddress {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        PatriciaKey::try_from(felt).map(ContractAddress).map_err(out_of_range(felt, "ContractAddress"))
+    }
+}
+
+impl TryFromFelt for StorageKey {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        PatriciaKey::try_from(felt).map(StorageKey).map_err(out_of_range(felt, "StorageKey"))
+    }
+}
+
+/// L1 addresses are 20 bytes long.
+impl TryFromFelt for H160 {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        let bytes = felt.to_bytes_be();
+        let (high, address) = bytes.split_at(32 - H160::len_bytes());
+        if high.iter().any(|&byte| byte != 0) {
+            return Err(FeltOutOfRange { felt, target: "H160" });
+        }
+        Ok(H160::from_slice(address))
+    }
+}
+
+impl TryFromFelt for EthAddress {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        EthAddress::try_from(felt).map_err(out_of_range(felt, "EthAddress"))
+    }
+}
+
+impl TryFromFelt for u64 {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        felt_to_u64(&felt).map_err(out_of_range(felt, "u64"))
+    }
+}
+
+impl TryFromFelt for u128 {
+    fn try_from_felt(felt: Felt) -> Result<Self, FeltOutOfRange> {
+        felt_to_u128(&felt).map_err(out_of_range(felt, "u128"))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Value is too big to convert to a felt.")]
+pub struct BytesToFeltError;
+
+/// Converts a big-endian 256 bits value, such as an L1 `uint256`, to a felt. Unlike [`Felt::from_bytes_be`], the values
+/// larger than [`Felt::MAX`] are rejected instead of being reduced modulo the prime.
+pub fn felt_from_be_bytes(bytes: &[u8; 32]) -> Result<Felt, BytesToFeltError> {
+    // Big-endian byte arrays of the same length compare like the numbers they hold.
+    if *bytes > Felt::MAX.to_bytes_be() {
+        return Err(BytesToFeltError);
+    }
+    Ok(Felt::from_bytes_be(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToFelt;
+
+    /// 2**251, the upper bound of the patricia keys.
+    const TWO_POW_251: Felt =
+        Felt::from_hex_unchecked("0x800000000000000000000000000000000000000000000000000000000000000");
+
+    #[test]
+    fn test_patricia_key_bound() {
+        let highest = TWO_POW_251 - Felt::ONE;
+        assert_eq!(*PatriciaKey::try_from_felt(highest).unwrap().key(), highest);
+        assert_eq!(ContractAddress::try_from_felt(highest).unwrap(), ContractAddress(highest.try_into().unwrap()));
+        assert!(StorageKey::try_from_felt(Felt::ZERO).is_ok());
+
+        assert_eq!(
+            ContractAddress::try_from_felt(TWO_POW_251),
+            Err(FeltOutOfRange { felt: TWO_POW_251, target: "ContractAddress" })
+        );
+        assert!(PatriciaKey::try_from_felt(TWO_POW_251).is_err());
+        assert!(StorageKey::try_from_felt(Felt::MAX).is_err());
+        assert_eq!(
+            StorageKey::try_from_felt(TWO_POW_251).unwrap_err().to_string(),
+            "Felt 0x800000000000000000000000000000000000000000000000000000000000000 is out of range for a StorageKey."
+        );
+    }
+
+    #[test]
+    fn test_l1_address_bound() {
+        let two_pow_160 = Felt::from_hex_unchecked("0x10000000000000000000000000000000000000000");
+        let highest = H160::try_from_felt(two_pow_160 - Felt::ONE).unwrap();
+        assert_eq!(highest, H160::repeat_byte(0xff));
+        assert_eq!(highest.to_felt(), two_pow_160 - Felt::ONE);
+        assert_eq!(H160::try_from_felt(Felt::from(0x1234)).unwrap().to_felt(), Felt::from(0x1234));
+        assert!(H160::try_from_felt(two_pow_160).is_err());
+        assert!(EthAddress::try_from_felt(two_pow_160).is_err());
+    }
+
+    #[test]
+    fn test_try_from_felts() {
+        assert_eq!(u64::try_from_felts([Felt::ONE, Felt::TWO]).unwrap(), [1, 2]);
+        let too_big = Felt::from(u64::MAX) + Felt::ONE;
+        assert_eq!(
+            u64::try_from_felts([Felt::ONE, too_big, Felt::MAX]),
+            Err(FeltOutOfRange { felt: too_big, target: "u64" })
+        );
+        assert_eq!(u128::try_from_felt(too_big).unwrap(), u64::MAX as u128 + 1);
+    }
+
+    #[test]
+    fn test_felt_from_be_bytes() {
+        assert_eq!(felt_from_be_bytes(&Felt::MAX.to_bytes_be()).unwrap(), Felt::MAX);
+        assert_eq!(felt_from_be_bytes(&[0; 32]).unwrap(), Felt::ZERO);
+        // The prime is the first value out of range.
+        let mut prime = Felt::MAX.to_bytes_be();
+        prime[31] += 1;
+        assert!(felt_from_be_bytes(&prime).is_err());
+        assert!(felt_from_be_bytes(&[0xff; 32]).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 mod felt;
+mod from_felt;
 mod state_update;
 mod to_felt;
 
 pub use felt::{felt_to_u128, felt_to_u64};
+pub use from_felt::{felt_from_be_bytes, BytesToFeltError, FeltOutOfRange, TryFromFelt};
 pub use state_update::ToStateUpdateCore;
-pub use to_felt::ToFelt;
+pub use to_felt::{ToFelt, ToFelts};
@@ -14,6 +14,21 @@ pub trait ToFelt {
     fn to_felt(self) -> Felt;
 }
 
+/// Converts every item of a collection, such as a `Vec` or a slice of a [`ToFelt`] type.
+pub trait ToFelts {
+    fn to_felts(self) -> Vec<Felt>;
+}
+
+impl<I> ToFelts for I
+where
+    I: IntoIterator,
+    I::Item: ToFelt,
+{
+    fn to_felts(self) -> Vec<Felt> {
+        self.into_iter().map(ToFelt::to_felt).collect()
+    }
+}
+
 impl ToFelt for EthAddress {
     fn to_felt(self) -> Felt {
         self.into()
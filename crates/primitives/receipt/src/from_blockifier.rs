@@ -2,14 +2,16 @@ use blockifier::transaction::{
     account_transaction::AccountTransaction,
     objects::{FeeType, GasVector, HasRelatedFeeType, TransactionExecutionInfo},
     transaction_execution::Transaction,
+    transactions::L1HandlerTransaction,
 };
 use cairo_vm::types::builtin_name::BuiltinName;
 use dp_convert::ToFelt;
 use starknet_core::types::Felt;
 
 use crate::{
-    DataAvailabilityResources, DeclareTransactionReceipt, DeployAccountTransactionReceipt, Event, ExecutionResources,
-    ExecutionResult, FeePayment, InvokeTransactionReceipt, MsgToL1, PriceUnit, TransactionReceipt,
+    l1_handler_message_hash, DataAvailabilityResources, DeclareTransactionReceipt, DeployAccountTransactionReceipt,
+    Event, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt, L1HandlerTransactionReceipt,
+    MsgToL1, PriceUnit, TransactionReceipt,
 };
 
 fn blockifier_tx_fee_type(tx: &Transaction) -> FeeType {
@@ -122,19 +124,25 @@ pub fn from_blockifier_execution_info(res: &TransactionExecutionInfo, tx: &Trans
                 execution_result,
             })
         }
-        Transaction::L1HandlerTransaction(_tx) => unimplemented!("l1 handler tx"),
-        // Transaction::L1HandlerTransactionv(tx) => TransactionReceipt::L1Handler(L1HandlerTransactionReceipt {
-        //     transaction_hash,
-        //     actual_fee,
-        //     messages_sent,
-        //     events,
-        //     execution_resources,
-        //     execution_result,
-        //     message_hash: todo!(),
-        // }),
+        Transaction::L1HandlerTransaction(tx) => TransactionReceipt::L1Handler(L1HandlerTransactionReceipt {
+            message_hash: message_hash(tx),
+            transaction_hash,
+            actual_fee,
+            messages_sent,
+            events,
+            execution_resources,
+            execution_result,
+        }),
     }
 }
 
+fn message_hash(tx: &L1HandlerTransaction) -> [u8; 32] {
+    let tx = &tx.tx;
+    let nonce_bytes = tx.nonce.0.to_bytes_le();
+    let nonce = u64::from_le_bytes(nonce_bytes[..8].try_into().unwrap());
+    l1_handler_message_hash(&tx.calldata.0, tx.contract_address.to_felt(), tx.entry_point_selector.0, nonce)
+}
+
 impl From<GasVector> for DataAvailabilityResources {
     fn from(value: GasVector) -> Self {
         DataAvailabilityResources { l1_gas: value.l1_gas as _, l1_data_gas: value.l1_data_gas as _ }
@@ -2,9 +2,9 @@ use dp_convert::ToFelt;
 use starknet_core::types::Felt;
 
 use crate::{
-    DataAvailabilityResources, DeclareTransactionReceipt, DeployAccountTransactionReceipt, DeployTransactionReceipt,
-    Event, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt, L1HandlerTransactionReceipt,
-    MsgToL1, PriceUnit, TransactionReceipt,
+    l1_handler_message_hash, DataAvailabilityResources, DeclareTransactionReceipt, DeployAccountTransactionReceipt,
+    DeployTransactionReceipt, Event, ExecutionResources, ExecutionResult, FeePayment, InvokeTransactionReceipt,
+    L1HandlerTransactionReceipt, MsgToL1, PriceUnit, TransactionReceipt,
 };
 
 impl TransactionReceipt {
@@ -12,45 +12,63 @@ impl TransactionReceipt {
         receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
         tx_type: &starknet_providers::sequencer::models::TransactionType,
     ) -> Self {
+        let actual_fee = FeePayment { amount: receipt.actual_fee, unit: fee_unit(tx_type) };
         match tx_type {
             starknet_providers::sequencer::models::TransactionType::Declare(_) => {
-                TransactionReceipt::Declare(DeclareTransactionReceipt::from(receipt))
-            }
-            starknet_providers::sequencer::models::TransactionType::Deploy(tx) => {
-                TransactionReceipt::Deploy(DeployTransactionReceipt::from_provider(receipt, tx.contract_address))
+                TransactionReceipt::Declare(DeclareTransactionReceipt::from_provider(receipt, actual_fee))
             }
+            starknet_providers::sequencer::models::TransactionType::Deploy(tx) => TransactionReceipt::Deploy(
+                DeployTransactionReceipt::from_provider(receipt, actual_fee, tx.contract_address),
+            ),
             starknet_providers::sequencer::models::TransactionType::DeployAccount(tx) => {
                 TransactionReceipt::DeployAccount(DeployAccountTransactionReceipt::from_provider(
                     receipt,
+                    actual_fee,
                     tx.contract_address.unwrap_or_default(),
                 ))
             }
             starknet_providers::sequencer::models::TransactionType::InvokeFunction(_) => {
-                TransactionReceipt::Invoke(InvokeTransactionReceipt::from(receipt))
+                TransactionReceipt::Invoke(InvokeTransactionReceipt::from_provider(receipt, actual_fee))
             }
             starknet_providers::sequencer::models::TransactionType::L1Handler(tx) => {
-                let (from_address, payload) = tx.calldata.split_first().unwrap_or((&Felt::ZERO, &[]));
                 let nonce_bytes = tx.nonce.unwrap_or_default().to_bytes_le();
                 let nonce = u64::from_le_bytes(nonce_bytes[..8].try_into().unwrap());
-                let msg_to_l2 = starknet_core::types::MsgToL2 {
-                    from_address: (*from_address).try_into().unwrap_or(Felt::ZERO.try_into().unwrap()),
-                    to_address: tx.contract_address,
-                    selector: tx.entry_point_selector,
-                    payload: payload.to_vec(),
-                    nonce,
-                };
-                let message_hash = msg_to_l2.hash();
-                TransactionReceipt::L1Handler(L1HandlerTransactionReceipt::from_provider(receipt, message_hash))
+                let message_hash =
+                    l1_handler_message_hash(&tx.calldata, tx.contract_address, tx.entry_point_selector, nonce);
+                TransactionReceipt::L1Handler(L1HandlerTransactionReceipt::from_provider(
+                    receipt,
+                    actual_fee,
+                    message_hash,
+                ))
             }
         }
     }
 }
 
-impl From<starknet_providers::sequencer::models::ConfirmedTransactionReceipt> for DeclareTransactionReceipt {
-    fn from(receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt) -> Self {
+/// The fees are paid in STRK from the v3 transactions, and in ETH before.
+fn fee_unit(tx_type: &starknet_providers::sequencer::models::TransactionType) -> PriceUnit {
+    let version = match tx_type {
+        starknet_providers::sequencer::models::TransactionType::Declare(tx) => tx.version,
+        starknet_providers::sequencer::models::TransactionType::Deploy(tx) => tx.version,
+        starknet_providers::sequencer::models::TransactionType::DeployAccount(tx) => tx.version,
+        starknet_providers::sequencer::models::TransactionType::InvokeFunction(tx) => tx.version,
+        starknet_providers::sequencer::models::TransactionType::L1Handler(tx) => tx.version,
+    };
+    if version == Felt::THREE {
+        PriceUnit::Fri
+    } else {
+        PriceUnit::Wei
+    }
+}
+
+impl DeclareTransactionReceipt {
+    fn from_provider(
+        receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
+        actual_fee: FeePayment,
+    ) -> Self {
         Self {
             transaction_hash: receipt.transaction_hash,
-            actual_fee: receipt.actual_fee.into(),
+            actual_fee,
             messages_sent: receipt.l2_to_l1_messages.into_iter().map(MsgToL1::from).collect(),
             events: receipt.events.into_iter().map(Event::from).collect(),
             execution_resources: receipt.execution_resources.map(ExecutionResources::from).unwrap_or_default(),
@@ -62,11 +80,12 @@ impl From<starknet_providers::sequencer::models::ConfirmedTransactionReceipt> fo
 impl DeployTransactionReceipt {
     fn from_provider(
         receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
+        actual_fee: FeePayment,
         contract_address: Felt,
     ) -> Self {
         Self {
             transaction_hash: receipt.transaction_hash,
-            actual_fee: receipt.actual_fee.into(),
+            actual_fee,
             messages_sent: receipt.l2_to_l1_messages.into_iter().map(MsgToL1::from).collect(),
             events: receipt.events.into_iter().map(Event::from).collect(),
             execution_resources: receipt.execution_resources.map(ExecutionResources::from).unwrap_or_default(),
@@ -79,11 +98,12 @@ impl DeployTransactionReceipt {
 impl DeployAccountTransactionReceipt {
     fn from_provider(
         receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
+        actual_fee: FeePayment,
         contract_address: Felt,
     ) -> Self {
         Self {
             transaction_hash: receipt.transaction_hash,
-            actual_fee: receipt.actual_fee.into(),
+            actual_fee,
             messages_sent: receipt.l2_to_l1_messages.into_iter().map(MsgToL1::from).collect(),
             events: receipt.events.into_iter().map(Event::from).collect(),
             execution_resources: receipt.execution_resources.map(ExecutionResources::from).unwrap_or_default(),
@@ -93,11 +113,14 @@ impl DeployAccountTransactionReceipt {
     }
 }
 
-impl From<starknet_providers::sequencer::models::ConfirmedTransactionReceipt> for InvokeTransactionReceipt {
-    fn from(receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt) -> Self {
+impl InvokeTransactionReceipt {
+    fn from_provider(
+        receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
+        actual_fee: FeePayment,
+    ) -> Self {
         Self {
             transaction_hash: receipt.transaction_hash,
-            actual_fee: receipt.actual_fee.into(),
+            actual_fee,
             messages_sent: receipt.l2_to_l1_messages.into_iter().map(MsgToL1::from).collect(),
             events: receipt.events.into_iter().map(Event::from).collect(),
             execution_resources: receipt.execution_resources.map(ExecutionResources::from).unwrap_or_default(),
@@ -109,12 +132,13 @@ impl From<starknet_providers::sequencer::models::ConfirmedTransactionReceipt> fo
 impl L1HandlerTransactionReceipt {
     fn from_provider(
         receipt: starknet_providers::sequencer::models::ConfirmedTransactionReceipt,
-        message_hash: starknet_core::types::Hash256,
+        actual_fee: FeePayment,
+        message_hash: [u8; 32],
     ) -> Self {
         Self {
-            message_hash: message_hash.try_into().unwrap_or_default(),
+            message_hash,
             transaction_hash: receipt.transaction_hash,
-            actual_fee: receipt.actual_fee.into(),
+            actual_fee,
             messages_sent: receipt.l2_to_l1_messages.into_iter().map(MsgToL1::from).collect(),
             events: receipt.events.into_iter().map(Event::from).collect(),
             execution_resources: receipt.execution_resources.map(ExecutionResources::from).unwrap_or_default(),
@@ -123,12 +147,6 @@ impl L1HandlerTransactionReceipt {
     }
 }
 
-impl From<Felt> for FeePayment {
-    fn from(fee: Felt) -> Self {
-        Self { amount: fee, unit: PriceUnit::Wei }
-    }
-}
-
 impl From<starknet_providers::sequencer::models::L2ToL1Message> for MsgToL1 {
     fn from(msg: starknet_providers::sequencer::models::L2ToL1Message) -> Self {
         Self { from_address: msg.from_address, to_address: msg.to_address.to_felt(), payload: msg.payload }
@@ -161,21 +179,95 @@ impl From<starknet_providers::sequencer::models::ExecutionResources> for Executi
     }
 }
 
+/// The blocks before 0.12 have no execution status: their transactions are all successful, the failed ones were not
+/// included.
 fn execution_result(
     status: Option<starknet_providers::sequencer::models::TransactionExecutionStatus>,
     revert_error: Option<String>,
 ) -> ExecutionResult {
     let reason = revert_error.unwrap_or_default();
     match status {
-        Some(starknet_providers::sequencer::models::TransactionExecutionStatus::Succeeded) => {
+        Some(starknet_providers::sequencer::models::TransactionExecutionStatus::Succeeded) | None => {
             ExecutionResult::Succeeded
         }
         Some(starknet_providers::sequencer::models::TransactionExecutionStatus::Reverted) => {
             ExecutionResult::Reverted { reason }
         }
         Some(starknet_providers::sequencer::models::TransactionExecutionStatus::Rejected) => {
-            ExecutionResult::Reverted { reason }
+            ExecutionResult::Rejected { reason }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts the receipt of an invoke transaction of version `version`, the feeder fields of the receipt are
+    /// overridden by `receipt_fields`.
+    fn invoke_receipt(version: &str, receipt_fields: serde_json::Value) -> InvokeTransactionReceipt {
+        let mut receipt = serde_json::json!({
+            "transaction_hash": "0x123",
+            "transaction_index": 0,
+            "l2_to_l1_messages": [],
+            "events": [],
+            "actual_fee": "0x2a",
+        });
+        receipt.as_object_mut().unwrap().extend(receipt_fields.as_object().unwrap().clone());
+        let tx = serde_json::from_value(serde_json::json!({
+            "type": "INVOKE_FUNCTION",
+            "transaction_hash": "0x123",
+            "sender_address": "0x456",
+            "calldata": [],
+            "signature": [],
+            "nonce": "0x0",
+            "version": version,
+        }))
+        .unwrap();
+        match TransactionReceipt::from_provider(serde_json::from_value(receipt).unwrap(), &tx) {
+            TransactionReceipt::Invoke(receipt) => receipt,
+            receipt => panic!("Expected an invoke receipt, got {receipt:?}"),
         }
-        None => ExecutionResult::Reverted { reason },
+    }
+
+    #[test]
+    fn test_fee_unit_from_provider() {
+        let succeeded = serde_json::json!({ "execution_status": "SUCCEEDED" });
+        let v1 = invoke_receipt("0x1", succeeded.clone());
+        let v3 = invoke_receipt("0x3", succeeded);
+        assert_eq!(v1.actual_fee, FeePayment { amount: Felt::from(42), unit: PriceUnit::Wei });
+        assert_eq!(v3.actual_fee, FeePayment { amount: Felt::from(42), unit: PriceUnit::Fri });
+        assert_ne!(v1.actual_fee.unit, v3.actual_fee.unit);
+    }
+
+    #[test]
+    fn test_execution_result_before_0_12() {
+        // The receipts had no execution status, only the successful transactions were included.
+        let receipt = invoke_receipt("0x0", serde_json::json!({}));
+        assert_eq!(receipt.execution_result, ExecutionResult::Succeeded);
+    }
+
+    #[test]
+    fn test_execution_result_reverted() {
+        let receipt =
+            invoke_receipt("0x1", serde_json::json!({ "execution_status": "REVERTED", "revert_error": "Out of gas" }));
+        assert_eq!(receipt.execution_result, ExecutionResult::Reverted { reason: "Out of gas".into() });
+    }
+
+    #[test]
+    fn test_execution_result_rejected() {
+        let receipt = invoke_receipt(
+            "0x1",
+            serde_json::json!({ "execution_status": "REJECTED", "revert_error": "Invalid transaction nonce" }),
+        );
+        assert_eq!(receipt.execution_result, ExecutionResult::Rejected { reason: "Invalid transaction nonce".into() });
+        assert_eq!(receipt.execution_result.revert_reason(), Some("Invalid transaction nonce"));
+
+        // Served as reverted by the specification.
+        let core: starknet_core::types::ExecutionResult = receipt.execution_result.into();
+        assert_eq!(
+            core,
+            starknet_core::types::ExecutionResult::Reverted { reason: "Invalid transaction nonce".into() }
+        );
     }
 }
@@ -41,6 +41,36 @@ impl TransactionReceipt {
         }
     }
 
+    pub fn actual_fee_mut(&mut self) -> &mut FeePayment {
+        match self {
+            TransactionReceipt::Invoke(receipt) => &mut receipt.actual_fee,
+            TransactionReceipt::L1Handler(receipt) => &mut receipt.actual_fee,
+            TransactionReceipt::Declare(receipt) => &mut receipt.actual_fee,
+            TransactionReceipt::Deploy(receipt) => &mut receipt.actual_fee,
+            TransactionReceipt::DeployAccount(receipt) => &mut receipt.actual_fee,
+        }
+    }
+
+    pub fn execution_resources(&self) -> &ExecutionResources {
+        match self {
+            TransactionReceipt::Invoke(receipt) => &receipt.execution_resources,
+            TransactionReceipt::L1Handler(receipt) => &receipt.execution_resources,
+            TransactionReceipt::Declare(receipt) => &receipt.execution_resources,
+            TransactionReceipt::Deploy(receipt) => &receipt.execution_resources,
+            TransactionReceipt::DeployAccount(receipt) => &receipt.execution_resources,
+        }
+    }
+
+    pub fn execution_resources_mut(&mut self) -> &mut ExecutionResources {
+        match self {
+            TransactionReceipt::Invoke(receipt) => &mut receipt.execution_resources,
+            TransactionReceipt::L1Handler(receipt) => &mut receipt.execution_resources,
+            TransactionReceipt::Declare(receipt) => &mut receipt.execution_resources,
+            TransactionReceipt::Deploy(receipt) => &mut receipt.execution_resources,
+            TransactionReceipt::DeployAccount(receipt) => &mut receipt.execution_resources,
+        }
+    }
+
     pub fn data_availability(&self) -> &DataAvailabilityResources {
         match self {
             TransactionReceipt::Invoke(receipt) => &receipt.execution_resources.data_availability,
@@ -81,16 +111,17 @@ impl TransactionReceipt {
         }
     }
 
-    pub fn execution_result(&self) -> ExecutionResult {
+    pub fn execution_result(&self) -> &ExecutionResult {
         match self {
-            TransactionReceipt::Invoke(receipt) => receipt.execution_result.clone(),
-            TransactionReceipt::L1Handler(receipt) => receipt.execution_result.clone(),
-            TransactionReceipt::Declare(receipt) => receipt.execution_result.clone(),
-            TransactionReceipt::Deploy(receipt) => receipt.execution_result.clone(),
-            TransactionReceipt::DeployAccount(receipt) => receipt.execution_result.clone(),
+            TransactionReceipt::Invoke(receipt) => &receipt.execution_result,
+            TransactionReceipt::L1Handler(receipt) => &receipt.execution_result,
+            TransactionReceipt::Declare(receipt) => &receipt.execution_result,
+            TransactionReceipt::Deploy(receipt) => &receipt.execution_result,
+            TransactionReceipt::DeployAccount(receipt) => &receipt.execution_result,
         }
     }
 
+    /// The leaf of the receipt in the receipt commitment of the 0.13.2 block headers.
     pub fn compute_hash(&self) -> Felt {
         Poseidon::hash_array(&[
             self.transaction_hash(),
@@ -104,6 +135,41 @@ impl TransactionReceipt {
     }
 }
 
+/// Hash of the L1 to L2 message executed by an L1 handler transaction. The first calldata item of the transaction is
+/// the L1 sender of the message, the others are its payload.
+///
+/// The L1 handler transactions of the chain always start with an Ethereum address. When one does not, the hash is the
+/// one of a message sent by the zero address, which is not the hash of any message, and the error is logged.
+pub fn l1_handler_message_hash(
+    calldata: &[Felt],
+    contract_address: Felt,
+    entry_point_selector: Felt,
+    nonce: u64,
+) -> [u8; 32] {
+    let Some((from_address, payload)) = calldata.split_first() else {
+        log::error!("L1 handler transaction with nonce {nonce} has no L1 sender, its message hash is not valid");
+        return l1_handler_message_hash(&[Felt::ZERO], contract_address, entry_point_selector, nonce);
+    };
+    let from_address = match (*from_address).try_into() {
+        Ok(from_address) => from_address,
+        Err(_) => {
+            log::error!(
+                "L1 handler transaction with nonce {nonce} has L1 sender {from_address:#x}, which is not an Ethereum \
+                 address: its message hash is not valid"
+            );
+            Felt::ZERO.try_into().expect("Zero is an Ethereum address")
+        }
+    };
+    let msg_to_l2 = starknet_core::types::MsgToL2 {
+        from_address,
+        to_address: contract_address,
+        selector: entry_point_selector,
+        payload: payload.to_vec(),
+        nonce,
+    };
+    *msg_to_l2.hash().as_bytes()
+}
+
 fn compute_messages_sent_hash(messages: &[MsgToL1]) -> Felt {
     let messages_len_as_felt: Felt = (messages.len() as u64).into();
 
@@ -113,7 +179,7 @@ fn compute_messages_sent_hash(messages: &[MsgToL1]) -> Felt {
             std::iter::once(msg.from_address)
                 .chain(std::iter::once(msg.to_address))
                 .chain(std::iter::once(payload_len_as_felt))
-                .chain(msg.payload.iter().cloned())
+                .chain(msg.payload.iter().copied())
         }))
         .collect();
 
@@ -132,8 +198,9 @@ pub struct InvokeTransactionReceipt {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct L1HandlerTransactionReceipt {
-    // normally this would be a Hash256, but the serde implementation doesn't work with bincode.
-    pub message_hash: Felt,
+    /// Keccak hash of the L1 to L2 message, which does not fit in a felt. This is the bytes of a
+    /// [`starknet_core::types::Hash256`], whose serde implementation doesn't work with bincode.
+    pub message_hash: [u8; 32],
     pub transaction_hash: Felt,
     pub actual_fee: FeePayment,
     pub messages_sent: Vec<MsgToL1>,
@@ -247,14 +314,29 @@ pub struct DataAvailabilityResources {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ExecutionResult {
     Succeeded,
-    Reverted { reason: String },
+    Reverted {
+        reason: String,
+    },
+    /// Rejected by the sequencer, which still included the transaction in its block. The specification has no such
+    /// status, these transactions are served as reverted.
+    Rejected {
+        reason: String,
+    },
 }
 
 impl ExecutionResult {
-    fn compute_hash(&self) -> Felt {
+    /// The reason of the failure, `None` when the transaction succeeded.
+    pub fn revert_reason(&self) -> Option<&str> {
         match self {
-            ExecutionResult::Succeeded => Felt::ZERO,
-            ExecutionResult::Reverted { reason } => starknet_keccak(reason.as_bytes()),
+            ExecutionResult::Succeeded => None,
+            ExecutionResult::Reverted { reason } | ExecutionResult::Rejected { reason } => Some(reason),
+        }
+    }
+
+    fn compute_hash(&self) -> Felt {
+        match self.revert_reason() {
+            None => Felt::ZERO,
+            Some(reason) => starknet_keccak(reason.as_bytes()),
         }
     }
 }
@@ -296,6 +378,51 @@ mod tests {
         assert_eq!(receipt, decoded_receipt);
     }
 
+    #[test]
+    fn test_l1_handler_message_hash_above_felt_modulus() {
+        // Keccak hashes are 256 bits: most of them do not fit in a felt.
+        let receipt = L1HandlerTransactionReceipt {
+            message_hash: [0xff; 32],
+            transaction_hash: Felt::from(1),
+            actual_fee: FeePayment { amount: Felt::from(2), unit: PriceUnit::Wei },
+            messages_sent: vec![],
+            events: vec![],
+            execution_resources: ExecutionResources::default(),
+            execution_result: ExecutionResult::Succeeded,
+        };
+
+        let encoded_receipt = bincode::serialize(&TransactionReceipt::L1Handler(receipt.clone())).unwrap();
+        let decoded_receipt: TransactionReceipt = bincode::deserialize(&encoded_receipt).unwrap();
+        assert_eq!(decoded_receipt, TransactionReceipt::L1Handler(receipt.clone()));
+
+        let core_receipt =
+            receipt.clone().to_starknet_core(starknet_core::types::TransactionFinalityStatus::AcceptedOnL2);
+        assert_eq!(core_receipt.message_hash.as_bytes(), &[0xff; 32]);
+        assert_eq!(L1HandlerTransactionReceipt::from(core_receipt).message_hash, [0xff; 32]);
+    }
+
+    // TODO: check the hash of a mainnet L1 handler transaction whose message hash does not fit in a felt, against the
+    // message hash served by the feeder gateway.
+    #[test]
+    fn test_l1_handler_message_hash() {
+        let calldata = [Felt::from(0xae), Felt::ONE, Felt::TWO];
+        let hash = l1_handler_message_hash(&calldata, Felt::from(0x1234), Felt::from(0x5678), 7);
+
+        assert_ne!(hash, [0; 32]);
+        assert_ne!(hash, l1_handler_message_hash(&calldata, Felt::from(0x1234), Felt::from(0x5678), 8));
+        assert_ne!(hash, l1_handler_message_hash(&calldata[..2], Felt::from(0x1234), Felt::from(0x5678), 7));
+        assert_ne!(hash, l1_handler_message_hash(&calldata, Felt::from(0x1235), Felt::from(0x5678), 7));
+
+        // A sender that is not an Ethereum address is hashed as the zero address.
+        let zero_sender = l1_handler_message_hash(&[Felt::ZERO, Felt::ONE], Felt::from(0x1234), Felt::from(0x5678), 7);
+        let invalid_sender = [Felt::from_hex_unchecked("0x10000000000000000000000000000000000000000"), Felt::ONE];
+        assert_eq!(l1_handler_message_hash(&invalid_sender, Felt::from(0x1234), Felt::from(0x5678), 7), zero_sender);
+        assert_eq!(
+            l1_handler_message_hash(&[], Felt::from(0x1234), Felt::from(0x5678), 7),
+            l1_handler_message_hash(&[Felt::ZERO], Felt::from(0x1234), Felt::from(0x5678), 7)
+        );
+    }
+
     #[test]
     fn test_compute_messages_sent_hash() {
         let msg1 = MsgToL1 { from_address: Felt::ZERO, to_address: Felt::ONE, payload: vec![Felt::TWO, Felt::THREE] };
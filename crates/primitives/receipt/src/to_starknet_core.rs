@@ -52,7 +52,7 @@ impl L1HandlerTransactionReceipt {
         finality_status: starknet_core::types::TransactionFinalityStatus,
     ) -> starknet_core::types::L1HandlerTransactionReceipt {
         starknet_core::types::L1HandlerTransactionReceipt {
-            message_hash: self.message_hash.into(),
+            message_hash: starknet_core::types::Hash256::from_bytes(self.message_hash),
             transaction_hash: self.transaction_hash,
             actual_fee: self.actual_fee.into(),
             finality_status,
@@ -176,7 +176,9 @@ impl From<ExecutionResult> for starknet_core::types::ExecutionResult {
     fn from(result: ExecutionResult) -> Self {
         match result {
             ExecutionResult::Succeeded => starknet_core::types::ExecutionResult::Succeeded,
-            ExecutionResult::Reverted { reason } => starknet_core::types::ExecutionResult::Reverted { reason },
+            ExecutionResult::Reverted { reason } | ExecutionResult::Rejected { reason } => {
+                starknet_core::types::ExecutionResult::Reverted { reason }
+            }
         }
     }
 }
@@ -39,26 +39,14 @@ impl From<PendingStateUpdate> for starknet_core::types::PendingStateUpdate {
 
 impl From<starknet_core::types::StateDiff> for StateDiff {
     fn from(state_diff: starknet_core::types::StateDiff) -> Self {
-        Self {
-            storage_diffs: state_diff.storage_diffs.into_iter().map(|diff| diff.into()).collect(),
-            deprecated_declared_classes: state_diff.deprecated_declared_classes,
-            declared_classes: state_diff
-                .declared_classes
-                .into_iter()
-                .map(|declared_class| declared_class.into())
-                .collect(),
-            deployed_contracts: state_diff
-                .deployed_contracts
-                .into_iter()
-                .map(|deployed_contract| deployed_contract.into())
-                .collect(),
-            replaced_classes: state_diff
-                .replaced_classes
-                .into_iter()
-                .map(|replaced_class| replaced_class.into())
-                .collect(),
-            nonces: state_diff.nonces.into_iter().map(|nonce| nonce.into()).collect(),
-        }
+        Self::new(
+            state_diff.storage_diffs.into_iter().map(|diff| diff.into()).collect(),
+            state_diff.deprecated_declared_classes,
+            state_diff.declared_classes.into_iter().map(|declared_class| declared_class.into()).collect(),
+            state_diff.deployed_contracts.into_iter().map(|deployed_contract| deployed_contract.into()).collect(),
+            state_diff.replaced_classes.into_iter().map(|replaced_class| replaced_class.into()).collect(),
+            state_diff.nonces.into_iter().map(|nonce| nonce.into()).collect(),
+        )
     }
 }
 
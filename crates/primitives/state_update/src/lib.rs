@@ -1,4 +1,5 @@
 mod into_starknet_core;
+mod normalize;
 
 use starknet_types_core::{
     felt::Felt,
@@ -19,17 +20,64 @@ pub struct PendingStateUpdate {
     pub state_diff: StateDiff,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// A state diff in canonical form. The fields are private so that every state diff goes through
+/// [`StateDiff::new`], which normalizes it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct StateDiff {
-    pub storage_diffs: Vec<ContractStorageDiffItem>,
-    pub deprecated_declared_classes: Vec<Felt>,
-    pub declared_classes: Vec<DeclaredClassItem>,
-    pub deployed_contracts: Vec<DeployedContractItem>,
-    pub replaced_classes: Vec<ReplacedClassItem>,
-    pub nonces: Vec<NonceUpdate>,
+    storage_diffs: Vec<ContractStorageDiffItem>,
+    deprecated_declared_classes: Vec<Felt>,
+    declared_classes: Vec<DeclaredClassItem>,
+    deployed_contracts: Vec<DeployedContractItem>,
+    replaced_classes: Vec<ReplacedClassItem>,
+    nonces: Vec<NonceUpdate>,
 }
 
+/// The lists of a [`StateDiff`], in the order of the arguments of [`StateDiff::new`].
+pub type StateDiffParts = (
+    Vec<ContractStorageDiffItem>,
+    Vec<Felt>,
+    Vec<DeclaredClassItem>,
+    Vec<DeployedContractItem>,
+    Vec<ReplacedClassItem>,
+    Vec<NonceUpdate>,
+);
+
 impl StateDiff {
+    pub fn storage_diffs(&self) -> &[ContractStorageDiffItem] {
+        &self.storage_diffs
+    }
+
+    pub fn deprecated_declared_classes(&self) -> &[Felt] {
+        &self.deprecated_declared_classes
+    }
+
+    pub fn declared_classes(&self) -> &[DeclaredClassItem] {
+        &self.declared_classes
+    }
+
+    pub fn deployed_contracts(&self) -> &[DeployedContractItem] {
+        &self.deployed_contracts
+    }
+
+    pub fn replaced_classes(&self) -> &[ReplacedClassItem] {
+        &self.replaced_classes
+    }
+
+    pub fn nonces(&self) -> &[NonceUpdate] {
+        &self.nonces
+    }
+
+    pub fn into_parts(self) -> StateDiffParts {
+        (
+            self.storage_diffs,
+            self.deprecated_declared_classes,
+            self.declared_classes,
+            self.deployed_contracts,
+            self.replaced_classes,
+            self.nonces,
+        )
+    }
+
     pub fn is_empty(&self) -> bool {
         self.deployed_contracts.is_empty()
             && self.declared_classes.is_empty()
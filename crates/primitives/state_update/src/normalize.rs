@@ -0,0 +1,303 @@
+//! The canonical form of a [`StateDiff`].
+//!
+//! The feeder gateway and blockifier can both return the same storage key twice in a diff. The storage writes then
+//! depend on which one is applied last, so every state diff is normalized when it is built.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use starknet_types_core::felt::Felt;
+
+use crate::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, ReplacedClassItem, StateDiff,
+    StorageEntry,
+};
+
+impl StateDiff {
+    /// Builds a state diff in canonical form, see [`StateDiff::normalize`].
+    pub fn new(
+        storage_diffs: Vec<ContractStorageDiffItem>,
+        deprecated_declared_classes: Vec<Felt>,
+        declared_classes: Vec<DeclaredClassItem>,
+        deployed_contracts: Vec<DeployedContractItem>,
+        replaced_classes: Vec<ReplacedClassItem>,
+        nonces: Vec<NonceUpdate>,
+    ) -> Self {
+        let mut state_diff = Self {
+            storage_diffs,
+            deprecated_declared_classes,
+            declared_classes,
+            deployed_contracts,
+            replaced_classes,
+            nonces,
+        };
+        state_diff.normalize();
+        state_diff
+    }
+
+    /// Sorts every list by address, and the storage entries by key. An address, storage key or class hash that
+    /// appears several times is only kept once, with the last value.
+    pub fn normalize(&mut self) {
+        let storage = last_write_wins(
+            "storage key",
+            self.storage_diffs.drain(..).flat_map(|ContractStorageDiffItem { address, storage_entries }| {
+                storage_entries.into_iter().map(move |StorageEntry { key, value }| ((address, key), value))
+            }),
+        );
+        for ((address, key), value) in storage {
+            match self.storage_diffs.last_mut() {
+                Some(diff) if diff.address == address => diff.storage_entries.push(StorageEntry { key, value }),
+                _ => self
+                    .storage_diffs
+                    .push(ContractStorageDiffItem { address, storage_entries: vec![StorageEntry { key, value }] }),
+            }
+        }
+
+        let mut deprecated_declared_classes = BTreeSet::new();
+        for class_hash in self.deprecated_declared_classes.drain(..) {
+            if !deprecated_declared_classes.insert(class_hash) {
+                log::debug!("Duplicate deprecated declared class {class_hash:#x} in state diff");
+            }
+        }
+        self.deprecated_declared_classes = deprecated_declared_classes.into_iter().collect();
+
+        self.declared_classes = last_write_wins(
+            "declared class",
+            self.declared_classes.drain(..).map(|item| (item.class_hash, item.compiled_class_hash)),
+        )
+        .into_iter()
+        .map(|(class_hash, compiled_class_hash)| DeclaredClassItem { class_hash, compiled_class_hash })
+        .collect();
+
+        self.deployed_contracts = last_write_wins(
+            "deployed contract",
+            self.deployed_contracts.drain(..).map(|item| (item.address, item.class_hash)),
+        )
+        .into_iter()
+        .map(|(address, class_hash)| DeployedContractItem { address, class_hash })
+        .collect();
+
+        self.replaced_classes = last_write_wins(
+            "replaced class",
+            self.replaced_classes.drain(..).map(|item| (item.contract_address, item.class_hash)),
+        )
+        .into_iter()
+        .map(|(contract_address, class_hash)| ReplacedClassItem { contract_address, class_hash })
+        .collect();
+
+        self.nonces = last_write_wins("nonce", self.nonces.drain(..).map(|item| (item.contract_address, item.nonce)))
+            .into_iter()
+            .map(|(contract_address, nonce)| NonceUpdate { contract_address, nonce })
+            .collect();
+    }
+
+    /// Drops the storage writes that leave the value as it was before the block. `previous_value` returns the value
+    /// of a storage key of a contract, `None` when it was never written.
+    ///
+    /// The normal form does not require this, the state diffs of synced blocks must be kept as the sequencer committed
+    /// to them. It only makes the diffs of produced blocks smaller.
+    pub fn remove_noop_storage_writes<E>(
+        &mut self,
+        mut previous_value: impl FnMut(&Felt, &Felt) -> Result<Option<Felt>, E>,
+    ) -> Result<(), E> {
+        for diff in &mut self.storage_diffs {
+            let mut entries = Vec::with_capacity(diff.storage_entries.len());
+            for entry in diff.storage_entries.drain(..) {
+                if previous_value(&diff.address, &entry.key)?.unwrap_or(Felt::ZERO) != entry.value {
+                    entries.push(entry);
+                }
+            }
+            diff.storage_entries = entries;
+        }
+        self.storage_diffs.retain(|diff| !diff.storage_entries.is_empty());
+        Ok(())
+    }
+}
+
+fn last_write_wins<K: Ord + fmt::Debug, V>(what: &str, items: impl Iterator<Item = (K, V)>) -> BTreeMap<K, V> {
+    let mut map = BTreeMap::new();
+    for (key, value) in items {
+        match map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                log::debug!("Duplicate {what} {:?} in state diff, keeping the last value", entry.key());
+                entry.insert(value);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn felt() -> impl Strategy<Value = Felt> {
+        // Few distinct values, so that the duplicates are common.
+        (0u64..8).prop_map(Felt::from)
+    }
+
+    prop_compose! {
+        fn state_diff()(
+            storage in prop::collection::vec((felt(), felt(), felt()), 0..24),
+            deprecated_declared_classes in prop::collection::vec(felt(), 0..8),
+            declared_classes in prop::collection::vec((felt(), felt()), 0..8),
+            deployed_contracts in prop::collection::vec((felt(), felt()), 0..8),
+            replaced_classes in prop::collection::vec((felt(), felt()), 0..8),
+            nonces in prop::collection::vec((felt(), felt()), 0..8),
+        ) -> StateDiff {
+            StateDiff {
+                storage_diffs: storage
+                    .into_iter()
+                    .map(|(address, key, value)| ContractStorageDiffItem {
+                        address,
+                        storage_entries: vec![StorageEntry { key, value }],
+                    })
+                    .collect(),
+                deprecated_declared_classes,
+                declared_classes: declared_classes
+                    .into_iter()
+                    .map(|(class_hash, compiled_class_hash)| DeclaredClassItem { class_hash, compiled_class_hash })
+                    .collect(),
+                deployed_contracts: deployed_contracts
+                    .into_iter()
+                    .map(|(address, class_hash)| DeployedContractItem { address, class_hash })
+                    .collect(),
+                replaced_classes: replaced_classes
+                    .into_iter()
+                    .map(|(contract_address, class_hash)| ReplacedClassItem { contract_address, class_hash })
+                    .collect(),
+                nonces: nonces
+                    .into_iter()
+                    .map(|(contract_address, nonce)| NonceUpdate { contract_address, nonce })
+                    .collect(),
+            }
+        }
+    }
+
+    fn normalized(mut state_diff: StateDiff) -> StateDiff {
+        state_diff.normalize();
+        state_diff
+    }
+
+    #[test]
+    fn test_duplicate_storage_keys_last_write_wins() {
+        let state_diff = StateDiff::new(
+            vec![
+                ContractStorageDiffItem {
+                    address: Felt::TWO,
+                    storage_entries: vec![
+                        StorageEntry { key: Felt::THREE, value: Felt::ONE },
+                        StorageEntry { key: Felt::ONE, value: Felt::ONE },
+                    ],
+                },
+                ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::ONE }],
+                },
+                ContractStorageDiffItem {
+                    address: Felt::TWO,
+                    storage_entries: vec![StorageEntry { key: Felt::THREE, value: Felt::TWO }],
+                },
+            ],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![
+                NonceUpdate { contract_address: Felt::ONE, nonce: Felt::ONE },
+                NonceUpdate { contract_address: Felt::ONE, nonce: Felt::TWO },
+            ],
+        );
+
+        assert_eq!(
+            state_diff.storage_diffs,
+            vec![
+                ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::ONE }],
+                },
+                ContractStorageDiffItem {
+                    address: Felt::TWO,
+                    storage_entries: vec![
+                        StorageEntry { key: Felt::ONE, value: Felt::ONE },
+                        StorageEntry { key: Felt::THREE, value: Felt::TWO },
+                    ],
+                },
+            ]
+        );
+        assert_eq!(state_diff.nonces, vec![NonceUpdate { contract_address: Felt::ONE, nonce: Felt::TWO }]);
+    }
+
+    #[test]
+    fn test_remove_noop_storage_writes() {
+        let mut state_diff = StateDiff::new(
+            vec![
+                ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![
+                        StorageEntry { key: Felt::ONE, value: Felt::ZERO },
+                        StorageEntry { key: Felt::TWO, value: Felt::THREE },
+                    ],
+                },
+                ContractStorageDiffItem {
+                    address: Felt::TWO,
+                    storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::TWO }],
+                },
+            ],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        );
+
+        // Key 1 of contract 1 was never written, and key 1 of contract 2 is already 2.
+        state_diff
+            .remove_noop_storage_writes(|address, key| {
+                Ok::<_, ()>((*address == Felt::TWO && *key == Felt::ONE).then_some(Felt::TWO))
+            })
+            .unwrap();
+
+        assert_eq!(
+            state_diff.storage_diffs,
+            vec![ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![StorageEntry { key: Felt::TWO, value: Felt::THREE }],
+            }]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_normalize_is_idempotent(state_diff in state_diff()) {
+            let once = normalized(state_diff);
+            prop_assert_eq!(normalized(once.clone()), once);
+        }
+
+        #[test]
+        fn proptest_normalized_hash_is_stable(state_diff in state_diff()) {
+            // The order of the writes to different keys does not matter.
+            let mut shuffled = state_diff.clone();
+            shuffled.storage_diffs.reverse();
+            shuffled.storage_diffs.sort_by_key(|diff| diff.storage_entries[0].key);
+            let last_writes: BTreeMap<_, _> = state_diff
+                .storage_diffs
+                .iter()
+                .map(|diff| ((diff.address, diff.storage_entries[0].key), diff.storage_entries[0].value))
+                .collect();
+            shuffled.storage_diffs.retain(|diff| {
+                last_writes.get(&(diff.address, diff.storage_entries[0].key)) == Some(&diff.storage_entries[0].value)
+            });
+
+            let (normalized_diff, normalized_shuffled) = (normalized(state_diff), normalized(shuffled));
+            prop_assert_eq!(normalized_shuffled.compute_hash(), normalized_diff.compute_hash());
+            prop_assert_eq!(normalized_shuffled, normalized_diff);
+        }
+    }
+}
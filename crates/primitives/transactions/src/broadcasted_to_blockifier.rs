@@ -1,6 +1,10 @@
 use crate::{to_starknet_api::TransactionApiError, Transaction, TransactionWithHash};
 use blockifier::{execution::errors::ContractClassError, transaction::errors::TransactionExecutionError};
-use dp_class::{to_blockifier_class, ClassHash, ClassInfo, ContractClass, ConvertedClass, ToCompiledClass};
+use dp_class::{
+    to_blockifier_class, ClassHash, ClassInfo, CompiledClass, ContractClass, ConvertedClass, ToCompiledClass,
+};
+use dp_convert::TryFromFelt;
+use starknet_api::core::ContractAddress;
 use starknet_api::transaction::TransactionHash;
 use starknet_types_core::felt::Felt;
 
@@ -18,6 +22,14 @@ pub enum BroadcastedToBlockifierError {
     ConvertTxBlockifierError(#[from] TransactionExecutionError),
     #[error("Failed to convert contract class: {0}")]
     ConvertContractClassError(#[from] ContractClassError),
+    #[error("The transaction declares the compiled class hash {declared:#x}, the class compiles to {computed:#x}")]
+    CompiledClassHashMismatch { declared: Felt, computed: Felt },
+    #[error("Query transactions can only be estimated or simulated")]
+    QueryTransactionSubmitted,
+    #[error("The max fee of the transaction is zero")]
+    ZeroMaxFee,
+    #[error("The L1 gas bounds of the transaction are zero")]
+    ZeroResourceBounds,
 }
 
 pub fn broadcasted_to_blockifier(
@@ -59,6 +71,7 @@ pub fn broadcasted_to_blockifier(
             starknet_core::types::BroadcastedDeclareTransaction::V2(tx) => {
                 let compiled = tx.contract_class.compile().map_err(BroadcastedToBlockifierError::CompilationFailed)?;
                 let compiled_class_hash = tx.compiled_class_hash;
+                check_compiled_class_hash(&compiled, compiled_class_hash)?;
                 let class_hash = tx.contract_class.class_hash();
                 let class_info = ClassInfo {
                     contract_class: ContractClass::Sierra((*tx.contract_class).clone().into()),
@@ -82,6 +95,7 @@ pub fn broadcasted_to_blockifier(
             starknet_core::types::BroadcastedDeclareTransaction::V3(tx) => {
                 let compiled = tx.contract_class.compile().map_err(BroadcastedToBlockifierError::CompilationFailed)?;
                 let compiled_class_hash = tx.compiled_class_hash;
+                check_compiled_class_hash(&compiled, compiled_class_hash)?;
                 let class_hash = tx.contract_class.class_hash();
                 let class_info = ClassInfo {
                     contract_class: ContractClass::Sierra((*tx.contract_class).clone().into()),
@@ -110,7 +124,10 @@ pub fn broadcasted_to_blockifier(
     let TransactionWithHash { transaction, hash } =
         TransactionWithHash::from_broadcasted(transaction, chain_id, class_hash);
     let deployed_address = match &transaction {
-        Transaction::DeployAccount(tx) => Some(tx.calculate_contract_address()),
+        Transaction::DeployAccount(tx) => Some(
+            ContractAddress::try_from_felt(tx.calculate_contract_address())
+                .map_err(|_| TransactionApiError::ContractAddress)?,
+        ),
         _ => None,
     };
     let transaction: starknet_api::transaction::Transaction = (&transaction).try_into()?;
@@ -121,13 +138,60 @@ pub fn broadcasted_to_blockifier(
             TransactionHash(hash),
             class_info,
             None,
-            deployed_address.map(|address| address.try_into().unwrap()),
+            deployed_address,
             is_query,
         )?,
         extra_class_info,
     ))
 }
 
+/// The compiled class hash of a declare transaction commits to the CASM the sequencer runs, which is the one this node
+/// compiles.
+fn check_compiled_class_hash(compiled: &CompiledClass, declared: Felt) -> Result<(), BroadcastedToBlockifierError> {
+    let CompiledClass::Sierra(compiled) = compiled else { return Ok(()) };
+    let computed = compiled.compiled_class_hash().map_err(BroadcastedToBlockifierError::CompilationFailed)?;
+    if computed != declared {
+        return Err(BroadcastedToBlockifierError::CompiledClassHashMismatch { declared, computed });
+    }
+    Ok(())
+}
+
+/// Checks the transactions submitted to the node, before their conversion: the queries are only meant for the
+/// estimates and the simulations, and a transaction that cannot pay any fee is never included.
+pub fn validate_submitted_transaction(
+    transaction: &starknet_core::types::BroadcastedTransaction,
+) -> Result<(), BroadcastedToBlockifierError> {
+    use starknet_core::types::{
+        BroadcastedDeclareTransaction as Declare, BroadcastedDeployAccountTransaction as DeployAccount,
+        BroadcastedInvokeTransaction as Invoke, BroadcastedTransaction as Tx,
+    };
+
+    if is_query(transaction) {
+        return Err(BroadcastedToBlockifierError::QueryTransactionSubmitted);
+    }
+    let max_fee = match transaction {
+        Tx::Invoke(Invoke::V1(tx)) => Some(tx.max_fee),
+        Tx::Declare(Declare::V1(tx)) => Some(tx.max_fee),
+        Tx::Declare(Declare::V2(tx)) => Some(tx.max_fee),
+        Tx::DeployAccount(DeployAccount::V1(tx)) => Some(tx.max_fee),
+        _ => None,
+    };
+    let l1_gas = match transaction {
+        Tx::Invoke(Invoke::V3(tx)) => Some(&tx.resource_bounds.l1_gas),
+        Tx::Declare(Declare::V3(tx)) => Some(&tx.resource_bounds.l1_gas),
+        Tx::DeployAccount(DeployAccount::V3(tx)) => Some(&tx.resource_bounds.l1_gas),
+        _ => None,
+    };
+
+    if max_fee == Some(Felt::ZERO) {
+        return Err(BroadcastedToBlockifierError::ZeroMaxFee);
+    }
+    if l1_gas.is_some_and(|l1_gas| l1_gas.max_amount == 0 || l1_gas.max_price_per_unit == 0) {
+        return Err(BroadcastedToBlockifierError::ZeroResourceBounds);
+    }
+    Ok(())
+}
+
 fn is_query(transaction: &starknet_core::types::BroadcastedTransaction) -> bool {
     match transaction {
         starknet_core::types::BroadcastedTransaction::Invoke(tx) => match tx {
@@ -145,3 +209,106 @@ fn is_query(transaction: &starknet_core::types::BroadcastedTransaction) -> bool
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use starknet_core::types::{
+        BlockId, BlockTag, BroadcastedDeclareTransaction, BroadcastedDeclareTransactionV2,
+        BroadcastedInvokeTransaction, BroadcastedInvokeTransactionV1, BroadcastedInvokeTransactionV3,
+        BroadcastedTransaction, DataAvailabilityMode, ResourceBounds, ResourceBoundsMapping,
+    };
+    use starknet_providers::{Provider, SequencerGatewayProvider};
+
+    use super::*;
+
+    fn invoke_v1(max_fee: Felt, is_query: bool) -> BroadcastedTransaction {
+        BroadcastedTransaction::Invoke(BroadcastedInvokeTransaction::V1(BroadcastedInvokeTransactionV1 {
+            sender_address: Felt::from(0x1234),
+            calldata: vec![],
+            max_fee,
+            signature: vec![],
+            nonce: Felt::ZERO,
+            is_query,
+        }))
+    }
+
+    fn invoke_v3(l1_gas: ResourceBounds) -> BroadcastedTransaction {
+        BroadcastedTransaction::Invoke(BroadcastedInvokeTransaction::V3(BroadcastedInvokeTransactionV3 {
+            sender_address: Felt::from(0x1234),
+            calldata: vec![],
+            signature: vec![],
+            nonce: Felt::ZERO,
+            resource_bounds: ResourceBoundsMapping {
+                l1_gas,
+                l2_gas: ResourceBounds { max_amount: 0, max_price_per_unit: 0 },
+            },
+            tip: 0,
+            paymaster_data: vec![],
+            account_deployment_data: vec![],
+            nonce_data_availability_mode: DataAvailabilityMode::L1,
+            fee_data_availability_mode: DataAvailabilityMode::L1,
+            is_query: false,
+        }))
+    }
+
+    #[test]
+    fn test_validate_submitted_transaction() {
+        validate_submitted_transaction(&invoke_v1(Felt::ONE, false)).unwrap();
+        validate_submitted_transaction(&invoke_v3(ResourceBounds { max_amount: 1, max_price_per_unit: 1 })).unwrap();
+
+        assert_matches::assert_matches!(
+            validate_submitted_transaction(&invoke_v1(Felt::ONE, true)),
+            Err(BroadcastedToBlockifierError::QueryTransactionSubmitted)
+        );
+        assert_matches::assert_matches!(
+            validate_submitted_transaction(&invoke_v1(Felt::ZERO, false)),
+            Err(BroadcastedToBlockifierError::ZeroMaxFee)
+        );
+        for l1_gas in [
+            ResourceBounds { max_amount: 0, max_price_per_unit: 0 },
+            ResourceBounds { max_amount: 0, max_price_per_unit: 1 },
+            ResourceBounds { max_amount: 1, max_price_per_unit: 0 },
+        ] {
+            assert_matches::assert_matches!(
+                validate_submitted_transaction(&invoke_v3(l1_gas)),
+                Err(BroadcastedToBlockifierError::ZeroResourceBounds)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_declare_with_a_wrong_compiled_class_hash() {
+        let provider = SequencerGatewayProvider::starknet_alpha_mainnet();
+        let class_hash = Felt::from_hex_unchecked("0x816dd0297efc55dc1e7559020a3a825e81ef734b558f03c83325d4da7e6253");
+        let starknet_core::types::ContractClass::Sierra(class) =
+            provider.get_class(BlockId::Tag(BlockTag::Latest), class_hash).await.unwrap()
+        else {
+            panic!("Not a Sierra contract")
+        };
+        let class = Arc::new(class);
+        let declare = |compiled_class_hash| {
+            BroadcastedTransaction::Declare(BroadcastedDeclareTransaction::V2(BroadcastedDeclareTransactionV2 {
+                sender_address: Felt::from(0x1234),
+                compiled_class_hash,
+                max_fee: Felt::ONE,
+                signature: vec![],
+                nonce: Felt::ZERO,
+                contract_class: Arc::clone(&class),
+                is_query: false,
+            }))
+        };
+
+        let Err(BroadcastedToBlockifierError::CompiledClassHashMismatch { declared, computed }) =
+            broadcasted_to_blockifier(declare(Felt::ONE), Felt::ONE, None)
+        else {
+            panic!("The declare was converted")
+        };
+        assert_eq!(declared, Felt::ONE);
+
+        if let Err(err) = broadcasted_to_blockifier(declare(computed), Felt::ONE, None) {
+            panic!("{err:#}")
+        }
+    }
+}
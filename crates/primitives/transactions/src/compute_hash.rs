@@ -10,7 +10,7 @@ use crate::{
     L1HandlerTransaction, ResourceBoundsMapping, Transaction,
 };
 
-use super::SIMULATE_TX_VERSION_OFFSET;
+use super::{LEGACY_BLOCK_NUMBER, MAIN_CHAIN_ID, SIMULATE_TX_VERSION_OFFSET, V0_7_BLOCK_NUMBER};
 
 // contants for transaction prefixes
 const DECLARE_PREFIX: Felt = Felt::from_hex_unchecked("0x6465636c617265"); // b"declare"
@@ -23,6 +23,26 @@ const L1_GAS: &[u8] = b"L1_GAS";
 const L2_GAS: &[u8] = b"L2_GAS";
 
 impl Transaction {
+    /// Hash of the transaction included in the block `block_number` of the chain `chain_id`, `None` for the pending
+    /// block and the transactions not included yet. `offset_version` gives the hash of the query-only variant of the
+    /// transaction, whose version is offset by 2^128 so that it cannot be included in a block.
+    ///
+    /// The formula depends on the version of the transaction and, for the first mainnet blocks, on the Starknet
+    /// version of the block:
+    /// - before Starknet 0.7, the L1 handlers were hashed as invokes, without their nonce;
+    /// - before Starknet 0.8, the hashes had neither the version nor the max fee of the transaction.
+    pub fn compute_hash_at(&self, chain_id: Felt, block_number: Option<u64>, offset_version: bool) -> Felt {
+        match block_number.filter(|_| chain_id == MAIN_CHAIN_ID) {
+            Some(block_number) if block_number < V0_7_BLOCK_NUMBER => {
+                self.compute_hash_pre_v0_7(chain_id, offset_version)
+            }
+            Some(block_number) if block_number < LEGACY_BLOCK_NUMBER => {
+                self.compute_hash(chain_id, offset_version, true)
+            }
+            _ => self.compute_hash(chain_id, offset_version, false),
+        }
+    }
+
     pub fn compute_hash(&self, chain_id: Felt, offset_version: bool, legacy: bool) -> Felt {
         match self {
             crate::Transaction::Invoke(tx) => tx.compute_hash(chain_id, offset_version, legacy),
@@ -193,15 +213,15 @@ impl DeclareTransaction {
 impl DeclareTransactionV0 {
     pub fn compute_hash(&self, chain_id: Felt, offset_version: bool) -> Felt {
         let version = if offset_version { SIMULATE_TX_VERSION_OFFSET } else { Felt::ZERO };
-        let class_or_nothing_hash =
-            if version == Felt::ZERO { Pedersen::hash_array(&[]) } else { Pedersen::hash_array(&[self.class_hash]) };
+        // Declare v0 transactions have no nonce, the class hash is hashed in its place.
+        let nothing_hash = Pedersen::hash_array(&[]);
 
         Pedersen::hash_array(&[
             DECLARE_PREFIX,
             version,
             self.sender_address,
             Felt::ZERO,
-            class_or_nothing_hash,
+            nothing_hash,
             self.max_fee,
             chain_id,
             self.class_hash,
@@ -211,16 +231,15 @@ impl DeclareTransactionV0 {
 
 impl DeclareTransactionV1 {
     pub fn compute_hash(&self, chain_id: Felt, offset_version: bool) -> Felt {
-        let version = if offset_version { SIMULATE_TX_VERSION_OFFSET } else { Felt::ONE };
-        let class_or_nothing_hash =
-            if version == Felt::ZERO { Pedersen::hash_array(&[]) } else { Pedersen::hash_array(&[self.class_hash]) };
+        let version = if offset_version { SIMULATE_TX_VERSION_OFFSET + Felt::ONE } else { Felt::ONE };
+        let class_hash = Pedersen::hash_array(&[self.class_hash]);
 
         Pedersen::hash_array(&[
             DECLARE_PREFIX,
             version,
             self.sender_address,
             Felt::ZERO,
-            class_or_nothing_hash,
+            class_hash,
             self.max_fee,
             chain_id,
             self.nonce,
@@ -475,7 +494,7 @@ pub fn calculate_contract_address(
 
 #[cfg(test)]
 mod tests {
-    use crate::ResourceBounds;
+    use crate::{ResourceBounds, TEST_CHAIN_ID};
 
     use super::*;
 
@@ -512,4 +531,195 @@ mod tests {
             Felt::from_hex_unchecked("0x10000000100000000000000000000000000000000")
         );
     }
+
+    fn l1_handler() -> L1HandlerTransaction {
+        L1HandlerTransaction {
+            version: Felt::ZERO,
+            nonce: 7,
+            contract_address: Felt::from(0x1234),
+            entry_point_selector: Felt::from(0x5678),
+            calldata: vec![Felt::ONE, Felt::TWO, Felt::THREE],
+        }
+    }
+
+    #[test]
+    fn test_simulate_tx_version_offset() {
+        assert_eq!(SIMULATE_TX_VERSION_OFFSET, Felt::from_hex_unchecked("0x100000000000000000000000000000000"));
+    }
+
+    #[test]
+    fn test_l1_handler_hash_by_block() {
+        let tx = l1_handler();
+        let calldata_hash = Pedersen::hash_array(&tx.calldata);
+        let pre_v0_7 = Pedersen::hash_array(&[
+            INVOKE_PREFIX,
+            tx.contract_address,
+            tx.entry_point_selector,
+            calldata_hash,
+            MAIN_CHAIN_ID,
+        ]);
+        let legacy = Pedersen::hash_array(&[
+            L1_HANDLER_PREFIX,
+            tx.contract_address,
+            tx.entry_point_selector,
+            calldata_hash,
+            MAIN_CHAIN_ID,
+            Felt::from(7),
+        ]);
+        let current = Pedersen::hash_array(&[
+            L1_HANDLER_PREFIX,
+            Felt::ZERO,
+            tx.contract_address,
+            tx.entry_point_selector,
+            calldata_hash,
+            Felt::ZERO,
+            MAIN_CHAIN_ID,
+            Felt::from(7),
+        ]);
+
+        let tx = Transaction::L1Handler(tx);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(0), false), pre_v0_7);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(V0_7_BLOCK_NUMBER - 1), false), pre_v0_7);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(V0_7_BLOCK_NUMBER), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER - 1), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER), false), current);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, None, false), current);
+    }
+
+    #[test]
+    fn test_other_chains_use_the_current_hash() {
+        let tx = Transaction::L1Handler(l1_handler());
+        let current = tx.compute_hash(TEST_CHAIN_ID, false, false);
+        assert_eq!(tx.compute_hash_at(TEST_CHAIN_ID, Some(0), false), current);
+        assert_eq!(tx.compute_hash_at(TEST_CHAIN_ID, Some(V0_7_BLOCK_NUMBER), false), current);
+        assert_eq!(tx.compute_hash_at(TEST_CHAIN_ID, None, false), current);
+    }
+
+    #[test]
+    fn test_invoke_v0_hash_by_block() {
+        let tx = InvokeTransactionV0 {
+            max_fee: Felt::from(1000),
+            signature: vec![],
+            contract_address: Felt::from(0x1234),
+            entry_point_selector: Felt::from(0x5678),
+            calldata: vec![Felt::ONE, Felt::TWO],
+        };
+        let calldata_hash = Pedersen::hash_array(&tx.calldata);
+        let legacy = Pedersen::hash_array(&[
+            INVOKE_PREFIX,
+            tx.contract_address,
+            tx.entry_point_selector,
+            calldata_hash,
+            MAIN_CHAIN_ID,
+        ]);
+        let current = |version| {
+            Pedersen::hash_array(&[
+                INVOKE_PREFIX,
+                version,
+                tx.contract_address,
+                tx.entry_point_selector,
+                calldata_hash,
+                tx.max_fee,
+                MAIN_CHAIN_ID,
+            ])
+        };
+
+        let tx = Transaction::Invoke(InvokeTransaction::V0(tx.clone()));
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(V0_7_BLOCK_NUMBER - 1), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER - 1), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER), false), current(Felt::ZERO));
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, None, true), current(SIMULATE_TX_VERSION_OFFSET));
+    }
+
+    #[test]
+    fn test_deploy_hash_by_block() {
+        let deploy = |constructor_calldata| DeployTransaction {
+            version: Felt::ZERO,
+            contract_address_salt: Felt::from(0x5a17),
+            constructor_calldata,
+            class_hash: Felt::from(0xc1a55),
+        };
+        let constructor = Felt::from_bytes_be(&starknet_keccak(b"constructor").to_bytes_be());
+        // The deployed address is derived from the constructor calldata, which is also hashed on its own.
+        let hashes = |tx: &DeployTransaction| {
+            let address = calculate_contract_address(
+                tx.contract_address_salt,
+                tx.class_hash,
+                &tx.constructor_calldata,
+                Felt::ZERO,
+            );
+            let calldata_hash = Pedersen::hash_array(&tx.constructor_calldata);
+            let legacy = Pedersen::hash_array(&[DEPLOY_PREFIX, address, constructor, calldata_hash, MAIN_CHAIN_ID]);
+            let current = Pedersen::hash_array(&[
+                DEPLOY_PREFIX,
+                tx.version,
+                address,
+                constructor,
+                calldata_hash,
+                Felt::ZERO,
+                MAIN_CHAIN_ID,
+            ]);
+            (legacy, current)
+        };
+
+        let tx = deploy(vec![Felt::ONE, Felt::TWO]);
+        let (legacy, current) = hashes(&tx);
+        let other_calldata = deploy(vec![Felt::TWO, Felt::ONE]);
+        let (other_legacy, other_current) = hashes(&other_calldata);
+        assert_ne!(other_legacy, legacy);
+        assert_ne!(other_current, current);
+
+        let tx = Transaction::Deploy(tx);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(0), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(V0_7_BLOCK_NUMBER), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER - 1), false), legacy);
+        assert_eq!(tx.compute_hash_at(MAIN_CHAIN_ID, Some(LEGACY_BLOCK_NUMBER), false), current);
+
+        let other_calldata = Transaction::Deploy(other_calldata);
+        assert_eq!(other_calldata.compute_hash_at(MAIN_CHAIN_ID, Some(0), false), other_legacy);
+        assert_eq!(other_calldata.compute_hash_at(MAIN_CHAIN_ID, None, false), other_current);
+    }
+
+    // TODO: check the hashes of real mainnet transactions of every kind (v0 invoke and declare, deploy, the v1, v2 and
+    // v3 variants, L1 handlers with and without nonce) against the feeder, once they are vendored as fixtures.
+
+    #[test]
+    fn test_declare_query_hash() {
+        let sender_address = Felt::from(0x1234);
+        let max_fee = Felt::from(1000);
+        let class_hash = Felt::from(0xabcd);
+        let nonce = Felt::from(3);
+
+        let v0 = DeclareTransactionV0 { sender_address, max_fee, signature: vec![], class_hash };
+        let hash_v0 = |version| {
+            Pedersen::hash_array(&[
+                DECLARE_PREFIX,
+                version,
+                sender_address,
+                Felt::ZERO,
+                Pedersen::hash_array(&[]),
+                max_fee,
+                MAIN_CHAIN_ID,
+                class_hash,
+            ])
+        };
+        assert_eq!(v0.compute_hash(MAIN_CHAIN_ID, false), hash_v0(Felt::ZERO));
+        assert_eq!(v0.compute_hash(MAIN_CHAIN_ID, true), hash_v0(SIMULATE_TX_VERSION_OFFSET));
+
+        let v1 = DeclareTransactionV1 { sender_address, max_fee, signature: vec![], nonce, class_hash };
+        let hash_v1 = |version| {
+            Pedersen::hash_array(&[
+                DECLARE_PREFIX,
+                version,
+                sender_address,
+                Felt::ZERO,
+                Pedersen::hash_array(&[class_hash]),
+                max_fee,
+                MAIN_CHAIN_ID,
+                nonce,
+            ])
+        };
+        assert_eq!(v1.compute_hash(MAIN_CHAIN_ID, false), hash_v1(Felt::ONE));
+        assert_eq!(v1.compute_hash(MAIN_CHAIN_ID, true), hash_v1(SIMULATE_TX_VERSION_OFFSET + Felt::ONE));
+    }
 }
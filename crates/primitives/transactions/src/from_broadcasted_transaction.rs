@@ -14,15 +14,22 @@ impl TransactionWithHash {
         class_hash: Option<Felt>,
     ) -> Self {
         let is_query = is_query(&tx);
-        let transaction: Transaction = match tx {
+        let transaction = Transaction::from_broadcasted(tx, class_hash);
+        let hash = transaction.compute_hash(chain_id, is_query, false);
+        Self { hash, transaction }
+    }
+}
+
+impl Transaction {
+    /// Keeps the fields of the transaction as they were submitted. `class_hash` is required for declare transactions.
+    pub fn from_broadcasted(tx: starknet_core::types::BroadcastedTransaction, class_hash: Option<Felt>) -> Self {
+        match tx {
             starknet_core::types::BroadcastedTransaction::Invoke(tx) => Transaction::Invoke(tx.into()),
             starknet_core::types::BroadcastedTransaction::Declare(tx) => {
                 Transaction::Declare(DeclareTransaction::from_broadcasted(tx, class_hash.unwrap()))
             }
             starknet_core::types::BroadcastedTransaction::DeployAccount(tx) => Transaction::DeployAccount(tx.into()),
-        };
-        let hash = transaction.compute_hash(chain_id, is_query, false);
-        Self { hash, transaction }
+        }
     }
 }
 
@@ -9,11 +9,14 @@ mod to_starknet_core;
 pub mod utils;
 
 use blockifier::transaction::objects::FeeType;
-pub use broadcasted_to_blockifier::broadcasted_to_blockifier;
+pub use broadcasted_to_blockifier::{
+    broadcasted_to_blockifier, validate_submitted_transaction, BroadcastedToBlockifierError,
+};
 use dp_convert::ToFelt;
 pub use from_starknet_provider::TransactionTypeError;
 use starknet_api::transaction::TransactionVersion;
 use starknet_types_core::{felt::Felt, hash::StarkHash};
+pub use to_starknet_api::TransactionApiError;
 
 const SIMULATE_TX_VERSION_OFFSET: Felt =
     Felt::from_raw([576460752142434320, 18446744073709551584, 17407, 18446744073700081665]);
@@ -225,6 +228,15 @@ impl DeclareTransaction {
         }
     }
 
+    pub fn class_hash(&self) -> &Felt {
+        match self {
+            DeclareTransaction::V0(tx) => &tx.class_hash,
+            DeclareTransaction::V1(tx) => &tx.class_hash,
+            DeclareTransaction::V2(tx) => &tx.class_hash,
+            DeclareTransaction::V3(tx) => &tx.class_hash,
+        }
+    }
+
     pub fn sender_address(&self) -> &Felt {
         match self {
             DeclareTransaction::V0(tx) => &tx.sender_address,
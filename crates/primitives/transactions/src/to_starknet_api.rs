@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use dp_convert::felt_to_u128;
+use dp_convert::{felt_to_u128, TryFromFelt};
 use starknet_types_core::felt::Felt;
 
 use crate::{
@@ -247,7 +247,8 @@ fn signature(signature: &[Felt]) -> starknet_api::transaction::TransactionSignat
 }
 
 fn contract_address(contract_address: &Felt) -> Result<starknet_api::core::ContractAddress, TransactionApiError> {
-    (*contract_address).try_into().map_err(|_| TransactionApiError::ContractAddress)
+    starknet_api::core::ContractAddress::try_from_felt(*contract_address)
+        .map_err(|_| TransactionApiError::ContractAddress)
 }
 
 fn class_hash(class_hash: &Felt) -> starknet_api::core::ClassHash {
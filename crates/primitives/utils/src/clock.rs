@@ -0,0 +1,105 @@
+//! Source of the current time. Components reading the time or waiting for it get it from a [`Clock`], so that their
+//! time-dependent behavior can be tested with a [`MockClock`] instead of waiting.
+
+use std::time::{Duration, SystemTime};
+
+use futures::future::BoxFuture;
+use tokio::sync::watch;
+
+pub trait Clock: Send + Sync {
+    /// Wall clock time, which the timestamps and the ages are taken from.
+    fn now_unix(&self) -> SystemTime;
+
+    /// Resolves once the clock moved forward by `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Current unix timestamp, in seconds.
+    fn unix_timestamp(&self) -> u64 {
+        self.now_unix()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Current system time is before the UNIX epoch")
+            .as_secs()
+    }
+
+    /// Time elapsed since `earlier`. A time in the future, after the system clock went back, is as old as it gets.
+    fn elapsed_since(&self, earlier: SystemTime) -> Duration {
+        self.now_unix().duration_since(earlier).unwrap_or(Duration::MAX)
+    }
+}
+
+/// The time of the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_unix(&self) -> SystemTime {
+        SystemTime::now()
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock that stands still until it is advanced.
+#[derive(Debug)]
+pub struct MockClock(watch::Sender<SystemTime>);
+
+impl MockClock {
+    /// A clock showing the unix timestamp `unix_timestamp`, in seconds.
+    pub fn new(unix_timestamp: u64) -> Self {
+        Self(watch::channel(SystemTime::UNIX_EPOCH + Duration::from_secs(unix_timestamp)).0)
+    }
+
+    /// Moves the clock forward, and wakes up the sleeps that end in the meantime.
+    pub fn advance(&self, duration: Duration) {
+        self.0.send_modify(|now| *now += duration);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_unix(&self) -> SystemTime {
+        *self.0.borrow()
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut now = self.0.subscribe();
+        let wake_up = *now.borrow() + duration;
+        Box::pin(async move {
+            // The sender lives as long as the clock.
+            let _ = now.wait_for(|now| *now >= wake_up).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(1_700_000_000);
+        let start = clock.now_unix();
+        assert_eq!(clock.unix_timestamp(), 1_700_000_000);
+        assert_eq!(clock.elapsed_since(start), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(2_500));
+        assert_eq!(clock.unix_timestamp(), 1_700_000_002);
+        assert_eq!(clock.now_unix(), SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_002_500));
+        assert_eq!(clock.elapsed_since(start), Duration::from_millis(2_500));
+        assert_eq!(clock.elapsed_since(clock.now_unix() + Duration::from_secs(1)), Duration::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_sleep() {
+        let clock = MockClock::new(1_700_000_000);
+        let mut sleep = clock.sleep(Duration::from_secs(2));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        sleep.now_or_never().unwrap();
+        clock.sleep(Duration::ZERO).now_or_never().unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Dedicated rayon thread pools for the compute heavy work of the node.
+//!
+//! The sync and the RPC each get their own pool, so that the block conversion and hashing of an initial sync cannot
+//! starve the transaction executions of the RPC, and the other way around. The tasks submitted to a pool and not
+//! started yet are reported to an observer as its queue depth.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+
+/// Gets notified of the queue depth of the compute pools.
+pub trait ComputePoolObserver: Send + Sync {
+    fn on_queue_depth(&self, pool: &'static str, depth: usize);
+}
+
+/// Handle to a rayon thread pool. Cloning it gives a handle to the same pool.
+#[derive(Clone)]
+pub struct ComputePool {
+    name: &'static str,
+    pool: Arc<rayon::ThreadPool>,
+    queued: Arc<AtomicUsize>,
+    observer: Option<Arc<dyn ComputePoolObserver>>,
+}
+
+/// The number of threads of a pool when it is not given: half of the cores, so that the sync and the RPC share them.
+pub fn default_num_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get().div_ceil(2))
+}
+
+impl ComputePool {
+    /// A pool of `num_threads` threads, or of [`default_num_threads`] when it is 0.
+    pub fn new(name: &'static str, num_threads: usize) -> anyhow::Result<Self> {
+        let num_threads = if num_threads == 0 { default_num_threads() } else { num_threads };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(move |i| format!("{name}-compute-{i}"))
+            .build()
+            .with_context(|| format!("Building the {name} compute pool"))?;
+        Ok(Self { name, pool: Arc::new(pool), queued: Default::default(), observer: None })
+    }
+
+    pub fn with_observer(self, observer: Arc<dyn ComputePoolObserver>) -> Self {
+        observer.on_queue_depth(self.name, self.queue_depth());
+        Self { observer: Some(observer), ..self }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Number of tasks submitted to the pool which are not running yet.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    fn task_queued(&self) {
+        self.report_queue_depth(self.queued.fetch_add(1, Ordering::Relaxed) + 1);
+    }
+
+    fn task_started(&self) {
+        self.report_queue_depth(self.queued.fetch_sub(1, Ordering::Relaxed) - 1);
+    }
+
+    fn report_queue_depth(&self, depth: usize) {
+        if let Some(observer) = &self.observer {
+            observer.on_queue_depth(self.name, depth);
+        }
+    }
+}
+
+/// Runs `func` on the threads of `pool`. The nested rayon calls of `func`, such as [`rayon::join`], stay on this pool.
+pub async fn spawn_compute<F, R>(pool: &ComputePool, func: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    pool.task_queued();
+    let pool_ = pool.clone();
+    pool.pool.spawn(move || {
+        pool_.task_started();
+        let _result = tx.send(func());
+    });
+
+    rx.await.expect("tokio channel closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        depths: Mutex<Vec<(&'static str, usize)>>,
+    }
+
+    impl ComputePoolObserver for RecordingObserver {
+        fn on_queue_depth(&self, pool: &'static str, depth: usize) {
+            self.depths.lock().unwrap().push((pool, depth));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_saturated_pool_does_not_delay_the_others() {
+        let observer = Arc::new(RecordingObserver::default());
+        let sync_pool = ComputePool::new("sync", 2).unwrap().with_observer(observer.clone());
+        let rpc_pool = ComputePool::new("rpc", 2).unwrap();
+        assert_eq!(sync_pool.num_threads(), 2);
+
+        // Keeps every thread of the sync pool busy for a while, with more tasks waiting.
+        let saturating: Vec<_> = (0..8)
+            .map(|_| {
+                let sync_pool = sync_pool.clone();
+                tokio::spawn(async move {
+                    spawn_compute(&sync_pool, || std::thread::sleep(Duration::from_millis(500))).await
+                })
+            })
+            .collect();
+        for _ in 0..100 {
+            if sync_pool.queue_depth() == 6 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(sync_pool.queue_depth(), 6);
+
+        let started = Instant::now();
+        for i in 0..10u64 {
+            assert_eq!(spawn_compute(&rpc_pool, move || i * 2).await, i * 2);
+        }
+        assert!(started.elapsed() < Duration::from_millis(250), "rpc tasks took {:?}", started.elapsed());
+        assert_eq!(rpc_pool.queue_depth(), 0);
+
+        for task in saturating {
+            task.await.unwrap();
+        }
+        assert_eq!(sync_pool.queue_depth(), 0);
+        let depths = observer.depths.lock().unwrap();
+        assert!(depths.iter().all(|(pool, _)| *pool == "sync"));
+        assert!(depths.iter().any(|(_, depth)| *depth >= 6));
+    }
+
+    #[tokio::test]
+    async fn test_nested_join_runs_on_the_pool() {
+        let pool = ComputePool::new("sync", 3).unwrap();
+        let (a, b) = spawn_compute(&pool, || {
+            rayon::join(rayon::current_num_threads, || std::thread::current().name().map(str::to_owned))
+        })
+        .await;
+        assert_eq!(a, 3);
+        assert!(b.unwrap().starts_with("sync-compute-"));
+    }
+
+    #[test]
+    fn test_default_pools_share_the_cores() {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        let (sync_pool, rpc_pool) = (ComputePool::new("sync", 0).unwrap(), ComputePool::new("rpc", 0).unwrap());
+        assert_eq!(sync_pool.num_threads(), default_num_threads());
+        assert!(sync_pool.num_threads() + rpc_pool.num_threads() <= cores + 1);
+        assert!(rpc_pool.num_threads() >= 1);
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(clippy::new_without_default)]
 
+pub mod clock;
+pub mod compute_pool;
+pub mod error_reporting;
+pub mod lock;
 pub mod service;
+pub mod supervisor;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
@@ -42,6 +47,11 @@ pub async fn graceful_shutdown() {
     graceful_shutdown_inner().await
 }
 
+/// Whether the node received the shutdown signal.
+pub fn is_shutting_down() -> bool {
+    CTRL_C.load(Ordering::SeqCst)
+}
+
 /// Should be used with streams/channels `next`/`recv` function.
 pub async fn wait_or_graceful_shutdown<T>(future: impl Future<Output = T>) -> Option<T> {
     if CTRL_C.load(Ordering::SeqCst) {
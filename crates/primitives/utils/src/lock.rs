@@ -0,0 +1,87 @@
+//! Locking that survives a panic in another thread.
+//!
+//! A std lock is poisoned when a thread panics while holding it, and every later `lock().expect(..)` panics in turn:
+//! one panicking task would take down every task sharing the lock. These helpers recover the guarded value instead,
+//! and clear the poison so that the recovery is only logged once.
+
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub trait RwLockExt<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T>;
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T> RwLockExt<T> for RwLock<T> {
+    fn read_or_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            log::warn!("Recovering from a poisoned lock, a thread panicked while holding it");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_or_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            log::warn!("Recovering from a poisoned lock, a thread panicked while holding it");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+pub trait MutexExt<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            log::warn!("Recovering from a poisoned lock, a thread panicked while holding it");
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_readers_survive_panicking_writer() {
+        let lock = Arc::new(RwLock::new(vec![1, 2, 3]));
+
+        let writer = Arc::clone(&lock);
+        let res = std::thread::spawn(move || {
+            let mut guard = writer.write().unwrap();
+            guard.push(4);
+            panic!("Writer panicked while holding the lock");
+        })
+        .join();
+        assert!(res.is_err());
+        assert!(lock.is_poisoned());
+
+        assert_eq!(*lock.read_or_recover(), vec![1, 2, 3, 4]);
+        assert!(!lock.is_poisoned());
+        lock.write_or_recover().push(5);
+        assert_eq!(*lock.read().unwrap(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_mutex_survives_panicking_holder() {
+        let lock = Arc::new(Mutex::new(0));
+
+        let holder = Arc::clone(&lock);
+        let res = std::thread::spawn(move || {
+            let _guard = holder.lock().unwrap();
+            panic!("Holder panicked while holding the lock");
+        })
+        .join();
+        assert!(res.is_err());
+
+        *lock.lock_or_recover() += 1;
+        assert_eq!(*lock.lock().unwrap(), 1);
+    }
+}
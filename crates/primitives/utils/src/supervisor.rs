@@ -0,0 +1,341 @@
+//! Restarts the long-running tasks of the node when they fail.
+//!
+//! A failed task is started again after an exponential backoff. A task that keeps failing shortly after being started
+//! is given up on: its subsystem is marked as degraded and the rest of the node keeps running. Panics are bugs, they
+//! are not restarted.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinSet;
+
+use crate::lock::RwLockExt;
+use crate::{is_shutting_down, wait_or_graceful_shutdown};
+
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// Delay before the first restart. It doubles with every failure, up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The subsystem is marked as degraded after this many rapid failures in a row.
+    pub max_rapid_failures: u32,
+    /// A task that fails after running for longer than this is considered to have been healthy: its failures and
+    /// backoff start over.
+    pub rapid_failure_window: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_rapid_failures: 5,
+            rapid_failure_window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubsystemState {
+    Running,
+    /// The task failed and waits for its backoff to elapse.
+    Restarting,
+    /// The task failed too many times in a row, it is not restarted anymore.
+    Degraded,
+    /// The task returned, or the node is shutting down.
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsystemStatus {
+    pub state: SubsystemState,
+    pub restarts: u64,
+    /// The error of the last failure, if the task failed.
+    pub last_error: Option<String>,
+}
+
+/// Notified of the restarts and state changes of the supervised subsystems, to export them as metrics.
+pub trait SupervisorObserver: Send + Sync {
+    fn on_restart(&self, subsystem: &'static str);
+    fn on_state_change(&self, subsystem: &'static str, state: SubsystemState);
+}
+
+/// Owns the long-running tasks of the node. Cloning it gives a handle to the same subsystems.
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    config: SupervisorConfig,
+    subsystems: Arc<RwLock<BTreeMap<&'static str, SubsystemStatus>>>,
+    observer: Option<Arc<dyn SupervisorObserver>>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self { config, ..Default::default() }
+    }
+
+    pub fn with_observer(self, observer: Arc<dyn SupervisorObserver>) -> Self {
+        Self { observer: Some(observer), ..self }
+    }
+
+    /// Spawns the supervision of `task` into the join set of a service. Aborting the join set cancels the task.
+    pub fn spawn<F, Fut>(&self, join_set: &mut JoinSet<anyhow::Result<()>>, subsystem: &'static str, task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        join_set.spawn(async move { supervisor.supervise(subsystem, task).await });
+    }
+
+    /// Runs the task built by `task`, and builds it again every time it fails. Returns when the task returns, when
+    /// the subsystem gets degraded, or when the node shuts down. The errors of a task failing during the shutdown
+    /// are returned.
+    pub async fn supervise<F, Fut>(&self, subsystem: &'static str, mut task: F) -> anyhow::Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let mut backoff = self.config.initial_backoff;
+        let mut rapid_failures = 0;
+        loop {
+            self.set_state(subsystem, SubsystemState::Running);
+            let started_at = Instant::now();
+            let err = match task().await {
+                Ok(()) => {
+                    self.set_state(subsystem, SubsystemState::Stopped);
+                    return Ok(());
+                }
+                Err(err) if is_shutting_down() => {
+                    self.set_state(subsystem, SubsystemState::Stopped);
+                    return Err(err);
+                }
+                Err(err) => err,
+            };
+
+            if started_at.elapsed() >= self.config.rapid_failure_window {
+                rapid_failures = 0;
+                backoff = self.config.initial_backoff;
+            }
+            rapid_failures += 1;
+            self.update(subsystem, |status| status.last_error = Some(format!("{err:#}")));
+
+            if rapid_failures >= self.config.max_rapid_failures {
+                log::error!(
+                    "❗ Giving up on subsystem {subsystem} after {rapid_failures} rapid failures, the node is degraded: \
+                     {err:#}"
+                );
+                self.set_state(subsystem, SubsystemState::Degraded);
+                return Ok(());
+            }
+
+            log::warn!(
+                "🔁 Restarting subsystem {subsystem} in {backoff:?} (failure {rapid_failures}/{}): {err:#}",
+                self.config.max_rapid_failures
+            );
+            self.update(subsystem, |status| status.restarts += 1);
+            if let Some(observer) = &self.observer {
+                observer.on_restart(subsystem);
+            }
+            self.set_state(subsystem, SubsystemState::Restarting);
+
+            if wait_or_graceful_shutdown(tokio::time::sleep(backoff)).await.is_none() {
+                self.set_state(subsystem, SubsystemState::Stopped);
+                return Ok(());
+            }
+            backoff = (backoff * 2).min(self.config.max_backoff);
+        }
+    }
+
+    pub fn status(&self, subsystem: &str) -> Option<SubsystemStatus> {
+        self.subsystems.read_or_recover().get(subsystem).cloned()
+    }
+
+    /// The subsystems that are not restarted anymore.
+    pub fn degraded(&self) -> Vec<&'static str> {
+        self.subsystems
+            .read_or_recover()
+            .iter()
+            .filter(|(_, status)| status.state == SubsystemState::Degraded)
+            .map(|(subsystem, _)| *subsystem)
+            .collect()
+    }
+
+    fn set_state(&self, subsystem: &'static str, state: SubsystemState) {
+        self.update(subsystem, |status| status.state = state);
+        if let Some(observer) = &self.observer {
+            observer.on_state_change(subsystem, state);
+        }
+    }
+
+    fn update(&self, subsystem: &'static str, f: impl FnOnce(&mut SubsystemStatus)) {
+        let mut subsystems = self.subsystems.write_or_recover();
+        let status = subsystems.entry(subsystem).or_insert(SubsystemStatus {
+            state: SubsystemState::Running,
+            restarts: 0,
+            last_error: None,
+        });
+        f(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    fn test_config() -> SupervisorConfig {
+        SupervisorConfig {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(40),
+            max_rapid_failures: 3,
+            rapid_failure_window: Duration::from_secs(60),
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl SupervisorObserver for RecordingObserver {
+        fn on_restart(&self, subsystem: &'static str) {
+            self.events.lock().unwrap().push(format!("restart {subsystem}"));
+        }
+        fn on_state_change(&self, subsystem: &'static str, state: SubsystemState) {
+            self.events.lock().unwrap().push(format!("{subsystem} {state:?}"));
+        }
+    }
+
+    async fn wait_for_state(supervisor: &Supervisor, subsystem: &str, state: SubsystemState) {
+        for _ in 0..100 {
+            if supervisor.status(subsystem).is_some_and(|status| status.state == state) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Subsystem {subsystem} did not reach {state:?}: {:?}", supervisor.status(subsystem));
+    }
+
+    #[tokio::test]
+    async fn test_restarts_a_failing_task_until_it_runs() {
+        let observer = Arc::new(RecordingObserver::default());
+        let supervisor = Supervisor::new(test_config()).with_observer(observer.clone());
+        let attempts = Arc::new(AtomicU32::new(0));
+        let (stop_sender, stop_receiver) = oneshot::channel::<()>();
+        let stop_receiver = Arc::new(tokio::sync::Mutex::new(Some(stop_receiver)));
+
+        let mut join_set = JoinSet::new();
+        supervisor.spawn(&mut join_set, "sync", {
+            let attempts = Arc::clone(&attempts);
+            move || {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                let stop_receiver = Arc::clone(&stop_receiver);
+                async move {
+                    if attempt < 2 {
+                        anyhow::bail!("Connection reset {attempt}");
+                    }
+                    let stop_receiver = stop_receiver.lock().await.take().unwrap();
+                    let _ = stop_receiver.await;
+                    Ok(())
+                }
+            }
+        });
+
+        // The task fails twice, and keeps running the third time.
+        wait_for_state(&supervisor, "sync", SubsystemState::Running).await;
+        for _ in 0..100 {
+            if attempts.load(Ordering::SeqCst) == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            supervisor.status("sync"),
+            Some(SubsystemStatus {
+                state: SubsystemState::Running,
+                restarts: 2,
+                last_error: Some("Connection reset 1".into())
+            })
+        );
+        assert!(supervisor.degraded().is_empty());
+
+        // A task returning is not restarted.
+        stop_sender.send(()).unwrap();
+        join_set.join_next().await.unwrap().unwrap().unwrap();
+        assert_eq!(supervisor.status("sync").unwrap().state, SubsystemState::Stopped);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            [
+                "sync Running",
+                "restart sync",
+                "sync Restarting",
+                "sync Running",
+                "restart sync",
+                "sync Restarting",
+                "sync Running",
+                "sync Stopped"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_rapid_failures() {
+        let supervisor = Supervisor::new(test_config());
+        let attempts = AtomicU32::new(0);
+
+        let started_at = Instant::now();
+        supervisor
+            .supervise("l1_messaging", || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { anyhow::bail!("L1 endpoint unreachable") }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        // Backoffs of 10ms and 20ms.
+        assert!(started_at.elapsed() >= Duration::from_millis(30));
+        let status = supervisor.status("l1_messaging").unwrap();
+        assert_eq!((status.state, status.restarts), (SubsystemState::Degraded, 2));
+        assert_eq!(status.last_error.as_deref(), Some("L1 endpoint unreachable"));
+        assert_eq!(supervisor.degraded(), ["l1_messaging"]);
+    }
+
+    #[tokio::test]
+    async fn test_aborting_cancels_the_supervised_tasks() {
+        struct DropGuard(Arc<AtomicU32>);
+        impl Drop for DropGuard {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let supervisor = Supervisor::new(test_config());
+        let dropped = Arc::new(AtomicU32::new(0));
+        let mut join_set = JoinSet::new();
+        for subsystem in ["sync", "block_production"] {
+            let dropped = Arc::clone(&dropped);
+            supervisor.spawn(&mut join_set, subsystem, move || {
+                let guard = DropGuard(Arc::clone(&dropped));
+                async move {
+                    let _guard = guard;
+                    std::future::pending().await
+                }
+            });
+        }
+        wait_for_state(&supervisor, "sync", SubsystemState::Running).await;
+        wait_for_state(&supervisor, "block_production", SubsystemState::Running).await;
+
+        join_set.shutdown().await;
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+}